@@ -19,5 +19,5 @@
 // DEALINGS IN THE SOFTWARE.
 
 fn main() {
-	prost_build::compile_protos(&["src/keys.proto"], &["src"]).unwrap();
+	prost_build::compile_protos(&["src/keys.proto", "src/envelope.proto", "src/peer_record.proto", "src/key_rotation.proto"], &["src"]).unwrap();
 }
@@ -0,0 +1,371 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Policies deciding which addresses learned from the network are worth using.
+//!
+//! Remote peers freely advertise addresses of their own, or of other peers, and many of these
+//! addresses are meaningless outside of the network they originate from. An [`AddressFilter`]
+//! decides which of them are dialed, stored or passed on to other peers.
+//!
+//! ```
+//! use libp2p_core::address_filter::AddressFilter;
+//!
+//! let filter = AddressFilter::new()
+//!     .deny_private()
+//!     .deny_loopback()
+//!     .allow_protocol_stacks(vec!["/ip4/tcp".parse().unwrap(), "/ip6/tcp".parse().unwrap()]);
+//!
+//! assert!(filter.allows(&"/ip4/1.2.3.4/tcp/4001".parse().unwrap()));
+//! assert!(!filter.allows(&"/ip4/192.168.1.2/tcp/4001".parse().unwrap()));
+//! assert!(!filter.allows(&"/ip4/1.2.3.4/udp/4001/quic".parse().unwrap()));
+//! ```
+
+use multiaddr::{Multiaddr, Protocol};
+use std::{error, fmt, net::{IpAddr, Ipv4Addr, Ipv6Addr}, str::FromStr};
+
+/// A set of rules that [`Multiaddr`]esses must all satisfy.
+///
+/// Rules are added with the builder methods and combine with each other, i.e. an address is
+/// allowed only if it is allowed by every rule. The default filter has no rule and allows every
+/// address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressFilter {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Rule {
+    DenyPrivate,
+    DenyLoopback,
+    DenySubnets(Vec<Subnet>),
+    AllowSubnets(Vec<Subnet>),
+    AllowStacks(Vec<ProtocolStack>),
+}
+
+impl AddressFilter {
+    /// Creates a filter that allows every address.
+    pub fn new() -> Self {
+        AddressFilter::default()
+    }
+
+    /// Denies addresses in private networks.
+    ///
+    /// These are the RFC 1918 IPv4 ranges, the shared address space of RFC 6598, IPv6 unique
+    /// local addresses and link-local addresses of either family.
+    pub fn deny_private(mut self) -> Self {
+        self.rules.push(Rule::DenyPrivate);
+        self
+    }
+
+    /// Denies loopback addresses.
+    pub fn deny_loopback(mut self) -> Self {
+        self.rules.push(Rule::DenyLoopback);
+        self
+    }
+
+    /// Denies addresses in any of the given subnets.
+    pub fn deny_subnets(mut self, subnets: impl IntoIterator<Item = Subnet>) -> Self {
+        self.rules.push(Rule::DenySubnets(subnets.into_iter().collect()));
+        self
+    }
+
+    /// Only allows IP addresses in one of the given subnets.
+    ///
+    /// Addresses without IP address, e.g. `/dns4/example.com/tcp/1`, are not affected.
+    pub fn allow_subnets(mut self, subnets: impl IntoIterator<Item = Subnet>) -> Self {
+        self.rules.push(Rule::AllowSubnets(subnets.into_iter().collect()));
+        self
+    }
+
+    /// Only allows addresses made of one of the given protocol stacks.
+    pub fn allow_protocol_stacks(mut self, stacks: impl IntoIterator<Item = ProtocolStack>) -> Self {
+        self.rules.push(Rule::AllowStacks(stacks.into_iter().collect()));
+        self
+    }
+
+    /// Returns `true` if the filter doesn't deny any address.
+    pub fn allows_all(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns `true` if the given address passes all the rules of the filter.
+    pub fn allows(&self, addr: &Multiaddr) -> bool {
+        let ips = || addr.iter().filter_map(|p| match p {
+            Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+            Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+            _ => None,
+        });
+        self.rules.iter().all(|rule| match rule {
+            Rule::DenyPrivate => !ips().any(is_private),
+            Rule::DenyLoopback => !ips().any(|ip| ip.is_loopback()),
+            Rule::DenySubnets(subnets) => !ips().any(|ip| subnets.iter().any(|s| s.contains(ip))),
+            Rule::AllowSubnets(subnets) => ips().all(|ip| subnets.iter().any(|s| s.contains(ip))),
+            Rule::AllowStacks(stacks) => stacks.iter().any(|s| s.matches(addr)),
+        })
+    }
+}
+
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private() || ip.is_link_local() || (a == 100 && b & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+/// A range of IP addresses sharing a common prefix, e.g. `10.0.0.0/8`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Subnet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Subnet {
+    /// Creates the subnet of the addresses sharing the first `prefix` bits of `addr`.
+    ///
+    /// Returns an error if `prefix` exceeds the length of the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, ParseError> {
+        let addr = match addr {
+            IpAddr::V4(ip) if prefix <= 32 => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask))
+            }
+            IpAddr::V6(ip) if prefix <= 128 => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+            }
+            _ => return Err(ParseError::InvalidPrefix),
+        };
+        Ok(Subnet { addr, prefix })
+    }
+
+    /// Returns the first address of the subnet.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Returns the length of the prefix shared by the addresses of the subnet.
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Returns `true` if the given address is part of the subnet.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match Subnet::new(ip, self.prefix) {
+            Ok(s) => s.addr == self.addr,
+            Err(_) => false,
+        }
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '/');
+        let addr = parts.next()
+            .and_then(|a| a.parse().ok())
+            .ok_or(ParseError::InvalidAddress)?;
+        let prefix = parts.next()
+            .and_then(|p| p.parse().ok())
+            .ok_or(ParseError::InvalidPrefix)?;
+        Subnet::new(addr, prefix)
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// The sequence of protocols an address is made of, e.g. `/ip4/tcp/ws`.
+///
+/// An address matches the stack if its protocols are exactly those of the stack, ignoring a
+/// trailing `/p2p`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProtocolStack {
+    tags: Vec<String>,
+}
+
+impl ProtocolStack {
+    /// Returns `true` if the address is made of this stack of protocols.
+    pub fn matches(&self, addr: &Multiaddr) -> bool {
+        let mut protocols = addr.iter().collect::<Vec<_>>();
+        if let Some(Protocol::P2p(_)) = protocols.last() {
+            protocols.pop();
+        }
+        protocols.len() == self.tags.len()
+            && protocols.iter().zip(&self.tags).all(|(p, t)| p.tag() == t)
+    }
+}
+
+impl FromStr for ProtocolStack {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with('/') {
+            return Err(ParseError::InvalidProtocolStack)
+        }
+        let tags = s[1..].split('/').map(String::from).collect::<Vec<_>>();
+        if tags.iter().any(String::is_empty) {
+            return Err(ParseError::InvalidProtocolStack)
+        }
+        Ok(ProtocolStack { tags })
+    }
+}
+
+impl fmt::Display for ProtocolStack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for t in &self.tags {
+            write!(f, "/{}", t)?
+        }
+        Ok(())
+    }
+}
+
+/// An error while parsing a [`Subnet`] or [`ProtocolStack`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseError {
+    /// The IP address of a subnet is invalid.
+    InvalidAddress,
+    /// The prefix length of a subnet is missing or too large.
+    InvalidPrefix,
+    /// A protocol stack is not of the form `/<protocol>/<protocol>/...`.
+    InvalidProtocolStack,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::InvalidAddress => f.write_str("Invalid subnet address"),
+            ParseError::InvalidPrefix => f.write_str("Invalid subnet prefix length"),
+            ParseError::InvalidProtocolStack => f.write_str("Invalid protocol stack"),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allows(filter: &AddressFilter, addr: &str) -> bool {
+        filter.allows(&addr.parse().unwrap())
+    }
+
+    #[test]
+    fn default_allows_all() {
+        let filter = AddressFilter::new();
+        assert!(filter.allows_all());
+        assert!(allows(&filter, "/ip4/127.0.0.1/tcp/1"));
+        assert!(allows(&filter, "/ip4/10.0.0.1/tcp/1"));
+        assert!(allows(&filter, "/memory/1"));
+    }
+
+    #[test]
+    fn deny_private() {
+        let filter = AddressFilter::new().deny_private();
+        for addr in &[
+            "/ip4/10.1.2.3/tcp/1",
+            "/ip4/172.16.0.1/tcp/1",
+            "/ip4/172.31.255.255/tcp/1",
+            "/ip4/192.168.0.1/udp/1/quic",
+            "/ip4/100.64.0.1/tcp/1",
+            "/ip4/169.254.1.1/tcp/1",
+            "/ip6/fd00::1/tcp/1",
+            "/ip6/fe80::1/tcp/1",
+            "/ip4/1.2.3.4/tcp/1/p2p-circuit/ip4/10.0.0.1/tcp/1",
+        ] {
+            assert!(!allows(&filter, addr), "{}", addr);
+        }
+        for addr in &[
+            "/ip4/1.2.3.4/tcp/1",
+            "/ip4/172.32.0.1/tcp/1",
+            "/ip4/100.128.0.1/tcp/1",
+            "/ip4/127.0.0.1/tcp/1",
+            "/ip6/2001:db8::1/tcp/1",
+            "/dns4/example.com/tcp/1",
+        ] {
+            assert!(allows(&filter, addr), "{}", addr);
+        }
+    }
+
+    #[test]
+    fn deny_loopback() {
+        let filter = AddressFilter::new().deny_loopback();
+        assert!(!allows(&filter, "/ip4/127.0.0.1/tcp/1"));
+        assert!(!allows(&filter, "/ip4/127.1.2.3/tcp/1"));
+        assert!(!allows(&filter, "/ip6/::1/tcp/1"));
+        assert!(allows(&filter, "/ip4/10.0.0.1/tcp/1"));
+    }
+
+    #[test]
+    fn subnets() {
+        let filter = AddressFilter::new()
+            .allow_subnets(vec!["10.0.0.0/8".parse().unwrap(), "2001:db8::/32".parse().unwrap()])
+            .deny_subnets(vec!["10.1.0.0/16".parse().unwrap()]);
+        assert!(allows(&filter, "/ip4/10.0.0.1/tcp/1"));
+        assert!(allows(&filter, "/ip6/2001:db8::1/tcp/1"));
+        assert!(allows(&filter, "/dns4/example.com/tcp/1"));
+        assert!(!allows(&filter, "/ip4/10.1.0.1/tcp/1"));
+        assert!(!allows(&filter, "/ip4/11.0.0.1/tcp/1"));
+        assert!(!allows(&filter, "/ip6/2001:db9::1/tcp/1"));
+    }
+
+    #[test]
+    fn protocol_stacks() {
+        let filter = AddressFilter::new()
+            .allow_protocol_stacks(vec!["/ip4/tcp".parse().unwrap(), "/dns4/tcp/ws".parse().unwrap()]);
+        assert!(allows(&filter, "/ip4/1.2.3.4/tcp/1"));
+        assert!(allows(&filter, "/ip4/1.2.3.4/tcp/1/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"));
+        assert!(allows(&filter, "/dns4/example.com/tcp/1/ws"));
+        assert!(!allows(&filter, "/ip4/1.2.3.4/tcp/1/ws"));
+        assert!(!allows(&filter, "/ip6/::1/tcp/1"));
+        assert!(!allows(&filter, "/ip4/1.2.3.4/udp/1/quic"));
+    }
+
+    #[test]
+    fn parse_subnet() {
+        let s: Subnet = "192.168.1.77/24".parse().unwrap();
+        assert_eq!(s.to_string(), "192.168.1.0/24");
+        assert_eq!("0.0.0.0/0".parse::<Subnet>().unwrap().prefix(), 0);
+        assert!("0.0.0.0/0".parse::<Subnet>().unwrap().contains("8.8.8.8".parse().unwrap()));
+        assert!(!"0.0.0.0/0".parse::<Subnet>().unwrap().contains("::1".parse().unwrap()));
+        assert_eq!("10.0.0.0/33".parse::<Subnet>(), Err(ParseError::InvalidPrefix));
+        assert_eq!("10.0.0.0".parse::<Subnet>(), Err(ParseError::InvalidPrefix));
+        assert_eq!("foo/8".parse::<Subnet>(), Err(ParseError::InvalidAddress));
+    }
+
+    #[test]
+    fn parse_protocol_stack() {
+        assert_eq!("/ip4/tcp".parse::<ProtocolStack>().unwrap().to_string(), "/ip4/tcp");
+        assert_eq!("ip4/tcp".parse::<ProtocolStack>(), Err(ParseError::InvalidProtocolStack));
+        assert_eq!("/ip4//tcp".parse::<ProtocolStack>(), Err(ParseError::InvalidProtocolStack));
+        assert_eq!("/".parse::<ProtocolStack>(), Err(ParseError::InvalidProtocolStack));
+    }
+}
@@ -18,7 +18,8 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::{muxing::StreamMuxer, ProtocolName, transport::ListenerEvent};
+use crate::{muxing::{MuxerStats, StreamMuxer, SubstreamPriority}, ProtocolName, transport::ListenerEvent};
+use crate::upgrade::{SecuredStream, SecurityStats};
 use futures::{prelude::*, io::{IoSlice, IoSliceMut}};
 use pin_project::{pin_project, project};
 use std::{fmt, io::{Error as IoError}, pin::Pin, task::Context, task::Poll};
@@ -64,6 +65,18 @@ pub enum EitherOutput<A, B> {
     Second(#[pin] B),
 }
 
+impl<I, A, B> EitherOutput<(I, A), (I, B)> {
+    /// Factors out the common first element of the outputs of two transports,
+    /// e.g. the `PeerId` of an authenticated connection, leaving the rest of the
+    /// output, e.g. the `StreamMuxer`, as an `EitherOutput`.
+    pub fn factor_first(self) -> (I, EitherOutput<A, B>) {
+        match self {
+            EitherOutput::First((i, a)) => (i, EitherOutput::First(a)),
+            EitherOutput::Second((i, b)) => (i, EitherOutput::Second(b)),
+        }
+    }
+}
+
 impl<A, B> AsyncRead for EitherOutput<A, B>
 where
     A: AsyncRead,
@@ -134,6 +147,26 @@ where
     }
 }
 
+impl<A, B> SecuredStream for EitherOutput<A, B>
+where
+    A: SecuredStream,
+    B: SecuredStream,
+{
+    fn security_stats(&self) -> SecurityStats {
+        match self {
+            EitherOutput::First(a) => a.security_stats(),
+            EitherOutput::Second(b) => b.security_stats(),
+        }
+    }
+
+    fn early_muxer(&self) -> Option<&[u8]> {
+        match self {
+            EitherOutput::First(a) => a.early_muxer(),
+            EitherOutput::Second(b) => b.early_muxer(),
+        }
+    }
+}
+
 impl<A, B, I> Stream for EitherOutput<A, B>
 where
     A: TryStream<Ok = I>,
@@ -220,6 +253,13 @@ where
         }
     }
 
+    fn open_outbound_with_priority(&self, priority: SubstreamPriority) -> Self::OutboundSubstream {
+        match self {
+            EitherOutput::First(inner) => EitherOutbound::A(inner.open_outbound_with_priority(priority)),
+            EitherOutput::Second(inner) => EitherOutbound::B(inner.open_outbound_with_priority(priority)),
+        }
+    }
+
     fn poll_outbound(&self, cx: &mut Context, substream: &mut Self::OutboundSubstream) -> Poll<Result<Self::Substream, Self::Error>> {
         match (self, substream) {
             (EitherOutput::First(ref inner), EitherOutbound::A(ref mut substream)) => {
@@ -334,6 +374,13 @@ where
             EitherOutput::Second(inner) => inner.flush_all(cx).map_err(|e| e.into()),
         }
     }
+
+    fn stats(&self) -> Option<MuxerStats> {
+        match self {
+            EitherOutput::First(inner) => inner.stats(),
+            EitherOutput::Second(inner) => inner.stats(),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
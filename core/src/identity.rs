@@ -20,6 +20,10 @@
 
 //! A node's network identity keys.
 
+#[cfg(feature = "bls")]
+pub mod bls;
+#[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+pub mod ecdsa;
 pub mod ed25519;
 #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
 pub mod rsa;
@@ -28,8 +32,12 @@ pub mod secp256k1;
 
 pub mod error;
 
+mod pkcs8;
+
 use self::error::*;
 use crate::{PeerId, keys_proto};
+use std::{fmt, sync::Arc};
+use zeroize::Zeroize;
 
 /// Identity keypair of a node.
 ///
@@ -48,6 +56,25 @@ use crate::{PeerId, keys_proto};
 /// let keypair = Keypair::rsa_from_pkcs8(&mut bytes);
 /// ```
 ///
+/// # Example: Converting ECDSA keys with OpenSSL
+///
+/// ```text
+/// openssl ecparam -name prime256v1 -genkey -noout -out private.pem      # optional
+/// openssl pkcs8 -in private.pem -inform PEM -topk8 -out private.pk8 -outform DER -nocrypt
+/// ```
+///
+/// Loading the keys:
+///
+/// ```text
+/// let mut bytes = std::fs::read("private.pk8").unwrap();
+/// let keypair = Keypair::ecdsa_from_pkcs8(&mut bytes);
+/// ```
+///
+/// # Example: Signing with a hardware-backed key
+///
+/// The private key of an identity may be kept outside of the process, e.g.
+/// in an HSM, a TPM or a remote key management service, by implementing
+/// [`Signer`] and wrapping it with [`Keypair::from_signer`].
 #[derive(Clone)]
 pub enum Keypair {
     /// An Ed25519 keypair.
@@ -57,7 +84,18 @@ pub enum Keypair {
     Rsa(rsa::Keypair),
     /// A Secp256k1 keypair.
     #[cfg(feature = "secp256k1")]
-    Secp256k1(secp256k1::Keypair)
+    Secp256k1(secp256k1::Keypair),
+    /// An ECDSA keypair.
+    #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+    Ecdsa(ecdsa::Keypair),
+    /// A keypair whose private key is only available to a [`Signer`].
+    Signer(Arc<dyn Signer>)
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Keypair").field("public", &self.public()).finish()
+    }
 }
 
 impl Keypair {
@@ -72,6 +110,17 @@ impl Keypair {
         Keypair::Secp256k1(secp256k1::Keypair::generate())
     }
 
+    /// Generate a new ECDSA keypair on the P-256 curve.
+    #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+    pub fn generate_ecdsa() -> Keypair {
+        Keypair::Ecdsa(ecdsa::Keypair::generate())
+    }
+
+    /// Create a keypair producing its signatures with the given [`Signer`].
+    pub fn from_signer(signer: impl Signer + 'static) -> Keypair {
+        Keypair::Signer(Arc::new(signer))
+    }
+
     /// Decode an keypair from a DER-encoded secret key in PKCS#8 PrivateKeyInfo
     /// format (i.e. unencrypted) as defined in [RFC5208].
     ///
@@ -91,6 +140,15 @@ impl Keypair {
             .map(|sk| Keypair::Secp256k1(secp256k1::Keypair::from(sk)))
     }
 
+    /// Decode an ECDSA keypair on the P-256 curve from a DER-encoded secret key
+    /// in PKCS#8 PrivateKeyInfo format (i.e. unencrypted) as defined in [RFC5208].
+    ///
+    /// [RFC5208]: https://tools.ietf.org/html/rfc5208#section-5
+    #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+    pub fn ecdsa_from_pkcs8(pkcs8_der: &mut [u8]) -> Result<Keypair, DecodingError> {
+        ecdsa::Keypair::from_pkcs8(pkcs8_der).map(Keypair::Ecdsa)
+    }
+
     /// Sign a message using the private key of this keypair, producing
     /// a signature that can be verified using the corresponding public key.
     pub fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError> {
@@ -100,7 +158,10 @@ impl Keypair {
             #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
             Rsa(ref pair) => pair.sign(msg),
             #[cfg(feature = "secp256k1")]
-            Secp256k1(ref pair) => pair.secret().sign(msg)
+            Secp256k1(ref pair) => pair.secret().sign(msg),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            Ecdsa(ref pair) => pair.sign(msg),
+            Signer(ref signer) => signer.sign(msg)
         }
     }
 
@@ -113,10 +174,184 @@ impl Keypair {
             Rsa(pair) => PublicKey::Rsa(pair.public()),
             #[cfg(feature = "secp256k1")]
             Secp256k1(pair) => PublicKey::Secp256k1(pair.public().clone()),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            Ecdsa(pair) => PublicKey::Ecdsa(pair.public().clone()),
+            Signer(signer) => signer.public(),
+        }
+    }
+
+    /// Encode the keypair into a protobuf structure for storage or exchange
+    /// with other implementations.
+    ///
+    /// Fails for a [`Keypair::Signer`], whose private key is not available.
+    pub fn to_protobuf_encoding(&self) -> Result<Vec<u8>, EncodingError> {
+        use prost::Message;
+
+        let mut private_key = match self {
+            Keypair::Ed25519(pair) =>
+                keys_proto::PrivateKey {
+                    r#type: keys_proto::KeyType::Ed25519 as i32,
+                    data: pair.encode().to_vec()
+                },
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            Keypair::Rsa(pair) =>
+                keys_proto::PrivateKey {
+                    r#type: keys_proto::KeyType::Rsa as i32,
+                    data: pair.encode_pkcs1()
+                },
+            #[cfg(feature = "secp256k1")]
+            Keypair::Secp256k1(pair) =>
+                keys_proto::PrivateKey {
+                    r#type: keys_proto::KeyType::Secp256k1 as i32,
+                    data: pair.secret().to_bytes().to_vec()
+                },
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            Keypair::Ecdsa(pair) =>
+                keys_proto::PrivateKey {
+                    r#type: keys_proto::KeyType::Ecdsa as i32,
+                    data: pair.encode_der()
+                },
+            Keypair::Signer(_) => return Err(EncodingError::new("the private key is only available to the signer"))
+        };
+
+        let mut buf = Vec::with_capacity(private_key.encoded_len());
+        private_key.encode(&mut buf).expect("Vec<u8> provides capacity as needed");
+        private_key.data.zeroize();
+        Ok(buf)
+    }
+
+    /// Decode a keypair from a protobuf structure, e.g. read from storage,
+    /// zeroing the input on success. See also `to_protobuf_encoding`.
+    pub fn from_protobuf_encoding(bytes: &mut [u8]) -> Result<Keypair, DecodingError> {
+        use prost::Message;
+
+        let mut private_key = keys_proto::PrivateKey::decode(&*bytes)
+            .map_err(|e| DecodingError::new("Protobuf").source(e))?;
+
+        let key_type = keys_proto::KeyType::from_i32(private_key.r#type)
+            .ok_or_else(|| DecodingError::new(format!("unknown key type: {}", private_key.r#type)));
+
+        let keypair = key_type.and_then(|key_type| match key_type {
+            keys_proto::KeyType::Ed25519 => {
+                ed25519::Keypair::decode(&mut private_key.data).map(Keypair::Ed25519)
+            }
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            keys_proto::KeyType::Rsa => {
+                rsa::Keypair::from_pkcs1(&mut private_key.data).map(Keypair::Rsa)
+            }
+            #[cfg(feature = "secp256k1")]
+            keys_proto::KeyType::Secp256k1 => {
+                secp256k1::SecretKey::from_bytes(&mut private_key.data)
+                    .map(|sk| Keypair::Secp256k1(sk.into()))
+            }
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            keys_proto::KeyType::Ecdsa => {
+                ecdsa::Keypair::from_der(&mut private_key.data).map(Keypair::Ecdsa)
+            }
+            #[allow(unreachable_patterns)] // Due to conditional compilation.
+            key_type => {
+                log::debug!("support for {:?} was disabled at compile-time", key_type);
+                Err(DecodingError::new("Unsupported"))
+            }
+        });
+
+        private_key.data.zeroize();
+        if keypair.is_ok() {
+            bytes.zeroize();
+        }
+        keypair
+    }
+
+    /// Encode the keypair in DER as a PKCS#8 PrivateKeyInfo structure, as
+    /// defined in [RFC5208], for storage or use with other tools.
+    ///
+    /// Fails for a [`Keypair::Signer`], whose private key is not available.
+    ///
+    /// [RFC5208]: https://tools.ietf.org/html/rfc5208#section-5
+    pub fn to_pkcs8(&self) -> Result<Vec<u8>, EncodingError> {
+        match self {
+            Keypair::Ed25519(pair) => Ok(pair.encode_pkcs8()),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            Keypair::Rsa(pair) => Ok(pair.encode_pkcs8()),
+            #[cfg(feature = "secp256k1")]
+            Keypair::Secp256k1(pair) => Ok(pair.encode_pkcs8()),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            Keypair::Ecdsa(pair) => Ok(pair.encode_pkcs8()),
+            Keypair::Signer(_) => Err(EncodingError::new("the private key is only available to the signer"))
+        }
+    }
+
+    /// Decode a keypair of any type from a DER-encoded PKCS#8 PrivateKeyInfo
+    /// structure (i.e. unencrypted), zeroing the input on success.
+    /// See also `to_pkcs8`.
+    pub fn from_pkcs8(der: &mut [u8]) -> Result<Keypair, DecodingError> {
+        let (algorithm, _) = pkcs8::decode(der)?;
+        match algorithm {
+            pkcs8::Algorithm::Ed25519 => ed25519::Keypair::from_pkcs8(der).map(Keypair::Ed25519),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            pkcs8::Algorithm::Rsa => rsa::Keypair::from_pkcs8(der).map(Keypair::Rsa),
+            #[cfg(feature = "secp256k1")]
+            pkcs8::Algorithm::Secp256k1 => secp256k1::Keypair::from_pkcs8(der).map(Keypair::Secp256k1),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            pkcs8::Algorithm::P256 => ecdsa::Keypair::from_pkcs8(der).map(Keypair::Ecdsa),
+            #[allow(unreachable_patterns)] // Due to conditional compilation.
+            algorithm => {
+                log::debug!("support for {:?} was disabled at compile-time", algorithm);
+                Err(DecodingError::new("Unsupported"))
+            }
+        }
+    }
+
+    /// Encode the keypair as PEM, i.e. as a "PRIVATE KEY" document
+    /// containing the encoding of `to_pkcs8`.
+    pub fn to_pem(&self) -> Result<String, EncodingError> {
+        let mut der = self.to_pkcs8()?;
+        let pem = pkcs8::encode_pem(pkcs8::PEM_PKCS8, &der);
+        der.zeroize();
+        Ok(pem)
+    }
+
+    /// Decode a keypair from the first PEM document in `pem`, which is either
+    /// a PKCS#8 "PRIVATE KEY", a PKCS#1 "RSA PRIVATE KEY" or an "EC PRIVATE KEY"
+    /// as defined in [RFC5915], e.g. as written by OpenSSL.
+    ///
+    /// [RFC5915]: https://tools.ietf.org/html/rfc5915
+    pub fn from_pem(pem: &str) -> Result<Keypair, DecodingError> {
+        let (label, mut der) = pkcs8::decode_pem(pem)?;
+        match label.as_str() {
+            pkcs8::PEM_PKCS8 => Keypair::from_pkcs8(&mut der),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            pkcs8::PEM_RSA => rsa::Keypair::from_pkcs1(&mut der).map(Keypair::Rsa),
+            pkcs8::PEM_EC => match pkcs8::ec_curve(&der)? {
+                #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+                pkcs8::Algorithm::P256 => ecdsa::Keypair::from_der(&mut der).map(Keypair::Ecdsa),
+                #[cfg(feature = "secp256k1")]
+                pkcs8::Algorithm::Secp256k1 => secp256k1::SecretKey::from_der(&mut *der)
+                    .map(|sk| Keypair::Secp256k1(sk.into())),
+                #[allow(unreachable_patterns)] // Due to conditional compilation.
+                curve => {
+                    log::debug!("support for {:?} was disabled at compile-time", curve);
+                    Err(DecodingError::new("Unsupported"))
+                }
+            },
+            label => Err(DecodingError::new(format!("unsupported PEM label: {}", label)))
         }
     }
 }
 
+/// A producer of signatures with the private key of a node's identity,
+/// e.g. an HSM, a TPM or a remote key management service.
+///
+/// See [`Keypair::from_signer`].
+pub trait Signer: Send + Sync {
+    /// Get the public key verifying the signatures of this signer.
+    fn public(&self) -> PublicKey;
+
+    /// Sign a message, producing a signature that can be verified using
+    /// the public key of this signer.
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError>;
+}
+
 /// The public key of a node's identity keypair.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum PublicKey {
@@ -127,7 +362,10 @@ pub enum PublicKey {
     Rsa(rsa::PublicKey),
     #[cfg(feature = "secp256k1")]
     /// A public Secp256k1 key.
-    Secp256k1(secp256k1::PublicKey)
+    Secp256k1(secp256k1::PublicKey),
+    #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+    /// A public ECDSA key.
+    Ecdsa(ecdsa::PublicKey)
 }
 
 impl PublicKey {
@@ -142,7 +380,9 @@ impl PublicKey {
             #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
             Rsa(pk) => pk.verify(msg, sig),
             #[cfg(feature = "secp256k1")]
-            Secp256k1(pk) => pk.verify(msg, sig)
+            Secp256k1(pk) => pk.verify(msg, sig),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            Ecdsa(pk) => pk.verify(msg, sig)
         }
     }
 
@@ -168,6 +408,12 @@ impl PublicKey {
                 keys_proto::PublicKey {
                     r#type: keys_proto::KeyType::Secp256k1 as i32,
                     data: key.encode().to_vec()
+                },
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            PublicKey::Ecdsa(key) =>
+                keys_proto::PublicKey {
+                    r#type: keys_proto::KeyType::Ecdsa as i32,
+                    data: key.encode_x509()
                 }
         };
 
@@ -210,6 +456,64 @@ impl PublicKey {
                 log::debug!("support for secp256k1 was disabled at compile-time");
                 Err("Unsupported".to_string().into())
             }
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            keys_proto::KeyType::Ecdsa => {
+                ecdsa::PublicKey::decode_x509(&pubkey.data).map(PublicKey::Ecdsa)
+            }
+            #[cfg(any(target_os = "emscripten", target_os = "unknown"))]
+            keys_proto::KeyType::Ecdsa => {
+                log::debug!("support for ECDSA was disabled at compile-time");
+                Err(DecodingError::new("Unsupported"))
+            }
+        }
+    }
+
+    /// Encode the public key in DER as a X.509 SubjectPublicKeyInfo structure,
+    /// as used in certificates and by other PKI tools.
+    pub fn to_x509(&self) -> Vec<u8> {
+        match self {
+            PublicKey::Ed25519(key) => key.encode_x509(),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            PublicKey::Rsa(key) => key.encode_x509(),
+            #[cfg(feature = "secp256k1")]
+            PublicKey::Secp256k1(key) => key.encode_x509(),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            PublicKey::Ecdsa(key) => key.encode_x509()
+        }
+    }
+
+    /// Decode a public key from a DER-encoded X.509 SubjectPublicKeyInfo
+    /// structure. See also `to_x509`.
+    pub fn from_x509(der: &[u8]) -> Result<PublicKey, DecodingError> {
+        let (algorithm, _) = pkcs8::decode_spki(der)?;
+        match algorithm {
+            pkcs8::Algorithm::Ed25519 => ed25519::PublicKey::decode_x509(der).map(PublicKey::Ed25519),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            pkcs8::Algorithm::Rsa => rsa::PublicKey::decode_x509(der).map(PublicKey::Rsa),
+            #[cfg(feature = "secp256k1")]
+            pkcs8::Algorithm::Secp256k1 => secp256k1::PublicKey::decode_x509(der).map(PublicKey::Secp256k1),
+            #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+            pkcs8::Algorithm::P256 => ecdsa::PublicKey::decode_x509(der).map(PublicKey::Ecdsa),
+            #[allow(unreachable_patterns)] // Due to conditional compilation.
+            algorithm => {
+                log::debug!("support for {:?} was disabled at compile-time", algorithm);
+                Err(DecodingError::new("Unsupported"))
+            }
+        }
+    }
+
+    /// Encode the public key as a PEM "PUBLIC KEY" document, i.e. the PEM
+    /// form of `to_x509`.
+    pub fn to_pem(&self) -> String {
+        pkcs8::encode_pem(pkcs8::PEM_PUBLIC_KEY, &self.to_x509())
+    }
+
+    /// Decode a public key from the first PEM "PUBLIC KEY" document in `pem`,
+    /// e.g. as written by OpenSSL.
+    pub fn from_pem(pem: &str) -> Result<PublicKey, DecodingError> {
+        match pkcs8::decode_pem(pem)? {
+            (ref label, ref der) if label == pkcs8::PEM_PUBLIC_KEY => PublicKey::from_x509(der),
+            (label, _) => Err(DecodingError::new(format!("unsupported PEM label: {}", label)))
         }
     }
 
@@ -219,3 +523,143 @@ impl PublicKey {
     }
 }
 
+
+/// Verify many signatures at once, returning for each `(public key, message,
+/// signature)` whether the signature is valid, e.g. when validating a batch of
+/// signed messages or records received from the network.
+///
+/// Ed25519 signatures are checked together with [`ed25519::verify_batch`],
+/// falling back to verifying them one by one if the batch is invalid in order
+/// to find the invalid signatures. Signatures of other key types are always
+/// verified one by one. See [`ed25519::verify_batch`] for how the result for
+/// maliciously crafted Ed25519 signatures may differ from [`PublicKey::verify`].
+pub fn verify_batch(items: &[(&PublicKey, &[u8], &[u8])]) -> Vec<bool> {
+    let ed25519 = items.iter()
+        .filter_map(|(pk, msg, sig)| match pk {
+            PublicKey::Ed25519(pk) => Some((pk, *msg, *sig)),
+            #[allow(unreachable_patterns)] // Due to conditional compilation.
+            _ => None
+        })
+        .collect::<Vec<_>>();
+    let ed25519_valid = ed25519.len() > 1 && ed25519::verify_batch(&ed25519);
+
+    items.iter()
+        .map(|(pk, msg, sig)| match pk {
+            PublicKey::Ed25519(_) if ed25519_valid => true,
+            _ => pk.verify(msg, sig)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keypairs() -> Vec<Keypair> {
+        vec![
+            Keypair::generate_ed25519(),
+            Keypair::rsa_from_pkcs8(&mut include_bytes!("identity/test/rsa-2048.pk8").to_vec()).unwrap(),
+            #[cfg(feature = "secp256k1")]
+            Keypair::generate_secp256k1(),
+            Keypair::generate_ecdsa(),
+        ]
+    }
+
+    #[test]
+    fn keypair_protobuf_encode_decode() {
+        for kp in keypairs() {
+            let mut encoded = kp.to_protobuf_encoding().unwrap();
+            let kp2 = Keypair::from_protobuf_encoding(&mut encoded).unwrap();
+            assert_eq!(kp2.public(), kp.public());
+            assert!(encoded.iter().all(|b| *b == 0));
+        }
+    }
+
+    #[test]
+    fn keypair_pkcs8_encode_decode() {
+        for kp in keypairs() {
+            let kp2 = Keypair::from_pkcs8(&mut kp.to_pkcs8().unwrap()).unwrap();
+            assert_eq!(kp2.public(), kp.public());
+            let kp3 = Keypair::from_pem(&kp.to_pem().unwrap()).unwrap();
+            assert_eq!(kp3.public(), kp.public());
+        }
+    }
+
+    #[test]
+    fn keypair_from_ec_pem() {
+        let kp = Keypair::from_pem(include_str!("identity/test/ecdsa-p256.pem")).unwrap();
+        let pk = ecdsa::PublicKey::decode_x509(include_bytes!("identity/test/ecdsa-p256.spki")).unwrap();
+        assert_eq!(kp.public(), PublicKey::Ecdsa(pk));
+    }
+
+    #[test]
+    fn public_key_x509_encode_decode() {
+        for kp in keypairs() {
+            let pk = kp.public();
+            assert_eq!(PublicKey::from_x509(&pk.to_x509()).unwrap(), pk);
+            assert_eq!(PublicKey::from_pem(&pk.to_pem()).unwrap(), pk);
+        }
+    }
+
+    #[test]
+    fn public_key_from_openssl_x509() {
+        let fixtures: &[(&[u8], &[u8])] = &[
+            (include_bytes!("identity/test/ed25519.pk8"), include_bytes!("identity/test/ed25519.spki")),
+            #[cfg(feature = "secp256k1")]
+            (include_bytes!("identity/test/secp256k1.pk8"), include_bytes!("identity/test/secp256k1.spki")),
+            (include_bytes!("identity/test/ecdsa-p256.pk8"), include_bytes!("identity/test/ecdsa-p256.spki")),
+        ];
+        for (pk8, spki) in fixtures.iter() {
+            let kp = Keypair::from_pkcs8(&mut pk8.to_vec()).unwrap();
+            assert_eq!(PublicKey::from_x509(spki).unwrap(), kp.public());
+            assert_eq!(&kp.public().to_x509()[..], *spki);
+        }
+
+        let pk = PublicKey::from_pem(include_str!("identity/test/ed25519.spki.pem")).unwrap();
+        assert_eq!(&pk.to_x509()[..], &include_bytes!("identity/test/ed25519.spki")[..]);
+    }
+
+    #[test]
+    fn verify_batch_finds_invalid_signatures() {
+        let keys = keypairs().into_iter().chain(keypairs()).collect::<Vec<_>>();
+        let pks = keys.iter().map(Keypair::public).collect::<Vec<_>>();
+        let mut sigs = keys.iter().map(|k| k.sign(b"message").unwrap()).collect::<Vec<_>>();
+        let items = |sigs: &[Vec<u8>]| -> Vec<bool> {
+            let items = pks.iter().zip(sigs)
+                .map(|(pk, sig)| (pk, &b"message"[..], &sig[..]))
+                .collect::<Vec<_>>();
+            verify_batch(&items)
+        };
+        assert!(items(&sigs).iter().all(|valid| *valid));
+
+        // Invalidate the signature of the second Ed25519 key and of the RSA key.
+        let ed25519 = keys.len() / 2;
+        sigs[ed25519][0] ^= 1;
+        sigs[1][0] ^= 1;
+        let valid = items(&sigs);
+        assert_eq!(valid.iter().filter(|valid| !**valid).count(), 2);
+        assert!(!valid[ed25519] && !valid[1]);
+    }
+
+    #[test]
+    fn keypair_from_signer() {
+        struct Remote(Keypair);
+
+        impl Signer for Remote {
+            fn public(&self) -> PublicKey {
+                self.0.public()
+            }
+
+            fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError> {
+                self.0.sign(msg)
+            }
+        }
+
+        let remote = Keypair::generate_ed25519();
+        let kp = Keypair::from_signer(Remote(remote.clone()));
+        assert_eq!(kp.public(), remote.public());
+        assert!(kp.public().verify(b"message", &kp.sign(b"message").unwrap()));
+        assert!(kp.to_protobuf_encoding().is_err());
+        assert!(kp.to_pem().is_err());
+    }
+}
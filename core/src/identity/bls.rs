@@ -0,0 +1,226 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! BLS12-381 keys.
+//!
+//! The public keys are points on G1 and the signatures points on G2, as is
+//! common for validator keys of blockchains. Signatures follow the proof of
+//! possession scheme of the [IRTF BLS signature draft][bls-draft], but with
+//! a libp2p-specific domain separation tag, such that a signature produced
+//! for libp2p is never valid for a consensus protocol using the same key,
+//! and vice versa. Signatures with the tag of another protocol are available
+//! via [`SecretKey::sign_with_dst`] and [`PublicKey::verify_with_dst`].
+//!
+//! The libp2p key specification assigns no key type to BLS12-381, so these
+//! keys are not variants of [`Keypair`](super::Keypair) and
+//! [`PublicKey`](super::PublicKey), have no protobuf encoding and cannot
+//! identify a peer. They can be used alongside the identity keys, e.g. to
+//! sign messages exchanged over libp2p with a validator key.
+//!
+//! [bls-draft]: https://tools.ietf.org/html/draft-irtf-cfrg-bls-signature-02
+
+use blst::{BLST_ERROR, min_pk as bls};
+use rand::RngCore;
+use super::error::DecodingError;
+use zeroize::Zeroize;
+use core::fmt;
+
+/// The domain separation tag of signatures produced with [`Keypair::sign`].
+pub const DST: &[u8] = b"LIBP2P_BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// A BLS12-381 keypair.
+#[derive(Clone)]
+pub struct Keypair {
+    secret: SecretKey,
+    public: PublicKey
+}
+
+impl Keypair {
+    /// Generate a new BLS12-381 keypair.
+    pub fn generate() -> Keypair {
+        Keypair::from(SecretKey::generate())
+    }
+
+    /// Get the public key of this keypair.
+    pub fn public(&self) -> &PublicKey {
+        &self.public
+    }
+
+    /// Get the secret key of this keypair.
+    pub fn secret(&self) -> &SecretKey {
+        &self.secret
+    }
+
+    /// Sign a message using the private key of this keypair, with the
+    /// libp2p domain separation tag [`DST`].
+    pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        self.secret.sign_with_dst(msg, DST)
+    }
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keypair").field("public", &self.public).finish()
+    }
+}
+
+/// Promote a BLS12-381 secret key into a keypair.
+impl From<SecretKey> for Keypair {
+    fn from(secret: SecretKey) -> Keypair {
+        let public = PublicKey(secret.0.sk_to_pk());
+        Keypair { secret, public }
+    }
+}
+
+/// Demote a BLS12-381 keypair into a secret key.
+impl From<Keypair> for SecretKey {
+    fn from(kp: Keypair) -> SecretKey {
+        kp.secret
+    }
+}
+
+/// A BLS12-381 secret key.
+#[derive(Clone)]
+pub struct SecretKey(bls::SecretKey);
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretKey")
+    }
+}
+
+impl SecretKey {
+    /// Generate a new BLS12-381 secret key.
+    pub fn generate() -> SecretKey {
+        let mut ikm = [0; 32];
+        rand::thread_rng().fill_bytes(&mut ikm);
+        let sk = bls::SecretKey::key_gen(&ikm, &[])
+            .expect("32 bytes of key material suffice to derive a key");
+        ikm.zeroize();
+        SecretKey(sk)
+    }
+
+    /// Create a secret key from its big-endian byte representation, zeroing
+    /// the input on success, e.g. to use an existing validator key.
+    pub fn from_bytes(mut sk: impl AsMut<[u8]>) -> Result<SecretKey, DecodingError> {
+        let sk_bytes = sk.as_mut();
+        let secret = bls::SecretKey::from_bytes(sk_bytes)
+            .map_err(|e| DecodingError::new(format!("BLS12-381 secret key: {:?}", e)))?;
+        sk_bytes.zeroize();
+        Ok(SecretKey(secret))
+    }
+
+    /// Returns the big-endian byte representation of the secret key.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    /// Sign a message with the given domain separation tag, producing
+    /// a compressed signature.
+    pub fn sign_with_dst(&self, msg: &[u8], dst: &[u8]) -> Vec<u8> {
+        self.0.sign(msg, dst, &[]).compress().to_vec()
+    }
+}
+
+/// A BLS12-381 public key.
+#[derive(Clone)]
+pub struct PublicKey(bls::PublicKey);
+
+impl PublicKey {
+    /// Verify a signature on a message produced with the libp2p domain
+    /// separation tag [`DST`].
+    pub fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        self.verify_with_dst(msg, sig, DST)
+    }
+
+    /// Verify a compressed signature on a message produced with the given
+    /// domain separation tag.
+    pub fn verify_with_dst(&self, msg: &[u8], sig: &[u8], dst: &[u8]) -> bool {
+        bls::Signature::from_bytes(sig)
+            .map(|s| s.verify(true, msg, dst, &[], &self.0, false) == BLST_ERROR::BLST_SUCCESS)
+            .unwrap_or(false)
+    }
+
+    /// Encode the public key in compressed form.
+    pub fn encode(&self) -> [u8; 48] {
+        self.0.compress()
+    }
+
+    /// Decode a public key from the compressed form produced by `encode`,
+    /// rejecting points that are not in the prime-order subgroup, including
+    /// the point at infinity.
+    pub fn decode(k: &[u8]) -> Result<PublicKey, DecodingError> {
+        bls::PublicKey::key_validate(k)
+            .map_err(|e| DecodingError::new(format!("BLS12-381 public key: {:?}", e)))
+            .map(PublicKey)
+    }
+}
+
+impl PartialEq for PublicKey {
+    fn eq(&self, other: &PublicKey) -> bool {
+        self.encode()[..] == other.encode()[..]
+    }
+}
+
+impl Eq for PublicKey {}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PublicKey(")?;
+        for byte in self.encode().iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        f.write_str(")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bls_sign_verify() {
+        let kp = Keypair::generate();
+        let sig = kp.sign(b"message");
+        assert!(kp.public().verify(b"message", &sig));
+        assert!(!kp.public().verify(b"other message", &sig));
+
+        // Signatures with a different domain separation tag are not interchangeable.
+        let other = kp.secret().sign_with_dst(b"message", b"OTHER_DST_");
+        assert!(!kp.public().verify(b"message", &other));
+        assert!(kp.public().verify_with_dst(b"message", &other, b"OTHER_DST_"));
+    }
+
+    #[test]
+    fn bls_secret_from_bytes() {
+        let sk1 = SecretKey::generate();
+        let mut sk_bytes = sk1.to_bytes();
+        let sk2 = SecretKey::from_bytes(&mut sk_bytes).unwrap();
+        assert_eq!(sk1.to_bytes(), sk2.to_bytes());
+        assert_eq!(sk_bytes, [0; 32]);
+    }
+
+    #[test]
+    fn bls_public_key_encode_decode() {
+        let pk = Keypair::generate().public().clone();
+        assert_eq!(PublicKey::decode(&pk.encode()).unwrap(), pk);
+        assert!(PublicKey::decode(&[0; 48]).is_err());
+    }
+}
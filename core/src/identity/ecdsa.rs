@@ -0,0 +1,226 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! ECDSA keys with the NIST P-256 curve.
+
+use asn1_der::{DerObject, DerTag};
+use super::error::*;
+use super::pkcs8::{self, Algorithm};
+use ring::rand::SystemRandom;
+use ring::signature::{self, EcdsaKeyPair, ECDSA_P256_SHA256_ASN1, ECDSA_P256_SHA256_ASN1_SIGNING};
+use ring::signature::KeyPair;
+use std::{fmt, sync::Arc};
+use zeroize::{Zeroize, Zeroizing};
+
+/// The DER encoding of the start of a X.509 SubjectPublicKeyInfo structure
+/// of a P-256 public key, i.e. of the AlgorithmIdentifier with the object
+/// identifiers 'id-ecPublicKey' and 'prime256v1' defined in [RFC5480],
+/// followed by the header of the BIT STRING of the public key.
+///
+/// Since a public key is always an uncompressed point of 65 bytes, this
+/// prefix is the same for all public keys.
+///
+/// [RFC5480]: https://tools.ietf.org/html/rfc5480#section-2
+const SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01,
+    0x06, 0x08, 0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00
+];
+
+/// The length of an uncompressed point on the P-256 curve.
+const POINT_LEN: usize = 65;
+
+/// An ECDSA keypair.
+#[derive(Clone)]
+pub struct Keypair {
+    pair: Arc<EcdsaKeyPair>,
+    /// The PKCS#8 document of the keypair, since `EcdsaKeyPair`
+    /// cannot be encoded.
+    pkcs8: Arc<Zeroizing<Vec<u8>>>,
+    public: PublicKey
+}
+
+impl Keypair {
+    /// Generate a new ECDSA keypair.
+    pub fn generate() -> Keypair {
+        let rng = SystemRandom::new();
+        let mut pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .expect("ECDSA key generation failed.")
+            .as_ref()
+            .to_vec();
+        Keypair::from_pkcs8(&mut pkcs8).expect("generated PKCS#8 document is valid.")
+    }
+
+    /// Decode an ECDSA keypair from a DER-encoded private key in PKCS#8
+    /// PrivateKeyInfo format (i.e. unencrypted) as defined in [RFC5208],
+    /// which includes the public key.
+    ///
+    /// [RFC5208]: https://tools.ietf.org/html/rfc5208#section-5
+    pub fn from_pkcs8(der: &mut [u8]) -> Result<Keypair, DecodingError> {
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, der)
+            .map_err(|e| DecodingError::new("ECDSA PKCS#8 PrivateKeyInfo").source(e))?;
+        let pkcs8 = Arc::new(Zeroizing::new(der.to_vec()));
+        der.zeroize();
+        let public = PublicKey(pair.public_key().as_ref().to_vec());
+        Ok(Keypair { pair: Arc::new(pair), pkcs8, public })
+    }
+
+    /// Decode an ECDSA keypair from a DER-encoded private key in an ECPrivateKey
+    /// structure as defined in [RFC5915], which includes the public key.
+    ///
+    /// [RFC5915]: https://tools.ietf.org/html/rfc5915
+    pub fn from_der(der: &mut [u8]) -> Result<Keypair, DecodingError> {
+        let mut pkcs8 = pkcs8::encode(Algorithm::P256, der);
+        let keypair = Keypair::from_pkcs8(&mut pkcs8);
+        pkcs8.zeroize();
+        der.zeroize();
+        keypair
+    }
+
+    /// Encode the keypair in DER as a PKCS#8 PrivateKeyInfo structure.
+    /// See also `from_pkcs8`.
+    pub fn encode_pkcs8(&self) -> Vec<u8> {
+        self.pkcs8.to_vec()
+    }
+
+    /// Encode the keypair in DER as an ECPrivateKey structure, including
+    /// the curve and the public key. See also `from_der`.
+    pub fn encode_der(&self) -> Vec<u8> {
+        let (_, private_key) = pkcs8::decode(&self.pkcs8)
+            .expect("the PKCS#8 document of a keypair is valid");
+        let mut obj = pkcs8::deserialize_sequence(&private_key, "ECDSA ECPrivateKey")
+            .expect("the PKCS#8 document of a keypair is valid");
+        // The curve is optional inside a PKCS#8 document, but required by
+        // other implementations in an ECPrivateKey on its own.
+        if obj.get(2).map(|o| o.tag) != Some(DerTag::xa0) {
+            let curve = pkcs8::serialize(pkcs8::oid(pkcs8::OID_P256));
+            obj.insert(2, DerObject::new(DerTag::xa0, curve.into()));
+        }
+        let der = pkcs8::serialize(obj.clone());
+        obj.iter_mut().for_each(|o| o.value.data.zeroize());
+        der
+    }
+
+    /// Get the public key of this keypair.
+    pub fn public(&self) -> &PublicKey {
+        &self.public
+    }
+
+    /// Sign a message with this keypair, producing a DER-encoded signature.
+    pub fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError> {
+        let rng = SystemRandom::new();
+        self.pair.sign(&rng, msg)
+            .map(|sig| sig.as_ref().to_vec())
+            .map_err(|e| SigningError::new("ECDSA").source(e))
+    }
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keypair").field("public", &self.public).finish()
+    }
+}
+
+/// An ECDSA public key, i.e. an uncompressed point on the P-256 curve.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PublicKey(Vec<u8>);
+
+impl PublicKey {
+    /// Verify a DER-encoded ECDSA signature on a message using the public key.
+    pub fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        let key = signature::UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, &self.0);
+        key.verify(msg, sig).is_ok()
+    }
+
+    /// Encode the public key in DER as a X.509 SubjectPublicKeyInfo structure,
+    /// as defined in [RFC5480].
+    ///
+    /// [RFC5480]: https://tools.ietf.org/html/rfc5480#section-2
+    pub fn encode_x509(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(SPKI_PREFIX.len() + self.0.len());
+        buf.extend_from_slice(&SPKI_PREFIX);
+        buf.extend_from_slice(&self.0);
+        buf
+    }
+
+    /// Decode a public key from a DER-encoded X.509 SubjectPublicKeyInfo
+    /// structure. See also `encode_x509`.
+    pub fn decode_x509(pk: &[u8]) -> Result<PublicKey, DecodingError> {
+        if pk.len() != SPKI_PREFIX.len() + POINT_LEN || !pk.starts_with(&SPKI_PREFIX) {
+            return Err(DecodingError::new("ECDSA X.509: not a P-256 public key"))
+        }
+        let point = &pk[SPKI_PREFIX.len() ..];
+        if point[0] != 0x04 {
+            return Err(DecodingError::new("ECDSA X.509: not an uncompressed point"))
+        }
+        Ok(PublicKey(point.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    const KEY: &'static [u8] = include_bytes!("test/ecdsa-p256.pk8");
+    const KEY_X509: &'static [u8] = include_bytes!("test/ecdsa-p256.spki");
+    const KEY_DER: &'static [u8] = include_bytes!("test/ecdsa-p256.der");
+
+    #[test]
+    fn ecdsa_from_pkcs8() {
+        let kp = Keypair::from_pkcs8(&mut KEY.to_vec()).unwrap();
+        assert_eq!(kp.public().encode_x509(), KEY_X509);
+        assert_eq!(PublicKey::decode_x509(KEY_X509).unwrap(), *kp.public());
+        assert_eq!(kp.encode_der(), KEY_DER);
+        assert_eq!(Keypair::from_der(&mut KEY_DER.to_vec()).unwrap().public(), kp.public());
+    }
+
+    #[test]
+    fn ecdsa_der_encode_decode() {
+        let kp = Keypair::generate();
+        let mut der = kp.encode_der();
+        let kp2 = Keypair::from_der(&mut der).unwrap();
+        assert_eq!(kp2.public(), kp.public());
+        assert!(der.iter().all(|b| *b == 0));
+        assert_eq!(Keypair::from_pkcs8(&mut kp.encode_pkcs8()).unwrap().public(), kp.public());
+    }
+
+    #[test]
+    fn ecdsa_x509_encode_decode() {
+        let pk = Keypair::generate().public().clone();
+        assert_eq!(PublicKey::decode_x509(&pk.encode_x509()).unwrap(), pk);
+    }
+
+    #[test]
+    fn ecdsa_protobuf_encode_decode() {
+        let pk = crate::identity::PublicKey::Ecdsa(Keypair::generate().public().clone());
+        let decoded = crate::identity::PublicKey::from_protobuf_encoding(&pk.clone().into_protobuf_encoding());
+        assert_eq!(decoded.unwrap(), pk);
+    }
+
+    #[test]
+    fn ecdsa_sign_verify() {
+        fn prop(msg: Vec<u8>) -> Result<bool, SigningError> {
+            let kp = Keypair::generate();
+            let other = Keypair::generate();
+            kp.sign(&msg).map(|s| kp.public().verify(&msg, &s) && !other.public().verify(&msg, &s))
+        }
+        QuickCheck::new().tests(10).quickcheck(prop as fn(_) -> _);
+    }
+}
@@ -20,9 +20,11 @@
 
 //! Ed25519 keys.
 
+use asn1_der::{FromDerObject, IntoDerObject};
 use ed25519_dalek as ed25519;
 use rand::RngCore;
 use super::error::DecodingError;
+use super::pkcs8::{self, Algorithm};
 use zeroize::Zeroize;
 use core::fmt;
 
@@ -50,6 +52,31 @@ impl Keypair {
             .map_err(|e| DecodingError::new("Ed25519 keypair").source(e))
     }
 
+    /// Encode the keypair in DER as a PKCS#8 PrivateKeyInfo structure,
+    /// as defined in [RFC8410].
+    ///
+    /// [RFC8410]: https://tools.ietf.org/html/rfc8410#section-7
+    pub fn encode_pkcs8(&self) -> Vec<u8> {
+        let mut private_key = self.0.secret.to_bytes().to_vec().into_der_object();
+        let der = pkcs8::encode(Algorithm::Ed25519, &pkcs8::serialize(private_key.clone()));
+        private_key.value.data.zeroize();
+        der
+    }
+
+    /// Decode a keypair from a DER-encoded PKCS#8 PrivateKeyInfo structure,
+    /// zeroing the input on success. See also `encode_pkcs8`.
+    pub fn from_pkcs8(der: &mut [u8]) -> Result<Keypair, DecodingError> {
+        let (algorithm, private_key) = pkcs8::decode(der)?;
+        if algorithm != Algorithm::Ed25519 {
+            return Err(DecodingError::new("PKCS#8: not an Ed25519 key"))
+        }
+        let mut sk_bytes: Vec<u8> = FromDerObject::deserialize(private_key.iter())
+            .map_err(|e| DecodingError::new("Ed25519 PKCS#8 CurvePrivateKey").source(e))?;
+        let secret = SecretKey::from_bytes(&mut sk_bytes)?;
+        der.zeroize();
+        Ok(Keypair::from(secret))
+    }
+
     /// Sign a message using the private key of this keypair.
     pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
         self.0.sign(msg).to_bytes().to_vec()
@@ -122,6 +149,48 @@ impl PublicKey {
             .map_err(|e| DecodingError::new("Ed25519 public key").source(e))
             .map(PublicKey)
     }
+
+    /// Encode the public key in DER as a X.509 SubjectPublicKeyInfo structure,
+    /// as defined in [RFC8410].
+    ///
+    /// [RFC8410]: https://tools.ietf.org/html/rfc8410#section-4
+    pub fn encode_x509(&self) -> Vec<u8> {
+        pkcs8::encode_spki(Algorithm::Ed25519, &self.encode())
+    }
+
+    /// Decode a public key from a DER-encoded X.509 SubjectPublicKeyInfo
+    /// structure. See also `encode_x509`.
+    pub fn decode_x509(pk: &[u8]) -> Result<PublicKey, DecodingError> {
+        match pkcs8::decode_spki(pk)? {
+            (Algorithm::Ed25519, key) => PublicKey::decode(&key),
+            _ => Err(DecodingError::new("Ed25519 X.509: not an Ed25519 public key"))
+        }
+    }
+}
+
+/// Verify many Ed25519 signatures at once, returning whether all of them are
+/// valid for their `(public key, message, signature)`.
+///
+/// This checks a random linear combination of the verification equations,
+/// which is considerably faster than verifying the signatures one by one.
+///
+/// > **Note**: Unlike individual verification, the batch equation may hold
+/// > for a signature whose `R` has a small-order component, i.e. the batch
+/// > can pass even though such a signature fails [`PublicKey::verify`].
+/// > Honest signers never produce such signatures.
+pub fn verify_batch(items: &[(&PublicKey, &[u8], &[u8])]) -> bool {
+    let mut messages = Vec::with_capacity(items.len());
+    let mut signatures = Vec::with_capacity(items.len());
+    let mut public_keys = Vec::with_capacity(items.len());
+    for (pk, msg, sig) in items {
+        match ed25519::Signature::from_bytes(sig) {
+            Ok(sig) => signatures.push(sig),
+            Err(_) => return false
+        }
+        messages.push(*msg);
+        public_keys.push(pk.0);
+    }
+    ed25519::verify_batch(&messages, &signatures, &public_keys).is_ok()
 }
 
 /// An Ed25519 secret key.
@@ -174,6 +243,9 @@ mod tests {
     use super::*;
     use quickcheck::*;
 
+    const KEY: &'static [u8] = include_bytes!("test/ed25519.pk8");
+    const KEY_PUBLIC: &'static [u8] = include_bytes!("test/ed25519.pub");
+
     fn eq_keypairs(kp1: &Keypair, kp2: &Keypair) -> bool {
         kp1.public() == kp2.public()
             &&
@@ -193,6 +265,13 @@ mod tests {
         QuickCheck::new().tests(10).quickcheck(prop as fn() -> _);
     }
 
+    #[test]
+    fn ed25519_keypair_from_pkcs8() {
+        let kp = Keypair::from_pkcs8(&mut KEY.to_vec()).unwrap();
+        assert_eq!(&kp.public().encode()[..], KEY_PUBLIC);
+        assert_eq!(kp.encode_pkcs8(), KEY);
+    }
+
     #[test]
     fn ed25519_keypair_from_secret() {
         fn prop() -> bool {
@@ -222,4 +301,22 @@ mod tests {
         let invalid_msg = "h3ll0 w0rld".as_bytes();
         assert!(!pk.verify(invalid_msg, &sig));
     }
+
+    #[test]
+    fn ed25519_verify_batch() {
+        let keys = (0 .. 8).map(|_| Keypair::generate()).collect::<Vec<_>>();
+        let msgs = (0 .. 8u8).map(|i| vec![i; i as usize]).collect::<Vec<_>>();
+        let sigs = keys.iter().zip(&msgs).map(|(k, m)| k.sign(m)).collect::<Vec<_>>();
+        let pks = keys.iter().map(Keypair::public).collect::<Vec<_>>();
+        let mut items = pks.iter().zip(&msgs).zip(&sigs)
+            .map(|((pk, msg), sig)| (pk, &msg[..], &sig[..]))
+            .collect::<Vec<_>>();
+        assert!(verify_batch(&items));
+        assert!(verify_batch(&[]));
+
+        // Swapping the messages of two signatures invalidates the batch.
+        items[1].1 = &msgs[2];
+        items[2].1 = &msgs[1];
+        assert!(!verify_batch(&items));
+    }
 }
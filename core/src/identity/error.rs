@@ -59,13 +59,15 @@ pub struct SigningError {
     source: Option<Box<dyn Error + Send + Sync>>
 }
 
-/// An error during encoding of key material.
 impl SigningError {
-    pub(crate) fn new<S: ToString>(msg: S) -> Self {
+    /// Creates a new signing error with the given message, e.g. for
+    /// implementations of [`Signer`](super::Signer).
+    pub fn new<S: ToString>(msg: S) -> Self {
         Self { msg: msg.to_string(), source: None }
     }
 
-    pub(crate) fn source(self, source: impl Error + Send + Sync + 'static) -> Self {
+    /// Sets the underlying cause of the signing error.
+    pub fn source(self, source: impl Error + Send + Sync + 'static) -> Self {
         Self { source: Some(Box::new(source)), .. self }
     }
 }
@@ -82,3 +84,22 @@ impl Error for SigningError {
     }
 }
 
+/// An error during encoding of key material.
+#[derive(Debug)]
+pub struct EncodingError {
+    msg: String
+}
+
+impl EncodingError {
+    pub(crate) fn new<S: ToString>(msg: S) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Key encoding error: {}", self.msg)
+    }
+}
+
+impl Error for EncodingError {}
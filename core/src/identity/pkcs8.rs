@@ -0,0 +1,258 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! PKCS#8 encoding of private keys, X.509 encoding of public keys and PEM.
+
+use asn1_der::{FromDerObject, IntoDerObject, DerObject, DerTag};
+use super::error::DecodingError;
+use zeroize::{Zeroize, Zeroizing};
+
+/// The DER encoding of the object identifier 'rsaEncryption' of [RFC3279].
+///
+/// [RFC3279]: https://tools.ietf.org/html/rfc3279#section-2.3.1
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01];
+
+/// The DER encoding of the object identifier 'id-Ed25519' of [RFC8410].
+///
+/// [RFC8410]: https://tools.ietf.org/html/rfc8410#section-3
+const OID_ED25519: &[u8] = &[0x2B, 0x65, 0x70];
+
+/// The DER encoding of the object identifier 'id-ecPublicKey' of [RFC5480].
+///
+/// [RFC5480]: https://tools.ietf.org/html/rfc5480#section-2.1.1
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+
+/// The DER encoding of the object identifier 'secp256r1' (P-256) of [RFC5480].
+///
+/// [RFC5480]: https://tools.ietf.org/html/rfc5480#section-2.1.1.1
+pub(crate) const OID_P256: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+
+/// The DER encoding of the object identifier 'secp256k1' of [SEC2].
+///
+/// [SEC2]: https://www.secg.org/sec2-v2.pdf
+pub(crate) const OID_SECP256K1: &[u8] = &[0x2B, 0x81, 0x04, 0x00, 0x0A];
+
+/// The PEM label of a PKCS#8 PrivateKeyInfo.
+pub(crate) const PEM_PKCS8: &str = "PRIVATE KEY";
+
+/// The PEM label of a PKCS#1 RSAPrivateKey.
+pub(crate) const PEM_RSA: &str = "RSA PRIVATE KEY";
+
+/// The PEM label of an ECPrivateKey as defined in [RFC5915].
+///
+/// [RFC5915]: https://tools.ietf.org/html/rfc5915
+pub(crate) const PEM_EC: &str = "EC PRIVATE KEY";
+
+/// The PEM label of a X.509 SubjectPublicKeyInfo.
+pub(crate) const PEM_PUBLIC_KEY: &str = "PUBLIC KEY";
+
+/// The algorithm of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Algorithm {
+    Rsa,
+    Ed25519,
+    P256,
+    Secp256k1,
+}
+
+/// Returns the DER encoding of an object identifier.
+pub(crate) fn oid(oid: &[u8]) -> DerObject {
+    DerObject::new(DerTag::x06, oid.to_vec().into())
+}
+
+/// Serializes a DER object.
+pub(crate) fn serialize(obj: impl IntoDerObject) -> Vec<u8> {
+    let mut buf = vec![0u8; obj.serialized_len()];
+    obj.serialize(buf.iter_mut()).map(|_| buf)
+        .expect("the buffer has the serialized length")
+}
+
+/// Returns the elements of a DER-encoded SEQUENCE.
+pub(crate) fn deserialize_sequence(der: &[u8], what: &str) -> Result<Vec<DerObject>, DecodingError> {
+    FromDerObject::deserialize(der.iter())
+        .map_err(|e| DecodingError::new(what).source(e))
+}
+
+/// Encode a private key of the given algorithm in DER as a PKCS#8
+/// PrivateKeyInfo structure, as defined in [RFC5208].
+///
+/// [RFC5208]: https://tools.ietf.org/html/rfc5208#section-5
+pub(crate) fn encode(algorithm: Algorithm, private_key: &[u8]) -> Vec<u8> {
+    let mut info = vec![
+        DerObject::new(DerTag::Integer, vec![0].into()),
+        identifier(algorithm),
+        DerObject::new(DerTag::OctetString, private_key.to_vec().into()),
+    ];
+    let der = serialize(info.clone());
+    info[2].value.data.zeroize();
+    der
+}
+
+/// Decode a DER-encoded PKCS#8 PrivateKeyInfo structure into the
+/// algorithm and the encoding of the private key. See also `encode`.
+pub(crate) fn decode(der: &[u8]) -> Result<(Algorithm, Zeroizing<Vec<u8>>), DecodingError> {
+    let mut info = deserialize_sequence(der, "PKCS#8 PrivateKeyInfo")?;
+    if info.len() < 3 || info[0].tag != DerTag::Integer || info[2].tag != DerTag::OctetString {
+        info.iter_mut().for_each(|obj| obj.value.data.zeroize());
+        return Err(DecodingError::new("PKCS#8 PrivateKeyInfo"))
+    }
+    let private_key = Zeroizing::new(std::mem::take(&mut info[2].value.data));
+    let algorithm = algorithm(info[1].clone(), "PKCS#8")?;
+    Ok((algorithm, private_key))
+}
+
+/// Encode a public key of the given algorithm in DER as a X.509
+/// SubjectPublicKeyInfo structure, as defined in [RFC5280].
+///
+/// [RFC5280]: https://tools.ietf.org/html/rfc5280#section-4.1
+pub(crate) fn encode_spki(algorithm: Algorithm, public_key: &[u8]) -> Vec<u8> {
+    let mut bits = Vec::with_capacity(public_key.len() + 1);
+    bits.push(0); // The number of unused bits.
+    bits.extend_from_slice(public_key);
+    serialize(vec![identifier(algorithm), DerObject::new(DerTag::x03, bits.into())])
+}
+
+/// Decode a DER-encoded X.509 SubjectPublicKeyInfo structure into the
+/// algorithm and the encoding of the public key. See also `encode_spki`.
+pub(crate) fn decode_spki(der: &[u8]) -> Result<(Algorithm, Vec<u8>), DecodingError> {
+    let mut info = deserialize_sequence(der, "X.509 SubjectPublicKeyInfo")?;
+    if info.len() != 2 || info[1].tag != DerTag::x03 || info[1].value.data.first() != Some(&0) {
+        return Err(DecodingError::new("X.509 SubjectPublicKeyInfo"))
+    }
+    let algorithm = algorithm(info[0].clone(), "X.509")?;
+    let public_key = std::mem::take(&mut info[1].value.data).split_off(1);
+    Ok((algorithm, public_key))
+}
+
+/// Returns the DER-encoded AlgorithmIdentifier of an algorithm.
+fn identifier(algorithm: Algorithm) -> DerObject {
+    let identifier = match algorithm {
+        Algorithm::Rsa => vec![oid(OID_RSA_ENCRYPTION), ().into_der_object()],
+        Algorithm::Ed25519 => vec![oid(OID_ED25519)],
+        Algorithm::P256 => vec![oid(OID_EC_PUBLIC_KEY), oid(OID_P256)],
+        Algorithm::Secp256k1 => vec![oid(OID_EC_PUBLIC_KEY), oid(OID_SECP256K1)],
+    };
+    identifier.into_der_object()
+}
+
+/// Returns the algorithm of a DER-encoded AlgorithmIdentifier.
+fn algorithm(identifier: DerObject, what: &str) -> Result<Algorithm, DecodingError> {
+    let identifier = Vec::<DerObject>::from_der_object(identifier)
+        .map_err(|e| DecodingError::new(format!("{} AlgorithmIdentifier", what)).source(e))?;
+    let is = |i: usize, expected: &[u8]| identifier.get(i) == Some(&oid(expected));
+    if is(0, OID_RSA_ENCRYPTION) {
+        Ok(Algorithm::Rsa)
+    } else if is(0, OID_ED25519) {
+        Ok(Algorithm::Ed25519)
+    } else if is(0, OID_EC_PUBLIC_KEY) && is(1, OID_P256) {
+        Ok(Algorithm::P256)
+    } else if is(0, OID_EC_PUBLIC_KEY) && is(1, OID_SECP256K1) {
+        Ok(Algorithm::Secp256k1)
+    } else {
+        Err(DecodingError::new(format!("{}: unsupported key algorithm", what)))
+    }
+}
+
+/// Returns the curve of a DER-encoded ECPrivateKey structure as defined
+/// in [RFC5915].
+///
+/// [RFC5915]: https://tools.ietf.org/html/rfc5915
+pub(crate) fn ec_curve(der: &[u8]) -> Result<Algorithm, DecodingError> {
+    let mut obj = deserialize_sequence(der, "ECPrivateKey")?;
+    let curve = obj.iter().find(|o| o.tag == DerTag::xa0).map(|o| o.value.data.clone());
+    obj.iter_mut().for_each(|o| o.value.data.zeroize());
+    match curve {
+        Some(ref c) if *c == serialize(oid(OID_P256)) => Ok(Algorithm::P256),
+        Some(ref c) if *c == serialize(oid(OID_SECP256K1)) => Ok(Algorithm::Secp256k1),
+        _ => Err(DecodingError::new("ECPrivateKey: unsupported curve"))
+    }
+}
+
+/// Encode DER-encoded data as PEM with the given label, as defined in [RFC7468].
+///
+/// [RFC7468]: https://tools.ietf.org/html/rfc7468
+pub(crate) fn encode_pem(label: &str, der: &[u8]) -> String {
+    let base64 = Zeroizing::new(base64::encode(der));
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in base64.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+/// Decode the first PEM-encoded document of `pem` into its label and the
+/// DER-encoded data. See also `encode_pem`.
+pub(crate) fn decode_pem(pem: &str) -> Result<(String, Zeroizing<Vec<u8>>), DecodingError> {
+    let mut lines = pem.lines().map(str::trim).skip_while(|l| !l.starts_with("-----BEGIN "));
+    let label = lines.next()
+        .and_then(|l| l.strip_prefix("-----BEGIN "))
+        .and_then(|l| l.strip_suffix("-----"))
+        .ok_or_else(|| DecodingError::new("PEM: missing BEGIN line"))?
+        .to_string();
+    let end = format!("-----END {}-----", label);
+    let mut base64 = Zeroizing::new(String::new());
+    loop {
+        match lines.next() {
+            Some(l) if l == end => break,
+            Some(l) => base64.push_str(l),
+            None => return Err(DecodingError::new("PEM: missing END line"))
+        }
+    }
+    let der = base64::decode(base64.as_bytes())
+        .map_err(|e| DecodingError::new("PEM").source(e))?;
+    Ok((label, Zeroizing::new(der)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkcs8_encode_decode() {
+        for algorithm in &[Algorithm::Rsa, Algorithm::Ed25519, Algorithm::P256, Algorithm::Secp256k1] {
+            let der = encode(*algorithm, b"private key");
+            let (decoded, private_key) = decode(&der).unwrap();
+            assert_eq!(decoded, *algorithm);
+            assert_eq!(&private_key[..], b"private key");
+        }
+    }
+
+    #[test]
+    fn spki_encode_decode() {
+        for algorithm in &[Algorithm::Rsa, Algorithm::Ed25519, Algorithm::P256, Algorithm::Secp256k1] {
+            let der = encode_spki(*algorithm, b"public key");
+            let (decoded, public_key) = decode_spki(&der).unwrap();
+            assert_eq!(decoded, *algorithm);
+            assert_eq!(&public_key[..], b"public key");
+        }
+    }
+
+    #[test]
+    fn pem_encode_decode() {
+        let der = (0 ..= 255).collect::<Vec<u8>>();
+        let pem = encode_pem(PEM_PKCS8, &der);
+        assert!(pem.lines().all(|l| l.len() <= 64));
+        let (label, decoded) = decode_pem(&format!("comment\n{}", pem)).unwrap();
+        assert_eq!(label, PEM_PKCS8);
+        assert_eq!(&decoded[..], &der[..]);
+    }
+}
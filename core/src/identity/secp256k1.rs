@@ -20,11 +20,12 @@
 
 //! Secp256k1 keys.
 
-use asn1_der::{FromDerObject, DerObject};
+use asn1_der::{FromDerObject, IntoDerObject, DerObject, DerTag};
 use rand::RngCore;
 use sha2::{Digest as ShaDigestTrait, Sha256};
 use secp256k1::{Message, Signature};
 use super::error::{DecodingError, SigningError};
+use super::pkcs8::{self, Algorithm};
 use zeroize::Zeroize;
 use core::fmt;
 
@@ -50,6 +51,29 @@ impl Keypair {
     pub fn secret(&self) -> &SecretKey {
         &self.secret
     }
+
+    /// Encode the keypair in DER as a PKCS#8 PrivateKeyInfo structure,
+    /// as defined in [RFC5208].
+    ///
+    /// [RFC5208]: https://tools.ietf.org/html/rfc5208#section-5
+    pub fn encode_pkcs8(&self) -> Vec<u8> {
+        let mut der = self.secret.encode_der();
+        let pkcs8 = pkcs8::encode(Algorithm::Secp256k1, &der);
+        der.zeroize();
+        pkcs8
+    }
+
+    /// Decode a keypair from a DER-encoded PKCS#8 PrivateKeyInfo structure,
+    /// zeroing the input on success. See also `encode_pkcs8`.
+    pub fn from_pkcs8(der: &mut [u8]) -> Result<Keypair, DecodingError> {
+        let (algorithm, mut private_key) = pkcs8::decode(der)?;
+        if algorithm != Algorithm::Secp256k1 {
+            return Err(DecodingError::new("PKCS#8: not a Secp256k1 key"))
+        }
+        let secret = SecretKey::from_der(&mut *private_key)?;
+        der.zeroize();
+        Ok(Keypair::from(secret))
+    }
 }
 
 impl fmt::Debug for Keypair {
@@ -128,6 +152,25 @@ impl SecretKey {
         Ok(sk)
     }
 
+    /// Encode the secret key in DER as an ECPrivateKey structure, as defined
+    /// in [RFC5915], including the curve and the public key.
+    ///
+    /// [RFC5915]: https://tools.ietf.org/html/rfc5915
+    pub fn encode_der(&self) -> Vec<u8> {
+        let mut public_key = vec![0u8];
+        public_key.extend_from_slice(&secp256k1::PublicKey::from_secret_key(&self.0).serialize());
+        let mut obj = vec![
+            DerObject::new(DerTag::Integer, vec![1].into()),
+            self.to_bytes().to_vec().into_der_object(),
+            DerObject::new(DerTag::xa0, pkcs8::serialize(pkcs8::oid(pkcs8::OID_SECP256K1)).into()),
+            DerObject::new(DerTag::xa1, pkcs8::serialize(DerObject::new(DerTag::x03, public_key.into()))
+                .into()),
+        ];
+        let der = pkcs8::serialize(obj.clone());
+        obj[1].value.data.zeroize();
+        der
+    }
+
     /// Sign a message with this secret key, producing a DER-encoded
     /// ECDSA signature, as defined in [RFC3278].
     ///
@@ -185,12 +228,36 @@ impl PublicKey {
             .map_err(|_| DecodingError::new("failed to parse secp256k1 public key"))
             .map(PublicKey)
     }
+
+    /// Encode the public key in DER as a X.509 SubjectPublicKeyInfo structure
+    /// with an uncompressed point, as defined in [RFC5480].
+    ///
+    /// [RFC5480]: https://tools.ietf.org/html/rfc5480#section-2
+    pub fn encode_x509(&self) -> Vec<u8> {
+        pkcs8::encode_spki(Algorithm::Secp256k1, &self.encode_uncompressed())
+    }
+
+    /// Decode a public key from a DER-encoded X.509 SubjectPublicKeyInfo
+    /// structure with either a compressed or an uncompressed point. See also
+    /// `encode_x509`.
+    pub fn decode_x509(pk: &[u8]) -> Result<PublicKey, DecodingError> {
+        match pkcs8::decode_spki(pk)? {
+            (Algorithm::Secp256k1, key) => secp256k1::PublicKey::parse_slice(&key, None)
+                .map_err(|_| DecodingError::new("Secp256k1 X.509: invalid point"))
+                .map(PublicKey),
+            _ => Err(DecodingError::new("Secp256k1 X.509: not a secp256k1 public key"))
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const KEY: &'static [u8] = include_bytes!("test/secp256k1.pk8");
+    const KEY_DER: &'static [u8] = include_bytes!("test/secp256k1.der");
+    const KEY_PUBLIC: &'static [u8] = include_bytes!("test/secp256k1.pub");
+
     #[test]
     fn secp256k1_secret_from_bytes() {
         let sk1 = SecretKey::generate();
@@ -200,4 +267,18 @@ mod tests {
         assert_eq!(sk1.0.serialize(), sk2.0.serialize());
         assert_eq!(sk_bytes, [0; 32]);
     }
+
+    #[test]
+    fn secp256k1_keypair_from_pkcs8() {
+        let kp = Keypair::from_pkcs8(&mut KEY.to_vec()).unwrap();
+        assert_eq!(&kp.public().encode()[..], KEY_PUBLIC);
+        let kp2 = Keypair::from_pkcs8(&mut kp.encode_pkcs8()).unwrap();
+        assert_eq!(kp2.public(), kp.public());
+    }
+
+    #[test]
+    fn secp256k1_secret_der_encode_decode() {
+        let sk = SecretKey::from_der(&mut KEY_DER.to_vec()).unwrap();
+        assert_eq!(sk.encode_der(), KEY_DER);
+    }
 }
@@ -0,0 +1,385 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+//! Rotation of identity keys.
+//!
+//! Since the [`PeerId`] of a node is derived from its identity key, replacing the key changes
+//! the identity of the node. A [`KeyRotation`] is a statement signed with both the old and the
+//! new key which links the two identities, so that a long-lived node can retire a key, e.g. a
+//! compromised one, and other nodes can carry over the state they associate with the old
+//! `PeerId`, such as its reputation or routing information.
+//!
+//! The successive rotations of a node form an [`IdentityChain`], which other nodes can verify
+//! to check that the current `PeerId` of a node descends from one they already know.
+//!
+//! # Example
+//!
+//! ```
+//! use libp2p_core::identity::Keypair;
+//! use libp2p_core::key_rotation::{IdentityChain, KeyRotation};
+//!
+//! let old = Keypair::generate_ed25519();
+//! let mut chain = IdentityChain::new(old.public());
+//!
+//! let new = Keypair::generate_ed25519();
+//! chain.push(KeyRotation::new(&old, &new).unwrap()).unwrap();
+//!
+//! // A remote node receiving the chain verifies it while decoding it.
+//! let chain = IdentityChain::from_protobuf_encoding(&chain.into_protobuf_encoding()).unwrap();
+//! assert_eq!(chain.current_peer_id(), new.public().into_peer_id());
+//! assert!(chain.contains(&old.public().into_peer_id()));
+//! ```
+
+use crate::identity::{Keypair, PublicKey, error::{DecodingError, SigningError}};
+use crate::signed_envelope::length_prefixed;
+use crate::{PeerId, key_rotation_proto};
+use prost::Message;
+use std::{error, fmt, time::SystemTime};
+
+/// The domain separation string of key rotation statements.
+const DOMAIN_SEP: &[u8] = b"libp2p-key-rotation";
+
+/// A statement that a node replaced its identity key, signed with both keys.
+///
+/// The signature of the old key proves that its owner delegated its identity to the new key,
+/// and the signature of the new key proves that the delegation was accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRotation {
+    old_key: PublicKey,
+    new_key: PublicKey,
+    timestamp: u64,
+    old_signature: Vec<u8>,
+    new_signature: Vec<u8>,
+}
+
+impl KeyRotation {
+    /// Builds a statement that `old` is replaced with `new`, signed with both keypairs.
+    pub fn new(old: &Keypair, new: &Keypair) -> Result<Self, SigningError> {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("now() is never before UNIX_EPOCH")
+            .as_secs();
+        KeyRotation::with_timestamp(old, new, timestamp)
+    }
+
+    /// Builds a statement that `old` is replaced with `new` at the given time, in seconds since
+    /// the UNIX epoch, signed with both keypairs.
+    pub fn with_timestamp(old: &Keypair, new: &Keypair, timestamp: u64)
+        -> Result<Self, SigningError>
+    {
+        let old_key = old.public();
+        let new_key = new.public();
+        let msg = statement(&old_key, &new_key, timestamp);
+        Ok(KeyRotation {
+            old_signature: old.sign(&msg)?,
+            new_signature: new.sign(&msg)?,
+            old_key,
+            new_key,
+            timestamp,
+        })
+    }
+
+    /// Checks the signatures of both keys.
+    pub fn verify(&self) -> bool {
+        let msg = statement(&self.old_key, &self.new_key, self.timestamp);
+        self.old_key.verify(&msg, &self.old_signature)
+            && self.new_key.verify(&msg, &self.new_signature)
+    }
+
+    /// Returns the retired key.
+    pub fn old_key(&self) -> &PublicKey {
+        &self.old_key
+    }
+
+    /// Returns the key replacing `old_key`.
+    pub fn new_key(&self) -> &PublicKey {
+        &self.new_key
+    }
+
+    /// Returns the `PeerId` of the retired key.
+    pub fn old_peer_id(&self) -> PeerId {
+        self.old_key.clone().into_peer_id()
+    }
+
+    /// Returns the `PeerId` of the key replacing `old_key`.
+    pub fn new_peer_id(&self) -> PeerId {
+        self.new_key.clone().into_peer_id()
+    }
+
+    /// Returns the time of the rotation, in seconds since the UNIX epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Encodes the statement into its protobuf representation.
+    pub fn into_protobuf_encoding(self) -> Vec<u8> {
+        encode(&self.into_proto())
+    }
+
+    /// Decodes a statement from its protobuf representation, checking its signatures.
+    pub fn from_protobuf_encoding(bytes: &[u8]) -> Result<Self, RotationError> {
+        let rotation = key_rotation_proto::KeyRotation::decode(bytes)
+            .map_err(|e| DecodingError::new("KeyRotation").source(e))?;
+        KeyRotation::from_proto(rotation)
+    }
+
+    fn into_proto(self) -> key_rotation_proto::KeyRotation {
+        key_rotation_proto::KeyRotation {
+            old_public_key: self.old_key.into_protobuf_encoding(),
+            new_public_key: self.new_key.into_protobuf_encoding(),
+            timestamp: self.timestamp,
+            old_signature: self.old_signature,
+            new_signature: self.new_signature,
+        }
+    }
+
+    fn from_proto(rotation: key_rotation_proto::KeyRotation) -> Result<Self, RotationError> {
+        let rotation = KeyRotation {
+            old_key: PublicKey::from_protobuf_encoding(&rotation.old_public_key)?,
+            new_key: PublicKey::from_protobuf_encoding(&rotation.new_public_key)?,
+            timestamp: rotation.timestamp,
+            old_signature: rotation.old_signature,
+            new_signature: rotation.new_signature,
+        };
+        if rotation.verify() {
+            Ok(rotation)
+        } else {
+            Err(RotationError::InvalidSignature)
+        }
+    }
+}
+
+/// The successive identity keys of a node, linked by [`KeyRotation`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityChain {
+    original: PublicKey,
+    rotations: Vec<KeyRotation>,
+}
+
+impl IdentityChain {
+    /// Creates a chain starting at the given key, without rotations.
+    pub fn new(original: PublicKey) -> Self {
+        IdentityChain { original, rotations: Vec::new() }
+    }
+
+    /// Appends a rotation to the chain.
+    ///
+    /// The rotation must be validly signed, retire the current key of the chain and not be
+    /// older than the last rotation of the chain.
+    pub fn push(&mut self, rotation: KeyRotation) -> Result<(), RotationError> {
+        if !rotation.verify() {
+            return Err(RotationError::InvalidSignature)
+        }
+        if rotation.old_key() != self.current_key() {
+            return Err(RotationError::Discontinuous)
+        }
+        if self.rotations.last().map_or(false, |last| last.timestamp > rotation.timestamp) {
+            return Err(RotationError::OutOfOrder)
+        }
+        self.rotations.push(rotation);
+        Ok(())
+    }
+
+    /// Returns the first key of the chain.
+    pub fn original_key(&self) -> &PublicKey {
+        &self.original
+    }
+
+    /// Returns the last key of the chain, i.e. the key currently used by the node.
+    pub fn current_key(&self) -> &PublicKey {
+        self.rotations.last().map_or(&self.original, |r| &r.new_key)
+    }
+
+    /// Returns the `PeerId` of the first key of the chain.
+    pub fn original_peer_id(&self) -> PeerId {
+        self.original.clone().into_peer_id()
+    }
+
+    /// Returns the `PeerId` of the last key of the chain.
+    pub fn current_peer_id(&self) -> PeerId {
+        self.current_key().clone().into_peer_id()
+    }
+
+    /// Returns the rotations of the chain, oldest first.
+    pub fn rotations(&self) -> &[KeyRotation] {
+        &self.rotations
+    }
+
+    /// Returns the `PeerId`s of all the keys of the chain, oldest first.
+    pub fn peer_ids(&self) -> impl Iterator<Item = PeerId> + '_ {
+        std::iter::once(&self.original)
+            .chain(self.rotations.iter().map(|r| &r.new_key))
+            .map(|key| key.clone().into_peer_id())
+    }
+
+    /// Returns true if `peer_id` is the `PeerId` of one of the keys of the chain, i.e. if the
+    /// node now known by `current_peer_id` was once known by `peer_id`.
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.peer_ids().any(|p| &p == peer_id)
+    }
+
+    /// Encodes the chain into its protobuf representation.
+    pub fn into_protobuf_encoding(self) -> Vec<u8> {
+        encode(&key_rotation_proto::IdentityChain {
+            original_public_key: self.original.into_protobuf_encoding(),
+            rotations: self.rotations.into_iter().map(KeyRotation::into_proto).collect(),
+        })
+    }
+
+    /// Decodes a chain from its protobuf representation, verifying all of its rotations.
+    pub fn from_protobuf_encoding(bytes: &[u8]) -> Result<Self, RotationError> {
+        let chain = key_rotation_proto::IdentityChain::decode(bytes)
+            .map_err(|e| DecodingError::new("IdentityChain").source(e))?;
+        let original = PublicKey::from_protobuf_encoding(&chain.original_public_key)?;
+        let mut identity = IdentityChain::new(original);
+        for rotation in chain.rotations {
+            identity.push(KeyRotation::from_proto(rotation)?)?;
+        }
+        Ok(identity)
+    }
+}
+
+/// Builds the message signed by both keys of a rotation.
+fn statement(old_key: &PublicKey, new_key: &PublicKey, timestamp: u64) -> Vec<u8> {
+    length_prefixed(&[
+        DOMAIN_SEP,
+        &old_key.clone().into_protobuf_encoding(),
+        &new_key.clone().into_protobuf_encoding(),
+        &timestamp.to_be_bytes(),
+    ])
+}
+
+fn encode(msg: &impl Message) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(msg.encoded_len());
+    msg.encode(&mut buf).expect("Vec<u8> provides capacity as needed");
+    buf
+}
+
+/// An error while verifying a [`KeyRotation`] or an [`IdentityChain`].
+#[derive(Debug)]
+pub enum RotationError {
+    /// The encoding of the rotation or chain is invalid.
+    Decoding(DecodingError),
+    /// A rotation isn't validly signed by both of its keys.
+    InvalidSignature,
+    /// A rotation doesn't retire the key introduced by the previous one.
+    Discontinuous,
+    /// A rotation is older than the previous one.
+    OutOfOrder,
+}
+
+impl From<DecodingError> for RotationError {
+    fn from(e: DecodingError) -> Self {
+        RotationError::Decoding(e)
+    }
+}
+
+impl fmt::Display for RotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RotationError::Decoding(e) => write!(f, "Failed to decode key rotation: {}", e),
+            RotationError::InvalidSignature => write!(f, "Invalid key rotation signature"),
+            RotationError::Discontinuous => write!(f, "Key rotation doesn't retire the current key"),
+            RotationError::OutOfOrder => write!(f, "Key rotation is older than the previous one"),
+        }
+    }
+}
+
+impl error::Error for RotationError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RotationError::Decoding(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_roundtrip() {
+        let old = Keypair::generate_ed25519();
+        let new = Keypair::generate_ed25519();
+        let rotation = KeyRotation::new(&old, &new).unwrap();
+        assert!(rotation.verify());
+        assert_eq!(rotation.old_peer_id(), old.public().into_peer_id());
+        assert_eq!(rotation.new_peer_id(), new.public().into_peer_id());
+
+        let decoded = KeyRotation::from_protobuf_encoding(&rotation.clone().into_protobuf_encoding())
+            .unwrap();
+        assert_eq!(decoded, rotation);
+    }
+
+    #[test]
+    fn forged_rotation_is_rejected() {
+        let old = Keypair::generate_ed25519();
+        let new = Keypair::generate_ed25519();
+        let attacker = Keypair::generate_ed25519();
+
+        // Substituting the new key invalidates the signature of the old key.
+        let mut rotation = KeyRotation::new(&old, &new).unwrap();
+        rotation.new_key = attacker.public();
+        assert!(!rotation.verify());
+
+        // A rotation must be signed with the old key, not only the new one.
+        let mut rotation = KeyRotation::with_timestamp(&attacker, &new, 1).unwrap();
+        rotation.old_key = old.public();
+        assert!(!rotation.verify());
+        match KeyRotation::from_protobuf_encoding(&rotation.into_protobuf_encoding()) {
+            Err(RotationError::InvalidSignature) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn chain_roundtrip() {
+        let keys = (0 .. 4).map(|_| Keypair::generate_ed25519()).collect::<Vec<_>>();
+        let mut chain = IdentityChain::new(keys[0].public());
+        for (i, pair) in keys.windows(2).enumerate() {
+            chain.push(KeyRotation::with_timestamp(&pair[0], &pair[1], i as u64).unwrap()).unwrap();
+        }
+        assert_eq!(chain.original_peer_id(), keys[0].public().into_peer_id());
+        assert_eq!(chain.current_peer_id(), keys[3].public().into_peer_id());
+        assert!(keys.iter().all(|k| chain.contains(&k.public().into_peer_id())));
+        assert!(!chain.contains(&PeerId::random()));
+
+        let decoded = IdentityChain::from_protobuf_encoding(&chain.clone().into_protobuf_encoding())
+            .unwrap();
+        assert_eq!(decoded, chain);
+    }
+
+    #[test]
+    fn broken_chain_is_rejected() {
+        let keys = (0 .. 3).map(|_| Keypair::generate_ed25519()).collect::<Vec<_>>();
+        let mut chain = IdentityChain::new(keys[0].public());
+
+        match chain.push(KeyRotation::with_timestamp(&keys[1], &keys[2], 1).unwrap()) {
+            Err(RotationError::Discontinuous) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        chain.push(KeyRotation::with_timestamp(&keys[0], &keys[1], 2).unwrap()).unwrap();
+        match chain.push(KeyRotation::with_timestamp(&keys[1], &keys[2], 1).unwrap()) {
+            Err(RotationError::OutOfOrder) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
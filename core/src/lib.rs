@@ -39,6 +39,18 @@ mod keys_proto {
     include!(concat!(env!("OUT_DIR"), "/keys_proto.rs"));
 }
 
+mod envelope_proto {
+    include!(concat!(env!("OUT_DIR"), "/envelope_proto.rs"));
+}
+
+mod key_rotation_proto {
+    include!(concat!(env!("OUT_DIR"), "/key_rotation_proto.rs"));
+}
+
+mod peer_record_proto {
+    include!(concat!(env!("OUT_DIR"), "/peer_record_proto.rs"));
+}
+
 /// Multi-address re-export.
 pub use multiaddr;
 pub type Negotiated<T> = futures::compat::Compat01As03<multistream_select::Negotiated<futures::compat::Compat<T>>>;
@@ -46,19 +58,27 @@ pub type Negotiated<T> = futures::compat::Compat01As03<multistream_select::Negot
 mod peer_id;
 mod translation;
 
+pub mod address_filter;
 pub mod either;
 pub mod identity;
+pub mod key_rotation;
 pub mod muxing;
 pub mod nodes;
+pub mod peer_record;
+pub mod psk;
+pub mod signed_envelope;
 pub mod transport;
 pub mod upgrade;
 
+pub use address_filter::AddressFilter;
 pub use multiaddr::Multiaddr;
 pub use muxing::StreamMuxer;
-pub use peer_id::PeerId;
+pub use peer_id::{PeerId, PeerIdConfig};
+pub use peer_record::PeerRecord;
+pub use signed_envelope::SignedEnvelope;
 pub use identity::PublicKey;
 pub use transport::Transport;
-pub use translation::address_translation;
+pub use translation::{address_translation, port_translation};
 pub use upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo, UpgradeError, ProtocolName};
 pub use nodes::ConnectionInfo;
 
@@ -54,12 +54,86 @@
 use fnv::FnvHashMap;
 use futures::{future, prelude::*, task::Context, task::Poll};
 use parking_lot::Mutex;
-use std::{io, ops::Deref, fmt, pin::Pin, sync::atomic::{AtomicUsize, Ordering}};
+use crate::Endpoint;
+use std::{io, ops::Deref, fmt, pin::Pin, sync::atomic::{AtomicUsize, Ordering}, time::Duration};
 
+pub use self::hooks::{HookedMuxer, HookedSubstream, SubstreamEvent};
 pub use self::singleton::SingletonMuxer;
 
+mod hooks;
 mod singleton;
 
+/// Priority of a substream relative to the other substreams of the same connection.
+///
+/// Priorities are a hint to the muxer, whose support varies. A muxer may schedule the frames
+/// of a congested connection so that the substreams share it in proportion to their
+/// [`weight`](SubstreamPriority::weight), or merely scale the size of each write with it, or
+/// ignore priorities entirely.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SubstreamPriority {
+    /// For bulk transfers.
+    Low,
+    /// The priority of inbound substreams and of `open_outbound`.
+    Normal,
+    /// For small and latency-sensitive exchanges, such as pings.
+    High,
+}
+
+impl Default for SubstreamPriority {
+    fn default() -> Self {
+        SubstreamPriority::Normal
+    }
+}
+
+impl SubstreamPriority {
+    /// Returns the share of the bandwidth of the substream relative to the other priorities.
+    pub fn weight(self) -> u32 {
+        match self {
+            SubstreamPriority::Low => 1,
+            SubstreamPriority::Normal => 4,
+            SubstreamPriority::High => 16,
+        }
+    }
+}
+
+/// Statistics of a muxed connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MuxerStats {
+    /// The number of substreams currently open.
+    pub open_substreams: usize,
+    /// The number of substreams opened since the connection was established, in both
+    /// directions.
+    pub total_substreams: u64,
+    /// The statistics of the substreams currently open.
+    pub substreams: Vec<SubstreamStats>,
+    /// The total time writes to substreams waited for the remote, e.g. for the window of a
+    /// substream to be replenished. Always zero for muxers without flow control.
+    pub window_stall_time: Duration,
+    /// The number of substreams opened by the remote that have been refused because of a limit
+    /// on the number of inbound substreams.
+    pub refused_substreams: u64,
+}
+
+impl MuxerStats {
+    /// Returns the open substream which has sent and received the most bytes, if any.
+    pub fn busiest_substream(&self) -> Option<&SubstreamStats> {
+        self.substreams.iter().max_by_key(|s| s.bytes_sent + s.bytes_received)
+    }
+}
+
+/// Statistics of a substream of a muxed connection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SubstreamStats {
+    /// The identifier of the substream on the wire.
+    pub id: u64,
+    /// `Dialer` if we opened the substream, `Listener` if the remote did.
+    pub endpoint: Endpoint,
+    /// The number of bytes written to the substream.
+    pub bytes_sent: u64,
+    /// The number of bytes read from the substream.
+    pub bytes_received: u64,
+}
+
 /// Implemented on objects that can open and manage substreams.
 ///
 /// The state of a muxer, as exposed by this API, is the following:
@@ -100,6 +174,14 @@ pub trait StreamMuxer {
     /// through the methods on the `StreamMuxer` trait.
     fn open_outbound(&self) -> Self::OutboundSubstream;
 
+    /// Same as `open_outbound`, but the substream is given the priority `priority`.
+    ///
+    /// The default implementation ignores the priority.
+    fn open_outbound_with_priority(&self, priority: SubstreamPriority) -> Self::OutboundSubstream {
+        let _ = priority;
+        self.open_outbound()
+    }
+
     /// Polls the outbound substream.
     ///
     /// If `Pending` is returned, then the current task will be notified once the substream
@@ -202,6 +284,13 @@ pub trait StreamMuxer {
     /// due to `shutdown_substream` or `close`. One may thus shutdown groups of substreams
     /// followed by a final `flush_all` instead of having to do `flush_substream` for each.
     fn flush_all(&self, cx: &mut Context) -> Poll<Result<(), Self::Error>>;
+
+    /// Returns the statistics of the connection, if the muxer keeps any.
+    ///
+    /// The default implementation returns `None`.
+    fn stats(&self) -> Option<MuxerStats> {
+        None
+    }
 }
 
 /// Polls for an inbound from the muxer but wraps the output in an object that
@@ -231,6 +320,18 @@ where
     OutboundSubstreamRefWrapFuture { inner }
 }
 
+/// Same as `outbound_from_ref_and_wrap`, but the substream is given the priority `priority`.
+#[inline]
+pub fn outbound_from_ref_and_wrap_with_priority<P>(muxer: P, priority: SubstreamPriority)
+    -> OutboundSubstreamRefWrapFuture<P>
+where
+    P: Deref + Clone,
+    P::Target: StreamMuxer,
+{
+    let inner = outbound_from_ref_with_priority(muxer, priority);
+    OutboundSubstreamRefWrapFuture { inner }
+}
+
 /// Future returned by `outbound_from_ref_and_wrap`.
 pub struct OutboundSubstreamRefWrapFuture<P>
 where
@@ -273,6 +374,20 @@ where
     }
 }
 
+/// Same as `outbound_from_ref`, but the substream is given the priority `priority`.
+#[inline]
+pub fn outbound_from_ref_with_priority<P>(muxer: P, priority: SubstreamPriority) -> OutboundSubstreamRefFuture<P>
+where
+    P: Deref,
+    P::Target: StreamMuxer,
+{
+    let outbound = muxer.open_outbound_with_priority(priority);
+    OutboundSubstreamRefFuture {
+        muxer,
+        outbound: Some(outbound),
+    }
+}
+
 /// Future returned by `outbound_from_ref`.
 pub struct OutboundSubstreamRefFuture<P>
 where
@@ -492,6 +607,11 @@ impl StreamMuxer for StreamMuxerBox {
         self.inner.open_outbound()
     }
 
+    #[inline]
+    fn open_outbound_with_priority(&self, priority: SubstreamPriority) -> Self::OutboundSubstream {
+        self.inner.open_outbound_with_priority(priority)
+    }
+
     #[inline]
     fn poll_outbound(&self, cx: &mut Context, s: &mut Self::OutboundSubstream) -> Poll<Result<Self::Substream, Self::Error>> {
         self.inner.poll_outbound(cx, s)
@@ -541,6 +661,11 @@ impl StreamMuxer for StreamMuxerBox {
     fn flush_all(&self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
         self.inner.flush_all(cx)
     }
+
+    #[inline]
+    fn stats(&self) -> Option<MuxerStats> {
+        self.inner.stats()
+    }
 }
 
 struct Wrap<T> where T: StreamMuxer {
@@ -580,6 +705,14 @@ where
         id
     }
 
+    #[inline]
+    fn open_outbound_with_priority(&self, priority: SubstreamPriority) -> Self::OutboundSubstream {
+        let outbound = self.inner.open_outbound_with_priority(priority);
+        let id = self.next_outbound.fetch_add(1, Ordering::Relaxed);
+        self.outbound.lock().insert(id, outbound);
+        id
+    }
+
     #[inline]
     fn poll_outbound(
         &self,
@@ -647,4 +780,9 @@ where
     fn flush_all(&self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
         self.inner.flush_all(cx).map_err(|e| e.into())
     }
+
+    #[inline]
+    fn stats(&self) -> Option<MuxerStats> {
+        self.inner.stats()
+    }
 }
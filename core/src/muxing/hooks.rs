@@ -0,0 +1,301 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::{Endpoint, muxing::{MuxerStats, StreamMuxer, SubstreamPriority}};
+use futures::ready;
+use std::{fmt, sync::atomic::{AtomicU64, Ordering}, task::Context, task::Poll};
+
+/// The maximum number of bytes of a substream searched for the negotiated protocol.
+const MAX_NEGOTIATION_LEN: usize = 1024;
+
+/// The header of the multistream-select protocol.
+const MSS_HEADER: &[u8] = b"/multistream/1.0.0\n";
+
+/// An event of a substream of a [`HookedMuxer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubstreamEvent<'a> {
+    /// A substream has been opened.
+    Opened {
+        /// The identifier of the substream, unique to the connection.
+        id: u64,
+        /// Whether the substream was opened by the local node or by the remote.
+        endpoint: Endpoint,
+    },
+    /// The protocol of a substream has been negotiated.
+    Negotiated {
+        id: u64,
+        endpoint: Endpoint,
+        /// The name of the negotiated protocol.
+        protocol: &'a [u8],
+    },
+    /// A substream has been closed, i.e. destroyed.
+    Closed {
+        id: u64,
+        endpoint: Endpoint,
+        /// The name of the negotiated protocol, if known.
+        protocol: Option<&'a [u8]>,
+    },
+}
+
+/// Wraps a [`StreamMuxer`], calling a callback whenever a substream is opened,
+/// has negotiated its protocol or is closed.
+///
+/// This allows to account for the substreams of a connection per protocol and
+/// to find substreams which are never closed, independently of the protocols
+/// using them.
+///
+/// The protocol of a substream is taken from the multistream-select messages
+/// at the start of the substream, once the listener has confirmed it. The
+/// protocol of a substream which is not negotiated with multistream-select,
+/// or whose confirmation is never read by the dialer, is unknown.
+pub struct HookedMuxer<M> {
+    inner: M,
+    callback: Box<dyn Fn(&SubstreamEvent) + Send + Sync>,
+    next_id: AtomicU64,
+}
+
+impl<M> HookedMuxer<M> {
+    /// Wraps `inner`, calling `callback` with the events of its substreams.
+    pub fn new<F>(inner: M, callback: F) -> Self
+    where
+        F: Fn(&SubstreamEvent) + Send + Sync + 'static
+    {
+        HookedMuxer { inner, callback: Box::new(callback), next_id: AtomicU64::new(0) }
+    }
+
+    /// Wraps a new substream of the inner muxer.
+    fn opened<S>(&self, inner: S, endpoint: Endpoint) -> HookedSubstream<S> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        (self.callback)(&SubstreamEvent::Opened { id, endpoint });
+        HookedSubstream { inner, id, endpoint, protocol: Protocol::Negotiating(Vec::new()) }
+    }
+
+    /// Searches the data exchanged on a substream for the negotiated protocol.
+    fn inspect<S>(&self, substream: &mut HookedSubstream<S>, data: &[u8]) {
+        if let Some(protocol) = substream.protocol.inspect(data) {
+            (self.callback)(&SubstreamEvent::Negotiated {
+                id: substream.id,
+                endpoint: substream.endpoint,
+                protocol,
+            })
+        }
+    }
+}
+
+impl<M> fmt::Debug for HookedMuxer<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("HookedMuxer")
+    }
+}
+
+/// A substream of a [`HookedMuxer`].
+pub struct HookedSubstream<S> {
+    inner: S,
+    id: u64,
+    endpoint: Endpoint,
+    protocol: Protocol,
+}
+
+/// The protocol of a substream.
+#[derive(Debug)]
+enum Protocol {
+    /// Still negotiating, with the data of the negotiation seen so far.
+    Negotiating(Vec<u8>),
+    /// The negotiated protocol.
+    Negotiated(Vec<u8>),
+    /// The protocol isn't known.
+    Unknown,
+}
+
+impl Protocol {
+    /// Returns the protocol if known.
+    fn name(&self) -> Option<&[u8]> {
+        match self {
+            Protocol::Negotiated(name) => Some(name),
+            Protocol::Negotiating(_) | Protocol::Unknown => None,
+        }
+    }
+
+    /// Searches the multistream-select messages confirming a protocol for the
+    /// name of the protocol, returning it if `data` completes the confirmation.
+    ///
+    /// The listener writes and the dialer reads the confirmation.
+    fn inspect(&mut self, data: &[u8]) -> Option<&[u8]> {
+        let buf = match self {
+            Protocol::Negotiating(buf) => buf,
+            Protocol::Negotiated(_) | Protocol::Unknown => return None,
+        };
+        let len = std::cmp::min(data.len(), MAX_NEGOTIATION_LEN - buf.len());
+        buf.extend_from_slice(&data[.. len]);
+
+        let mut rest = &buf[..];
+        loop {
+            match unsigned_varint::decode::usize(rest) {
+                Ok((len, tail)) if len <= tail.len() => {
+                    let (msg, tail) = tail.split_at(len);
+                    rest = tail;
+                    // Skip the header, refusals (`na`) and the responses to `ls`.
+                    if msg != MSS_HEADER && msg.starts_with(b"/") && msg.ends_with(b"\n") {
+                        *self = Protocol::Negotiated(msg[.. msg.len() - 1].to_vec());
+                        return self.name()
+                    }
+                }
+                Ok(_) | Err(unsigned_varint::decode::Error::Insufficient) => {
+                    if buf.len() == MAX_NEGOTIATION_LEN {
+                        *self = Protocol::Unknown
+                    }
+                    return None
+                }
+                Err(_) => {
+                    *self = Protocol::Unknown;
+                    return None
+                }
+            }
+        }
+    }
+}
+
+impl<M> StreamMuxer for HookedMuxer<M>
+where
+    M: StreamMuxer
+{
+    type Substream = HookedSubstream<M::Substream>;
+    type OutboundSubstream = M::OutboundSubstream;
+    type Error = M::Error;
+
+    fn poll_inbound(&self, cx: &mut Context) -> Poll<Result<Self::Substream, Self::Error>> {
+        let substream = ready!(self.inner.poll_inbound(cx))?;
+        Poll::Ready(Ok(self.opened(substream, Endpoint::Listener)))
+    }
+
+    fn open_outbound(&self) -> Self::OutboundSubstream {
+        self.inner.open_outbound()
+    }
+
+    fn open_outbound_with_priority(&self, priority: SubstreamPriority) -> Self::OutboundSubstream {
+        self.inner.open_outbound_with_priority(priority)
+    }
+
+    fn poll_outbound(&self, cx: &mut Context, s: &mut Self::OutboundSubstream)
+        -> Poll<Result<Self::Substream, Self::Error>>
+    {
+        let substream = ready!(self.inner.poll_outbound(cx, s))?;
+        Poll::Ready(Ok(self.opened(substream, Endpoint::Dialer)))
+    }
+
+    fn destroy_outbound(&self, s: Self::OutboundSubstream) {
+        self.inner.destroy_outbound(s)
+    }
+
+    fn read_substream(&self, cx: &mut Context, s: &mut Self::Substream, buf: &mut [u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        let n = ready!(self.inner.read_substream(cx, &mut s.inner, buf))?;
+        if s.endpoint == Endpoint::Dialer {
+            self.inspect(s, &buf[.. n])
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn write_substream(&self, cx: &mut Context, s: &mut Self::Substream, buf: &[u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        let n = ready!(self.inner.write_substream(cx, &mut s.inner, buf))?;
+        if s.endpoint == Endpoint::Listener {
+            self.inspect(s, &buf[.. n])
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn flush_substream(&self, cx: &mut Context, s: &mut Self::Substream) -> Poll<Result<(), Self::Error>> {
+        self.inner.flush_substream(cx, &mut s.inner)
+    }
+
+    fn shutdown_substream(&self, cx: &mut Context, s: &mut Self::Substream) -> Poll<Result<(), Self::Error>> {
+        self.inner.shutdown_substream(cx, &mut s.inner)
+    }
+
+    fn destroy_substream(&self, s: Self::Substream) {
+        (self.callback)(&SubstreamEvent::Closed {
+            id: s.id,
+            endpoint: s.endpoint,
+            protocol: s.protocol.name(),
+        });
+        self.inner.destroy_substream(s.inner)
+    }
+
+    fn is_remote_acknowledged(&self) -> bool {
+        self.inner.is_remote_acknowledged()
+    }
+
+    fn close(&self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.inner.close(cx)
+    }
+
+    fn flush_all(&self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.inner.flush_all(cx)
+    }
+
+    fn stats(&self) -> Option<MuxerStats> {
+        self.inner.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a multistream-select message.
+    fn message(msg: &[u8]) -> Vec<u8> {
+        let mut buf = unsigned_varint::encode::usize_buffer();
+        let mut out = unsigned_varint::encode::usize(msg.len(), &mut buf).to_vec();
+        out.extend_from_slice(msg);
+        out
+    }
+
+    #[test]
+    fn protocol_of_confirmation() {
+        let mut data = message(MSS_HEADER);
+        data.extend(message(b"na\n"));
+        data.extend(message(b"/proto/1.0.0\n"));
+        data.extend_from_slice(b"payload");
+
+        // The confirmation is found, however the data is split.
+        for chunk_len in 1 .. data.len() {
+            let mut protocol = Protocol::Negotiating(Vec::new());
+            let mut found = Vec::new();
+            for chunk in data.chunks(chunk_len) {
+                if let Some(name) = protocol.inspect(chunk) {
+                    found.push(name.to_vec())
+                }
+            }
+            assert_eq!(found, vec![b"/proto/1.0.0".to_vec()]);
+            assert_eq!(protocol.name(), Some(&b"/proto/1.0.0"[..]));
+        }
+    }
+
+    #[test]
+    fn protocol_of_other_data() {
+        let mut protocol = Protocol::Negotiating(Vec::new());
+        assert_eq!(protocol.inspect(&[0xff; MAX_NEGOTIATION_LEN + 1]), None);
+        assert_eq!(protocol.name(), None);
+        assert_eq!(protocol.inspect(&message(b"/proto/1.0.0\n")), None);
+    }
+}
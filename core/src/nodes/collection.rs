@@ -20,7 +20,7 @@
 
 use crate::{
     PeerId,
-    muxing::StreamMuxer,
+    muxing::{MuxerStats, StreamMuxer},
     nodes::{
         node::Substream,
         handled_node::{HandledNodeError, IntoNodeHandler, NodeHandler},
@@ -101,6 +101,15 @@ pub enum CollectionEvent<'a, TInEvent, TOutEvent, THandler, TReachErr, THandlerE
         /// The produced event.
         event: TOutEvent,
     },
+
+    /// A node has reported the statistics of its muxer, as requested with
+    /// `PeerMut::start_request_muxer_stats`.
+    MuxerStats {
+        /// The node that has reported the statistics.
+        peer: PeerMut<'a, TInEvent, TUserData, TConnInfo, TPeerId>,
+        /// The statistics.
+        stats: MuxerStats,
+    },
 }
 
 impl<'a, TInEvent, TOutEvent, THandler, TReachErr, THandlerErr, TUserData, TConnInfo, TPeerId> fmt::Debug for
@@ -137,6 +146,12 @@ where TOutEvent: fmt::Debug,
                 .field("event", event)
                 .finish()
             },
+            CollectionEvent::MuxerStats { ref peer, ref stats } => {
+                f.debug_struct("CollectionEvent::MuxerStats")
+                .field("conn_info", peer.info())
+                .field("stats", stats)
+                .finish()
+            },
         }
     }
 }
@@ -315,6 +330,14 @@ where
         }
     }
 
+    /// Processes the nodes added from now on in [`CollectionStream::poll`], instead of on a
+    /// threads pool.
+    ///
+    /// This makes the processing of the nodes deterministic, which is mostly useful for tests.
+    pub fn run_tasks_locally(&mut self) {
+        self.inner.run_tasks_locally()
+    }
+
     /// Adds to the collection a future that tries to reach a remote.
     ///
     /// This method spawns a task dedicated to resolving this future and processing the node's
@@ -519,6 +542,22 @@ where
                     event,
                 })
             }
+            tasks::Event::MuxerStats { task, stats } => {
+                let conn_info = match task.user_data() {
+                    TaskState::Connected(conn_info, _) => conn_info.clone(),
+                    _ => panic!("we can only receive MuxerStats events from a task after we \
+                                 received a corresponding NodeReached event from that same task; \
+                                 when we receive a NodeReached event, we ensure that the entry in \
+                                 self.tasks is switched to the Connected state; QED"),
+                };
+                Poll::Ready(CollectionEvent::MuxerStats {
+                    peer: self.peer_mut(conn_info.peer_id())
+                        .expect("we can only receive MuxerStats events from a task after we \
+                                 received a corresponding NodeReached event from that same task;\
+                                 when that happens, peer_mut will always return Some; QED"),
+                    stats,
+                })
+            }
         }
     }
 }
@@ -624,6 +663,13 @@ where
         self.inner.poll_ready_event(cx)
     }
 
+    /// Begin requesting the statistics of the muxer of the node, which are reported with a
+    /// `CollectionEvent::MuxerStats` if the muxer keeps any. Must be called only after a
+    /// successful call to `poll_ready_event`.
+    pub fn start_request_muxer_stats(&mut self) {
+        self.inner.start_request_muxer_stats()
+    }
+
     /// Closes the connections to this node. Returns the user data.
     ///
     /// No further event will be generated for this node.
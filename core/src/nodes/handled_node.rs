@@ -18,7 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::{PeerId, muxing::StreamMuxer};
+use crate::{PeerId, muxing::{MuxerStats, StreamMuxer}};
 use crate::nodes::node::{NodeEvent, NodeStream, Substream, Close};
 use std::{error, fmt, io, pin::Pin, task::Context, task::Poll};
 
@@ -212,6 +212,13 @@ where
         self.node.is_remote_acknowledged()
     }
 
+    /// Returns the statistics of the muxer, if it keeps any.
+    ///
+    /// See `StreamMuxer::stats`.
+    pub fn muxer_stats(&self) -> Option<MuxerStats> {
+        self.node.muxer_stats()
+    }
+
     /// Indicates to the handled node that it should shut down. After calling this method, the
     /// `Stream` will end in the not-so-distant future.
     pub fn close(self) -> Close<TMuxer> {
@@ -219,6 +219,11 @@ where
         &self.transport
     }
 
+    /// Returns an iterator that produces the IDs of the active listeners.
+    pub fn listener_ids(&self) -> impl Iterator<Item = ListenerId> + '_ {
+        self.listeners.iter().map(|l| l.id)
+    }
+
     /// Returns an iterator that produces the list of addresses we're listening on.
     pub fn listen_addrs(&self) -> impl Iterator<Item = &Multiaddr> {
         self.listeners.iter().flat_map(|l| l.addresses.iter())
@@ -18,7 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::muxing::StreamMuxer;
+use crate::muxing::{MuxerStats, StreamMuxer};
 use crate::{
     ConnectedPoint, Multiaddr, PeerId, address_translation,
     nodes::{
@@ -44,7 +44,8 @@ use crate::{
 use fnv::FnvHashMap;
 use futures::{prelude::*, future};
 use std::{
-    collections::hash_map::{Entry, OccupiedEntry},
+    cmp,
+    collections::{VecDeque, hash_map::{Entry, OccupiedEntry}},
     error,
     fmt,
     hash::Hash,
@@ -53,8 +54,14 @@ use std::{
     task::{Context, Poll},
 };
 
+mod dial;
+mod limits;
 mod tests;
 
+pub use dial::{DialHandle, DialOpts, PeerCondition};
+use dial::RaceFuture;
+pub use limits::{ConnectionLimit, ConnectionLimits};
+
 /// Implementation of `Stream` that handles the nodes.
 pub struct Network<TTrans, TInEvent, TOutEvent, THandler, THandlerErr, TConnInfo = PeerId, TPeerId = PeerId>
 where
@@ -73,6 +80,12 @@ where
     /// Max number of incoming connections.
     incoming_limit: Option<u32>,
 
+    /// Limits on the connections, denying those exceeding them.
+    limits: ConnectionLimits,
+
+    /// Failures of addresses dialed concurrently, yet to be reported.
+    raced_errors: VecDeque<RacedError<TPeerId, TTrans::Error, TConnInfo>>,
+
     /// Unfinished take over message to be delivered.
     ///
     /// If the pair's second element is `AsyncSink::NotReady`, the take over
@@ -97,6 +110,7 @@ where
             .field("active_nodes", &self.active_nodes)
             .field("reach_attempts", &self.reach_attempts)
             .field("incoming_limit", &self.incoming_limit)
+            .field("limits", &self.limits)
             .field("take_over_to_complete", &self.take_over_to_complete)
             .finish()
     }
@@ -156,10 +170,17 @@ where
 struct OutReachAttempt {
     /// Identifier for the reach attempt.
     id: ReachAttemptId,
-    /// Multiaddr currently being attempted.
+    /// Multiaddr currently being attempted. The first of the batch if several addresses are
+    /// dialed concurrently.
     cur_attempted: Multiaddr,
+    /// Number of addresses currently being attempted.
+    num_attempted: usize,
     /// Multiaddresses to attempt if the current one fails.
     next_attempts: Vec<Multiaddr>,
+    /// Handle to cancel the attempt.
+    handle: DialHandle,
+    /// Options of the attempt.
+    opts: DialOpts,
 }
 
 /// Event that can happen on the `Network`.
@@ -281,6 +302,15 @@ where
         /// Event that was produced by the node.
         event: TOutEvent,
     },
+
+    /// A node reported the statistics of its muxer, as requested with
+    /// `PeerConnected::start_request_muxer_stats`.
+    MuxerStats {
+        /// Connection whose muxer the statistics are of.
+        conn_info: TConnInfo,
+        /// The statistics.
+        stats: MuxerStats,
+    },
 }
 
 impl<'a, TTrans, TInEvent, TOutEvent, THandler, THandlerErr, TConnInfo, TPeerId> fmt::Debug for
@@ -372,10 +402,23 @@ where
                     .field("event", event)
                     .finish()
             }
+            NetworkEvent::MuxerStats { conn_info, stats } => {
+                f.debug_struct("MuxerStats")
+                    .field("conn_info", conn_info)
+                    .field("stats", stats)
+                    .finish()
+            }
         }
     }
 }
 
+/// The failure of an address of a peer dialed concurrently with others.
+type RacedError<TPeerId, TTransErr, TConnInfo> = (TPeerId, Multiaddr, NetworkReachError<TTransErr, TConnInfo>);
+
+/// A connection attempt to a peer at a single address.
+type PeerDial<TConnInfo, TMuxer, TTransErr> =
+    future::BoxFuture<'static, Result<((TConnInfo, ConnectedPoint), TMuxer), InternalReachErr<TTransErr, TConnInfo>>>;
+
 /// Internal error type that contains all the possible errors that can happen in a reach attempt.
 #[derive(Debug)]
 enum InternalReachErr<TTransErr, TConnInfo> {
@@ -389,6 +432,14 @@ enum InternalReachErr<TTransErr, TConnInfo> {
     },
     /// The negotiated `PeerId` is the same as the one of the local node.
     FoundLocalPeerId,
+    /// The dial has been cancelled through its `DialHandle`.
+    Cancelled,
+    /// The dial did not finish within the timeout of its `DialOpts`.
+    Timeout,
+    /// The dial has been denied by the `ConnectionLimits`.
+    ConnectionDenied(ConnectionLimit),
+    /// All the addresses dialed concurrently failed.
+    Raced(Vec<(Multiaddr, InternalReachErr<TTransErr, TConnInfo>)>),
 }
 
 impl<TTransErr, TConnInfo> fmt::Display for InternalReachErr<TTransErr, TConnInfo>
@@ -405,6 +456,16 @@ where
             InternalReachErr::FoundLocalPeerId => {
                 write!(f, "Remote has the same PeerId as us")
             }
+            InternalReachErr::Cancelled => write!(f, "Dial cancelled"),
+            InternalReachErr::Timeout => write!(f, "Dial timed out"),
+            InternalReachErr::ConnectionDenied(limit) => write!(f, "{}", limit),
+            InternalReachErr::Raced(errors) => {
+                write!(f, "Failed to reach all addresses:")?;
+                for (addr, err) in errors {
+                    write!(f, " {}: {};", addr, err)?;
+                }
+                Ok(())
+            },
         }
     }
 }
@@ -419,6 +480,10 @@ where
             InternalReachErr::Transport(err) => Some(err),
             InternalReachErr::PeerIdMismatch { .. } => None,
             InternalReachErr::FoundLocalPeerId => None,
+            InternalReachErr::Cancelled => None,
+            InternalReachErr::Timeout => None,
+            InternalReachErr::ConnectionDenied(limit) => Some(limit),
+            InternalReachErr::Raced(_) => None,
         }
     }
 }
@@ -448,7 +513,16 @@ pub enum NetworkReachError<TTransErr, TConnInfo> {
     PeerIdMismatch {
         /// The information about the other connection.
         obtained: TConnInfo,
-    }
+    },
+
+    /// The dial has been cancelled through its `DialHandle`.
+    Cancelled,
+
+    /// The dial did not finish within the timeout of its `DialOpts`.
+    Timeout,
+
+    /// The dial has been denied by the `ConnectionLimits` of the network.
+    ConnectionDenied(ConnectionLimit),
 }
 
 impl<TTransErr, TConnInfo> fmt::Display for NetworkReachError<TTransErr, TConnInfo>
@@ -462,6 +536,9 @@ where
             NetworkReachError::PeerIdMismatch { obtained } => {
                 write!(f, "Peer ID mismatch, obtained: {:?}", obtained)
             },
+            NetworkReachError::Cancelled => write!(f, "Dial cancelled"),
+            NetworkReachError::Timeout => write!(f, "Dial timed out"),
+            NetworkReachError::ConnectionDenied(limit) => write!(f, "{}", limit),
         }
     }
 }
@@ -475,6 +552,9 @@ where
         match self {
             NetworkReachError::Transport(err) => Some(err),
             NetworkReachError::PeerIdMismatch { .. } => None,
+            NetworkReachError::Cancelled => None,
+            NetworkReachError::Timeout => None,
+            NetworkReachError::ConnectionDenied(limit) => Some(limit),
         }
     }
 }
@@ -486,6 +566,12 @@ pub enum UnknownPeerDialErr<TTransErr> {
     Transport(TransportError<TTransErr>),
     /// The negotiated `PeerId` is the same as the local node.
     FoundLocalPeerId,
+    /// The dial has been cancelled through its `DialHandle`.
+    Cancelled,
+    /// The dial did not finish within the timeout of its `DialOpts`.
+    Timeout,
+    /// The dial has been denied by the `ConnectionLimits` of the network.
+    ConnectionDenied(ConnectionLimit),
 }
 
 impl<TTransErr> fmt::Display for UnknownPeerDialErr<TTransErr>
@@ -497,6 +583,9 @@ where TTransErr: fmt::Display
             UnknownPeerDialErr::FoundLocalPeerId => {
                 write!(f, "Unknown peer has same PeerId as us")
             },
+            UnknownPeerDialErr::Cancelled => write!(f, "Dial cancelled"),
+            UnknownPeerDialErr::Timeout => write!(f, "Dial timed out"),
+            UnknownPeerDialErr::ConnectionDenied(limit) => write!(f, "{}", limit),
         }
     }
 }
@@ -508,6 +597,9 @@ where TTransErr: error::Error + 'static
         match self {
             UnknownPeerDialErr::Transport(err) => Some(err),
             UnknownPeerDialErr::FoundLocalPeerId => None,
+            UnknownPeerDialErr::Cancelled => None,
+            UnknownPeerDialErr::Timeout => None,
+            UnknownPeerDialErr::ConnectionDenied(limit) => Some(limit),
         }
     }
 }
@@ -523,6 +615,8 @@ pub enum IncomingError<TTransErr> {
     DeniedLowerPriority,
     /// The negotiated `PeerId` is the same as the local node.
     FoundLocalPeerId,
+    /// Denied the incoming connection because of the `ConnectionLimits` of the network.
+    ConnectionDenied(ConnectionLimit),
 }
 
 impl<TTransErr> fmt::Display for IncomingError<TTransErr>
@@ -537,6 +631,7 @@ where TTransErr: fmt::Display
             IncomingError::FoundLocalPeerId => {
                 write!(f, "Incoming connection has same PeerId as us")
             },
+            IncomingError::ConnectionDenied(limit) => write!(f, "{}", limit),
         }
     }
 }
@@ -549,6 +644,7 @@ where TTransErr: error::Error + 'static
             IncomingError::Transport(err) => Some(err),
             IncomingError::DeniedLowerPriority => None,
             IncomingError::FoundLocalPeerId => None,
+            IncomingError::ConnectionDenied(limit) => Some(limit),
         }
     }
 }
@@ -700,7 +796,9 @@ where
                 connected_points: Default::default(),
             },
             incoming_limit: None,
-            take_over_to_complete: None
+            limits: ConnectionLimits::default(),
+            take_over_to_complete: None,
+            raced_errors: VecDeque::new(),
         }
     }
 
@@ -710,6 +808,7 @@ where
     {
         Network {
             incoming_limit,
+            limits: ConnectionLimits::default(),
             listeners: ListenersStream::new(transport),
             active_nodes: CollectionStream::new(),
             reach_attempts: ReachAttempts {
@@ -718,7 +817,8 @@ where
                 other_reach_attempts: Vec::new(),
                 connected_points: Default::default(),
             },
-            take_over_to_complete: None
+            take_over_to_complete: None,
+            raced_errors: VecDeque::new(),
         }
     }
 
@@ -744,11 +844,38 @@ where
         self.listeners.listen_addrs()
     }
 
+    /// Returns an iterator that produces the IDs of the active listeners.
+    pub fn listener_ids(&self) -> impl Iterator<Item = ListenerId> + '_ {
+        self.listeners.listener_ids()
+    }
+
     /// Returns limit on incoming connections.
     pub fn incoming_limit(&self) -> Option<u32> {
         self.incoming_limit
     }
 
+    /// Returns the limits on the connections.
+    pub fn connection_limits(&self) -> &ConnectionLimits {
+        &self.limits
+    }
+
+    /// Sets the limits on the connections.
+    ///
+    /// Connections exceeding them are denied, producing `ConnectionDenied` errors. Existing
+    /// connections and attempts are not affected.
+    pub fn set_connection_limits(&mut self, limits: ConnectionLimits) {
+        self.limits = limits;
+    }
+
+    /// Processes the connections established from now on in [`Network::poll`], instead of on a
+    /// threads pool.
+    ///
+    /// This makes the processing of the connections deterministic, which is mostly useful for
+    /// tests.
+    pub fn run_tasks_locally(&mut self) {
+        self.active_nodes.run_tasks_locally()
+    }
+
     /// Call this function in order to know which address remotes should dial to
     /// access your local node.
     ///
@@ -785,6 +912,24 @@ where
     ///
     /// The second parameter is the handler to use if we manage to reach a node.
     pub fn dial(&mut self, addr: Multiaddr, handler: THandler) -> Result<(), TransportError<TTrans::Error>>
+    where
+        TTrans: Transport<Output = (TConnInfo, TMuxer)>,
+        TTrans::Error: Send + 'static,
+        TTrans::Dial: Send + 'static,
+        TMuxer: Send + Sync + 'static,
+        TMuxer::OutboundSubstream: Send,
+        TInEvent: Send + 'static,
+        TOutEvent: Send + 'static,
+        TConnInfo: Send + 'static,
+        TPeerId: Send + 'static,
+    {
+        self.dial_with_opts(addr, handler, DialOpts::default()).map(|_| ())
+    }
+
+    /// Same as `dial`, but applies the given options to the dial and returns a handle to
+    /// cancel it.
+    pub fn dial_with_opts(&mut self, addr: Multiaddr, handler: THandler, opts: DialOpts)
+        -> Result<DialHandle, TransportError<TTrans::Error>>
     where
         TTrans: Transport<Output = (TConnInfo, TMuxer)>,
         TTrans::Error: Send + 'static,
@@ -798,6 +943,13 @@ where
     {
         let local_peer_id = self.reach_attempts.local_peer_id.clone();
         let connected_point = ConnectedPoint::Dialer { address: addr.clone() };
+        let handle = DialHandle::new();
+        if let Err(limit) = self.limits.check_dial(&self.reach_attempts, None, &addr) {
+            let future = future::err(InternalReachErr::ConnectionDenied(limit));
+            let reach_id = self.active_nodes.add_reach_attempt(future, handler);
+            self.reach_attempts.other_reach_attempts.push((reach_id, connected_point));
+            return Ok(handle)
+        }
         let future = self.transport().clone().dial(addr)?
             .map_err(|err| InternalReachErr::Transport(TransportError::Other(err)))
             .and_then({
@@ -811,9 +963,10 @@ where
                 }
             });
 
+        let future = handle.guard(future, &opts);
         let reach_id = self.active_nodes.add_reach_attempt(future, handler);
         self.reach_attempts.other_reach_attempts.push((reach_id, connected_point));
-        Ok(())
+        Ok(handle)
     }
 
     /// Returns the number of incoming connections that are currently in the process of being
@@ -933,7 +1086,8 @@ where
     ///
     /// It is a logic error to call this method if we already have an outgoing attempt to the
     /// given peer.
-    fn start_dial_out(&mut self, peer_id: TPeerId, handler: THandler, first: Multiaddr, rest: Vec<Multiaddr>)
+    fn start_dial_out(&mut self, peer_id: TPeerId, handler: THandler, first: Multiaddr, mut rest: Vec<Multiaddr>,
+        handle: DialHandle, opts: DialOpts)
     where
         TTrans: Transport<Output = (TConnInfo, TMuxer)>,
         TTrans::Dial: Send + 'static,
@@ -944,25 +1098,20 @@ where
         TOutEvent: Send + 'static,
         TPeerId: Send + 'static,
     {
-        let reach_id = match self.transport().clone().dial(first.clone()) {
-            Ok(fut) => {
-                let expected_peer_id = peer_id.clone();
-                let connected_point = ConnectedPoint::Dialer { address: first.clone() };
-                let fut = fut
-                    .map_err(|err| InternalReachErr::Transport(TransportError::Other(err)))
-                    .and_then(move |(actual_conn_info, muxer)| {
-                        if *actual_conn_info.peer_id() == expected_peer_id {
-                            future::ready(Ok(((actual_conn_info, connected_point), muxer)))
-                        } else {
-                            future::ready(Err(InternalReachErr::PeerIdMismatch { obtained: actual_conn_info }))
-                        }
-                    });
-                self.active_nodes.add_reach_attempt(fut, handler)
-            },
-            Err(err) => {
-                let fut = future::err(InternalReachErr::Transport(err));
-                self.active_nodes.add_reach_attempt(fut, handler)
-            },
+        let concurrency = usize::from(opts.get_concurrency().get());
+        let num_attempted = cmp::min(concurrency, 1 + rest.len());
+        let reach_id = if num_attempted > 1 {
+            let batch = rest.drain(.. num_attempted - 1).collect::<Vec<_>>();
+            let dials = Some(first.clone()).into_iter().chain(batch)
+                .map(|addr| {
+                    let dial = self.dial_peer_addr(peer_id.clone(), addr.clone(), &handle, &opts);
+                    dial.map(move |result| (addr, result)).boxed()
+                })
+                .collect::<Vec<_>>();
+            self.active_nodes.add_reach_attempt(RaceFuture::new(dials), handler)
+        } else {
+            let dial = self.dial_peer_addr(peer_id.clone(), first.clone(), &handle, &opts);
+            self.active_nodes.add_reach_attempt(dial, handler)
         };
 
         let former = self.reach_attempts.out_reach_attempts.insert(
@@ -970,13 +1119,47 @@ where
             OutReachAttempt {
                 id: reach_id,
                 cur_attempted: first,
+                num_attempted,
                 next_attempts: rest,
+                handle,
+                opts,
             },
         );
 
         debug_assert!(former.is_none());
     }
 
+    /// Dials a peer at a single address, enforcing the limits and the options of the dial.
+    fn dial_peer_addr(&self, peer_id: TPeerId, addr: Multiaddr, handle: &DialHandle, opts: &DialOpts)
+        -> PeerDial<TConnInfo, TMuxer, TTrans::Error>
+    where
+        TTrans: Transport<Output = (TConnInfo, TMuxer)>,
+        TTrans::Dial: Send + 'static,
+        TTrans::Error: Send + 'static,
+        TMuxer: Send + Sync + 'static,
+        TPeerId: Send + 'static,
+    {
+        let dial = self.limits.check_dial(&self.reach_attempts, Some(&peer_id), &addr)
+            .map_err(InternalReachErr::ConnectionDenied)
+            .and_then(|()| self.transport().clone().dial(addr.clone()).map_err(InternalReachErr::Transport));
+        match dial {
+            Ok(fut) => {
+                let connected_point = ConnectedPoint::Dialer { address: addr };
+                let fut = fut
+                    .map_err(|err| InternalReachErr::Transport(TransportError::Other(err)))
+                    .and_then(move |(actual_conn_info, muxer)| {
+                        if *actual_conn_info.peer_id() == peer_id {
+                            future::ready(Ok(((actual_conn_info, connected_point), muxer)))
+                        } else {
+                            future::ready(Err(InternalReachErr::PeerIdMismatch { obtained: actual_conn_info }))
+                        }
+                    });
+                handle.guard(fut, opts).boxed()
+            },
+            Err(err) => future::err(err).boxed(),
+        }
+    }
+
     /// Provides an API similar to `Stream`, except that it cannot error.
     pub fn poll<'a>(&'a mut self, cx: &mut Context) -> Poll<NetworkEvent<'a, TTrans, TInEvent, TOutEvent, THandler, THandlerErr, TConnInfo, TPeerId>>
     where
@@ -1003,6 +1186,13 @@ where
                 match ListenersStream::poll(Pin::new(&mut self.listeners), cx) {
                     Poll::Pending => (),
                     Poll::Ready(ListenersEvent::Incoming { listener_id, upgrade, local_addr, send_back_addr }) => {
+                        if let Err(limit) = self.limits.check_incoming(&self.reach_attempts, &send_back_addr) {
+                            return Poll::Ready(NetworkEvent::IncomingConnectionError {
+                                local_addr,
+                                send_back_addr,
+                                error: IncomingError::ConnectionDenied(limit),
+                            })
+                        }
                         let event = IncomingConnectionEvent {
                             listener_id,
                             upgrade,
@@ -1030,6 +1220,15 @@ where
             }
         }
 
+        // Report the failures of addresses dialed concurrently.
+        if let Some((peer_id, multiaddr, error)) = self.raced_errors.pop_front() {
+            let num_remain = self.raced_errors.iter().filter(|(p, ..)| *p == peer_id).count()
+                + self.reach_attempts.out_reach_attempts.get(&peer_id)
+                    .map_or(0, |a| a.num_attempted + a.next_attempts.len());
+            let new_state = dial_error_state(&self.reach_attempts, &peer_id, num_remain);
+            return Poll::Ready(NetworkEvent::DialError { new_state, peer_id, multiaddr, error })
+        }
+
         // Attempt to deliver any pending take over messages.
         if let Some((id, interrupted)) = self.take_over_to_complete.take() {
             if let Some(mut peer) = self.active_nodes.peer_mut(&id) {
@@ -1047,12 +1246,12 @@ where
         match self.active_nodes.poll(cx) {
             Poll::Pending => return Poll::Pending,
             Poll::Ready(CollectionEvent::NodeReached(reach_event)) => {
-                let (a, e) = handle_node_reached(&mut self.reach_attempts, reach_event);
+                let (a, e) = handle_node_reached(&mut self.reach_attempts, &self.limits, reach_event);
                 action = a;
                 out_event = e;
             }
             Poll::Ready(CollectionEvent::ReachError { id, error, handler }) => {
-                let (a, e) = handle_reach_error(&mut self.reach_attempts, id, error, handler);
+                let (a, e) = handle_reach_error(&mut self.reach_attempts, &mut self.raced_errors, id, error, handler);
                 action = a;
                 out_event = e;
             }
@@ -1078,10 +1277,14 @@ where
                 action = Default::default();
                 out_event = NetworkEvent::NodeEvent { conn_info: peer.info().0.clone(), event };
             }
+            Poll::Ready(CollectionEvent::MuxerStats { peer, stats }) => {
+                action = Default::default();
+                out_event = NetworkEvent::MuxerStats { conn_info: peer.info().0.clone(), stats };
+            }
         }
 
-        if let Some((peer_id, handler, first, rest)) = action.start_dial_out {
-            self.start_dial_out(peer_id, handler, first, rest);
+        if let Some((peer_id, handler, first, rest, handle, opts)) = action.start_dial_out {
+            self.start_dial_out(peer_id, handler, first, rest, handle, opts);
         }
 
         if let Some((peer_id, interrupt)) = action.take_over {
@@ -1111,7 +1314,7 @@ where
 #[derive(Debug)]
 #[must_use]
 struct ActionItem<THandler, TPeerId> {
-    start_dial_out: Option<(TPeerId, THandler, Multiaddr, Vec<Multiaddr>)>,
+    start_dial_out: Option<(TPeerId, THandler, Multiaddr, Vec<Multiaddr>, DialHandle, DialOpts)>,
     /// The `ReachAttemptId` should be interrupted, and the task for the given `PeerId` should take
     /// over it.
     take_over: Option<(TPeerId, ReachAttemptId)>,
@@ -1134,6 +1337,7 @@ impl<THandler, TPeerId> Default for ActionItem<THandler, TPeerId> {
 /// >           panics will likely happen.
 fn handle_node_reached<'a, TTrans, TMuxer, TInEvent, TOutEvent, THandler, THandlerErr, TConnInfo, TPeerId>(
     reach_attempts: &mut ReachAttempts<TPeerId>,
+    limits: &ConnectionLimits,
     event: CollectionReachEvent<'_, TInEvent, TOutEvent, THandler, InternalReachErr<TTrans::Error, TConnInfo>, THandlerErr, (), (TConnInfo, ConnectedPoint), TPeerId>,
 ) -> (ActionItem<THandler, TPeerId>, NetworkEvent<'a, TTrans, TInEvent, TOutEvent, THandler, THandlerErr, TConnInfo, TPeerId>)
 where
@@ -1169,6 +1373,21 @@ where
             }
         }
 
+        // Incoming connections are checked once more, as others may have been established
+        // since their arrival.
+        if !event.would_replace() {
+            if let ConnectedPoint::Listener { local_addr, send_back_addr } = &opened_endpoint {
+                if let Err(limit) = limits.check_established(reach_attempts) {
+                    event.deny();
+                    return (Default::default(), NetworkEvent::IncomingConnectionError {
+                        local_addr: local_addr.clone(),
+                        send_back_addr: send_back_addr.clone(),
+                        error: IncomingError::ConnectionDenied(limit),
+                    });
+                }
+            }
+        }
+
         // Set the endpoint for this peer.
         let closed_endpoint = reach_attempts.connected_points.insert(event.peer_id().clone(), opened_endpoint.clone());
 
@@ -1225,13 +1444,14 @@ where
     // We only remove the attempt from `out_reach_attempts` if it both matches the reach id
     // and the expected peer id.
     if is_outgoing_and_ok {
-        let attempt = reach_attempts.out_reach_attempts.remove(event.peer_id())
+        reach_attempts.out_reach_attempts.remove(event.peer_id())
             .expect("is_outgoing_and_ok is true only if reach_attempts.out_reach_attempts.get(event.peer_id()) \
                         returned Some");
 
-        let opened_endpoint = ConnectedPoint::Dialer {
-            address: attempt.cur_attempted,
-        };
+        // The address that succeeded may not be the first one if several were dialed
+        // concurrently.
+        let opened_endpoint = event.connection_info().1.clone();
+        debug_assert!(opened_endpoint.is_dialer());
 
         let closed_endpoint = reach_attempts.connected_points
             .insert(event.peer_id().clone(), opened_endpoint.clone());
@@ -1278,6 +1498,45 @@ where
     local.as_ref() < other.as_ref()
 }
 
+/// Converts the error of a reach attempt of a known peer.
+fn network_reach_error<TTransErr, TConnInfo>(error: InternalReachErr<TTransErr, TConnInfo>)
+    -> NetworkReachError<TTransErr, TConnInfo>
+{
+    match error {
+        InternalReachErr::Transport(err) => NetworkReachError::Transport(err),
+        InternalReachErr::PeerIdMismatch { obtained } => {
+            NetworkReachError::PeerIdMismatch { obtained }
+        },
+        InternalReachErr::Cancelled => NetworkReachError::Cancelled,
+        InternalReachErr::Timeout => NetworkReachError::Timeout,
+        InternalReachErr::ConnectionDenied(limit) => NetworkReachError::ConnectionDenied(limit),
+        InternalReachErr::FoundLocalPeerId => {
+            unreachable!("We only generate FoundLocalPeerId within dial() or accept(); neither \
+                          of these methods add an entry to out_reach_attempts; QED")
+        },
+        InternalReachErr::Raced(_) => {
+            unreachable!("The errors of a batch of concurrent dials are converted one by one, and \
+                          a batch never contains another one; QED")
+        },
+    }
+}
+
+/// Returns the state of a peer after a dial error, given the number of addresses still to be
+/// attempted or reported.
+fn dial_error_state<TPeerId>(reach_attempts: &ReachAttempts<TPeerId>, peer_id: &TPeerId, num_remain: usize)
+    -> PeerState
+where
+    TPeerId: Eq + Hash,
+{
+    if reach_attempts.connected_points.contains_key(peer_id) {
+        PeerState::Connected
+    } else if let Some(num_pending_addresses) = NonZeroUsize::new(num_remain) {
+        PeerState::Dialing { num_pending_addresses }
+    } else {
+        PeerState::NotConnected
+    }
+}
+
 /// Handles a reach error event from the collection.
 ///
 /// Optionally returns an event to return from the stream.
@@ -1286,6 +1545,7 @@ where
 /// >           panics will likely happen.
 fn handle_reach_error<'a, TTrans, TInEvent, TOutEvent, THandler, THandlerErr, TConnInfo, TPeerId>(
     reach_attempts: &mut ReachAttempts<TPeerId>,
+    raced_errors: &mut VecDeque<RacedError<TPeerId, TTrans::Error, TConnInfo>>,
     reach_id: ReachAttemptId,
     error: InternalReachErr<TTrans::Error, TConnInfo>,
     handler: THandler,
@@ -1303,45 +1563,44 @@ where
         .find(|(_, a)| a.id == reach_id)
         .map(|(p, _)| p.clone());
     if let Some(peer_id) = out_reach_peer_id {
-        let attempt = reach_attempts.out_reach_attempts.remove(&peer_id)
+        let mut attempt = reach_attempts.out_reach_attempts.remove(&peer_id)
             .expect("out_reach_peer_id is a key that is grabbed from out_reach_attempts");
 
-        let num_remain = attempt.next_attempts.len();
-        let failed_addr = attempt.cur_attempted.clone();
+        // A cancelled dial doesn't proceed with the remaining addresses.
+        if attempt.handle.is_cancelled() {
+            attempt.next_attempts.clear();
+        }
 
-        let new_state = if reach_attempts.connected_points.contains_key(&peer_id) {
-            PeerState::Connected
-        } else if num_remain == 0 {
-            PeerState::NotConnected
-        } else {
-            PeerState::Dialing {
-                num_pending_addresses: NonZeroUsize::new(num_remain)
-                    .expect("We check that num_remain is not 0 right above; QED"),
-            }
+        // The failures of addresses dialed concurrently are reported one at a time.
+        let (failed_addr, error) = match error {
+            InternalReachErr::Raced(errors) => {
+                for (addr, err) in errors {
+                    raced_errors.push_back((peer_id.clone(), addr, network_reach_error(err)));
+                }
+                let (_, addr, err) = raced_errors.iter()
+                    .position(|(p, ..)| *p == peer_id)
+                    .and_then(|pos| raced_errors.remove(pos))
+                    .expect("A batch of concurrent dials contains at least two addresses; QED");
+                (addr, err)
+            },
+            error => (attempt.cur_attempted.clone(), network_reach_error(error)),
         };
 
+        let num_remain = attempt.next_attempts.len()
+            + raced_errors.iter().filter(|(p, ..)| *p == peer_id).count();
+        let new_state = dial_error_state(reach_attempts, &peer_id, num_remain);
+
         let action = if !attempt.next_attempts.is_empty() {
-            let mut attempt = attempt;
             let next_attempt = attempt.next_attempts.remove(0);
             ActionItem {
-                start_dial_out: Some((peer_id.clone(), handler, next_attempt, attempt.next_attempts)),
+                start_dial_out: Some((peer_id.clone(), handler, next_attempt, attempt.next_attempts,
+                    attempt.handle, attempt.opts)),
                 .. Default::default()
             }
         } else {
             Default::default()
         };
 
-        let error = match error {
-            InternalReachErr::Transport(err) => NetworkReachError::Transport(err),
-            InternalReachErr::PeerIdMismatch { obtained } => {
-                NetworkReachError::PeerIdMismatch { obtained }
-            },
-            InternalReachErr::FoundLocalPeerId => {
-                unreachable!("We only generate FoundLocalPeerId within dial() or accept(); neither \
-                              of these methods add an entry to out_reach_attempts; QED")
-            },
-        };
-
         return (action, NetworkEvent::DialError {
             new_state,
             peer_id,
@@ -1362,9 +1621,13 @@ where
                 let error = match error {
                     InternalReachErr::Transport(err) => UnknownPeerDialErr::Transport(err),
                     InternalReachErr::FoundLocalPeerId => UnknownPeerDialErr::FoundLocalPeerId,
-                    InternalReachErr::PeerIdMismatch { .. } => {
-                        unreachable!("We only generate PeerIdMismatch within start_dial_out(),
-                                      which doesn't add any entry in other_reach_attempts; QED")
+                    InternalReachErr::Cancelled => UnknownPeerDialErr::Cancelled,
+                    InternalReachErr::Timeout => UnknownPeerDialErr::Timeout,
+                    InternalReachErr::ConnectionDenied(limit) => UnknownPeerDialErr::ConnectionDenied(limit),
+                    InternalReachErr::PeerIdMismatch { .. } | InternalReachErr::Raced(_) => {
+                        unreachable!("We only generate PeerIdMismatch and Raced within \
+                                      start_dial_out(), which doesn't add any entry in \
+                                      other_reach_attempts; QED")
                     },
                 };
                 return (Default::default(), NetworkEvent::UnknownPeerDialError {
@@ -1377,9 +1640,15 @@ where
                 let error = match error {
                     InternalReachErr::Transport(err) => IncomingError::Transport(err),
                     InternalReachErr::FoundLocalPeerId => IncomingError::FoundLocalPeerId,
-                    InternalReachErr::PeerIdMismatch { .. } => {
-                        unreachable!("We only generate PeerIdMismatch within start_dial_out(),
-                                      which doesn't add any entry in other_reach_attempts; QED")
+                    InternalReachErr::PeerIdMismatch { .. } | InternalReachErr::Raced(_) => {
+                        unreachable!("We only generate PeerIdMismatch and Raced within \
+                                      start_dial_out(), which doesn't add any entry in \
+                                      other_reach_attempts; QED")
+                    },
+                    InternalReachErr::Cancelled | InternalReachErr::Timeout |
+                    InternalReachErr::ConnectionDenied(_) => {
+                        unreachable!("We only generate Cancelled, Timeout and ConnectionDenied \
+                                      for dials, which are never listeners; QED")
                     },
                 };
                 return (Default::default(), NetworkEvent::IncomingConnectionError {
@@ -1666,6 +1935,15 @@ where
             .expect("A PeerConnected is always created with a PeerId in active_nodes; QED")
             .poll_ready_event(cx)
     }
+
+    /// Begin requesting the statistics of the muxer of the node, which are reported with a
+    /// `NetworkEvent::MuxerStats` if the muxer keeps any. Must be called only after a successful
+    /// call to `poll_ready_event`.
+    pub fn start_request_muxer_stats(&mut self) {
+        self.active_nodes.peer_mut(&self.peer_id)
+            .expect("A PeerConnected is always created with a PeerId in active_nodes; QED")
+            .start_request_muxer_stats()
+    }
 }
 
 /// Access to a peer we are attempting to connect to.
@@ -1700,6 +1978,13 @@ where
         }
     }
 
+    /// Returns a handle to cancel this connection attempt.
+    ///
+    /// Contrary to `interrupt`, cancelling through the handle produces a `DialError` event.
+    pub fn dial_handle(&self) -> DialHandle {
+        self.attempt.get().handle.clone()
+    }
+
     /// Returns the multiaddress we're currently trying to dial.
     pub fn attempted_multiaddr(&self) -> &Multiaddr {
         &self.attempt.get().cur_attempted
@@ -1779,7 +2064,7 @@ where
         TConnInfo: fmt::Debug + ConnectionInfo<PeerId = TPeerId> + Send + 'static,
         TPeerId: Eq + Hash + Clone + Send + 'static,
     {
-        self.connect_inner(handler, addr, Vec::new())
+        self.connect_inner(handler, addr, Vec::new(), DialOpts::default())
     }
 
     /// Attempts a new connection to this node using the given multiaddresses.
@@ -1796,6 +2081,19 @@ where
         TIter: IntoIterator<Item = Multiaddr>,
         TConnInfo: fmt::Debug + ConnectionInfo<PeerId = TPeerId> + Send + 'static,
         TPeerId: Eq + Hash + Clone + Send + 'static,
+    {
+        self.connect_iter_with_opts(addrs, handler, DialOpts::default())
+    }
+
+    /// Same as `connect_iter`, but applies the given options to the dial.
+    ///
+    /// The handle to cancel the dial can be obtained with `PeerPendingConnect::dial_handle`.
+    pub fn connect_iter_with_opts<TIter>(self, addrs: TIter, handler: THandler, opts: DialOpts)
+        -> Result<PeerPendingConnect<'a, TTrans, TInEvent, TOutEvent, THandler, THandlerErr, TConnInfo, TPeerId>, Self>
+    where
+        TIter: IntoIterator<Item = Multiaddr>,
+        TConnInfo: fmt::Debug + ConnectionInfo<PeerId = TPeerId> + Send + 'static,
+        TPeerId: Eq + Hash + Clone + Send + 'static,
     {
         let mut addrs = addrs.into_iter();
         let first = match addrs.next() {
@@ -1803,7 +2101,7 @@ where
             None => return Err(self)
         };
         let rest = addrs.collect();
-        Ok(self.connect_inner(handler, first, rest))
+        Ok(self.connect_inner(handler, first, rest, opts))
     }
 
     /// Moves the given node to a connected state using the given connection info and muxer.
@@ -1840,13 +2138,13 @@ where
     }
 
     /// Inner implementation of `connect`.
-    fn connect_inner(self, handler: THandler, first: Multiaddr, rest: Vec<Multiaddr>)
+    fn connect_inner(self, handler: THandler, first: Multiaddr, rest: Vec<Multiaddr>, opts: DialOpts)
         -> PeerPendingConnect<'a, TTrans, TInEvent, TOutEvent, THandler, THandlerErr, TConnInfo, TPeerId>
     where
         TConnInfo: fmt::Debug + ConnectionInfo<PeerId = TPeerId> + Send + 'static,
         TPeerId: Eq + Hash + Clone + Send + 'static,
     {
-        self.nodes.start_dial_out(self.peer_id.clone(), handler, first, rest);
+        self.nodes.start_dial_out(self.peer_id.clone(), handler, first, rest, DialHandle::new(), opts);
         PeerPendingConnect {
             attempt: match self.nodes.reach_attempts.out_reach_attempts.entry(self.peer_id) {
                 Entry::Occupied(e) => e,
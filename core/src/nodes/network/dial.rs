@@ -0,0 +1,274 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Timeouts and cancellation of individual dials.
+
+use super::InternalReachErr;
+use crate::Multiaddr;
+use futures::{prelude::*, future::BoxFuture, stream::FuturesUnordered, task::AtomicWaker};
+use futures_timer::Delay;
+use std::{
+    mem,
+    num::NonZeroU8,
+    pin::Pin,
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Options applying to an individual dial.
+#[derive(Debug, Clone)]
+pub struct DialOpts {
+    timeout: Option<Duration>,
+    concurrency: NonZeroU8,
+    condition: PeerCondition,
+    addresses: Option<Vec<Multiaddr>>,
+    tag: Option<u64>,
+}
+
+/// The condition under which a peer is dialed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PeerCondition {
+    /// Dial the peer unless we are connected to it. If we are already dialing the peer, the
+    /// addresses to dial are appended to the ongoing dial.
+    Disconnected,
+    /// Dial the peer unless we are connected to it or already dialing it.
+    NotDialing,
+}
+
+impl Default for PeerCondition {
+    fn default() -> Self {
+        PeerCondition::Disconnected
+    }
+}
+
+impl Default for DialOpts {
+    fn default() -> Self {
+        DialOpts {
+            timeout: None,
+            concurrency: NonZeroU8::new(1).expect("1 > 0"),
+            condition: PeerCondition::default(),
+            addresses: None,
+            tag: None,
+        }
+    }
+}
+
+impl DialOpts {
+    /// Creates the default options, i.e. no timeout and one address dialed at a time.
+    pub fn new() -> Self {
+        DialOpts::default()
+    }
+
+    /// Sets the maximum duration of each connection attempt, including the upgrades applied
+    /// by the transport.
+    ///
+    /// When dialing a peer at multiple addresses, the timeout applies to each address
+    /// separately.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the number of addresses of a peer dialed at the same time.
+    ///
+    /// The first address to succeed wins and the other dials of the batch are aborted. The next
+    /// batch of addresses is dialed once all the addresses of the batch failed.
+    pub fn concurrency(mut self, concurrency: NonZeroU8) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Sets the condition under which a peer is dialed. Defaults to
+    /// [`PeerCondition::Disconnected`].
+    ///
+    /// Only applies to the dials of the swarm.
+    pub fn condition(mut self, condition: PeerCondition) -> Self {
+        self.condition = condition;
+        self
+    }
+
+    /// Sets the addresses to dial the peer at, instead of the known addresses of the peer.
+    ///
+    /// Only applies to the dials of the swarm.
+    pub fn addresses(mut self, addresses: impl IntoIterator<Item = Multiaddr>) -> Self {
+        self.addresses = Some(addresses.into_iter().collect());
+        self
+    }
+
+    /// Sets an opaque tag, echoed back in the events of the swarm resulting from the dial.
+    pub fn tag(mut self, tag: u64) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    /// Returns the number of addresses of a peer dialed at the same time.
+    pub fn get_concurrency(&self) -> NonZeroU8 {
+        self.concurrency
+    }
+
+    /// Returns the condition under which a peer is dialed.
+    pub fn get_condition(&self) -> PeerCondition {
+        self.condition
+    }
+
+    /// Returns the addresses to dial the peer at, if set.
+    pub fn get_addresses(&self) -> Option<&[Multiaddr]> {
+        self.addresses.as_deref()
+    }
+
+    /// Returns the tag of the dial, if set.
+    pub fn get_tag(&self) -> Option<u64> {
+        self.tag
+    }
+}
+
+/// Handle to an individual dial, allowing to cancel it.
+///
+/// Dropping the handle does not cancel the dial.
+#[derive(Debug, Clone)]
+pub struct DialHandle {
+    inner: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    cancelled: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl DialHandle {
+    pub(super) fn new() -> Self {
+        DialHandle {
+            inner: Arc::new(Shared {
+                cancelled: AtomicBool::new(false),
+                waker: AtomicWaker::new(),
+            })
+        }
+    }
+
+    /// Cancels the dial.
+    ///
+    /// Unless the connection has already been established, the dial fails with a `Cancelled`
+    /// error and the remaining addresses of the peer, if any, are not attempted.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.waker.wake();
+    }
+
+    /// Returns true if `cancel` has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wraps around the future of a connection attempt, enforcing the cancellation and the
+    /// timeout of `opts`.
+    pub(super) fn guard<F>(&self, inner: F, opts: &DialOpts) -> DialFuture<F> {
+        DialFuture {
+            inner,
+            handle: self.clone(),
+            timer: opts.timeout.map(Delay::new),
+        }
+    }
+}
+
+/// A connection attempt that can time out or be cancelled through its `DialHandle`.
+#[pin_project::pin_project]
+#[must_use = "futures do nothing unless polled"]
+pub(super) struct DialFuture<F> {
+    #[pin]
+    inner: F,
+    handle: DialHandle,
+    timer: Option<Delay>,
+}
+
+impl<F, T, TTransErr, TConnInfo> Future for DialFuture<F>
+where
+    F: Future<Output = Result<T, InternalReachErr<TTransErr, TConnInfo>>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+
+        this.handle.inner.waker.register(cx.waker());
+        if this.handle.is_cancelled() {
+            return Poll::Ready(Err(InternalReachErr::Cancelled))
+        }
+
+        if let Poll::Ready(v) = this.inner.poll(cx) {
+            return Poll::Ready(v)
+        }
+
+        match this.timer {
+            Some(timer) => match timer.poll_unpin(cx) {
+                Poll::Ready(()) => Poll::Ready(Err(InternalReachErr::Timeout)),
+                Poll::Pending => Poll::Pending,
+            },
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// A connection attempt within a `RaceFuture`, resolving along with the address it dials.
+pub(super) type RacedDial<T, TTransErr, TConnInfo> =
+    BoxFuture<'static, (Multiaddr, Result<T, InternalReachErr<TTransErr, TConnInfo>>)>;
+
+/// A batch of connection attempts to the same peer at different addresses, racing each other.
+///
+/// Resolves to the first successful attempt. Fails with all the errors once every attempt
+/// failed, or at once if the dial is cancelled.
+#[must_use = "futures do nothing unless polled"]
+pub(super) struct RaceFuture<T, TTransErr, TConnInfo> {
+    dials: FuturesUnordered<RacedDial<T, TTransErr, TConnInfo>>,
+    errors: Vec<(Multiaddr, InternalReachErr<TTransErr, TConnInfo>)>,
+}
+
+impl<T, TTransErr, TConnInfo> RaceFuture<T, TTransErr, TConnInfo> {
+    pub(super) fn new(dials: impl IntoIterator<Item = RacedDial<T, TTransErr, TConnInfo>>) -> Self {
+        RaceFuture {
+            dials: dials.into_iter().collect(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl<T, TTransErr, TConnInfo> Unpin for RaceFuture<T, TTransErr, TConnInfo> {}
+
+impl<T, TTransErr, TConnInfo> Future for RaceFuture<T, TTransErr, TConnInfo> {
+    type Output = Result<T, InternalReachErr<TTransErr, TConnInfo>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            match this.dials.poll_next_unpin(cx) {
+                Poll::Ready(Some((_, Ok(v)))) => return Poll::Ready(Ok(v)),
+                Poll::Ready(Some((_, Err(InternalReachErr::Cancelled)))) =>
+                    return Poll::Ready(Err(InternalReachErr::Cancelled)),
+                Poll::Ready(Some((addr, Err(err)))) => this.errors.push((addr, err)),
+                Poll::Ready(None) => {
+                    let errors = mem::take(&mut this.errors);
+                    return Poll::Ready(Err(InternalReachErr::Raced(errors)))
+                },
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Limits on the connections of a `Network`.
+
+use super::ReachAttempts;
+use crate::{ConnectedPoint, Multiaddr, multiaddr::Protocol};
+use std::{error, fmt, hash::Hash, net::IpAddr};
+
+/// Limits on the connections of a `Network`, protecting it from exhaustion.
+///
+/// Incoming connections are checked when they arrive, and once more against the limit of
+/// established connections when they are established. Dials are checked when they start.
+///
+/// As the `Network` keeps at most one connection per peer, replacing the existing one when a
+/// new one is established, connections per peer are inherently limited to one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    max_established: Option<usize>,
+    max_pending_dials: Option<usize>,
+    max_pending_incoming: Option<usize>,
+    max_per_subnet: Option<SubnetLimit>,
+}
+
+/// Limit on the connections with the remote IP addresses of a subnet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct SubnetLimit {
+    ipv4_prefix_len: u8,
+    ipv6_prefix_len: u8,
+    max: usize,
+}
+
+impl ConnectionLimits {
+    /// Creates limits which do not limit anything.
+    pub fn new() -> Self {
+        ConnectionLimits::default()
+    }
+
+    /// Sets the maximum number of established connections.
+    pub fn max_established(mut self, max: usize) -> Self {
+        self.max_established = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of dials in progress.
+    pub fn max_pending_dials(mut self, max: usize) -> Self {
+        self.max_pending_dials = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of incoming connections being negotiated.
+    pub fn max_pending_incoming(mut self, max: usize) -> Self {
+        self.max_pending_incoming = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of connections, established or pending, per remote IP address.
+    pub fn max_per_ip(self, max: usize) -> Self {
+        self.max_per_subnet(32, 128, max)
+    }
+
+    /// Sets the maximum number of connections, established or pending, with the remote IP
+    /// addresses of a subnet, given by the lengths of the IPv4 and IPv6 network prefixes,
+    /// e.g. 24 and 64.
+    ///
+    /// Connections over addresses without IP address, e.g. DNS names, are not limited.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a prefix length exceeds the length of the addresses.
+    pub fn max_per_subnet(mut self, ipv4_prefix_len: u8, ipv6_prefix_len: u8, max: usize) -> Self {
+        assert!(ipv4_prefix_len <= 32, "IPv4 prefix length must be at most 32");
+        assert!(ipv6_prefix_len <= 128, "IPv6 prefix length must be at most 128");
+        self.max_per_subnet = Some(SubnetLimit { ipv4_prefix_len, ipv6_prefix_len, max });
+        self
+    }
+
+    /// Checks whether a dial to `addr` of the peer `peer`, if known, can start.
+    ///
+    /// A dial of a peer with an established connection is exempt from the limit of
+    /// established connections, as its connection would replace the existing one.
+    pub(super) fn check_dial<TPeerId>(&self, attempts: &ReachAttempts<TPeerId>, peer: Option<&TPeerId>, addr: &Multiaddr)
+        -> Result<(), ConnectionLimit>
+    where
+        TPeerId: Eq + Hash,
+    {
+        if !peer.map_or(false, |peer| attempts.connected_points.contains_key(peer)) {
+            self.check_established(attempts)?;
+        }
+        if let Some(max) = self.max_pending_dials {
+            let pending = attempts.out_reach_attempts.len() + attempts.other_reach_attempts
+                .iter()
+                .filter(|(_, endpoint)| endpoint.is_dialer())
+                .count();
+            if pending >= max {
+                return Err(ConnectionLimit::PendingDials(max))
+            }
+        }
+        self.check_subnet(attempts, addr)
+    }
+
+    /// Checks whether an incoming connection from `send_back_addr` can be negotiated.
+    pub(super) fn check_incoming<TPeerId>(&self, attempts: &ReachAttempts<TPeerId>, send_back_addr: &Multiaddr)
+        -> Result<(), ConnectionLimit>
+    where
+        TPeerId: Eq + Hash,
+    {
+        self.check_established(attempts)?;
+        if let Some(max) = self.max_pending_incoming {
+            let pending = attempts.other_reach_attempts
+                .iter()
+                .filter(|(_, endpoint)| endpoint.is_listener())
+                .count();
+            if pending >= max {
+                return Err(ConnectionLimit::PendingIncoming(max))
+            }
+        }
+        self.check_subnet(attempts, send_back_addr)
+    }
+
+    /// Checks whether a connection can be established without replacing an existing one.
+    pub(super) fn check_established<TPeerId>(&self, attempts: &ReachAttempts<TPeerId>)
+        -> Result<(), ConnectionLimit>
+    where
+        TPeerId: Eq + Hash,
+    {
+        match self.max_established {
+            Some(max) if attempts.connected_points.len() >= max =>
+                Err(ConnectionLimit::Established(max)),
+            _ => Ok(())
+        }
+    }
+
+    fn check_subnet<TPeerId>(&self, attempts: &ReachAttempts<TPeerId>, addr: &Multiaddr)
+        -> Result<(), ConnectionLimit>
+    where
+        TPeerId: Eq + Hash,
+    {
+        let limit = match self.max_per_subnet {
+            Some(limit) => limit,
+            None => return Ok(())
+        };
+        let subnet = match limit.subnet(addr) {
+            Some(subnet) => subnet,
+            None => return Ok(())
+        };
+        let remote_addrs = attempts.connected_points.values()
+            .chain(attempts.other_reach_attempts.iter().map(|(_, endpoint)| endpoint))
+            .map(remote_addr)
+            .chain(attempts.out_reach_attempts.values().map(|attempt| &attempt.cur_attempted));
+        let count = remote_addrs.filter(|addr| limit.subnet(addr) == Some(subnet)).count();
+        if count >= limit.max {
+            return Err(ConnectionLimit::Subnet(limit.max))
+        }
+        Ok(())
+    }
+}
+
+impl SubnetLimit {
+    /// Returns the subnet of the IP address of `addr`, if any.
+    fn subnet(&self, addr: &Multiaddr) -> Option<IpAddr> {
+        match addr.iter().next()? {
+            Protocol::Ip4(ip) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.ipv4_prefix_len)).unwrap_or(0);
+                Some(IpAddr::V4((u32::from(ip) & mask).into()))
+            }
+            Protocol::Ip6(ip) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.ipv6_prefix_len)).unwrap_or(0);
+                Some(IpAddr::V6((u128::from(ip) & mask).into()))
+            }
+            _ => None
+        }
+    }
+}
+
+/// Returns the address of the remote of a connection.
+fn remote_addr(endpoint: &ConnectedPoint) -> &Multiaddr {
+    match endpoint {
+        ConnectedPoint::Dialer { address } => address,
+        ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
+    }
+}
+
+/// A limit of [`ConnectionLimits`] which denied a connection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConnectionLimit {
+    /// The maximum number of established connections.
+    Established(usize),
+    /// The maximum number of dials in progress.
+    PendingDials(usize),
+    /// The maximum number of incoming connections being negotiated.
+    PendingIncoming(usize),
+    /// The maximum number of connections with a subnet.
+    Subnet(usize),
+}
+
+impl fmt::Display for ConnectionLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionLimit::Established(max) =>
+                write!(f, "Limit of {} established connections reached", max),
+            ConnectionLimit::PendingDials(max) =>
+                write!(f, "Limit of {} pending dials reached", max),
+            ConnectionLimit::PendingIncoming(max) =>
+                write!(f, "Limit of {} pending incoming connections reached", max),
+            ConnectionLimit::Subnet(max) =>
+                write!(f, "Limit of {} connections with the subnet reached", max),
+        }
+    }
+}
+
+impl error::Error for ConnectionLimit {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subnets() {
+        let limit = SubnetLimit { ipv4_prefix_len: 24, ipv6_prefix_len: 64, max: 1 };
+        let subnet = |s: &str| limit.subnet(&s.parse().unwrap());
+        assert_eq!(subnet("/ip4/1.2.3.4/tcp/1"), subnet("/ip4/1.2.3.200/tcp/2"));
+        assert_ne!(subnet("/ip4/1.2.3.4/tcp/1"), subnet("/ip4/1.2.4.4/tcp/1"));
+        assert_eq!(subnet("/ip6/2001:db8::1/tcp/1"), subnet("/ip6/2001:db8::ffff:1/tcp/1"));
+        assert_ne!(subnet("/ip6/2001:db8::1/tcp/1"), subnet("/ip6/2001:db9::1/tcp/1"));
+        assert_eq!(subnet("/dns4/example.com/tcp/1"), None);
+
+        let any = SubnetLimit { ipv4_prefix_len: 0, ipv6_prefix_len: 0, max: 1 };
+        assert_eq!(any.subnet(&"/ip4/1.2.3.4/tcp/1".parse().unwrap()), Some(IpAddr::V4([0; 4].into())));
+    }
+
+    #[test]
+    fn dials_replacing_a_connection_are_not_limited() {
+        let address: Multiaddr = "/ip4/1.2.3.4/tcp/1".parse().unwrap();
+        let mut attempts = ReachAttempts {
+            local_peer_id: 0,
+            out_reach_attempts: Default::default(),
+            other_reach_attempts: Vec::new(),
+            connected_points: Default::default(),
+        };
+        attempts.connected_points.insert(1, ConnectedPoint::Dialer { address: address.clone() });
+
+        let limits = ConnectionLimits::new().max_established(1);
+        assert_eq!(limits.check_dial(&attempts, None, &address), Err(ConnectionLimit::Established(1)));
+        assert_eq!(limits.check_dial(&attempts, Some(&2), &address), Err(ConnectionLimit::Established(1)));
+        assert_eq!(limits.check_dial(&attempts, Some(&1), &address), Ok(()));
+    }
+}
@@ -114,6 +114,12 @@ where
         self.outbound_substreams.push((user_data, raw));
     }
 
+    /// Same as `open_substream`, but the substream is given the priority `priority`.
+    pub fn open_substream_with_priority(&mut self, user_data: TUserData, priority: muxing::SubstreamPriority) {
+        let raw = self.muxer.open_outbound_with_priority(priority);
+        self.outbound_substreams.push((user_data, raw));
+    }
+
     /// Returns `true` if the remote has shown any sign of activity after the muxer has been open.
     ///
     /// See `StreamMuxer::is_remote_acknowledged`.
@@ -121,6 +127,13 @@ where
         self.muxer.is_remote_acknowledged()
     }
 
+    /// Returns the statistics of the muxer, if it keeps any.
+    ///
+    /// See `StreamMuxer::stats`.
+    pub fn muxer_stats(&self) -> Option<muxing::MuxerStats> {
+        self.muxer.stats()
+    }
+
     /// Destroys the node stream and returns all the pending outbound substreams, plus an object
     /// that signals the remote that we shut down the connection.
     #[must_use]
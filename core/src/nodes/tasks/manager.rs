@@ -20,7 +20,7 @@
 
 use crate::{
     PeerId,
-    muxing::StreamMuxer,
+    muxing::{MuxerStats, StreamMuxer},
     nodes::{
         handled_node::{HandledNode, IntoNodeHandler, NodeHandler},
         node::Substream
@@ -130,6 +130,14 @@ pub enum Event<'a, I, O, H, E, HE, T, C = PeerId> {
         task: TaskEntry<'a, I, T>,
         /// The produced event.
         event: O
+    },
+
+    /// A task has reported the statistics of its muxer.
+    MuxerStats {
+        /// The task that reported the statistics.
+        task: TaskEntry<'a, I, T>,
+        /// The statistics.
+        stats: MuxerStats
     }
 }
 
@@ -151,6 +159,13 @@ impl<I, O, H, E, HE, T, C> Manager<I, O, H, E, HE, T, C> {
         }
     }
 
+    /// Runs the tasks spawned from now on in [`Manager::poll`], instead of on the threads pool.
+    ///
+    /// This makes the execution of the tasks deterministic, which is mostly useful for tests.
+    pub fn run_tasks_locally(&mut self) {
+        self.threads_pool = None;
+    }
+
     /// Adds to the manager a future that tries to reach a node.
     ///
     /// This method spawns a task dedicated to resolving this future and
@@ -293,6 +308,14 @@ impl<I, O, H, E, HE, T, C> Manager<I, O, H, E, HE, T, C> {
                     },
                     event
                 },
+            FromTaskMessage::MuxerStats(stats) =>
+                Event::MuxerStats {
+                    task: match self.tasks.entry(task_id) {
+                        Entry::Occupied(inner) => TaskEntry { inner },
+                        Entry::Vacant(_) => panic!("poll_inner only returns valid TaskIds; QED")
+                    },
+                    stats
+                },
             FromTaskMessage::NodeReached(conn_info) =>
                 Event::NodeReached {
                     task: match self.tasks.entry(task_id) {
@@ -332,6 +355,13 @@ impl<'a, E, T> TaskEntry<'a, E, T> {
         self.poll_ready_event_msg(cx)
     }
 
+    /// Begin requesting the statistics of the muxer of the node, which the task reports with an
+    /// `Event::MuxerStats` if the muxer keeps any. Must be called only after a successful call to
+    /// `poll_ready_event`.
+    pub fn start_request_muxer_stats(&mut self) {
+        self.start_send_event_msg(ToTaskMessage::MuxerStats);
+    }
+
     /// Returns the user data associated with the task.
     pub fn user_data(&self) -> &T {
         &self.inner.get().user_data
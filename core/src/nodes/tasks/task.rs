@@ -19,7 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::{
-    muxing::StreamMuxer,
+    muxing::{MuxerStats, StreamMuxer},
     nodes::{
         handled_node::{HandledNode, IntoNodeHandler, NodeHandler},
         node::{Close, Substream}
@@ -37,7 +37,9 @@ pub enum ToTaskMessage<T> {
     HandlerEvent(T),
     /// When received, stores the parameter inside the task and keeps it alive
     /// until we have an acknowledgment that the remote has accepted our handshake.
-    TakeOver(mpsc::Sender<ToTaskMessage<T>>)
+    TakeOver(mpsc::Sender<ToTaskMessage<T>>),
+    /// Requests the statistics of the muxer of the node.
+    MuxerStats
 }
 
 /// Message to transmit from a task to the public API.
@@ -48,7 +50,9 @@ pub enum FromTaskMessage<T, H, E, HE, C> {
     /// The task closed.
     TaskClosed(Error<E, HE>, Option<H>),
     /// An event from the node.
-    NodeEvent(T)
+    NodeEvent(T),
+    /// The statistics of the muxer of the node, as requested.
+    MuxerStats(MuxerStats)
 }
 
 /// Implementation of [`Future`] that handles a single node.
@@ -71,7 +75,10 @@ where
     state: State<F, M, H, I, O, E, C>,
 
     /// Channels to keep alive for as long as we don't have an acknowledgment from the remote.
-    taken_over: SmallVec<[mpsc::Sender<ToTaskMessage<I>>; 1]>
+    taken_over: SmallVec<[mpsc::Sender<ToTaskMessage<I>>; 1]>,
+
+    /// Whether the statistics of the muxer have been requested and not sent yet.
+    stats_requested: bool
 }
 
 impl<F, M, H, I, O, E, C> Task<F, M, H, I, O, E, C>
@@ -93,7 +100,8 @@ where
             sender: s,
             receiver: r.fuse(),
             state: State::Future { future: Box::pin(f), handler: h, events_buffer: Vec::new() },
-            taken_over: SmallVec::new()
+            taken_over: SmallVec::new(),
+            stats_requested: false
         }
     }
 
@@ -109,7 +117,8 @@ where
             sender: s,
             receiver: r.fuse(),
             state: State::Node(n),
-            taken_over: SmallVec::new()
+            taken_over: SmallVec::new(),
+            stats_requested: false
         }
     }
 }
@@ -190,6 +199,8 @@ where
                                 events_buffer.push(event),
                             Poll::Ready(Some(ToTaskMessage::TakeOver(take_over))) =>
                                 this.taken_over.push(take_over),
+                            Poll::Ready(Some(ToTaskMessage::MuxerStats)) =>
+                                this.stats_requested = true,
                         }
                     }
                     // Check if dialing succeeded.
@@ -223,6 +234,8 @@ where
                                 node.inject_event(event),
                             Poll::Ready(Some(ToTaskMessage::TakeOver(take_over))) =>
                                 this.taken_over.push(take_over),
+                            Poll::Ready(Some(ToTaskMessage::MuxerStats)) =>
+                                this.stats_requested = true,
                             Poll::Ready(None) => {
                                 // Node closed by the external API; start closing.
                                 this.state = State::Closing(node.close());
@@ -230,6 +243,17 @@ where
                             }
                         }
                     }
+                    // Answer a pending request for the statistics of the muxer.
+                    if this.stats_requested {
+                        this.stats_requested = false;
+                        if let Some(stats) = node.muxer_stats() {
+                            this.state = State::SendEvent {
+                                node: Some(node),
+                                event: FromTaskMessage::MuxerStats(stats)
+                            };
+                            continue 'poll
+                        }
+                    }
                     // Process the node.
                     loop {
                         if !this.taken_over.is_empty() && node.is_remote_acknowledged() {
@@ -266,6 +290,8 @@ where
                                 }
                             Poll::Ready(Some(ToTaskMessage::TakeOver(take_over))) =>
                                 this.taken_over.push(take_over),
+                            Poll::Ready(Some(ToTaskMessage::MuxerStats)) =>
+                                this.stats_requested = true,
                             Poll::Ready(None) =>
                                 // Node closed by the external API; start closing.
                                 if let Some(n) = node {
@@ -22,19 +22,49 @@ use crate::PublicKey;
 use bs58;
 use thiserror::Error;
 use multihash;
-use std::{convert::TryFrom, fmt, str::FromStr};
+use std::{borrow::Cow, convert::TryFrom, fmt, hash, str::FromStr};
 
 /// Public keys with byte-lengths smaller than `MAX_INLINE_KEY_LENGTH` will be
-/// automatically used as the peer id using an identity multihash.
-//
-// Note: see `from_public_key` for how this value will be used in the future.
-const _MAX_INLINE_KEY_LENGTH: usize = 42;
+/// used as the peer id using an identity multihash, if inlining is requested.
+const MAX_INLINE_KEY_LENGTH: usize = 42;
+
+/// Configuration for building `PeerId`s from public keys.
+///
+/// By default keys are always hashed. Enabling `inline_public_keys` embeds small keys as-is,
+/// like go-libp2p does. The setting only affects the `PeerId`s built through this
+/// configuration, so that different parts of a program can make different choices.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerIdConfig {
+    inline_public_keys: bool,
+}
+
+impl PeerIdConfig {
+    /// Creates a configuration that always hashes public keys.
+    pub fn new() -> Self {
+        PeerIdConfig::default()
+    }
+
+    /// Sets whether public keys whose protobuf encoding is at most 42 bytes long, such as
+    /// Ed25519 and Secp256k1 keys, are embedded with the identity multihash.
+    pub fn inline_public_keys(mut self, inline: bool) -> Self {
+        self.inline_public_keys = inline;
+        self
+    }
+
+    /// Builds the `PeerId` of a public key according to this configuration.
+    pub fn peer_id(&self, key: PublicKey) -> PeerId {
+        PeerId::from_public_key_with(key, self.inline_public_keys)
+    }
+}
 
 /// Identifier of a peer of the network.
 ///
-/// The data is a multihash of the public key of the peer.
-// TODO: maybe keep things in decoded version?
-#[derive(Clone, PartialEq, Eq, Hash)]
+/// The data is a multihash of the public key of the peer. Small public keys may instead be
+/// embedded as-is using the identity multihash. A `PeerId` using the identity multihash compares
+/// and hashes equal to the `PeerId` containing the SHA2-256 hash of the same key. Its bytes, as
+/// returned by `as_bytes`, `into_bytes`, `to_base58` and `AsRef<[u8]>`, are those of the form
+/// the `PeerId` was built with. See `to_hashed` for the hashed form.
+#[derive(Clone)]
 pub struct PeerId {
     multihash: multihash::Multihash,
 }
@@ -55,24 +85,38 @@ impl fmt::Display for PeerId {
 
 impl PeerId {
     /// Builds a `PeerId` from a public key.
+    ///
+    /// The key is always hashed. See `from_public_key_inlined` or `PeerIdConfig` for embedding
+    /// small keys.
     #[inline]
     pub fn from_public_key(key: PublicKey) -> PeerId {
-        let key_enc = key.into_protobuf_encoding();
+        PeerId::from_public_key_hashed(key)
+    }
+
+    /// Builds a `PeerId` from a public key, always hashing the key.
+    #[inline]
+    pub fn from_public_key_hashed(key: PublicKey) -> PeerId {
+        PeerId::from_public_key_with(key, false)
+    }
 
-        // Note: the correct behaviour, according to the libp2p specifications, is the
-        // commented-out code, which consists it transmitting small keys un-hashed. However, this
-        // version and all previous versions of rust-libp2p always hash the key. Starting from
-        // version 0.13, rust-libp2p accepts both hashed and non-hashed keys as input
-        // (see `from_bytes`). Starting from version 0.14, rust-libp2p will switch to not hashing
-        // the key (a.k.a. the correct behaviour).
-        // In other words, rust-libp2p 0.13 is compatible with all versions of rust-libp2p.
-        // Rust-libp2p 0.12 and below is **NOT** compatible with rust-libp2p 0.14 and above.
-        /*let hash_algorithm = if key_enc.len() <= MAX_INLINE_KEY_LENGTH {
+    /// Builds a `PeerId` from a public key, embedding the key with the identity multihash if
+    /// its protobuf encoding is at most 42 bytes long, and hashing it otherwise.
+    ///
+    /// This is what go-libp2p does for small keys such as Ed25519 and Secp256k1 keys.
+    /// Rust-libp2p 0.13 and above accept both forms as input (see `from_bytes`), but
+    /// rust-libp2p 0.12 and below do not understand inlined keys.
+    #[inline]
+    pub fn from_public_key_inlined(key: PublicKey) -> PeerId {
+        PeerId::from_public_key_with(key, true)
+    }
+
+    fn from_public_key_with(key: PublicKey, inline: bool) -> PeerId {
+        let key_enc = key.into_protobuf_encoding();
+        let hash_algorithm = if inline && key_enc.len() <= MAX_INLINE_KEY_LENGTH {
             multihash::Hash::Identity
         } else {
             multihash::Hash::SHA2256
-        };*/
-        let hash_algorithm = multihash::Hash::SHA2256;
+        };
         let multihash = multihash::encode(hash_algorithm, &key_enc)
             .expect("identity and sha2-256 are always supported by known public key types");
         PeerId { multihash }
@@ -125,7 +169,7 @@ impl PeerId {
         self.multihash.into_bytes()
     }
 
-    /// Returns a raw bytes representation of this `PeerId`.
+    /// Returns a raw bytes representation of this `PeerId`, in the form it was built with.
     ///
     /// Note that this is not the same as the public key of the peer.
     #[inline]
@@ -145,21 +189,80 @@ impl PeerId {
         self.multihash.digest()
     }
 
+    /// Returns true if the public key is embedded in this `PeerId` using the identity
+    /// multihash.
+    #[inline]
+    pub fn is_inlined(&self) -> bool {
+        self.multihash.algorithm() == multihash::Hash::Identity
+    }
+
+    /// Returns the public key embedded in this `PeerId`, if any.
+    ///
+    /// Returns `None` if the key is hashed or if the embedded bytes aren't a valid public key.
+    pub fn to_public_key(&self) -> Option<PublicKey> {
+        if self.is_inlined() {
+            PublicKey::from_protobuf_encoding(self.multihash.digest()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Returns this `PeerId` with the public key hashed, converting it if it is inlined.
+    ///
+    /// Equal `PeerId`s always have the same bytes in their hashed form.
+    pub fn to_hashed(&self) -> PeerId {
+        PeerId { multihash: canonical(&self.multihash).into_owned() }
+    }
+
     /// Checks whether the public key passed as parameter matches the public key of this `PeerId`.
     ///
+    /// Both the hashed and the inlined forms of the key are accepted.
+    ///
     /// Returns `None` if this `PeerId`s hash algorithm is not supported when encoding the
     /// given public key, otherwise `Some` boolean as the result of an equality check.
     pub fn is_public_key(&self, public_key: &PublicKey) -> Option<bool> {
-        let alg = self.multihash.algorithm();
         let enc = public_key.clone().into_protobuf_encoding();
-        match multihash::encode(alg, &enc) {
-            Ok(h) => Some(h == self.multihash),
+        match multihash::encode(multihash::Hash::SHA2256, &enc) {
+            Ok(h) => Some(h == *canonical(&self.multihash)),
             Err(multihash::EncodeError::UnsupportedType) => None,
             Err(multihash::EncodeError::UnsupportedInputLength) => None,
         }
     }
 }
 
+/// Returns the SHA2-256 form of a `PeerId` multihash, which is only computed for the
+/// identity multihash.
+///
+/// The identity multihash of a key contains its protobuf encoding, whose hash is the digest of
+/// the hashed form of the same key.
+fn canonical(multihash: &multihash::Multihash) -> Cow<'_, multihash::Multihash> {
+    if multihash.algorithm() == multihash::Hash::Identity {
+        Cow::Owned(multihash::encode(multihash::Hash::SHA2256, multihash.digest())
+            .expect("sha2-256 is always supported"))
+    } else {
+        Cow::Borrowed(multihash)
+    }
+}
+
+impl PartialEq for PeerId {
+    #[inline]
+    fn eq(&self, other: &PeerId) -> bool {
+        if self.multihash.algorithm() == other.multihash.algorithm() {
+            self.multihash == other.multihash
+        } else {
+            canonical(&self.multihash) == canonical(&other.multihash)
+        }
+    }
+}
+
+impl Eq for PeerId {}
+
+impl hash::Hash for PeerId {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        canonical(&self.multihash).hash(state)
+    }
+}
+
 impl From<PublicKey> for PeerId {
     #[inline]
     fn from(key: PublicKey) -> PeerId {
@@ -186,14 +289,14 @@ impl TryFrom<multihash::Multihash> for PeerId {
 impl PartialEq<multihash::Multihash> for PeerId {
     #[inline]
     fn eq(&self, other: &multihash::Multihash) -> bool {
-        &self.multihash == other
+        &self.multihash == other || canonical(&self.multihash) == canonical(other)
     }
 }
 
 impl PartialEq<PeerId> for multihash::Multihash {
     #[inline]
     fn eq(&self, other: &PeerId) -> bool {
-        self == &other.multihash
+        other == self
     }
 }
 
@@ -238,7 +341,7 @@ impl FromStr for PeerId {
 
 #[cfg(test)]
 mod tests {
-    use crate::{PeerId, identity};
+    use crate::{PeerId, PeerIdConfig, identity};
 
     #[test]
     fn peer_id_is_public_key() {
@@ -261,6 +364,56 @@ mod tests {
         assert_eq!(peer_id, second);
     }
 
+    #[test]
+    fn inlined_peer_id_is_public_key() {
+        let key = identity::Keypair::generate_ed25519().public();
+        let peer_id = PeerId::from_public_key_inlined(key.clone());
+        assert!(peer_id.is_inlined());
+        assert_eq!(peer_id.is_public_key(&key), Some(true));
+        assert_eq!(peer_id.to_public_key(), Some(key));
+    }
+
+    #[test]
+    fn inlined_and_hashed_peer_ids_are_equal() {
+        use std::collections::HashSet;
+
+        let key = identity::Keypair::generate_ed25519().public();
+        let inlined = PeerId::from_public_key_inlined(key.clone());
+        let hashed = PeerId::from_public_key_hashed(key);
+        assert!(inlined.is_inlined());
+        assert!(!hashed.is_inlined());
+        assert_ne!(inlined.as_bytes(), hashed.as_bytes());
+        assert_eq!(inlined, hashed);
+        assert_eq!(inlined.to_hashed().as_bytes(), hashed.as_bytes());
+        assert_eq!(AsRef::<[u8]>::as_ref(&inlined), inlined.as_bytes());
+
+        let set = vec![inlined.clone()].into_iter().collect::<HashSet<_>>();
+        assert!(set.contains(&hashed));
+
+        let parsed: PeerId = inlined.to_base58().parse().unwrap();
+        assert!(parsed.is_inlined());
+        assert_eq!(parsed, hashed);
+    }
+
+    #[test]
+    fn config_inlines_public_keys_only_when_enabled() {
+        let key = identity::Keypair::generate_ed25519().public();
+        assert!(!PeerIdConfig::new().peer_id(key.clone()).is_inlined());
+        let inlined = PeerIdConfig::new().inline_public_keys(true).peer_id(key.clone());
+        assert!(inlined.is_inlined());
+        assert!(!PeerId::from_public_key(key).is_inlined());
+    }
+
+    #[test]
+    #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+    fn large_keys_are_never_inlined() {
+        let key = identity::Keypair::generate_ecdsa().public();
+        let peer_id = PeerId::from_public_key_inlined(key.clone());
+        assert!(!peer_id.is_inlined());
+        assert_eq!(peer_id.to_public_key(), None);
+        assert_eq!(peer_id.is_public_key(&key), Some(true));
+    }
+
     #[test]
     fn random_peer_id_is_valid() {
         for _ in 0 .. 5000 {
@@ -0,0 +1,229 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+//! Peer records, as defined in the [libp2p routing records RFC].
+//!
+//! A [`PeerRecord`] lists the addresses a peer can be reached at. It is always carried inside a
+//! [`SignedEnvelope`] signed by the peer itself, which lets other nodes forward it without
+//! the addresses being tampered with.
+//!
+//! [libp2p routing records RFC]: https://github.com/libp2p/specs/blob/master/RFC/0003-routing-records.md
+
+use crate::identity::{Keypair, error::{DecodingError, SigningError}};
+use crate::signed_envelope::{ReadPayloadError, SignedEnvelope};
+use crate::{Multiaddr, PeerId, peer_record_proto};
+use prost::Message;
+use std::{convert::TryFrom, error, fmt, time::SystemTime};
+
+/// The domain separation string of peer record envelopes.
+const DOMAIN_SEP: &str = "libp2p-routing-state";
+/// The payload type of peer record envelopes: the `libp2p-peer-record` multicodec.
+const PAYLOAD_TYPE: &[u8] = &[0x03, 0x01];
+
+/// The addresses of a peer, authenticated by a signature of the peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerRecord {
+    peer_id: PeerId,
+    seq: u64,
+    addresses: Vec<Multiaddr>,
+    /// The envelope the record was signed in or decoded from.
+    envelope: SignedEnvelope,
+}
+
+impl PeerRecord {
+    /// Builds and signs a new record of the given addresses of the local peer.
+    ///
+    /// The sequence number is derived from the current time, so that newer records supersede
+    /// older ones.
+    pub fn new(key: &Keypair, addresses: Vec<Multiaddr>) -> Result<Self, SigningError> {
+        let seq = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("now() is never before UNIX_EPOCH")
+            .as_secs();
+        PeerRecord::with_seq(key, seq, addresses)
+    }
+
+    /// Builds and signs a new record of the given addresses of the local peer, with an explicit
+    /// sequence number.
+    pub fn with_seq(key: &Keypair, seq: u64, addresses: Vec<Multiaddr>)
+        -> Result<Self, SigningError>
+    {
+        let peer_id = key.public().into_peer_id();
+
+        let record = peer_record_proto::PeerRecord {
+            peer_id: peer_id.as_bytes().to_vec(),
+            seq,
+            addresses: addresses.iter()
+                .map(|addr| peer_record_proto::peer_record::AddressInfo { multiaddr: addr.to_vec() })
+                .collect(),
+        };
+        let mut payload = Vec::with_capacity(record.encoded_len());
+        record.encode(&mut payload).expect("Vec<u8> provides capacity as needed");
+
+        let envelope = SignedEnvelope::new(key, DOMAIN_SEP, PAYLOAD_TYPE.to_vec(), payload)?;
+
+        Ok(PeerRecord { peer_id, seq, addresses, envelope })
+    }
+
+    /// Extracts a record from a signed envelope, checking the signature and that the record was
+    /// signed by the peer it is about.
+    pub fn from_signed_envelope(envelope: SignedEnvelope) -> Result<Self, FromEnvelopeError> {
+        let payload = envelope.payload(DOMAIN_SEP, PAYLOAD_TYPE)?;
+        let record = peer_record_proto::PeerRecord::decode(payload)
+            .map_err(|e| FromEnvelopeError::InvalidPeerRecord(
+                DecodingError::new("PeerRecord").source(e)))?;
+
+        let peer_id = PeerId::from_bytes(record.peer_id)
+            .map_err(|_| FromEnvelopeError::InvalidPeerRecord(DecodingError::new("PeerId")))?;
+        if peer_id.is_public_key(envelope.key()) != Some(true) {
+            return Err(FromEnvelopeError::MismatchedSignature);
+        }
+
+        let addresses = record.addresses.into_iter()
+            .map(|a| Multiaddr::try_from(a.multiaddr)
+                .map_err(|e| FromEnvelopeError::InvalidPeerRecord(
+                    DecodingError::new("Multiaddr").source(e))))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(PeerRecord { peer_id, seq: record.seq, addresses, envelope })
+    }
+
+    /// Returns the envelope of the record, for sending it to other peers.
+    pub fn to_signed_envelope(&self) -> SignedEnvelope {
+        self.envelope.clone()
+    }
+
+    /// Returns the envelope of the record, for sending it to other peers.
+    pub fn into_signed_envelope(self) -> SignedEnvelope {
+        self.envelope
+    }
+
+    /// Returns the peer the record is about.
+    pub fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    /// Returns the sequence number of the record. Of two records of the same peer, the one
+    /// with the highest sequence number is the most recent.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns the addresses of the peer.
+    pub fn addresses(&self) -> &[Multiaddr] {
+        &self.addresses
+    }
+}
+
+/// An error while extracting a [`PeerRecord`] from a [`SignedEnvelope`].
+#[derive(Debug)]
+pub enum FromEnvelopeError {
+    /// The envelope doesn't hold a validly signed peer record.
+    BadPayload(ReadPayloadError),
+    /// The peer record couldn't be decoded.
+    InvalidPeerRecord(DecodingError),
+    /// The envelope was signed by a different peer than the one of the record.
+    MismatchedSignature,
+}
+
+impl From<ReadPayloadError> for FromEnvelopeError {
+    fn from(e: ReadPayloadError) -> Self {
+        FromEnvelopeError::BadPayload(e)
+    }
+}
+
+impl fmt::Display for FromEnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FromEnvelopeError::BadPayload(e) =>
+                write!(f, "Failed to read peer record envelope: {}", e),
+            FromEnvelopeError::InvalidPeerRecord(e) =>
+                write!(f, "Failed to decode peer record: {}", e),
+            FromEnvelopeError::MismatchedSignature =>
+                write!(f, "Peer record was signed by another peer"),
+        }
+    }
+}
+
+impl error::Error for FromEnvelopeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            FromEnvelopeError::BadPayload(e) => Some(e),
+            FromEnvelopeError::InvalidPeerRecord(e) => Some(e),
+            FromEnvelopeError::MismatchedSignature => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_record_roundtrip() {
+        let key = Keypair::generate_ed25519();
+        let addresses: Vec<Multiaddr> = vec![
+            "/ip4/1.2.3.4/tcp/1234".parse().unwrap(),
+            "/ip6/::1/udp/4321".parse().unwrap(),
+        ];
+        let record = PeerRecord::with_seq(&key, 7, addresses.clone()).unwrap();
+
+        let bytes = record.to_signed_envelope().into_protobuf_encoding();
+        let envelope = SignedEnvelope::from_protobuf_encoding(&bytes).unwrap();
+        let decoded = PeerRecord::from_signed_envelope(envelope).unwrap();
+
+        assert_eq!(decoded, record);
+        assert_eq!(decoded.peer_id(), &key.public().into_peer_id());
+        assert_eq!(decoded.seq(), 7);
+        assert_eq!(decoded.addresses(), &addresses[..]);
+    }
+
+    #[test]
+    fn record_signed_by_other_peer_is_rejected() {
+        let key = Keypair::generate_ed25519();
+        let other = Keypair::generate_ed25519();
+
+        let record = peer_record_proto::PeerRecord {
+            peer_id: key.public().into_peer_id().into_bytes(),
+            seq: 1,
+            addresses: Vec::new(),
+        };
+        let mut payload = Vec::new();
+        record.encode(&mut payload).unwrap();
+        let envelope = SignedEnvelope::new(&other, DOMAIN_SEP, PAYLOAD_TYPE.to_vec(), payload)
+            .unwrap();
+
+        match PeerRecord::from_signed_envelope(envelope) {
+            Err(FromEnvelopeError::MismatchedSignature) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn envelope_of_other_domain_is_rejected() {
+        let key = Keypair::generate_ed25519();
+        let envelope = SignedEnvelope::new(&key, "libp2p-other", PAYLOAD_TYPE.to_vec(), Vec::new())
+            .unwrap();
+
+        match PeerRecord::from_signed_envelope(envelope) {
+            Err(FromEnvelopeError::BadPayload(ReadPayloadError::InvalidSignature)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
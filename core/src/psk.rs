@@ -0,0 +1,201 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Pre-shared keys of private networks.
+//!
+//! A [`PreSharedKey`] is shared by all nodes of a private network, usually as a
+//! `swarm.key` file. It is used by `libp2p-pnet` to encrypt connections and by
+//! the pre-shared key mode of `libp2p-noise`.
+
+use std::{error, fmt, num::ParseIntError, str::FromStr};
+use zeroize::Zeroize;
+
+/// The size of a pre-shared key in bytes.
+pub const KEY_SIZE: usize = 32;
+
+/// The first line of a swarm key file.
+const KEY_CODEC: &str = "/key/swarm/psk/1.0.0/";
+/// The second line of a swarm key file, the only supported key encoding.
+const KEY_ENCODING: &str = "/base16/";
+
+/// A pre-shared key of a private network.
+///
+/// Keys are usually parsed from the content of a swarm key file, which is
+/// also what the `Display` implementation produces:
+///
+/// ```text
+/// /key/swarm/psk/1.0.0/
+/// /base16/
+/// <64 hexadecimal digits>
+/// ```
+///
+/// The key material is zeroed when the key is dropped and never shown
+/// by the `Debug` implementation.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PreSharedKey([u8; KEY_SIZE]);
+
+impl PreSharedKey {
+    /// Creates a new pre-shared key from raw key material.
+    pub fn new(data: [u8; KEY_SIZE]) -> Self {
+        PreSharedKey(data)
+    }
+}
+
+impl AsRef<[u8]> for PreSharedKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for PreSharedKey {
+    fn drop(&mut self) {
+        self.0.zeroize()
+    }
+}
+
+/// Parses a key from the content of a swarm key file.
+impl FromStr for PreSharedKey {
+    type Err = KeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        match lines.next() {
+            Some(KEY_CODEC) => {}
+            _ => return Err(KeyParseError::InvalidKeyFile)
+        }
+        match lines.next() {
+            Some(KEY_ENCODING) => {}
+            Some(_) => return Err(KeyParseError::InvalidKeyEncoding),
+            None => return Err(KeyParseError::InvalidKeyFile)
+        }
+        let key = lines.next().ok_or(KeyParseError::InvalidKeyFile)?.trim();
+        if lines.any(|l| !l.trim().is_empty()) {
+            return Err(KeyParseError::InvalidKeyFile)
+        }
+        if key.len() != KEY_SIZE * 2 || !key.is_ascii() {
+            return Err(KeyParseError::InvalidKeyLength)
+        }
+        let mut data = [0u8; KEY_SIZE];
+        for (i, b) in data.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&key[i * 2 .. i * 2 + 2], 16)?;
+        }
+        Ok(PreSharedKey(data))
+    }
+}
+
+/// Formats the key as the content of a swarm key file.
+impl fmt::Display for PreSharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", KEY_CODEC)?;
+        writeln!(f, "{}", KEY_ENCODING)?;
+        for b in &self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Does not show the key, so that it does not end up in logs.
+impl fmt::Debug for PreSharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PreSharedKey")
+    }
+}
+
+/// Error when parsing a [`PreSharedKey`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyParseError {
+    /// The content is not a swarm key file.
+    InvalidKeyFile,
+    /// The key is not base16 encoded.
+    InvalidKeyEncoding,
+    /// The key does not have the expected length.
+    InvalidKeyLength,
+    /// The key contains a character which is not a hexadecimal digit.
+    InvalidKeyDigit(ParseIntError)
+}
+
+impl fmt::Display for KeyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyParseError::InvalidKeyFile => f.write_str("invalid swarm key file"),
+            KeyParseError::InvalidKeyEncoding => f.write_str("unsupported swarm key encoding"),
+            KeyParseError::InvalidKeyLength => f.write_str("invalid swarm key length"),
+            KeyParseError::InvalidKeyDigit(e) => write!(f, "invalid swarm key digit: {}", e)
+        }
+    }
+}
+
+impl error::Error for KeyParseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            KeyParseError::InvalidKeyDigit(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<ParseIntError> for KeyParseError {
+    fn from(e: ParseIntError) -> Self {
+        KeyParseError::InvalidKeyDigit(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_SPEC: &str = "/key/swarm/psk/1.0.0/\n/base16/\n\
+        6189c5cf0b87fb800c1a9feeda73c6ab5e998db48fb9e6a978575c770ceef683";
+
+    #[test]
+    fn key_roundtrip() {
+        let key = PreSharedKey::new(rand::random());
+        assert_eq!(key.to_string().parse::<PreSharedKey>(), Ok(key));
+        assert_eq!(KEY_SPEC.parse::<PreSharedKey>().unwrap().to_string(), KEY_SPEC);
+    }
+
+    #[test]
+    fn parse_key() {
+        let key = KEY_SPEC.parse::<PreSharedKey>().unwrap();
+        assert_eq!(&key.as_ref()[.. 4], &[0x61, 0x89, 0xc5, 0xcf]);
+        // Trailing empty lines are accepted.
+        assert_eq!(format!("{}\n\n", KEY_SPEC).parse::<PreSharedKey>(), Ok(key.clone()));
+        // The key is not disclosed in debug output.
+        assert!(!format!("{:?}", key).contains("6189c5cf"));
+    }
+
+    #[test]
+    fn invalid_keys() {
+        assert_eq!("".parse::<PreSharedKey>(), Err(KeyParseError::InvalidKeyFile));
+        assert_eq!(
+            KEY_SPEC.replace("/base16/", "/base64/").parse::<PreSharedKey>(),
+            Err(KeyParseError::InvalidKeyEncoding)
+        );
+        assert_eq!(
+            KEY_SPEC[.. KEY_SPEC.len() - 2].parse::<PreSharedKey>(),
+            Err(KeyParseError::InvalidKeyLength)
+        );
+        match KEY_SPEC.replace("6189", "x189").parse::<PreSharedKey>() {
+            Err(KeyParseError::InvalidKeyDigit(_)) => {}
+            other => panic!("unexpected result: {:?}", other)
+        }
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+//! Signed envelopes, as defined in the [libp2p signed envelope RFC].
+//!
+//! A [`SignedEnvelope`] wraps an arbitrary payload together with the public key that signed it.
+//! The signature covers a *domain separation string* in addition to the payload, so that a
+//! signature produced for one purpose can't be reused for another.
+//!
+//! [libp2p signed envelope RFC]: https://github.com/libp2p/specs/blob/master/RFC/0002-signed-envelopes.md
+
+use crate::envelope_proto;
+use crate::identity::{Keypair, PublicKey, error::{DecodingError, SigningError}};
+use prost::Message;
+use std::{error, fmt};
+
+/// A payload signed by a [`Keypair`], along with the corresponding [`PublicKey`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedEnvelope {
+    key: PublicKey,
+    payload_type: Vec<u8>,
+    payload: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl SignedEnvelope {
+    /// Signs `payload` of the given type with `key`, in the given domain.
+    pub fn new(
+        key: &Keypair,
+        domain_separation: &str,
+        payload_type: Vec<u8>,
+        payload: Vec<u8>,
+    ) -> Result<Self, SigningError> {
+        let buffer = signature_payload(domain_separation, &payload_type, &payload);
+        let signature = key.sign(&buffer)?;
+
+        Ok(SignedEnvelope {
+            key: key.public(),
+            payload_type,
+            payload,
+            signature,
+        })
+    }
+
+    /// Checks whether the signature of the envelope is valid for the given domain.
+    pub fn verify(&self, domain_separation: &str) -> bool {
+        let buffer = signature_payload(domain_separation, &self.payload_type, &self.payload);
+        self.key.verify(&buffer, &self.signature)
+    }
+
+    /// Returns the payload of the envelope, after checking the signature for the given domain
+    /// and the payload type.
+    pub fn payload(
+        &self,
+        domain_separation: &str,
+        expected_payload_type: &[u8],
+    ) -> Result<&[u8], ReadPayloadError> {
+        if self.payload_type != expected_payload_type {
+            return Err(ReadPayloadError::UnexpectedPayloadType {
+                expected: expected_payload_type.to_vec(),
+                got: self.payload_type.clone(),
+            });
+        }
+
+        if !self.verify(domain_separation) {
+            return Err(ReadPayloadError::InvalidSignature);
+        }
+
+        Ok(&self.payload)
+    }
+
+    /// Returns the public key that signed the envelope.
+    pub fn key(&self) -> &PublicKey {
+        &self.key
+    }
+
+    /// Returns the type of the payload.
+    pub fn payload_type(&self) -> &[u8] {
+        &self.payload_type
+    }
+
+    /// Encodes the envelope into its protobuf representation.
+    pub fn into_protobuf_encoding(self) -> Vec<u8> {
+        let envelope = envelope_proto::Envelope {
+            public_key: self.key.into_protobuf_encoding(),
+            payload_type: self.payload_type,
+            payload: self.payload,
+            signature: self.signature,
+        };
+
+        let mut buf = Vec::with_capacity(envelope.encoded_len());
+        envelope.encode(&mut buf).expect("Vec<u8> provides capacity as needed");
+        buf
+    }
+
+    /// Decodes an envelope from its protobuf representation.
+    ///
+    /// Note that the signature isn't checked; see [`SignedEnvelope::payload`].
+    pub fn from_protobuf_encoding(bytes: &[u8]) -> Result<Self, DecodingError> {
+        let envelope = envelope_proto::Envelope::decode(bytes)
+            .map_err(|e| DecodingError::new("Envelope").source(e))?;
+
+        Ok(SignedEnvelope {
+            key: PublicKey::from_protobuf_encoding(&envelope.public_key)?,
+            payload_type: envelope.payload_type,
+            payload: envelope.payload,
+            signature: envelope.signature,
+        })
+    }
+}
+
+/// Builds the buffer that is signed: the domain, payload type and payload, each prefixed with
+/// its length as an unsigned varint.
+fn signature_payload(domain_separation: &str, payload_type: &[u8], payload: &[u8]) -> Vec<u8> {
+    length_prefixed(&[domain_separation.as_bytes(), payload_type, payload])
+}
+
+/// Concatenates the given fields, each prefixed with its length as an unsigned varint.
+pub(crate) fn length_prefixed(fields: &[&[u8]]) -> Vec<u8> {
+    let len = fields.iter().map(|f| f.len() + 10).sum();
+    let mut buffer = Vec::with_capacity(len);
+    for field in fields {
+        let mut len = unsigned_varint::encode::usize_buffer();
+        buffer.extend_from_slice(unsigned_varint::encode::usize(field.len(), &mut len));
+        buffer.extend_from_slice(field);
+    }
+    buffer
+}
+
+/// An error while reading the payload of a [`SignedEnvelope`].
+#[derive(Debug)]
+pub enum ReadPayloadError {
+    /// The signature of the envelope is invalid.
+    InvalidSignature,
+    /// The payload of the envelope isn't of the expected type.
+    UnexpectedPayloadType {
+        expected: Vec<u8>,
+        got: Vec<u8>,
+    },
+}
+
+impl fmt::Display for ReadPayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadPayloadError::InvalidSignature =>
+                write!(f, "Invalid envelope signature"),
+            ReadPayloadError::UnexpectedPayloadType { expected, got } =>
+                write!(f, "Unexpected envelope payload type: expected {:?}, got {:?}", expected, got),
+        }
+    }
+}
+
+impl error::Error for ReadPayloadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOMAIN: &str = "libp2p-testing";
+    const PAYLOAD_TYPE: &[u8] = b"/test";
+
+    #[test]
+    fn envelope_roundtrip() {
+        let key = Keypair::generate_ed25519();
+        let envelope = SignedEnvelope::new(&key, DOMAIN, PAYLOAD_TYPE.to_vec(), b"hello".to_vec())
+            .unwrap();
+
+        let decoded = SignedEnvelope::from_protobuf_encoding(&envelope.clone().into_protobuf_encoding())
+            .unwrap();
+        assert_eq!(decoded, envelope);
+        assert_eq!(decoded.key(), &key.public());
+        assert_eq!(decoded.payload(DOMAIN, PAYLOAD_TYPE).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn envelope_domain_and_type_are_checked() {
+        let key = Keypair::generate_ed25519();
+        let envelope = SignedEnvelope::new(&key, DOMAIN, PAYLOAD_TYPE.to_vec(), b"hello".to_vec())
+            .unwrap();
+
+        assert!(!envelope.verify("libp2p-other"));
+        match envelope.payload("libp2p-other", PAYLOAD_TYPE) {
+            Err(ReadPayloadError::InvalidSignature) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match envelope.payload(DOMAIN, b"/other") {
+            Err(ReadPayloadError::UnexpectedPayloadType { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn tampered_envelope_is_rejected() {
+        let key = Keypair::generate_ed25519();
+        let mut envelope = SignedEnvelope::new(&key, DOMAIN, PAYLOAD_TYPE.to_vec(), b"hello".to_vec())
+            .unwrap();
+        envelope.payload = b"world".to_vec();
+        assert!(!envelope.verify(DOMAIN));
+    }
+}
@@ -34,20 +34,64 @@ use multiaddr::{Multiaddr, Protocol};
 /// This is a mixed-mode translation, i.e. an IPv4 / DNS4 address may be replaced by an IPv6 / DNS6
 /// address and vice versa.
 ///
+/// The `observed` address may stem from a different transport than the `original`, e.g. the IP
+/// address observed on a TCP connection applies to a QUIC listen address as well.
+///
 /// If the first [`Protocol`]s are not IP addresses, `None` is returned instead.
+///
+/// Only the first protocol is inspected, so the remainder of `original` (e.g. `/udp/1/quic-v1`,
+/// `/webtransport` or `/certhash` components) is carried over unchanged.
 pub fn address_translation(original: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
     original.replace(0, move |proto| match proto {
-        Protocol::Ip4(_) | Protocol::Ip6(_) | Protocol::Dns4(_) | Protocol::Dns6(_) => match observed.iter().next() {
-            x @ Some(Protocol::Ip4(_)) => x,
-            x @ Some(Protocol::Ip6(_)) => x,
-            x @ Some(Protocol::Dns4(_)) => x,
-            x @ Some(Protocol::Dns6(_)) => x,
-            _ => None,
-        },
+        Protocol::Ip4(_) | Protocol::Ip6(_) | Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_) =>
+            match observed.iter().next() {
+                x @ Some(Protocol::Ip4(_)) => x,
+                x @ Some(Protocol::Ip6(_)) => x,
+                x @ Some(Protocol::Dns(_)) => x,
+                x @ Some(Protocol::Dns4(_)) => x,
+                x @ Some(Protocol::Dns6(_)) => x,
+                _ => None,
+            },
         _ => None,
     })
 }
 
+/// Perform IP address and port translation.
+///
+/// Given an `original` [`Multiaddr`] we listen on and some `observed` [`Multiaddr`] made of the
+/// same protocols, replace the IP address and the port of the `original` with those of the
+/// `observed` [`Multiaddr`] and return this translated [`Multiaddr`].
+///
+/// Contrary to [`address_translation`], this is only meaningful for transports dialing from
+/// their listening port, such as QUIC or TCP with port reuse, behind a NAT that maps this port
+/// to the same external port regardless of the remote.
+///
+/// The addresses must be of the same IP version and use the same transport protocol after the IP
+/// address. The remaining protocols of `observed`, ignoring a trailing `/p2p`, must be a prefix
+/// of those of `original`. Otherwise `None` is returned.
+pub fn port_translation(original: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+    let mut observed = observed.iter().collect::<Vec<_>>();
+    if let Some(Protocol::P2p(_)) = observed.last() {
+        observed.pop();
+    }
+    let mut original = original.iter();
+    let mut prefix = observed.iter();
+    match (original.next()?, prefix.next()?) {
+        (Protocol::Ip4(_), Protocol::Ip4(_)) | (Protocol::Ip6(_), Protocol::Ip6(_)) => {}
+        _ => return None
+    }
+    match (original.next()?, prefix.next()?) {
+        (Protocol::Tcp(_), Protocol::Tcp(_)) | (Protocol::Udp(_), Protocol::Udp(_)) => {}
+        _ => return None
+    }
+    for p in prefix {
+        if original.next().as_ref() != Some(p) {
+            return None
+        }
+    }
+    Some(observed.into_iter().chain(original).collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -97,6 +141,18 @@ mod tests {
                 observed: "/dns4/bar/tcp/2".parse().unwrap(),
                 expected: "/dns4/bar/tcp/1".parse().unwrap(),
             },
+            // QUIC v1 with WebTransport and certificate hashes.
+            Test {
+                original: "/ip4/192.0.2.1/udp/1/quic-v1/webtransport/certhash/uEiDVLruJ2FsCooSUggOmL_KDicV8n0K-7E7CDbdqaJEcCw".parse().unwrap(),
+                observed: "/ip6/2001:db8:0:0:0:0:0:1/udp/2/quic-v1".parse().unwrap(),
+                expected: "/ip6/2001:db8:0:0:0:0:0:1/udp/1/quic-v1/webtransport/certhash/uEiDVLruJ2FsCooSUggOmL_KDicV8n0K-7E7CDbdqaJEcCw".parse().unwrap(),
+            },
+            // TLS over TCP.
+            Test {
+                original: "/ip4/192.0.2.1/tcp/1/tls/ws".parse().unwrap(),
+                observed: "/ip4/192.0.2.2/tcp/2".parse().unwrap(),
+                expected: "/ip4/192.0.2.2/tcp/1/tls/ws".parse().unwrap(),
+            },
         ];
 
         for test in tests.iter() {
@@ -106,4 +162,35 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_port_translation() {
+        let translate = |original: &str, observed: &str| {
+            port_translation(&original.parse().unwrap(), &observed.parse().unwrap())
+                .map(|a| a.to_string())
+        };
+
+        assert_eq!(
+            translate("/ip4/192.0.2.1/udp/1/quic", "/ip4/192.0.2.2/udp/2/quic"),
+            Some("/ip4/192.0.2.2/udp/2/quic".to_string())
+        );
+        assert_eq!(
+            translate("/ip4/192.0.2.1/tcp/1/ws", "/ip4/192.0.2.2/tcp/2"),
+            Some("/ip4/192.0.2.2/tcp/2/ws".to_string())
+        );
+        assert_eq!(
+            translate(
+                "/ip4/192.0.2.1/udp/1/quic-v1/webtransport",
+                "/ip4/192.0.2.2/udp/2/quic-v1/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"
+            ),
+            Some("/ip4/192.0.2.2/udp/2/quic-v1/webtransport".to_string())
+        );
+        // Different transports.
+        assert_eq!(translate("/ip4/192.0.2.1/udp/1/quic", "/ip4/192.0.2.2/tcp/2"), None);
+        assert_eq!(translate("/ip4/192.0.2.1/udp/1/quic", "/ip4/192.0.2.2/udp/2/quic-v1"), None);
+        assert_eq!(translate("/ip4/192.0.2.1/tcp/1", "/ip4/192.0.2.2/tcp/2/ws"), None);
+        // Different IP versions.
+        assert_eq!(translate("/ip4/192.0.2.1/tcp/1", "/ip6/2001:db8::1/tcp/2"), None);
+        assert_eq!(translate("/dns4/foo/tcp/1", "/ip4/192.0.2.2/tcp/2"), None);
+    }
 }
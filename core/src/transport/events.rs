@@ -0,0 +1,245 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Structured events published by transports.
+//!
+//! Errors of composed transports are deeply nested, e.g. a TLS failure of a
+//! websocket dialed through a proxy, and their `Display` output is meant for
+//! humans. Transports can instead publish [`TransportEvent`]s to a shared
+//! [`TransportEvents`] bus, which applications subscribe to in order to
+//! observe why low-level dials fail.
+//!
+//! [`Transport::observe`] publishes the start and outcome of the dials of any
+//! transport. Transports may additionally publish failures specific to them,
+//! like the `libp2p-websocket` transport does for TLS handshakes.
+
+use crate::{Multiaddr, Transport, transport::TransportError};
+use futures::{prelude::*, channel::mpsc, ready};
+use parking_lot::Mutex;
+use std::{fmt, pin::Pin, sync::Arc, task::Context, task::Poll};
+
+/// A bus of [`TransportEvent`]s.
+///
+/// Cloning a `TransportEvents` returns a handle to the same bus, so that one
+/// bus can be shared by all transports of a composition.
+#[derive(Clone, Default)]
+pub struct TransportEvents {
+    subscribers: Arc<Mutex<Vec<mpsc::UnboundedSender<TransportEvent>>>>,
+}
+
+impl TransportEvents {
+    /// Creates a bus without subscribers.
+    pub fn new() -> Self {
+        TransportEvents::default()
+    }
+
+    /// Returns a stream of the events published from now on.
+    ///
+    /// Events are buffered until they are consumed, hence the stream should
+    /// be polled continuously, or dropped to unsubscribe.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<TransportEvent> {
+        let (tx, rx) = mpsc::unbounded();
+        self.subscribers.lock().push(tx);
+        rx
+    }
+
+    /// Publishes an event to all subscribers.
+    pub fn publish(&self, event: TransportEvent) {
+        self.subscribers.lock().retain(|tx| tx.unbounded_send(event.clone()).is_ok());
+    }
+}
+
+impl fmt::Debug for TransportEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransportEvents")
+            .field("subscribers", &self.subscribers.lock().len())
+            .finish()
+    }
+}
+
+/// An event published by a transport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportEvent {
+    /// The name of the publishing transport, e.g. `"tcp"`.
+    pub transport: &'static str,
+    /// The address being dialed.
+    pub address: Multiaddr,
+    /// What happened.
+    pub kind: TransportEventKind,
+}
+
+/// The kind of a [`TransportEvent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportEventKind {
+    /// A dial has started.
+    DialStarted,
+    /// A dial has succeeded.
+    DialSucceeded,
+    /// A dial has failed.
+    DialFailed {
+        /// The error of the publishing transport.
+        error: String,
+    },
+    /// The TLS handshake of a dial has failed.
+    TlsFailed {
+        /// The error of the TLS handshake.
+        error: String,
+    },
+    /// The proxy of a dial could not be reached or failed to connect to the
+    /// address.
+    ProxyFailed {
+        /// The error of the proxy connection.
+        error: String,
+    },
+}
+
+/// See [`Transport::observe`].
+#[derive(Debug, Clone)]
+pub struct Observed<T> {
+    inner: T,
+    name: &'static str,
+    events: TransportEvents,
+}
+
+impl<T> Observed<T> {
+    /// Internal function that builds an `Observed`.
+    pub(crate) fn new(inner: T, name: &'static str, events: TransportEvents) -> Self {
+        Observed { inner, name, events }
+    }
+}
+
+impl<T> Transport for Observed<T>
+where
+    T: Transport,
+{
+    type Output = T::Output;
+    type Error = T::Error;
+    type Listener = T::Listener;
+    type ListenerUpgrade = T::ListenerUpgrade;
+    type Dial = ObservedDial<T::Dial>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        self.inner.listen_on(addr)
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let publisher = Publisher { name: self.name, address: addr.clone(), events: self.events };
+        match self.inner.dial(addr) {
+            Ok(inner) => {
+                publisher.publish(TransportEventKind::DialStarted);
+                Ok(ObservedDial { inner, publisher: Some(publisher) })
+            }
+            Err(TransportError::Other(err)) => {
+                publisher.publish(TransportEventKind::DialFailed { error: err.to_string() });
+                Err(TransportError::Other(err))
+            }
+            Err(err) => Err(err)
+        }
+    }
+}
+
+/// Publishes the events of a dial.
+struct Publisher {
+    name: &'static str,
+    address: Multiaddr,
+    events: TransportEvents,
+}
+
+impl Publisher {
+    fn publish(&self, kind: TransportEventKind) {
+        let event = TransportEvent { transport: self.name, address: self.address.clone(), kind };
+        self.events.publish(event);
+    }
+}
+
+/// Dialing future for [`Observed`].
+#[pin_project::pin_project]
+pub struct ObservedDial<F> {
+    #[pin]
+    inner: F,
+    publisher: Option<Publisher>,
+}
+
+impl<F, O, E> Future for ObservedDial<F>
+where
+    F: TryFuture<Ok = O, Error = E>,
+    E: fmt::Display,
+{
+    type Output = Result<O, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(TryFuture::try_poll(this.inner, cx));
+        let publisher = this.publisher.take().expect("ObservedDial polled after completion");
+        match &result {
+            Ok(_) => publisher.publish(TransportEventKind::DialSucceeded),
+            Err(err) => publisher.publish(TransportEventKind::DialFailed { error: err.to_string() }),
+        }
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MemoryTransport;
+    use multiaddr::Protocol;
+    use rand::random;
+
+    fn event(address: &Multiaddr, kind: TransportEventKind) -> TransportEvent {
+        TransportEvent { transport: "memory", address: address.clone(), kind }
+    }
+
+    #[test]
+    fn publishes_dial_outcomes() {
+        let events = TransportEvents::new();
+        let mut subscriber = events.subscribe();
+        let transport = MemoryTransport::default().observe("memory", events.clone());
+
+        let addr = Multiaddr::from(Protocol::Memory(random::<u64>()));
+        let _listener = transport.clone().listen_on(addr.clone()).unwrap();
+        let unreachable = Multiaddr::from(Protocol::Memory(random::<u64>()));
+
+        futures::executor::block_on(async move {
+            transport.clone().dial(addr.clone()).unwrap().await.unwrap();
+            assert!(transport.dial(unreachable.clone()).is_err());
+
+            assert_eq!(subscriber.next().await, Some(event(&addr, TransportEventKind::DialStarted)));
+            assert_eq!(subscriber.next().await, Some(event(&addr, TransportEventKind::DialSucceeded)));
+            match subscriber.next().await {
+                Some(TransportEvent { address, kind: TransportEventKind::DialFailed { .. }, .. }) =>
+                    assert_eq!(address, unreachable),
+                e => panic!("Unexpected event: {:?}", e)
+            }
+        });
+    }
+
+    #[test]
+    fn dropped_subscribers_are_removed() {
+        let events = TransportEvents::new();
+        let subscriber = events.subscribe();
+        let _other = events.subscribe();
+        drop(subscriber);
+
+        let addr = Multiaddr::from(Protocol::Memory(random::<u64>()));
+        events.publish(event(&addr, TransportEventKind::DialStarted));
+        assert_eq!(events.subscribers.lock().len(), 1);
+    }
+}
@@ -0,0 +1,709 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Simulation of network conditions between in-memory endpoints.
+//!
+//! A [`Network`] is an isolated set of endpoints, each identified by a
+//! non-zero number `n` and reachable at `/memory/n` through the
+//! [`SimulatedTransport`] returned by [`Network::transport`]. Connections
+//! between two endpoints are subject to the [`Link`] configured between
+//! them, and endpoints can be partitioned from each other at any time.
+//!
+//! Random decisions, i.e. jitter and loss, are taken from a random number
+//! generator seeded through [`Network::with_seed`], so that a test behaves
+//! the same way from one run to the next.
+//!
+//! ```
+//! use libp2p_core::transport::{Transport, memory::simulation::{Link, Network}};
+//! use std::time::Duration;
+//!
+//! let network = Network::with_seed(42);
+//! network.set_link(1, 2, Link::new().latency(Duration::from_millis(50)));
+//! let listener = network.transport(1).listen_on("/memory/1".parse().unwrap()).unwrap();
+//! let dial = network.transport(2).dial("/memory/1".parse().unwrap()).unwrap();
+//! network.partition(1, 2);
+//! ```
+
+use crate::{Transport, transport::{TransportError, ListenerEvent}};
+use super::MemoryTransportError;
+use fnv::FnvHashMap;
+use futures::{future::{self, BoxFuture, Ready}, prelude::*, channel::mpsc, task::AtomicWaker};
+use futures_timer::Delay;
+use multiaddr::{Protocol, Multiaddr};
+use parking_lot::Mutex;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{
+    cmp, fmt, io,
+    num::NonZeroU64,
+    pin::Pin,
+    sync::{Arc, Weak, atomic::{AtomicBool, Ordering}},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// Conditions applying to the traffic between two endpoints.
+#[derive(Debug, Copy, Clone)]
+pub struct Link {
+    latency: Duration,
+    jitter: Duration,
+    bandwidth: Option<u64>,
+    loss: f64,
+    retransmission_timeout: Duration,
+}
+
+impl Link {
+    /// Creates a link without latency, loss or bandwidth limit.
+    pub fn new() -> Self {
+        Link {
+            latency: Duration::from_secs(0),
+            jitter: Duration::from_secs(0),
+            bandwidth: None,
+            loss: 0.0,
+            retransmission_timeout: Duration::from_millis(200),
+        }
+    }
+
+    /// Sets the one-way delay of the data sent over the link.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Sets the maximum random delay added to the latency.
+    ///
+    /// Data sent over a connection is never reordered by the jitter.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Limits the number of bytes per second sent in each direction of the
+    /// link, shared by all connections between the two endpoints.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_sec` is 0.
+    pub fn bandwidth(mut self, bytes_per_sec: u64) -> Self {
+        assert!(bytes_per_sec > 0, "bandwidth must be positive");
+        self.bandwidth = Some(bytes_per_sec);
+        self
+    }
+
+    /// Sets the probability for each write to be lost.
+    ///
+    /// Connections are reliable streams, hence lost data is not dropped but
+    /// delivered after an additional retransmission timeout, once for every
+    /// time it is lost.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `loss` is not within `[0, 1)`.
+    pub fn loss(mut self, loss: f64) -> Self {
+        assert!((0.0 .. 1.0).contains(&loss), "loss must be within [0, 1)");
+        self.loss = loss;
+        self
+    }
+
+    /// Sets the delay added to the delivery of lost data.
+    pub fn retransmission_timeout(mut self, timeout: Duration) -> Self {
+        self.retransmission_timeout = timeout;
+        self
+    }
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Link::new()
+    }
+}
+
+/// An isolated set of simulated endpoints.
+///
+/// Cloning a `Network` returns a handle to the same set of endpoints.
+#[derive(Clone)]
+pub struct Network {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    /// The link between endpoints without a specific configuration.
+    default_link: Link,
+    /// The links between pairs of endpoints, keyed by the smallest
+    /// identifier first.
+    links: FnvHashMap<(u64, u64), Link>,
+    /// The pairs of endpoints that cannot reach each other, keyed by the
+    /// smallest identifier first.
+    partitions: FnvHashMap<(u64, u64), ()>,
+    /// The time at which each direction of a link is done sending the data
+    /// queued so far, keyed by the sending endpoint first.
+    busy_until: FnvHashMap<(u64, u64), Instant>,
+    /// The listening endpoints.
+    listeners: FnvHashMap<u64, mpsc::Sender<SimulatedConnection>>,
+    /// The established connections, used to reset them on partitions.
+    connections: Vec<(u64, u64, Weak<Shared>)>,
+    rng: StdRng,
+}
+
+impl Network {
+    /// Creates a network seeded with a random number.
+    pub fn new() -> Self {
+        Network::with_seed(rand::random())
+    }
+
+    /// Creates a network whose random decisions derive from `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        Network {
+            inner: Arc::new(Mutex::new(Inner {
+                default_link: Link::new(),
+                links: FnvHashMap::default(),
+                partitions: FnvHashMap::default(),
+                busy_until: FnvHashMap::default(),
+                listeners: FnvHashMap::default(),
+                connections: Vec::new(),
+                rng: StdRng::seed_from_u64(seed),
+            }))
+        }
+    }
+
+    /// Returns the transport of endpoint `node`, which listens on
+    /// `/memory/<node>`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is 0.
+    pub fn transport(&self, node: u64) -> SimulatedTransport {
+        SimulatedTransport {
+            network: self.clone(),
+            node: NonZeroU64::new(node).expect("node must not be 0"),
+        }
+    }
+
+    /// Sets the link used between endpoints without a specific link.
+    pub fn set_default_link(&self, link: Link) {
+        self.inner.lock().default_link = link
+    }
+
+    /// Sets the link between endpoints `a` and `b`, in both directions.
+    ///
+    /// The new conditions apply to the data sent afterwards, including over
+    /// established connections.
+    pub fn set_link(&self, a: u64, b: u64, link: Link) {
+        self.inner.lock().links.insert(pair(a, b), link);
+    }
+
+    /// Restores the default link between endpoints `a` and `b`.
+    pub fn remove_link(&self, a: u64, b: u64) {
+        self.inner.lock().links.remove(&pair(a, b));
+    }
+
+    /// Prevents endpoints `a` and `b` from reaching each other.
+    ///
+    /// Dials between them fail and the connections between them are reset,
+    /// dropping the data in flight.
+    pub fn partition(&self, a: u64, b: u64) {
+        let mut inner = self.inner.lock();
+        inner.partitions.insert(pair(a, b), ());
+        inner.connections.retain(|(x, y, shared)| {
+            let shared = match shared.upgrade() {
+                Some(shared) => shared,
+                None => return false
+            };
+            if pair(*x, *y) == pair(a, b) {
+                shared.reset();
+                return false
+            }
+            true
+        });
+    }
+
+    /// Partitions every endpoint of `group` from every endpoint of `other`.
+    pub fn partition_groups(&self, group: &[u64], other: &[u64]) {
+        for a in group {
+            for b in other {
+                self.partition(*a, *b)
+            }
+        }
+    }
+
+    /// Allows endpoints `a` and `b` to reach each other again.
+    pub fn heal(&self, a: u64, b: u64) {
+        self.inner.lock().partitions.remove(&pair(a, b));
+    }
+
+    /// Removes all partitions.
+    pub fn heal_all(&self) {
+        self.inner.lock().partitions.clear()
+    }
+
+    /// Returns true if endpoints `a` and `b` are partitioned from each other.
+    pub fn is_partitioned(&self, a: u64, b: u64) -> bool {
+        self.inner.lock().partitions.contains_key(&pair(a, b))
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network::new()
+    }
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock();
+        f.debug_struct("Network")
+            .field("default_link", &inner.default_link)
+            .field("links", &inner.links)
+            .field("partitions", &inner.partitions.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Inner {
+    fn link(&self, a: u64, b: u64) -> Link {
+        self.links.get(&pair(a, b)).copied().unwrap_or(self.default_link)
+    }
+
+    /// Returns the one-way delay of a message between `from` and `to`,
+    /// excluding the bandwidth limit.
+    fn delay(&mut self, from: u64, to: u64) -> Duration {
+        let link = self.link(from, to);
+        let mut delay = link.latency;
+        if link.jitter > Duration::from_secs(0) {
+            delay += link.jitter.mul_f64(self.rng.gen::<f64>());
+        }
+        while link.loss > 0.0 && self.rng.gen::<f64>() < link.loss {
+            delay += link.retransmission_timeout;
+        }
+        delay
+    }
+
+    /// Returns the time at which `len` bytes sent now from `from` to `to`
+    /// are delivered.
+    fn schedule(&mut self, from: u64, to: u64, len: usize) -> Instant {
+        let now = Instant::now();
+        let sent = match self.link(from, to).bandwidth {
+            Some(bandwidth) => {
+                let start = self.busy_until.get(&(from, to)).map_or(now, |t| cmp::max(*t, now));
+                let end = start + Duration::from_secs_f64(len as f64 / bandwidth as f64);
+                self.busy_until.insert((from, to), end);
+                end
+            }
+            None => now
+        };
+        sent + self.delay(from, to)
+    }
+}
+
+/// Orders the identifiers of two endpoints.
+fn pair(a: u64, b: u64) -> (u64, u64) {
+    (cmp::min(a, b), cmp::max(a, b))
+}
+
+/// Transport of an endpoint of a [`Network`].
+///
+/// Supports `/memory/N` addresses, where `N` is the identifier of an
+/// endpoint. Listening is only possible on the endpoint's own address or on
+/// `/memory/0`.
+#[derive(Debug, Clone)]
+pub struct SimulatedTransport {
+    network: Network,
+    node: NonZeroU64,
+}
+
+impl SimulatedTransport {
+    /// Returns the identifier of the endpoint.
+    pub fn node(&self) -> u64 {
+        self.node.get()
+    }
+}
+
+impl Transport for SimulatedTransport {
+    type Output = SimulatedConnection;
+    type Error = MemoryTransportError;
+    type Listener = SimulatedListener;
+    type ListenerUpgrade = Ready<Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        match super::parse_memory_addr(&addr) {
+            Ok(port) if port == 0 || port == self.node.get() => {}
+            _ => return Err(TransportError::MultiaddrNotSupported(addr))
+        }
+
+        let mut inner = self.network.inner.lock();
+        if inner.listeners.contains_key(&self.node.get()) {
+            return Err(TransportError::Other(MemoryTransportError::AlreadyInUse))
+        }
+        let (tx, rx) = mpsc::channel(2);
+        inner.listeners.insert(self.node.get(), tx);
+        drop(inner);
+
+        Ok(SimulatedListener {
+            network: self.network,
+            node: self.node.get(),
+            receiver: rx,
+            tell_listen_addr: true,
+        })
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let remote = match super::parse_memory_addr(&addr) {
+            Ok(port) => port,
+            Err(()) => return Err(TransportError::MultiaddrNotSupported(addr))
+        };
+        let local = self.node.get();
+        let network = self.network;
+
+        let delay = {
+            let mut inner = network.inner.lock();
+            if !inner.listeners.contains_key(&remote) || inner.partitions.contains_key(&pair(local, remote)) {
+                return Err(TransportError::Other(MemoryTransportError::Unreachable))
+            }
+            inner.delay(local, remote)
+        };
+
+        Ok(async move {
+            // The connection is established once the dial reached the listener.
+            Delay::new(delay).await;
+
+            let mut sender = {
+                let inner = network.inner.lock();
+                if inner.partitions.contains_key(&pair(local, remote)) {
+                    return Err(MemoryTransportError::Unreachable)
+                }
+                match inner.listeners.get(&remote) {
+                    Some(sender) => sender.clone(),
+                    None => return Err(MemoryTransportError::Unreachable)
+                }
+            };
+
+            let (dialer, listener) = SimulatedConnection::pair(&network, local, remote);
+            sender.send(listener).await.map_err(|_| MemoryTransportError::Unreachable)?;
+            Ok(dialer)
+        }.boxed())
+    }
+}
+
+/// Listener of a [`SimulatedTransport`].
+pub struct SimulatedListener {
+    network: Network,
+    node: u64,
+    receiver: mpsc::Receiver<SimulatedConnection>,
+    tell_listen_addr: bool,
+}
+
+impl fmt::Debug for SimulatedListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimulatedListener").field("node", &self.node).finish()
+    }
+}
+
+impl Stream for SimulatedListener {
+    type Item = Result<ListenerEvent<Ready<Result<SimulatedConnection, MemoryTransportError>>>, MemoryTransportError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let addr = Multiaddr::from(Protocol::Memory(self.node));
+        if self.tell_listen_addr {
+            self.tell_listen_addr = false;
+            return Poll::Ready(Some(Ok(ListenerEvent::NewAddress(addr))))
+        }
+
+        let connection = match self.receiver.poll_next_unpin(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(None) => panic!("Alive listeners always have a sender."),
+            Poll::Ready(Some(c)) => c,
+        };
+
+        let event = ListenerEvent::Upgrade {
+            remote_addr: Protocol::Memory(connection.remote).into(),
+            upgrade: future::ready(Ok(connection)),
+            local_addr: addr,
+        };
+
+        Poll::Ready(Some(Ok(event)))
+    }
+}
+
+impl Drop for SimulatedListener {
+    fn drop(&mut self) {
+        self.network.inner.lock().listeners.remove(&self.node);
+    }
+}
+
+/// State shared by both ends of a connection.
+#[derive(Debug)]
+struct Shared {
+    reset: AtomicBool,
+    wakers: [AtomicWaker; 4],
+}
+
+impl Shared {
+    fn reset(&self) {
+        self.reset.store(true, Ordering::SeqCst);
+        for waker in &self.wakers {
+            waker.wake()
+        }
+    }
+
+    fn is_reset(&self) -> bool {
+        self.reset.load(Ordering::SeqCst)
+    }
+}
+
+/// Connection between two endpoints of a [`Network`].
+///
+/// Implements `AsyncRead` and `AsyncWrite`.
+pub struct SimulatedConnection {
+    network: Network,
+    local: u64,
+    remote: u64,
+    shared: Arc<Shared>,
+    /// The index of this end of the connection, 0 or 1.
+    side: usize,
+    outgoing: mpsc::Sender<(Instant, Vec<u8>)>,
+    incoming: mpsc::Receiver<(Instant, Vec<u8>)>,
+    /// The delivery time of the data last sent, preventing reordering.
+    last_delivery: Option<Instant>,
+    /// The data received but not yet delivered or read.
+    pending: Option<(Instant, Vec<u8>, usize)>,
+    timer: Option<Delay>,
+}
+
+impl SimulatedConnection {
+    fn pair(network: &Network, a: u64, b: u64) -> (Self, Self) {
+        let shared = Arc::new(Shared {
+            reset: AtomicBool::new(false),
+            wakers: Default::default(),
+        });
+        network.inner.lock().connections.push((a, b, Arc::downgrade(&shared)));
+        let (a_tx, a_rx) = mpsc::channel(4096);
+        let (b_tx, b_rx) = mpsc::channel(4096);
+        let end = |local, remote, side, outgoing, incoming| SimulatedConnection {
+            network: network.clone(),
+            local,
+            remote,
+            shared: shared.clone(),
+            side,
+            outgoing,
+            incoming,
+            last_delivery: None,
+            pending: None,
+            timer: None,
+        };
+        (end(a, b, 0, a_tx, b_rx), end(b, a, 1, b_tx, a_rx))
+    }
+
+    /// Returns the identifier of the local endpoint.
+    pub fn local_node(&self) -> u64 {
+        self.local
+    }
+
+    /// Returns the identifier of the remote endpoint.
+    pub fn remote_node(&self) -> u64 {
+        self.remote
+    }
+
+    fn read_waker(&self) -> &AtomicWaker {
+        &self.shared.wakers[self.side * 2]
+    }
+
+    fn write_waker(&self) -> &AtomicWaker {
+        &self.shared.wakers[self.side * 2 + 1]
+    }
+}
+
+impl fmt::Debug for SimulatedConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimulatedConnection")
+            .field("local", &self.local)
+            .field("remote", &self.remote)
+            .finish()
+    }
+}
+
+impl AsyncRead for SimulatedConnection {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        this.read_waker().register(cx.waker());
+        loop {
+            if this.shared.is_reset() {
+                return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+            }
+
+            let (at, data, offset) = match &mut this.pending {
+                Some(pending) => pending,
+                None => match this.incoming.poll_next_unpin(cx) {
+                    Poll::Ready(Some((at, data))) => this.pending.get_or_insert((at, data, 0)),
+                    Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                    Poll::Pending => return Poll::Pending,
+                }
+            };
+
+            let now = Instant::now();
+            if *at > now {
+                let timer = this.timer.get_or_insert_with(|| Delay::new(*at - now));
+                match timer.poll_unpin(cx) {
+                    Poll::Ready(()) => this.timer = None,
+                    Poll::Pending => return Poll::Pending,
+                }
+                continue
+            }
+
+            let len = cmp::min(buf.len(), data.len() - *offset);
+            buf[.. len].copy_from_slice(&data[*offset .. *offset + len]);
+            *offset += len;
+            if *offset == data.len() {
+                this.pending = None
+            }
+            return Poll::Ready(Ok(len))
+        }
+    }
+}
+
+impl AsyncWrite for SimulatedConnection {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        this.write_waker().register(cx.waker());
+        if this.shared.is_reset() {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+        }
+        match this.outgoing.poll_ready(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(_)) => return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        let at = this.network.inner.lock().schedule(this.local, this.remote, buf.len());
+        let at = this.last_delivery.map_or(at, |last| cmp::max(last, at));
+        this.last_delivery = Some(at);
+        this.outgoing.start_send((at, buf.to_vec()))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        if self.shared.is_reset() {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        self.outgoing.close_channel();
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect(network: &Network, a: u64, b: u64) -> (SimulatedConnection, SimulatedConnection) {
+        let listener = network.transport(b).listen_on(format!("/memory/{}", b).parse().unwrap()).unwrap();
+        let dial = network.transport(a).dial(format!("/memory/{}", b).parse().unwrap()).unwrap();
+        futures::executor::block_on(async move {
+            let accept = listener
+                .filter_map(|ev| future::ready(ev.unwrap().into_upgrade()))
+                .into_future()
+                .then(|(upgrade, listener)| async move {
+                    // Keep the listener alive until the dial completes.
+                    let connection = upgrade.unwrap().0.await.unwrap();
+                    drop(listener);
+                    connection
+                });
+            let (dialer, listener) = future::join(dial, accept).await;
+            (dialer.unwrap(), listener)
+        })
+    }
+
+    #[test]
+    fn latency_delays_delivery() {
+        let network = Network::with_seed(1);
+        network.set_link(1, 2, Link::new().latency(Duration::from_millis(100)));
+        let (mut a, mut b) = connect(&network, 1, 2);
+        assert_eq!((a.local_node(), a.remote_node()), (1, 2));
+        assert_eq!((b.local_node(), b.remote_node()), (2, 1));
+
+        futures::executor::block_on(async move {
+            let start = Instant::now();
+            a.write_all(b"ping").await.unwrap();
+            let mut buf = [0; 4];
+            b.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"ping");
+            assert!(start.elapsed() >= Duration::from_millis(100));
+        })
+    }
+
+    #[test]
+    fn bandwidth_and_loss_preserve_order() {
+        let network = Network::with_seed(2);
+        network.set_link(1, 2, Link::new()
+            .jitter(Duration::from_millis(10))
+            .bandwidth(100_000)
+            .loss(0.3)
+            .retransmission_timeout(Duration::from_millis(5)));
+        let (mut a, mut b) = connect(&network, 1, 2);
+
+        futures::executor::block_on(async move {
+            let start = Instant::now();
+            for i in 0 .. 10u8 {
+                a.write_all(&[i; 1000]).await.unwrap();
+            }
+            a.close().await.unwrap();
+            let mut received = Vec::new();
+            b.read_to_end(&mut received).await.unwrap();
+            let expected = (0 .. 10u8).flat_map(|i| vec![i; 1000]).collect::<Vec<_>>();
+            assert_eq!(received, expected);
+            // 10 kB at 100 kB/s.
+            assert!(start.elapsed() >= Duration::from_millis(100));
+        })
+    }
+
+    #[test]
+    fn partitions_refuse_dials_and_reset_connections() {
+        let network = Network::with_seed(3);
+        let (mut a, mut b) = connect(&network, 1, 2);
+        let _listener = network.transport(3).listen_on("/memory/3".parse().unwrap()).unwrap();
+
+        network.partition_groups(&[1], &[2, 3]);
+        assert!(network.is_partitioned(3, 1));
+        assert!(network.transport(1).dial("/memory/3".parse().unwrap()).is_err());
+        futures::executor::block_on(async {
+            let mut buf = [0; 1];
+            assert_eq!(b.read(&mut buf).await.unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+            assert_eq!(a.write(b"x").await.unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+        });
+
+        network.heal_all();
+        assert!(!network.is_partitioned(1, 3));
+        assert!(network.transport(1).dial("/memory/3".parse().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn listen_address_is_the_node() {
+        let network = Network::new();
+        let transport = network.transport(5);
+        assert!(transport.clone().listen_on("/memory/6".parse().unwrap()).is_err());
+        let mut listener = transport.clone().listen_on("/memory/0".parse().unwrap()).unwrap();
+        assert!(transport.listen_on("/memory/5".parse().unwrap()).is_err());
+        let event = futures::executor::block_on(listener.next()).unwrap().unwrap();
+        assert_eq!(event.into_new_address(), Some("/memory/5".parse().unwrap()));
+    }
+}
@@ -35,17 +35,21 @@ pub mod and_then;
 pub mod boxed;
 pub mod choice;
 pub mod dummy;
+pub mod events;
 pub mod map;
 pub mod map_err;
 pub mod memory;
+pub mod registry;
 pub mod timeout;
 pub mod upgrade;
 
 mod optional;
 
 pub use self::choice::OrTransport;
+pub use self::events::{TransportEvent, TransportEventKind, TransportEvents};
 pub use self::memory::MemoryTransport;
 pub use self::optional::OptionalTransport;
+pub use self::registry::TransportRegistry;
 pub use self::upgrade::Upgrade;
 
 /// A transport provides connection-oriented communication between two peers
@@ -80,6 +84,13 @@ pub trait Transport {
     /// Typically the output contains at least a handle to a data stream (i.e. a
     /// connection or a substream multiplexer on top of a connection) that
     /// provides APIs for sending and receiving data through the connection.
+    ///
+    /// Transports whose protocol already provides authentication and multiplexing,
+    /// e.g. QUIC, can directly output a `(PeerId, StreamMuxer)` tuple, like the one
+    /// obtained by [upgrading](Transport::upgrade) a transport, instead of a raw
+    /// connection. Such a transport can be combined with an upgraded one through
+    /// [`or_transport`](Transport::or_transport) and
+    /// [`EitherOutput::factor_first`](crate::either::EitherOutput::factor_first).
     type Output;
 
     /// An error that occurred during connection setup.
@@ -208,6 +219,15 @@ pub trait Transport {
         timeout::TransportTimeout::with_ingoing_timeout(self, timeout)
     }
 
+    /// Publishes the start and outcome of the dials of the transport to `events`,
+    /// under the given transport name.
+    fn observe(self, name: &'static str, events: TransportEvents) -> events::Observed<Self>
+    where
+        Self: Sized
+    {
+        events::Observed::new(self, name, events)
+    }
+
     /// Begins a series of protocol upgrades via an [`upgrade::Builder`].
     fn upgrade(self, version: upgrade::Version) -> upgrade::Builder<Self>
     where
@@ -224,6 +244,11 @@ pub trait Transport {
 /// listen addresses which have previously been announced via
 /// a `NewAddress` event and which have not been invalidated by
 /// an `AddressExpired` event yet.
+///
+/// `NewAddress` and `AddressExpired` events may be produced at any
+/// time during the lifetime of a listener, e.g. when a network
+/// interface goes up or down or a DHCP lease is renewed with a
+/// different address.
 #[derive(Clone, Debug, PartialEq)]
 pub enum ListenerEvent<T> {
     /// The transport is listening on a new additional [`Multiaddr`].
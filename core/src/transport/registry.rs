@@ -0,0 +1,269 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A set of transports that can be changed at runtime.
+//!
+//! Contrary to an [`OrTransport`](crate::transport::OrTransport) chain, whose
+//! transports are fixed at compile time, a [`TransportRegistry`] holds boxed
+//! transports that can be registered and unregistered while it is in use, e.g.
+//! by the `Swarm`. This lets plugins contribute transports, like an onion
+//! transport, to a running node.
+//!
+//! Each transport is registered under the names of the multiaddr protocols it
+//! handles, e.g. `["onion3"]` or `["tcp", "ws"]`. An address is dialed or
+//! listened on with the transports all of whose protocols occur in the address,
+//! the transports with the most protocols first, then in registration order,
+//! until one of them does not report the address as unsupported.
+//!
+//! All transports of a registry share the same output and error types, which
+//! transports are mapped to with [`Transport::map`] and [`Transport::map_err`],
+//! e.g. to a `(PeerId, StreamMuxerBox)` output and an `io::Error`.
+
+use crate::transport::{Transport, TransportError, boxed::{self, Boxed}};
+use multiaddr::Multiaddr;
+use parking_lot::RwLock;
+use std::{cmp, error, fmt, sync::Arc};
+
+/// Identifies a transport registered with a [`TransportRegistry`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RegistrationId(u64);
+
+/// A set of boxed transports that can be changed at runtime.
+///
+/// Cloning a `TransportRegistry` returns a handle to the same set of transports.
+pub struct TransportRegistry<O, E> {
+    inner: Arc<RwLock<Inner<O, E>>>,
+}
+
+struct Inner<O, E> {
+    /// The identifier of the next registration.
+    next_id: u64,
+    /// The registered transports, in registration order.
+    entries: Vec<Entry<O, E>>,
+}
+
+struct Entry<O, E> {
+    id: RegistrationId,
+    protocols: Vec<String>,
+    transport: Boxed<O, E>,
+}
+
+impl<O, E> TransportRegistry<O, E> {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        TransportRegistry {
+            inner: Arc::new(RwLock::new(Inner {
+                next_id: 0,
+                entries: Vec::new(),
+            }))
+        }
+    }
+
+    /// Registers `transport` for the addresses containing all of `protocols`, given by
+    /// their names, e.g. `"tcp"`.
+    ///
+    /// A transport registered without protocols is a candidate for all addresses.
+    pub fn register<T, I, S>(&self, protocols: I, transport: T) -> RegistrationId
+    where
+        T: Transport<Output = O, Error = E> + Clone + Send + Sync + 'static,
+        T::Dial: Send + 'static,
+        T::Listener: Send + 'static,
+        T::ListenerUpgrade: Send + 'static,
+        E: error::Error,
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut inner = self.inner.write();
+        let id = RegistrationId(inner.next_id);
+        inner.next_id += 1;
+        inner.entries.push(Entry {
+            id,
+            protocols: protocols.into_iter().map(Into::into).collect(),
+            transport: transport.boxed(),
+        });
+        id
+    }
+
+    /// Unregisters a transport. Returns false if it was not registered.
+    ///
+    /// Listeners and dials in progress are unaffected.
+    pub fn unregister(&self, id: RegistrationId) -> bool {
+        let mut inner = self.inner.write();
+        let len = inner.entries.len();
+        inner.entries.retain(|e| e.id != id);
+        inner.entries.len() != len
+    }
+
+    /// Returns true if a transport is registered for the protocols of `addr`.
+    ///
+    /// The transport may still reject the address as unsupported.
+    pub fn supports(&self, addr: &Multiaddr) -> bool {
+        !self.candidates(addr).is_empty()
+    }
+
+    /// Returns the number of registered transports.
+    pub fn len(&self) -> usize {
+        self.inner.read().entries.len()
+    }
+
+    /// Returns true if no transport is registered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the transports to try for `addr`, in order.
+    fn candidates(&self, addr: &Multiaddr) -> Vec<Boxed<O, E>> {
+        let names = addr.iter().map(|p| protocol_name(&p.to_string())).collect::<Vec<_>>();
+        let inner = self.inner.read();
+        let mut candidates = inner.entries.iter()
+            .filter(|e| e.protocols.iter().all(|p| names.contains(p)))
+            .collect::<Vec<_>>();
+        // The sort is stable, preserving the registration order.
+        candidates.sort_by_key(|e| cmp::Reverse(e.protocols.len()));
+        candidates.into_iter().map(|e| e.transport.clone()).collect()
+    }
+}
+
+/// Returns the name of a protocol from its textual representation.
+fn protocol_name(protocol: &str) -> String {
+    protocol.split('/').nth(1).unwrap_or_default().to_owned()
+}
+
+impl<O, E> Default for TransportRegistry<O, E> {
+    fn default() -> Self {
+        TransportRegistry::new()
+    }
+}
+
+impl<O, E> Clone for TransportRegistry<O, E> {
+    fn clone(&self) -> Self {
+        TransportRegistry {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<O, E> fmt::Debug for TransportRegistry<O, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.read();
+        f.debug_list()
+            .entries(inner.entries.iter().map(|e| (e.id, &e.protocols)))
+            .finish()
+    }
+}
+
+impl<O, E> Transport for TransportRegistry<O, E>
+where E: error::Error,
+{
+    type Output = O;
+    type Error = E;
+    type Listener = boxed::Listener<O, E>;
+    type ListenerUpgrade = boxed::ListenerUpgrade<O, E>;
+    type Dial = boxed::Dial<O, E>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let mut addr = addr;
+        for transport in self.candidates(&addr) {
+            match transport.listen_on(addr) {
+                Err(TransportError::MultiaddrNotSupported(a)) => addr = a,
+                result => return result
+            }
+        }
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let mut addr = addr;
+        for transport in self.candidates(&addr) {
+            match transport.dial(addr) {
+                Err(TransportError::MultiaddrNotSupported(a)) => addr = a,
+                result => return result
+            }
+        }
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{ListenerEvent, MemoryTransport, dummy::DummyTransport};
+    use futures::prelude::*;
+    use std::io;
+
+    type Registry = TransportRegistry<Vec<u8>, io::Error>;
+
+    /// Registers a memory transport whose connections output `tag`.
+    fn register_memory(registry: &Registry, protocols: &[&str], tag: u8) -> RegistrationId {
+        let transport = MemoryTransport
+            .map(move |_, _| vec![tag])
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+        registry.register(protocols.iter().cloned(), transport)
+    }
+
+    fn dial(registry: &Registry, addr: &str) -> Option<Vec<u8>> {
+        let addr: Multiaddr = addr.parse().unwrap();
+        match registry.clone().dial(addr) {
+            Ok(dial) => futures::executor::block_on(dial).ok(),
+            Err(TransportError::MultiaddrNotSupported(_)) => None,
+            Err(TransportError::Other(e)) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn most_specific_transport_first() {
+        let registry = Registry::new();
+        let port = rand::random::<u64>().saturating_add(1);
+        let mut listener = MemoryTransport.listen_on(format!("/memory/{}", port).parse().unwrap()).unwrap();
+        let _ = futures::executor::block_on(listener.next());
+        let addr = format!("/memory/{}", port);
+
+        assert!(!registry.supports(&addr.parse().unwrap()));
+        assert_eq!(dial(&registry, &addr), None);
+
+        let generic = register_memory(&registry, &[], 1);
+        assert_eq!(dial(&registry, &addr), Some(vec![1]));
+
+        let specific = register_memory(&registry, &["memory"], 2);
+        let _unrelated = register_memory(&registry, &["memory", "tcp"], 3);
+        assert_eq!(dial(&registry, &addr), Some(vec![2]));
+
+        assert!(registry.unregister(specific));
+        assert!(!registry.unregister(specific));
+        assert_eq!(dial(&registry, &addr), Some(vec![1]));
+        assert!(registry.unregister(generic));
+        assert_eq!(dial(&registry, &addr), None);
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn unsupported_addresses_fall_through() {
+        let registry = Registry::new();
+        registry.register(vec!["memory"], DummyTransport::<Vec<u8>>::new());
+        register_memory(&registry, &["memory"], 1);
+
+        let mut listener = registry.clone().listen_on("/memory/0".parse().unwrap()).unwrap();
+        let addr = match futures::executor::block_on(listener.next()) {
+            Some(Ok(ListenerEvent::NewAddress(addr))) => addr,
+            _ => panic!("Expected a listen address")
+        };
+        assert_eq!(dial(&registry, &addr.to_string()), Some(vec![1]));
+    }
+}
@@ -38,15 +38,23 @@ use crate::{
         OutboundUpgrade,
         InboundUpgrade,
         apply_inbound,
-        apply_outbound,
         UpgradeError,
+        UpgradeStage,
         OutboundUpgradeApply,
-        InboundUpgradeApply
+        InboundUpgradeApply,
+        ProtocolName,
+        Role,
+        RoleUpgradeApply,
+        SecuredStream,
+        SecuritySelector,
+        SelectedSecurity,
+        UpgradeInfo
     }
 };
 use futures::{prelude::*, ready};
+use futures_timer::Delay;
 use multiaddr::Multiaddr;
-use std::{error::Error, fmt, pin::Pin, task::Context, task::Poll};
+use std::{error::Error, fmt, pin::Pin, task::Context, task::Poll, time::Duration};
 
 /// A `Builder` facilitates upgrading of a [`Transport`] for use with
 /// a [`Network`].
@@ -68,10 +76,27 @@ use std::{error::Error, fmt, pin::Pin, task::Context, task::Poll};
 ///      namely a tuple of a [`ConnectionInfo`] (from the authentication upgrade) and a
 ///      [`StreamMuxer`] (from the multiplexing upgrade).
 ///
+/// Transports whose connections are natively authenticated and multiplexed,
+/// e.g. QUIC, skip these stages with
+/// [`multiplex_native`](Builder::multiplex_native).
+///
+/// The authentication upgrade resolves the [`Role`] of the local node in
+/// the upgrade process, which all subsequent stages adhere to. Listeners are
+/// always responders and dialers initiators, unless the builder is configured
+/// with [`Version::V1SimultaneousOpen`](upgrade::Version::V1SimultaneousOpen)
+/// for connections that both peers may have dialed at the same time, e.g.
+/// when punching holes through NATs with TCP. The roles are then negotiated
+/// with the remote before authentication, such that exactly one of the two
+/// dialers acts as the initiator of the security and multiplexer upgrades.
+///
+/// Each stage of the upgrade process can be subject to a separate timeout,
+/// see [`Builder::timeouts`].
+///
 /// [`Network`]: crate::nodes::Network
 pub struct Builder<T> {
     inner: T,
     version: upgrade::Version,
+    timeouts: UpgradeTimeouts,
 }
 
 impl<T> Builder<T>
@@ -81,7 +106,19 @@ where
 {
     /// Creates a `Builder` over the given (base) `Transport`.
     pub fn new(inner: T, version: upgrade::Version) -> Builder<T> {
-        Builder { inner, version }
+        Builder { inner, version, timeouts: UpgradeTimeouts::default() }
+    }
+
+    /// Sets the timeouts of the stages of the upgrade process.
+    ///
+    /// A stage not completing within its timeout fails the upgrade with an
+    /// [`UpgradeError::Timeout`] naming the stage. By default, no stage is
+    /// subject to a timeout.
+    ///
+    /// The timeouts must be set before the stages they apply to are configured.
+    pub fn timeouts(mut self, timeouts: UpgradeTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
     }
 
     /// Upgrades the transport to perform authentication of the remote.
@@ -96,7 +133,7 @@ where
     /// ## Transitions
     ///
     ///   * I/O upgrade: `C -> (I, D)`.
-    ///   * Transport output: `C -> (I, D)`
+    ///   * Transport output: `C -> ((I, Role), D)`
     pub fn authenticate<C, D, U, I, E>(self, upgrade: U) -> Builder<
         AndThen<T, impl FnOnce(C, ConnectedPoint) -> Authenticate<C, U> + Clone>
     > where
@@ -109,11 +146,53 @@ where
         E: Error + 'static,
     {
         let version = self.version;
-        Builder::new(self.inner.and_then(move |conn, endpoint| {
+        let timeouts = self.timeouts;
+        let inner = self.inner.and_then(move |conn, endpoint| {
+            Authenticate {
+                inner: upgrade::apply_with_role(conn, upgrade, endpoint, version),
+                timeouts,
+                timer: StageTimer::default(),
+            }
+        });
+        Builder { inner, version, timeouts }
+    }
+
+    /// Upgrades the transport to perform authentication of the remote,
+    /// like [`Builder::authenticate`], offering the protocols of the
+    /// upgrade chosen by the given [`SecuritySelector`] for every connection.
+    ///
+    /// This allows to express a preference among the security protocols
+    /// of the upgrade, e.g. of a [`SelectUpgrade`](upgrade::SelectUpgrade),
+    /// depending on the direction of the connection, the address or the
+    /// expected peer ID of the remote.
+    ///
+    /// ## Transitions
+    ///
+    ///   * I/O upgrade: `C -> (I, D)`.
+    ///   * Transport output: `C -> ((I, Role), D)`
+    pub fn authenticate_with<C, D, U, S, I, E>(self, upgrade: U, selector: S) -> Builder<
+        AndThen<T, impl FnOnce(C, ConnectedPoint) -> Authenticate<C, SelectedSecurity<U>> + Clone>
+    > where
+        T: Transport<Output = C>,
+        I: ConnectionInfo,
+        C: AsyncRead + AsyncWrite + Unpin,
+        D: AsyncRead + AsyncWrite + Unpin,
+        U: InboundUpgrade<Negotiated<C>, Output = (I, D), Error = E>,
+        U: OutboundUpgrade<Negotiated<C>, Output = (I, D), Error = E> + Clone,
+        S: SecuritySelector<<U as UpgradeInfo>::Info> + Clone,
+        E: Error + 'static,
+    {
+        let version = self.version;
+        let timeouts = self.timeouts;
+        let inner = self.inner.and_then(move |conn, endpoint| {
+            let upgrade = SelectedSecurity::new(upgrade, &selector, &endpoint);
             Authenticate {
-                inner: upgrade::apply(conn, upgrade, endpoint, version)
+                inner: upgrade::apply_with_role(conn, upgrade, endpoint, version),
+                timeouts,
+                timer: StageTimer::default(),
             }
-        }), version)
+        });
+        Builder { inner, version, timeouts }
     }
 
     /// Applies an arbitrary upgrade on an authenticated, non-multiplexed
@@ -126,10 +205,10 @@ where
     /// ## Transitions
     ///
     ///   * I/O upgrade: `C -> D`.
-    ///   * Transport output: `(I, C) -> (I, D)`.
+    ///   * Transport output: `((I, Role), C) -> ((I, Role), D)`.
     pub fn apply<C, D, U, I, E>(self, upgrade: U) -> Builder<Upgrade<T, U>>
     where
-        T: Transport<Output = (I, C)>,
+        T: Transport<Output = ((I, Role), C)>,
         C: AsyncRead + AsyncWrite + Unpin,
         D: AsyncRead + AsyncWrite + Unpin,
         I: ConnectionInfo,
@@ -137,7 +216,11 @@ where
         U: OutboundUpgrade<Negotiated<C>, Output = D, Error = E> + Clone,
         E: Error + 'static,
     {
-        Builder::new(Upgrade::new(self.inner, upgrade), self.version)
+        Builder {
+            inner: Upgrade::new(self.inner, upgrade),
+            version: self.version,
+            timeouts: self.timeouts,
+        }
     }
 
     /// Upgrades the transport with a (sub)stream multiplexer.
@@ -150,11 +233,11 @@ where
     /// ## Transitions
     ///
     ///   * I/O upgrade: `C -> M`.
-    ///   * Transport output: `(I, C) -> (I, M)`.
+    ///   * Transport output: `((I, Role), C) -> (I, M)`.
     pub fn multiplex<C, M, U, I, E>(self, upgrade: U)
-        -> AndThen<T, impl FnOnce((I, C), ConnectedPoint) -> Multiplex<C, U, I> + Clone>
+        -> AndThen<T, impl FnOnce(((I, Role), C), ConnectedPoint) -> Multiplex<C, U, I> + Clone>
     where
-        T: Transport<Output = (I, C)>,
+        T: Transport<Output = ((I, Role), C)>,
         C: AsyncRead + AsyncWrite + Unpin,
         M: StreamMuxer,
         I: ConnectionInfo,
@@ -163,11 +246,146 @@ where
         E: Error + 'static,
     {
         let version = self.version;
-        self.inner.and_then(move |(i, c), endpoint| {
-            let upgrade = upgrade::apply(c, upgrade, endpoint, version);
-            Multiplex { info: Some(i), upgrade }
+        let timeout = self.timeouts.muxer_negotiation;
+        self.inner.and_then(move |((i, role), c), _| {
+            let upgrade = future::Either::Left(upgrade::apply_as(c, upgrade, role, version));
+            Multiplex { info: Some(i), upgrade, timer: timeout.map(Delay::new) }
         })
     }
+
+    /// Upgrades the transport with a (sub)stream multiplexer, like
+    /// [`Builder::multiplex`], using the multiplexer agreed on during the
+    /// security handshake without negotiating it, if any.
+    ///
+    /// Security protocols supporting the early negotiation of a multiplexer,
+    /// i.e. noise, exchange the names of the multiplexers supported by both
+    /// parties during the handshake, which saves the roundtrip of the
+    /// negotiation. The agreed multiplexer is applied if it is one of the
+    /// protocols of `upgrade`, otherwise the multiplexer is negotiated, as
+    /// it always is with other security protocols, e.g. TLS.
+    ///
+    /// ## Transitions
+    ///
+    ///   * I/O upgrade: `C -> M`.
+    ///   * Transport output: `((I, Role), C) -> (I, M)`.
+    pub fn multiplex_early<C, M, U, I, E>(self, upgrade: U)
+        -> AndThen<T, impl FnOnce(((I, Role), C), ConnectedPoint) -> Multiplex<C, U, I> + Clone>
+    where
+        T: Transport<Output = ((I, Role), C)>,
+        C: AsyncRead + AsyncWrite + SecuredStream + Unpin,
+        M: StreamMuxer,
+        I: ConnectionInfo,
+        U: InboundUpgrade<Negotiated<C>, Output = M, Error = E>,
+        U: OutboundUpgrade<Negotiated<C>, Output = M, Error = E> + Clone,
+        E: Error + 'static,
+    {
+        let version = self.version;
+        let timeout = self.timeouts.muxer_negotiation;
+        self.inner.and_then(move |((i, role), c), _| {
+            let early = c.early_muxer().and_then(|name| {
+                upgrade.protocol_info().into_iter().find(|p| p.protocol_name() == name)
+            });
+            let upgrade = match early {
+                Some(info) if role == Role::Responder => future::Either::Right(future::Either::Left(
+                    upgrade.upgrade_inbound(upgrade::agreed(c), info).map_err(UpgradeError::Apply as fn(E) -> _)
+                )),
+                Some(info) => future::Either::Right(future::Either::Right(
+                    upgrade.upgrade_outbound(upgrade::agreed(c), info).map_err(UpgradeError::Apply as fn(E) -> _)
+                )),
+                None => future::Either::Left(upgrade::apply_as(c, upgrade, role, version)),
+            };
+            Multiplex { info: Some(i), upgrade, timer: timeout.map(Delay::new) }
+        })
+    }
+
+    /// Ends the upgrade process of a transport whose connections are
+    /// natively authenticated and multiplexed, e.g. QUIC, without negotiating
+    /// a multiplexer.
+    ///
+    /// Negotiating a multiplexer with [`Builder::multiplex`] over a substream
+    /// of such a transport would needlessly multiplex the substreams twice.
+    ///
+    /// ## Transitions
+    ///
+    ///   * I/O upgrade: none.
+    ///   * Transport output: `(I, M) -> (I, M)`.
+    pub fn multiplex_native<M, I>(self) -> T
+    where
+        T: Transport<Output = (I, M)>,
+        M: StreamMuxer,
+        I: ConnectionInfo,
+    {
+        self.inner
+    }
+}
+
+/// The timeouts of the stages of the upgrade process configured
+/// through a [`Builder`].
+///
+/// The timeout of a stage only covers the time spent in that stage,
+/// starting when the previous stage completed.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct UpgradeTimeouts {
+    security_negotiation: Option<Duration>,
+    security_handshake: Option<Duration>,
+    muxer_negotiation: Option<Duration>,
+}
+
+impl UpgradeTimeouts {
+    /// Creates timeouts not limiting any stage.
+    pub fn new() -> Self {
+        UpgradeTimeouts::default()
+    }
+
+    /// Sets the timeout of the negotiation of the security protocol,
+    /// including the resolution of roles of simultaneously opened connections.
+    pub fn security_negotiation(mut self, timeout: Duration) -> Self {
+        self.security_negotiation = Some(timeout);
+        self
+    }
+
+    /// Sets the timeout of the handshake of the negotiated security protocol.
+    pub fn security_handshake(mut self, timeout: Duration) -> Self {
+        self.security_handshake = Some(timeout);
+        self
+    }
+
+    /// Sets the timeout of the negotiation of the multiplexer.
+    pub fn muxer_negotiation(mut self, timeout: Duration) -> Self {
+        self.muxer_negotiation = Some(timeout);
+        self
+    }
+
+    /// Returns the timeout of the given stage, if any.
+    pub fn get(&self, stage: UpgradeStage) -> Option<Duration> {
+        match stage {
+            UpgradeStage::SecurityNegotiation => self.security_negotiation,
+            UpgradeStage::SecurityHandshake => self.security_handshake,
+            UpgradeStage::MuxerNegotiation => self.muxer_negotiation,
+        }
+    }
+}
+
+/// The timer of the current stage of an upgrade, restarted on every
+/// transition to another stage.
+#[derive(Default)]
+struct StageTimer {
+    stage: Option<UpgradeStage>,
+    delay: Option<Delay>,
+}
+
+impl StageTimer {
+    /// Polls the timer of the given current stage, resolving if the stage timed out.
+    fn poll(&mut self, stage: UpgradeStage, timeouts: &UpgradeTimeouts, cx: &mut Context) -> Poll<()> {
+        if self.stage != Some(stage) {
+            self.stage = Some(stage);
+            self.delay = timeouts.get(stage).map(Delay::new);
+        }
+        match &mut self.delay {
+            Some(delay) => delay.poll_unpin(cx),
+            None => Poll::Pending,
+        }
+    }
 }
 
 /// An upgrade that authenticates the remote peer, typically
@@ -181,29 +399,42 @@ where
     U: InboundUpgrade<Negotiated<C>> + OutboundUpgrade<Negotiated<C>>
 {
     #[pin]
-    inner: EitherUpgrade<C, U>
+    inner: RoleUpgradeApply<C, U>,
+    timeouts: UpgradeTimeouts,
+    timer: StageTimer,
 }
 
-impl<C, U> Future for Authenticate<C, U>
+impl<C, U, I, D, E> Future for Authenticate<C, U>
 where
     C: AsyncRead + AsyncWrite + Unpin,
-    U: InboundUpgrade<Negotiated<C>> + OutboundUpgrade<Negotiated<C>,
-        Output = <U as InboundUpgrade<Negotiated<C>>>::Output,
-        Error = <U as InboundUpgrade<Negotiated<C>>>::Error
-    >
+    U: InboundUpgrade<Negotiated<C>, Output = (I, D), Error = E>,
+    U: OutboundUpgrade<Negotiated<C>, Output = (I, D), Error = E>
 {
-    type Output = <EitherUpgrade<C, U> as Future>::Output;
+    type Output = Result<((I, Role), D), UpgradeError<E>>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        let this = self.project();
-        Future::poll(this.inner, cx)
+        let mut this = self.project();
+        let ((i, d), role) = match Future::poll(this.inner.as_mut(), cx) {
+            Poll::Ready(Ok(v)) => v,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => {
+                let stage = if this.inner.is_negotiated() {
+                    UpgradeStage::SecurityHandshake
+                } else {
+                    UpgradeStage::SecurityNegotiation
+                };
+                ready!(this.timer.poll(stage, this.timeouts, cx));
+                return Poll::Ready(Err(UpgradeError::Timeout(stage)))
+            }
+        };
+        Poll::Ready(Ok(((i, role), d)))
     }
 }
 
 /// An upgrade that negotiates a (sub)stream multiplexer on
 /// top of an authenticated transport.
 ///
-/// Configured through [`Builder::multiplex`].
+/// Configured through [`Builder::multiplex`] or [`Builder::multiplex_early`].
 #[pin_project::pin_project]
 pub struct Multiplex<C, U, I>
 where
@@ -212,7 +443,8 @@ where
 {
     info: Option<I>,
     #[pin]
-    upgrade: EitherUpgrade<C, U>,
+    upgrade: future::Either<EitherUpgrade<C, U>, EarlyUpgrade<C, U>>,
+    timer: Option<Delay>,
 }
 
 impl<C, U, I, M, E> Future for Multiplex<C, U, I>
@@ -225,9 +457,16 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         let this = self.project();
-        let m = match ready!(Future::poll(this.upgrade, cx)) {
-            Ok(m) => m,
-            Err(err) => return Poll::Ready(Err(err)),
+        let m = match Future::poll(this.upgrade, cx) {
+            Poll::Ready(Ok(m)) => m,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => {
+                if let Some(timer) = this.timer {
+                    ready!(timer.poll_unpin(cx));
+                    return Poll::Ready(Err(UpgradeError::Timeout(UpgradeStage::MuxerNegotiation)))
+                }
+                return Poll::Pending
+            }
         };
         let i = this.info.take().expect("Multiplex future polled after completion.");
         Poll::Ready(Ok((i, m)))
@@ -237,6 +476,18 @@ where
 /// An inbound or outbound upgrade.
 type EitherUpgrade<C, U> = future::Either<InboundUpgradeApply<C, U>, OutboundUpgradeApply<C, U>>;
 
+/// An inbound or outbound upgrade with a protocol agreed on without negotiation.
+type EarlyUpgrade<C, U> = future::Either<
+    future::MapErr<
+        <U as InboundUpgrade<Negotiated<C>>>::Future,
+        fn(<U as InboundUpgrade<Negotiated<C>>>::Error) -> UpgradeError<<U as InboundUpgrade<Negotiated<C>>>::Error>
+    >,
+    future::MapErr<
+        <U as OutboundUpgrade<Negotiated<C>>>::Future,
+        fn(<U as OutboundUpgrade<Negotiated<C>>>::Error) -> UpgradeError<<U as OutboundUpgrade<Negotiated<C>>>::Error>
+    >
+>;
+
 /// An upgrade on an authenticated, non-multiplexed [`Transport`].
 ///
 /// See [`Builder::upgrade`](Builder::upgrade).
@@ -251,18 +502,18 @@ impl<T, U> Upgrade<T, U> {
 
 impl<T, C, D, U, I, E> Transport for Upgrade<T, U>
 where
-    T: Transport<Output = (I, C)>,
+    T: Transport<Output = ((I, Role), C)>,
     T::Error: 'static,
     C: AsyncRead + AsyncWrite + Unpin,
     U: InboundUpgrade<Negotiated<C>, Output = D, Error = E>,
     U: OutboundUpgrade<Negotiated<C>, Output = D, Error = E> + Clone,
     E: Error + 'static
 {
-    type Output = (I, D);
+    type Output = ((I, Role), D);
     type Error = TransportUpgradeError<T::Error, E>;
     type Listener = ListenerStream<T::Listener, U>;
-    type ListenerUpgrade = ListenerUpgradeFuture<T::ListenerUpgrade, U, I, C>;
-    type Dial = DialUpgradeFuture<T::Dial, U, I, C>;
+    type ListenerUpgrade = ListenerUpgradeFuture<T::ListenerUpgrade, U, (I, Role), C>;
+    type Dial = DialUpgradeFuture<T::Dial, U, (I, Role), C>;
 
     fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
         let future = self.inner.dial(addr.clone())
@@ -319,23 +570,26 @@ where
 }
 
 /// The [`Transport::Dial`] future of an [`Upgrade`]d transport.
+///
+/// The upgrade is applied in the [`Role`] resolved for the connection.
 pub struct DialUpgradeFuture<F, U, I, C>
 where
-    U: OutboundUpgrade<Negotiated<C>>,
+    U: InboundUpgrade<Negotiated<C>> + OutboundUpgrade<Negotiated<C>>,
     C: AsyncRead + AsyncWrite + Unpin,
 {
     future: Pin<Box<F>>,
-    upgrade: future::Either<Option<U>, (Option<I>, OutboundUpgradeApply<C, U>)>
+    upgrade: future::Either<Option<U>, (Option<I>, EitherUpgrade<C, U>)>
 }
 
-impl<F, U, I, C, D> Future for DialUpgradeFuture<F, U, I, C>
+impl<F, U, I, C, D, E> Future for DialUpgradeFuture<F, U, (I, Role), C>
 where
-    F: TryFuture<Ok = (I, C)>,
+    F: TryFuture<Ok = ((I, Role), C)>,
     C: AsyncRead + AsyncWrite + Unpin,
-    U: OutboundUpgrade<Negotiated<C>, Output = D>,
-    U::Error: Error
+    U: InboundUpgrade<Negotiated<C>, Output = D, Error = E>,
+    U: OutboundUpgrade<Negotiated<C>, Output = D, Error = E>,
+    E: Error
 {
-    type Output = Result<(I, D), TransportUpgradeError<F::Error, U::Error>>;
+    type Output = Result<((I, Role), D), TransportUpgradeError<F::Error, E>>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         // We use a `this` variable because the compiler can't mutably borrow multiple times
@@ -345,12 +599,12 @@ where
         loop {
             this.upgrade = match this.upgrade {
                 future::Either::Left(ref mut up) => {
-                    let (i, c) = match ready!(TryFuture::try_poll(this.future.as_mut(), cx).map_err(TransportUpgradeError::Transport)) {
+                    let ((i, role), c) = match ready!(TryFuture::try_poll(this.future.as_mut(), cx).map_err(TransportUpgradeError::Transport)) {
                         Ok(v) => v,
                         Err(err) => return Poll::Ready(Err(err)),
                     };
                     let u = up.take().expect("DialUpgradeFuture is constructed with Either::Left(Some).");
-                    future::Either::Right((Some(i), apply_outbound(c, u, upgrade::Version::V1)))
+                    future::Either::Right((Some((i, role)), upgrade::apply_as(c, u, role, upgrade::Version::V1)))
                 }
                 future::Either::Right((ref mut i, ref mut up)) => {
                     let d = match ready!(Future::poll(Pin::new(up), cx).map_err(TransportUpgradeError::Upgrade)) {
@@ -367,7 +621,7 @@ where
 
 impl<F, U, I, C> Unpin for DialUpgradeFuture<F, U, I, C>
 where
-    U: OutboundUpgrade<Negotiated<C>>,
+    U: InboundUpgrade<Negotiated<C>> + OutboundUpgrade<Negotiated<C>>,
     C: AsyncRead + AsyncWrite + Unpin,
 {
 }
@@ -19,13 +19,13 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::{ConnectedPoint, Negotiated};
-use crate::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeError, ProtocolName};
+use crate::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeError, UpgradeInfo, ProtocolName};
 use futures::{future::Either, prelude::*, compat::Compat, compat::Compat01As03, compat::Future01CompatExt};
 use log::debug;
-use multistream_select::{self, DialerSelectFuture, ListenerSelectFuture};
+use multistream_select::{self, DialerSelectFuture, DialerSelectSimOpen, ListenerSelectFuture};
 use std::{iter, mem, pin::Pin, task::Context, task::Poll};
 
-pub use multistream_select::Version;
+pub use multistream_select::{Role, Version};
 
 /// Applies an upgrade to the inbound and outbound direction of a connection or substream.
 pub fn apply<C, U>(conn: C, up: U, cp: ConnectedPoint, v: Version)
@@ -41,6 +41,54 @@ where
     }
 }
 
+/// Applies an upgrade to a connection or substream in the given role of the local node.
+pub(crate) fn apply_as<C, U>(conn: C, up: U, role: Role, v: Version)
+    -> Either<InboundUpgradeApply<C, U>, OutboundUpgradeApply<C, U>>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: InboundUpgrade<Negotiated<C>> + OutboundUpgrade<Negotiated<C>>,
+{
+    match role {
+        Role::Responder => Either::Left(apply_inbound(conn, up)),
+        Role::Initiator => Either::Right(apply_outbound(conn, up, v)),
+    }
+}
+
+/// Applies an upgrade to a connection, resolving the role of the local node
+/// in the upgrade process.
+///
+/// The role of a listener is always [`Role::Responder`], that of a dialer
+/// [`Role::Initiator`], unless the given version is [`Version::V1SimultaneousOpen`].
+/// In that case, the dialer negotiates its role with the remote, which may
+/// have dialed the same connection simultaneously.
+pub fn apply_with_role<C, U>(conn: C, up: U, cp: ConnectedPoint, v: Version) -> RoleUpgradeApply<C, U>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: InboundUpgrade<Negotiated<C>> + OutboundUpgrade<Negotiated<C>>,
+{
+    let inner = match (cp, v) {
+        (ConnectedPoint::Listener { .. }, _) =>
+            RoleUpgradeApplyState::Inbound(apply_inbound(conn, up)),
+        (ConnectedPoint::Dialer { .. }, Version::V1SimultaneousOpen) => {
+            let iter = up.protocol_info().into_iter().map(NameWrap as fn(_) -> NameWrap<_>);
+            let future = multistream_select::dialer_select_proto_simopen(Compat::new(conn), iter).compat();
+            RoleUpgradeApplyState::SimOpen { future, upgrade: up }
+        }
+        (ConnectedPoint::Dialer { .. }, v) =>
+            RoleUpgradeApplyState::Outbound(apply_outbound(conn, up, v)),
+    };
+    RoleUpgradeApply { inner }
+}
+
+/// Wraps a connection or substream whose protocol has been agreed on
+/// without protocol negotiation, e.g. during a security handshake.
+pub(crate) fn agreed<C>(conn: C) -> Negotiated<C>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    Compat01As03::new(multistream_select::Negotiated::agreed(Compat::new(conn)))
+}
+
 /// Tries to perform an upgrade on an inbound connection or substream.
 pub fn apply_inbound<C, U>(conn: C, up: U) -> InboundUpgradeApply<C, U>
 where
@@ -219,6 +267,118 @@ where
     }
 }
 
+/// Future returned by `apply_with_role`. Drives the upgrade process.
+pub struct RoleUpgradeApply<C, U>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: InboundUpgrade<Negotiated<C>> + OutboundUpgrade<Negotiated<C>>,
+{
+    inner: RoleUpgradeApplyState<C, U>
+}
+
+enum RoleUpgradeApplyState<C, U>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: InboundUpgrade<Negotiated<C>> + OutboundUpgrade<Negotiated<C>>,
+{
+    SimOpen {
+        future: SimOpenFuture<C, U>,
+        upgrade: U
+    },
+    Inbound(InboundUpgradeApply<C, U>),
+    Outbound(OutboundUpgradeApply<C, U>),
+    Undefined
+}
+
+impl<C, U> RoleUpgradeApply<C, U>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: InboundUpgrade<Negotiated<C>> + OutboundUpgrade<Negotiated<C>>,
+{
+    /// Returns true if the protocol has been negotiated and is being applied.
+    pub(crate) fn is_negotiated(&self) -> bool {
+        matches!(&self.inner,
+            RoleUpgradeApplyState::Inbound(InboundUpgradeApply {
+                inner: InboundUpgradeApplyState::Upgrade { .. }
+            }) |
+            RoleUpgradeApplyState::Outbound(OutboundUpgradeApply {
+                inner: OutboundUpgradeApplyState::Upgrade { .. }
+            }))
+    }
+}
+
+impl<C, U> Unpin for RoleUpgradeApply<C, U>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: InboundUpgrade<Negotiated<C>> + OutboundUpgrade<Negotiated<C>>,
+{
+}
+
+impl<C, U, O, E> Future for RoleUpgradeApply<C, U>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: InboundUpgrade<Negotiated<C>, Output = O, Error = E>,
+    U: OutboundUpgrade<Negotiated<C>, Output = O, Error = E>,
+{
+    type Output = Result<(O, Role), UpgradeError<E>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        loop {
+            match mem::replace(&mut self.inner, RoleUpgradeApplyState::Undefined) {
+                RoleUpgradeApplyState::SimOpen { mut future, upgrade } => {
+                    let (info, io, role) = match Future::poll(Pin::new(&mut future), cx)? {
+                        Poll::Ready(x) => x,
+                        Poll::Pending => {
+                            self.inner = RoleUpgradeApplyState::SimOpen { future, upgrade };
+                            return Poll::Pending
+                        }
+                    };
+                    let io = Compat01As03::new(io);
+                    self.inner = match role {
+                        Role::Initiator => RoleUpgradeApplyState::Outbound(OutboundUpgradeApply {
+                            inner: OutboundUpgradeApplyState::Upgrade {
+                                future: Box::pin(upgrade.upgrade_outbound(io, info.0))
+                            }
+                        }),
+                        Role::Responder => RoleUpgradeApplyState::Inbound(InboundUpgradeApply {
+                            inner: InboundUpgradeApplyState::Upgrade {
+                                future: Box::pin(upgrade.upgrade_inbound(io, info.0))
+                            }
+                        }),
+                    };
+                }
+                RoleUpgradeApplyState::Inbound(mut future) => {
+                    match Future::poll(Pin::new(&mut future), cx) {
+                        Poll::Pending => {
+                            self.inner = RoleUpgradeApplyState::Inbound(future);
+                            return Poll::Pending
+                        }
+                        Poll::Ready(result) =>
+                            return Poll::Ready(result.map(|o| (o, Role::Responder)))
+                    }
+                }
+                RoleUpgradeApplyState::Outbound(mut future) => {
+                    match Future::poll(Pin::new(&mut future), cx) {
+                        Poll::Pending => {
+                            self.inner = RoleUpgradeApplyState::Outbound(future);
+                            return Poll::Pending
+                        }
+                        Poll::Ready(result) =>
+                            return Poll::Ready(result.map(|o| (o, Role::Initiator)))
+                    }
+                }
+                RoleUpgradeApplyState::Undefined =>
+                    panic!("RoleUpgradeApplyState::poll called after completion")
+            }
+        }
+    }
+}
+
+type SimOpenFuture<C, U> = Compat01As03<DialerSelectSimOpen<
+    Compat<C>,
+    NameWrapIter<<<U as UpgradeInfo>::InfoIter as IntoIterator>::IntoIter>
+>>;
+
 type NameWrapIter<I> = iter::Map<I, fn(<I as Iterator>::Item) -> NameWrap<<I as Iterator>::Item>>;
 
 /// Wrapper type to expose an `AsRef<[u8]>` impl for all types implementing `ProtocolName`.
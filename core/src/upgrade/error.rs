@@ -28,6 +28,31 @@ pub enum UpgradeError<E> {
     Select(NegotiationError),
     /// Error during the post-negotiation handshake.
     Apply(E),
+    /// A stage of the upgrade process of a connection did not complete in time.
+    ///
+    /// See [`UpgradeTimeouts`](crate::transport::upgrade::UpgradeTimeouts).
+    Timeout(UpgradeStage),
+}
+
+/// A stage of the upgrade process of a connection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UpgradeStage {
+    /// The negotiation of the security protocol.
+    SecurityNegotiation,
+    /// The handshake of the negotiated security protocol.
+    SecurityHandshake,
+    /// The negotiation of the (sub)stream multiplexer.
+    MuxerNegotiation,
+}
+
+impl fmt::Display for UpgradeStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpgradeStage::SecurityNegotiation => write!(f, "security negotiation"),
+            UpgradeStage::SecurityHandshake => write!(f, "security handshake"),
+            UpgradeStage::MuxerNegotiation => write!(f, "muxer negotiation"),
+        }
+    }
 }
 
 impl<E> UpgradeError<E> {
@@ -38,6 +63,7 @@ impl<E> UpgradeError<E> {
         match self {
             UpgradeError::Select(e) => UpgradeError::Select(e),
             UpgradeError::Apply(e) => UpgradeError::Apply(f(e)),
+            UpgradeError::Timeout(s) => UpgradeError::Timeout(s),
         }
     }
 
@@ -57,6 +83,7 @@ where
         match self {
             UpgradeError::Select(e) => write!(f, "select error: {}", e),
             UpgradeError::Apply(e) => write!(f, "upgrade apply error: {}", e),
+            UpgradeError::Timeout(s) => write!(f, "timeout during {}", s),
         }
     }
 }
@@ -69,6 +96,7 @@ where
         match self {
             UpgradeError::Select(e) => Some(e),
             UpgradeError::Apply(e) => Some(e),
+            UpgradeError::Timeout(_) => None,
         }
     }
 }
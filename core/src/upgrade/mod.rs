@@ -64,24 +64,30 @@ mod error;
 mod from_fn;
 mod map;
 mod optional;
+mod security;
 mod select;
 mod transfer;
 
 use futures::future::Future;
 
 pub use crate::Negotiated;
-pub use multistream_select::{Version, NegotiatedComplete, NegotiationError, ProtocolError};
+pub use multistream_select::{Version, NegotiatedComplete, NegotiationError, ProtocolError, Role};
 pub use self::{
-    apply::{apply, apply_inbound, apply_outbound, InboundUpgradeApply, OutboundUpgradeApply},
+    apply::{
+        apply, apply_inbound, apply_outbound, apply_with_role,
+        InboundUpgradeApply, OutboundUpgradeApply, RoleUpgradeApply
+    },
     denied::DeniedUpgrade,
     either::EitherUpgrade,
-    error::UpgradeError,
+    error::{UpgradeError, UpgradeStage},
     from_fn::{from_fn, FromFnUpgrade},
     map::{MapInboundUpgrade, MapOutboundUpgrade, MapInboundUpgradeErr, MapOutboundUpgradeErr},
     optional::OptionalUpgrade,
+    security::{SecuredStream, SecurityStats, SecuritySelector, SelectedSecurity},
     select::SelectUpgrade,
     transfer::{write_one, write_with_len_prefix, write_varint, read_one, ReadOneError, read_varint},
 };
+pub(crate) use self::apply::{agreed, apply_as};
 
 /// Types serving as protocol names.
 ///
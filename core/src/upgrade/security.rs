@@ -0,0 +1,220 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Selection of the security protocols negotiated on a connection.
+
+use crate::{
+    ConnectedPoint,
+    PeerId,
+    upgrade::{InboundUpgrade, OutboundUpgrade, ProtocolName, UpgradeInfo}
+};
+use multiaddr::Protocol;
+
+/// Decides which protocols of a security upgrade are offered on a
+/// connection, and in which order of preference.
+///
+/// The selector is consulted for every connection before the security
+/// protocol is negotiated, with the [`ConnectedPoint`] of the connection
+/// and the [`PeerId`] of the remote, if known, i.e. if the dialed address
+/// ends with a `/p2p` component.
+///
+/// As the dialer, the protocols are proposed to the remote in the returned
+/// order. As the listener, the remote proposes the protocols and the order
+/// is irrelevant, but omitting a protocol refuses it.
+pub trait SecuritySelector<I> {
+    /// Returns the protocols to offer, in order of preference, among the
+    /// `protocols` supported by the security upgrade.
+    fn select(&self, endpoint: &ConnectedPoint, remote: Option<&PeerId>, protocols: Vec<I>) -> Vec<I>;
+}
+
+impl<I, F> SecuritySelector<I> for F
+where
+    F: Fn(&ConnectedPoint, Option<&PeerId>, Vec<I>) -> Vec<I>
+{
+    fn select(&self, endpoint: &ConnectedPoint, remote: Option<&PeerId>, protocols: Vec<I>) -> Vec<I> {
+        self(endpoint, remote, protocols)
+    }
+}
+
+/// Security upgrade offering the protocols chosen by a [`SecuritySelector`]
+/// for a particular connection.
+#[derive(Debug, Clone)]
+pub struct SelectedSecurity<U: UpgradeInfo> {
+    upgrade: U,
+    protocols: Vec<U::Info>
+}
+
+impl<U: UpgradeInfo> SelectedSecurity<U> {
+    /// Chooses the protocols of `upgrade` to offer on the connection with
+    /// the given endpoint with the help of `selector`.
+    ///
+    /// Protocols returned by the selector that `upgrade` does not support
+    /// are never offered.
+    pub fn new<S>(upgrade: U, selector: &S, endpoint: &ConnectedPoint) -> Self
+    where
+        S: SecuritySelector<U::Info>
+    {
+        let remote = remote_peer(endpoint);
+        let supported = upgrade.protocol_info().into_iter().collect::<Vec<_>>();
+        let mut protocols = selector.select(endpoint, remote.as_ref(), supported.clone());
+        protocols.retain(|p| supported.iter().any(|s| s.protocol_name() == p.protocol_name()));
+        SelectedSecurity { upgrade, protocols }
+    }
+}
+
+impl<U: UpgradeInfo> UpgradeInfo for SelectedSecurity<U> {
+    type Info = U::Info;
+    type InfoIter = std::vec::IntoIter<U::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.protocols.clone().into_iter()
+    }
+}
+
+impl<C, U> InboundUpgrade<C> for SelectedSecurity<U>
+where
+    U: InboundUpgrade<C>
+{
+    type Output = U::Output;
+    type Error = U::Error;
+    type Future = U::Future;
+
+    fn upgrade_inbound(self, sock: C, info: Self::Info) -> Self::Future {
+        self.upgrade.upgrade_inbound(sock, info)
+    }
+}
+
+impl<C, U> OutboundUpgrade<C> for SelectedSecurity<U>
+where
+    U: OutboundUpgrade<C>
+{
+    type Output = U::Output;
+    type Error = U::Error;
+    type Future = U::Future;
+
+    fn upgrade_outbound(self, sock: C, info: Self::Info) -> Self::Future {
+        self.upgrade.upgrade_outbound(sock, info)
+    }
+}
+
+/// Traffic statistics of a session of a security protocol.
+///
+/// Comparing the plaintext and ciphertext byte counts separates the
+/// payload of a connection from the overhead of the security protocol.
+/// The ciphertext and frame counts include the handshake, whose messages
+/// are thus part of the overhead.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SecurityStats {
+    /// The number of plaintext bytes sent, i.e. the payload.
+    pub plaintext_sent: u64,
+    /// The number of plaintext bytes received, i.e. the payload.
+    pub plaintext_received: u64,
+    /// The number of bytes sent on the underlying stream, including
+    /// framing, authentication tags and padding.
+    pub ciphertext_sent: u64,
+    /// The number of bytes received on the underlying stream, including
+    /// framing, authentication tags and padding.
+    pub ciphertext_received: u64,
+    /// The number of frames, or records, sent.
+    pub frames_sent: u64,
+    /// The number of frames, or records, received.
+    pub frames_received: u64
+}
+
+impl SecurityStats {
+    /// The number of bytes sent in addition to the payload.
+    pub fn overhead_sent(&self) -> u64 {
+        self.ciphertext_sent.saturating_sub(self.plaintext_sent)
+    }
+
+    /// The number of bytes received in addition to the payload.
+    pub fn overhead_received(&self) -> u64 {
+        self.ciphertext_received.saturating_sub(self.plaintext_received)
+    }
+}
+
+/// The output of a security upgrade which keeps [`SecurityStats`] of
+/// the established session.
+pub trait SecuredStream {
+    /// The statistics of the session so far.
+    fn security_stats(&self) -> SecurityStats;
+
+    /// The name of the stream multiplexer agreed on during the handshake
+    /// of the session, if any.
+    ///
+    /// See [`Builder::multiplex_early`](crate::transport::upgrade::Builder::multiplex_early).
+    fn early_muxer(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+/// The peer ID of the remote of a connection, if known from the dialed address.
+fn remote_peer(endpoint: &ConnectedPoint) -> Option<PeerId> {
+    match endpoint {
+        ConnectedPoint::Dialer { address } => match address.iter().last() {
+            Some(Protocol::P2p(hash)) => PeerId::from_multihash(hash).ok(),
+            _ => None
+        },
+        ConnectedPoint::Listener { .. } => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity;
+
+    #[test]
+    fn remote_peer_of_dialed_address() {
+        let peer = identity::Keypair::generate_ed25519().public().into_peer_id();
+        let address = format!("/ip4/127.0.0.1/tcp/4001/p2p/{}", peer).parse().unwrap();
+        assert_eq!(remote_peer(&ConnectedPoint::Dialer { address }), Some(peer));
+
+        let address = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        assert_eq!(remote_peer(&ConnectedPoint::Dialer { address }), None);
+
+        let endpoint = ConnectedPoint::Listener {
+            local_addr: "/ip4/127.0.0.1/tcp/4001".parse().unwrap(),
+            send_back_addr: "/ip4/127.0.0.1/tcp/4002".parse().unwrap()
+        };
+        assert_eq!(remote_peer(&endpoint), None);
+    }
+
+    #[test]
+    fn unsupported_protocols_are_not_offered() {
+        struct Supported;
+
+        impl UpgradeInfo for Supported {
+            type Info = &'static [u8];
+            type InfoIter = std::vec::IntoIter<Self::Info>;
+
+            fn protocol_info(&self) -> Self::InfoIter {
+                vec![&b"/a/1.0.0"[..], &b"/b/1.0.0"[..]].into_iter()
+            }
+        }
+
+        let endpoint = ConnectedPoint::Dialer { address: "/ip4/127.0.0.1/tcp/4001".parse().unwrap() };
+        let selector = |_: &ConnectedPoint, _: Option<&PeerId>, _: Vec<&'static [u8]>| {
+            vec![&b"/c/1.0.0"[..], &b"/b/1.0.0"[..]]
+        };
+        let selected = SelectedSecurity::new(Supported, &selector, &endpoint);
+        assert_eq!(selected.protocol_info().collect::<Vec<_>>(), vec![&b"/b/1.0.0"[..]]);
+    }
+}
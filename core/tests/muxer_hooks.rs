@@ -0,0 +1,158 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::{channel::oneshot, prelude::*};
+use libp2p_core::Endpoint;
+use libp2p_core::muxing::{self, HookedMuxer, SubstreamEvent};
+use libp2p_core::transport::{Transport, MemoryTransport};
+use libp2p_core::upgrade::{self, UpgradeInfo, InboundUpgrade, OutboundUpgrade};
+use libp2p_mplex::MplexConfig;
+use multiaddr::{Multiaddr, Protocol};
+use rand::random;
+use std::{io, pin::Pin, sync::{Arc, Mutex}};
+
+#[derive(Clone)]
+struct HelloUpgrade {}
+
+impl UpgradeInfo for HelloUpgrade {
+    type Info = &'static str;
+    type InfoIter = std::iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        std::iter::once("/hello/1")
+    }
+}
+
+impl<C> InboundUpgrade<C> for HelloUpgrade
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + 'static
+{
+    type Output = C;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
+
+    fn upgrade_inbound(self, mut socket: C, _: Self::Info) -> Self::Future {
+        Box::pin(async move {
+            let mut buf = [0u8; 5];
+            socket.read_exact(&mut buf).await?;
+            assert_eq!(&buf[..], "hello".as_bytes());
+            Ok(socket)
+        })
+    }
+}
+
+impl<C> OutboundUpgrade<C> for HelloUpgrade
+where
+    C: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+{
+    type Output = C;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
+
+    fn upgrade_outbound(self, mut socket: C, _: Self::Info) -> Self::Future {
+        Box::pin(async move {
+            socket.write_all(b"hello").await?;
+            socket.flush().await?;
+            Ok(socket)
+        })
+    }
+}
+
+/// An owned [`SubstreamEvent`].
+#[derive(Debug, PartialEq, Eq)]
+enum Event {
+    Opened(u64, Endpoint),
+    Negotiated(u64, Endpoint, Vec<u8>),
+    Closed(u64, Endpoint, Option<Vec<u8>>),
+}
+
+impl From<&SubstreamEvent<'_>> for Event {
+    fn from(event: &SubstreamEvent) -> Self {
+        match *event {
+            SubstreamEvent::Opened { id, endpoint } => Event::Opened(id, endpoint),
+            SubstreamEvent::Negotiated { id, endpoint, protocol } =>
+                Event::Negotiated(id, endpoint, protocol.to_vec()),
+            SubstreamEvent::Closed { id, endpoint, protocol } =>
+                Event::Closed(id, endpoint, protocol.map(|p| p.to_vec())),
+        }
+    }
+}
+
+#[test]
+fn substream_events() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let transport = {
+        let events = events.clone();
+        MemoryTransport::default()
+            .and_then(|conn, endpoint| {
+                if endpoint.is_dialer() {
+                    upgrade::apply_outbound(conn, MplexConfig::new(), upgrade::Version::V1).left_future()
+                } else {
+                    upgrade::apply_inbound(conn, MplexConfig::new()).right_future()
+                }
+            })
+            .map(move |mplex, _| {
+                let events = events.clone();
+                Arc::new(HookedMuxer::new(mplex, move |event: &SubstreamEvent| {
+                    events.lock().unwrap().push(Event::from(event))
+                }))
+            })
+    };
+
+    let listen_addr1 = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let listen_addr2 = listen_addr1.clone();
+
+    let listener = transport.clone().listen_on(listen_addr1).unwrap();
+    let (done_tx, done_rx) = oneshot::channel();
+
+    let server = async move {
+        let muxer = listener
+            .filter_map(|ev| future::ready(ev.unwrap().into_upgrade()))
+            .next().await.unwrap().0.await.unwrap();
+        let substream = muxing::inbound_from_ref_and_wrap(muxer.clone()).await.unwrap();
+        let substream = upgrade::apply_inbound(substream, HelloUpgrade {}).await.unwrap();
+        drop(substream);
+        done_tx.send(()).unwrap();
+        muxer
+    };
+
+    let client = async move {
+        let muxer = transport.dial(listen_addr2).unwrap().await.unwrap();
+        let substream = muxing::outbound_from_ref_and_wrap(muxer.clone()).await.unwrap();
+        let substream = upgrade::apply_outbound(substream, HelloUpgrade {}, upgrade::Version::V1).await.unwrap();
+        done_rx.await.unwrap();
+        drop(substream);
+        muxer
+    };
+
+    let (_server, _client) = async_std::task::block_on(future::join(server, client));
+
+    // The events of both muxers, in the order of the negotiation.
+    let protocol = b"/hello/1".to_vec();
+    assert_eq!(*events.lock().unwrap(), vec![
+        Event::Opened(0, Endpoint::Dialer),
+        Event::Opened(0, Endpoint::Listener),
+        Event::Negotiated(0, Endpoint::Listener, protocol.clone()),
+        Event::Negotiated(0, Endpoint::Dialer, protocol.clone()),
+        Event::Closed(0, Endpoint::Listener, Some(protocol.clone())),
+        Event::Closed(0, Endpoint::Dialer, Some(protocol)),
+    ]);
+}
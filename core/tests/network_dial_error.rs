@@ -23,7 +23,10 @@ mod util;
 use futures::prelude::*;
 use libp2p_core::identity;
 use libp2p_core::multiaddr::multiaddr;
-use libp2p_core::nodes::network::{Network, NetworkEvent, NetworkReachError, PeerState, UnknownPeerDialErr, IncomingError};
+use libp2p_core::nodes::network::{
+    ConnectionLimit, ConnectionLimits, DialOpts, Network, NetworkEvent, NetworkReachError, PeerState,
+    UnknownPeerDialErr, IncomingError
+};
 use libp2p_core::{PeerId, Transport, upgrade};
 use libp2p_swarm::{
     ProtocolsHandler,
@@ -34,7 +37,7 @@ use libp2p_swarm::{
     protocols_handler::NodeHandlerWrapperBuilder
 };
 use rand::seq::SliceRandom;
-use std::{io, task::Context, task::Poll};
+use std::{io, num::NonZeroU8, task::Context, task::Poll, time::Duration};
 
 // TODO: replace with DummyProtocolsHandler after https://github.com/servo/rust-smallvec/issues/139 ?
 struct TestHandler<TSubstream>(std::marker::PhantomData<TSubstream>);
@@ -314,3 +317,280 @@ fn multiple_addresses_err() {
         }
     })).unwrap();
 }
+
+#[test]
+fn concurrent_addresses_err() {
+    // Dials multiple addresses concurrently, and makes sure there's still one dialing error per
+    // address.
+
+    let mut swarm = {
+        let local_key = identity::Keypair::generate_ed25519();
+        let local_public_key = local_key.public();
+        let transport = libp2p_tcp::TcpConfig::new()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(libp2p_secio::SecioConfig::new(local_key))
+            .multiplex(libp2p_mplex::MplexConfig::new());
+        Network::new(transport, local_public_key.into())
+    };
+
+    let mut addresses = Vec::new();
+    for _ in 0 .. 3 {
+        addresses.push(multiaddr![Ip4([0, 0, 0, 0]), Tcp(rand::random::<u16>())]);
+    }
+    for _ in 0 .. 5 {
+        addresses.push(multiaddr![Udp(rand::random::<u16>())]);
+    }
+    addresses.shuffle(&mut rand::thread_rng());
+
+    let target = PeerId::random();
+    let opts = DialOpts::new().concurrency(NonZeroU8::new(3).unwrap());
+    swarm.peer(target.clone())
+        .into_not_connected().unwrap()
+        .connect_iter_with_opts(addresses.clone(), TestHandler::default().into_node_handler_builder(), opts)
+        .unwrap();
+
+    async_std::task::block_on(future::poll_fn(|cx| -> Poll<Result<(), io::Error>> {
+        loop {
+            match swarm.poll(cx) {
+                Poll::Ready(NetworkEvent::DialError {
+                    new_state,
+                    peer_id,
+                    multiaddr,
+                    error: NetworkReachError::Transport(_)
+                }) => {
+                    assert_eq!(peer_id, target);
+                    let pos = addresses.iter().position(|a| *a == multiaddr)
+                        .expect("Each address is reported once");
+                    addresses.remove(pos);
+                    if addresses.is_empty() {
+                        assert_eq!(new_state, PeerState::NotConnected);
+                        return Poll::Ready(Ok(()));
+                    } else {
+                        match new_state {
+                            PeerState::Dialing { num_pending_addresses } => {
+                                assert_eq!(num_pending_addresses.get(), addresses.len());
+                            },
+                            _ => panic!()
+                        }
+                    }
+                },
+                Poll::Ready(_) => unreachable!(),
+                Poll::Pending => break Poll::Pending,
+            }
+        }
+    })).unwrap();
+}
+
+#[test]
+fn cancel_dial() {
+    // Cancels the dial of a peer that never completes the handshake, and makes sure the
+    // remaining addresses are not attempted.
+
+    let mut swarm = {
+        let local_key = identity::Keypair::generate_ed25519();
+        let local_public_key = local_key.public();
+        let transport = libp2p_tcp::TcpConfig::new()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(libp2p_secio::SecioConfig::new(local_key))
+            .multiplex(libp2p_mplex::MplexConfig::new());
+        Network::new(transport, local_public_key.into())
+    };
+
+    // Connections are queued by the kernel, but never accepted.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let address = multiaddr![Ip4([127, 0, 0, 1]), Tcp(port)];
+
+    let target = PeerId::random();
+    let handle = swarm.peer(target.clone())
+        .into_not_connected().unwrap()
+        .connect_iter_with_opts(
+            vec![address.clone(), address.clone()],
+            TestHandler::default().into_node_handler_builder(),
+            DialOpts::new()
+        )
+        .unwrap()
+        .dial_handle();
+
+    let mut cancelled = false;
+    async_std::task::block_on(future::poll_fn(|cx| -> Poll<Result<(), io::Error>> {
+        if !cancelled {
+            assert!(swarm.poll(cx).is_pending());
+            handle.cancel();
+            cancelled = true;
+        }
+        match swarm.poll(cx) {
+            Poll::Ready(NetworkEvent::DialError {
+                new_state: PeerState::NotConnected,
+                peer_id,
+                multiaddr,
+                error: NetworkReachError::Cancelled
+            }) => {
+                assert_eq!(peer_id, target);
+                assert_eq!(multiaddr, address);
+                Poll::Ready(Ok(()))
+            },
+            Poll::Ready(ev) => panic!("Unexpected event: {:?}", ev),
+            Poll::Pending => Poll::Pending,
+        }
+    })).unwrap();
+
+    assert!(swarm.peer(target).into_not_connected().is_some());
+    drop(listener);
+}
+
+#[test]
+fn dial_timeout() {
+    // Dials an address that never completes the handshake, and makes sure the dial times out.
+
+    let mut swarm: Network<_, _, _, NodeHandlerWrapperBuilder<TestHandler<_>>, _> = {
+        let local_key = identity::Keypair::generate_ed25519();
+        let local_public_key = local_key.public();
+        let transport = libp2p_tcp::TcpConfig::new()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(libp2p_secio::SecioConfig::new(local_key))
+            .multiplex(libp2p_mplex::MplexConfig::new());
+        Network::new(transport, local_public_key.into())
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let address = multiaddr![Ip4([127, 0, 0, 1]), Tcp(port)];
+
+    let opts = DialOpts::new().timeout(Duration::from_millis(100));
+    swarm.dial_with_opts(address.clone(), TestHandler::default().into_node_handler_builder(), opts)
+        .unwrap();
+
+    async_std::task::block_on(future::poll_fn(|cx| -> Poll<Result<(), io::Error>> {
+        match swarm.poll(cx) {
+            Poll::Ready(NetworkEvent::UnknownPeerDialError {
+                multiaddr,
+                error: UnknownPeerDialErr::Timeout,
+                handler: _
+            }) => {
+                assert_eq!(multiaddr, address);
+                Poll::Ready(Ok(()))
+            },
+            Poll::Ready(ev) => panic!("Unexpected event: {:?}", ev),
+            Poll::Pending => Poll::Pending,
+        }
+    })).unwrap();
+
+    drop(listener);
+}
+
+#[test]
+fn dial_denied_by_pending_dials_limit() {
+    // Dials two addresses with a limit of one pending dial, and makes sure the second dial is
+    // denied.
+
+    let mut swarm: Network<_, _, _, NodeHandlerWrapperBuilder<TestHandler<_>>, _> = {
+        let local_key = identity::Keypair::generate_ed25519();
+        let local_public_key = local_key.public();
+        let transport = libp2p_tcp::TcpConfig::new()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(libp2p_secio::SecioConfig::new(local_key))
+            .multiplex(libp2p_mplex::MplexConfig::new());
+        Network::new(transport, local_public_key.into())
+    };
+    swarm.set_connection_limits(ConnectionLimits::new().max_pending_dials(1));
+
+    // The listener never completes the handshake, keeping the first dial pending.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let address = multiaddr![Ip4([127, 0, 0, 1]), Tcp(port)];
+
+    swarm.dial(address.clone(), TestHandler::default().into_node_handler_builder()).unwrap();
+    swarm.dial(address.clone(), TestHandler::default().into_node_handler_builder()).unwrap();
+
+    async_std::task::block_on(future::poll_fn(|cx| -> Poll<Result<(), io::Error>> {
+        match swarm.poll(cx) {
+            Poll::Ready(NetworkEvent::UnknownPeerDialError {
+                multiaddr,
+                error: UnknownPeerDialErr::ConnectionDenied(ConnectionLimit::PendingDials(1)),
+                handler: _
+            }) => {
+                assert_eq!(multiaddr, address);
+                Poll::Ready(Ok(()))
+            },
+            Poll::Ready(ev) => panic!("Unexpected event: {:?}", ev),
+            Poll::Pending => Poll::Pending,
+        }
+    })).unwrap();
+
+    assert_eq!(swarm.unknown_dials().count(), 1);
+    drop(listener);
+}
+
+#[test]
+fn incoming_denied_by_subnet_limit() {
+    // Dials a network accepting no connections from the IP address of the dialer, and makes
+    // sure the incoming connection is denied.
+
+    let mut swarm1: Network<_, _, _, NodeHandlerWrapperBuilder<TestHandler<_>>, _> = {
+        let local_key = identity::Keypair::generate_ed25519();
+        let local_public_key = local_key.public();
+        let transport = libp2p_tcp::TcpConfig::new()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(libp2p_secio::SecioConfig::new(local_key))
+            .multiplex(libp2p_mplex::MplexConfig::new());
+        Network::new(transport, local_public_key.into())
+    };
+    swarm1.set_connection_limits(ConnectionLimits::new().max_per_ip(0));
+
+    let mut swarm2 = {
+        let local_key = identity::Keypair::generate_ed25519();
+        let local_public_key = local_key.public();
+        let transport = libp2p_tcp::TcpConfig::new()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(libp2p_secio::SecioConfig::new(local_key))
+            .multiplex(libp2p_mplex::MplexConfig::new());
+        Network::new(transport, local_public_key.into())
+    };
+
+    swarm1.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+
+    let address = async_std::task::block_on(future::poll_fn(|cx| {
+        if let Poll::Ready(NetworkEvent::NewListenerAddress { listen_addr, .. }) = swarm1.poll(cx) {
+            Poll::Ready(listen_addr)
+        } else {
+            panic!("Was expecting the listen address to be reported")
+        }
+    }));
+
+    swarm2
+        .peer(swarm1.local_peer_id().clone())
+        .into_not_connected().unwrap()
+        .connect(address.clone(), TestHandler::default().into_node_handler_builder());
+
+    let mut denied = false;
+    async_std::task::block_on(future::poll_fn(|cx| -> Poll<Result<(), io::Error>> {
+        match swarm1.poll(cx) {
+            Poll::Ready(NetworkEvent::IncomingConnectionError {
+                local_addr,
+                error: IncomingError::ConnectionDenied(ConnectionLimit::Subnet(0)),
+                ..
+            }) => {
+                assert_eq!(local_addr, address);
+                denied = true;
+            },
+            Poll::Ready(ev) => panic!("Unexpected event: {:?}", ev),
+            Poll::Pending => (),
+        }
+
+        match swarm2.poll(cx) {
+            Poll::Ready(NetworkEvent::DialError {
+                new_state: PeerState::NotConnected,
+                error: NetworkReachError::Transport(_),
+                ..
+            }) => {
+                assert!(denied);
+                return Poll::Ready(Ok(()));
+            },
+            Poll::Ready(ev) => panic!("Unexpected event: {:?}", ev),
+            Poll::Pending => (),
+        }
+
+        Poll::Pending
+    })).unwrap();
+}
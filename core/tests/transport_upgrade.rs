@@ -21,14 +21,16 @@
 mod util;
 
 use futures::prelude::*;
-use libp2p_core::identity;
-use libp2p_core::transport::{Transport, MemoryTransport};
-use libp2p_core::upgrade::{self, UpgradeInfo, InboundUpgrade, OutboundUpgrade};
+use libp2p_core::{identity, muxing, ConnectedPoint, PeerId, either::EitherError};
+use libp2p_core::transport::{Transport, TransportError, ListenerEvent, MemoryTransport, upgrade::UpgradeTimeouts};
+use libp2p_core::upgrade::{
+    self, UpgradeInfo, InboundUpgrade, OutboundUpgrade, SecuredStream, SecurityStats, UpgradeError, UpgradeStage
+};
 use libp2p_mplex::MplexConfig;
 use libp2p_secio::SecioConfig;
 use multiaddr::{Multiaddr, Protocol};
 use rand::random;
-use std::{io, pin::Pin};
+use std::{io, pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll}, time::Duration};
 
 #[derive(Clone)]
 struct HelloUpgrade {}
@@ -77,11 +79,186 @@ where
     }
 }
 
+/// A fake security upgrade offering two protocols, which records the
+/// negotiated protocol and yields the configured remote peer ID.
+#[derive(Clone)]
+struct SecurityUpgrade {
+    remote: PeerId,
+    negotiated: Arc<Mutex<Vec<&'static str>>>
+}
+
+impl UpgradeInfo for SecurityUpgrade {
+    type Info = &'static str;
+    type InfoIter = std::vec::IntoIter<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        vec!["/security/1", "/security/2"].into_iter()
+    }
+}
+
+impl<C> InboundUpgrade<C> for SecurityUpgrade {
+    type Output = (PeerId, C);
+    type Error = io::Error;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: C, info: Self::Info) -> Self::Future {
+        self.negotiated.lock().unwrap().push(info);
+        future::ok((self.remote, socket))
+    }
+}
+
+impl<C> OutboundUpgrade<C> for SecurityUpgrade {
+    type Output = (PeerId, C);
+    type Error = io::Error;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: C, info: Self::Info) -> Self::Future {
+        self.negotiated.lock().unwrap().push(info);
+        future::ok((self.remote, socket))
+    }
+}
+
+/// A fake secured stream which agreed on mplex during the handshake,
+/// recording the bytes written to it.
+struct EarlyMuxed<C> {
+    io: C,
+    written: Arc<Mutex<Vec<u8>>>
+}
+
+impl<C> SecuredStream for EarlyMuxed<C> {
+    fn security_stats(&self) -> SecurityStats {
+        SecurityStats::default()
+    }
+
+    fn early_muxer(&self) -> Option<&[u8]> {
+        Some(b"/mplex/6.7.0")
+    }
+}
+
+impl<C: AsyncRead + Unpin> AsyncRead for EarlyMuxed<C> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl<C: AsyncWrite + Unpin> AsyncWrite for EarlyMuxed<C> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let n = futures::ready!(Pin::new(&mut self.io).poll_write(cx, buf))?;
+        self.written.lock().unwrap().extend_from_slice(&buf[.. n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_close(cx)
+    }
+}
+
+/// A fake security upgrade yielding an [`EarlyMuxed`] stream.
+#[derive(Clone)]
+struct EarlyMuxerSecurity {
+    remote: PeerId,
+    written: Arc<Mutex<Vec<u8>>>
+}
+
+impl UpgradeInfo for EarlyMuxerSecurity {
+    type Info = &'static str;
+    type InfoIter = std::iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        std::iter::once("/security/1")
+    }
+}
+
+impl<C> InboundUpgrade<C> for EarlyMuxerSecurity {
+    type Output = (PeerId, EarlyMuxed<C>);
+    type Error = io::Error;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, io: C, _: Self::Info) -> Self::Future {
+        future::ok((self.remote, EarlyMuxed { io, written: self.written }))
+    }
+}
+
+impl<C> OutboundUpgrade<C> for EarlyMuxerSecurity {
+    type Output = (PeerId, EarlyMuxed<C>);
+    type Error = io::Error;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, io: C, _: Self::Info) -> Self::Future {
+        future::ok((self.remote, EarlyMuxed { io, written: self.written }))
+    }
+}
+
+#[test]
+fn security_selector() {
+    let listener_id = PeerId::random();
+    let dialer_id = PeerId::random();
+    let negotiated = Arc::new(Mutex::new(Vec::new()));
+
+    let listener_security = SecurityUpgrade { remote: dialer_id.clone(), negotiated: negotiated.clone() };
+    let listener_transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate_with(listener_security, |endpoint: &ConnectedPoint, _: Option<&PeerId>, protocols: Vec<&'static str>| {
+            assert!(endpoint.is_listener());
+            protocols.into_iter().filter(|p| *p != "/security/2").collect()
+        })
+        .multiplex(MplexConfig::default())
+        .and_then(|(peer, mplex), _| {
+            util::CloseMuxer::new(mplex).map_ok(move |mplex| (peer, mplex))
+        });
+
+    let dialer_security = SecurityUpgrade { remote: listener_id.clone(), negotiated: negotiated.clone() };
+    let dialer_transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate_with(dialer_security, |endpoint: &ConnectedPoint, remote: Option<&PeerId>, mut protocols: Vec<&'static str>| {
+            assert!(endpoint.is_dialer());
+            assert!(remote.is_none());
+            protocols.reverse();
+            protocols
+        })
+        .multiplex(MplexConfig::default())
+        .and_then(|(peer, mplex), _| {
+            util::CloseMuxer::new(mplex).map_ok(move |mplex| (peer, mplex))
+        });
+
+    let listen_addr1 = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let listen_addr2 = listen_addr1.clone();
+
+    let mut listener = listener_transport.listen_on(listen_addr1).unwrap();
+
+    let server = async move {
+        loop {
+            let (upgrade, _remote_addr) =
+                match listener.next().await.unwrap().unwrap().into_upgrade() {
+                    Some(u) => u,
+                    None => continue
+                };
+            let (peer, _mplex) = upgrade.await.unwrap();
+            assert_eq!(peer, dialer_id);
+            return
+        }
+    };
+
+    let client = async move {
+        let (peer, _mplex) = dialer_transport.dial(listen_addr2).unwrap().await.unwrap();
+        assert_eq!(peer, listener_id);
+    };
+
+    async_std::task::block_on(future::join(server, client));
+
+    // The dialer prefers the second protocol, which is refused by the listener.
+    assert_eq!(*negotiated.lock().unwrap(), vec!["/security/1", "/security/1"]);
+}
+
 #[test]
 fn upgrade_pipeline() {
     let listener_keys = identity::Keypair::generate_ed25519();
     let listener_id = listener_keys.public().into_peer_id();
-    let listener_transport = MemoryTransport::default()
+    let listener_transport = MemoryTransport
         .upgrade(upgrade::Version::V1)
         .authenticate(SecioConfig::new(listener_keys))
         .apply(HelloUpgrade {})
@@ -96,7 +273,7 @@ fn upgrade_pipeline() {
 
     let dialer_keys = identity::Keypair::generate_ed25519();
     let dialer_id = dialer_keys.public().into_peer_id();
-    let dialer_transport = MemoryTransport::default()
+    let dialer_transport = MemoryTransport
         .upgrade(upgrade::Version::V1)
         .authenticate(SecioConfig::new(dialer_keys))
         .apply(HelloUpgrade {})
@@ -135,3 +312,358 @@ fn upgrade_pipeline() {
     async_std::task::block_on(client);
 }
 
+
+#[test]
+fn native_multiplexing() {
+    let listener_id = PeerId::random();
+    let dialer_id = PeerId::random();
+
+    // Connections multiplexed with mplex without any negotiation, like those
+    // of a natively multiplexed transport, authenticated as `remote`.
+    let native_transport = |remote: PeerId| {
+        MemoryTransport.and_then(move |conn, endpoint| {
+            let mplex = if endpoint.is_dialer() {
+                OutboundUpgrade::upgrade_outbound(MplexConfig::new(), conn, b"/mplex/6.7.0")
+            } else {
+                InboundUpgrade::upgrade_inbound(MplexConfig::new(), conn, b"/mplex/6.7.0")
+            };
+            mplex.map_ok(move |mplex| (remote, mplex))
+        })
+    };
+
+    let listener_transport = native_transport(dialer_id.clone())
+        .upgrade(upgrade::Version::V1)
+        .multiplex_native();
+    let dialer_transport = native_transport(listener_id.clone())
+        .upgrade(upgrade::Version::V1)
+        .multiplex_native();
+
+    let listen_addr1 = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let listen_addr2 = listen_addr1.clone();
+
+    let mut listener = listener_transport.listen_on(listen_addr1).unwrap();
+
+    let server = async move {
+        loop {
+            let (upgrade, _remote_addr) =
+                match listener.next().await.unwrap().unwrap().into_upgrade() {
+                    Some(u) => u,
+                    None => continue
+                };
+            let (peer, mplex) = upgrade.await.unwrap();
+            assert_eq!(peer, dialer_id);
+            let mut substream = muxing::inbound_from_ref_and_wrap(Arc::new(mplex)).await.unwrap();
+            let mut buf = [0u8; 5];
+            substream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+            return
+        }
+    };
+
+    let client = async move {
+        let (peer, mplex) = dialer_transport.dial(listen_addr2).unwrap().await.unwrap();
+        assert_eq!(peer, listener_id);
+        let mut substream = muxing::outbound_from_ref_and_wrap(Arc::new(mplex)).await.unwrap();
+        substream.write_all(b"hello").await.unwrap();
+        substream.flush().await.unwrap();
+        substream
+    };
+
+    let (_, _substream) = async_std::task::block_on(future::join(server, client));
+}
+
+#[test]
+fn early_multiplexer_negotiation() {
+    let listener_id = PeerId::random();
+    let dialer_id = PeerId::random();
+    let written = Arc::new(Mutex::new(Vec::new()));
+
+    let listener_security = EarlyMuxerSecurity { remote: dialer_id.clone(), written: written.clone() };
+    let listener_transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(listener_security)
+        .multiplex_early(MplexConfig::default());
+
+    let dialer_security = EarlyMuxerSecurity { remote: listener_id.clone(), written: written.clone() };
+    let dialer_transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(dialer_security)
+        .multiplex_early(MplexConfig::default());
+
+    let listen_addr1 = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let listen_addr2 = listen_addr1.clone();
+
+    let mut listener = listener_transport.listen_on(listen_addr1).unwrap();
+
+    let server = async move {
+        loop {
+            let (upgrade, _remote_addr) =
+                match listener.next().await.unwrap().unwrap().into_upgrade() {
+                    Some(u) => u,
+                    None => continue
+                };
+            let (peer, mplex) = upgrade.await.unwrap();
+            assert_eq!(peer, dialer_id);
+            let mut substream = muxing::inbound_from_ref_and_wrap(Arc::new(mplex)).await.unwrap();
+            let mut buf = [0u8; 5];
+            substream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+            return
+        }
+    };
+
+    let client = async move {
+        let (peer, mplex) = dialer_transport.dial(listen_addr2).unwrap().await.unwrap();
+        assert_eq!(peer, listener_id);
+        let mut substream = muxing::outbound_from_ref_and_wrap(Arc::new(mplex)).await.unwrap();
+        substream.write_all(b"hello").await.unwrap();
+        substream.flush().await.unwrap();
+        substream
+    };
+
+    let (_, _substream) = async_std::task::block_on(future::join(server, client));
+
+    // Only the security protocol has been negotiated on the connections.
+    let written = written.lock().unwrap();
+    assert!(!written.windows(12).any(|w| w == b"/mplex/6.7.0"));
+}
+
+/// A transport "dialing" a connection that is already established, such that
+/// both ends of the connection are dialers, like after a simultaneous open.
+struct Established<C>(Option<C>);
+
+impl<C> Transport for Established<C> {
+    type Output = C;
+    type Error = io::Error;
+    type Listener = stream::Pending<Result<ListenerEvent<Self::ListenerUpgrade>, io::Error>>;
+    type ListenerUpgrade = future::Pending<Result<C, io::Error>>;
+    type Dial = future::Ready<Result<C, io::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let conn = self.0.ok_or(TransportError::MultiaddrNotSupported(addr))?;
+        Ok(future::ok(conn))
+    }
+}
+
+#[test]
+fn simultaneous_open() {
+    let keys1 = identity::Keypair::generate_ed25519();
+    let keys2 = identity::Keypair::generate_ed25519();
+    let id1 = keys1.public().into_peer_id();
+    let id2 = keys2.public().into_peer_id();
+
+    let addr = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let mut listener = MemoryTransport.listen_on(addr.clone()).unwrap();
+    let dial = MemoryTransport.dial(addr.clone()).unwrap();
+    let accept = async move {
+        loop {
+            if let Some((upgrade, _)) = listener.next().await.unwrap().unwrap().into_upgrade() {
+                return upgrade.await.unwrap()
+            }
+        }
+    };
+    let (conn1, conn2) = async_std::task::block_on(future::join(dial, accept));
+
+    // Both peers dial the connection. The hello upgrade only succeeds if
+    // exactly one of them writes and the other reads.
+    let transport = |conn, keys| {
+        Established(Some(conn))
+            .upgrade(upgrade::Version::V1SimultaneousOpen)
+            .authenticate(SecioConfig::new(keys))
+            .apply(HelloUpgrade {})
+            .multiplex(MplexConfig::default())
+    };
+    let dial1 = transport(conn1.unwrap(), keys1).dial(addr.clone()).unwrap();
+    let dial2 = transport(conn2, keys2).dial(addr).unwrap();
+
+    let server = async move {
+        let (peer, mplex) = dial2.await.unwrap();
+        assert_eq!(peer, id1);
+        let mut substream = muxing::inbound_from_ref_and_wrap(Arc::new(mplex)).await.unwrap();
+        let mut buf = [0u8; 5];
+        substream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    };
+
+    let client = async move {
+        let (peer, mplex) = dial1.await.unwrap();
+        assert_eq!(peer, id2);
+        let mut substream = muxing::outbound_from_ref_and_wrap(Arc::new(mplex)).await.unwrap();
+        substream.write_all(b"hello").await.unwrap();
+        substream.flush().await.unwrap();
+        substream
+    };
+
+    let (_, _substream) = async_std::task::block_on(future::join(server, client));
+}
+
+#[test]
+fn simultaneous_open_dialer_with_listener() {
+    let listener_keys = identity::Keypair::generate_ed25519();
+    let listener_id = listener_keys.public().into_peer_id();
+    let listener_transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(SecioConfig::new(listener_keys))
+        .apply(HelloUpgrade {})
+        .multiplex(MplexConfig::default());
+
+    let dialer_keys = identity::Keypair::generate_ed25519();
+    let dialer_id = dialer_keys.public().into_peer_id();
+    let dialer_transport = MemoryTransport
+        .upgrade(upgrade::Version::V1SimultaneousOpen)
+        .authenticate(SecioConfig::new(dialer_keys))
+        .apply(HelloUpgrade {})
+        .multiplex(MplexConfig::default());
+
+    let listen_addr1 = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let listen_addr2 = listen_addr1.clone();
+
+    let mut listener = listener_transport.listen_on(listen_addr1).unwrap();
+
+    let server = async move {
+        loop {
+            let (upgrade, _remote_addr) =
+                match listener.next().await.unwrap().unwrap().into_upgrade() {
+                    Some(u) => u,
+                    None => continue
+                };
+            let (peer, mplex) = upgrade.await.unwrap();
+            assert_eq!(peer, dialer_id);
+            let mut substream = muxing::inbound_from_ref_and_wrap(Arc::new(mplex)).await.unwrap();
+            let mut buf = [0u8; 5];
+            substream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+            return
+        }
+    };
+
+    let client = async move {
+        let (peer, mplex) = dialer_transport.dial(listen_addr2).unwrap().await.unwrap();
+        assert_eq!(peer, listener_id);
+        let mut substream = muxing::outbound_from_ref_and_wrap(Arc::new(mplex)).await.unwrap();
+        substream.write_all(b"hello").await.unwrap();
+        substream.flush().await.unwrap();
+        substream
+    };
+
+    let (_, _substream) = async_std::task::block_on(future::join(server, client));
+}
+
+/// A fake security upgrade whose handshake never completes.
+#[derive(Clone)]
+struct PendingSecurity;
+
+impl UpgradeInfo for PendingSecurity {
+    type Info = &'static str;
+    type InfoIter = std::iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        std::iter::once("/security/1")
+    }
+}
+
+impl<C> InboundUpgrade<C> for PendingSecurity {
+    type Output = (PeerId, C);
+    type Error = io::Error;
+    type Future = future::Pending<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, _: C, _: Self::Info) -> Self::Future {
+        future::pending()
+    }
+}
+
+impl<C> OutboundUpgrade<C> for PendingSecurity {
+    type Output = (PeerId, C);
+    type Error = io::Error;
+    type Future = future::Pending<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, _: C, _: Self::Info) -> Self::Future {
+        future::pending()
+    }
+}
+
+/// Dials a listener that stops responding after the upgrades of its transport,
+/// returning the error of the dial.
+fn dial_unresponsive<L, T>(listener_transport: L, dialer_transport: T) -> T::Error
+where
+    L: Transport,
+    L::Listener: Stream<Item = Result<ListenerEvent<L::ListenerUpgrade>, L::Error>>,
+    L::Error: std::fmt::Debug,
+    T: Transport,
+{
+    let listen_addr = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let mut listener = Box::pin(listener_transport.listen_on(listen_addr.clone()).unwrap());
+    let server = async move {
+        loop {
+            if let Some((upgrade, _)) = listener.next().await.unwrap().unwrap().into_upgrade() {
+                return upgrade.await.unwrap()
+            }
+        }
+    };
+    let client = async move {
+        match dialer_transport.dial(listen_addr).unwrap().await {
+            Ok(_) => panic!("Unexpected success"),
+            Err(err) => err
+        }
+    };
+    let (_conn, err) = async_std::task::block_on(future::join(server, client));
+    err
+}
+
+#[test]
+fn security_negotiation_timeout() {
+    let dialer_transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .timeouts(UpgradeTimeouts::new().security_negotiation(Duration::from_millis(50)))
+        .authenticate(SecurityUpgrade { remote: PeerId::random(), negotiated: Default::default() })
+        .multiplex(MplexConfig::default());
+
+    match dial_unresponsive(MemoryTransport, dialer_transport) {
+        EitherError::A(EitherError::B(UpgradeError::Timeout(UpgradeStage::SecurityNegotiation))) => {}
+        e => panic!("Unexpected error: {:?}", e)
+    }
+}
+
+#[test]
+fn security_handshake_timeout() {
+    let dialer_transport = MemoryTransport
+        .upgrade(upgrade::Version::V1Lazy)
+        .timeouts(UpgradeTimeouts::new()
+            .security_negotiation(Duration::from_secs(10))
+            .security_handshake(Duration::from_millis(50)))
+        .authenticate(PendingSecurity)
+        .multiplex(MplexConfig::default());
+
+    match dial_unresponsive(MemoryTransport, dialer_transport) {
+        EitherError::A(EitherError::B(UpgradeError::Timeout(UpgradeStage::SecurityHandshake))) => {}
+        e => panic!("Unexpected error: {:?}", e)
+    }
+}
+
+#[test]
+fn muxer_negotiation_timeout() {
+    // The listener only negotiates the security protocol, flushing its
+    // confirmation which would otherwise wait for the next write.
+    let listener_transport = MemoryTransport.and_then(|conn, endpoint| {
+        let security = SecurityUpgrade { remote: PeerId::random(), negotiated: Default::default() };
+        upgrade::apply(conn, security, endpoint, upgrade::Version::V1)
+            .and_then(|(peer, mut conn)| async move {
+                conn.flush().await.map_err(UpgradeError::Apply)?;
+                Ok((peer, conn))
+            })
+    });
+    let dialer_transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .timeouts(UpgradeTimeouts::new().muxer_negotiation(Duration::from_millis(50)))
+        .authenticate(SecurityUpgrade { remote: PeerId::random(), negotiated: Default::default() })
+        .multiplex(MplexConfig::default());
+
+    match dial_unresponsive(listener_transport, dialer_transport) {
+        EitherError::B(UpgradeError::Timeout(UpgradeStage::MuxerNegotiation)) => {}
+        e => panic!("Unexpected error: {:?}", e)
+    }
+}
@@ -25,6 +25,7 @@ extern crate proc_macro;
 use quote::quote;
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, DeriveInput, Data, DataStruct, Ident};
+use syn::{ext::IdentExt, spanned::Spanned};
 
 /// Generates a delegating `NetworkBehaviour` implementation for the struct this is used for. See
 /// the trait documentation for better description.
@@ -58,6 +59,7 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
     let peer_id = quote!{::libp2p::core::PeerId};
     let connected_point = quote!{::libp2p::core::ConnectedPoint};
     let listener_id = quote!{::libp2p::core::nodes::ListenerId};
+    let address_filter = quote!{::libp2p::core::AddressFilter};
 
     // Name of the type parameter that represents the substream.
     let substream_generic = {
@@ -80,21 +82,81 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
         quote!{<#(#lf,)* #(#tp,)* #(#cst,)* #substream_generic>}
     };
 
+    // Whether the events of the children are processed by the struct.
+    // If we find a `#[behaviour(event_process = false)]` attribute on the struct, the events of
+    // the children are instead converted into the out event and returned from `poll()`.
+    let event_process = {
+        let mut out = true;
+        for meta_items in ast.attrs.iter().filter_map(get_meta_items) {
+            for meta_item in meta_items {
+                match meta_item {
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(ref m)) if m.path.is_ident("event_process") => {
+                        if let syn::Lit::Bool(ref b) = m.lit {
+                            out = b.value;
+                        }
+                    }
+                    _ => ()
+                }
+            }
+        }
+        out
+    };
+
+    // The out event specified by the user, if any.
+    // If we find a `#[behaviour(out_event = "Foo")]` attribute on the struct, we use `Foo`.
+    let custom_out_event = {
+        let mut out = None;
+        for meta_items in ast.attrs.iter().filter_map(get_meta_items) {
+            for meta_item in meta_items {
+                match meta_item {
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(ref m)) if m.path.is_ident("out_event") => {
+                        if let syn::Lit::Str(ref s) = m.lit {
+                            let ty: syn::Type = syn::parse_str(&s.value()).unwrap();
+                            out = Some(ty);
+                        }
+                    }
+                    _ => ()
+                }
+            }
+        }
+        out
+    };
+
+    // Name of the enum generated when `event_process` is `false` and no out event is specified.
+    let out_event_enum = Ident::new(&format!("{}Event", name), name.span());
+
+    // The final out event.
+    // This is the out event specified by the user, or else the generated enum if the events are
+    // not processed by the struct. Otherwise we use `()`.
+    let out_event = match custom_out_event {
+        Some(ref ty) => quote!{#ty},
+        None if !event_process => quote!{#out_event_enum #ty_generics},
+        None => quote!{()},
+    };
+
     // Build the `where ...` clause of the trait implementation.
     let where_clause = {
         let mut additional = data_struct.fields.iter()
             .filter(|x| !is_ignored(x))
             .flat_map(|field| {
                 let ty = &field.ty;
+                let event_bound = if event_process {
+                    quote!{Self: #net_behv_event_proc<<#ty as #trait_to_impl>::OutEvent>}
+                } else if custom_out_event.is_some() {
+                    quote!{#out_event: From<<#ty as #trait_to_impl>::OutEvent>}
+                } else {
+                    quote!{}
+                };
                 vec![
                     quote!{#ty: #trait_to_impl},
-                    quote!{Self: #net_behv_event_proc<<#ty as #trait_to_impl>::OutEvent>},
+                    event_bound,
                     quote!{<<#ty as #trait_to_impl>::ProtocolsHandler as #into_protocols_handler>::Handler: #protocols_handler<Substream = #substream_generic>},
                     // Note: this bound is required because of https://github.com/rust-lang/rust/issues/55697
                     quote!{<<<#ty as #trait_to_impl>::ProtocolsHandler as #into_protocols_handler>::Handler as #protocols_handler>::InboundProtocol: ::libp2p::core::InboundUpgrade<::libp2p::core::Negotiated<#substream_generic>>},
                     quote!{<<<#ty as #trait_to_impl>::ProtocolsHandler as #into_protocols_handler>::Handler as #protocols_handler>::OutboundProtocol: ::libp2p::core::OutboundUpgrade<::libp2p::core::Negotiated<#substream_generic>>},
                 ]
             })
+            .filter(|bound| !bound.is_empty())
             .collect::<Vec<_>>();
 
         additional.push(quote!{#substream_generic: ::libp2p::futures::io::AsyncRead});
@@ -112,58 +174,39 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
         }
     };
 
-    // The final out event.
-    // If we find a `#[behaviour(out_event = "Foo")]` attribute on the struct, we set `Foo` as
-    // the out event. Otherwise we use `()`.
-    let out_event = {
-        let mut out = quote!{()};
-        for meta_items in ast.attrs.iter().filter_map(get_meta_items) {
-            for meta_item in meta_items {
-                match meta_item {
-                    syn::NestedMeta::Meta(syn::Meta::NameValue(ref m)) if m.path.is_ident("out_event") => {
-                        if let syn::Lit::Str(ref s) = m.lit {
-                            let ident: syn::Type = syn::parse_str(&s.value()).unwrap();
-                            out = quote!{#ident};
-                        }
-                    }
-                    _ => ()
-                }
-            }
-        }
-        out
-    };
-
     // Build the list of statements to put in the body of `addresses_of_peer()`.
     let addresses_of_peer_stmts = {
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
             if is_ignored(&field) {
                 return None;
             }
 
             Some(match field.ident {
                 Some(ref i) => quote!{ out.extend(self.#i.addresses_of_peer(peer_id)); },
-                None => quote!{ out.extend(self.#field_n.addresses_of_peer(peer_id)); },
+                None => quote!{ out.extend(self.#field_idx.addresses_of_peer(peer_id)); },
             })
         })
     };
 
     // Build the list of statements to put in the body of `inject_connected()`.
     let inject_connected_stmts = {
-        let num_fields = data_struct.fields.iter().filter(|f| !is_ignored(f)).count();
+        let last_field_n = data_struct.fields.iter().rposition(|f| !is_ignored(f));
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
             if is_ignored(&field) {
                 return None;
             }
 
-            Some(if field_n == num_fields - 1 {
+            Some(if Some(field_n) == last_field_n {
                 match field.ident {
                     Some(ref i) => quote!{ self.#i.inject_connected(peer_id, endpoint); },
-                    None => quote!{ self.#field_n.inject_connected(peer_id, endpoint); },
+                    None => quote!{ self.#field_idx.inject_connected(peer_id, endpoint); },
                 }
             } else {
                 match field.ident {
                     Some(ref i) => quote!{ self.#i.inject_connected(peer_id.clone(), endpoint.clone()); },
-                    None => quote!{ self.#field_n.inject_connected(peer_id.clone(), endpoint.clone()); },
+                    None => quote!{ self.#field_idx.inject_connected(peer_id.clone(), endpoint.clone()); },
                 }
             })
         })
@@ -171,21 +214,22 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
 
     // Build the list of statements to put in the body of `inject_disconnected()`.
     let inject_disconnected_stmts = {
-        let num_fields = data_struct.fields.iter().filter(|f| !is_ignored(f)).count();
+        let last_field_n = data_struct.fields.iter().rposition(|f| !is_ignored(f));
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
             if is_ignored(&field) {
                 return None;
             }
 
-            Some(if field_n == num_fields - 1 {
+            Some(if Some(field_n) == last_field_n {
                 match field.ident {
                     Some(ref i) => quote!{ self.#i.inject_disconnected(peer_id, endpoint); },
-                    None => quote!{ self.#field_n.inject_disconnected(peer_id, endpoint); },
+                    None => quote!{ self.#field_idx.inject_disconnected(peer_id, endpoint); },
                 }
             } else {
                 match field.ident {
                     Some(ref i) => quote!{ self.#i.inject_disconnected(peer_id, endpoint.clone()); },
-                    None => quote!{ self.#field_n.inject_disconnected(peer_id, endpoint.clone()); },
+                    None => quote!{ self.#field_idx.inject_disconnected(peer_id, endpoint.clone()); },
                 }
             })
         })
@@ -193,16 +237,17 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
 
     // Build the list of statements to put in the body of `inject_replaced()`.
     let inject_replaced_stmts = {
-        let num_fields = data_struct.fields.iter().filter(|f| !is_ignored(f)).count();
+        let last_field_n = data_struct.fields.iter().rposition(|f| !is_ignored(f));
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
             if is_ignored(&field) {
                 return None;
             }
 
-            Some(if field_n == num_fields - 1 {
+            Some(if Some(field_n) == last_field_n {
                 match field.ident {
                     Some(ref i) => quote!{ self.#i.inject_replaced(peer_id, closed_endpoint, new_endpoint); },
-                    None => quote!{ self.#field_n.inject_replaced(peer_id, closed_endpoint, new_endpoint); },
+                    None => quote!{ self.#field_idx.inject_replaced(peer_id, closed_endpoint, new_endpoint); },
                 }
             } else {
                 match field.ident {
@@ -210,7 +255,7 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                         self.#i.inject_replaced(peer_id.clone(), closed_endpoint.clone(), new_endpoint.clone());
                     },
                     None => quote!{
-                        self.#field_n.inject_replaced(peer_id.clone(), closed_endpoint.clone(), new_endpoint.clone());
+                        self.#field_idx.inject_replaced(peer_id.clone(), closed_endpoint.clone(), new_endpoint.clone());
                     },
                 }
             })
@@ -220,13 +265,14 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
     // Build the list of statements to put in the body of `inject_addr_reach_failure()`.
     let inject_addr_reach_failure_stmts = {
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
             if is_ignored(&field) {
                 return None;
             }
 
             Some(match field.ident {
                 Some(ref i) => quote!{ self.#i.inject_addr_reach_failure(peer_id, addr, error); },
-                None => quote!{ self.#field_n.inject_addr_reach_failure(peer_id, addr, error); },
+                None => quote!{ self.#field_idx.inject_addr_reach_failure(peer_id, addr, error); },
             })
         })
     };
@@ -234,13 +280,14 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
     // Build the list of statements to put in the body of `inject_dial_failure()`.
     let inject_dial_failure_stmts = {
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
             if is_ignored(&field) {
                 return None;
             }
 
             Some(match field.ident {
                 Some(ref i) => quote!{ self.#i.inject_dial_failure(peer_id); },
-                None => quote!{ self.#field_n.inject_dial_failure(peer_id); },
+                None => quote!{ self.#field_idx.inject_dial_failure(peer_id); },
             })
         })
     };
@@ -248,13 +295,14 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
     // Build the list of statements to put in the body of `inject_new_listen_addr()`.
     let inject_new_listen_addr_stmts = {
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
             if is_ignored(&field) {
                 return None;
             }
 
             Some(match field.ident {
                 Some(ref i) => quote!{ self.#i.inject_new_listen_addr(addr); },
-                None => quote!{ self.#field_n.inject_new_listen_addr(addr); },
+                None => quote!{ self.#field_idx.inject_new_listen_addr(addr); },
             })
         })
     };
@@ -262,13 +310,14 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
     // Build the list of statements to put in the body of `inject_expired_listen_addr()`.
     let inject_expired_listen_addr_stmts = {
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
             if is_ignored(&field) {
                 return None;
             }
 
             Some(match field.ident {
                 Some(ref i) => quote!{ self.#i.inject_expired_listen_addr(addr); },
-                None => quote!{ self.#field_n.inject_expired_listen_addr(addr); },
+                None => quote!{ self.#field_idx.inject_expired_listen_addr(addr); },
             })
         })
     };
@@ -276,13 +325,29 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
     // Build the list of statements to put in the body of `inject_new_external_addr()`.
     let inject_new_external_addr_stmts = {
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
             if is_ignored(&field) {
                 return None;
             }
 
             Some(match field.ident {
                 Some(ref i) => quote!{ self.#i.inject_new_external_addr(addr); },
-                None => quote!{ self.#field_n.inject_new_external_addr(addr); },
+                None => quote!{ self.#field_idx.inject_new_external_addr(addr); },
+            })
+        })
+    };
+
+    // Build the list of statements to put in the body of `inject_expired_external_addr()`.
+    let inject_expired_external_addr_stmts = {
+        data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
+            if is_ignored(&field) {
+                return None;
+            }
+
+            Some(match field.ident {
+                Some(ref i) => quote!{ self.#i.inject_expired_external_addr(addr); },
+                None => quote!{ self.#field_idx.inject_expired_external_addr(addr); },
             })
         })
     };
@@ -290,12 +355,13 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
     // Build the list of statements to put in the body of `inject_listener_error()`.
     let inject_listener_error_stmts = {
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
             if is_ignored(&field) {
                 return None
             }
             Some(match field.ident {
                 Some(ref i) => quote!(self.#i.inject_listener_error(id, err);),
-                None => quote!(self.#field_n.inject_listener_error(id, err);)
+                None => quote!(self.#field_idx.inject_listener_error(id, err);)
             })
         })
     };
@@ -303,12 +369,41 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
     // Build the list of statements to put in the body of `inject_listener_closed()`.
     let inject_listener_closed_stmts = {
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
             if is_ignored(&field) {
                 return None
             }
             Some(match field.ident {
                 Some(ref i) => quote!(self.#i.inject_listener_closed(id);),
-                None => quote!(self.#field_n.inject_listener_closed(id);)
+                None => quote!(self.#field_idx.inject_listener_closed(id);)
+            })
+        })
+    };
+
+    // Build the list of statements to put in the body of `inject_address_filter()`.
+    let inject_address_filter_stmts = {
+        data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
+            if is_ignored(&field) {
+                return None
+            }
+            Some(match field.ident {
+                Some(ref i) => quote!(self.#i.inject_address_filter(filter);),
+                None => quote!(self.#field_idx.inject_address_filter(filter);)
+            })
+        })
+    };
+
+    // Build the list of statements to put in the body of `inject_shutdown()`.
+    let inject_shutdown_stmts = {
+        data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            let field_idx = syn::Index::from(field_n);
+            if is_ignored(&field) {
+                return None
+            }
+            Some(match field.ident {
+                Some(ref i) => quote!(self.#i.inject_shutdown();),
+                None => quote!(self.#field_idx.inject_shutdown();)
             })
         })
     };
@@ -318,19 +413,20 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
     // The event type is a construction of nested `#either_ident`s of the events of the children.
     // We call `inject_node_event` on the corresponding child.
     let inject_node_event_stmts = data_struct.fields.iter().enumerate().filter(|f| !is_ignored(&f.1)).enumerate().map(|(enum_n, (field_n, field))| {
+        let field_idx = syn::Index::from(field_n);
         let mut elem = if enum_n != 0 {
             quote!{ #either_ident::Second(ev) }
         } else {
             quote!{ ev }
         };
 
-        for _ in 0 .. data_struct.fields.iter().filter(|f| !is_ignored(f)).count() - 1 - field_n {
+        for _ in 0 .. data_struct.fields.iter().filter(|f| !is_ignored(f)).count() - 1 - enum_n {
             elem = quote!{ #either_ident::First(#elem) };
         }
 
         Some(match field.ident {
             Some(ref i) => quote!{ #elem => self.#i.inject_node_event(peer_id, ev) },
-            None => quote!{ #elem => self.#field_n.inject_node_event(peer_id, ev) },
+            None => quote!{ #elem => self.#field_idx.inject_node_event(peer_id, ev) },
         })
     });
 
@@ -360,10 +456,11 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
             if is_ignored(&field) {
                 continue;
             }
+            let field_idx = syn::Index::from(field_n);
 
             let field_name = match field.ident {
                 Some(ref i) => quote!{ self.#i },
-                None => quote!{ self.#field_n },
+                None => quote!{ self.#field_idx },
             };
 
             let builder = quote! {
@@ -404,9 +501,10 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
     //
     // We poll each child one by one and wrap around the output.
     let poll_stmts = data_struct.fields.iter().enumerate().filter(|f| !is_ignored(&f.1)).enumerate().map(|(enum_n, (field_n, field))| {
+        let field_idx = syn::Index::from(field_n);
         let field_name = match field.ident {
             Some(ref i) => quote!{ self.#i },
-            None => quote!{ self.#field_n },
+            None => quote!{ self.#field_idx },
         };
 
         let mut wrapped_event = if enum_n != 0 {
@@ -414,15 +512,24 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
         } else {
             quote!{ event }
         };
-        for _ in 0 .. data_struct.fields.iter().filter(|f| !is_ignored(f)).count() - 1 - field_n {
+        for _ in 0 .. data_struct.fields.iter().filter(|f| !is_ignored(f)).count() - 1 - enum_n {
             wrapped_event = quote!{ #either_ident::First(#wrapped_event) };
         }
 
+        let generate_event = if event_process {
+            quote!{ #net_behv_event_proc::inject_event(self, event) }
+        } else if custom_out_event.is_some() {
+            quote!{ return std::task::Poll::Ready(#network_behaviour_action::GenerateEvent(From::from(event))); }
+        } else {
+            let variant = variant_name(field, field_n);
+            quote!{ return std::task::Poll::Ready(#network_behaviour_action::GenerateEvent(#out_event_enum::#variant(event))); }
+        };
+
         Some(quote!{
             loop {
                 match #field_name.poll(cx, poll_params) {
                     std::task::Poll::Ready(#network_behaviour_action::GenerateEvent(event)) => {
-                        #net_behv_event_proc::inject_event(self, event)
+                        #generate_event
                     }
                     std::task::Poll::Ready(#network_behaviour_action::DialAddress { address }) => {
                         return std::task::Poll::Ready(#network_behaviour_action::DialAddress { address });
@@ -436,8 +543,11 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                             event: #wrapped_event,
                         });
                     }
-                    std::task::Poll::Ready(#network_behaviour_action::ReportObservedAddr { address }) => {
-                        return std::task::Poll::Ready(#network_behaviour_action::ReportObservedAddr { address });
+                    std::task::Poll::Ready(#network_behaviour_action::ReportObservedAddr { address, observer }) => {
+                        return std::task::Poll::Ready(#network_behaviour_action::ReportObservedAddr { address, observer });
+                    }
+                    std::task::Poll::Ready(#network_behaviour_action::DisconnectPeer { peer_id }) => {
+                        return std::task::Poll::Ready(#network_behaviour_action::DisconnectPeer { peer_id });
                     }
                     std::task::Poll::Pending => break,
                 }
@@ -445,6 +555,89 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
         })
     });
 
+    // The definition of the out event enum, if we have to generate it.
+    //
+    // The enum has one variant per child, wrapping the out event of that child. We also implement
+    // `From` for the out events of the children marked with `#[behaviour(event = "Foo")]`, as
+    // the out events of the children are otherwise only known through projections, which the
+    // coherence checker can't tell apart from the enum itself.
+    let out_event_def = if event_process || custom_out_event.is_some() {
+        quote!{}
+    } else {
+        let vis = &ast.vis;
+        let generics = &ast.generics;
+        let (enum_impl_generics, _, _) = ast.generics.split_for_impl();
+        let children = data_struct.fields.iter().enumerate()
+            .filter(|(_, f)| !is_ignored(f))
+            .map(|(field_n, field)| (variant_name(field, field_n), &field.ty))
+            .collect::<Vec<_>>();
+
+        let enum_where_clause = {
+            let additional = children.iter().map(|(_, ty)| quote!{#ty: #trait_to_impl});
+            match ast.generics.where_clause {
+                Some(ref w) if w.predicates.trailing_punct() => quote!{#w #(#additional),*},
+                Some(ref w) => quote!{#w, #(#additional),*},
+                None => quote!{where #(#additional),*},
+            }
+        };
+
+        let variants = children.iter().map(|(variant, ty)| {
+            quote!{ #variant(<#ty as #trait_to_impl>::OutEvent) }
+        });
+
+        let debug_where_clause = {
+            let additional = children.iter().map(|(_, ty)| {
+                quote!{<#ty as #trait_to_impl>::OutEvent: std::fmt::Debug}
+            });
+            quote!{#enum_where_clause, #(#additional),*}
+        };
+
+        let debug_arms = children.iter().map(|(variant, _)| {
+            let variant_str = variant.to_string();
+            quote!{ #out_event_enum::#variant(event) => f.debug_tuple(#variant_str).field(event).finish() }
+        });
+
+        let from_impls = data_struct.fields.iter().enumerate()
+            .filter(|(_, f)| !is_ignored(f))
+            .filter_map(|(field_n, field)| {
+                let event_ty = field_event_type(field)?;
+                let variant = variant_name(field, field_n);
+                let ty = &field.ty;
+                let from_where_clause = quote!{#enum_where_clause, #ty: #trait_to_impl<OutEvent = #event_ty>};
+                Some(quote!{
+                    impl #enum_impl_generics From<#event_ty> for #out_event_enum #ty_generics
+                    #from_where_clause
+                    {
+                        fn from(event: #event_ty) -> Self {
+                            #out_event_enum::#variant(event)
+                        }
+                    }
+                })
+            });
+
+        let doc = format!("Event generated by the `{}` network behaviour.", name);
+        quote!{
+            #[doc = #doc]
+            #vis enum #out_event_enum #generics
+            #enum_where_clause
+            {
+                #(#variants),*
+            }
+
+            impl #enum_impl_generics std::fmt::Debug for #out_event_enum #ty_generics
+            #debug_where_clause
+            {
+                fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    match self {
+                        #(#debug_arms),*
+                    }
+                }
+            }
+
+            #(#from_impls)*
+        }
+    };
+
     // Now the magic happens.
     let final_quote = quote!{
         impl #impl_generics #trait_to_impl for #name #ty_generics
@@ -496,6 +689,10 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                 #(#inject_new_external_addr_stmts);*
             }
 
+            fn inject_expired_external_addr(&mut self, addr: &#multiaddr) {
+                #(#inject_expired_external_addr_stmts);*
+            }
+
             fn inject_listener_error(&mut self, id: #listener_id, err: &(dyn std::error::Error + 'static)) {
                 #(#inject_listener_error_stmts);*
             }
@@ -504,6 +701,14 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                 #(#inject_listener_closed_stmts);*
             }
 
+            fn inject_address_filter(&mut self, filter: &#address_filter) {
+                #(#inject_address_filter_stmts);*
+            }
+
+            fn inject_shutdown(&mut self) {
+                #(#inject_shutdown_stmts);*
+            }
+
             fn inject_node_event(
                 &mut self,
                 peer_id: #peer_id,
@@ -521,6 +726,8 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                 f
             }
         }
+
+        #out_event_def
     };
 
     final_quote.into()
@@ -556,3 +763,43 @@ fn is_ignored(field: &syn::Field) -> bool {
 
     false
 }
+
+/// Returns the out event type of a field, if specified by the user with
+/// `#[behaviour(event = "Foo")]`.
+fn field_event_type(field: &syn::Field) -> Option<syn::Type> {
+    for meta_items in field.attrs.iter().filter_map(get_meta_items) {
+        for meta_item in meta_items {
+            match meta_item {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(ref m)) if m.path.is_ident("event") => {
+                    if let syn::Lit::Str(ref s) = m.lit {
+                        return Some(syn::parse_str(&s.value()).unwrap());
+                    }
+                }
+                _ => ()
+            }
+        }
+    }
+
+    None
+}
+
+/// Returns the name of the variant of the generated out event enum for the given field.
+///
+/// Named fields are converted to camel case, while tuple fields are named after their index.
+fn variant_name(field: &syn::Field, field_n: usize) -> Ident {
+    let name = match field.ident {
+        Some(ref i) => i.unraw().to_string()
+            .split('_')
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let mut chars = part.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                    None => String::new(),
+                }
+            })
+            .collect::<String>(),
+        None => format!("Field{}", field_n),
+    };
+    Ident::new(&name, field.span())
+}
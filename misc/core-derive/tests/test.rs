@@ -221,3 +221,231 @@ fn where_clause() {
         ping: libp2p::ping::Ping<TSubstream>,
     }
 }
+
+#[test]
+fn ignored_leading_field() {
+    #[allow(dead_code)]
+    #[derive(NetworkBehaviour)]
+    struct Foo<TSubstream> {
+        #[behaviour(ignore)]
+        count: u32,
+        ping: libp2p::ping::Ping<TSubstream>,
+        identify: libp2p::identify::Identify<TSubstream>,
+        #[behaviour(ignore)]
+        name: String,
+    }
+
+    impl<TSubstream> libp2p::swarm::NetworkBehaviourEventProcess<libp2p::ping::PingEvent> for Foo<TSubstream> {
+        fn inject_event(&mut self, _: libp2p::ping::PingEvent) {
+        }
+    }
+
+    impl<TSubstream> libp2p::swarm::NetworkBehaviourEventProcess<libp2p::identify::IdentifyEvent> for Foo<TSubstream> {
+        fn inject_event(&mut self, _: libp2p::identify::IdentifyEvent) {
+        }
+    }
+
+    #[allow(dead_code)]
+    fn foo<TSubstream: libp2p::futures::AsyncRead + libp2p::futures::AsyncWrite + Send + Unpin + 'static>() {
+        require_net_behaviour::<Foo<TSubstream>>();
+    }
+}
+
+#[test]
+fn tuple_struct() {
+    #[allow(dead_code)]
+    #[derive(NetworkBehaviour)]
+    struct Foo<TSubstream>(
+        #[behaviour(ignore)] u32,
+        libp2p::ping::Ping<TSubstream>,
+        libp2p::identify::Identify<TSubstream>,
+    );
+
+    impl<TSubstream> libp2p::swarm::NetworkBehaviourEventProcess<libp2p::ping::PingEvent> for Foo<TSubstream> {
+        fn inject_event(&mut self, _: libp2p::ping::PingEvent) {
+        }
+    }
+
+    impl<TSubstream> libp2p::swarm::NetworkBehaviourEventProcess<libp2p::identify::IdentifyEvent> for Foo<TSubstream> {
+        fn inject_event(&mut self, _: libp2p::identify::IdentifyEvent) {
+        }
+    }
+
+    #[allow(dead_code)]
+    fn foo<TSubstream: libp2p::futures::AsyncRead + libp2p::futures::AsyncWrite + Send + Unpin + 'static>() {
+        require_net_behaviour::<Foo<TSubstream>>();
+    }
+}
+
+#[test]
+fn generated_out_event() {
+    #[allow(dead_code)]
+    #[derive(NetworkBehaviour)]
+    #[behaviour(event_process = false)]
+    struct Foo<TSubstream> {
+        #[behaviour(event = "libp2p::ping::PingEvent")]
+        ping: libp2p::ping::Ping<TSubstream>,
+        identify: libp2p::identify::Identify<TSubstream>,
+        #[behaviour(ignore)]
+        count: u32,
+    }
+
+    #[allow(dead_code)]
+    fn foo<TSubstream: libp2p::futures::AsyncRead + libp2p::futures::AsyncWrite + Send + Unpin + 'static>() {
+        require_net_behaviour::<Foo<TSubstream>>();
+    }
+
+    #[allow(dead_code)]
+    fn bar<TSubstream: libp2p::futures::AsyncRead + libp2p::futures::AsyncWrite + Send + Unpin + 'static>(
+        event: FooEvent<TSubstream>
+    ) -> String {
+        match event {
+            FooEvent::Ping(ev) => format!("{:?}", FooEvent::<TSubstream>::from(ev)),
+            FooEvent::Identify(_) => String::new(),
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(NetworkBehaviour)]
+    #[behaviour(event_process = false)]
+    struct Bar(
+        #[behaviour(event = "libp2p::ping::PingEvent")]
+        libp2p::ping::Ping<libp2p::core::Negotiated<libp2p::tcp::TcpTransStream>>,
+    );
+
+    let event = libp2p::ping::PingEvent {
+        peer: libp2p::PeerId::random(),
+        result: Err(libp2p::ping::PingFailure::Timeout),
+    };
+    match BarEvent::from(event) {
+        BarEvent::Field0(ev) => assert!(ev.result.is_err()),
+    }
+}
+
+#[test]
+fn multiple_fields_of_same_type() {
+    use libp2p::{
+        PeerId, Swarm, Transport,
+        core::{identity, muxing::StreamMuxerBox, nodes::Substream, transport::MemoryTransport, upgrade},
+        futures::{executor::block_on, future::poll_fn, prelude::*},
+        kad::{Kademlia, KademliaConfig, KademliaEvent, record::store::MemoryStore},
+    };
+    use std::{collections::HashSet, task::Poll};
+
+    #[derive(NetworkBehaviour)]
+    #[behaviour(event_process = false)]
+    struct Foo<TSubstream> {
+        public_kad: Kademlia<TSubstream, MemoryStore>,
+        private_kad: Kademlia<TSubstream, MemoryStore>,
+    }
+
+    fn kad(local_id: &PeerId, protocol_name: &'static [u8]) -> Kademlia<Substream<StreamMuxerBox>, MemoryStore> {
+        let mut cfg = KademliaConfig::default();
+        cfg.set_protocol_name(protocol_name);
+        Kademlia::with_config(local_id.clone(), MemoryStore::new(local_id.clone()), cfg)
+    }
+
+    // All nodes share the public DHT, but only the first two share the private one.
+    let private_names: [&'static [u8]; 3] = [b"/private/kad/1.0.0", b"/private/kad/1.0.0", b"/other/kad/1.0.0"];
+    let mut swarms = private_names.iter().map(|private_name| {
+        let local_key = identity::Keypair::generate_ed25519();
+        let local_id = local_key.public().into_peer_id();
+        let transport = MemoryTransport
+            .upgrade(upgrade::Version::V1)
+            .authenticate(libp2p::secio::SecioConfig::new(local_key))
+            .multiplex(libp2p::yamux::Config::default())
+            .map(|(p, m), _| (p, StreamMuxerBox::new(m)));
+        let behaviour = Foo {
+            public_kad: kad(&local_id, b"/public/kad/1.0.0"),
+            private_kad: kad(&local_id, private_name),
+        };
+        let mut swarm = Swarm::new(transport, behaviour, local_id);
+        Swarm::listen_on(&mut swarm, "/memory/0".parse().unwrap()).unwrap();
+        swarm
+    }).collect::<Vec<_>>();
+    let ids = swarms.iter().map(|s| Swarm::local_peer_id(s).clone()).collect::<Vec<_>>();
+
+    let addrs = block_on(poll_fn(|ctx| {
+        for swarm in &mut swarms {
+            while let Poll::Ready(Some(_)) = swarm.poll_next_unpin(ctx) {}
+        }
+        let addrs = swarms.iter().filter_map(|s| Swarm::listeners(s).next().cloned()).collect::<Vec<_>>();
+        if addrs.len() == swarms.len() { Poll::Ready(addrs) } else { Poll::Pending }
+    }));
+
+    // The first node dials the others, so that it knows their addresses.
+    for addr in &addrs[1..] {
+        Swarm::dial_addr(&mut swarms[0], addr.clone()).unwrap();
+    }
+    block_on(poll_fn(|ctx| {
+        for swarm in &mut swarms {
+            while let Poll::Ready(Some(_)) = swarm.poll_next_unpin(ctx) {}
+        }
+        let (first, others) = swarms.split_first_mut().unwrap();
+        let connected = ids[1..].iter().all(|id| Swarm::connection_info(first, id).is_some())
+            && others.iter_mut().all(|s| Swarm::connection_info(s, &ids[0]).is_some());
+        if connected { Poll::Ready(()) } else { Poll::Pending }
+    }));
+
+    // The other nodes then bootstrap both of their DHTs from the first node.
+    for swarm in &mut swarms[1..] {
+        swarm.public_kad.add_address(&ids[0], addrs[0].clone());
+        swarm.public_kad.bootstrap();
+        swarm.private_kad.add_address(&ids[0], addrs[0].clone());
+        swarm.private_kad.bootstrap();
+    }
+
+    let mut finished = HashSet::new();
+    block_on(poll_fn(|ctx| {
+        for (i, swarm) in swarms.iter_mut().enumerate() {
+            while let Poll::Ready(Some(event)) = swarm.poll_next_unpin(ctx) {
+                match event {
+                    FooEvent::PublicKad(KademliaEvent::BootstrapResult(_)) => { finished.insert((i, true)); }
+                    FooEvent::PrivateKad(KademliaEvent::BootstrapResult(_)) => { finished.insert((i, false)); }
+                    _ => {}
+                }
+            }
+        }
+        if finished.len() == 4 { Poll::Ready(()) } else { Poll::Pending }
+    }));
+
+    // Each routing table of the first node only contains the peers of its own DHT.
+    let public = swarms[0].public_kad.kbuckets_entries().cloned().collect::<HashSet<_>>();
+    assert_eq!(public, ids[1..].iter().cloned().collect());
+    let private = swarms[0].private_kad.kbuckets_entries().cloned().collect::<Vec<_>>();
+    assert_eq!(private, vec![ids[1].clone()]);
+}
+
+#[test]
+fn custom_out_event_no_process() {
+    #[allow(dead_code)]
+    enum MyEvent {
+        Ping(libp2p::ping::PingEvent),
+        Identify(Box<libp2p::identify::IdentifyEvent>),
+    }
+
+    impl From<libp2p::ping::PingEvent> for MyEvent {
+        fn from(event: libp2p::ping::PingEvent) -> Self {
+            MyEvent::Ping(event)
+        }
+    }
+
+    impl From<libp2p::identify::IdentifyEvent> for MyEvent {
+        fn from(event: libp2p::identify::IdentifyEvent) -> Self {
+            MyEvent::Identify(Box::new(event))
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(NetworkBehaviour)]
+    #[behaviour(out_event = "MyEvent", event_process = false)]
+    struct Foo<TSubstream> {
+        ping: libp2p::ping::Ping<TSubstream>,
+        identify: libp2p::identify::Identify<TSubstream>,
+    }
+
+    #[allow(dead_code)]
+    fn foo<TSubstream: libp2p::futures::AsyncRead + libp2p::futures::AsyncWrite + Send + Unpin + 'static>() {
+        require_net_behaviour::<Foo<TSubstream>>();
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_core::identity::error::{DecodingError, EncodingError, SigningError};
+use std::{error::Error, fmt, io};
+
+/// An error of a [`Keystore`](crate::Keystore).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum KeystoreError {
+    /// An I/O error has been encountered.
+    Io(io::Error),
+    /// The name of a key slot is invalid.
+    InvalidName,
+    /// A key slot could not be decrypted, either because the password is
+    /// wrong or because the slot has been tampered with.
+    Decryption,
+    /// A key slot is not in the format of the keystore, or its parameters
+    /// are out of bounds.
+    InvalidFormat,
+    /// A keypair could not be decoded.
+    Decoding(DecodingError),
+    /// A keypair could not be encoded.
+    Encoding(EncodingError),
+    /// A key rotation could not be signed.
+    Signing(SigningError),
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::Io(e) => write!(f, "{}", e),
+            KeystoreError::InvalidName => f.write_str("invalid key slot name"),
+            KeystoreError::Decryption => f.write_str("wrong password or corrupted key slot"),
+            KeystoreError::InvalidFormat => f.write_str("invalid key slot format"),
+            KeystoreError::Decoding(e) => write!(f, "{}", e),
+            KeystoreError::Encoding(e) => write!(f, "{}", e),
+            KeystoreError::Signing(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for KeystoreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KeystoreError::Io(e) => Some(e),
+            KeystoreError::InvalidName => None,
+            KeystoreError::Decryption => None,
+            KeystoreError::InvalidFormat => None,
+            KeystoreError::Decoding(e) => Some(e),
+            KeystoreError::Encoding(e) => Some(e),
+            KeystoreError::Signing(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for KeystoreError {
+    fn from(e: io::Error) -> Self {
+        KeystoreError::Io(e)
+    }
+}
+
+impl From<DecodingError> for KeystoreError {
+    fn from(e: DecodingError) -> Self {
+        KeystoreError::Decoding(e)
+    }
+}
+
+impl From<EncodingError> for KeystoreError {
+    fn from(e: EncodingError) -> Self {
+        KeystoreError::Encoding(e)
+    }
+}
+
+impl From<SigningError> for KeystoreError {
+    fn from(e: SigningError) -> Self {
+        KeystoreError::Signing(e)
+    }
+}
@@ -0,0 +1,628 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Encrypted on-disk storage of keys.
+//!
+//! A [`Keystore`] is a directory of named key slots, each holding an identity
+//! [`Keypair`](identity::Keypair) or the raw bytes of a protocol key, e.g. a
+//! pre-shared key. Each slot is a file encrypted with ChaCha20-Poly1305 under
+//! a key derived from the password of the keystore with scrypt.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use libp2p_core::identity;
+//! use libp2p_keystore::Keystore;
+//!
+//! # fn main() -> Result<(), libp2p_keystore::KeystoreError> {
+//! let keystore = Keystore::open("/var/lib/node/keys", "password")?;
+//! let local_key = keystore.keypair_or_insert_with("identity", identity::Keypair::generate_ed25519)?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod error;
+
+pub use error::KeystoreError;
+
+use libp2p_core::{identity, key_rotation::KeyRotation};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::{fs, io::{self, Write}, ops::Deref, path::{Path, PathBuf}};
+use zeroize::{Zeroize, Zeroizing};
+
+/// The first bytes of every key slot file.
+const MAGIC: &[u8; 4] = b"lpks";
+
+/// The version of the format of key slot files.
+const VERSION: u8 = 1;
+
+/// The length of the salt of the key derivation.
+const SALT_LEN: usize = 16;
+
+/// The length of the header of a key slot file, i.e. of the magic bytes,
+/// the version, the scrypt parameters, the salt and the nonce.
+const HEADER_LEN: usize = MAGIC.len() + 1 + 1 + 4 + 4 + SALT_LEN + NONCE_LEN;
+
+/// The extension of key slot files.
+const EXTENSION: &str = "key";
+
+/// The suffix of the name of the slot holding the previous key of a
+/// rotated slot.
+const PREVIOUS: &str = ".previous";
+
+/// The maximum length of the name of a key slot.
+const MAX_NAME_LEN: usize = 64;
+
+/// The maximum memory used by the scrypt derivation of a key, in bytes.
+const MAX_SCRYPT_MEMORY: u64 = 2 << 30;
+
+/// The parameters of the scrypt derivation of the keys of the slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScryptParams {
+    /// The base 2 logarithm of the CPU/memory cost `N`.
+    pub log_n: u8,
+    /// The block size `r`.
+    pub r: u32,
+    /// The parallelization `p`.
+    pub p: u32,
+}
+
+impl ScryptParams {
+    /// The largest `log_n` accepted, at which a derivation with `r = 2`
+    /// uses 2 GiB of memory.
+    ///
+    /// It cannot be reached with `r = 1`, for which scrypt limits `log_n`
+    /// to 15.
+    pub const MAX_LOG_N: u8 = 23;
+
+    /// Whether the parameters are within the bounds of the keystore,
+    /// which limit the time used by a derivation as well as its memory,
+    /// `128 * r * 2^log_n` bytes, to 2 GiB.
+    ///
+    /// scrypt itself also requires `log_n < 16 * r`.
+    pub fn is_valid(&self) -> bool {
+        self.log_n >= 1 && self.log_n <= Self::MAX_LOG_N
+            && self.r >= 1 && self.r <= 32
+            && self.p >= 1 && self.p <= 16
+            && u32::from(self.log_n) < 16 * self.r
+            && (128 * u64::from(self.r)) << self.log_n <= MAX_SCRYPT_MEMORY
+    }
+}
+
+impl Default for ScryptParams {
+    /// The parameters recommended for interactive use, i.e. 32 MiB of memory.
+    fn default() -> Self {
+        ScryptParams { log_n: 15, r: 8, p: 1 }
+    }
+}
+
+/// The bytes of a key read from a [`Keystore`], which are zeroed on drop.
+#[derive(Clone)]
+pub struct Secret(Zeroizing<Vec<u8>>);
+
+impl Deref for Secret {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Secret {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret")
+    }
+}
+
+/// A directory of named key slots encrypted with a password.
+///
+/// The name of a slot consists of 1 to 64 ASCII letters, digits, `-` and `_`.
+pub struct Keystore {
+    dir: PathBuf,
+    password: Zeroizing<Vec<u8>>,
+    params: ScryptParams,
+    rng: SystemRandom,
+}
+
+impl std::fmt::Debug for Keystore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keystore")
+            .field("dir", &self.dir)
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl Keystore {
+    /// Opens the keystore in the directory `dir` with the given password,
+    /// creating the directory if necessary.
+    pub fn open(dir: impl Into<PathBuf>, password: impl AsRef<[u8]>) -> Result<Self, KeystoreError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Keystore {
+            dir,
+            password: Zeroizing::new(password.as_ref().to_vec()),
+            params: ScryptParams::default(),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Sets the scrypt parameters of the slots written from now on.
+    ///
+    /// Slots are always read with the parameters they have been written with.
+    /// Fails with [`KeystoreError::InvalidFormat`] if the parameters are
+    /// invalid, see [`ScryptParams::is_valid`].
+    pub fn set_scrypt_params(&mut self, params: ScryptParams) -> Result<&mut Self, KeystoreError> {
+        if !params.is_valid() {
+            return Err(KeystoreError::InvalidFormat)
+        }
+        self.params = params;
+        Ok(self)
+    }
+
+    /// Returns the names of the slots of the keystore, in no particular order.
+    pub fn names(&self) -> Result<Vec<String>, KeystoreError> {
+        let mut names = self.files()?;
+        names.retain(|name| !name.ends_with(PREVIOUS));
+        Ok(names)
+    }
+
+    /// Returns the key of the slot `name`, if any.
+    pub fn get(&self, name: &str) -> Result<Option<Secret>, KeystoreError> {
+        self.check(name)?;
+        self.read(name)
+    }
+
+    /// Stores `key` in the slot `name`, replacing its key, if any.
+    pub fn insert(&self, name: &str, key: &[u8]) -> Result<(), KeystoreError> {
+        self.check(name)?;
+        self.write(name, key)
+    }
+
+    /// Removes the slot `name` and the previous key of the slot, returning
+    /// whether the slot existed.
+    pub fn remove(&self, name: &str) -> Result<bool, KeystoreError> {
+        self.check(name)?;
+        remove_file(&self.path(&format!("{}{}", name, PREVIOUS)))?;
+        remove_file(&self.path(name))
+    }
+
+    /// Stores `key` in the slot `name`, keeping the key it replaces, if any,
+    /// as the previous key of the slot, e.g. to allow the remote nodes to
+    /// verify existing signatures while the new key is being rolled out.
+    ///
+    /// Returns the replaced key.
+    pub fn rotate(&self, name: &str, key: &[u8]) -> Result<Option<Secret>, KeystoreError> {
+        self.check(name)?;
+        let previous = self.read(name)?;
+        if let Some(previous) = &previous {
+            self.write(&format!("{}{}", name, PREVIOUS), previous)?;
+        }
+        self.write(name, key)?;
+        Ok(previous)
+    }
+
+    /// Returns the previous key of the slot `name`, i.e. the key replaced
+    /// by the last [`Keystore::rotate`], if any.
+    pub fn previous(&self, name: &str) -> Result<Option<Secret>, KeystoreError> {
+        self.check(name)?;
+        self.read(&format!("{}{}", name, PREVIOUS))
+    }
+
+    /// Returns the identity keypair of the slot `name`, if any.
+    pub fn keypair(&self, name: &str) -> Result<Option<identity::Keypair>, KeystoreError> {
+        match self.get(name)? {
+            Some(secret) => {
+                let mut bytes = Zeroizing::new(secret.to_vec());
+                Ok(Some(identity::Keypair::from_protobuf_encoding(&mut bytes)?))
+            }
+            None => Ok(None)
+        }
+    }
+
+    /// Stores the identity keypair `keypair` in the slot `name`, replacing its
+    /// key, if any.
+    pub fn insert_keypair(&self, name: &str, keypair: &identity::Keypair) -> Result<(), KeystoreError> {
+        let encoded = Zeroizing::new(keypair.to_protobuf_encoding()?);
+        self.insert(name, &encoded)
+    }
+
+    /// Replaces the identity keypair of the slot `name` with `keypair`, keeping
+    /// the replaced keypair as the previous key of the slot.
+    ///
+    /// Returns the [`KeyRotation`] statement linking the replaced keypair to
+    /// the new one, to be published to the remote nodes, or `None` if the slot
+    /// was empty.
+    pub fn rotate_keypair(&self, name: &str, keypair: &identity::Keypair)
+        -> Result<Option<KeyRotation>, KeystoreError>
+    {
+        let rotation = match self.keypair(name)? {
+            Some(old) => Some(KeyRotation::new(&old, keypair)?),
+            None => None
+        };
+        let encoded = Zeroizing::new(keypair.to_protobuf_encoding()?);
+        self.rotate(name, &encoded)?;
+        Ok(rotation)
+    }
+
+    /// Returns the identity keypair of the slot `name`, first storing the
+    /// keypair created by `f` if the slot is empty.
+    ///
+    /// If several processes share the directory of the keystore, they all
+    /// obtain the keypair of the first one to store its keypair.
+    pub fn keypair_or_insert_with<F>(&self, name: &str, f: F) -> Result<identity::Keypair, KeystoreError>
+    where
+        F: FnOnce() -> identity::Keypair
+    {
+        if let Some(keypair) = self.keypair(name)? {
+            return Ok(keypair)
+        }
+        let keypair = f();
+        let encoded = Zeroizing::new(keypair.to_protobuf_encoding()?);
+        if self.write_new(name, &encoded)? {
+            return Ok(keypair)
+        }
+        // Another process has filled the slot in the meantime.
+        self.keypair(name)?.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "key slot removed").into())
+    }
+
+    /// Changes the password of the keystore, encrypting every slot with
+    /// the new password and the current scrypt parameters.
+    ///
+    /// Slots already encrypted with the new password are left unchanged, so
+    /// an interrupted change can be completed by calling this method again.
+    pub fn change_password(&mut self, password: impl AsRef<[u8]>) -> Result<(), KeystoreError> {
+        let new = Keystore {
+            dir: self.dir.clone(),
+            password: Zeroizing::new(password.as_ref().to_vec()),
+            params: self.params,
+            rng: SystemRandom::new(),
+        };
+        for file in self.files()? {
+            let key = match self.read(&file) {
+                Ok(Some(key)) => key,
+                Ok(None) => continue,
+                Err(KeystoreError::Decryption) if new.read(&file).is_ok() => continue,
+                Err(e) => return Err(e)
+            };
+            new.write(&file, &key)?;
+        }
+        *self = new;
+        Ok(())
+    }
+
+    /// Checks that `name` is a valid slot name.
+    fn check(&self, name: &str) -> Result<(), KeystoreError> {
+        let valid = !name.is_empty()
+            && name.len() <= MAX_NAME_LEN
+            && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !valid {
+            return Err(KeystoreError::InvalidName)
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the files of the keystore, without extension,
+    /// i.e. of the slots and of the previous keys of rotated slots.
+    fn files(&self) -> Result<Vec<String>, KeystoreError> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some(EXTENSION) {
+                if let Some(file) = path.file_stem().and_then(|s| s.to_str()) {
+                    files.push(file.to_owned())
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Returns the path of the file `file`.
+    fn path(&self, file: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", file, EXTENSION))
+    }
+
+    /// Reads and decrypts the file `file`.
+    fn read(&self, file: &str) -> Result<Option<Secret>, KeystoreError> {
+        let mut data = match fs::read(self.path(file)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into())
+        };
+        if data.len() < HEADER_LEN + CHACHA20_POLY1305.tag_len()
+            || &data[.. MAGIC.len()] != MAGIC
+            || data[MAGIC.len()] != VERSION
+        {
+            return Err(KeystoreError::InvalidFormat)
+        }
+
+        let (header, ciphertext) = data.split_at_mut(HEADER_LEN);
+        let mut h = &header[MAGIC.len() + 1 ..];
+        let params = ScryptParams {
+            log_n: take(&mut h, 1)[0],
+            r: u32::from_be_bytes(array(take(&mut h, 4))),
+            p: u32::from_be_bytes(array(take(&mut h, 4))),
+        };
+        if !params.is_valid() {
+            return Err(KeystoreError::InvalidFormat)
+        }
+        let salt = take(&mut h, SALT_LEN);
+        let nonce = Nonce::assume_unique_for_key(array(take(&mut h, NONCE_LEN)));
+
+        let key = self.key(params, salt)?;
+        let aad = [&header[..], file.as_bytes()].concat();
+        let plaintext = key.open_in_place(nonce, Aad::from(aad), ciphertext)
+            .map(|p| p.to_vec())
+            .map_err(|_| KeystoreError::Decryption);
+        data.zeroize();
+        Ok(Some(Secret(Zeroizing::new(plaintext?))))
+    }
+
+    /// Encrypts and writes `key` to the file `file`.
+    ///
+    /// The file is replaced atomically, so that the key is never lost.
+    fn write(&self, file: &str, key: &[u8]) -> Result<(), KeystoreError> {
+        let tmp = self.write_tmp(file, key)?;
+        fs::rename(&tmp, self.path(file))?;
+        Ok(())
+    }
+
+    /// Encrypts and writes `key` to the file `file` unless it exists,
+    /// returning whether the file has been written.
+    ///
+    /// The file is created atomically, so that of several processes writing
+    /// the same file at the same time only one succeeds.
+    fn write_new(&self, file: &str, key: &[u8]) -> Result<bool, KeystoreError> {
+        let tmp = self.write_tmp(file, key)?;
+        let result = fs::hard_link(&tmp, self.path(file));
+        fs::remove_file(&tmp)?;
+        match result {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Encrypts and writes `key` for the file `file` to a new temporary
+    /// file, whose path is returned.
+    fn write_tmp(&self, file: &str, key: &[u8]) -> Result<PathBuf, KeystoreError> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut salt).map_err(|_| io::Error::new(io::ErrorKind::Other, "no randomness"))?;
+        self.rng.fill(&mut nonce).map_err(|_| io::Error::new(io::ErrorKind::Other, "no randomness"))?;
+
+        let mut data = Vec::with_capacity(HEADER_LEN + key.len() + CHACHA20_POLY1305.tag_len());
+        data.extend_from_slice(MAGIC);
+        data.push(VERSION);
+        data.push(self.params.log_n);
+        data.extend_from_slice(&self.params.r.to_be_bytes());
+        data.extend_from_slice(&self.params.p.to_be_bytes());
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&nonce);
+        let aad = [&data[..], file.as_bytes()].concat();
+
+        let mut ciphertext = Zeroizing::new(key.to_vec());
+        self.key(self.params, &salt)?
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut *ciphertext)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "encryption failed"))?;
+        data.extend_from_slice(&ciphertext);
+
+        // The name of the temporary file is random, so that processes writing
+        // the same file at the same time do not write to the same temporary file.
+        let mut suffix = [0u8; 8];
+        self.rng.fill(&mut suffix).map_err(|_| io::Error::new(io::ErrorKind::Other, "no randomness"))?;
+        let tmp = self.dir.join(format!("{}.{:016x}.tmp", file, u64::from_be_bytes(suffix)));
+        let mut f = create_private(&tmp)?;
+        f.write_all(&data)?;
+        f.sync_all()?;
+        Ok(tmp)
+    }
+
+    /// Derives the encryption key of a slot.
+    fn key(&self, params: ScryptParams, salt: &[u8]) -> Result<LessSafeKey, KeystoreError> {
+        let mut key = [0u8; 32];
+        let params = scrypt::Params::new(params.log_n, params.r, params.p, key.len())
+            .map_err(|_| KeystoreError::InvalidFormat)?;
+        scrypt::scrypt(&self.password, salt, &params, &mut key)
+            .expect("the key has a valid length");
+        let unbound = UnboundKey::new(&CHACHA20_POLY1305, &key).expect("the key has the right length");
+        key.zeroize();
+        Ok(LessSafeKey::new(unbound))
+    }
+}
+
+/// Splits off the first `n` bytes of `data`.
+fn take<'a>(data: &mut &'a [u8], n: usize) -> &'a [u8] {
+    let (head, tail) = data.split_at(n);
+    *data = tail;
+    head
+}
+
+/// Converts a slice into an array of the same length.
+fn array<A: Default + AsMut<[u8]>>(bytes: &[u8]) -> A {
+    let mut a = A::default();
+    a.as_mut().copy_from_slice(bytes);
+    a
+}
+
+/// Removes a file, returning whether it existed.
+fn remove_file(path: &Path) -> Result<bool, KeystoreError> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into())
+    }
+}
+
+/// Creates a file only readable and writable by the current user.
+fn create_private(path: &Path) -> io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opens a keystore in `dir` with cheap scrypt parameters.
+    fn open(dir: &Path, password: &str) -> Keystore {
+        let mut keystore = Keystore::open(dir, password).unwrap();
+        keystore.set_scrypt_params(ScryptParams { log_n: 4, r: 8, p: 1 }).unwrap();
+        keystore
+    }
+
+    #[test]
+    fn scrypt_params_bounds() {
+        assert!(ScryptParams::default().is_valid());
+        // 2 GiB of memory.
+        assert!(ScryptParams { log_n: ScryptParams::MAX_LOG_N, r: 2, p: 1 }.is_valid());
+        assert!(ScryptParams { log_n: 15, r: 1, p: 1 }.is_valid());
+        assert!(!ScryptParams { log_n: ScryptParams::MAX_LOG_N + 1, r: 2, p: 1 }.is_valid());
+        // 8 GiB of memory.
+        assert!(!ScryptParams { log_n: ScryptParams::MAX_LOG_N, r: 8, p: 1 }.is_valid());
+        assert!(!ScryptParams { log_n: 20, r: 32, p: 1 }.is_valid());
+        // Rejected by scrypt, as `log_n >= 16 * r`.
+        assert!(!ScryptParams { log_n: 16, r: 1, p: 1 }.is_valid());
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut keystore = Keystore::open(dir.path(), "password").unwrap();
+        let invalid = ScryptParams { log_n: 16, r: 1, p: 1 };
+        assert!(matches!(keystore.set_scrypt_params(invalid), Err(KeystoreError::InvalidFormat)));
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = open(dir.path(), "password");
+        assert!(keystore.get("psk").unwrap().is_none());
+        keystore.insert("psk", b"secret").unwrap();
+        assert_eq!(&*keystore.get("psk").unwrap().unwrap(), b"secret");
+        assert_eq!(keystore.names().unwrap(), vec!["psk".to_string()]);
+        assert!(keystore.remove("psk").unwrap());
+        assert!(keystore.get("psk").unwrap().is_none());
+        assert!(!keystore.remove("psk").unwrap());
+    }
+
+    #[test]
+    fn keys_are_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = open(dir.path(), "password");
+        keystore.insert("psk", b"secret").unwrap();
+        let data = fs::read(dir.path().join("psk.key")).unwrap();
+        assert!(!data.windows(6).any(|w| w == b"secret"));
+
+        let wrong = open(dir.path(), "wrong");
+        assert!(matches!(wrong.get("psk"), Err(KeystoreError::Decryption)));
+
+        // A slot cannot be moved to another name.
+        fs::copy(dir.path().join("psk.key"), dir.path().join("other.key")).unwrap();
+        assert!(matches!(keystore.get("other"), Err(KeystoreError::Decryption)));
+    }
+
+    #[test]
+    fn invalid_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = open(dir.path(), "password");
+        for name in &["", "../psk", "psk.previous", &"a".repeat(MAX_NAME_LEN + 1)] {
+            assert!(matches!(keystore.insert(name, b"secret"), Err(KeystoreError::InvalidName)));
+        }
+    }
+
+    #[test]
+    fn keypairs() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = open(dir.path(), "password");
+        let keypair = keystore.keypair_or_insert_with("identity", identity::Keypair::generate_ed25519).unwrap();
+        let keypair2 = keystore.keypair_or_insert_with("identity", || panic!()).unwrap();
+        assert_eq!(keypair.public(), keypair2.public());
+    }
+
+    #[test]
+    fn rotate_keypair() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = open(dir.path(), "password");
+        let old = identity::Keypair::generate_ed25519();
+        let new = identity::Keypair::generate_ed25519();
+        assert!(keystore.rotate_keypair("identity", &old).unwrap().is_none());
+        let rotation = keystore.rotate_keypair("identity", &new).unwrap().unwrap();
+        assert!(rotation.verify());
+        assert_eq!(rotation.old_key(), &old.public());
+        assert_eq!(rotation.new_key(), &new.public());
+        assert_eq!(keystore.keypair("identity").unwrap().unwrap().public(), new.public());
+    }
+
+    #[test]
+    fn rotate() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = open(dir.path(), "password");
+        assert!(keystore.rotate("psk", b"first").unwrap().is_none());
+        assert!(keystore.previous("psk").unwrap().is_none());
+        assert_eq!(&*keystore.rotate("psk", b"second").unwrap().unwrap(), b"first");
+        assert_eq!(&*keystore.get("psk").unwrap().unwrap(), b"second");
+        assert_eq!(&*keystore.previous("psk").unwrap().unwrap(), b"first");
+        keystore.remove("psk").unwrap();
+        assert!(keystore.names().unwrap().is_empty());
+    }
+
+    #[test]
+    fn concurrent_keypair_or_insert_with() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypairs = (0 .. 4).map(|_| {
+            let dir = dir.path().to_owned();
+            std::thread::spawn(move || {
+                open(&dir, "password")
+                    .keypair_or_insert_with("identity", identity::Keypair::generate_ed25519)
+                    .unwrap()
+                    .public()
+            })
+        }).collect::<Vec<_>>().into_iter().map(|t| t.join().unwrap()).collect::<Vec<_>>();
+        assert!(keypairs.iter().all(|k| k == &keypairs[0]));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn change_password() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut keystore = open(dir.path(), "password");
+        keystore.insert("a", b"first").unwrap();
+        keystore.rotate("a", b"second").unwrap();
+        keystore.insert("b", b"third").unwrap();
+        keystore.change_password("new").unwrap();
+
+        let reopened = open(dir.path(), "new");
+        assert_eq!(&*reopened.get("a").unwrap().unwrap(), b"second");
+        assert_eq!(&*reopened.previous("a").unwrap().unwrap(), b"first");
+        assert_eq!(&*reopened.get("b").unwrap().unwrap(), b"third");
+        assert!(matches!(open(dir.path(), "password").get("b"), Err(KeystoreError::Decryption)));
+    }
+}
@@ -20,7 +20,7 @@
 
 use crate::service::{MdnsService, MdnsPacket, build_query_response, build_service_discovery_response};
 use futures::prelude::*;
-use libp2p_core::{address_translation, ConnectedPoint, Multiaddr, PeerId, multiaddr::Protocol};
+use libp2p_core::{address_translation, AddressFilter, ConnectedPoint, Multiaddr, PeerId, multiaddr::Protocol};
 use libp2p_swarm::{
     NetworkBehaviour,
     NetworkBehaviourAction,
@@ -52,6 +52,9 @@ pub struct Mdns<TSubstream> {
     /// `None` if `discovered_nodes` is empty.
     closest_expiration: Option<Delay>,
 
+    /// Filter that discovered addresses must pass, as set by the swarm.
+    address_filter: AddressFilter,
+
     /// Marker to pin the generic.
     marker: PhantomData<TSubstream>,
 }
@@ -93,6 +96,7 @@ impl<TSubstream> Mdns<TSubstream> {
             service: MaybeBusyMdnsService::Free(MdnsService::new()?),
             discovered_nodes: SmallVec::new(),
             closest_expiration: None,
+            address_filter: AddressFilter::default(),
             marker: PhantomData,
         })
     }
@@ -198,6 +202,10 @@ where
 
     fn inject_disconnected(&mut self, _: &PeerId, _: ConnectedPoint) {}
 
+    fn inject_address_filter(&mut self, filter: &AddressFilter) {
+        self.address_filter = filter.clone();
+    }
+
     fn inject_node_event(
         &mut self,
         _: PeerId,
@@ -302,6 +310,9 @@ where
                             addrs.push(addr.clone())
                         }
 
+                        let filter = &self.address_filter;
+                        addrs.retain(|a| filter.allows(a));
+
                         for addr in addrs {
                             if let Some((_, _, cur_expires)) = self.discovered_nodes.iter_mut()
                                 .find(|(p, a, _)| p == peer.id() && *a == addr)
@@ -3,7 +3,7 @@ use arrayref::array_ref;
 use bs58;
 use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 use crate::{Result, Error};
-use data_encoding::BASE32;
+use data_encoding::{BASE32, BASE64URL_NOPAD};
 use multihash::Multihash;
 use std::{
     borrow::Cow,
@@ -15,9 +15,12 @@ use std::{
 };
 use unsigned_varint::{encode, decode};
 
+const CERTHASH: u32 = 466;
 const DCCP: u32 = 33;
+const DNS: u32 = 53;
 const DNS4: u32 = 54;
 const DNS6: u32 = 55;
+const DNSADDR: u32 = 56;
 const HTTP: u32 = 480;
 const HTTPS: u32 = 443;
 const IP4: u32 = 4;
@@ -27,15 +30,21 @@ const P2P_WEBRTC_STAR: u32 = 275;
 const P2P_WEBSOCKET_STAR: u32 = 479;
 const MEMORY: u32 = 777;
 const ONION: u32 = 444;
+const ONION3: u32 = 445;
 const P2P: u32 = 421;
 const P2P_CIRCUIT: u32 = 290;
 const QUIC: u32 = 460;
+const QUIC_V1: u32 = 461;
 const SCTP: u32 = 132;
 const TCP: u32 = 6;
+const TLS: u32 = 448;
 const UDP: u32 = 273;
 const UDT: u32 = 301;
 const UNIX: u32 = 400;
 const UTP: u32 = 302;
+const WEBRTC: u32 = 281;
+const WEBRTC_DIRECT: u32 = 280;
+const WEBTRANSPORT: u32 = 465;
 const WS: u32 = 477;
 const WS_WITH_PATH: u32 = 4770;         // Note: not standard
 const WSS: u32 = 478;
@@ -62,9 +71,14 @@ const PATH_SEGMENT_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::
 /// happen separately.
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum Protocol<'a> {
+    /// The multihash of a TLS certificate, allowing self-signed certificates
+    /// to be authenticated.
+    Certhash(Multihash),
     Dccp(u16),
+    Dns(Cow<'a, str>),
     Dns4(Cow<'a, str>),
     Dns6(Cow<'a, str>),
+    Dnsaddr(Cow<'a, str>),
     Http,
     Https,
     Ip4(Ipv4Addr),
@@ -75,15 +89,31 @@ pub enum Protocol<'a> {
     /// Contains the "port" to contact. Similar to TCP or UDP, 0 means "assign me a port".
     Memory(u64),
     Onion(Cow<'a, [u8; 10]>, u16),
+    /// A version 3 Tor hidden service: the 35 bytes of the address, i.e. the
+    /// public key, checksum and version, and the port.
+    Onion3(Cow<'a, [u8; 35]>, u16),
     P2p(Multihash),
     P2pCircuit,
+    /// QUIC in one of the draft versions supported by the first libp2p
+    /// implementations.
     Quic,
+    /// QUIC version 1, as defined in RFC 9000.
+    QuicV1,
     Sctp(u16),
     Tcp(u16),
+    /// A TLS session over the preceding protocol, e.g. `/tcp/443/tls/ws`
+    /// as an equivalent of `/tcp/443/wss`.
+    Tls,
     Udp(u16),
     Udt,
     Unix(Cow<'a, str>),
     Utp,
+    /// WebRTC connections between browsers, established through a relay.
+    WebRtc,
+    /// WebRTC connections to a node with a self-signed certificate, whose
+    /// hash is given by a following `/certhash`.
+    WebRtcDirect,
+    WebTransport,
     Ws(Cow<'a, str>),
     Wss(Cow<'a, str>),
 }
@@ -120,6 +150,10 @@ impl<'a> Protocol<'a> {
                 let s = iter.next().ok_or(Error::InvalidProtocolString)?;
                 Ok(Protocol::Ip6(Ipv6Addr::from_str(s)?))
             }
+            "dns" => {
+                let s = iter.next().ok_or(Error::InvalidProtocolString)?;
+                Ok(Protocol::Dns(Cow::Borrowed(s)))
+            }
             "dns4" => {
                 let s = iter.next().ok_or(Error::InvalidProtocolString)?;
                 Ok(Protocol::Dns4(Cow::Borrowed(s)))
@@ -128,12 +162,20 @@ impl<'a> Protocol<'a> {
                 let s = iter.next().ok_or(Error::InvalidProtocolString)?;
                 Ok(Protocol::Dns6(Cow::Borrowed(s)))
             }
+            "dnsaddr" => {
+                let s = iter.next().ok_or(Error::InvalidProtocolString)?;
+                Ok(Protocol::Dnsaddr(Cow::Borrowed(s)))
+            }
             "sctp" => {
                 let s = iter.next().ok_or(Error::InvalidProtocolString)?;
                 Ok(Protocol::Sctp(s.parse()?))
             }
             "udt" => Ok(Protocol::Udt),
             "utp" => Ok(Protocol::Utp),
+            "webtransport" => Ok(Protocol::WebTransport),
+            "webrtc" => Ok(Protocol::WebRtc),
+            "webrtc-direct" => Ok(Protocol::WebRtcDirect),
+            "tls" => Ok(Protocol::Tls),
             "unix" => {
                 let s = iter.next().ok_or(Error::InvalidProtocolString)?;
                 Ok(Protocol::Unix(Cow::Borrowed(s)))
@@ -143,6 +185,10 @@ impl<'a> Protocol<'a> {
                 let decoded = bs58::decode(s).into_vec()?;
                 Ok(Protocol::P2p(Multihash::from_bytes(decoded)?))
             }
+            "certhash" => {
+                let s = iter.next().ok_or(Error::InvalidProtocolString)?;
+                Ok(Protocol::Certhash(Multihash::from_bytes(read_multibase(s)?)?))
+            }
             "http" => Ok(Protocol::Http),
             "https" => Ok(Protocol::Https),
             "onion" =>
@@ -150,7 +196,13 @@ impl<'a> Protocol<'a> {
                     .ok_or(Error::InvalidProtocolString)
                     .and_then(|s| read_onion(&s.to_uppercase()))
                     .map(|(a, p)| Protocol::Onion(Cow::Owned(a), p)),
+            "onion3" =>
+                iter.next()
+                    .ok_or(Error::InvalidProtocolString)
+                    .and_then(|s| read_onion3(&s.to_uppercase()))
+                    .map(|(a, p)| Protocol::Onion3(Cow::Owned(a), p)),
             "quic" => Ok(Protocol::Quic),
+            "quic-v1" => Ok(Protocol::QuicV1),
             "ws" => Ok(Protocol::Ws(Cow::Borrowed("/"))),
             "wss" => Ok(Protocol::Wss(Cow::Borrowed("/"))),
             "x-parity-ws" => {
@@ -186,12 +238,22 @@ impl<'a> Protocol<'a> {
         }
         let (id, input) = decode::u32(input)?;
         match id {
+            CERTHASH => {
+                let (n, input) = decode::usize(input)?;
+                let (data, rest) = split_at(n, input)?;
+                Ok((Protocol::Certhash(Multihash::from_bytes(data.to_owned())?), rest))
+            }
             DCCP => {
                 let (data, rest) = split_at(2, input)?;
                 let mut rdr = Cursor::new(data);
                 let num = rdr.read_u16::<BigEndian>()?;
                 Ok((Protocol::Dccp(num), rest))
             }
+            DNS => {
+                let (n, input) = decode::usize(input)?;
+                let (data, rest) = split_at(n, input)?;
+                Ok((Protocol::Dns(Cow::Borrowed(str::from_utf8(data)?)), rest))
+            }
             DNS4 => {
                 let (n, input) = decode::usize(input)?;
                 let (data, rest) = split_at(n, input)?;
@@ -202,6 +264,11 @@ impl<'a> Protocol<'a> {
                 let (data, rest) = split_at(n, input)?;
                 Ok((Protocol::Dns6(Cow::Borrowed(str::from_utf8(data)?)), rest))
             }
+            DNSADDR => {
+                let (n, input) = decode::usize(input)?;
+                let (data, rest) = split_at(n, input)?;
+                Ok((Protocol::Dnsaddr(Cow::Borrowed(str::from_utf8(data)?)), rest))
+            }
             HTTP => Ok((Protocol::Http, input)),
             HTTPS => Ok((Protocol::Https, input)),
             IP4 => {
@@ -242,6 +309,11 @@ impl<'a> Protocol<'a> {
                 let port = BigEndian::read_u16(&data[10 ..]);
                 Ok((Protocol::Onion(Cow::Borrowed(array_ref!(data, 0, 10)), port), rest))
             }
+            ONION3 => {
+                let (data, rest) = split_at(37, input)?;
+                let port = BigEndian::read_u16(&data[35 ..]);
+                Ok((Protocol::Onion3(Cow::Borrowed(array_ref!(data, 0, 35)), port), rest))
+            }
             P2P => {
                 let (n, input) = decode::usize(input)?;
                 let (data, rest) = split_at(n, input)?;
@@ -249,6 +321,7 @@ impl<'a> Protocol<'a> {
             }
             P2P_CIRCUIT => Ok((Protocol::P2pCircuit, input)),
             QUIC => Ok((Protocol::Quic, input)),
+            QUIC_V1 => Ok((Protocol::QuicV1, input)),
             SCTP => {
                 let (data, rest) = split_at(2, input)?;
                 let mut rdr = Cursor::new(data);
@@ -267,6 +340,7 @@ impl<'a> Protocol<'a> {
                 let num = rdr.read_u16::<BigEndian>()?;
                 Ok((Protocol::Udp(num), rest))
             }
+            TLS => Ok((Protocol::Tls, input)),
             UDT => Ok((Protocol::Udt, input)),
             UNIX => {
                 let (n, input) = decode::usize(input)?;
@@ -274,6 +348,9 @@ impl<'a> Protocol<'a> {
                 Ok((Protocol::Unix(Cow::Borrowed(str::from_utf8(data)?)), rest))
             }
             UTP => Ok((Protocol::Utp, input)),
+            WEBRTC => Ok((Protocol::WebRtc, input)),
+            WEBRTC_DIRECT => Ok((Protocol::WebRtcDirect, input)),
+            WEBTRANSPORT => Ok((Protocol::WebTransport, input)),
             WS => Ok((Protocol::Ws(Cow::Borrowed("/")), input)),
             WS_WITH_PATH => {
                 let (n, input) = decode::usize(input)?;
@@ -321,6 +398,12 @@ impl<'a> Protocol<'a> {
                 w.write_all(encode::u32(SCTP, &mut buf))?;
                 w.write_u16::<BigEndian>(*port)?
             }
+            Protocol::Dns(s) => {
+                w.write_all(encode::u32(DNS, &mut buf))?;
+                let bytes = s.as_bytes();
+                w.write_all(encode::usize(bytes.len(), &mut encode::usize_buffer()))?;
+                w.write_all(&bytes)?
+            }
             Protocol::Dns4(s) => {
                 w.write_all(encode::u32(DNS4, &mut buf))?;
                 let bytes = s.as_bytes();
@@ -333,6 +416,12 @@ impl<'a> Protocol<'a> {
                 w.write_all(encode::usize(bytes.len(), &mut encode::usize_buffer()))?;
                 w.write_all(&bytes)?
             }
+            Protocol::Dnsaddr(s) => {
+                w.write_all(encode::u32(DNSADDR, &mut buf))?;
+                let bytes = s.as_bytes();
+                w.write_all(encode::usize(bytes.len(), &mut encode::usize_buffer()))?;
+                w.write_all(&bytes)?
+            }
             Protocol::Unix(s) => {
                 w.write_all(encode::u32(UNIX, &mut buf))?;
                 let bytes = s.as_bytes();
@@ -345,13 +434,29 @@ impl<'a> Protocol<'a> {
                 w.write_all(encode::usize(bytes.len(), &mut encode::usize_buffer()))?;
                 w.write_all(&bytes)?
             }
+            Protocol::Certhash(multihash) => {
+                w.write_all(encode::u32(CERTHASH, &mut buf))?;
+                let bytes = multihash.as_bytes();
+                w.write_all(encode::usize(bytes.len(), &mut encode::usize_buffer()))?;
+                w.write_all(&bytes)?
+            }
             Protocol::Onion(addr, port) => {
                 w.write_all(encode::u32(ONION, &mut buf))?;
                 w.write_all(addr.as_ref())?;
                 w.write_u16::<BigEndian>(*port)?
             }
+            Protocol::Onion3(addr, port) => {
+                w.write_all(encode::u32(ONION3, &mut buf))?;
+                w.write_all(addr.as_ref())?;
+                w.write_u16::<BigEndian>(*port)?
+            }
             Protocol::Quic => w.write_all(encode::u32(QUIC, &mut buf))?,
+            Protocol::QuicV1 => w.write_all(encode::u32(QUIC_V1, &mut buf))?,
+            Protocol::Tls => w.write_all(encode::u32(TLS, &mut buf))?,
             Protocol::Utp => w.write_all(encode::u32(UTP, &mut buf))?,
+            Protocol::WebRtc => w.write_all(encode::u32(WEBRTC, &mut buf))?,
+            Protocol::WebRtcDirect => w.write_all(encode::u32(WEBRTC_DIRECT, &mut buf))?,
+            Protocol::WebTransport => w.write_all(encode::u32(WEBTRANSPORT, &mut buf))?,
             Protocol::Udt => w.write_all(encode::u32(UDT, &mut buf))?,
             Protocol::Http => w.write_all(encode::u32(HTTP, &mut buf))?,
             Protocol::Https => w.write_all(encode::u32(HTTPS, &mut buf))?,
@@ -385,9 +490,12 @@ impl<'a> Protocol<'a> {
     pub fn acquire<'b>(self) -> Protocol<'b> {
         use self::Protocol::*;
         match self {
+            Certhash(a) => Certhash(a),
             Dccp(a) => Dccp(a),
+            Dns(cow) => Dns(Cow::Owned(cow.into_owned())),
             Dns4(cow) => Dns4(Cow::Owned(cow.into_owned())),
             Dns6(cow) => Dns6(Cow::Owned(cow.into_owned())),
+            Dnsaddr(cow) => Dnsaddr(Cow::Owned(cow.into_owned())),
             Http => Http,
             Https => Https,
             Ip4(a) => Ip4(a),
@@ -397,28 +505,77 @@ impl<'a> Protocol<'a> {
             P2pWebSocketStar => P2pWebSocketStar,
             Memory(a) => Memory(a),
             Onion(addr, port) => Onion(Cow::Owned(addr.into_owned()), port),
+            Onion3(addr, port) => Onion3(Cow::Owned(addr.into_owned()), port),
             P2p(a) => P2p(a),
             P2pCircuit => P2pCircuit,
             Quic => Quic,
+            QuicV1 => QuicV1,
             Sctp(a) => Sctp(a),
             Tcp(a) => Tcp(a),
+            Tls => Tls,
             Udp(a) => Udp(a),
             Udt => Udt,
             Unix(cow) => Unix(Cow::Owned(cow.into_owned())),
             Utp => Utp,
+            WebRtc => WebRtc,
+            WebRtcDirect => WebRtcDirect,
+            WebTransport => WebTransport,
             Ws(cow) => Ws(Cow::Owned(cow.into_owned())),
             Wss(cow) => Wss(Cow::Owned(cow.into_owned())),
         }
     }
+
+    /// Returns the name of this protocol, as it appears in the textual representation of a
+    /// [`Multiaddr`](crate::Multiaddr), e.g. `"tcp"` for `/tcp/1234`.
+    pub fn tag(&self) -> &'static str {
+        use self::Protocol::*;
+        match self {
+            Certhash(_) => "certhash",
+            Dccp(_) => "dccp",
+            Dns(_) => "dns",
+            Dns4(_) => "dns4",
+            Dns6(_) => "dns6",
+            Dnsaddr(_) => "dnsaddr",
+            Http => "http",
+            Https => "https",
+            Ip4(_) => "ip4",
+            Ip6(_) => "ip6",
+            P2pWebRtcDirect => "p2p-webrtc-direct",
+            P2pWebRtcStar => "p2p-webrtc-star",
+            P2pWebSocketStar => "p2p-websocket-star",
+            Memory(_) => "memory",
+            Onion(..) => "onion",
+            Onion3(..) => "onion3",
+            P2p(_) => "p2p",
+            P2pCircuit => "p2p-circuit",
+            Quic => "quic",
+            QuicV1 => "quic-v1",
+            Sctp(_) => "sctp",
+            Tcp(_) => "tcp",
+            Tls => "tls",
+            Udp(_) => "udp",
+            Udt => "udt",
+            Unix(_) => "unix",
+            Utp => "utp",
+            WebRtc => "webrtc",
+            WebRtcDirect => "webrtc-direct",
+            WebTransport => "webtransport",
+            Ws(_) => "ws",
+            Wss(_) => "wss",
+        }
+    }
 }
 
 impl<'a> fmt::Display for Protocol<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use self::Protocol::*;
         match self {
+            Certhash(c) => write!(f, "/certhash/u{}", BASE64URL_NOPAD.encode(c.as_bytes())),
             Dccp(port) => write!(f, "/dccp/{}", port),
+            Dns(s) => write!(f, "/dns/{}", s),
             Dns4(s) => write!(f, "/dns4/{}", s),
             Dns6(s) => write!(f, "/dns6/{}", s),
+            Dnsaddr(s) => write!(f, "/dnsaddr/{}", s),
             Http => f.write_str("/http"),
             Https => f.write_str("/https"),
             Ip4(addr) => write!(f, "/ip4/{}", addr),
@@ -431,15 +588,24 @@ impl<'a> fmt::Display for Protocol<'a> {
                 let s = BASE32.encode(addr.as_ref());
                 write!(f, "/onion/{}:{}", s.to_lowercase(), port)
             }
+            Onion3(addr, port) => {
+                let s = BASE32.encode(addr.as_ref());
+                write!(f, "/onion3/{}:{}", s.to_lowercase(), port)
+            }
             P2p(c) => write!(f, "/p2p/{}", bs58::encode(c.as_bytes()).into_string()),
             P2pCircuit => f.write_str("/p2p-circuit"),
             Quic => f.write_str("/quic"),
+            QuicV1 => f.write_str("/quic-v1"),
             Sctp(port) => write!(f, "/sctp/{}", port),
             Tcp(port) => write!(f, "/tcp/{}", port),
+            Tls => f.write_str("/tls"),
             Udp(port) => write!(f, "/udp/{}", port),
             Udt => f.write_str("/udt"),
             Unix(s) => write!(f, "/unix/{}", s),
             Utp => f.write_str("/utp"),
+            WebRtc => f.write_str("/webrtc"),
+            WebRtcDirect => f.write_str("/webrtc-direct"),
+            WebTransport => f.write_str("/webtransport"),
             Ws(ref s) if s == "/" => f.write_str("/ws"),
             Ws(s) => {
                 let encoded = percent_encoding::percent_encode(s.as_bytes(), PATH_SEGMENT_ENCODE_SET);
@@ -478,6 +644,16 @@ impl<'a> From<Ipv6Addr> for Protocol<'a> {
     }
 }
 
+// Decode a multibase string in base64url or base58btc encoding.
+fn read_multibase(s: &str) -> Result<Vec<u8>> {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some('u') => BASE64URL_NOPAD.decode(chars.as_str().as_bytes()).map_err(|_| Error::InvalidMultiaddr),
+        Some('z') => Ok(bs58::decode(chars.as_str()).into_vec()?),
+        _ => Err(Error::InvalidMultiaddr)
+    }
+}
+
 // Parse a version 2 onion address and return its binary representation.
 //
 // Format: <base-32 address> ":" <port number>
@@ -509,3 +685,35 @@ fn read_onion(s: &str) -> Result<([u8; 10], u16)> {
 
     Ok((buf, port))
 }
+
+// Parse a version 3 onion address and return its binary representation.
+//
+// Format: <base-32 address> ":" <port number>
+fn read_onion3(s: &str) -> Result<([u8; 35], u16)> {
+    let mut parts = s.split(':');
+
+    // address part (without ".onion")
+    let b32 = parts.next().ok_or(Error::InvalidMultiaddr)?;
+    if b32.len() != 56 {
+        return Err(Error::InvalidMultiaddr)
+    }
+
+    // port number
+    let port = parts.next()
+        .ok_or(Error::InvalidMultiaddr)
+        .and_then(|p| str::parse(p).map_err(From::from))?;
+
+    // nothing else expected
+    if parts.next().is_some() {
+        return Err(Error::InvalidMultiaddr)
+    }
+
+    if 35 != BASE32.decode_len(b32.len()).map_err(|_| Error::InvalidMultiaddr)? {
+        return Err(Error::InvalidMultiaddr)
+    }
+
+    let mut buf = [0u8; 35];
+    BASE32.decode_mut(b32.as_bytes(), &mut buf).map_err(|_| Error::InvalidMultiaddr)?;
+
+    Ok((buf, port))
+}
@@ -56,6 +56,16 @@ fn push_pop_identity() {
     QuickCheck::new().quickcheck(prop as fn(Ma, Proto) -> bool)
 }
 
+#[test]
+fn tag_is_protocol_name() {
+    fn prop(p: Proto) -> bool {
+        let s = p.0.to_string();
+        let name = s[1..].split('/').next().unwrap();
+        name == p.0.tag() || name == format!("x-parity-{}", p.0.tag())
+    }
+    QuickCheck::new().quickcheck(prop as fn(Proto) -> bool)
+}
+
 
 // Arbitrary impls
 
@@ -76,7 +86,7 @@ struct Proto(Protocol<'static>);
 impl Arbitrary for Proto {
     fn arbitrary<G: Gen>(g: &mut G) -> Self {
         use Protocol::*;
-        match g.gen_range(0, 23) { // TODO: Add Protocol::Quic
+        match g.gen_range(0, 32) {
              0 => Proto(Dccp(g.gen())),
              1 => Proto(Dns4(Cow::Owned(SubString::arbitrary(g).0))),
              2 => Proto(Dns6(Cow::Owned(SubString::arbitrary(g).0))),
@@ -105,6 +115,19 @@ impl Arbitrary for Proto {
                 g.fill(&mut a);
                 Proto(Onion(Cow::Owned(a), g.gen()))
             }
+            23 => Proto(Dnsaddr(Cow::Owned(SubString::arbitrary(g).0))),
+            24 => Proto(Dns(Cow::Owned(SubString::arbitrary(g).0))),
+            25 => Proto(Certhash(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))),
+            26 => Proto(WebTransport),
+            27 => {
+                let mut a = [0; 35];
+                g.fill(&mut a[..]);
+                Proto(Onion3(Cow::Owned(a), g.gen()))
+            }
+            28 => Proto(QuicV1),
+            29 => Proto(Tls),
+            30 => Proto(WebRtc),
+            31 => Proto(WebRtcDirect),
              _ => panic!("outside range")
         }
     }
@@ -181,33 +204,55 @@ fn construct_success() {
     ma_valid("/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC/tcp/1234",
              "A503221220D52EBB89D85B02A284948203A62FF28389C57C9F42BEEC4EC20DB76A68911C0B0604D2",
              vec![P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC")), Tcp(1234)]);
-    ma_valid("/ip4/127.0.0.1/udp/1234", "047F000001910204D2", vec![Ip4(local.clone()), Udp(1234)]);
-    ma_valid("/ip4/127.0.0.1/udp/0", "047F00000191020000", vec![Ip4(local.clone()), Udp(0)]);
-    ma_valid("/ip4/127.0.0.1/tcp/1234", "047F0000010604D2", vec![Ip4(local.clone()), Tcp(1234)]);
+    ma_valid("/ip4/127.0.0.1/udp/1234", "047F000001910204D2", vec![Ip4(local), Udp(1234)]);
+    ma_valid("/ip4/127.0.0.1/udp/0", "047F00000191020000", vec![Ip4(local), Udp(0)]);
+    ma_valid("/ip4/127.0.0.1/tcp/1234", "047F0000010604D2", vec![Ip4(local), Tcp(1234)]);
     ma_valid("/ip4/127.0.0.1/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
              "047F000001A503221220D52EBB89D85B02A284948203A62FF28389C57C9F42BEEC4EC20DB76A68911C0B",
-             vec![Ip4(local.clone()), P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))]);
+             vec![Ip4(local), P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))]);
     ma_valid("/ip4/127.0.0.1/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC/tcp/1234",
              "047F000001A503221220D52EBB89D85B02A284948203A62FF28389C57C9F42BEEC4EC20DB76A68911C0B0604D2",
-             vec![Ip4(local.clone()), P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC")), Tcp(1234)]);
+             vec![Ip4(local), P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC")), Tcp(1234)]);
     // /unix/a/b/c/d/e,
     // /unix/stdio,
     // /ip4/1.2.3.4/tcp/80/unix/a/b/c/d/e/f,
     // /ip4/127.0.0.1/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC/tcp/1234/unix/stdio
     ma_valid("/ip6/2001:8a0:7ac5:4201:3ac9:86ff:fe31:7095/tcp/8000/ws/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
              "29200108A07AC542013AC986FFFE317095061F40DD03A503221220D52EBB89D85B02A284948203A62FF28389C57C9F42BEEC4EC20DB76A68911C0B",
-             vec![Ip6(addr6.clone()), Tcp(8000), Ws("/".into()), P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))
+             vec![Ip6(addr6), Tcp(8000), Ws("/".into()), P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))
              ]);
     ma_valid("/p2p-webrtc-star/ip4/127.0.0.1/tcp/9090/ws/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
              "9302047F000001062382DD03A503221220D52EBB89D85B02A284948203A62FF28389C57C9F42BEEC4EC20DB76A68911C0B",
-             vec![P2pWebRtcStar, Ip4(local.clone()), Tcp(9090), Ws("/".into()), P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))
+             vec![P2pWebRtcStar, Ip4(local), Tcp(9090), Ws("/".into()), P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))
              ]);
     ma_valid("/ip6/2001:8a0:7ac5:4201:3ac9:86ff:fe31:7095/tcp/8000/wss/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
              "29200108A07AC542013AC986FFFE317095061F40DE03A503221220D52EBB89D85B02A284948203A62FF28389C57C9F42BEEC4EC20DB76A68911C0B",
-             vec![Ip6(addr6.clone()), Tcp(8000), Wss("/".into()), P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))]);
+             vec![Ip6(addr6), Tcp(8000), Wss("/".into()), P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))]);
     ma_valid("/ip4/127.0.0.1/tcp/9090/p2p-circuit/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
              "047F000001062382A202A503221220D52EBB89D85B02A284948203A62FF28389C57C9F42BEEC4EC20DB76A68911C0B",
-             vec![Ip4(local.clone()), Tcp(9090), P2pCircuit, P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))]);
+             vec![Ip4(local), Tcp(9090), P2pCircuit, P2p(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))]);
+
+    ma_valid("/dnsaddr/bootstrap.libp2p.io", "3813626F6F7473747261702E6C69627032702E696F",
+             vec![Dnsaddr("bootstrap.libp2p.io".into())]);
+    ma_valid("/dns/example.com/tcp/443/wss", "350B6578616D706C652E636F6D0601BBDE03",
+             vec![Dns("example.com".into()), Tcp(443), Wss("/".into())]);
+    ma_valid("/ip4/127.0.0.1/tcp/443/wss/certhash/uEiDVLruJ2FsCooSUggOmL_KDicV8n0K-7E7CDbdqaJEcCw",
+             "047F0000010601BBDE03D203221220D52EBB89D85B02A284948203A62FF28389C57C9F42BEEC4EC20DB76A68911C0B",
+             vec![Ip4(local), Tcp(443), Wss("/".into()), Certhash(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))]);
+    ma_valid("/ip4/127.0.0.1/udp/1234/quic/webtransport", "047F000001910204D2CC03D103",
+             vec![Ip4(local), Udp(1234), Quic, WebTransport]);
+    ma_valid("/ip4/127.0.0.1/udp/1234/quic-v1/webtransport/certhash/uEiDVLruJ2FsCooSUggOmL_KDicV8n0K-7E7CDbdqaJEcCw",
+             "047F000001910204D2CD03D103D203221220D52EBB89D85B02A284948203A62FF28389C57C9F42BEEC4EC20DB76A68911C0B",
+             vec![Ip4(local), Udp(1234), QuicV1, WebTransport, Certhash(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))]);
+    ma_valid("/dns4/example.com/tcp/443/tls/ws", "360B6578616D706C652E636F6D0601BBC003DD03",
+             vec![Dns4("example.com".into()), Tcp(443), Tls, Ws("/".into())]);
+    ma_valid("/ip4/127.0.0.1/udp/1234/webrtc-direct", "047F000001910204D29802",
+             vec![Ip4(local), Udp(1234), WebRtcDirect]);
+    ma_valid("/ip4/127.0.0.1/udp/1234/quic-v1/p2p-circuit/webrtc", "047F000001910204D2CD03A2029902",
+             vec![Ip4(local), Udp(1234), QuicV1, P2pCircuit, WebRtc]);
+    ma_valid("/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd:1234",
+             "BD03ADADEC040BE047F9658668B11A504F3155001F231A37F54C4476C07FB4CC139ED7E30304D2",
+             vec![Onion3(Cow::Owned([0xad, 0xad, 0xec, 0x04, 0x0b, 0xe0, 0x47, 0xf9, 0x65, 0x86, 0x68, 0xb1, 0x1a, 0x50, 0x4f, 0x31, 0x55, 0x00, 0x1f, 0x23, 0x1a, 0x37, 0xf5, 0x4c, 0x44, 0x76, 0xc0, 0x7f, 0xb4, 0xcc, 0x13, 0x9e, 0xd7, 0xe3, 0x03]), 1234)]);
 }
 
 #[test]
@@ -227,6 +272,9 @@ fn construct_fail() {
                      // "/onion/timaq4ygg2iegci7:-1",
                      // "/onion/timaq4ygg2iegci7",
                      // "/onion/timaq4ygg2iegci@:666",
+                     "/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyy:1234",
+                     "/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd",
+                     "/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd:65536",
                      "/udp/1234/sctp",
                      "/udp/1234/udt/1234",
                      "/udp/1234/utp/1234",
@@ -20,7 +20,8 @@
 
 //! Protocol negotiation strategies for the peer acting as the dialer.
 
-use crate::protocol::{Protocol, ProtocolError, MessageIO, Message, Version};
+use crate::protocol::{Protocol, ProtocolError, MessageIO, Message, HeaderLine, Version, SIM_OPEN_ID};
+use crate::listener_select::{listener_select_proto_no_header, ListenerSelectFuture};
 use futures::{future::Either, prelude::*};
 use log::debug;
 use std::{io, iter, mem, convert::TryFrom};
@@ -166,7 +167,7 @@ where
         loop {
             match mem::replace(&mut self.state, SeqState::Done) {
                 SeqState::SendHeader { mut io } => {
-                    if io.start_send(Message::Header(self.version))?.is_not_ready() {
+                    if io.start_send(Message::Header(HeaderLine::from(self.version)))?.is_not_ready() {
                         self.state = SeqState::SendHeader { io };
                         return Ok(Async::NotReady)
                     }
@@ -184,7 +185,8 @@ where
                         self.state = SeqState::FlushProtocol { io, protocol }
                     } else {
                         match self.version {
-                            Version::V1 => self.state = SeqState::FlushProtocol { io, protocol },
+                            Version::V1 | Version::V1SimultaneousOpen =>
+                                self.state = SeqState::FlushProtocol { io, protocol },
                             Version::V1Lazy => {
                                 debug!("Dialer: Expecting proposed protocol: {}", p);
                                 let io = Negotiated::expecting(io.into_reader(), p, self.version);
@@ -213,7 +215,7 @@ where
                     };
 
                     match msg {
-                        Message::Header(v) if v == self.version => {
+                        Message::Header(v) if v == HeaderLine::from(self.version) => {
                             self.state = SeqState::AwaitProtocol { io, protocol };
                         }
                         Message::Protocol(ref p) if p.as_ref() == protocol.as_ref() => {
@@ -278,7 +280,7 @@ where
         loop {
             match mem::replace(&mut self.state, ParState::Done) {
                 ParState::SendHeader { mut io } => {
-                    if io.start_send(Message::Header(self.version))?.is_not_ready() {
+                    if io.start_send(Message::Header(HeaderLine::from(self.version)))?.is_not_ready() {
                         self.state = ParState::SendHeader { io };
                         return Ok(Async::NotReady)
                     }
@@ -312,7 +314,7 @@ where
                     };
 
                     match &msg {
-                        Message::Header(v) if v == &self.version => {
+                        Message::Header(v) if *v == HeaderLine::from(self.version) => {
                             self.state = ParState::RecvProtocols { io }
                         }
                         Message::Protocols(supported) => {
@@ -343,3 +345,279 @@ where
     }
 }
 
+
+/// The role of a peer in the negotiation of a protocol on a connection that may
+/// have been opened simultaneously by both peers.
+///
+/// See [`dialer_select_proto_simopen`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Role {
+    /// The peer proposed the negotiated protocol, like a dialer.
+    Initiator,
+    /// The peer confirmed the negotiated protocol, like a listener.
+    Responder,
+}
+
+/// Returns a `Future` that negotiates a protocol on the given I/O stream
+/// for a peer acting as the _dialer_ of a connection that may have been opened
+/// simultaneously by the remote, following [`Version::V1SimultaneousOpen`].
+///
+/// The returned `Future` resolves with the name of the negotiated protocol,
+/// a [`Negotiated`] I/O stream and the [`Role`] of the local peer in the
+/// negotiation. The role is always [`Role::Initiator`] if the remote is a
+/// listener. Otherwise the roles of both peers are determined by random
+/// nonces, such that exactly one of them is the initiator. Identical nonces
+/// are redrawn by both peers.
+///
+/// The given protocols are proposed one-by-one by the initiator, as with
+/// [`dialer_select_proto_serial`], whereas a responder confirms the first
+/// protocol proposed by the remote that it supports.
+pub fn dialer_select_proto_simopen<R, I>(
+    inner: R,
+    protocols: I,
+) -> DialerSelectSimOpen<R, I::IntoIter>
+where
+    R: AsyncRead + AsyncWrite,
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>
+{
+    DialerSelectSimOpen {
+        protocols: Some(protocols.into_iter()),
+        state: SimOpenState::SendHeader {
+            io: MessageIO::new(inner),
+        }
+    }
+}
+
+/// A `Future` returned by [`dialer_select_proto_simopen`] which resolves
+/// the roles of both peers before negotiating a protocol.
+pub struct DialerSelectSimOpen<R, I>
+where
+    R: AsyncRead + AsyncWrite,
+    I: Iterator,
+    I::Item: AsRef<[u8]>
+{
+    protocols: Option<I>,
+    state: SimOpenState<R, I>,
+}
+
+enum SimOpenState<R, I>
+where
+    R: AsyncRead + AsyncWrite,
+    I: Iterator,
+    I::Item: AsRef<[u8]>
+{
+    SendHeader { io: MessageIO<R> },
+    SendSimOpen { io: MessageIO<R> },
+    FlushSimOpen { io: MessageIO<R> },
+    AwaitSimOpen { io: MessageIO<R> },
+    SendNonce { io: MessageIO<R>, nonce: u64 },
+    FlushNonce { io: MessageIO<R>, nonce: u64 },
+    AwaitNonce { io: MessageIO<R>, nonce: u64 },
+    SendRole { io: MessageIO<R>, role: Role },
+    FlushRole { io: MessageIO<R>, role: Role },
+    AwaitRole { io: MessageIO<R>, role: Role },
+    Initiator { future: Box<DialerSelectSeq<R, I>> },
+    Responder { future: Box<ListenerSelectFuture<R, I::Item>> },
+    Done
+}
+
+impl<R, I> DialerSelectSimOpen<R, I>
+where
+    R: AsyncRead + AsyncWrite,
+    I: Iterator,
+    I::Item: AsRef<[u8]>
+{
+    /// Continues the negotiation as the initiator, after the headers
+    /// have been exchanged.
+    fn initiator(&mut self, io: MessageIO<R>) -> Result<SimOpenState<R, I>, NegotiationError> {
+        let mut protocols = self.protocols.take()
+            .expect("The protocols are only taken once the role is known.")
+            .peekable();
+        let protocol = protocols.next().ok_or(NegotiationError::Failed)?;
+        Ok(SimOpenState::Initiator {
+            future: Box::new(DialerSelectSeq {
+                protocols,
+                state: SeqState::SendProtocol { io, protocol },
+                version: Version::V1,
+            })
+        })
+    }
+
+    /// Continues the negotiation as the responder, after the headers
+    /// have been exchanged.
+    fn responder(&mut self, io: MessageIO<R>) -> SimOpenState<R, I> {
+        let protocols = self.protocols.take()
+            .expect("The protocols are only taken once the role is known.");
+        SimOpenState::Responder {
+            future: Box::new(listener_select_proto_no_header(io, protocols))
+        }
+    }
+}
+
+impl<R, I> Future for DialerSelectSimOpen<R, I>
+where
+    R: AsyncRead + AsyncWrite,
+    I: Iterator,
+    I::Item: AsRef<[u8]> + Clone
+{
+    type Item = (I::Item, Negotiated<R>, Role);
+    type Error = NegotiationError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, SimOpenState::Done) {
+                SimOpenState::SendHeader { mut io } => {
+                    if io.start_send(Message::Header(HeaderLine::V1))?.is_not_ready() {
+                        self.state = SimOpenState::SendHeader { io };
+                        return Ok(Async::NotReady)
+                    }
+                    self.state = SimOpenState::SendSimOpen { io };
+                }
+                SimOpenState::SendSimOpen { mut io } => {
+                    let p = Protocol::try_from(SIM_OPEN_ID)?;
+                    if io.start_send(Message::Protocol(p))?.is_not_ready() {
+                        self.state = SimOpenState::SendSimOpen { io };
+                        return Ok(Async::NotReady)
+                    }
+                    self.state = SimOpenState::FlushSimOpen { io };
+                }
+                SimOpenState::FlushSimOpen { mut io } => {
+                    if io.poll_complete()?.is_not_ready() {
+                        self.state = SimOpenState::FlushSimOpen { io };
+                        return Ok(Async::NotReady)
+                    }
+                    self.state = SimOpenState::AwaitSimOpen { io };
+                }
+                SimOpenState::AwaitSimOpen { mut io } => {
+                    let msg = match io.poll()? {
+                        Async::NotReady => {
+                            self.state = SimOpenState::AwaitSimOpen { io };
+                            return Ok(Async::NotReady)
+                        }
+                        Async::Ready(None) =>
+                            return Err(NegotiationError::from(
+                                io::Error::from(io::ErrorKind::UnexpectedEof))),
+                        Async::Ready(Some(msg)) => msg,
+                    };
+
+                    match msg {
+                        Message::Header(HeaderLine::V1) => {
+                            self.state = SimOpenState::AwaitSimOpen { io };
+                        }
+                        Message::Protocol(ref p) if p.as_ref() == SIM_OPEN_ID => {
+                            debug!("Dialer: Remote is a dialer, too. Selecting roles.");
+                            let nonce = rand::random();
+                            self.state = SimOpenState::SendNonce { io, nonce };
+                        }
+                        Message::NotAvailable => {
+                            debug!("Dialer: Remote is a listener.");
+                            self.state = self.initiator(io)?;
+                        }
+                        _ => return Err(ProtocolError::InvalidMessage.into())
+                    }
+                }
+                SimOpenState::SendNonce { mut io, nonce } => {
+                    if io.start_send(Message::Select(nonce))?.is_not_ready() {
+                        self.state = SimOpenState::SendNonce { io, nonce };
+                        return Ok(Async::NotReady)
+                    }
+                    self.state = SimOpenState::FlushNonce { io, nonce };
+                }
+                SimOpenState::FlushNonce { mut io, nonce } => {
+                    if io.poll_complete()?.is_not_ready() {
+                        self.state = SimOpenState::FlushNonce { io, nonce };
+                        return Ok(Async::NotReady)
+                    }
+                    self.state = SimOpenState::AwaitNonce { io, nonce };
+                }
+                SimOpenState::AwaitNonce { mut io, nonce } => {
+                    let remote = match io.poll()? {
+                        Async::NotReady => {
+                            self.state = SimOpenState::AwaitNonce { io, nonce };
+                            return Ok(Async::NotReady)
+                        }
+                        Async::Ready(None) =>
+                            return Err(NegotiationError::from(
+                                io::Error::from(io::ErrorKind::UnexpectedEof))),
+                        Async::Ready(Some(Message::Select(remote))) => remote,
+                        Async::Ready(Some(_)) =>
+                            return Err(ProtocolError::InvalidMessage.into()),
+                    };
+
+                    let role = if nonce > remote {
+                        Role::Initiator
+                    } else if nonce < remote {
+                        Role::Responder
+                    } else {
+                        // The remote sees the same nonces and draws a new
+                        // nonce as well.
+                        debug!("Dialer: Identical nonces. Retrying the selection of roles.");
+                        let nonce = rand::random();
+                        self.state = SimOpenState::SendNonce { io, nonce };
+                        continue
+                    };
+                    debug!("Dialer: Selected role: {:?}", role);
+                    self.state = SimOpenState::SendRole { io, role };
+                }
+                SimOpenState::SendRole { mut io, role } => {
+                    let msg = match role {
+                        Role::Initiator => Message::Initiator,
+                        Role::Responder => Message::Responder,
+                    };
+                    if io.start_send(msg)?.is_not_ready() {
+                        self.state = SimOpenState::SendRole { io, role };
+                        return Ok(Async::NotReady)
+                    }
+                    self.state = SimOpenState::FlushRole { io, role };
+                }
+                SimOpenState::FlushRole { mut io, role } => {
+                    if io.poll_complete()?.is_not_ready() {
+                        self.state = SimOpenState::FlushRole { io, role };
+                        return Ok(Async::NotReady)
+                    }
+                    self.state = SimOpenState::AwaitRole { io, role };
+                }
+                SimOpenState::AwaitRole { mut io, role } => {
+                    let msg = match io.poll()? {
+                        Async::NotReady => {
+                            self.state = SimOpenState::AwaitRole { io, role };
+                            return Ok(Async::NotReady)
+                        }
+                        Async::Ready(None) =>
+                            return Err(NegotiationError::from(
+                                io::Error::from(io::ErrorKind::UnexpectedEof))),
+                        Async::Ready(Some(msg)) => msg,
+                    };
+
+                    self.state = match (role, msg) {
+                        (Role::Initiator, Message::Responder) => self.initiator(io)?,
+                        (Role::Responder, Message::Initiator) => self.responder(io),
+                        _ => return Err(ProtocolError::InvalidMessage.into())
+                    };
+                }
+                SimOpenState::Initiator { mut future } => {
+                    match future.poll()? {
+                        Async::Ready((protocol, io)) =>
+                            return Ok(Async::Ready((protocol, io, Role::Initiator))),
+                        Async::NotReady => {
+                            self.state = SimOpenState::Initiator { future };
+                            return Ok(Async::NotReady)
+                        }
+                    }
+                }
+                SimOpenState::Responder { mut future } => {
+                    match future.poll()? {
+                        Async::Ready((protocol, io)) =>
+                            return Ok(Async::Ready((protocol, io, Role::Responder))),
+                        Async::NotReady => {
+                            self.state = SimOpenState::Responder { future };
+                            return Ok(Async::NotReady)
+                        }
+                    }
+                }
+                SimOpenState::Done => panic!("SimOpenState::poll called after completion")
+            }
+        }
+    }
+}
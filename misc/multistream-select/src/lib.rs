@@ -110,6 +110,12 @@ mod tests;
 
 pub use self::negotiated::{Negotiated, NegotiatedComplete, NegotiationError};
 pub use self::protocol::{ProtocolError, Version};
-pub use self::dialer_select::{dialer_select_proto, DialerSelectFuture};
+pub use self::dialer_select::{
+    dialer_select_proto,
+    dialer_select_proto_simopen,
+    DialerSelectFuture,
+    DialerSelectSimOpen,
+    Role
+};
 pub use self::listener_select::{listener_select_proto, ListenerSelectFuture};
 
@@ -22,7 +22,7 @@
 //! in a multistream-select protocol negotiation.
 
 use futures::prelude::*;
-use crate::protocol::{Protocol, ProtocolError, MessageIO, Message, Version};
+use crate::protocol::{Protocol, ProtocolError, MessageIO, Message, HeaderLine};
 use log::{debug, warn};
 use smallvec::SmallVec;
 use std::{io, iter::FromIterator, mem, convert::TryFrom};
@@ -44,6 +44,36 @@ where
     R: AsyncRead + AsyncWrite,
     I: IntoIterator,
     I::Item: AsRef<[u8]>
+{
+    ListenerSelectFuture {
+        protocols: supported(protocols),
+        state: State::RecvHeader {
+            io: MessageIO::new(inner)
+        }
+    }
+}
+
+/// Like [`listener_select_proto`], for an I/O stream on which the
+/// multistream-select headers have already been exchanged.
+pub(crate) fn listener_select_proto_no_header<R, I>(
+    io: MessageIO<R>,
+    protocols: I,
+) -> ListenerSelectFuture<R, I::Item>
+where
+    R: AsyncRead + AsyncWrite,
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>
+{
+    ListenerSelectFuture {
+        protocols: supported(protocols),
+        state: State::RecvMessage { io }
+    }
+}
+
+fn supported<I>(protocols: I) -> SmallVec<[(I::Item, Protocol); 8]>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>
 {
     let protocols = protocols.into_iter().filter_map(|n|
         match Protocol::try_from(n.as_ref()) {
@@ -54,12 +84,7 @@ where
                 None
             }
         });
-    ListenerSelectFuture {
-        protocols: SmallVec::from_iter(protocols),
-        state: State::RecvHeader {
-            io: MessageIO::new(inner)
-        }
-    }
+    SmallVec::from_iter(protocols)
 }
 
 /// The `Future` returned by [`listener_select_proto`] that performs a
@@ -81,7 +106,7 @@ where
     N: AsRef<[u8]>
 {
     RecvHeader { io: MessageIO<R> },
-    SendHeader { io: MessageIO<R>, version: Version },
+    SendHeader { io: MessageIO<R>, header: HeaderLine },
     RecvMessage { io: MessageIO<R> },
     SendMessage {
         io: MessageIO<R>,
@@ -105,8 +130,8 @@ where
             match mem::replace(&mut self.state, State::Done) {
                 State::RecvHeader { mut io } => {
                     match io.poll()? {
-                        Async::Ready(Some(Message::Header(version))) => {
-                            self.state = State::SendHeader { io, version }
+                        Async::Ready(Some(Message::Header(header))) => {
+                            self.state = State::SendHeader { io, header }
                         }
                         Async::Ready(Some(_)) => {
                             return Err(ProtocolError::InvalidMessage.into())
@@ -121,13 +146,13 @@ where
                         }
                     }
                 }
-                State::SendHeader { mut io, version } => {
-                    if io.start_send(Message::Header(version))?.is_not_ready() {
+                State::SendHeader { mut io, header } => {
+                    if io.start_send(Message::Header(header))?.is_not_ready() {
                         return Ok(Async::NotReady)
                     }
-                    self.state = match version {
-                        Version::V1 => State::Flush { io },
-                        Version::V1Lazy => State::RecvMessage { io },
+                    self.state = match header {
+                        HeaderLine::V1 => State::Flush { io },
+                        HeaderLine::V1Lazy => State::RecvMessage { io },
                     }
                 }
                 State::RecvMessage { mut io } => {
@@ -19,7 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use bytes::{BytesMut, Buf};
-use crate::protocol::{Protocol, MessageReader, Message, Version, ProtocolError};
+use crate::protocol::{Protocol, MessageReader, Message, Version, ProtocolError, HeaderLine};
 use futures::{prelude::*, Async, try_ready};
 use log::debug;
 use tokio_io::{AsyncRead, AsyncWrite};
@@ -68,6 +68,13 @@ impl<TInner> Negotiated<TInner> {
         Negotiated { state: State::Completed { io, remaining } }
     }
 
+    /// Creates a `Negotiated` for an I/O stream whose protocol has been
+    /// agreed on by other means than protocol negotiation, e.g. during
+    /// a preceding security handshake.
+    pub fn agreed(io: TInner) -> Self {
+        Self::completed(io, BytesMut::new())
+    }
+
     /// Creates a `Negotiated` in state [`State::Expecting`] that is still
     /// expecting confirmation of the given `protocol`.
     pub(crate) fn expecting(io: MessageReader<TInner>, protocol: Protocol, version: Version) -> Self {
@@ -119,7 +126,7 @@ impl<TInner> Negotiated<TInner> {
                     };
 
                     if let Message::Header(v) = &msg {
-                        if v == &version {
+                        if *v == HeaderLine::from(version) {
                             self.state = State::Expecting { io, protocol, version };
                             continue
                         }
@@ -50,12 +50,23 @@ const MAX_PROTOCOL_LEN: usize = 140;
 
 /// The encoded form of a multistream-select 1.0.0 header message.
 const MSG_MULTISTREAM_1_0: &[u8] = b"/multistream/1.0.0\n";
-/// The encoded form of a multistream-select 1.0.0 header message.
+/// The encoded form of the header message of the lazy variant of multistream-select 1.0.0
+/// sent by earlier versions of this library.
 const MSG_MULTISTREAM_1_0_LAZY: &[u8] = b"/multistream-lazy/1\n";
 /// The encoded form of a multistream-select 'na' message.
 const MSG_PROTOCOL_NA: &[u8] = b"na\n";
 /// The encoded form of a multistream-select 'ls' message.
 const MSG_LS: &[u8] = b"ls\n";
+/// The prefix of the encoded form of a simultaneous open 'select' message.
+const MSG_SELECT_PREFIX: &[u8] = b"select:";
+/// The encoded form of a simultaneous open 'initiator' message.
+const MSG_INITIATOR: &[u8] = b"initiator\n";
+/// The encoded form of a simultaneous open 'responder' message.
+const MSG_RESPONDER: &[u8] = b"responder\n";
+
+/// The protocol proposed by dialers of connections that may have been
+/// opened simultaneously by both peers. See [`Version::V1SimultaneousOpen`].
+pub(crate) const SIM_OPEN_ID: &[u8] = b"/libp2p/simultaneous-connect";
 
 /// Supported multistream-select protocol versions.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -78,12 +89,9 @@ pub enum Version {
     /// with the first round of application protocol data (or an attempt
     /// is made to read from the `Negotiated` I/O stream).
     ///
-    /// A listener receiving a `V1Lazy` header will similarly delay sending
-    /// of the protocol confirmation.  Though typically the listener will need
-    /// to read the request data before sending its response, thus triggering
-    /// sending of the protocol confirmation, which, in absence of additional
-    /// buffering on lower layers will result in at least two response frames
-    /// to be sent.
+    /// A `V1Lazy` dialer sends the same header as a `V1` dialer and can thus
+    /// negotiate protocols with any `V1` listener. Listeners cannot tell both
+    /// apart and always respond like `V1` listeners.
     ///
     /// `V1Lazy` is specific to `rust-libp2p`: While the wire protocol
     /// is identical to `V1`, delayed sending of protocol negotiation frames
@@ -94,7 +102,8 @@ pub enum Version {
     ///      first waiting for confirmation of that header. Since the listener
     ///      delays sending the protocol confirmation, a deadlock situation may
     ///      otherwise occurs that is only resolved by a timeout. This assumption
-    ///      is trivially satisfied if both peers support and use `V1Lazy`.
+    ///      is satisfied by the listeners of this library and by other common
+    ///      implementations of `V1`.
     ///
     ///   2. When nesting multiple protocol negotiations, the listener is either
     ///      known to support all of the dialer's optimistically chosen protocols
@@ -108,6 +117,26 @@ pub enum Version {
     /// [1]: https://github.com/multiformats/go-multistream/issues/20
     /// [2]: https://github.com/libp2p/rust-libp2p/pull/1212
     V1Lazy,
+    /// Version 1 of the multistream-select protocol with the simultaneous open
+    /// extension, for connections that may have been opened by both peers at
+    /// the same time, e.g. when punching holes through NATs with TCP. See [1].
+    ///
+    /// Both ends of such a connection consider themselves the dialer. To
+    /// resolve the ambiguity, a dialer using [`dialer_select_proto_simopen`]
+    /// first proposes the `/libp2p/simultaneous-connect` protocol. A listener
+    /// rejects it, leaving the dialer the initiator of the negotiation. If the
+    /// remote is a dialer, too, both peers exchange random nonces and the one
+    /// with the larger nonce becomes the initiator, whereas the other proceeds
+    /// like a listener. See [`Role`].
+    ///
+    /// All other negotiations, e.g. on substreams, are not ambiguous and
+    /// dialers use plain [`Version::V1`] for them.
+    ///
+    /// [1]: https://github.com/libp2p/specs/pull/196
+    ///
+    /// [`dialer_select_proto_simopen`]: crate::dialer_select_proto_simopen
+    /// [`Role`]: crate::Role
+    V1SimultaneousOpen,
     // Draft: https://github.com/libp2p/specs/pull/95
     // V2,
 }
@@ -118,6 +147,25 @@ impl Default for Version {
     }
 }
 
+/// The header line of a multistream-select header message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HeaderLine {
+    /// The `/multistream/1.0.0` header of version 1, sent for both [`Version::V1`]
+    /// and [`Version::V1Lazy`].
+    V1,
+    /// The `/multistream-lazy/1` header that earlier versions of this library sent
+    /// for [`Version::V1Lazy`]. It is only ever sent back to dialers using it.
+    V1Lazy,
+}
+
+impl From<Version> for HeaderLine {
+    fn from(v: Version) -> HeaderLine {
+        match v {
+            Version::V1 | Version::V1Lazy | Version::V1SimultaneousOpen => HeaderLine::V1,
+        }
+    }
+}
+
 /// A protocol (name) exchanged during protocol negotiation.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Protocol(Bytes);
@@ -161,7 +209,7 @@ impl fmt::Display for Protocol {
 pub enum Message {
     /// A header message identifies the multistream-select protocol
     /// that the sender wishes to speak.
-    Header(Version),
+    Header(HeaderLine),
     /// A protocol message identifies a protocol request or acknowledgement.
     Protocol(Protocol),
     /// A message through which a peer requests the complete list of
@@ -171,18 +219,26 @@ pub enum Message {
     Protocols(Vec<Protocol>),
     /// A message signaling that a requested protocol is not available.
     NotAvailable,
+    /// A message carrying the random nonce of a peer during simultaneous open.
+    Select(u64),
+    /// A message through which the peer with the larger nonce confirms
+    /// that it is the initiator after simultaneous open.
+    Initiator,
+    /// A message through which the peer with the smaller nonce confirms
+    /// that it is the responder after simultaneous open.
+    Responder,
 }
 
 impl Message {
     /// Encodes a `Message` into its byte representation.
     pub fn encode(&self, dest: &mut BytesMut) -> Result<(), ProtocolError> {
         match self {
-            Message::Header(Version::V1) => {
+            Message::Header(HeaderLine::V1) => {
                 dest.reserve(MSG_MULTISTREAM_1_0.len());
                 dest.put(MSG_MULTISTREAM_1_0);
                 Ok(())
             }
-            Message::Header(Version::V1Lazy) => {
+            Message::Header(HeaderLine::V1Lazy) => {
                 dest.reserve(MSG_MULTISTREAM_1_0_LAZY.len());
                 dest.put(MSG_MULTISTREAM_1_0_LAZY);
                 Ok(())
@@ -216,17 +272,35 @@ impl Message {
                 dest.put(MSG_PROTOCOL_NA);
                 Ok(())
             }
+            Message::Select(nonce) => {
+                let nonce = nonce.to_string();
+                dest.reserve(MSG_SELECT_PREFIX.len() + nonce.len() + 1);
+                dest.put(MSG_SELECT_PREFIX);
+                dest.put(nonce.as_bytes());
+                dest.put(&b"\n"[..]);
+                Ok(())
+            }
+            Message::Initiator => {
+                dest.reserve(MSG_INITIATOR.len());
+                dest.put(MSG_INITIATOR);
+                Ok(())
+            }
+            Message::Responder => {
+                dest.reserve(MSG_RESPONDER.len());
+                dest.put(MSG_RESPONDER);
+                Ok(())
+            }
         }
     }
 
     /// Decodes a `Message` from its byte representation.
     pub fn decode(mut msg: Bytes) -> Result<Message, ProtocolError> {
         if msg == MSG_MULTISTREAM_1_0_LAZY {
-            return Ok(Message::Header(Version::V1Lazy))
+            return Ok(Message::Header(HeaderLine::V1Lazy))
         }
 
         if msg == MSG_MULTISTREAM_1_0 {
-            return Ok(Message::Header(Version::V1))
+            return Ok(Message::Header(HeaderLine::V1))
         }
 
         if msg.get(0) == Some(&b'/') && msg.last() == Some(&b'\n') && msg.len() <= MAX_PROTOCOL_LEN {
@@ -242,6 +316,22 @@ impl Message {
             return Ok(Message::ListProtocols)
         }
 
+        if msg.starts_with(MSG_SELECT_PREFIX) && msg.last() == Some(&b'\n') {
+            let nonce = &msg[MSG_SELECT_PREFIX.len() .. msg.len() - 1];
+            return std::str::from_utf8(nonce).ok()
+                .and_then(|n| n.parse().ok())
+                .map(Message::Select)
+                .ok_or(ProtocolError::InvalidMessage)
+        }
+
+        if msg == MSG_INITIATOR {
+            return Ok(Message::Initiator)
+        }
+
+        if msg == MSG_RESPONDER {
+            return Ok(Message::Responder)
+        }
+
         // At this point, it must be a varint number of protocols, i.e.
         // a `Protocols` message.
         let (num_protocols, mut remaining) = uvi::decode::usize(&msg)?;
@@ -511,12 +601,15 @@ mod tests {
 
     impl Arbitrary for Message {
         fn arbitrary<G: Gen>(g: &mut G) -> Message {
-            match g.gen_range(0, 5) {
-                0 => Message::Header(Version::V1),
+            match g.gen_range(0, 8) {
+                0 => Message::Header(HeaderLine::V1),
                 1 => Message::NotAvailable,
                 2 => Message::ListProtocols,
                 3 => Message::Protocol(Protocol::arbitrary(g)),
                 4 => Message::Protocols(Vec::arbitrary(g)),
+                5 => Message::Select(g.gen()),
+                6 => Message::Initiator,
+                7 => Message::Responder,
                 _ => panic!()
             }
         }
@@ -22,9 +22,9 @@
 
 #![cfg(test)]
 
-use crate::{Version, NegotiationError};
+use crate::{Version, NegotiationError, ProtocolError, Role};
 use crate::dialer_select::{dialer_select_proto_parallel, dialer_select_proto_serial};
-use crate::{dialer_select_proto, listener_select_proto};
+use crate::{dialer_select_proto, dialer_select_proto_simopen, listener_select_proto};
 use futures::prelude::*;
 use tokio::runtime::current_thread::Runtime;
 use tokio_tcp::{TcpListener, TcpStream};
@@ -186,3 +186,232 @@ fn select_proto_serial() {
     run(Version::V1);
     run(Version::V1Lazy);
 }
+
+/// A minimal, strict `V1` responder as found in other implementations, which reads and answers
+/// one negotiation message at a time on a blocking socket.
+mod strict {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    pub fn read_msg(s: &mut TcpStream) -> Vec<u8> {
+        let mut len = [0; 1];
+        s.read_exact(&mut len).unwrap();
+        assert!(len[0] < 0x80, "Test messages fit in a single byte length prefix");
+        let mut msg = vec![0; usize::from(len[0])];
+        s.read_exact(&mut msg).unwrap();
+        msg
+    }
+
+    pub fn write_msg(s: &mut TcpStream, msg: &[u8]) {
+        s.write_all(&[msg.len() as u8]).unwrap();
+        s.write_all(msg).unwrap();
+    }
+}
+
+#[test]
+fn interop_with_strict_v1_responder() {
+    fn run(version: Version) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let listener_addr = listener.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            assert_eq!(strict::read_msg(&mut s), b"/multistream/1.0.0\n");
+            strict::write_msg(&mut s, b"/multistream/1.0.0\n");
+            loop {
+                let proposal = strict::read_msg(&mut s);
+                if proposal == b"/proto2\n" {
+                    strict::write_msg(&mut s, &proposal);
+                    break
+                }
+                strict::write_msg(&mut s, b"na\n");
+            }
+            let mut ping = [0; 4];
+            std::io::Read::read_exact(&mut s, &mut ping).unwrap();
+            assert_eq!(&ping, b"ping");
+            std::io::Write::write_all(&mut s, b"pong").unwrap();
+        });
+
+        let client = TcpStream::connect(&listener_addr)
+            .from_err()
+            .and_then(move |connec| {
+                let protos = vec![b"/proto3", b"/proto2"];
+                dialer_select_proto(connec, protos, version)
+            })
+            .and_then(|(proto, io)| {
+                nio::write_all(io, b"ping").from_err().map(move |(io, _)| (proto, io))
+            })
+            .and_then(|(proto, io)| nio::flush(io).from_err().map(move |io| (proto, io)))
+            .and_then(|(proto, io)| {
+                nio::read_exact(io, [0; 4]).from_err().map(move |(_, msg)| {
+                    assert_eq!(&msg, b"pong");
+                    proto
+                })
+            });
+
+        let mut rt = Runtime::new().unwrap();
+        let dialer_chosen = rt.block_on(client).unwrap();
+        server.join().unwrap();
+        assert_eq!(dialer_chosen, b"/proto2");
+    }
+
+    run(Version::V1);
+    run(Version::V1Lazy);
+}
+
+#[test]
+fn v1_lazy_sends_proposal_with_payload() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let listener_addr = listener.local_addr().unwrap();
+
+    // The responder reads the payload before sending anything, which only
+    // succeeds if the dialer doesn't wait for a confirmation.
+    let server = std::thread::spawn(move || {
+        let (mut s, _) = listener.accept().unwrap();
+        assert_eq!(strict::read_msg(&mut s), b"/multistream/1.0.0\n");
+        assert_eq!(strict::read_msg(&mut s), b"/proto1\n");
+        let mut ping = [0; 4];
+        std::io::Read::read_exact(&mut s, &mut ping).unwrap();
+        assert_eq!(&ping, b"ping");
+        strict::write_msg(&mut s, b"/multistream/1.0.0\n");
+        strict::write_msg(&mut s, b"/proto1\n");
+        std::io::Write::write_all(&mut s, b"pong").unwrap();
+    });
+
+    let client = TcpStream::connect(&listener_addr)
+        .from_err()
+        .and_then(move |connec| dialer_select_proto(connec, vec![b"/proto1"], Version::V1Lazy))
+        .and_then(|(_, io)| nio::write_all(io, b"ping").from_err())
+        .and_then(|(io, _)| nio::flush(io).from_err())
+        .and_then(|io| nio::read_exact(io, [0; 4]).from_err())
+        .map(|(_, msg)| assert_eq!(&msg, b"pong"));
+
+    let mut rt = Runtime::new().unwrap();
+    rt.block_on(client).unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn v1_lazy_proposal_rejected_by_strict_v1_responder() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let listener_addr = listener.local_addr().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let (mut s, _) = listener.accept().unwrap();
+        assert_eq!(strict::read_msg(&mut s), b"/multistream/1.0.0\n");
+        strict::write_msg(&mut s, b"/multistream/1.0.0\n");
+        assert_eq!(strict::read_msg(&mut s), b"/proto1\n");
+        strict::write_msg(&mut s, b"na\n");
+    });
+
+    let client = TcpStream::connect(&listener_addr)
+        .from_err()
+        .and_then(move |connec| dialer_select_proto(connec, vec![b"/proto1"], Version::V1Lazy))
+        .and_then(|(_, io)| nio::read_exact(io, [0; 4]).from_err::<NegotiationError>());
+
+    let mut rt = Runtime::new().unwrap();
+    match rt.block_on(client) {
+        Err(NegotiationError::ProtocolError(ProtocolError::IoError(e))) => {
+            let inner = e.into_inner().expect("negotiation error");
+            match inner.downcast_ref::<NegotiationError>() {
+                Some(NegotiationError::Failed) => {}
+                e => panic!("Unexpected error: {:?}", e)
+            }
+        }
+        Ok(_) => panic!("Unexpected success"),
+        Err(e) => panic!("Unexpected error: {:?}", e),
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn listener_answers_legacy_lazy_header() {
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let listener_addr = listener.local_addr().unwrap();
+
+    let server = listener
+        .incoming()
+        .into_future()
+        .map(|s| s.0.unwrap())
+        .map_err(|(e, _)| e.into())
+        .and_then(move |connec| listener_select_proto(connec, vec![b"/proto1"]))
+        .and_then(|(proto, io)| io.complete().map(move |_| proto));
+
+    let client = std::thread::spawn(move || {
+        let mut s = std::net::TcpStream::connect(listener_addr).unwrap();
+        strict::write_msg(&mut s, b"/multistream-lazy/1\n");
+        strict::write_msg(&mut s, b"/proto1\n");
+        assert_eq!(strict::read_msg(&mut s), b"/multistream-lazy/1\n");
+        assert_eq!(strict::read_msg(&mut s), b"/proto1\n");
+    });
+
+    let mut rt = Runtime::new().unwrap();
+    let listener_chosen = rt.block_on(server).unwrap();
+    client.join().unwrap();
+    assert_eq!(listener_chosen, b"/proto1");
+}
+
+#[test]
+fn simultaneous_open() {
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let listener_addr = listener.local_addr().unwrap();
+
+    // Both ends of the connection act as dialers.
+    let server = listener
+        .incoming()
+        .into_future()
+        .map(|s| s.0.unwrap())
+        .map_err(|(e, _)| e.into())
+        .and_then(move |connec| {
+            let protos = vec![b"/proto1", b"/proto2"];
+            dialer_select_proto_simopen(connec, protos)
+        })
+        .and_then(|(proto, io, role)| io.complete().map(move |_| (proto, role)));
+
+    let client = TcpStream::connect(&listener_addr)
+        .from_err()
+        .and_then(move |connec| {
+            let protos = vec![b"/proto3", b"/proto2"];
+            dialer_select_proto_simopen(connec, protos)
+        })
+        .and_then(|(proto, io, role)| io.complete().map(move |_| (proto, role)));
+
+    let mut rt = Runtime::new().unwrap();
+    let ((server_chosen, server_role), (client_chosen, client_role)) =
+        rt.block_on(server.join(client)).unwrap();
+    assert_eq!(server_chosen, b"/proto2");
+    assert_eq!(client_chosen, b"/proto2");
+    assert_ne!(server_role, client_role);
+}
+
+#[test]
+fn simultaneous_open_with_listener() {
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let listener_addr = listener.local_addr().unwrap();
+
+    let server = listener
+        .incoming()
+        .into_future()
+        .map(|s| s.0.unwrap())
+        .map_err(|(e, _)| e.into())
+        .and_then(move |connec| {
+            let protos = vec![b"/proto1", b"/proto2"];
+            listener_select_proto(connec, protos)
+        })
+        .and_then(|(proto, io)| io.complete().map(move |_| proto));
+
+    let client = TcpStream::connect(&listener_addr)
+        .from_err()
+        .and_then(move |connec| {
+            let protos = vec![b"/proto3", b"/proto2"];
+            dialer_select_proto_simopen(connec, protos)
+        })
+        .and_then(|(proto, io, role)| io.complete().map(move |_| (proto, role)));
+
+    let mut rt = Runtime::new().unwrap();
+    let (server_chosen, (client_chosen, client_role)) =
+        rt.block_on(server.join(client)).unwrap();
+    assert_eq!(server_chosen, b"/proto2");
+    assert_eq!(client_chosen, b"/proto2");
+    assert_eq!(client_role, Role::Initiator);
+}
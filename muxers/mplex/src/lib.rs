@@ -20,19 +20,20 @@
 
 mod codec;
 
-use std::{cmp, iter, mem, pin::Pin, task::Context, task::Poll};
+use std::{cmp, fmt, iter, mem, pin::Pin, task::Context, task::Poll};
 use std::io::{Error as IoError, ErrorKind as IoErrorKind};
-use std::sync::Arc;
+use std::sync::{Arc, atomic::{AtomicU64, Ordering}};
 use std::task::Waker;
 use bytes::Bytes;
 use libp2p_core::{
     Endpoint,
     StreamMuxer,
+    muxing::{MuxerStats, SubstreamPriority, SubstreamStats},
     upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo},
 };
 use log::{debug, trace};
 use parking_lot::Mutex;
-use fnv::FnvHashSet;
+use fnv::{FnvHashMap, FnvHashSet};
 use futures::{prelude::*, future, ready, stream::Fuse};
 use futures::task::{ArcWake, waker_ref};
 use futures_codec::Framed;
@@ -42,13 +43,21 @@ use futures_codec::Framed;
 pub struct MplexConfig {
     /// Maximum number of simultaneously-open substreams.
     max_substreams: usize,
+    /// Maximum number of simultaneously-open substreams opened by the remote.
+    max_inbound_substreams: usize,
+    /// Called with the ID of the inbound substreams refused because of `max_inbound_substreams`.
+    on_substream_refused: Option<RefusalCallback>,
     /// Maximum number of elements in the internal buffer.
     max_buffer_len: usize,
+    /// Maximum number of elements in the internal buffer for a single substream.
+    max_substream_buffer_len: usize,
     /// Behaviour when the buffer size limit is reached.
     max_buffer_behaviour: MaxBufferBehaviour,
     /// When sending data, split it into frames whose maximum size is this value
     /// (max 1MByte, as per the Mplex spec).
     split_send_size: usize,
+    /// Counters shared by the connections using this configuration.
+    counters: MplexCounters,
 }
 
 impl MplexConfig {
@@ -68,6 +77,27 @@ impl MplexConfig {
         self
     }
 
+    /// Sets the maximum number of simultaneously opened substreams opened by the remote, after
+    /// which the substreams the remote opens are reset, without closing the connection.
+    ///
+    /// This protects against remotes flooding the connection with substreams. Not limited by
+    /// default.
+    #[inline]
+    pub fn max_inbound_substreams(&mut self, max: usize) -> &mut Self {
+        self.max_inbound_substreams = max;
+        self
+    }
+
+    /// Sets a callback called with the ID of each inbound substream reset because of the
+    /// limit set with `max_inbound_substreams`.
+    pub fn on_substream_refused<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(u32) + Send + Sync + 'static
+    {
+        self.on_substream_refused = Some(RefusalCallback(Arc::new(callback)));
+        self
+    }
+
     /// Sets the maximum number of pending incoming messages.
     ///
     /// A limit is necessary in order to avoid DoS attacks.
@@ -77,7 +107,17 @@ impl MplexConfig {
         self
     }
 
-    /// Sets the behaviour when the maximum buffer length has been reached.
+    /// Sets the maximum number of pending incoming messages of a single substream.
+    ///
+    /// This prevents a substream that isn't being read from taking up the whole buffer.
+    #[inline]
+    pub fn max_substream_buffer_len(&mut self, max: usize) -> &mut Self {
+        self.max_substream_buffer_len = max;
+        self
+    }
+
+    /// Sets the behaviour when the maximum buffer length of the connection or of a
+    /// substream has been reached.
     ///
     /// See the documentation of `MaxBufferBehaviour`.
     #[inline]
@@ -94,6 +134,11 @@ impl MplexConfig {
         self
     }
 
+    /// Returns the counters of the connections using this configuration or its clones.
+    pub fn counters(&self) -> MplexCounters {
+        self.counters.clone()
+    }
+
     #[inline]
     fn upgrade<C>(self, i: C) -> Multiplex<C>
     where
@@ -106,6 +151,14 @@ impl MplexConfig {
                 inner: Framed::new(i, codec::Codec::new()).fuse(),
                 config: self,
                 buffer: Vec::with_capacity(cmp::min(max_buffer_len, 512)),
+                buffered: Default::default(),
+                reset_substreams: Default::default(),
+                pending_resets: Vec::new(),
+                send_queue: Vec::new(),
+                virtual_time: 0,
+                substream_stats: Default::default(),
+                total_substreams: 0,
+                refused_substreams: 0,
                 opened_substreams: Default::default(),
                 next_outbound_stream_id: 0,
                 notifier_read: Arc::new(Notifier {
@@ -126,9 +179,13 @@ impl Default for MplexConfig {
     fn default() -> MplexConfig {
         MplexConfig {
             max_substreams: 128,
+            max_inbound_substreams: usize::MAX,
+            on_substream_refused: None,
             max_buffer_len: 4096,
+            max_substream_buffer_len: 4096,
             max_buffer_behaviour: MaxBufferBehaviour::CloseAll,
             split_send_size: 1024,
+            counters: MplexCounters::default(),
         }
     }
 }
@@ -143,6 +200,53 @@ pub enum MaxBufferBehaviour {
     /// This can potentially introduce a deadlock if you are waiting for a message from a substream
     /// before processing the messages received on another substream.
     Block,
+    /// Reset the substream whose buffer is full, or the substream with the most pending
+    /// messages if the buffer of the connection is full, dropping its pending messages.
+    ///
+    /// Reading from the substream then fails with `ConnectionReset`.
+    ResetSubstream,
+}
+
+/// Counters of the messages dropped by connections, shared by the connections using the
+/// same [`MplexConfig`] or its clones.
+#[derive(Debug, Clone, Default)]
+pub struct MplexCounters {
+    inner: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    dropped_frames: AtomicU64,
+    reset_substreams: AtomicU64,
+    refused_substreams: AtomicU64,
+}
+
+/// A callback for the inbound substreams refused by a connection.
+#[derive(Clone)]
+struct RefusalCallback(Arc<dyn Fn(u32) + Send + Sync>);
+
+impl fmt::Debug for RefusalCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("RefusalCallback")
+    }
+}
+
+impl MplexCounters {
+    /// Returns the number of incoming messages dropped because of the buffer limits.
+    pub fn dropped_frames(&self) -> u64 {
+        self.inner.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of substreams reset because of the buffer limits.
+    pub fn reset_substreams(&self) -> u64 {
+        self.inner.reset_substreams.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of inbound substreams refused because of the limit on the number of
+    /// inbound substreams.
+    pub fn refused_substreams(&self) -> u64 {
+        self.inner.refused_substreams.load(Ordering::Relaxed)
+    }
 }
 
 impl UpgradeInfo for MplexConfig {
@@ -196,6 +300,22 @@ struct MultiplexInner<C> {
     config: MplexConfig,
     // Buffer of elements pulled from the stream but not processed yet.
     buffer: Vec<codec::Elem>,
+    /// Number of elements in `buffer` per substream, excluding `Open` elements.
+    buffered: FnvHashMap<(u32, Endpoint), usize>,
+    /// Substreams reset because of the buffer limits, whose incoming messages are dropped.
+    reset_substreams: FnvHashSet<(u32, Endpoint)>,
+    /// `Reset` elements to send for `reset_substreams`.
+    pending_resets: Vec<codec::Elem>,
+    /// Data frames written to substreams and waiting to be sent, at most one per substream.
+    send_queue: Vec<QueuedFrame>,
+    /// Finish tag of the latest frame sent from `send_queue`.
+    virtual_time: u64,
+    /// Statistics of the substreams that haven't been destroyed yet.
+    substream_stats: FnvHashMap<(u32, Endpoint), SubstreamStats>,
+    /// Number of substreams opened since the start of the connection.
+    total_substreams: u64,
+    /// Number of inbound substreams refused because of `max_inbound_substreams`.
+    refused_substreams: u64,
     // List of Ids of opened substreams. Used to filter out messages that don't belong to any
     // substream. Note that this is handled exclusively by `next_match`.
     // The `Endpoint` value denotes who initiated the substream from our point of view
@@ -228,6 +348,77 @@ impl Notifier {
     }
 }
 
+impl<C> MultiplexInner<C> {
+    /// Starts keeping statistics of a newly opened substream.
+    fn add_substream_stats(&mut self, num: u32, endpoint: Endpoint) {
+        self.total_substreams += 1;
+        self.substream_stats.insert((num, endpoint), SubstreamStats {
+            id: u64::from(num),
+            endpoint,
+            bytes_sent: 0,
+            bytes_received: 0,
+        });
+    }
+
+    /// Returns true if the buffer of the connection or of a substream is full.
+    fn is_buffer_full(&self) -> bool {
+        self.buffer.len() >= self.config.max_buffer_len
+            || self.buffered.values().any(|n| *n >= self.config.max_substream_buffer_len)
+    }
+
+    /// Removes the element at `offset` from the buffer.
+    fn remove_buffered(&mut self, offset: usize) {
+        let elem = self.buffer.remove(offset);
+        if let Some(key) = buffer_key(&elem) {
+            if let Some(n) = self.buffered.get_mut(&key) {
+                *n -= 1;
+                if *n == 0 {
+                    self.buffered.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Retains the buffered elements matching `f`.
+    fn retain_buffered(&mut self, f: impl FnMut(&codec::Elem) -> bool) {
+        self.buffer.retain(f);
+        self.buffered.clear();
+        for key in self.buffer.iter().filter_map(buffer_key) {
+            *self.buffered.entry(key).or_insert(0) += 1;
+        }
+    }
+
+    /// Resets a substream, dropping its buffered elements and `dropped` more.
+    fn reset_substream(&mut self, key: (u32, Endpoint), dropped: usize) {
+        debug!("Resetting substream {} after reaching the maximum buffer length", key.0);
+        let before = self.buffer.len();
+        self.retain_buffered(|elem| buffer_key(elem) != Some(key));
+        let dropped = (before - self.buffer.len() + dropped) as u64;
+        self.config.counters.inner.dropped_frames.fetch_add(dropped, Ordering::Relaxed);
+        self.config.counters.inner.reset_substreams.fetch_add(1, Ordering::Relaxed);
+        self.opened_substreams.remove(&key);
+        self.reset_substreams.insert(key);
+        self.pending_resets.push(codec::Elem::Reset { substream_id: key.0, endpoint: key.1 });
+    }
+}
+
+/// A data frame waiting to be sent.
+///
+/// Frames are sent in the order of their finish tag, which grows with the length of the
+/// frames of a substream divided by its weight, so that the substreams contending for the
+/// connection share it in proportion to their weight (see note [Scheduling]).
+struct QueuedFrame {
+    tag: u64,
+    key: (u32, Endpoint),
+    elem: codec::Elem,
+}
+
+/// Returns the substream of a buffered element from our point of view (see note [StreamId]),
+/// or `None` for `Open` elements.
+fn buffer_key(elem: &codec::Elem) -> Option<(u32, Endpoint)> {
+    elem.endpoint().map(|endpoint| (elem.substream_id(), !endpoint))
+}
+
 impl ArcWake for Notifier {
     fn wake_by_ref(arc_self: &Arc<Self>) {
         let wakers = mem::replace(&mut *arc_self.to_wake.lock(), Default::default());
@@ -260,22 +451,32 @@ where C: AsyncRead + AsyncWrite + Unpin,
         return Poll::Ready(Err(IoError::new(err.kind(), err.to_string())));
     }
 
+    // Opportunistically notify the remote of the substreams we have reset. Errors are reported
+    // by the next write or flush.
+    if !inner.pending_resets.is_empty() {
+        if let Poll::Ready(Ok(())) = poll_send_resets(inner) {
+            let waker = waker_ref(&inner.notifier_write);
+            let _ = Sink::poll_flush(Pin::new(&mut inner.inner), &mut Context::from_waker(&waker));
+        }
+    }
+    let _ = poll_drain(inner);
+
     if let Some((offset, out)) = inner.buffer.iter().enumerate().filter_map(|(n, v)| filter(v).map(|v| (n, v))).next() {
         // Found a matching entry in the existing buffer!
 
         // The buffer was full and no longer is, so let's notify everything.
-        if inner.buffer.len() == inner.config.max_buffer_len {
+        if inner.is_buffer_full() {
             ArcWake::wake_by_ref(&inner.notifier_read);
         }
 
-        inner.buffer.remove(offset);
+        inner.remove_buffered(offset);
         return Poll::Ready(Ok(out));
     }
 
     loop {
         // Check if we reached max buffer length first.
         debug_assert!(inner.buffer.len() <= inner.config.max_buffer_len);
-        if inner.buffer.len() == inner.config.max_buffer_len {
+        if inner.is_buffer_full() {
             debug!("Reached mplex maximum buffer length");
             match inner.config.max_buffer_behaviour {
                 MaxBufferBehaviour::CloseAll => {
@@ -286,6 +487,19 @@ where C: AsyncRead + AsyncWrite + Unpin,
                     inner.notifier_read.insert(cx.waker());
                     return Poll::Pending
                 },
+                MaxBufferBehaviour::ResetSubstream => {
+                    // The buffer of a substream is never full here, as substreams are reset
+                    // before exceeding their limit.
+                    let largest = inner.buffered.iter().max_by_key(|(_, n)| **n).map(|(k, _)| *k);
+                    match largest {
+                        Some(key) => inner.reset_substream(key, 0),
+                        None => {
+                            // The buffer only contains `Open` elements.
+                            inner.error = Err(IoError::new(IoErrorKind::Other, "reached maximum buffer length"));
+                            return Poll::Ready(Err(IoError::new(IoErrorKind::Other, "reached maximum buffer length")));
+                        }
+                    }
+                },
             }
         }
 
@@ -307,6 +521,20 @@ where C: AsyncRead + AsyncWrite + Unpin,
         // Handle substreams opening/closing.
         match elem {
             codec::Elem::Open { substream_id } => {
+                let inbound = inner.opened_substreams.iter()
+                    .filter(|(_, endpoint)| *endpoint == Endpoint::Listener)
+                    .count();
+                if inbound >= inner.config.max_inbound_substreams {
+                    debug!("Refused substream {}; reached maximum number of inbound substreams {}",
+                        substream_id, inner.config.max_inbound_substreams);
+                    inner.refused_substreams += 1;
+                    inner.config.counters.inner.refused_substreams.fetch_add(1, Ordering::Relaxed);
+                    inner.pending_resets.push(codec::Elem::Reset { substream_id, endpoint: Endpoint::Listener });
+                    if let Some(RefusalCallback(callback)) = &inner.config.on_substream_refused {
+                        callback(substream_id)
+                    }
+                    continue
+                }
                 if !inner.opened_substreams.insert((substream_id, Endpoint::Listener)) {
                     debug!("Received open message for substream {} which was already open", substream_id)
                 }
@@ -321,8 +549,25 @@ where C: AsyncRead + AsyncWrite + Unpin,
             return Poll::Ready(Ok(out));
         } else {
             let endpoint = elem.endpoint().unwrap_or(Endpoint::Dialer);
-            if inner.opened_substreams.contains(&(elem.substream_id(), !endpoint)) || elem.is_open_msg() {
+            let key = (elem.substream_id(), !endpoint);
+            if inner.opened_substreams.contains(&key) || elem.is_open_msg() {
+                let buffered = buffer_key(&elem).map_or(0, |k| inner.buffered.get(&k).copied().unwrap_or(0));
+                if buffered >= inner.config.max_substream_buffer_len
+                    && inner.config.max_buffer_behaviour == MaxBufferBehaviour::ResetSubstream
+                {
+                    inner.reset_substream(key, 1);
+                    continue
+                }
+                if let Some(key) = buffer_key(&elem) {
+                    *inner.buffered.entry(key).or_insert(0) += 1;
+                }
                 inner.buffer.push(elem);
+            } else if inner.reset_substreams.contains(&key) {
+                if elem.is_close_or_reset_msg() {
+                    inner.reset_substreams.remove(&key);
+                } else {
+                    inner.config.counters.inner.dropped_frames.fetch_add(1, Ordering::Relaxed);
+                }
             } else if !elem.is_close_or_reset_msg() {
                 debug!("Ignored message {:?} because the substream wasn't open", elem);
             }
@@ -339,6 +584,9 @@ where C: AsyncRead + AsyncWrite + Unpin
     }
 
     inner.notifier_write.insert(cx.waker());
+    ready!(poll_send_resets(inner))?;
+    // Control frames are sent after the pending data frames, which keeps them in order.
+    ready!(poll_drain(inner))?;
 
     match Sink::poll_ready(Pin::new(&mut inner.inner), &mut Context::from_waker(&waker_ref(&inner.notifier_write))) {
         Poll::Ready(Ok(())) => {
@@ -352,6 +600,36 @@ where C: AsyncRead + AsyncWrite + Unpin
     }
 }
 
+// Sends the frames of `send_queue` in the order of their finish tag.
+fn poll_drain<C>(inner: &mut MultiplexInner<C>) -> Poll<Result<(), IoError>>
+where C: AsyncRead + AsyncWrite + Unpin
+{
+    while !inner.send_queue.is_empty() {
+        let waker = waker_ref(&inner.notifier_write);
+        ready!(Sink::poll_ready(Pin::new(&mut inner.inner), &mut Context::from_waker(&waker)))?;
+        let (offset, _) = inner.send_queue.iter().enumerate()
+            .min_by_key(|(_, frame)| frame.tag)
+            .expect("send queue is not empty");
+        let frame = inner.send_queue.remove(offset);
+        inner.virtual_time = frame.tag;
+        Sink::start_send(Pin::new(&mut inner.inner), frame.elem)?;
+    }
+    Poll::Ready(Ok(()))
+}
+
+// Sends the `Reset` elements of the substreams reset because of the buffer limits.
+fn poll_send_resets<C>(inner: &mut MultiplexInner<C>) -> Poll<Result<(), IoError>>
+where C: AsyncRead + AsyncWrite + Unpin
+{
+    while !inner.pending_resets.is_empty() {
+        let waker = waker_ref(&inner.notifier_write);
+        ready!(Sink::poll_ready(Pin::new(&mut inner.inner), &mut Context::from_waker(&waker)))?;
+        let elem = inner.pending_resets.remove(0);
+        Sink::start_send(Pin::new(&mut inner.inner), elem)?;
+    }
+    Poll::Ready(Ok(()))
+}
+
 impl<C> StreamMuxer for Multiplex<C>
 where C: AsyncRead + AsyncWrite + Unpin
 {
@@ -381,16 +659,23 @@ where C: AsyncRead + AsyncWrite + Unpin
         };
 
         debug!("Successfully opened inbound substream {}", num);
+        inner.add_substream_stats(num, Endpoint::Listener);
         Poll::Ready(Ok(Substream {
             current_data: Bytes::new(),
             num,
             endpoint: Endpoint::Listener,
             local_open: true,
             remote_open: true,
+            priority: SubstreamPriority::Normal,
+            finish: 0,
         }))
     }
 
     fn open_outbound(&self) -> Self::OutboundSubstream {
+        self.open_outbound_with_priority(SubstreamPriority::Normal)
+    }
+
+    fn open_outbound_with_priority(&self, priority: SubstreamPriority) -> Self::OutboundSubstream {
         let mut inner = self.inner.lock();
 
         // Assign a substream ID now.
@@ -405,6 +690,7 @@ where C: AsyncRead + AsyncWrite + Unpin
 
         OutboundSubstream {
             num: substream_id,
+            priority,
             state: OutboundSubstreamState::SendElem(codec::Elem::Open { substream_id }),
         }
     }
@@ -435,7 +721,7 @@ where C: AsyncRead + AsyncWrite + Unpin
                 Poll::Pending => return Poll::Pending,
                 Poll::Ready(Err(err)) => {
                     debug!("Failed to open outbound substream {}", substream.num);
-                    inner.buffer.retain(|elem| {
+                    inner.retain_buffered(|elem| {
                         elem.substream_id() != substream.num || elem.endpoint() == Some(Endpoint::Dialer)
                     });
                     return Poll::Ready(Err(err));
@@ -451,6 +737,7 @@ where C: AsyncRead + AsyncWrite + Unpin
                 },
                 OutboundSubstreamState::Flush => {
                     debug!("Successfully opened outbound substream {}", substream.num);
+                    self.inner.lock().add_substream_stats(substream.num, Endpoint::Dialer);
                     substream.state = OutboundSubstreamState::Done;
                     return Poll::Ready(Ok(Substream {
                         num: substream.num,
@@ -458,6 +745,8 @@ where C: AsyncRead + AsyncWrite + Unpin
                         endpoint: Endpoint::Dialer,
                         local_open: true,
                         remote_open: true,
+                        priority: substream.priority,
+                        finish: 0,
                     }));
                 },
                 OutboundSubstreamState::Done => unreachable!(),
@@ -486,6 +775,9 @@ where C: AsyncRead + AsyncWrite + Unpin
 
             // Try to find a packet of data in the buffer.
             let mut inner = self.inner.lock();
+            if inner.reset_substreams.contains(&(substream.num, substream.endpoint)) {
+                return Poll::Ready(Err(IoErrorKind::ConnectionReset.into()));
+            }
             let next_data_poll = next_match(&mut inner, cx, |elem| {
                 match elem {
                     codec::Elem::Data { substream_id, endpoint, data, .. }
@@ -505,7 +797,12 @@ where C: AsyncRead + AsyncWrite + Unpin
             // We're in a loop, so all we need to do is set `substream.current_data` to the data we
             // just read and wait for the next iteration.
             match next_data_poll {
-                Poll::Ready(Ok(Some(data))) => substream.current_data = data,
+                Poll::Ready(Ok(Some(data))) => {
+                    if let Some(stats) = inner.substream_stats.get_mut(&(substream.num, substream.endpoint)) {
+                        stats.bytes_received += data.len() as u64;
+                    }
+                    substream.current_data = data
+                },
                 Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
                 Poll::Ready(Ok(None)) => {
                     substream.remote_open = false;
@@ -530,6 +827,22 @@ where C: AsyncRead + AsyncWrite + Unpin
         }
 
         let mut inner = self.inner.lock();
+        if inner.reset_substreams.contains(&(substream.num, substream.endpoint)) {
+            return Poll::Ready(Err(IoErrorKind::ConnectionReset.into()));
+        }
+        if inner.is_shutdown {
+            return Poll::Ready(Err(IoError::new(IoErrorKind::Other, "connection is shut down")))
+        }
+
+        // Wait for the previous frame of the substream to leave the queue.
+        let key = (substream.num, substream.endpoint);
+        inner.notifier_write.insert(cx.waker());
+        if let Poll::Ready(Err(err)) = poll_drain(&mut inner) {
+            return Poll::Ready(Err(err));
+        }
+        if inner.send_queue.iter().any(|frame| frame.key == key) {
+            return Poll::Pending;
+        }
 
         let to_write = cmp::min(buf.len(), inner.config.split_send_size);
 
@@ -539,10 +852,22 @@ where C: AsyncRead + AsyncWrite + Unpin
             endpoint: substream.endpoint,
         };
 
-        match poll_send(&mut inner, cx, elem) {
-            Poll::Ready(Ok(())) => Poll::Ready(Ok(to_write)),
+        // Note [Scheduling]: the data frames of the substreams are queued and sent in the order
+        // of their virtual finish time, i.e. the time at which they would finish being sent if
+        // the connection was shared between the substreams in proportion to their weight. A
+        // substream starting to send after an idle period starts at the current virtual time,
+        // so that it can't accumulate credit.
+        let start = cmp::max(inner.virtual_time, substream.finish);
+        let cost = to_write as u64 * u64::from(SubstreamPriority::High.weight());
+        substream.finish = start + cost / u64::from(substream.priority.weight());
+        inner.send_queue.push(QueuedFrame { tag: substream.finish, key, elem });
+        if let Some(stats) = inner.substream_stats.get_mut(&key) {
+            stats.bytes_sent += to_write as u64;
+        }
+
+        match poll_drain(&mut inner) {
             Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
-            Poll::Pending => Poll::Pending,
+            _ => Poll::Ready(Ok(to_write)),
         }
     }
 
@@ -554,6 +879,8 @@ where C: AsyncRead + AsyncWrite + Unpin
 
         let inner = &mut *inner; // Avoids borrow errors
         inner.notifier_write.insert(cx.waker());
+        ready!(poll_send_resets(inner))?;
+        ready!(poll_drain(inner))?;
         Sink::poll_flush(Pin::new(&mut inner.inner), &mut Context::from_waker(&waker_ref(&inner.notifier_write)))
     }
 
@@ -576,7 +903,10 @@ where C: AsyncRead + AsyncWrite + Unpin
     }
 
     fn destroy_substream(&self, sub: Self::Substream) {
-        self.inner.lock().buffer.retain(|elem| {
+        let mut inner = self.inner.lock();
+        inner.reset_substreams.remove(&(sub.num, sub.endpoint));
+        inner.substream_stats.remove(&(sub.num, sub.endpoint));
+        inner.retain_buffered(|elem| {
             elem.substream_id() != sub.num || elem.endpoint() == Some(sub.endpoint)
         })
     }
@@ -589,6 +919,9 @@ where C: AsyncRead + AsyncWrite + Unpin
     fn close(&self, cx: &mut Context) -> Poll<Result<(), IoError>> {
         let inner = &mut *self.inner.lock();
         inner.notifier_write.insert(cx.waker());
+        if !inner.is_shutdown {
+            ready!(poll_drain(inner))?;
+        }
         match Sink::poll_close(Pin::new(&mut inner.inner), &mut Context::from_waker(&waker_ref(&inner.notifier_write))) {
             Poll::Ready(Ok(())) => {
                 inner.is_shutdown = true;
@@ -606,14 +939,29 @@ where C: AsyncRead + AsyncWrite + Unpin
             return Poll::Ready(Ok(()))
         }
         inner.notifier_write.insert(cx.waker());
+        ready!(poll_send_resets(inner))?;
+        ready!(poll_drain(inner))?;
         Sink::poll_flush(Pin::new(&mut inner.inner), &mut Context::from_waker(&waker_ref(&inner.notifier_write)))
     }
+
+    fn stats(&self) -> Option<MuxerStats> {
+        let inner = self.inner.lock();
+        Some(MuxerStats {
+            open_substreams: inner.substream_stats.len(),
+            total_substreams: inner.total_substreams,
+            substreams: inner.substream_stats.values().copied().collect(),
+            window_stall_time: Default::default(),
+            refused_substreams: inner.refused_substreams,
+        })
+    }
 }
 
 /// Active attempt to open an outbound substream.
 pub struct OutboundSubstream {
     /// Substream number.
     num: u32,
+    /// Priority of the substream once open.
+    priority: SubstreamPriority,
     state: OutboundSubstreamState,
 }
 
@@ -637,4 +985,8 @@ pub struct Substream {
     local_open: bool,
     /// If true, the remote writing side is still open.
     remote_open: bool,
+    /// Priority of the data frames of the substream.
+    priority: SubstreamPriority,
+    /// Virtual finish time of the latest data frame of the substream (see note [Scheduling]).
+    finish: u64,
 }
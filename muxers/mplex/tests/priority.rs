@@ -0,0 +1,98 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::{prelude::*, task::noop_waker};
+use libp2p_core::{muxing::{StreamMuxer, SubstreamPriority}, upgrade::OutboundUpgrade};
+use parking_lot::Mutex;
+use std::{io, pin::Pin, sync::Arc, task::{Context, Poll}};
+
+/// A connection whose writes can be blocked, recording what is written.
+#[derive(Clone, Default)]
+struct Connection {
+    state: Arc<Mutex<(bool, Vec<u8>)>>,
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(self: Pin<&mut Self>, _: &mut Context, _: &mut [u8]) -> Poll<io::Result<usize>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(self: Pin<&mut Self>, _: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let mut state = self.state.lock();
+        if state.0 {
+            return Poll::Pending
+        }
+        state.1.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[test]
+fn high_priority_overtakes_bulk_transfer() {
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    let connection = Connection::default();
+    let mut config = libp2p_mplex::MplexConfig::new();
+    config.split_send_size(1024);
+    let mplex = config.upgrade_outbound(connection.clone(), b"/mplex/6.7.0")
+        .now_or_never()
+        .unwrap()
+        .unwrap();
+
+    let mut open = |priority| {
+        let mut outbound = mplex.open_outbound_with_priority(priority);
+        match mplex.poll_outbound(&mut cx, &mut outbound) {
+            Poll::Ready(Ok(substream)) => substream,
+            _ => panic!("failed to open substream"),
+        }
+    };
+    let mut bulk = open(SubstreamPriority::Low);
+    let mut ping = open(SubstreamPriority::High);
+
+    // Fill the connection with the bulk transfer.
+    connection.state.lock().0 = true;
+    let mut written = 0;
+    while let Poll::Ready(n) = mplex.write_substream(&mut cx, &mut bulk, &[b'L'; 1024]) {
+        written += n.unwrap();
+        assert!(written < 1024 * 1024, "the connection never became busy");
+    }
+    assert!(mplex.write_substream(&mut cx, &mut ping, &[b'H'; 1024]).is_ready());
+
+    connection.state.lock().0 = false;
+    assert!(mplex.flush_all(&mut cx).is_ready());
+
+    // The ping is sent before the last frame of the bulk transfer.
+    let output = connection.state.lock().1.clone();
+    let first_ping = output.iter().position(|b| *b == b'H').unwrap();
+    let last_bulk = output.iter().rposition(|b| *b == b'L').unwrap();
+    assert!(first_ping < last_bulk);
+    assert_eq!(output.iter().filter(|b| **b == b'L').count(), written);
+}
@@ -18,7 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use libp2p_core::{muxing, upgrade, Transport};
+use libp2p_core::{Endpoint, muxing::{self, StreamMuxer}, upgrade, Transport};
 use libp2p_tcp::TcpConfig;
 use futures::{channel::oneshot, prelude::*};
 use std::sync::Arc;
@@ -124,3 +124,218 @@ fn client_to_server_inbound() {
         bg_thread.await;
     });
 }
+
+#[test]
+fn reset_substream_on_full_buffer() {
+    // A substream that isn't being read is reset once its buffer is full, without affecting
+    // the other substreams of the connection.
+
+    let (tx, rx) = oneshot::channel();
+
+    let mut mplex = libp2p_mplex::MplexConfig::new();
+    mplex.max_substream_buffer_len(2)
+        .max_buffer_len_behaviour(libp2p_mplex::MaxBufferBehaviour::ResetSubstream);
+    let counters = mplex.counters();
+
+    let bg_thread = async_std::task::spawn(async move {
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let mut listener = transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let addr = listener.next().await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        tx.send(addr).unwrap();
+
+        let client = Arc::new(listener
+            .next().await
+            .unwrap()
+            .unwrap()
+            .into_upgrade().unwrap().0.await.unwrap());
+
+        let mut flooded = muxing::inbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        let mut other = muxing::inbound_from_ref_and_wrap(client.clone()).await.unwrap();
+
+        let mut buf = Vec::new();
+        other.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello world");
+
+        let err = flooded.read_to_end(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionReset);
+    });
+
+    async_std::task::block_on(async {
+        let mut mplex = libp2p_mplex::MplexConfig::new();
+        mplex.split_send_size(1);
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let client = Arc::new(transport.dial(rx.await.unwrap()).unwrap().await.unwrap());
+        let mut flooded = muxing::outbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        flooded.write_all(b"0123456789").await.unwrap();
+        flooded.flush().await.unwrap();
+
+        let mut other = muxing::outbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        other.write_all(b"hello world").await.unwrap();
+        other.close().await.unwrap();
+
+        bg_thread.await;
+    });
+
+    assert_eq!(counters.reset_substreams(), 1);
+    assert!(counters.dropped_frames() >= 3);
+}
+
+#[test]
+fn substream_stats() {
+    // The statistics of the muxer account for the bytes sent and received on each substream.
+
+    let (tx, rx) = oneshot::channel();
+
+    let bg_thread = async_std::task::spawn(async move {
+        let mplex = libp2p_mplex::MplexConfig::new();
+
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let mut listener = transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let addr = listener.next().await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        tx.send(addr).unwrap();
+
+        let client = Arc::new(listener
+            .next().await
+            .unwrap()
+            .unwrap()
+            .into_upgrade().unwrap().0.await.unwrap());
+
+        let mut inbound = muxing::inbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        let mut buf = [0; 11];
+        inbound.read_exact(&mut buf).await.unwrap();
+        inbound.write_all(b"pong").await.unwrap();
+        inbound.close().await.unwrap();
+
+        let stats = client.stats().unwrap();
+        assert_eq!(stats.open_substreams, 1);
+        assert_eq!(stats.total_substreams, 1);
+        let substream = stats.busiest_substream().unwrap();
+        assert_eq!(substream.endpoint, Endpoint::Listener);
+        assert_eq!((substream.bytes_sent, substream.bytes_received), (4, 11));
+
+        drop(inbound);
+        assert_eq!(client.stats().unwrap().open_substreams, 0);
+    });
+
+    async_std::task::block_on(async {
+        let mplex = libp2p_mplex::MplexConfig::new();
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let client = Arc::new(transport.dial(rx.await.unwrap()).unwrap().await.unwrap());
+        let mut outbound = muxing::outbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        outbound.write_all(b"hello world").await.unwrap();
+        outbound.flush().await.unwrap();
+        let mut buf = Vec::new();
+        outbound.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"pong");
+
+        bg_thread.await;
+    });
+}
+
+#[test]
+fn refuse_substreams_over_inbound_limit() {
+    // The substreams the remote opens beyond the limit are reset, without affecting the
+    // substreams already open.
+
+    let (tx, rx) = oneshot::channel();
+    let (done_tx, done_rx) = oneshot::channel::<()>();
+    let (refused_tx, refused_rx) = std::sync::mpsc::channel();
+
+    let mut mplex = libp2p_mplex::MplexConfig::new();
+    let refused_tx = std::sync::Mutex::new(refused_tx);
+    mplex.max_inbound_substreams(1)
+        .on_substream_refused(move |id| refused_tx.lock().unwrap().send(id).unwrap());
+    let counters = mplex.counters();
+
+    let bg_thread = async_std::task::spawn(async move {
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let mut listener = transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let addr = listener.next().await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        tx.send(addr).unwrap();
+
+        let client = Arc::new(listener
+            .next().await
+            .unwrap()
+            .unwrap()
+            .into_upgrade().unwrap().0.await.unwrap());
+
+        let mut inbound = muxing::inbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        let mut buf = Vec::new();
+        inbound.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello world");
+        inbound.write_all(b"pong").await.unwrap();
+        inbound.close().await.unwrap();
+
+        let stats = client.stats().unwrap();
+        assert_eq!(stats.total_substreams, 1);
+        assert_eq!(stats.refused_substreams, 1);
+
+        // Keep the connection open until the remote has observed the reset.
+        done_rx.await.unwrap();
+    });
+
+    async_std::task::block_on(async {
+        let mplex = libp2p_mplex::MplexConfig::new();
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let client = Arc::new(transport.dial(rx.await.unwrap()).unwrap().await.unwrap());
+        let mut accepted = muxing::outbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        accepted.write_all(b"hello").await.unwrap();
+        accepted.flush().await.unwrap();
+
+        let mut refused = muxing::outbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        refused.write_all(b"flood").await.unwrap();
+        refused.flush().await.unwrap();
+
+        accepted.write_all(b" world").await.unwrap();
+        accepted.close().await.unwrap();
+        let mut buf = Vec::new();
+        accepted.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"pong");
+
+        let mut buf = Vec::new();
+        refused.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+        done_tx.send(()).unwrap();
+
+        bg_thread.await;
+    });
+
+    assert_eq!(counters.refused_substreams(), 1);
+    assert_eq!(refused_rx.try_iter().count(), 1);
+}
@@ -0,0 +1,261 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Keep-alive pings sent with the yamux ping frames.
+//!
+//! The yamux crate answers the pings of the remote but offers no way to send pings itself,
+//! so [`KeepAlive`] wraps the I/O resource of a connection and interleaves its own ping
+//! frames with the frames written by yamux. The pongs are passed on to yamux, which ignores
+//! them.
+
+use futures::prelude::*;
+use std::{cmp, io, pin::Pin, task::{Context, Poll}, time::Duration};
+use wasm_timer::Delay;
+
+/// The size of a frame header.
+const HEADER_SIZE: usize = 12;
+/// The type of data frames, the only frames with a body.
+const TYPE_DATA: u8 = 0;
+/// The type of ping frames.
+const TYPE_PING: u8 = 2;
+/// The flag of a ping.
+const FLAG_SYN: u16 = 1;
+/// The flag of a pong.
+const FLAG_ACK: u16 = 2;
+
+/// The configuration of the keep-alive pings of a connection.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KeepAliveConfig {
+    /// The interval between two pings.
+    pub(crate) interval: Duration,
+    /// The time to wait for a pong before the connection is considered dead.
+    pub(crate) timeout: Duration,
+}
+
+/// An I/O resource sending a ping to the remote at regular intervals, which fails with
+/// [`io::ErrorKind::TimedOut`] if a pong isn't received in time.
+pub(crate) struct KeepAlive<C> {
+    io: C,
+    config: KeepAliveConfig,
+    /// Fires when the next ping is due.
+    next_ping: Delay,
+    /// The nonce of the last ping.
+    nonce: u32,
+    /// The deadline of the ping awaiting a pong, if any.
+    deadline: Option<Delay>,
+    /// The ping frame being written and the number of bytes already written, if any.
+    outgoing: Option<([u8; HEADER_SIZE], usize)>,
+    /// The frames written by yamux.
+    written: Frames,
+    /// The frames read by yamux.
+    read: Frames,
+}
+
+impl<C> KeepAlive<C> {
+    pub(crate) fn new(io: C, config: KeepAliveConfig) -> Self {
+        KeepAlive {
+            io,
+            config,
+            next_ping: Delay::new(config.interval),
+            nonce: 0,
+            deadline: None,
+            outgoing: None,
+            written: Frames::default(),
+            read: Frames::default(),
+        }
+    }
+}
+
+impl<C: AsyncWrite + Unpin> KeepAlive<C> {
+    /// Queues a ping if one is due, writes the queued ping between two frames of yamux and
+    /// checks the deadline of the last ping.
+    ///
+    /// Returns `Poll::Pending` while the ping is partially written, in which case yamux must
+    /// not write.
+    fn poll_keep_alive(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        if self.next_ping.poll_unpin(cx).is_ready() {
+            self.next_ping.reset(self.config.interval);
+            let _ = self.next_ping.poll_unpin(cx);
+            if self.deadline.is_none() {
+                self.nonce = self.nonce.wrapping_add(1);
+                self.outgoing = Some((ping(self.nonce), 0));
+                self.deadline = Some(Delay::new(self.config.timeout));
+            }
+        }
+
+        if let Some(deadline) = &mut self.deadline {
+            if deadline.poll_unpin(cx).is_ready() {
+                log::debug!("Keep-alive ping {} timed out", self.nonce);
+                return Poll::Ready(Err(io::ErrorKind::TimedOut.into()))
+            }
+        }
+
+        if !self.written.at_boundary() {
+            return Poll::Ready(Ok(()))
+        }
+        while let Some((frame, offset)) = &mut self.outgoing {
+            match Pin::new(&mut self.io).poll_write(cx, &frame[*offset ..]) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(n)) => {
+                    *offset += n;
+                    if *offset == HEADER_SIZE {
+                        log::trace!("Sent keep-alive ping {}", self.nonce);
+                        self.outgoing = None;
+                        if let Poll::Ready(Err(e)) = Pin::new(&mut self.io).poll_flush(cx) {
+                            return Poll::Ready(Err(e))
+                        }
+                    }
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending if *offset == 0 => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<C: AsyncRead + AsyncWrite + Unpin> AsyncRead for KeepAlive<C> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        // Reading is pending while the connection is idle, so pings are also sent from here.
+        if let Poll::Ready(Err(e)) = this.poll_keep_alive(cx) {
+            return Poll::Ready(Err(e))
+        }
+        let n = futures::ready!(Pin::new(&mut this.io).poll_read(cx, buf))?;
+        let (nonce, deadline) = (this.nonce, &mut this.deadline);
+        this.read.advance(&buf[.. n], |header| {
+            if is_pong(header, nonce) {
+                log::trace!("Received keep-alive pong {}", nonce);
+                *deadline = None
+            }
+        });
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<C: AsyncWrite + Unpin> AsyncWrite for KeepAlive<C> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        futures::ready!(this.poll_keep_alive(cx))?;
+        if this.outgoing.is_some() && this.written.at_boundary() {
+            // The ping couldn't be started, so neither can the next frame.
+            return Poll::Pending
+        }
+        let n = futures::ready!(Pin::new(&mut this.io).poll_write(cx, buf))?;
+        this.written.advance(&buf[.. n], |_| ());
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        futures::ready!(self.poll_keep_alive(cx))?;
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_close(cx)
+    }
+}
+
+/// Tracks the boundaries of the frames of a stream of bytes.
+#[derive(Default)]
+struct Frames {
+    header: [u8; HEADER_SIZE],
+    /// The number of bytes of the current header seen so far.
+    header_len: usize,
+    /// The number of bytes of the current body not seen yet.
+    body_len: usize,
+}
+
+impl Frames {
+    /// Returns true if the next byte starts a new frame.
+    fn at_boundary(&self) -> bool {
+        self.header_len == 0 && self.body_len == 0
+    }
+
+    /// Advances over the bytes of `buf`, calling `on_header` with every complete header.
+    fn advance(&mut self, mut buf: &[u8], mut on_header: impl FnMut(&[u8; HEADER_SIZE])) {
+        while !buf.is_empty() {
+            if self.body_len > 0 {
+                let n = cmp::min(self.body_len, buf.len());
+                self.body_len -= n;
+                buf = &buf[n ..];
+                continue
+            }
+            let n = cmp::min(HEADER_SIZE - self.header_len, buf.len());
+            self.header[self.header_len .. self.header_len + n].copy_from_slice(&buf[.. n]);
+            self.header_len += n;
+            buf = &buf[n ..];
+            if self.header_len == HEADER_SIZE {
+                self.header_len = 0;
+                if self.header[1] == TYPE_DATA {
+                    self.body_len = length(&self.header) as usize
+                }
+                on_header(&self.header)
+            }
+        }
+    }
+}
+
+/// Encodes the header of a ping frame with the given nonce.
+fn ping(nonce: u32) -> [u8; HEADER_SIZE] {
+    let mut header = [0; HEADER_SIZE];
+    header[1] = TYPE_PING;
+    header[2 .. 4].copy_from_slice(&FLAG_SYN.to_be_bytes());
+    header[8 ..].copy_from_slice(&nonce.to_be_bytes());
+    header
+}
+
+/// Returns true if `header` is the header of the pong of the ping with the given nonce.
+fn is_pong(header: &[u8; HEADER_SIZE], nonce: u32) -> bool {
+    let flags = u16::from_be_bytes([header[2], header[3]]);
+    header[1] == TYPE_PING && flags & FLAG_ACK != 0 && length(header) == nonce
+}
+
+/// Returns the length field of a frame header.
+fn length(header: &[u8; HEADER_SIZE]) -> u32 {
+    u32::from_be_bytes([header[8], header[9], header[10], header[11]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_boundaries() {
+        let mut data = ping(7).to_vec();
+        data.extend_from_slice(&[0, TYPE_DATA, 0, 0, 0, 0, 0, 1, 0, 0, 0, 3, 1, 2, 3]);
+        let mut pong = ping(7);
+        pong[2 .. 4].copy_from_slice(&FLAG_ACK.to_be_bytes());
+        data.extend_from_slice(&pong);
+
+        // Feed the bytes in chunks which straddle the frames.
+        let mut frames = Frames::default();
+        let mut pongs = 0;
+        for chunk in data.chunks(5) {
+            frames.advance(chunk, |header| if is_pong(header, 7) { pongs += 1 });
+        }
+        assert!(frames.at_boundary());
+        assert_eq!(pongs, 1);
+
+        frames.advance(&data[.. 14], |_| ());
+        assert!(!frames.at_boundary());
+    }
+}
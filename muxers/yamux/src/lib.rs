@@ -22,10 +22,22 @@
 //! [specification](https://github.com/hashicorp/yamux/blob/master/spec.md).
 
 use futures::{future, prelude::*, ready, stream::{BoxStream, LocalBoxStream}};
-use libp2p_core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p_core::{
+    Endpoint,
+    muxing::{MuxerStats, SubstreamPriority, SubstreamStats},
+    upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo}
+};
 use parking_lot::Mutex;
-use std::{fmt, io, iter, pin::Pin, task::Context};
+use std::{cmp, collections::HashMap, fmt, io, iter, pin::Pin, sync::Arc, task::Context, time::{Duration, Instant}};
 use thiserror::Error;
+use keep_alive::{KeepAlive, KeepAliveConfig};
+use window::Meter;
+
+mod keep_alive;
+mod window;
+
+pub use window::AdaptiveWindow;
+pub use yamux::WindowUpdateMode;
 
 /// A Yamux connection.
 pub struct Yamux<S>(Mutex<Inner<S>>);
@@ -42,29 +54,108 @@ struct Inner<S> {
     /// Handle to control the connection.
     control: yamux::Control,
     /// True, once we have received an inbound substream.
-    acknowledged: bool
+    acknowledged: bool,
+    /// True if we are the client of the connection, which opens the odd substream IDs.
+    client: bool,
+    /// Measures the throughput for the adaptive receive window, if any.
+    meter: Option<Meter>,
+    /// The substreams that haven't been destroyed yet.
+    substreams: HashMap<yamux::StreamId, SubstreamState>,
+    /// The number of substreams opened since the start of the connection.
+    total_substreams: u64,
+    /// The time writes of substreams spent pending, excluding the pending ones.
+    stall_time: Duration,
+    /// The maximum number of substreams opened by the remote at the same time.
+    max_inbound_substreams: usize,
+    /// The number of inbound substreams refused because of `max_inbound_substreams`.
+    refused_substreams: u64,
+    /// Called with the ID of each refused inbound substream.
+    on_substream_refused: Option<RefusalCallback>,
+}
+
+/// A callback for the inbound substreams refused by a connection.
+type RefusalCallback = Arc<dyn Fn(u32) + Send + Sync>;
+
+impl<S> Inner<S> {
+    /// Keeps track of a newly opened substream.
+    fn add_substream(&mut self, stream: &yamux::Stream, priority: SubstreamPriority) {
+        self.total_substreams += 1;
+        self.substreams.insert(stream.id(), SubstreamState {
+            priority,
+            bytes_sent: 0,
+            bytes_received: 0,
+            stalled_since: None,
+        });
+    }
+
+    /// Returns the number of open substreams opened by the remote.
+    fn inbound_substreams(&self) -> usize {
+        self.substreams.keys().filter(|id| id.is_client() != self.client).count()
+    }
+}
+
+/// The state kept for each substream.
+struct SubstreamState {
+    priority: SubstreamPriority,
+    bytes_sent: u64,
+    bytes_received: u64,
+    /// When the pending write to the substream started waiting, if any.
+    stalled_since: Option<Instant>,
 }
 
+/// The default maximum buffer size of yamux.
+const DEFAULT_MAX_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// The maximum number of bytes written at once to a substream of weight 1.
+///
+/// Substream priorities are only used to cap the size of each write to `WRITE_QUANTUM` times
+/// the weight of the substream. The frames of all substreams are still sent in the order in
+/// which they are written, as yamux offers no way of scheduling them, so this is not a fair
+/// share of the connection. It only bounds the size of the frames a high priority write may
+/// find queued ahead of it.
+const WRITE_QUANTUM: usize = 16 * 1024;
+
 /// A token to poll for an outbound substream.
 #[derive(Debug)]
-pub struct OpenSubstreamToken(());
+pub struct OpenSubstreamToken(SubstreamPriority);
 
 impl<C> Yamux<Incoming<C>>
 where
     C: AsyncRead + AsyncWrite + Send + Unpin + 'static
 {
     /// Create a new Yamux connection.
-    pub fn new(io: C, mut cfg: yamux::Config, mode: yamux::Mode) -> Self {
+    pub fn new(io: C, cfg: yamux::Config, mode: yamux::Mode) -> Self {
+        Self::with_keep_alive(io, cfg, mode, None)
+    }
+
+    /// Create a new Yamux connection, sending keep-alive pings if configured.
+    fn with_keep_alive(io: C, mut cfg: yamux::Config, mode: yamux::Mode, keep_alive: Option<KeepAliveConfig>) -> Self {
         cfg.set_read_after_close(false);
-        let conn = yamux::Connection::new(io, cfg, mode);
-        let ctrl = conn.control();
+        let client = matches!(mode, yamux::Mode::Client);
+        let (stream, ctrl) = if let Some(keep_alive) = keep_alive {
+            let conn = yamux::Connection::new(KeepAlive::new(io, keep_alive), cfg, mode);
+            let ctrl = conn.control();
+            (yamux::into_stream(conn).err_into().boxed(), ctrl)
+        } else {
+            let conn = yamux::Connection::new(io, cfg, mode);
+            let ctrl = conn.control();
+            (yamux::into_stream(conn).err_into().boxed(), ctrl)
+        };
         let inner = Inner {
             incoming: Incoming {
-                stream: yamux::into_stream(conn).err_into().boxed(),
+                stream,
                 _marker: std::marker::PhantomData
             },
             control: ctrl,
-            acknowledged: false
+            acknowledged: false,
+            client,
+            meter: None,
+            substreams: HashMap::new(),
+            total_substreams: 0,
+            stall_time: Duration::from_secs(0),
+            max_inbound_substreams: usize::MAX,
+            refused_substreams: 0,
+            on_substream_refused: None,
         };
         Yamux(Mutex::new(inner))
     }
@@ -75,17 +166,39 @@ where
     C: AsyncRead + AsyncWrite + Unpin + 'static
 {
     /// Create a new Yamux connection (which is ![`Send`]).
-    pub fn local(io: C, mut cfg: yamux::Config, mode: yamux::Mode) -> Self {
+    pub fn local(io: C, cfg: yamux::Config, mode: yamux::Mode) -> Self {
+        Self::local_with_keep_alive(io, cfg, mode, None)
+    }
+
+    /// Create a new Yamux connection (which is ![`Send`]), sending keep-alive pings if
+    /// configured.
+    fn local_with_keep_alive(io: C, mut cfg: yamux::Config, mode: yamux::Mode, keep_alive: Option<KeepAliveConfig>) -> Self {
         cfg.set_read_after_close(false);
-        let conn = yamux::Connection::new(io, cfg, mode);
-        let ctrl = conn.control();
+        let client = matches!(mode, yamux::Mode::Client);
+        let (stream, ctrl) = if let Some(keep_alive) = keep_alive {
+            let conn = yamux::Connection::new(KeepAlive::new(io, keep_alive), cfg, mode);
+            let ctrl = conn.control();
+            (yamux::into_stream(conn).err_into().boxed_local(), ctrl)
+        } else {
+            let conn = yamux::Connection::new(io, cfg, mode);
+            let ctrl = conn.control();
+            (yamux::into_stream(conn).err_into().boxed_local(), ctrl)
+        };
         let inner = Inner {
             incoming: LocalIncoming {
-                stream: yamux::into_stream(conn).err_into().boxed_local(),
+                stream,
                 _marker: std::marker::PhantomData
             },
             control: ctrl,
-            acknowledged: false
+            acknowledged: false,
+            client,
+            meter: None,
+            substreams: HashMap::new(),
+            total_substreams: 0,
+            stall_time: Duration::from_secs(0),
+            max_inbound_substreams: usize::MAX,
+            refused_substreams: 0,
+            on_substream_refused: None,
         };
         Yamux(Mutex::new(inner))
     }
@@ -103,23 +216,42 @@ where
 
     fn poll_inbound(&self, c: &mut Context) -> Poll<Self::Substream> {
         let mut inner = self.0.lock();
-        match ready!(inner.incoming.poll_next_unpin(c)) {
-            Some(Ok(s)) => {
-                inner.acknowledged = true;
-                Poll::Ready(Ok(s))
+        loop {
+            match ready!(inner.incoming.poll_next_unpin(c)) {
+                Some(Ok(s)) => {
+                    inner.acknowledged = true;
+                    if inner.inbound_substreams() >= inner.max_inbound_substreams {
+                        // Dropping the stream without closing it resets it.
+                        log::debug!("Refused substream {}; reached maximum number of inbound \
+                            substreams {}", s.id(), inner.max_inbound_substreams);
+                        inner.refused_substreams += 1;
+                        if let Some(callback) = &inner.on_substream_refused {
+                            callback(s.id().val())
+                        }
+                        continue
+                    }
+                    inner.add_substream(&s, SubstreamPriority::Normal);
+                    return Poll::Ready(Ok(s))
+                }
+                Some(Err(e)) => return Poll::Ready(Err(e)),
+                None => return Poll::Ready(Err(yamux::ConnectionError::Closed.into()))
             }
-            Some(Err(e)) => Poll::Ready(Err(e)),
-            None => Poll::Ready(Err(yamux::ConnectionError::Closed.into()))
         }
     }
 
     fn open_outbound(&self) -> Self::OutboundSubstream {
-        OpenSubstreamToken(())
+        OpenSubstreamToken(SubstreamPriority::Normal)
+    }
+
+    fn open_outbound_with_priority(&self, priority: SubstreamPriority) -> Self::OutboundSubstream {
+        OpenSubstreamToken(priority)
     }
 
-    fn poll_outbound(&self, c: &mut Context, _: &mut OpenSubstreamToken) -> Poll<Self::Substream> {
+    fn poll_outbound(&self, c: &mut Context, t: &mut OpenSubstreamToken) -> Poll<Self::Substream> {
         let mut inner = self.0.lock();
-        Pin::new(&mut inner.control).poll_open_stream(c).map_err(YamuxError)
+        let stream = ready!(Pin::new(&mut inner.control).poll_open_stream(c).map_err(YamuxError))?;
+        inner.add_substream(&stream, t.0);
+        Poll::Ready(Ok(stream))
     }
 
     fn destroy_outbound(&self, _: Self::OutboundSubstream) {
@@ -127,11 +259,40 @@ where
     }
 
     fn read_substream(&self, c: &mut Context, s: &mut Self::Substream, b: &mut [u8]) -> Poll<usize> {
-        Pin::new(s).poll_read(c, b).map_err(|e| YamuxError(e.into()))
+        let id = s.id();
+        let n = ready!(Pin::new(s).poll_read(c, b)).map_err(|e| YamuxError(e.into()))?;
+        let mut inner = self.0.lock();
+        if let Some(meter) = &mut inner.meter {
+            meter.record(n)
+        }
+        if let Some(state) = inner.substreams.get_mut(&id) {
+            state.bytes_received += n as u64
+        }
+        Poll::Ready(Ok(n))
     }
 
     fn write_substream(&self, c: &mut Context, s: &mut Self::Substream, b: &[u8]) -> Poll<usize> {
-        Pin::new(s).poll_write(c, b).map_err(|e| YamuxError(e.into()))
+        let mut inner = self.0.lock();
+        let inner = &mut *inner;
+        let state = match inner.substreams.get_mut(&s.id()) {
+            Some(state) => state,
+            None => return Pin::new(s).poll_write(c, b).map_err(|e| YamuxError(e.into()))
+        };
+        let quantum = WRITE_QUANTUM * state.priority.weight() as usize;
+        match Pin::new(s).poll_write(c, &b[.. cmp::min(b.len(), quantum)]) {
+            std::task::Poll::Pending => {
+                state.stalled_since.get_or_insert_with(Instant::now);
+                Poll::Pending
+            }
+            std::task::Poll::Ready(result) => {
+                if let Some(since) = state.stalled_since.take() {
+                    inner.stall_time += since.elapsed()
+                }
+                let n = result.map_err(|e| YamuxError(e.into()))?;
+                state.bytes_sent += n as u64;
+                Poll::Ready(Ok(n))
+            }
+        }
     }
 
     fn flush_substream(&self, c: &mut Context, s: &mut Self::Substream) -> Poll<()> {
@@ -142,7 +303,12 @@ where
         Pin::new(s).poll_close(c).map_err(|e| YamuxError(e.into()))
     }
 
-    fn destroy_substream(&self, _: Self::Substream) { }
+    fn destroy_substream(&self, s: Self::Substream) {
+        let mut inner = self.0.lock();
+        if let Some(since) = inner.substreams.remove(&s.id()).and_then(|state| state.stalled_since) {
+            inner.stall_time += since.elapsed()
+        }
+    }
 
     fn is_remote_acknowledged(&self) -> bool {
         self.0.lock().acknowledged
@@ -150,17 +316,66 @@ where
 
     fn close(&self, c: &mut Context) -> Poll<()> {
         let mut inner = self.0.lock();
-        Pin::new(&mut inner.control).poll_close(c).map_err(YamuxError)
+        if let std::task::Poll::Ready(result) = Pin::new(&mut inner.control).poll_close(c) {
+            return Poll::Ready(result.map_err(YamuxError))
+        }
+        // The connection only processes the close command while its incoming substreams are
+        // polled. Substreams opened by the remote in the meantime are reset by dropping them.
+        loop {
+            match ready!(inner.incoming.poll_next_unpin(c)) {
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Poll::Ready(Err(e)),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
     }
 
     fn flush_all(&self, _: &mut Context) -> Poll<()> {
         Poll::Ready(Ok(()))
     }
+
+    fn stats(&self) -> Option<MuxerStats> {
+        let inner = self.0.lock();
+        let stalled = inner.substreams.values().filter_map(|state| state.stalled_since);
+        let substreams = inner.substreams.iter().map(|(id, state)| SubstreamStats {
+            id: u64::from(id.val()),
+            endpoint: if id.is_client() == inner.client { Endpoint::Dialer } else { Endpoint::Listener },
+            bytes_sent: state.bytes_sent,
+            bytes_received: state.bytes_received,
+        });
+        Some(MuxerStats {
+            open_substreams: inner.substreams.len(),
+            total_substreams: inner.total_substreams,
+            substreams: substreams.collect(),
+            window_stall_time: inner.stall_time + stalled.map(|since| since.elapsed()).sum(),
+            refused_substreams: inner.refused_substreams,
+        })
+    }
+}
+
+impl<S> Yamux<S> {
+    /// Applies the settings of `config` which are not part of the yamux configuration.
+    fn with_config(self, config: Config) -> Self {
+        {
+            let mut inner = self.0.lock();
+            inner.meter = config.adaptive_window.map(Meter::new);
+            inner.max_inbound_substreams = config.max_inbound_substreams;
+            inner.on_substream_refused = config.on_substream_refused;
+        }
+        self
+    }
 }
 
 /// The yamux configuration.
 #[derive(Clone)]
-pub struct Config(yamux::Config);
+pub struct Config {
+    inner: yamux::Config,
+    max_buffer_size: usize,
+    adaptive_window: Option<AdaptiveWindow>,
+    max_inbound_substreams: usize,
+    on_substream_refused: Option<RefusalCallback>,
+    keep_alive: Option<KeepAliveConfig>,
+}
 
 /// The yamux configuration for upgrading I/O resources which are ![`Send`].
 #[derive(Clone)]
@@ -168,7 +383,101 @@ pub struct LocalConfig(Config);
 
 impl Config {
     pub fn new(cfg: yamux::Config) -> Self {
-        Config(cfg)
+        Config {
+            inner: cfg,
+            max_buffer_size: DEFAULT_MAX_BUFFER_SIZE,
+            adaptive_window: None,
+            max_inbound_substreams: usize::MAX,
+            on_substream_refused: None,
+            keep_alive: None,
+        }
+    }
+
+    /// Sets the receive window of each substream, i.e. the amount of data the remote can
+    /// send before waiting for a window update.
+    ///
+    /// The throughput of a substream is bounded by the receive window divided by the
+    /// round-trip time. The maximum buffer size is raised to the receive window if lower.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the receive window is less than 256 KiB.
+    pub fn set_receive_window(&mut self, n: u32) -> &mut Self {
+        self.inner.set_receive_window(n);
+        if self.max_buffer_size < n as usize {
+            self.set_max_buffer_size(n as usize);
+        }
+        self
+    }
+
+    /// Sets the maximum size of the buffer of received data of each substream.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn set_max_buffer_size(&mut self, n: usize) -> &mut Self {
+        self.inner.set_max_buffer_size(n);
+        self.max_buffer_size = n;
+        self
+    }
+
+    /// Sets the maximum number of substreams of a connection.
+    pub fn set_max_num_streams(&mut self, n: usize) -> &mut Self {
+        self.inner.set_max_num_streams(n);
+        self
+    }
+
+    /// Sets the maximum number of substreams opened by the remote at the same time.
+    ///
+    /// The substreams the remote opens beyond this limit are reset, without closing the
+    /// connection. Not limited by default.
+    pub fn set_max_inbound_substreams(&mut self, n: usize) -> &mut Self {
+        self.max_inbound_substreams = n;
+        self
+    }
+
+    /// Sets a callback called with the ID of each substream reset because of the limit set
+    /// with [`Config::set_max_inbound_substreams`].
+    pub fn on_substream_refused<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(u32) + Send + Sync + 'static
+    {
+        self.on_substream_refused = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sends a yamux ping to the remote every `interval` and closes the connection with an
+    /// error if the remote doesn't answer within `timeout`.
+    ///
+    /// This keeps idle connections alive, e.g. through NATs, and detects dead connections
+    /// independently of the protocols of the substreams. Disabled by default.
+    pub fn set_keep_alive(&mut self, interval: Duration, timeout: Duration) -> &mut Self {
+        self.keep_alive = Some(KeepAliveConfig { interval, timeout });
+        self
+    }
+
+    /// Sets when window updates are sent.
+    ///
+    /// See [`WindowUpdateMode`] for the risk of deadlocks of [`WindowUpdateMode::OnRead`].
+    pub fn set_window_update_mode(&mut self, mode: WindowUpdateMode) -> &mut Self {
+        self.inner.set_window_update_mode(mode);
+        self
+    }
+
+    /// Scales the receive window of new connections to the bandwidth-delay product
+    /// estimated by `window`, overriding [`Config::set_receive_window`].
+    pub fn set_adaptive_window(&mut self, window: AdaptiveWindow) -> &mut Self {
+        self.adaptive_window = Some(window);
+        self
+    }
+
+    /// Returns the yamux configuration of a new connection.
+    fn connection_config(&self) -> yamux::Config {
+        let mut cfg = self.inner.clone();
+        if let Some(window) = &self.adaptive_window {
+            let receive_window = window.receive_window();
+            cfg.set_receive_window(receive_window);
+            cfg.set_max_buffer_size(cmp::max(self.max_buffer_size, receive_window as usize));
+        }
+        cfg
     }
 
     /// Turn this into a `LocalConfig` for use with upgrades of !Send resources.
@@ -179,7 +488,7 @@ impl Config {
 
 impl Default for Config {
     fn default() -> Self {
-        Config(yamux::Config::default())
+        Config::new(yamux::Config::default())
     }
 }
 
@@ -210,7 +519,9 @@ where
     type Future = future::Ready<Result<Self::Output, Self::Error>>;
 
     fn upgrade_inbound(self, io: C, _: Self::Info) -> Self::Future {
-        future::ready(Ok(Yamux::new(io, self.0, yamux::Mode::Server)))
+        let cfg = self.connection_config();
+        let keep_alive = self.keep_alive;
+        future::ready(Ok(Yamux::with_keep_alive(io, cfg, yamux::Mode::Server, keep_alive).with_config(self)))
     }
 }
 
@@ -223,7 +534,9 @@ where
     type Future = future::Ready<Result<Self::Output, Self::Error>>;
 
     fn upgrade_inbound(self, io: C, _: Self::Info) -> Self::Future {
-        future::ready(Ok(Yamux::local(io, (self.0).0, yamux::Mode::Server)))
+        let cfg = self.0.connection_config();
+        let keep_alive = self.0.keep_alive;
+        future::ready(Ok(Yamux::local_with_keep_alive(io, cfg, yamux::Mode::Server, keep_alive).with_config(self.0)))
     }
 }
 
@@ -236,7 +549,9 @@ where
     type Future = future::Ready<Result<Self::Output, Self::Error>>;
 
     fn upgrade_outbound(self, io: C, _: Self::Info) -> Self::Future {
-        future::ready(Ok(Yamux::new(io, self.0, yamux::Mode::Client)))
+        let cfg = self.connection_config();
+        let keep_alive = self.keep_alive;
+        future::ready(Ok(Yamux::with_keep_alive(io, cfg, yamux::Mode::Client, keep_alive).with_config(self)))
     }
 }
 
@@ -249,7 +564,9 @@ where
     type Future = future::Ready<Result<Self::Output, Self::Error>>;
 
     fn upgrade_outbound(self, io: C, _: Self::Info) -> Self::Future {
-        future::ready(Ok(Yamux::local(io, (self.0).0, yamux::Mode::Client)))
+        let cfg = self.0.connection_config();
+        let keep_alive = self.0.keep_alive;
+        future::ready(Ok(Yamux::local_with_keep_alive(io, cfg, yamux::Mode::Client, keep_alive).with_config(self.0)))
     }
 }
 
@@ -0,0 +1,161 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Scaling of the receive window to the bandwidth-delay product of links.
+
+use parking_lot::Mutex;
+use std::{cmp, sync::Arc, time::{Duration, Instant}};
+
+/// The minimum receive window, as per the yamux specification.
+pub(crate) const MIN_RECEIVE_WINDOW: u32 = 256 * 1024;
+
+/// The minimum duration over which the throughput is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Scales the receive window of new connections to the observed bandwidth-delay product.
+///
+/// With a fixed receive window, the throughput of a substream is bounded by the window
+/// divided by the round-trip time, e.g. about 2.5 MB/s for the default window of 256 KiB
+/// over a link with a round-trip time of 100 ms. An `AdaptiveWindow` estimates the
+/// bandwidth-delay product of the links of the connections it is used with and sizes the
+/// receive window of new connections to twice the estimate, within bounds.
+///
+/// The throughput is measured on the substreams of the connections. The round-trip time
+/// is not observable by yamux itself and is reported with [`AdaptiveWindow::record_rtt`],
+/// e.g. with the results of the ping protocol. Since yamux fixes the receive window of a
+/// connection when it is created, the estimate applies to subsequent connections.
+///
+/// Cloning an `AdaptiveWindow` returns a handle to the same estimate.
+#[derive(Debug, Clone)]
+pub struct AdaptiveWindow {
+    max_receive_window: u32,
+    estimate: Arc<Mutex<Estimate>>,
+}
+
+#[derive(Debug, Default)]
+struct Estimate {
+    /// The smoothed round-trip time.
+    rtt: Option<Duration>,
+    /// The highest recent throughput in bytes per second.
+    throughput: f64,
+}
+
+impl AdaptiveWindow {
+    /// Creates an estimate scaling the receive window up to `max_receive_window`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_receive_window` is less than 256 KiB.
+    pub fn new(max_receive_window: u32) -> Self {
+        assert!(max_receive_window >= MIN_RECEIVE_WINDOW, "the receive window must be at least 256 KiB");
+        AdaptiveWindow {
+            max_receive_window,
+            estimate: Arc::new(Mutex::new(Estimate::default())),
+        }
+    }
+
+    /// Records a round-trip time measurement, which is smoothed as by TCP.
+    pub fn record_rtt(&self, rtt: Duration) {
+        let mut estimate = self.estimate.lock();
+        estimate.rtt = Some(match estimate.rtt {
+            Some(srtt) => srtt.mul_f64(0.875) + rtt.mul_f64(0.125),
+            None => rtt,
+        });
+    }
+
+    /// Records that `bytes` were received within `elapsed`.
+    ///
+    /// The estimate follows increases immediately and decays slowly, such that idle
+    /// periods do not reset it.
+    fn record_throughput(&self, bytes: u64, elapsed: Duration) {
+        let sample = bytes as f64 / elapsed.as_secs_f64();
+        let mut estimate = self.estimate.lock();
+        estimate.throughput = f64::max(sample, estimate.throughput * 0.9);
+    }
+
+    /// Returns the receive window for a new connection.
+    pub fn receive_window(&self) -> u32 {
+        let estimate = self.estimate.lock();
+        let bdp = match estimate.rtt {
+            Some(rtt) => estimate.throughput * rtt.as_secs_f64(),
+            None => return MIN_RECEIVE_WINDOW,
+        };
+        let window = (2.0 * bdp).round().min(f64::from(self.max_receive_window)) as u32;
+        cmp::max(window, MIN_RECEIVE_WINDOW)
+    }
+}
+
+/// Measures the throughput of the substreams of a connection.
+#[derive(Debug)]
+pub(crate) struct Meter {
+    window: AdaptiveWindow,
+    start: Instant,
+    bytes: u64,
+}
+
+impl Meter {
+    pub(crate) fn new(window: AdaptiveWindow) -> Self {
+        Meter { window, start: Instant::now(), bytes: 0 }
+    }
+
+    /// Records that `n` bytes were read.
+    pub(crate) fn record(&mut self, n: usize) {
+        self.bytes += n as u64;
+        let elapsed = self.start.elapsed();
+        if elapsed >= SAMPLE_INTERVAL {
+            self.window.record_throughput(self.bytes, elapsed);
+            self.start = Instant::now();
+            self.bytes = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_follows_bandwidth_delay_product() {
+        let window = AdaptiveWindow::new(16 * 1024 * 1024);
+        assert_eq!(window.receive_window(), MIN_RECEIVE_WINDOW);
+
+        // 10 MB/s over 100 ms: a bandwidth-delay product of 1 MB.
+        window.record_throughput(1_000_000, Duration::from_millis(100));
+        assert_eq!(window.receive_window(), MIN_RECEIVE_WINDOW);
+        window.record_rtt(Duration::from_millis(100));
+        assert_eq!(window.receive_window(), 2_000_000);
+
+        // Slower samples decay the estimate gradually.
+        window.record_throughput(0, Duration::from_secs(1));
+        assert_eq!(window.receive_window(), 1_800_000);
+
+        // Bounded by the maximum.
+        window.record_throughput(1_000_000_000, Duration::from_secs(1));
+        assert_eq!(window.receive_window(), 16 * 1024 * 1024);
+    }
+
+    #[test]
+    fn rtt_is_smoothed() {
+        let window = AdaptiveWindow::new(MIN_RECEIVE_WINDOW);
+        window.record_rtt(Duration::from_millis(80));
+        window.record_rtt(Duration::from_millis(160));
+        assert_eq!(window.estimate.lock().rtt, Some(Duration::from_millis(90)));
+    }
+}
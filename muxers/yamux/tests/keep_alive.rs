@@ -0,0 +1,87 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use futures::{future::{self, Either}, prelude::*};
+use libp2p_core::{
+    Multiaddr,
+    Transport,
+    multiaddr::Protocol,
+    muxing::StreamMuxer,
+    transport::{ListenerEvent, MemoryTransport, memory::Channel},
+    upgrade::{InboundUpgrade, OutboundUpgrade}
+};
+use std::time::Duration;
+use wasm_timer::Delay;
+
+fn keep_alive_config() -> libp2p_yamux::Config {
+    let mut cfg = libp2p_yamux::Config::default();
+    cfg.set_keep_alive(Duration::from_millis(10), Duration::from_millis(100));
+    cfg
+}
+
+/// Returns both ends of a new memory connection.
+async fn connect() -> (Channel<Vec<u8>>, Channel<Vec<u8>>) {
+    let addr = Multiaddr::from(Protocol::Memory(rand::random::<u64>()));
+    let mut listener = MemoryTransport.listen_on(addr.clone()).unwrap();
+    let dial = MemoryTransport.dial(addr).unwrap();
+    let accept = async {
+        loop {
+            if let ListenerEvent::Upgrade { upgrade, .. } = listener.next().await.unwrap().unwrap() {
+                return upgrade.await.unwrap()
+            }
+        }
+    };
+    future::join(async { dial.await.unwrap() }, accept).await
+}
+
+#[test]
+fn dead_connection_times_out() {
+    // A remote which never answers the pings is detected.
+    futures::executor::block_on(async {
+        let (dialer, _listener) = connect().await;
+        let muxer = keep_alive_config().upgrade_outbound(dialer, b"/yamux/1.0.0").await.unwrap();
+
+        let inbound = future::poll_fn(|cx| muxer.poll_inbound(cx));
+        match future::select(Box::pin(inbound), Delay::new(Duration::from_secs(5))).await {
+            Either::Left((result, _)) => assert!(result.is_err()),
+            Either::Right(_) => panic!("the connection wasn't closed"),
+        }
+    });
+}
+
+#[test]
+fn live_connection_stays_open() {
+    // A remote answering the pings keeps the connection open, even though it's idle.
+    futures::executor::block_on(async {
+        let (dialer, listener) = connect().await;
+        let dialer = keep_alive_config().upgrade_outbound(dialer, b"/yamux/1.0.0").await.unwrap();
+        let listener = keep_alive_config().upgrade_inbound(listener, b"/yamux/1.0.0").await.unwrap();
+
+        let inbound = future::select(
+            Box::pin(future::poll_fn(|cx| dialer.poll_inbound(cx))),
+            Box::pin(future::poll_fn(|cx| listener.poll_inbound(cx))),
+        );
+        match future::select(inbound, Delay::new(Duration::from_millis(500))).await {
+            Either::Left(_) => panic!("the connection was closed"),
+            Either::Right(_) => (),
+        }
+    });
+}
@@ -22,10 +22,13 @@ use crate::handler::{IdentifyHandler, IdentifyHandlerEvent};
 use crate::protocol::{IdentifyInfo, ReplySubstream};
 use futures::prelude::*;
 use libp2p_core::{
+    AddressFilter,
     ConnectedPoint,
     Multiaddr,
     PeerId,
+    PeerRecord,
     PublicKey,
+    identity::Keypair,
     upgrade::{Negotiated, ReadOneError, UpgradeError}
 };
 use libp2p_swarm::{
@@ -46,8 +49,14 @@ pub struct Identify<TSubstream> {
     agent_version: String,
     /// The public key of the local node. To report on the wire.
     local_public_key: PublicKey,
+    /// The keypair of the local node, if peer records are to be signed.
+    local_key: Option<Keypair>,
+    /// The last peer record signed with `local_key`.
+    local_record: Option<PeerRecord>,
     /// For each peer we're connected to, the observed address to send back to it.
     observed_addresses: HashMap<PeerId, Multiaddr>,
+    /// Filter that the listen addresses reported by remotes must pass.
+    address_filter: AddressFilter,
     /// Pending replies to send.
     pending_replies: VecDeque<Reply<TSubstream>>,
     /// Pending events to be emitted when polled.
@@ -76,11 +85,49 @@ impl<TSubstream> Identify<TSubstream> {
             protocol_version,
             agent_version,
             local_public_key,
+            local_key: None,
+            local_record: None,
             observed_addresses: HashMap::new(),
+            address_filter: AddressFilter::default(),
             pending_replies: VecDeque::new(),
             events: VecDeque::new(),
         }
     }
+
+    /// Creates a new `Identify` network behaviour that sends the listen addresses of the local
+    /// node in a peer record signed with `local_key`, in addition to the bare addresses.
+    pub fn with_keypair(protocol_version: String, agent_version: String, local_key: Keypair) -> Self {
+        let mut identify = Identify::new(protocol_version, agent_version, local_key.public());
+        identify.local_key = Some(local_key);
+        identify
+    }
+
+    /// Returns a peer record of the given addresses, reusing the last one if they didn't change.
+    fn peer_record(&mut self, listen_addrs: &[Multiaddr]) -> Option<PeerRecord> {
+        let key = self.local_key.as_ref()?;
+        match &self.local_record {
+            Some(record) if record.addresses() == listen_addrs => {}
+            _ => {
+                // Sequence numbers are derived from the time in seconds and must increase even
+                // if the addresses change twice within a second.
+                let min_seq = self.local_record.as_ref().map_or(0, |r| r.seq() + 1);
+                let record = PeerRecord::new(key, listen_addrs.to_vec())
+                    .and_then(|r| if r.seq() < min_seq {
+                        PeerRecord::with_seq(key, min_seq, listen_addrs.to_vec())
+                    } else {
+                        Ok(r)
+                    });
+                match record {
+                    Ok(record) => self.local_record = Some(record),
+                    Err(err) => {
+                        log::debug!("Failed to sign peer record; error = {}", err);
+                        self.local_record = None;
+                    }
+                }
+            }
+        }
+        self.local_record.clone()
+    }
 }
 
 impl<TSubstream> NetworkBehaviour for Identify<TSubstream>
@@ -111,6 +158,10 @@ where
         self.observed_addresses.remove(peer_id);
     }
 
+    fn inject_address_filter(&mut self, filter: &AddressFilter) {
+        self.address_filter = filter.clone();
+    }
+
     fn inject_node_event(
         &mut self,
         peer_id: PeerId,
@@ -118,16 +169,20 @@ where
     ) {
         match event {
             IdentifyHandlerEvent::Identified(remote) => {
+                let mut info = remote.info;
+                let filter = &self.address_filter;
+                info.listen_addrs.retain(|addr| filter.allows(addr));
                 self.events.push_back(
                     NetworkBehaviourAction::GenerateEvent(
                         IdentifyEvent::Received {
-                            peer_id,
-                            info: remote.info,
+                            peer_id: peer_id.clone(),
+                            info,
                             observed_addr: remote.observed_addr.clone(),
                         }));
                 self.events.push_back(
                     NetworkBehaviourAction::ReportObservedAddr {
                         address: remote.observed_addr,
+                        observer: Some(peer_id),
                     });
             }
             IdentifyHandlerEvent::Identify(sender) => {
@@ -161,6 +216,9 @@ where
         >,
     > {
         if let Some(event) = self.events.pop_front() {
+            if let NetworkBehaviourAction::GenerateEvent(IdentifyEvent::Received { peer_id, info, .. }) = &event {
+                params.protocol_capabilities().set_advertised(peer_id, &info.protocols);
+            }
             return Poll::Ready(event);
         }
 
@@ -174,6 +232,7 @@ where
 
             let mut listen_addrs: Vec<_> = params.external_addresses().collect();
             listen_addrs.extend(params.listened_addresses());
+            let peer_record = self.peer_record(&listen_addrs);
 
             let mut sending = 0;
             let to_send = self.pending_replies.len() + 1;
@@ -187,6 +246,7 @@ where
                             agent_version: self.agent_version.clone(),
                             listen_addrs: listen_addrs.clone(),
                             protocols: protocols.clone(),
+                            peer_record: peer_record.clone(),
                         };
                         let io = Box::pin(io.send(info, &observed));
                         reply = Some(Reply::Sending { peer, io });
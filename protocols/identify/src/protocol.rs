@@ -22,7 +22,9 @@ use crate::structs_proto;
 use futures::prelude::*;
 use libp2p_core::{
     Multiaddr,
+    PeerRecord,
     PublicKey,
+    SignedEnvelope,
     upgrade::{self, InboundUpgrade, OutboundUpgrade, UpgradeInfo}
 };
 use log::{debug, trace};
@@ -76,13 +78,17 @@ where
 
         let pubkey_bytes = info.public_key.into_protobuf_encoding();
 
+        let signed_peer_record = info.peer_record
+            .map(|record| record.into_signed_envelope().into_protobuf_encoding());
+
         let message = structs_proto::Identify {
             agent_version: Some(info.agent_version),
             protocol_version: Some(info.protocol_version),
             public_key: Some(pubkey_bytes),
             listen_addrs: listen_addrs,
             observed_addr: Some(observed_addr.to_vec()),
-            protocols: info.protocols
+            protocols: info.protocols,
+            signed_peer_record,
         };
 
         async move {
@@ -105,9 +111,16 @@ pub struct IdentifyInfo {
     /// the HTTP protocol.
     pub agent_version: String,
     /// The addresses that the peer is listening on.
+    ///
+    /// Addresses denied by the address filter of the swarm are removed on reception.
     pub listen_addrs: Vec<Multiaddr>,
     /// The list of protocols supported by the peer, e.g. `/ipfs/ping/1.0.0`.
     pub protocols: Vec<String>,
+    /// A record of the listen addresses signed by the peer.
+    ///
+    /// When a valid record is received, `listen_addrs` contains the addresses of the record
+    /// rather than the unauthenticated addresses of the message.
+    pub peer_record: Option<PeerRecord>,
 }
 
 impl UpgradeInfo for IdentifyProtocolConfig {
@@ -188,13 +201,21 @@ fn parse_proto_msg(msg: impl AsRef<[u8]>) -> Result<(IdentifyInfo, Multiaddr), i
             let public_key = PublicKey::from_protobuf_encoding(&msg.public_key.unwrap_or_default())
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
+            let peer_record = msg.signed_peer_record
+                .and_then(|bytes| parse_peer_record(&bytes, &public_key));
+            let listen_addrs = match &peer_record {
+                Some(record) => record.addresses().to_vec(),
+                None => listen_addrs,
+            };
+
             let observed_addr = bytes_to_multiaddr(msg.observed_addr.unwrap_or_default())?;
             let info = IdentifyInfo {
                 public_key,
                 protocol_version: msg.protocol_version.unwrap_or_default(),
                 agent_version: msg.agent_version.unwrap_or_default(),
                 listen_addrs,
-                protocols: msg.protocols
+                protocols: msg.protocols,
+                peer_record,
             };
 
             Ok((info, observed_addr))
@@ -204,16 +225,39 @@ fn parse_proto_msg(msg: impl AsRef<[u8]>) -> Result<(IdentifyInfo, Multiaddr), i
     }
 }
 
+// Decodes and verifies a signed peer record sent by the peer with the given public key.
+// Invalid records are ignored, in which case the unauthenticated listen addresses are used.
+fn parse_peer_record(bytes: &[u8], public_key: &PublicKey) -> Option<PeerRecord> {
+    let record = SignedEnvelope::from_protobuf_encoding(bytes)
+        .map_err(|e| e.to_string())
+        .and_then(|envelope| PeerRecord::from_signed_envelope(envelope).map_err(|e| e.to_string()));
+    match record {
+        Ok(record) if record.peer_id().is_public_key(public_key) == Some(true) => Some(record),
+        Ok(_) => {
+            debug!("Ignoring peer record of another peer");
+            None
+        }
+        Err(err) => {
+            debug!("Ignoring invalid peer record; error = {}", err);
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::protocol::{IdentifyInfo, RemoteInfo, IdentifyProtocolConfig};
+    use crate::protocol::{IdentifyInfo, RemoteInfo, IdentifyProtocolConfig, parse_proto_msg};
+    use crate::structs_proto;
     use libp2p_tcp::TcpConfig;
     use futures::{prelude::*, channel::oneshot};
     use libp2p_core::{
         identity,
+        Multiaddr,
+        PeerRecord,
         Transport,
         upgrade::{self, apply_outbound, apply_inbound}
     };
+    use prost::Message;
 
     #[test]
     fn correct_transfer() {
@@ -250,6 +294,7 @@ mod tests {
                         "/ip6/::1/udp/1000".parse().unwrap(),
                     ],
                     protocols: vec!["proto1".to_string(), "proto2".to_string()],
+                    peer_record: None,
                 },
                 &"/ip4/100.101.102.103/tcp/5000".parse().unwrap(),
             ).await.unwrap();
@@ -269,8 +314,54 @@ mod tests {
                 &["/ip4/80.81.82.83/tcp/500".parse().unwrap(),
                 "/ip6/::1/udp/1000".parse().unwrap()]);
             assert_eq!(info.protocols, &["proto1".to_string(), "proto2".to_string()]);
+            assert!(info.peer_record.is_none());
 
             bg_task.await;
         });
     }
+
+    fn encode(key: &identity::PublicKey, listen_addrs: &[Multiaddr], record: Option<&PeerRecord>)
+        -> Vec<u8>
+    {
+        let message = structs_proto::Identify {
+            agent_version: None,
+            protocol_version: None,
+            public_key: Some(key.clone().into_protobuf_encoding()),
+            listen_addrs: listen_addrs.iter().map(|a| a.to_vec()).collect(),
+            observed_addr: Some(Multiaddr::empty().to_vec()),
+            protocols: Vec::new(),
+            signed_peer_record: record
+                .map(|r| r.to_signed_envelope().into_protobuf_encoding()),
+        };
+        let mut bytes = Vec::new();
+        message.encode(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn signed_peer_record_overrides_listen_addrs() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let signed: Multiaddr = "/ip4/1.2.3.4/tcp/1234".parse().unwrap();
+        let unsigned: Multiaddr = "/ip4/5.6.7.8/tcp/5678".parse().unwrap();
+        let record = PeerRecord::new(&keypair, vec![signed.clone()]).unwrap();
+
+        let msg = encode(&keypair.public(), &[unsigned], Some(&record));
+        let (info, _) = parse_proto_msg(msg).unwrap();
+        assert_eq!(info.listen_addrs, vec![signed]);
+        assert_eq!(info.peer_record, Some(record));
+    }
+
+    #[test]
+    fn peer_record_of_other_peer_is_ignored() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let other = identity::Keypair::generate_ed25519();
+        let unsigned: Multiaddr = "/ip4/5.6.7.8/tcp/5678".parse().unwrap();
+        let record = PeerRecord::new(&other, vec!["/ip4/1.2.3.4/tcp/1234".parse().unwrap()])
+            .unwrap();
+
+        let msg = encode(&keypair.public(), std::slice::from_ref(&unsigned), Some(&record));
+        let (info, _) = parse_proto_msg(msg).unwrap();
+        assert_eq!(info.listen_addrs, vec![unsigned]);
+        assert!(info.peer_record.is_none());
+    }
 }
@@ -27,17 +27,19 @@ use crate::addresses::Addresses;
 use crate::handler::{KademliaHandler, KademliaRequestId, KademliaHandlerEvent, KademliaHandlerIn};
 use crate::jobs::*;
 use crate::kbucket::{self, KBucketsTable, NodeStatus};
+use crate::metrics::{KademliaMetrics, QueryKind, QueryOutcome};
 use crate::protocol::{KadConnectionType, KadPeer};
-use crate::query::{Query, QueryId, QueryPool, QueryConfig, QueryPoolState};
-use crate::record::{self, store::{self, RecordStore}, Record, ProviderRecord};
+use crate::query::{Query, QueryId, QueryPool, QueryConfig, QueryParallelism, QueryPoolState};
+use crate::record::{self, store::{self, RecordStore}, validator::RecordValidator, Record, ProviderRecord};
 use fnv::{FnvHashMap, FnvHashSet};
 use futures::prelude::*;
-use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
+use libp2p_core::{AddressFilter, ConnectedPoint, Multiaddr, PeerId};
 use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters, ProtocolsHandler};
 use log::{info, debug, warn};
 use smallvec::SmallVec;
 use std::{borrow::Cow, error, iter, marker::PhantomData, time::Duration};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
 use std::num::NonZeroUsize;
 use std::task::{Context, Poll};
 use wasm_timer::Instant;
@@ -58,6 +60,11 @@ pub struct Kademlia<TSubstream, TStore> {
     /// This is a superset of the connected peers currently in the routing table.
     connected_peers: FnvHashSet<PeerId>,
 
+    /// The connected peers that are not in the routing table and have not
+    /// yet been confirmed to support the configured protocol, together with
+    /// the address to insert into the routing table upon confirmation.
+    unconfirmed_peers: FnvHashMap<PeerId, Option<Multiaddr>>,
+
     /// Periodic job for re-publication of provider records for keys
     /// provided by the local node.
     add_provider_job: Option<AddProviderJob>,
@@ -66,12 +73,46 @@ pub struct Kademlia<TSubstream, TStore> {
     /// regular (value-)records.
     put_record_job: Option<PutRecordJob>,
 
+    /// Periodic job for the removal of expired provider records.
+    expire_providers_job: Option<ExpireProvidersJob>,
+
+    /// Periodic job for bootstrapping the local node.
+    bootstrap_job: Option<BootstrapJob>,
+
+    /// The number of routing table entries below which the local node
+    /// bootstraps whenever the routing table changes.
+    bootstrap_threshold: Option<usize>,
+
+    /// The number of routing table entries observed on the last check
+    /// against the `bootstrap_threshold`.
+    num_routing_entries: usize,
+
+    /// The interval after which a bucket is considered stale and is
+    /// refreshed on the next bootstrap.
+    bucket_refresh_interval: Option<Duration>,
+
     /// The TTL of regular (value-)records.
     record_ttl: Option<Duration>,
 
     /// The TTL of provider records.
     provider_record_ttl: Option<Duration>,
 
+    /// The caching of records found by [`Kademlia::get_record`].
+    caching: KademliaCaching,
+
+    /// Filter that addresses must pass to be put into the routing table or
+    /// announced to other peers, as set by the swarm.
+    address_filter: AddressFilter,
+
+    /// The validator of records received from other peers, if any.
+    record_validator: Option<Box<dyn RecordValidator>>,
+
+    /// The receiver of query and routing table metrics, if any.
+    metrics: Option<Box<dyn KademliaMetrics>>,
+
+    /// The number of entries per bucket last reported to `metrics`.
+    bucket_sizes: Vec<usize>,
+
     /// Queued events to return when the behaviour is being polled.
     queued_events: VecDeque<NetworkBehaviourAction<KademliaHandlerIn<QueryId>, KademliaEvent>>,
 
@@ -95,6 +136,32 @@ pub struct KademliaConfig {
     record_publication_interval: Option<Duration>,
     provider_record_ttl: Option<Duration>,
     provider_publication_interval: Option<Duration>,
+    provider_expiry_interval: Option<Duration>,
+    bootstrap_interval: Option<Duration>,
+    bootstrap_threshold: Option<usize>,
+    bucket_refresh_interval: Option<Duration>,
+    caching: KademliaCaching,
+}
+
+/// The caching of records after a successful [`Kademlia::get_record`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KademliaCaching {
+    /// Found records are not cached.
+    Disabled,
+    /// A found record is stored at up to `max_peers` of the closest peers
+    /// to the key that were contacted but did not return the record.
+    /// Nothing is cached if the lookup found records with different values.
+    ///
+    /// The further a peer is from the key, the sooner the cached record
+    /// expires: The record TTL is halved for every contacted peer that
+    /// is closer to the key.
+    Enabled { max_peers: u16 },
+}
+
+impl Default for KademliaCaching {
+    fn default() -> Self {
+        KademliaCaching::Enabled { max_peers: 1 }
+    }
 }
 
 impl Default for KademliaConfig {
@@ -108,6 +175,11 @@ impl Default for KademliaConfig {
             record_publication_interval: Some(Duration::from_secs(24 * 60 * 60)),
             provider_publication_interval: Some(Duration::from_secs(12 * 60 * 60)),
             provider_record_ttl: Some(Duration::from_secs(24 * 60 * 60)),
+            provider_expiry_interval: Some(Duration::from_secs(60 * 60)),
+            bootstrap_interval: None,
+            bootstrap_threshold: None,
+            bucket_refresh_interval: Some(Duration::from_secs(60 * 60)),
+            caching: KademliaCaching::default(),
         }
     }
 }
@@ -117,6 +189,11 @@ impl KademliaConfig {
     ///
     /// Kademlia nodes only communicate with other nodes using the same protocol name. Using a
     /// custom name therefore allows to segregate the DHT from others, if that is desired.
+    ///
+    /// Several `Kademlia` behaviours with different protocol names can be part of the
+    /// same swarm, e.g. for a public and a private DHT. Each has its own routing table
+    /// and record store, and a connected peer only enters the routing table of a
+    /// behaviour once it has been confirmed to support the behaviour's protocol.
     pub fn set_protocol_name(&mut self, name: impl Into<Cow<'static, [u8]>>) -> &mut Self {
         self.protocol_name_override = Some(name.into());
         self
@@ -142,6 +219,17 @@ impl KademliaConfig {
         self
     }
 
+    /// Sets the default parallelism of iterative queries.
+    ///
+    /// The parallelism of an individual query can be changed with
+    /// [`Kademlia::set_query_parallelism`]. The default is an `α` of
+    /// [`ALPHA_VALUE`](crate::ALPHA_VALUE), a `β` of the replication factor
+    /// and no adaptive parallelism.
+    pub fn set_query_parallelism(&mut self, parallelism: QueryParallelism) -> &mut Self {
+        self.query_config.parallelism = parallelism;
+        self
+    }
+
     /// Sets the TTL for stored records.
     ///
     /// The TTL should be significantly longer than the (re-)publication
@@ -215,6 +303,56 @@ impl KademliaConfig {
         self.provider_publication_interval = interval;
         self
     }
+
+    /// Sets the interval at which expired provider records are removed
+    /// from the record store. The default is 1 hour.
+    ///
+    /// `None` means that expired provider records are never removed, although
+    /// they are not handed out to other nodes anymore.
+    pub fn set_provider_expiry_interval(&mut self, interval: Option<Duration>) -> &mut Self {
+        self.provider_expiry_interval = interval;
+        self
+    }
+
+    /// Sets the interval at which the local node automatically bootstraps,
+    /// see [`Kademlia::bootstrap`]. The default is `None`.
+    ///
+    /// `None` means that the local node never bootstraps periodically.
+    pub fn set_bootstrap_interval(&mut self, interval: Option<Duration>) -> &mut Self {
+        self.bootstrap_interval = interval;
+        self
+    }
+
+    /// Sets the number of routing table entries below which the local node
+    /// automatically bootstraps whenever an entry is added to or removed
+    /// from the routing table. The default is `None`.
+    ///
+    /// `None` means that the local node only bootstraps periodically
+    /// and when [`Kademlia::bootstrap`] is called.
+    pub fn set_bootstrap_threshold(&mut self, threshold: Option<usize>) -> &mut Self {
+        self.bootstrap_threshold = threshold;
+        self
+    }
+
+    /// Sets the interval after which a bucket of the routing table for whose
+    /// keys no lookup has been performed is considered stale. The default is
+    /// 1 hour.
+    ///
+    /// Only stale buckets are refreshed when bootstrapping. `None` means
+    /// that all buckets are refreshed on every bootstrap.
+    pub fn set_bucket_refresh_interval(&mut self, interval: Option<Duration>) -> &mut Self {
+        self.bucket_refresh_interval = interval;
+        self
+    }
+
+    /// Sets the caching of records found by [`Kademlia::get_record`].
+    ///
+    /// The default is to cache a found record at the closest peer to the
+    /// key that did not return it. See [`KademliaCaching`].
+    pub fn set_caching(&mut self, caching: KademliaCaching) -> &mut Self {
+        self.caching = caching;
+        self
+    }
 }
 
 impl<TSubstream, TStore> Kademlia<TSubstream, TStore>
@@ -244,6 +382,14 @@ where
             .provider_publication_interval
             .map(AddProviderJob::new);
 
+        let expire_providers_job = config
+            .provider_expiry_interval
+            .map(ExpireProvidersJob::new);
+
+        let bootstrap_job = config
+            .bootstrap_interval
+            .map(BootstrapJob::new);
+
         Kademlia {
             store,
             kbuckets: KBucketsTable::new(local_key, config.kbucket_pending_timeout),
@@ -251,10 +397,21 @@ where
             queued_events: VecDeque::with_capacity(config.query_config.replication_factor.get()),
             queries: QueryPool::new(config.query_config),
             connected_peers: Default::default(),
+            unconfirmed_peers: Default::default(),
             add_provider_job,
             put_record_job,
+            expire_providers_job,
+            bootstrap_job,
+            bootstrap_threshold: config.bootstrap_threshold,
+            num_routing_entries: 0,
+            bucket_refresh_interval: config.bucket_refresh_interval,
             record_ttl: config.record_ttl,
             provider_record_ttl: config.provider_record_ttl,
+            caching: config.caching,
+            address_filter: AddressFilter::default(),
+            record_validator: None,
+            metrics: None,
+            bucket_sizes: Vec::new(),
             marker: PhantomData,
         }
     }
@@ -275,8 +432,13 @@ where
     ///      in the DHT.
     ///
     /// If the routing table has been updated as a result of this operation,
-    /// a [`KademliaEvent::RoutingUpdated`] event is emitted.
+    /// a [`KademliaEvent::RoutingUpdated`] event is emitted. Addresses denied
+    /// by the address filter of the swarm are ignored.
     pub fn add_address(&mut self, peer: &PeerId, address: Multiaddr) {
+        if !self.address_filter.allows(&address) {
+            debug!("Filtered address {} of {} not added to routing table", address, peer);
+            return
+        }
         let key = kbucket::Key::new(peer.clone());
         match self.kbuckets.entry(&key) {
             kbucket::Entry::Present(mut entry, _) => {
@@ -331,10 +493,84 @@ where
         self.kbuckets.iter().map(|entry| entry.node.key.preimage())
     }
 
+    /// Returns an iterator over the non-empty buckets of the Kademlia routing
+    /// table, ordered by increasing distance to the local key.
+    ///
+    /// The entries of a bucket carry the status of the corresponding peer,
+    /// when the peer has last been seen connected and whether it is protected
+    /// from eviction.
+    pub fn kbuckets(&mut self)
+        -> impl Iterator<Item = kbucket::KBucketRef<'_, kbucket::Key<PeerId>, Addresses>>
+    {
+        self.kbuckets.buckets().filter(|b| b.num_entries() > 0)
+    }
+
+    /// Renders the contents of the Kademlia routing table in a human-readable
+    /// form, one line per non-empty bucket followed by one line per entry,
+    /// e.g. for logging when debugging routing problems.
+    pub fn dump_routing_table(&mut self) -> String {
+        let mut dump = String::new();
+        for bucket in self.kbuckets() {
+            let _ = write!(dump, "bucket {}: {} entries, {} connected",
+                bucket.index(), bucket.num_entries(), bucket.num_connected());
+            if bucket.has_pending() {
+                dump.push_str(", 1 pending");
+            }
+            dump.push('\n');
+            for entry in bucket.iter() {
+                let _ = write!(dump, "  {} {:?}", entry.node.key.preimage(), entry.status);
+                if let Some(t) = entry.last_seen {
+                    let _ = write!(dump, ", last seen {:?} ago", t.elapsed());
+                }
+                if entry.protected {
+                    dump.push_str(", protected");
+                }
+                for addr in entry.node.value.iter() {
+                    let _ = write!(dump, " {}", addr);
+                }
+                dump.push('\n');
+            }
+        }
+        dump
+    }
+
+    /// Removes a peer from the Kademlia routing table, regardless of whether
+    /// it is protected, returning the removed entry.
+    ///
+    /// Returns `None` if the peer is neither in the routing table nor pending
+    /// insertion. Note that the peer may be inserted again, e.g. when it
+    /// (re)connects and its address is known.
+    pub fn remove_peer(&mut self, peer: &PeerId)
+        -> Option<kbucket::EntryView<kbucket::Key<PeerId>, Addresses>>
+    {
+        let key = kbucket::Key::new(peer.clone());
+        match self.kbuckets.entry(&key) {
+            kbucket::Entry::Present(entry, _) => Some(entry.remove()),
+            kbucket::Entry::Pending(entry, _) => Some(entry.remove()),
+            kbucket::Entry::Absent(..) | kbucket::Entry::SelfEntry => None
+        }
+    }
+
+    /// Protects a peer in the Kademlia routing table from being evicted in
+    /// favour of a newly discovered peer, e.g. to pin well-known bootstrap nodes.
+    ///
+    /// Returns `false` if the peer is not in the routing table.
+    pub fn protect_peer(&mut self, peer: &PeerId) -> bool {
+        self.set_protected(peer, true)
+    }
+
+    /// Removes the protection from eviction of a peer in the Kademlia
+    /// routing table, see [`Kademlia::protect_peer`].
+    ///
+    /// Returns `false` if the peer is not in the routing table.
+    pub fn unprotect_peer(&mut self, peer: &PeerId) -> bool {
+        self.set_protected(peer, false)
+    }
+
     /// Performs a lookup for the closest peers to the given key.
     ///
     /// The result of this operation is delivered in [`KademliaEvent::GetClosestPeersResult`].
-    pub fn get_closest_peers<K>(&mut self, key: K)
+    pub fn get_closest_peers<K>(&mut self, key: K) -> QueryId
     where
         K: AsRef<[u8]> + Clone
     {
@@ -343,13 +579,18 @@ where
         let target = kbucket::Key::new(key);
         let peers = self.kbuckets.closest_keys(&target);
         let inner = QueryInner::new(info);
-        self.queries.add_iter_closest(target.clone(), peers, inner);
+        self.queries.add_iter_closest(target.clone(), peers, inner)
     }
 
     /// Performs a lookup for a record in the DHT.
     ///
-    /// The result of this operation is delivered in [`KademliaEvent::GetRecordResult`].
-    pub fn get_record(&mut self, key: &record::Key, quorum: Quorum) {
+    /// The lookup finishes as soon as `quorum` records have been found. Every
+    /// record found along the way, including a record in local storage, is
+    /// reported as it arrives in a [`KademliaEvent::GetRecordProgress`].
+    /// The final result of this operation is delivered in
+    /// [`KademliaEvent::GetRecordResult`].
+    pub fn get_record(&mut self, key: &record::Key, quorum: Quorum) -> QueryId {
+        let id = self.queries.next_query_id();
         let quorum = quorum.eval(self.queries.config().replication_factor);
         let mut records = Vec::with_capacity(quorum.get());
 
@@ -357,21 +598,41 @@ where
             if record.is_expired(Instant::now()) {
                 self.store.remove(key)
             } else {
-                records.push(record.into_owned());
+                let record = record.into_owned();
+                self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
+                    KademliaEvent::GetRecordProgress(GetRecordProgress {
+                        id,
+                        peer: None,
+                        record: record.clone(),
+                        num_records: 1,
+                        quorum,
+                    })
+                ));
+                records.push(record);
                 if quorum.get() == 1 {
                     self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
-                        KademliaEvent::GetRecordResult(Ok(GetRecordOk { records }))
+                        KademliaEvent::GetRecordResult(Ok(GetRecordOk {
+                            id,
+                            records,
+                            cache_candidates: Vec::new(),
+                        }))
                     ));
-                    return;
+                    return id;
                 }
             }
         }
 
         let target = kbucket::Key::new(key.clone());
-        let info = QueryInfo::GetRecord { key: key.clone(), records, quorum, cache_at: None };
+        let info = QueryInfo::GetRecord {
+            key: key.clone(),
+            records,
+            quorum,
+            cache_candidates: BTreeMap::new(),
+        };
         let peers = self.kbuckets.closest_keys(&target);
         let inner = QueryInner::new(info);
-        self.queries.add_iter_closest(target.clone(), peers, inner);
+        self.queries.continue_iter_closest(id, target.clone(), peers, inner);
+        id
     }
 
     /// Stores a record in the DHT.
@@ -388,28 +649,21 @@ where
     /// does not update the record's expiration in local storage, thus a given record
     /// with an explicit expiration will always expire at that instant and until then
     /// is subject to regular (re-)replication and (re-)publication.
-    pub fn put_record(&mut self, mut record: Record, quorum: Quorum) {
+    ///
+    /// Returns an error if the record could not be stored locally, in which
+    /// case no query is started.
+    pub fn put_record(&mut self, mut record: Record, quorum: Quorum) -> Result<QueryId, store::Error> {
         record.publisher = Some(self.kbuckets.local_key().preimage().clone());
-        if let Err(err) = self.store.put(record.clone()) {
-            self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
-                KademliaEvent::PutRecordResult(Err(
-                    PutRecordError::LocalStorageError {
-                        key: record.key,
-                        cause: err,
-                    }
-                ))
-            ));
-        } else {
-            record.expires = record.expires.or_else(||
-                self.record_ttl.map(|ttl| Instant::now() + ttl));
-            let quorum = quorum.eval(self.queries.config().replication_factor);
-            let target = kbucket::Key::new(record.key.clone());
-            let peers = self.kbuckets.closest_keys(&target);
-            let context = PutRecordContext::Publish;
-            let info = QueryInfo::PreparePutRecord { record, quorum, context };
-            let inner = QueryInner::new(info);
-            self.queries.add_iter_closest(target.clone(), peers, inner);
-        }
+        self.store.put(record.clone())?;
+        record.expires = record.expires.or_else(||
+            self.record_ttl.map(|ttl| Instant::now() + ttl));
+        let quorum = quorum.eval(self.queries.config().replication_factor);
+        let target = kbucket::Key::new(record.key.clone());
+        let peers = self.kbuckets.closest_keys(&target);
+        let context = PutRecordContext::Publish;
+        let info = QueryInfo::PreparePutRecord { record, quorum, context };
+        let inner = QueryInner::new(info);
+        Ok(self.queries.add_iter_closest(target.clone(), peers, inner))
     }
 
     /// Removes the record with the given key from _local_ storage,
@@ -434,28 +688,56 @@ where
         &mut self.store
     }
 
+    /// Sets the validator for records received from other peers.
+    ///
+    /// The validator is consulted for every record received in a `PUT_VALUE`
+    /// request and for every record returned by a remote peer for a
+    /// [`Kademlia::get_record`] lookup. If a record is received for a key that
+    /// is already stored locally, the existing record is only replaced if the
+    /// validator selects the received record over the existing one. The
+    /// records of a successful lookup are ordered such that the record
+    /// selected by the validator comes first.
+    pub fn set_record_validator(&mut self, validator: impl RecordValidator) {
+        self.record_validator = Some(Box::new(validator));
+    }
+
+    /// Sets the receiver of query and routing table metrics.
+    ///
+    /// Once set, the given [`KademliaMetrics`] is notified about every
+    /// finished query and every change to the sizes of the buckets and
+    /// evictions in the routing table, the current bucket sizes being
+    /// reported when the behaviour is next polled.
+    pub fn set_metrics(&mut self, metrics: impl KademliaMetrics) {
+        self.metrics = Some(Box::new(metrics));
+        self.bucket_sizes.clear();
+    }
+
     /// Bootstraps the local node to join the DHT.
     ///
     /// Bootstrapping is a multi-step operation that starts with a lookup of the local node's
     /// own ID in the DHT. This introduces the local node to the other nodes
     /// in the DHT and populates its routing table with the closest neighbours.
     ///
-    /// Subsequently, all buckets farther from the bucket of the closest neighbour are
-    /// refreshed by initiating an additional bootstrapping query for each such
-    /// bucket with random keys.
+    /// Subsequently, all stale buckets farther from the bucket of the closest neighbour
+    /// are refreshed by initiating an additional bootstrapping query for each such
+    /// bucket with random keys. See [`KademliaConfig::set_bucket_refresh_interval`].
     ///
     /// The result(s) of this operation are delivered in [`KademliaEvent::BootstrapResult`],
     /// with one event per bootstrapping query.
     ///
+    /// The local node also bootstraps automatically, as per the configured
+    /// [`KademliaConfig::set_bootstrap_interval`] and
+    /// [`KademliaConfig::set_bootstrap_threshold`].
+    ///
     /// > **Note**: Bootstrapping requires at least one node of the DHT to be known.
     /// > See [`Kademlia::add_address`].
-    pub fn bootstrap(&mut self) {
+    pub fn bootstrap(&mut self) -> QueryId {
         let local_key = self.kbuckets.local_key().clone();
         let info = QueryInfo::Bootstrap { peer: local_key.preimage().clone() };
         let peers = self.kbuckets.closest_keys(&local_key).collect::<Vec<_>>();
         // TODO: Emit error if `peers` is empty? BootstrapError::NoPeers?
         let inner = QueryInner::new(info);
-        self.queries.add_iter_closest(local_key, peers, inner);
+        self.queries.add_iter_closest(local_key, peers, inner)
     }
 
     /// Establishes the local node as a provider of a value for the given key.
@@ -477,22 +759,18 @@ where
     ///
     /// The results of the (repeated) provider announcements sent by this node are
     /// delivered in [`KademliaEvent::AddProviderResult`].
-    pub fn start_providing(&mut self, key: record::Key) {
+    ///
+    /// Returns an error if the provider record could not be stored locally,
+    /// in which case no query is started.
+    pub fn start_providing(&mut self, key: record::Key) -> Result<QueryId, store::Error> {
         let record = ProviderRecord::new(key.clone(), self.kbuckets.local_key().preimage().clone());
-        if let Err(err) = self.store.add_provider(record) {
-            self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
-                KademliaEvent::StartProvidingResult(Err(
-                    AddProviderError::LocalStorageError { key, cause: err }
-                ))
-            ));
-        } else {
-            let target = kbucket::Key::new(key.clone());
-            let peers = self.kbuckets.closest_keys(&target);
-            let context = AddProviderContext::Publish;
-            let info = QueryInfo::PrepareAddProvider { key, context };
-            let inner = QueryInner::new(info);
-            self.queries.add_iter_closest(target.clone(), peers, inner);
-        }
+        self.store.add_provider(record)?;
+        let target = kbucket::Key::new(key.clone());
+        let peers = self.kbuckets.closest_keys(&target);
+        let context = AddProviderContext::Publish;
+        let info = QueryInfo::PrepareAddProvider { key, context };
+        let inner = QueryInner::new(info);
+        Ok(self.queries.add_iter_closest(target.clone(), peers, inner))
     }
 
     /// Stops the local node from announcing that it is a provider for the given key.
@@ -506,7 +784,7 @@ where
     /// Performs a lookup for providers of a value to the given key.
     ///
     /// The result of this operation is delivered in [`KademliaEvent::GetProvidersResult`].
-    pub fn get_providers(&mut self, key: record::Key) {
+    pub fn get_providers(&mut self, key: record::Key) -> QueryId {
         let info = QueryInfo::GetProviders {
             key: key.clone(),
             providers: Vec::new(),
@@ -514,7 +792,23 @@ where
         let target = kbucket::Key::new(key);
         let peers = self.kbuckets.closest_keys(&target);
         let inner = QueryInner::new(info);
-        self.queries.add_iter_closest(target.clone(), peers, inner);
+        self.queries.add_iter_closest(target.clone(), peers, inner)
+    }
+
+    /// Changes the parallelism of the ongoing query with the given ID, as
+    /// returned by the method that started the query.
+    ///
+    /// Only affects the iterative phase of a query, i.e. the lookup of the
+    /// closest peers to a key. Returns `false` if no such query is in progress.
+    /// See also [`KademliaConfig::set_query_parallelism`].
+    pub fn set_query_parallelism(&mut self, id: &QueryId, parallelism: QueryParallelism) -> bool {
+        match self.queries.get_mut(id) {
+            Some(query) => {
+                query.set_parallelism(parallelism);
+                true
+            }
+            None => false
+        }
     }
 
     /// Processes discovered peers from a successful request in an iterative `Query`.
@@ -529,16 +823,18 @@ where
             self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
                 KademliaEvent::Discovered {
                     peer_id: peer.node_id.clone(),
-                    addresses: peer.multiaddrs.clone(),
+                    addresses: self.filter_addresses(&peer.multiaddrs),
                     ty: peer.connection_ty,
                 }
             ));
         }
 
+        let filter = &self.address_filter;
         if let Some(query) = self.queries.get_mut(query_id) {
             for peer in others_iter.clone() {
-                query.inner.addresses
-                    .insert(peer.node_id.clone(), peer.multiaddrs.iter().cloned().collect());
+                query.inner.addresses.insert(
+                    peer.node_id.clone(),
+                    peer.multiaddrs.iter().filter(|a| filter.allows(a)).cloned().collect());
             }
             query.on_success(source, others_iter.cloned().map(|kp| kp.node_id))
         }
@@ -561,12 +857,15 @@ where
     }
 
     /// Collects all peers who are known to be providers of the value for a given `Multihash`.
+    ///
+    /// Expired provider records are skipped, even if they haven't been removed from the store yet.
     fn provider_peers(&mut self, key: &record::Key, source: &PeerId) -> Vec<KadPeer> {
         let kbuckets = &mut self.kbuckets;
+        let now = Instant::now();
         self.store.providers(key)
             .into_iter()
             .filter_map(move |p|
-                if &p.provider != source {
+                if &p.provider != source && !p.is_expired(now) {
                     let key = kbucket::Key::new(p.provider.clone());
                     kbuckets.entry(&key).view().map(|e| KadPeer::from(e.to_owned()))
                 } else {
@@ -576,6 +875,58 @@ where
             .collect()
     }
 
+    /// Protects the given peer in the routing table from eviction, or
+    /// removes the protection, returning whether the peer is in the
+    /// routing table.
+    fn set_protected(&mut self, peer: &PeerId, protected: bool) -> bool {
+        let key = kbucket::Key::new(peer.clone());
+        if let kbucket::Entry::Present(mut entry, _) = self.kbuckets.entry(&key) {
+            entry.set_protected(protected);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Stores a record found by a lookup at up to `max_peers` of the given
+    /// cache candidates, i.e. the peers that did not return the record.
+    ///
+    /// The TTL of the record is halved for every one of the `closest_peers`
+    /// of the lookup that is closer to the key than the candidate.
+    fn cache_record(
+        &mut self,
+        record: &Record,
+        candidates: &BTreeMap<kbucket::Distance, PeerId>,
+        closest_peers: &[PeerId],
+        max_peers: u16
+    ) {
+        let now = Instant::now();
+        let target = kbucket::Key::new(record.key.clone());
+        let ttl = match record.expires {
+            Some(expires) if expires > now => Some(expires - now),
+            Some(_) => return,
+            None => self.record_ttl
+        };
+        let distances = closest_peers.iter()
+            .map(|p| kbucket::Key::new(p.clone()).distance(&target))
+            .collect::<Vec<_>>();
+        for (distance, peer) in candidates.iter().take(usize::from(max_peers)) {
+            let num_between = distances.iter().filter(|d| *d < distance).count() as u32;
+            let mut record = record.clone();
+            record.expires = ttl.map(|ttl| now + exp_decrease(ttl, num_between));
+            let quorum = NonZeroUsize::new(1).expect("1 > 0");
+            let context = PutRecordContext::Cache;
+            let info = QueryInfo::PutRecord { record, quorum, context, num_results: 0 };
+            let inner = QueryInner::new(info);
+            self.queries.add_fixed(iter::once(kbucket::Key::new(peer.clone())), inner);
+        }
+    }
+
+    /// Checks whether a bootstrap is currently in progress.
+    fn is_bootstrapping(&self) -> bool {
+        self.queries.iter().any(|q| matches!(q.inner.info, QueryInfo::Bootstrap { .. }))
+    }
+
     /// Starts an iterative `ADD_PROVIDER` query for the given key.
     fn start_add_provider(&mut self, key: record::Key, context: AddProviderContext) {
         let info = QueryInfo::PrepareAddProvider { key: key.clone(), context };
@@ -595,8 +946,14 @@ where
         self.queries.add_iter_closest(target.clone(), peers, inner);
     }
 
+    /// Returns the addresses allowed by the address filter.
+    fn filter_addresses(&self, addresses: &[Multiaddr]) -> Vec<Multiaddr> {
+        addresses.iter().filter(|a| self.address_filter.allows(a)).cloned().collect()
+    }
+
     /// Updates the connection status of a peer in the Kademlia routing table.
     fn connection_updated(&mut self, peer: PeerId, address: Option<Multiaddr>, new_status: NodeStatus) {
+        let address = address.filter(|a| self.address_filter.allows(a));
         let key = kbucket::Key::new(peer.clone());
         match self.kbuckets.entry(&key) {
             kbucket::Entry::Present(mut entry, old_status) => {
@@ -665,6 +1022,7 @@ where
     fn query_finished(&mut self, q: Query<QueryInner>, params: &mut impl PollParameters)
         -> Option<KademliaEvent>
     {
+        let query_id = q.id();
         let result = q.into_result();
         match result.inner.info {
             QueryInfo::Bootstrap { peer } => {
@@ -673,11 +1031,17 @@ where
                     // The lookup for the local key finished. To complete the bootstrap process,
                     // a bucket refresh should be performed for every bucket farther away than
                     // the first non-empty bucket (which are most likely no more than the last
-                    // few, i.e. farthest, buckets).
+                    // few, i.e. farthest, buckets) and has not been refreshed recently.
+                    let refresh_interval = self.bucket_refresh_interval;
                     let targets = self.kbuckets.buckets()
                         .skip_while(|b| b.num_entries() == 0)
                         .skip(1) // Skip the bucket with the closest neighbour.
-                        .map(|b| {
+                        .filter(|b| match refresh_interval {
+                            Some(interval) => b.is_stale(interval),
+                            None => true
+                        })
+                        .map(|mut b| {
+                            b.mark_refreshed();
                             // Try to find a key that falls into the bucket. While such keys can
                             // be generated fully deterministically, the current libp2p kademlia
                             // wire protocol requires transmission of the preimages of the actual
@@ -731,14 +1095,16 @@ where
             QueryInfo::PrepareAddProvider { key, context } => {
                 let closest_peers = result.peers.map(kbucket::Key::from);
                 let provider_id = params.local_peer_id().clone();
-                let external_addresses = params.external_addresses().collect();
+                let external_addresses = params.external_addresses()
+                    .filter(|a| self.address_filter.allows(a))
+                    .collect();
                 let inner = QueryInner::new(QueryInfo::AddProvider {
                     key,
                     provider_id,
                     external_addresses,
                     context,
                 });
-                self.queries.add_fixed(closest_peers, inner);
+                self.queries.continue_fixed(query_id, closest_peers, inner, result.stats);
                 None
             }
 
@@ -757,26 +1123,30 @@ where
                 }
             }
 
-            QueryInfo::GetRecord { key, records, quorum, cache_at } => {
+            QueryInfo::GetRecord { key, mut records, quorum, cache_candidates } => {
+                self.select_record(&key, &mut records);
+                let closest_peers = result.peers.collect::<Vec<_>>();
                 let result = if records.len() >= quorum.get() { // [not empty]
-                    if let Some(cache_key) = cache_at {
-                        // Cache the record at the closest node to the key that
-                        // did not return the record.
-                        let record = records.first().expect("[not empty]").clone();
-                        let quorum = NonZeroUsize::new(1).expect("1 > 0");
-                        let context = PutRecordContext::Cache;
-                        let info = QueryInfo::PutRecord { record, quorum, context, num_results: 0 };
-                        let inner = QueryInner::new(info);
-                        self.queries.add_fixed(iter::once(cache_key), inner);
+                    if let KademliaCaching::Enabled { max_peers } = self.caching {
+                        // With a quorum of more than one, the found records may
+                        // diverge, in which case none of them is cached.
+                        let record = records.first().expect("[not empty]");
+                        if records.iter().all(|r| r.value == record.value) {
+                            self.cache_record(record, &cache_candidates, &closest_peers, max_peers);
+                        }
                     }
-                    Ok(GetRecordOk { records })
+                    let cache_candidates = cache_candidates.into_iter().map(|(_, peer)| peer).collect();
+                    Ok(GetRecordOk { id: query_id, records, cache_candidates })
                 } else if records.is_empty() {
-                    Err(GetRecordError::NotFound {
+                    Err(GetRecordError::NotFound { id: query_id, key, closest_peers })
+                } else {
+                    Err(GetRecordError::QuorumFailed {
+                        id: query_id,
                         key,
-                        closest_peers: result.peers.collect()
+                        records,
+                        quorum,
+                        cache_candidates: cache_candidates.into_iter().map(|(_, peer)| peer).collect()
                     })
-                } else {
-                    Err(GetRecordError::QuorumFailed { key, records, quorum })
                 };
                 Some(KademliaEvent::GetRecordResult(result))
             }
@@ -785,7 +1155,7 @@ where
                 let closest_peers = result.peers.map(kbucket::Key::from);
                 let info = QueryInfo::PutRecord { record, quorum, context, num_results: 0 };
                 let inner = QueryInner::new(info);
-                self.queries.add_fixed(closest_peers, inner);
+                self.queries.continue_fixed(query_id, closest_peers, inner, result.stats);
                 None
             }
 
@@ -815,8 +1185,33 @@ where
         }
     }
 
+    /// Reports a finished or timed out query to the metrics receiver, if any.
+    fn report_query(&mut self, q: &Query<QueryInner>, timeout: bool) {
+        if let Some(metrics) = self.metrics.as_mut() {
+            if let Some((kind, outcome)) = q.inner.info.outcome(timeout) {
+                metrics.query_finished(q.id(), kind, outcome, q.stats());
+            }
+        }
+    }
+
+    /// Moves the record selected by the record validator, if any, to the
+    /// front of the given records for the same key.
+    fn select_record(&mut self, key: &record::Key, records: &mut [Record]) {
+        if let Some(validator) = self.record_validator.as_mut() {
+            if records.len() > 1 {
+                let best = validator.select(key, records);
+                if best < records.len() {
+                    records.swap(0, best)
+                } else {
+                    warn!("Record validator selected index {} out of {} records", best, records.len())
+                }
+            }
+        }
+    }
+
     /// Handles a query that timed out.
-    fn query_timeout(&self, query: Query<QueryInner>) -> Option<KademliaEvent> {
+    fn query_timeout(&mut self, query: Query<QueryInner>) -> Option<KademliaEvent> {
+        let query_id = query.id();
         let result = query.into_result();
         match result.inner.info {
             QueryInfo::Bootstrap { peer } =>
@@ -895,9 +1290,18 @@ where
                 }
             }
 
-            QueryInfo::GetRecord { key, records, quorum, .. } =>
+            QueryInfo::GetRecord { key, mut records, quorum, cache_candidates } => {
+                self.select_record(&key, &mut records);
+                let cache_candidates = cache_candidates.into_iter().map(|(_, peer)| peer).collect();
                 Some(KademliaEvent::GetRecordResult(Err(
-                    GetRecordError::Timeout { key, records, quorum }))),
+                    GetRecordError::Timeout {
+                        id: query_id,
+                        key,
+                        records,
+                        quorum,
+                        cache_candidates
+                    })))
+            }
 
             QueryInfo::GetProviders { key, providers } =>
                 Some(KademliaEvent::GetProvidersResult(Err(
@@ -928,6 +1332,33 @@ where
 
         let now = Instant::now();
 
+        if let Some(validator) = self.record_validator.as_mut() {
+            let rejected = match validator.validate(&record) {
+                Err(e) => {
+                    info!("Record not stored: {:?}: {}", record.key, e);
+                    true
+                }
+                Ok(()) => match self.store.get(&record.key) {
+                    Some(existing) if !existing.is_expired(now) => {
+                        let candidates = [record.clone(), existing.into_owned()];
+                        let keep_existing = validator.select(&record.key, &candidates) == 1;
+                        if keep_existing {
+                            debug!("Record not stored: {:?}; the existing record is preferred", record.key);
+                        }
+                        keep_existing
+                    }
+                    _ => false
+                }
+            };
+            if rejected {
+                self.queued_events.push_back(NetworkBehaviourAction::SendEvent {
+                    peer_id: source,
+                    event: KademliaHandlerIn::Reset(request_id)
+                });
+                return
+            }
+        }
+
         // Calculate the expiration exponentially inversely proportional to the
         // number of nodes between the local node and the closest node to the key
         // (beyond the replication factor). This ensures avoiding over-caching
@@ -936,9 +1367,7 @@ where
         let num_between = self.kbuckets.count_nodes_between(&target);
         let k = self.queries.config().replication_factor.get();
         let num_beyond_k = (usize::max(k, num_between) - k) as u32;
-        let expiration = self.record_ttl.map(|ttl|
-            now + Duration::from_secs(ttl.as_secs() >> num_beyond_k)
-        );
+        let expiration = self.record_ttl.map(|ttl| now + exp_decrease(ttl, num_beyond_k));
         // The smaller TTL prevails. Only if neither TTL is set is the record
         // stored "forever".
         record.expires = record.expires.or(expiration).min(expiration);
@@ -959,7 +1388,8 @@ where
         // either be overridden or left unchanged. At the moment and in the
         // absence of a decisive argument for another option, both are always
         // overridden as it avoids having to load the existing record in the
-        // first place.
+        // first place, unless a record validator has been configured, in which
+        // case the validator has already decided between the two records.
 
         // The record is cloned because of the weird libp2p protocol requirement
         // to send back the value in the response, although this is a waste of
@@ -991,7 +1421,7 @@ where
         self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
             KademliaEvent::Discovered {
                 peer_id: provider.node_id.clone(),
-                addresses: provider.multiaddrs.clone(),
+                addresses: self.filter_addresses(&provider.multiaddrs),
                 ty: provider.connection_ty,
             }));
 
@@ -1067,7 +1497,15 @@ where
             ConnectedPoint::Listener { .. } => None,
         };
 
-        self.connection_updated(peer.clone(), address, NodeStatus::Connected);
+        // A peer is only newly inserted into the routing table once it is known
+        // to support the configured protocol, since the remote may well be
+        // connected for the sake of other protocols, e.g. another DHT.
+        let key = kbucket::Key::new(peer.clone());
+        if let kbucket::Entry::Absent(_) = self.kbuckets.entry(&key) {
+            self.unconfirmed_peers.insert(peer.clone(), address);
+        } else {
+            self.connection_updated(peer.clone(), address, NodeStatus::Connected);
+        }
         self.connected_peers.insert(peer);
     }
 
@@ -1124,6 +1562,7 @@ where
         }
         self.connection_updated(id.clone(), None, NodeStatus::Disconnected);
         self.connected_peers.remove(id);
+        self.unconfirmed_peers.remove(id);
     }
 
     fn inject_replaced(&mut self, peer_id: PeerId, _old: ConnectedPoint, new_endpoint: ConnectedPoint) {
@@ -1137,15 +1576,34 @@ where
             }
         }
 
+        if let Some(address) = self.unconfirmed_peers.get_mut(&peer_id) {
+            *address = match new_endpoint {
+                ConnectedPoint::Dialer { ref address } => Some(address.clone()),
+                ConnectedPoint::Listener { .. } => None,
+            };
+        }
+
         if let Some(addrs) = self.kbuckets.entry(&kbucket::Key::new(peer_id)).value() {
             if let ConnectedPoint::Dialer { address } = new_endpoint {
-                addrs.insert(address);
+                if self.address_filter.allows(&address) {
+                    addrs.insert(address);
+                }
             }
         }
     }
 
+    fn inject_address_filter(&mut self, filter: &AddressFilter) {
+        self.address_filter = filter.clone();
+    }
+
     fn inject_node_event(&mut self, source: PeerId, event: KademliaHandlerEvent<QueryId>) {
         match event {
+            KademliaHandlerEvent::ProtocolConfirmed => {
+                if let Some(address) = self.unconfirmed_peers.remove(&source) {
+                    self.connection_updated(source, address, NodeStatus::Connected);
+                }
+            }
+
             KademliaHandlerEvent::FindNodeReq { key, request_id } => {
                 let closer_peers = self.find_closest(&kbucket::Key::new(key), &source);
                 self.queued_events.push_back(NetworkBehaviourAction::SendEvent {
@@ -1251,27 +1709,42 @@ where
             } => {
                 if let Some(query) = self.queries.get_mut(&user_data) {
                     if let QueryInfo::GetRecord {
-                        key, records, quorum, cache_at
+                        key, records, quorum, cache_candidates
                     } = &mut query.inner.info {
                         if let Some(record) = record {
-                            records.push(record);
-                            if records.len() == quorum.get() {
-                                query.finish()
-                            }
-                        } else if quorum.get() == 1 {
-                            // It is a "standard" Kademlia query, for which the
-                            // closest node to the key that did *not* return the
-                            // value is tracked in order to cache the record on
-                            // that node if the query turns out to be successful.
-                            let source_key = kbucket::Key::from(source.clone());
-                            if let Some(cache_key) = cache_at {
-                                let key = kbucket::Key::new(key.clone());
-                                if source_key.distance(&key) < cache_key.distance(&key) {
-                                    *cache_at = Some(source_key)
+                            let valid = match self.record_validator.as_mut() {
+                                Some(validator) => match validator.validate(&record) {
+                                    Ok(()) => true,
+                                    Err(e) => {
+                                        debug!("Invalid record from {:?}: {:?}: {}", source, record.key, e);
+                                        false
+                                    }
+                                },
+                                None => true
+                            };
+                            if valid {
+                                records.push(record.clone());
+                                self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
+                                    KademliaEvent::GetRecordProgress(GetRecordProgress {
+                                        id: user_data,
+                                        peer: Some(source.clone()),
+                                        record,
+                                        num_records: records.len(),
+                                        quorum: *quorum,
+                                    })
+                                ));
+                                if records.len() == quorum.get() {
+                                    query.finish()
                                 }
-                            } else {
-                                *cache_at = Some(source_key)
                             }
+                        } else {
+                            // The peers that did *not* return a record are tracked
+                            // by their distance to the key, as candidates for
+                            // caching the record if the query turns out to be
+                            // successful.
+                            let source_key = kbucket::Key::from(source.clone());
+                            let distance = source_key.distance(&kbucket::Key::new(key.clone()));
+                            cache_candidates.insert(distance, source.clone());
                         }
                     }
                 }
@@ -1347,6 +1820,50 @@ where
             self.put_record_job = Some(job);
         }
 
+        // Run the periodic removal of expired provider records.
+        if let Some(job) = self.expire_providers_job.as_mut() {
+            while let Poll::Ready(r) = job.poll(cx, &mut self.store, now) {
+                debug!("Provider record of {} for {:?} expired", r.provider, r.key);
+            }
+        }
+
+        // Bootstrap periodically, as well as whenever the routing table
+        // changes while it has fewer entries than the configured threshold.
+        let mut bootstrap = match self.bootstrap_job.as_mut() {
+            Some(job) => job.poll(cx, now).is_ready(),
+            None => false
+        };
+        if let Some(threshold) = self.bootstrap_threshold {
+            let num_entries = self.kbuckets.buckets().map(|b| b.num_entries()).sum();
+            if num_entries != self.num_routing_entries && num_entries < threshold {
+                bootstrap = true
+            }
+            self.num_routing_entries = num_entries;
+        }
+        if bootstrap && !self.is_bootstrapping() && self.kbuckets.iter().next().is_some() {
+            debug!("Bootstrapping automatically");
+            self.bootstrap();
+        }
+
+        // Report changes to the sizes of the buckets.
+        if let Some(metrics) = self.metrics.as_mut() {
+            let sizes = self.kbuckets.buckets().map(|b| b.num_entries());
+            if self.bucket_sizes.is_empty() {
+                // Report all buckets, including the empty ones, initially.
+                for (i, n) in sizes.enumerate() {
+                    metrics.bucket_size_changed(i, n);
+                    self.bucket_sizes.push(n);
+                }
+            } else {
+                for (i, (old, n)) in self.bucket_sizes.iter_mut().zip(sizes).enumerate() {
+                    if *old != n {
+                        metrics.bucket_size_changed(i, n);
+                        *old = n;
+                    }
+                }
+            }
+        }
+
         loop {
             // Drain queued events first.
             if let Some(event) = self.queued_events.pop_front() {
@@ -1355,6 +1872,9 @@ where
 
             // Drain applied pending entries from the routing table.
             if let Some(entry) = self.kbuckets.take_applied_pending() {
+                if let (Some(metrics), Some(evicted)) = (self.metrics.as_mut(), &entry.evicted) {
+                    metrics.peer_evicted(evicted.key.preimage());
+                }
                 let kbucket::Node { key, value } = entry.inserted;
                 let event = KademliaEvent::RoutingUpdated {
                     peer: key.into_preimage(),
@@ -1368,11 +1888,13 @@ where
             loop {
                 match self.queries.poll(now) {
                     QueryPoolState::Finished(q) => {
+                        self.report_query(&q, false);
                         if let Some(event) = self.query_finished(q, parameters) {
                             return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event))
                         }
                     }
                     QueryPoolState::Timeout(q) => {
+                        self.report_query(&q, true);
                         if let Some(event) = self.query_timeout(q) {
                             return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event))
                         }
@@ -1412,6 +1934,11 @@ where
     }
 }
 
+/// Exponentially decreases the given duration (base 2).
+fn exp_decrease(ttl: Duration, exp: u32) -> Duration {
+    Duration::from_secs(ttl.as_secs().checked_shr(exp).unwrap_or(0))
+}
+
 /// A quorum w.r.t. the configured replication factor specifies the minimum
 /// number of distinct nodes that must be successfully contacted in order
 /// for a query to succeed.
@@ -1458,7 +1985,10 @@ pub enum KademliaEvent {
     /// The result of a (automatic) republishing of a provider record.
     RepublishProviderResult(AddProviderResult),
 
-    /// The result of [`Kademlia::get_record`].
+    /// A record found in the course of [`Kademlia::get_record`].
+    GetRecordProgress(GetRecordProgress),
+
+    /// The final result of [`Kademlia::get_record`].
     GetRecordResult(GetRecordResult),
 
     /// The result of [`Kademlia::put_record`].
@@ -1501,32 +2031,71 @@ pub enum KademliaEvent {
 /// The result of [`Kademlia::get_record`].
 pub type GetRecordResult = Result<GetRecordOk, GetRecordError>;
 
+/// A record found in the course of [`Kademlia::get_record`].
+#[derive(Debug, Clone)]
+pub struct GetRecordProgress {
+    /// The ID of the query, as returned by [`Kademlia::get_record`].
+    pub id: QueryId,
+    /// The peer that returned the record, or `None` if the record
+    /// was found in local storage.
+    pub peer: Option<PeerId>,
+    /// The record.
+    pub record: Record,
+    /// The number of records found so far, including this one.
+    pub num_records: usize,
+    /// The number of records the query is looking for.
+    pub quorum: NonZeroUsize,
+}
+
 /// The successful result of [`Kademlia::get_record`].
+///
+/// If a [`RecordValidator`] is configured, the record it selects
+/// as the best among the returned records comes first.
 #[derive(Debug, Clone)]
 pub struct GetRecordOk {
-    pub records: Vec<Record>
+    /// The ID of the query, as returned by [`Kademlia::get_record`].
+    pub id: QueryId,
+    /// The records found.
+    pub records: Vec<Record>,
+    /// The peers contacted in the course of the query that did not
+    /// return a record, ordered by increasing distance to the key.
+    pub cache_candidates: Vec<PeerId>,
 }
 
 /// The error result of [`Kademlia::get_record`].
 #[derive(Debug, Clone)]
 pub enum GetRecordError {
     NotFound {
+        id: QueryId,
         key: record::Key,
         closest_peers: Vec<PeerId>
     },
     QuorumFailed {
+        id: QueryId,
         key: record::Key,
         records: Vec<Record>,
-        quorum: NonZeroUsize
+        quorum: NonZeroUsize,
+        cache_candidates: Vec<PeerId>
     },
     Timeout {
+        id: QueryId,
         key: record::Key,
         records: Vec<Record>,
-        quorum: NonZeroUsize
+        quorum: NonZeroUsize,
+        cache_candidates: Vec<PeerId>
     }
 }
 
 impl GetRecordError {
+    /// Gets the ID of the query that failed.
+    pub fn id(&self) -> QueryId {
+        match self {
+            GetRecordError::QuorumFailed { id, .. } => *id,
+            GetRecordError::Timeout { id, .. } => *id,
+            GetRecordError::NotFound { id, .. } => *id,
+        }
+    }
+
     /// Gets the key of the record for which the operation failed.
     pub fn key(&self) -> &record::Key {
         match self {
@@ -1568,10 +2137,6 @@ pub enum PutRecordError {
         key: record::Key,
         num_results: usize,
         quorum: NonZeroUsize
-    },
-    LocalStorageError {
-        key: record::Key,
-        cause: store::Error
     }
 }
 
@@ -1581,7 +2146,6 @@ impl PutRecordError {
         match self {
             PutRecordError::QuorumFailed { key, .. } => key,
             PutRecordError::Timeout { key, .. } => key,
-            PutRecordError::LocalStorageError { key, .. } => key
         }
     }
 
@@ -1591,7 +2155,6 @@ impl PutRecordError {
         match self {
             PutRecordError::QuorumFailed { key, .. } => key,
             PutRecordError::Timeout { key, .. } => key,
-            PutRecordError::LocalStorageError { key, .. } => key,
         }
     }
 }
@@ -1700,11 +2263,6 @@ pub enum AddProviderError {
     /// The query timed out.
     Timeout {
         key: record::Key,
-    },
-    /// The provider record could not be stored.
-    LocalStorageError {
-        key: record::Key,
-        cause: store::Error
     }
 }
 
@@ -1713,7 +2271,6 @@ impl AddProviderError {
     pub fn key(&self) -> &record::Key {
         match self {
             AddProviderError::Timeout { key, .. } => key,
-            AddProviderError::LocalStorageError { key, .. } => key,
         }
     }
 
@@ -1722,7 +2279,6 @@ impl AddProviderError {
     pub fn into_key(self) -> record::Key {
         match self {
             AddProviderError::Timeout { key, .. } => key,
-            AddProviderError::LocalStorageError { key, .. } => key,
         }
     }
 }
@@ -1838,15 +2394,60 @@ enum QueryInfo {
         records: Vec<Record>,
         /// The number of records to look for.
         quorum: NonZeroUsize,
-        /// The closest peer to `key` that did not return a record.
+        /// The peers that did not return a record, by distance to `key`.
         ///
-        /// When a record is found in a standard Kademlia query (quorum == 1),
-        /// it is cached at this peer.
-        cache_at: Option<kbucket::Key<PeerId>>,
+        /// When a record is found, it is cached at the closest of these
+        /// peers, as per the configured [`KademliaCaching`].
+        cache_candidates: BTreeMap<kbucket::Distance, PeerId>,
     },
 }
 
 impl QueryInfo {
+    /// Determines the kind and outcome of a finished or timed out query,
+    /// as reported to a [`KademliaMetrics`].
+    ///
+    /// Returns `None` for the first phase of a successful query that is
+    /// continued with a second phase under the same ID.
+    fn outcome(&self, timeout: bool) -> Option<(QueryKind, QueryOutcome)> {
+        let (kind, outcome) = match self {
+            QueryInfo::Bootstrap { .. } =>
+                (QueryKind::Bootstrap, QueryOutcome::Success),
+            QueryInfo::GetClosestPeers { .. } =>
+                (QueryKind::GetClosestPeers, QueryOutcome::Success),
+            QueryInfo::GetProviders { providers, .. } =>
+                if providers.is_empty() {
+                    (QueryKind::GetProviders, QueryOutcome::NotFound)
+                } else {
+                    (QueryKind::GetProviders, QueryOutcome::Success)
+                },
+            QueryInfo::PrepareAddProvider { .. } if !timeout => return None,
+            QueryInfo::PrepareAddProvider { .. } | QueryInfo::AddProvider { .. } =>
+                (QueryKind::AddProvider, QueryOutcome::Success),
+            QueryInfo::PreparePutRecord { .. } if !timeout => return None,
+            QueryInfo::PreparePutRecord { .. } =>
+                (QueryKind::PutRecord, QueryOutcome::Success),
+            QueryInfo::PutRecord { quorum, num_results, .. } =>
+                if *num_results >= quorum.get() {
+                    (QueryKind::PutRecord, QueryOutcome::Success)
+                } else {
+                    (QueryKind::PutRecord, QueryOutcome::QuorumFailed)
+                },
+            QueryInfo::GetRecord { records, quorum, .. } =>
+                if records.len() >= quorum.get() {
+                    (QueryKind::GetRecord, QueryOutcome::Success)
+                } else if records.is_empty() {
+                    (QueryKind::GetRecord, QueryOutcome::NotFound)
+                } else {
+                    (QueryKind::GetRecord, QueryOutcome::QuorumFailed)
+                },
+        };
+        if timeout {
+            Some((kind, QueryOutcome::Timeout))
+        } else {
+            Some((kind, outcome))
+        }
+    }
+
     /// Creates an event for a handler to issue an outgoing request in the
     /// context of a query.
     fn to_request(&self, query_id: QueryId) -> KademliaHandlerIn<QueryId> {
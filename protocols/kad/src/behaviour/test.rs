@@ -24,7 +24,8 @@ use super::*;
 
 use crate::K_VALUE;
 use crate::kbucket::Distance;
-use crate::record::store::MemoryStore;
+use crate::query::QueryStats;
+use crate::record::{store::MemoryStore, validator::InvalidRecord};
 use futures::{
     prelude::*,
     executor::block_on,
@@ -45,7 +46,8 @@ use libp2p_swarm::Swarm;
 use libp2p_yamux as yamux;
 use quickcheck::*;
 use rand::{Rng, random, thread_rng};
-use std::{collections::{HashSet, HashMap}, io, num::NonZeroUsize, u64};
+use std::{collections::{HashSet, HashMap}, io, num::NonZeroUsize, sync::{Arc, Mutex}, u64};
+use wasm_timer::Delay;
 use multihash::{Multihash, Hash::SHA2256};
 
 type TestSwarm = Swarm<
@@ -155,6 +157,47 @@ fn bootstrap() {
     }
 }
 
+/// Connects a node to another and waits for it to bootstrap automatically.
+fn run_automatic_bootstrap(cfg: KademliaConfig) {
+    let (port_base, mut swarms) = build_nodes_with_config(2, cfg);
+    let swarm_ids: Vec<_> = swarms.iter().map(Swarm::local_peer_id).cloned().collect();
+    swarms[0].add_address(&swarm_ids[1], Protocol::Memory(port_base + 1).into());
+
+    block_on(
+        poll_fn(move |ctx| {
+            for (i, swarm) in swarms.iter_mut().enumerate() {
+                loop {
+                    match swarm.poll_next_unpin(ctx) {
+                        Poll::Ready(Some(KademliaEvent::BootstrapResult(Ok(ok)))) if i == 0 => {
+                            assert_eq!(ok.peer, swarm_ids[0]);
+                            return Poll::Ready(())
+                        }
+                        // Ignore any other event.
+                        Poll::Ready(Some(_)) => (),
+                        e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                        Poll::Pending => break,
+                    }
+                }
+            }
+            Poll::Pending
+        })
+    )
+}
+
+#[test]
+fn bootstrap_periodically() {
+    let mut cfg = KademliaConfig::default();
+    cfg.set_bootstrap_interval(Some(Duration::from_millis(100)));
+    run_automatic_bootstrap(cfg)
+}
+
+#[test]
+fn bootstrap_below_threshold() {
+    let mut cfg = KademliaConfig::default();
+    cfg.set_bootstrap_threshold(Some(K_VALUE.get()));
+    run_automatic_bootstrap(cfg)
+}
+
 #[test]
 fn query_iter() {
     fn distances<K>(key: &kbucket::Key<K>, peers: Vec<PeerId>) -> Vec<Distance> {
@@ -213,6 +256,117 @@ fn query_iter() {
     }
 }
 
+#[test]
+fn query_parallelism() {
+    let mut cfg = KademliaConfig::default();
+    cfg.set_query_parallelism(QueryParallelism {
+        alpha: NonZeroUsize::new(1).unwrap(),
+        beta: NonZeroUsize::new(2),
+        adaptive: false,
+    });
+    let (swarm_ids, mut swarms) = build_connected_nodes_with_config(10, 1, cfg);
+
+    let search_target = PeerId::random();
+    let query_id = swarms[0].get_closest_peers(search_target.clone());
+    assert!(swarms[0].set_query_parallelism(&query_id, QueryParallelism {
+        adaptive: true,
+        .. QueryParallelism::default()
+    }));
+
+    let expected_peer_ids: Vec<_> = swarm_ids.iter().skip(1).cloned().collect();
+
+    block_on(
+        poll_fn(move |ctx| {
+            for swarm in &mut swarms {
+                loop {
+                    match swarm.poll_next_unpin(ctx) {
+                        Poll::Ready(Some(KademliaEvent::GetClosestPeersResult(Ok(ok)))) => {
+                            assert_eq!(&ok.key[..], search_target.as_bytes());
+                            assert!(expected_peer_ids.iter().all(|p| ok.peers.contains(p)));
+                            let parallelism = QueryParallelism::default();
+                            assert!(!swarm.set_query_parallelism(&query_id, parallelism));
+                            return Poll::Ready(());
+                        }
+                        // Ignore any other event.
+                        Poll::Ready(Some(_)) => (),
+                        e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                        Poll::Pending => break,
+                    }
+                }
+            }
+            Poll::Pending
+        })
+    )
+}
+
+/// The observations of a [`RecordingMetrics`].
+#[derive(Default)]
+struct Observations {
+    queries: Vec<(QueryId, QueryKind, QueryOutcome, QueryStats)>,
+    bucket_sizes: HashMap<usize, usize>,
+}
+
+/// A [`KademliaMetrics`] that records all observations.
+#[derive(Clone, Default)]
+struct RecordingMetrics(Arc<Mutex<Observations>>);
+
+impl KademliaMetrics for RecordingMetrics {
+    fn query_finished(&mut self, id: QueryId, kind: QueryKind, outcome: QueryOutcome, stats: &QueryStats) {
+        self.0.lock().unwrap().queries.push((id, kind, outcome, stats.clone()))
+    }
+
+    fn bucket_size_changed(&mut self, bucket: usize, num_entries: usize) {
+        self.0.lock().unwrap().bucket_sizes.insert(bucket, num_entries);
+    }
+}
+
+#[test]
+fn query_metrics() {
+    // Connect the nodes in a chain, such that the first node only knows the
+    // second one and the other peers have to be discovered over multiple hops.
+    let (swarm_ids, mut swarms) = build_connected_nodes(10, 1);
+    let metrics = RecordingMetrics::default();
+    swarms[0].set_metrics(metrics.clone());
+
+    let search_target = PeerId::random();
+    let query_id = swarms[0].get_closest_peers(search_target.clone());
+
+    block_on(
+        poll_fn(move |ctx| {
+            for swarm in &mut swarms {
+                loop {
+                    match swarm.poll_next_unpin(ctx) {
+                        Poll::Ready(Some(KademliaEvent::GetClosestPeersResult(Ok(ok)))) => {
+                            assert_eq!(&ok.key[..], search_target.as_bytes());
+                            return Poll::Ready(());
+                        }
+                        // Ignore any other event.
+                        Poll::Ready(Some(_)) => (),
+                        e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                        Poll::Pending => break,
+                    }
+                }
+            }
+            Poll::Pending
+        })
+    );
+
+    let observations = metrics.0.lock().unwrap();
+    let (_, kind, outcome, stats) = observations.queries.iter()
+        .find(|(id, ..)| *id == query_id)
+        .expect("The query to be reported.");
+    assert_eq!(*kind, QueryKind::GetClosestPeers);
+    assert_eq!(*outcome, QueryOutcome::Success);
+    assert!(stats.num_requests() >= (swarm_ids.len() - 1) as u32);
+    assert_eq!(stats.num_successes() + stats.num_failures() + stats.num_pending(), stats.num_requests());
+    assert!(stats.hops() > 1);
+    assert!(stats.duration().is_some());
+
+    let num_entries: usize = observations.bucket_sizes.values().sum();
+    assert_eq!(observations.bucket_sizes.len(), 256);
+    assert!(num_entries >= 1);
+}
+
 #[test]
 fn unresponsive_not_returned_direct() {
     // Build one node. It contains fake addresses to non-existing nodes. We ask it to find a
@@ -307,7 +461,7 @@ fn get_record_not_found() {
     swarms[1].add_address(&swarm_ids[2], Protocol::Memory(port_base + 2).into());
 
     let target_key = record::Key::from(Multihash::random(SHA2256));
-    swarms[0].get_record(&target_key, Quorum::One);
+    let query_id = swarms[0].get_record(&target_key, Quorum::One);
 
     block_on(
         poll_fn(move |ctx| {
@@ -315,7 +469,8 @@ fn get_record_not_found() {
                 loop {
                     match swarm.poll_next_unpin(ctx) {
                         Poll::Ready(Some(KademliaEvent::GetRecordResult(Err(e)))) => {
-                            if let GetRecordError::NotFound { key, closest_peers, } = e {
+                            if let GetRecordError::NotFound { id, key, closest_peers, } = e {
+                                assert_eq!(id, query_id);
                                 assert_eq!(key, target_key);
                                 assert_eq!(closest_peers.len(), 2);
                                 assert!(closest_peers.contains(&swarm_ids[1]));
@@ -361,7 +516,7 @@ fn put_record() {
             .collect::<HashMap<_,_>>();
 
         for r in records.values() {
-            swarms[0].put_record(r.clone(), Quorum::All);
+            swarms[0].put_record(r.clone(), Quorum::All).unwrap();
         }
 
         // Each test run republishes all records once.
@@ -552,7 +707,7 @@ fn add_provider() {
 
         // Initiate the first round of publishing.
         for k in &keys {
-            swarms[0].start_providing(k.clone());
+            swarms[0].start_providing(k.clone()).unwrap();
         }
 
         block_on(
@@ -682,3 +837,411 @@ fn exceed_jobs_max_queries() {
         })
     )
 }
+
+#[test]
+fn filtered_addresses_not_added() {
+    let (_, mut swarms) = build_nodes(1);
+    let filter = AddressFilter::new().deny_private();
+    Swarm::set_address_filter(&mut swarms[0], filter);
+
+    let peer = PeerId::random();
+    swarms[0].add_address(&peer, "/ip4/192.168.0.1/tcp/4001".parse().unwrap());
+    assert!(swarms[0].kbuckets.entry(&kbucket::Key::new(peer.clone())).value().is_none());
+
+    swarms[0].add_address(&peer, "/ip4/1.2.3.4/tcp/4001".parse().unwrap());
+    let addrs = swarms[0].kbuckets.entry(&kbucket::Key::new(peer)).value().unwrap().clone();
+    assert_eq!(addrs.into_vec(), vec!["/ip4/1.2.3.4/tcp/4001".parse::<Multiaddr>().unwrap()]);
+}
+
+#[test]
+fn expired_provider_records_removed() {
+    let (_, mut swarms) = build_nodes(1);
+    let key = record::Key::from(Multihash::random(SHA2256));
+    let now = Instant::now();
+    let expired = ProviderRecord {
+        key: key.clone(),
+        provider: PeerId::random(),
+        expires: Some(now - Duration::from_secs(1)),
+    };
+    let live = ProviderRecord {
+        key: key.clone(),
+        provider: PeerId::random(),
+        expires: Some(now + Duration::from_secs(60)),
+    };
+    swarms[0].store_mut().add_provider(expired).unwrap();
+    swarms[0].store_mut().add_provider(live.clone()).unwrap();
+    swarms[0].expire_providers_job.as_mut().unwrap().asap();
+
+    block_on(poll_fn(|ctx| {
+        assert!(swarms[0].poll_next_unpin(ctx).is_pending());
+        Poll::Ready(())
+    }));
+    assert_eq!(swarms[0].store_mut().providers(&key), vec![live]);
+}
+
+/// Rejects records with the value `invalid` and prefers the largest value.
+struct TestValidator;
+
+impl RecordValidator for TestValidator {
+    fn validate(&mut self, record: &Record) -> Result<(), InvalidRecord> {
+        if record.value == b"invalid" {
+            Err(InvalidRecord::new("test"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn select(&mut self, _: &record::Key, records: &[Record]) -> usize {
+        (0 .. records.len()).max_by_key(|&i| &records[i].value).unwrap()
+    }
+}
+
+#[test]
+fn put_record_rejected_by_validator() {
+    fn put_rejected(swarms: &mut [TestSwarm], record: Record) {
+        swarms[0].put_record(record, Quorum::One).unwrap();
+        // A rejected request is only noticed by the remote when the
+        // query times out, hence the timer to keep polling the swarms.
+        let mut timer = Delay::new(Duration::from_millis(100));
+        block_on(poll_fn(|ctx| {
+            while timer.poll_unpin(ctx).is_ready() {
+                timer.reset(Duration::from_millis(100));
+            }
+            for swarm in swarms.iter_mut() {
+                loop {
+                    match swarm.poll_next_unpin(ctx) {
+                        Poll::Ready(Some(KademliaEvent::PutRecordResult(res))) => {
+                            assert!(res.is_err());
+                            return Poll::Ready(());
+                        }
+                        // Ignore any other event.
+                        Poll::Ready(Some(_)) => (),
+                        e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                        Poll::Pending => break,
+                    }
+                }
+            }
+            Poll::Pending
+        }))
+    }
+
+    let mut cfg = KademliaConfig::default();
+    cfg.set_query_timeout(Duration::from_secs(1));
+    let (port_base, mut swarms) = build_nodes_with_config(2, cfg);
+    let swarm_ids: Vec<_> = swarms.iter().map(Swarm::local_peer_id).cloned().collect();
+    swarms[0].add_address(&swarm_ids[1], Protocol::Memory(port_base + 1).into());
+    swarms[1].set_record_validator(TestValidator);
+
+    let key = record::Key::from(Multihash::random(SHA2256));
+    swarms[1].store.put(Record::new(key.clone(), vec![9])).unwrap();
+
+    put_rejected(&mut swarms, Record::new(key.clone(), b"invalid".to_vec()));
+    // A valid record is rejected as well if the existing record is preferred.
+    put_rejected(&mut swarms, Record::new(key.clone(), vec![1]));
+
+    assert_eq!(swarms[1].store.get(&key).unwrap().value, vec![9]);
+}
+
+#[test]
+fn get_record_validated_and_selected() {
+    let (port_base, mut swarms) = build_nodes(4);
+    let swarm_ids: Vec<_> = swarms.iter().map(Swarm::local_peer_id).cloned().collect();
+    for (i, peer) in swarm_ids.iter().enumerate().skip(1) {
+        swarms[0].add_address(peer, Protocol::Memory(port_base + i as u64).into());
+    }
+    swarms[0].set_record_validator(TestValidator);
+
+    let key = record::Key::from(Multihash::random(SHA2256));
+    let values = [b"invalid".to_vec(), vec![1], vec![2]];
+    for (swarm, value) in swarms.iter_mut().skip(1).zip(values.iter()) {
+        swarm.store.put(Record::new(key.clone(), value.clone())).unwrap();
+    }
+
+    let quorum = Quorum::N(NonZeroUsize::new(2).unwrap());
+    swarms[0].get_record(&key, quorum);
+
+    block_on(poll_fn(|ctx| {
+        for swarm in &mut swarms {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(KademliaEvent::GetRecordResult(Ok(ok)))) => {
+                        assert_eq!(ok.records.len(), 2);
+                        assert_eq!(ok.records[0].value, vec![2]);
+                        assert_eq!(ok.records[1].value, vec![1]);
+                        return Poll::Ready(());
+                    }
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+        }
+        Poll::Pending
+    }));
+}
+
+#[test]
+fn get_record_progress() {
+    let (port_base, mut swarms) = build_nodes(5);
+    let swarm_ids: Vec<_> = swarms.iter().map(Swarm::local_peer_id).cloned().collect();
+    for (i, peer) in swarm_ids.iter().enumerate().skip(1) {
+        swarms[0].add_address(peer, Protocol::Memory(port_base + i as u64).into());
+    }
+
+    // The local node and two of the remote nodes have the record.
+    let record = Record::new(Multihash::random(SHA2256), vec![4,5,6]);
+    for swarm in swarms.iter_mut().take(3) {
+        swarm.store.put(record.clone()).unwrap();
+    }
+
+    let quorum = Quorum::N(NonZeroUsize::new(4).unwrap());
+    let query_id = swarms[0].get_record(&record.key, quorum);
+
+    let mut progress = Vec::new();
+    block_on(poll_fn(|ctx| {
+        for swarm in &mut swarms {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(KademliaEvent::GetRecordProgress(p))) => {
+                        assert_eq!(p.id, query_id);
+                        assert_eq!(p.record, record);
+                        assert_eq!(p.num_records, progress.len() + 1);
+                        assert_eq!(p.quorum.get(), 4);
+                        progress.push(p.peer);
+                    }
+                    Poll::Ready(Some(KademliaEvent::GetRecordResult(Err(e)))) => {
+                        if let GetRecordError::QuorumFailed {
+                            id, records, cache_candidates, ..
+                        } = e {
+                            assert_eq!(id, query_id);
+                            assert_eq!(records.len(), 3);
+                            // The remote nodes without the record, closest first.
+                            let key = kbucket::Key::new(record.key.clone());
+                            let mut expected = swarm_ids[3..].to_vec();
+                            expected.sort_by_key(|p| kbucket::Key::new(p.clone()).distance(&key));
+                            assert_eq!(cache_candidates, expected);
+                            return Poll::Ready(());
+                        } else {
+                            panic!("Unexpected error result: {:?}", e);
+                        }
+                    }
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+        }
+        Poll::Pending
+    }));
+
+    // The locally stored record is reported first.
+    assert_eq!(progress.len(), 3);
+    assert_eq!(progress[0], None);
+    let remote = progress[1..].iter().cloned().collect::<HashSet<_>>();
+    let expected = swarm_ids[1..3].iter().cloned().map(Some).collect::<HashSet<_>>();
+    assert_eq!(remote, expected);
+}
+
+#[test]
+fn get_record_caches_at_closest_peers() {
+    let one = NonZeroUsize::new(1).unwrap();
+    let mut cfg = KademliaConfig::default();
+    cfg.set_caching(KademliaCaching::Enabled { max_peers: 2 });
+    // Contact one peer at a time, closest to the key first.
+    cfg.set_query_parallelism(QueryParallelism { alpha: one, beta: Some(one), adaptive: false });
+    let (port_base, mut swarms) = build_nodes_with_config(5, cfg);
+    let swarm_ids: Vec<_> = swarms.iter().map(Swarm::local_peer_id).cloned().collect();
+    for (i, peer) in swarm_ids.iter().enumerate().skip(1) {
+        swarms[0].add_address(peer, Protocol::Memory(port_base + i as u64).into());
+    }
+
+    // The record is only stored at the remote node farthest from the key.
+    let record = Record::new(Multihash::random(SHA2256), vec![4,5,6]);
+    let target = kbucket::Key::new(record.key.clone());
+    let mut remotes = (1 .. 5).collect::<Vec<_>>();
+    remotes.sort_by_key(|i| kbucket::Key::new(swarm_ids[*i].clone()).distance(&target));
+    swarms[remotes[3]].store.put(record.clone()).unwrap();
+
+    swarms[0].get_record(&record.key, Quorum::One);
+
+    block_on(poll_fn(|ctx| {
+        for swarm in &mut swarms {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(KademliaEvent::GetRecordResult(Ok(ok)))) => {
+                        let expected = remotes[.. 3].iter()
+                            .map(|i| swarm_ids[*i].clone())
+                            .collect::<Vec<_>>();
+                        assert_eq!(ok.cache_candidates, expected);
+                    }
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+        }
+        let cached = remotes[.. 2].iter().all(|i| swarms[*i].store.get(&record.key).is_some());
+        if cached { Poll::Ready(()) } else { Poll::Pending }
+    }));
+
+    // The record is not cached beyond `max_peers` and expires sooner the
+    // farther a peer is from the key.
+    assert!(swarms[remotes[2]].store.get(&record.key).is_none());
+    let expires = |i: usize| swarms[i].store.get(&record.key).unwrap().expires.unwrap();
+    let ttl = Duration::from_secs(36 * 60 * 60);
+    assert!(expires(remotes[0]) > Instant::now() + ttl - Duration::from_secs(60));
+    assert!(expires(remotes[1]) <= Instant::now() + ttl / 2);
+    assert!(expires(remotes[1]) > Instant::now() + ttl / 2 - Duration::from_secs(60));
+}
+
+#[test]
+fn get_record_with_quorum_caches_only_agreeing_records() {
+    fn run(agree: bool) {
+        let one = NonZeroUsize::new(1).unwrap();
+        let two = NonZeroUsize::new(2).unwrap();
+        let mut cfg = KademliaConfig::default();
+        // Contact one peer at a time, closest to the key first.
+        cfg.set_query_parallelism(QueryParallelism { alpha: one, beta: Some(one), adaptive: false });
+        let (port_base, mut swarms) = build_nodes_with_config(5, cfg);
+        let swarm_ids: Vec<_> = swarms.iter().map(Swarm::local_peer_id).cloned().collect();
+        for (i, peer) in swarm_ids.iter().enumerate().skip(1) {
+            swarms[0].add_address(peer, Protocol::Memory(port_base + i as u64).into());
+        }
+
+        // The record is only stored at the two remote nodes farthest from the
+        // key, with the same or with different values.
+        let record = Record::new(Multihash::random(SHA2256), vec![4,5,6]);
+        let other = Record { value: if agree { vec![4,5,6] } else { vec![7,8,9] }, .. record.clone() };
+        let target = kbucket::Key::new(record.key.clone());
+        let mut remotes = (1 .. 5).collect::<Vec<_>>();
+        remotes.sort_by_key(|i| kbucket::Key::new(swarm_ids[*i].clone()).distance(&target));
+        swarms[remotes[2]].store.put(record.clone()).unwrap();
+        swarms[remotes[3]].store.put(other).unwrap();
+
+        swarms[0].get_record(&record.key, Quorum::N(two));
+
+        block_on(poll_fn(|ctx| {
+            for swarm in &mut swarms {
+                loop {
+                    match swarm.poll_next_unpin(ctx) {
+                        Poll::Ready(Some(KademliaEvent::GetRecordResult(Ok(ok)))) => {
+                            assert_eq!(ok.records.len(), 2);
+                            assert!(!ok.cache_candidates.is_empty());
+                            return Poll::Ready(())
+                        }
+                        // Ignore any other event.
+                        Poll::Ready(Some(_)) => (),
+                        e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                        Poll::Pending => break,
+                    }
+                }
+            }
+            Poll::Pending
+        }));
+
+        let caching = swarms[0].queries.iter().any(|q| matches!(
+            q.inner.info,
+            QueryInfo::PutRecord { context: PutRecordContext::Cache, .. }
+        ));
+        assert_eq!(caching, agree);
+    }
+
+    run(true);
+    run(false);
+}
+
+#[test]
+fn peers_without_protocol_not_added() {
+    let mut cfg = KademliaConfig::default();
+    cfg.set_protocol_name(&b"/private/kad/1.0.0"[..]);
+    let (private_port_base, mut swarms) = build_nodes_with_config(2, cfg);
+    let (public_port_base, public) = build_nodes(1);
+    swarms.extend(public);
+    let swarm_ids: Vec<_> = swarms.iter().map(Swarm::local_peer_id).cloned().collect();
+    let addrs: Vec<Multiaddr> = vec![
+        Protocol::Memory(private_port_base).into(),
+        Protocol::Memory(private_port_base + 1).into(),
+        Protocol::Memory(public_port_base).into(),
+    ];
+
+    // The first private node dials the other private node and the public node.
+    Swarm::dial_addr(&mut swarms[0], addrs[1].clone()).unwrap();
+    Swarm::dial_addr(&mut swarms[0], addrs[2].clone()).unwrap();
+    block_on(poll_fn(|ctx| {
+        for swarm in &mut swarms {
+            while let Poll::Ready(Some(_)) = swarm.poll_next_unpin(ctx) {}
+        }
+        let connected = swarm_ids[1..].iter().all(|p| swarms[0].connected_peers.contains(p))
+            && swarms[1..].iter().all(|s| s.connected_peers.contains(&swarm_ids[0]));
+        if connected { Poll::Ready(()) } else { Poll::Pending }
+    }));
+    assert_eq!(swarms[0].kbuckets.iter().count(), 0);
+
+    // Both remotes send a request to the first private node over the existing connections.
+    for swarm in &mut swarms[1..] {
+        swarm.add_address(&swarm_ids[0], addrs[0].clone());
+        swarm.bootstrap();
+    }
+
+    let mut finished = HashSet::new();
+    block_on(poll_fn(|ctx| {
+        for (i, swarm) in swarms.iter_mut().enumerate() {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(KademliaEvent::BootstrapResult(_))) => {
+                        finished.insert(i);
+                    }
+                    Poll::Ready(Some(KademliaEvent::UnroutablePeer { peer })) => {
+                        panic!("Unexpected unroutable peer: {:?}", peer)
+                    }
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+        }
+        if finished.contains(&1) && finished.contains(&2) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }));
+
+    // Only the private node has been added to the routing table.
+    let entries = swarms[0].kbuckets_entries().cloned().collect::<Vec<_>>();
+    assert_eq!(entries, vec![swarm_ids[1].clone()]);
+}
+
+#[test]
+fn routing_table_manipulation() {
+    let (_, mut swarms) = build_nodes(1);
+    let peers = (0 .. 5).map(|_| PeerId::random()).collect::<Vec<_>>();
+    for peer in &peers {
+        swarms[0].add_address(peer, Protocol::Memory(random::<u64>()).into());
+    }
+
+    let entries = swarms[0].kbuckets()
+        .flat_map(|b| b.iter()
+            .map(|e| (e.node.key.preimage().clone(), e.status, e.protected))
+            .collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(entries.len(), peers.len());
+    assert!(entries.iter().all(|(_, status, protected)|
+        *status == NodeStatus::Disconnected && !protected));
+
+    assert!(swarms[0].protect_peer(&peers[0]));
+    assert!(!swarms[0].protect_peer(&PeerId::random()));
+    let dump = swarms[0].dump_routing_table();
+    assert_eq!(dump.lines().filter(|l| l.starts_with("  ")).count(), peers.len());
+    assert!(dump.lines().any(|l| l.contains(&peers[0].to_string()) && l.contains("protected")));
+
+    let removed = swarms[0].remove_peer(&peers[1]).unwrap();
+    assert_eq!(removed.node.key.preimage(), &peers[1]);
+    assert!(swarms[0].remove_peer(&peers[1]).is_none());
+    assert!(swarms[0].kbuckets_entries().all(|p| p != &peers[1]));
+    assert_eq!(swarms[0].kbuckets().map(|b| b.num_entries()).sum::<usize>(), peers.len() - 1);
+}
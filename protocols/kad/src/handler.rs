@@ -63,6 +63,21 @@ where
 
     /// Until when to keep the connection alive.
     keep_alive: KeepAlive,
+
+    /// Whether the remote is known to support the Kademlia protocol.
+    protocol_status: ProtocolStatus,
+}
+
+/// The status of the Kademlia protocol on a connection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ProtocolStatus {
+    /// No substream has been successfully negotiated yet.
+    Unconfirmed,
+    /// A substream has been successfully negotiated, but this
+    /// has not yet been reported to the behaviour.
+    Confirmed,
+    /// The behaviour has been informed that the remote supports the protocol.
+    Reported,
 }
 
 /// State of an active substream, opened either by us or by the remote.
@@ -133,6 +148,12 @@ where
 /// Event produced by the Kademlia handler.
 #[derive(Debug)]
 pub enum KademliaHandlerEvent<TUserData> {
+    /// The configured protocol name has been confirmed for the connection,
+    /// i.e. a substream has been successfully negotiated with the remote.
+    ///
+    /// Emitted at most once per connection, before any other event.
+    ProtocolConfirmed,
+
     /// Request for the list of nodes whose IDs are the closest to `key`. The number of nodes
     /// returned is not specified, but should be around 20.
     FindNodeReq {
@@ -404,6 +425,7 @@ where
             next_connec_unique_id: UniqueConnecId(0),
             substreams: Vec::new(),
             keep_alive: KeepAlive::Yes,
+            protocol_status: ProtocolStatus::Unconfirmed,
         }
     }
 
@@ -455,6 +477,9 @@ where
     ) {
         self.substreams
             .push(SubstreamState::OutPendingSend(protocol, msg, user_data));
+        if let ProtocolStatus::Unconfirmed = self.protocol_status {
+            self.protocol_status = ProtocolStatus::Confirmed;
+        }
     }
 
     fn inject_fully_negotiated_inbound(
@@ -473,6 +498,9 @@ where
         self.next_connec_unique_id.0 += 1;
         self.substreams
             .push(SubstreamState::InWaitingMessage(connec_unique_id, protocol));
+        if let ProtocolStatus::Unconfirmed = self.protocol_status {
+            self.protocol_status = ProtocolStatus::Confirmed;
+        }
     }
 
     fn inject_event(&mut self, message: KademliaHandlerIn<TUserData>) {
@@ -645,6 +673,11 @@ where
     ) -> Poll<
         ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>,
     > {
+        if let ProtocolStatus::Confirmed = self.protocol_status {
+            self.protocol_status = ProtocolStatus::Reported;
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(KademliaHandlerEvent::ProtocolConfirmed));
+        }
+
         // We remove each element from `substreams` one by one and add them back.
         for n in (0..self.substreams.len()).rev() {
             let mut substream = self.substreams.swap_remove(n);
@@ -39,7 +39,7 @@
 //! intervals should be shorter than publication intervals and
 //! publication intervals should be shorter than the TTL.
 //!
-//! This module implements two periodic jobs:
+//! This module implements three periodic jobs:
 //!
 //!   * [`jobs::PutRecordJob`]: For (re-)publication and (re-)replication of
 //!     regular (value-)records.
@@ -47,6 +47,10 @@
 //!   * [`jobs::AddProviderJob`]: For (re-)publication of provider records.
 //!     Provider records currently have no separate replication mechanism.
 //!
+//!   * [`jobs::ExpireProvidersJob`]: For the removal of the expired provider
+//!     records received from other nodes, which are not re-published and
+//!     would otherwise stay in the `RecordStore` forever.
+//!
 //! A periodic job is driven like a `Future` or `Stream` by `poll`ing it.
 //! Once a job starts running it emits records to send to the `k` closest
 //! nodes to the key, where `k` is the replication factor.
@@ -60,6 +64,12 @@
 //! > for the worst case, it temporarily requires additional memory proportional
 //! > to the size of all stored records. As a job runs, the records are moved
 //! > out of the job to the consumer, where they can be dropped after being sent.
+//!
+//! ## Bootstrapping
+//!
+//! In addition, the [`jobs::BootstrapJob`] periodically signals that the
+//! local node should bootstrap again, i.e. look up its own key and refresh
+//! stale buckets, to keep its routing table up-to-date.
 
 use crate::record::{self, Record, ProviderRecord, store::RecordStore};
 use libp2p_core::PeerId;
@@ -323,6 +333,125 @@ impl AddProviderJob {
     }
 }
 
+//////////////////////////////////////////////////////////////////////////////
+// ExpireProvidersJob
+
+/// Periodic job for removing expired provider records.
+pub struct ExpireProvidersJob {
+    inner: PeriodicJob<vec::IntoIter<ProviderRecord>>
+}
+
+impl ExpireProvidersJob {
+    /// Creates a new periodic job for removing expired provider records.
+    pub fn new(interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            inner: PeriodicJob {
+                interval,
+                state: {
+                    let deadline = now + interval;
+                    PeriodicJobState::Waiting(Delay::new_at(deadline), deadline)
+                }
+            }
+        }
+    }
+
+    /// Checks whether the job is currently running.
+    pub fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    /// Cuts short the remaining delay, if the job is currently waiting
+    /// for the delay to expire.
+    ///
+    /// The job is guaranteed to run on the next invocation of `poll`.
+    pub fn asap(&mut self) {
+        self.inner.asap()
+    }
+
+    /// Polls the job for expired provider records, which are removed from
+    /// the store before being returned.
+    ///
+    /// Must be called in the context of a task. When `NotReady` is returned,
+    /// the current task is registered to be notified when the job is ready
+    /// to be run.
+    pub fn poll<T>(&mut self, cx: &mut Context, store: &mut T, now: Instant) -> Poll<ProviderRecord>
+    where
+        for<'a> T: RecordStore<'a>
+    {
+        if self.inner.is_ready(cx, now) {
+            let records = store.provider_records()
+                .filter(|r| r.is_expired(now))
+                .map(|r| r.into_owned())
+                .collect::<Vec<_>>()
+                .into_iter();
+            self.inner.state = PeriodicJobState::Running(records);
+        }
+
+        if let PeriodicJobState::Running(records) = &mut self.inner.state {
+            if let Some(r) = records.next() {
+                store.remove_provider(&r.key, &r.provider);
+                return Poll::Ready(r)
+            }
+
+            let deadline = now + self.inner.interval;
+            let delay = Delay::new_at(deadline);
+            self.inner.state = PeriodicJobState::Waiting(delay, deadline);
+            assert!(!self.inner.is_ready(cx, now));
+        }
+
+        Poll::Pending
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// BootstrapJob
+
+/// Periodic job for bootstrapping the local node.
+pub struct BootstrapJob {
+    inner: PeriodicJob<()>
+}
+
+impl BootstrapJob {
+    /// Creates a new periodic job for bootstrapping the local node.
+    pub fn new(interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            inner: PeriodicJob {
+                interval,
+                state: {
+                    let deadline = now + interval;
+                    PeriodicJobState::Waiting(Delay::new_at(deadline), deadline)
+                }
+            }
+        }
+    }
+
+    /// Cuts short the remaining delay.
+    ///
+    /// The job is guaranteed to be ready on the next invocation of `poll`.
+    pub fn asap(&mut self) {
+        self.inner.asap()
+    }
+
+    /// Polls the job, which is ready once per interval.
+    ///
+    /// Must be called in the context of a task. When `NotReady` is returned,
+    /// the current task is registered to be notified when the job is ready
+    /// to be run.
+    pub fn poll(&mut self, cx: &mut Context, now: Instant) -> Poll<()> {
+        if self.inner.is_ready(cx, now) {
+            let deadline = now + self.inner.interval;
+            let delay = Delay::new_at(deadline);
+            self.inner.state = PeriodicJobState::Waiting(delay, deadline);
+            assert!(!self.inner.is_ready(cx, now));
+            return Poll::Ready(())
+        }
+
+        Poll::Pending
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::record::store::MemoryStore;
@@ -346,12 +475,34 @@ mod tests {
         AddProviderJob::new(interval)
     }
 
+    fn rand_expire_providers_job() -> ExpireProvidersJob {
+        let mut rng = rand::thread_rng();
+        let interval = Duration::from_secs(rng.gen_range(1, 60));
+        ExpireProvidersJob::new(interval)
+    }
+
+    #[test]
+    fn run_bootstrap_job() {
+        let mut job = BootstrapJob::new(Duration::from_secs(60));
+        block_on(poll_fn(|ctx| {
+            let now = Instant::now();
+            assert_eq!(job.poll(ctx, now), Poll::Pending);
+            assert_eq!(job.poll(ctx, now + Duration::from_secs(60)), Poll::Ready(()));
+            assert_eq!(job.poll(ctx, now + Duration::from_secs(60)), Poll::Pending);
+            job.asap();
+            assert_eq!(job.poll(ctx, now), Poll::Ready(()));
+            Poll::Ready(())
+        }));
+    }
+
     #[test]
     fn new_job_not_running() {
         let job = rand_put_record_job();
         assert!(!job.is_running());
         let job = rand_add_provider_job();
         assert!(!job.is_running());
+        let job = rand_expire_providers_job();
+        assert!(!job.is_running());
     }
 
     #[test]
@@ -411,4 +562,37 @@ mod tests {
 
         quickcheck(prop as fn(_))
     }
+
+    #[test]
+    fn run_expire_providers_job() {
+        fn prop(records: Vec<ProviderRecord>) {
+            let mut job = rand_expire_providers_job();
+            // Fill a record store.
+            let mut store = MemoryStore::new(PeerId::random());
+            for r in records {
+                let _ = store.add_provider(r);
+            }
+
+            block_on(poll_fn(|ctx| {
+                let now = Instant::now() + job.inner.interval;
+                // All expired records in the store must be yielded and removed by the job.
+                let (expired, live): (Vec<_>, Vec<_>) = store.provider_records()
+                    .map(|r| r.into_owned())
+                    .partition(|r| r.is_expired(now));
+                let mut removed = Vec::new();
+                while let Poll::Ready(r) = job.poll(ctx, &mut store, now) {
+                    assert!(job.is_running());
+                    removed.push(r);
+                }
+                assert!(!job.is_running());
+                assert_eq!(removed.len(), expired.len());
+                assert!(removed.iter().all(|r| expired.contains(r)));
+                let remaining = store.provider_records().map(|r| r.into_owned()).collect::<Vec<_>>();
+                assert_eq!(remaining.len(), live.len());
+                Poll::Ready(())
+            }));
+        }
+
+        quickcheck(prop as fn(_))
+    }
 }
@@ -32,12 +32,16 @@
 //! an [`AppliedPending`] result which must be consumed by calling [`take_applied_pending`]
 //! regularly and / or after performing lookup operations like [`entry`] and [`closest`].
 //!
+//! Entries that are protected through [`PresentEntry::set_protected`] are never
+//! evicted in favour of a pending entry, but can still be removed explicitly.
+//!
 //! [`entry`]: kbucket::KBucketsTable::entry
 //! [`closest`]: kbucket::KBucketsTable::closest
 //! [`AppliedPending`]: kbucket::AppliedPending
 //! [`KBucketsTable`]: kbucket::KBucketsTable
 //! [`take_applied_pending`]: kbucket::KBucketsTable::take_applied_pending
 //! [`PendingEntry`]: kbucket::PendingEntry
+//! [`PresentEntry::set_protected`]: kbucket::PresentEntry::set_protected
 
 // [Implementation Notes]
 //
@@ -88,6 +92,8 @@ pub struct KBucketsTable<TKey, TVal> {
     local_key: TKey,
     /// The buckets comprising the routing table.
     buckets: Vec<KBucket<TKey, TVal>>,
+    /// The instants at which the buckets have last been refreshed, if ever.
+    refreshed: Vec<Option<Instant>>,
     /// The list of evicted entries that have been replaced with pending
     /// entries since the last call to [`KBucketsTable::take_applied_pending`].
     applied_pending: VecDeque<AppliedPending<TKey, TVal>>
@@ -147,6 +153,7 @@ where
         KBucketsTable {
             local_key,
             buckets: (0 .. NUM_BUCKETS).map(|_| KBucket::new(pending_timeout)).collect(),
+            refreshed: vec![None; NUM_BUCKETS],
             applied_pending: VecDeque::new()
         }
     }
@@ -179,15 +186,7 @@ where
                 applied_pending.push_back(applied)
             }
             let table = &*table;
-            table.iter().map(move |(n, status)| {
-                EntryRefView {
-                    node: NodeRefView {
-                        key: &n.key,
-                        value: &n.value
-                    },
-                    status
-                }
-            })
+            table.iter_views()
         })
     }
 
@@ -197,13 +196,14 @@ where
     /// bucket is the closest bucket (containing at most one key).
     pub fn buckets<'a>(&'a mut self) -> impl Iterator<Item = KBucketRef<'a, TKey, TVal>> + 'a {
         let applied_pending = &mut self.applied_pending;
-        self.buckets.iter_mut().enumerate().map(move |(i, b)| {
+        self.buckets.iter_mut().zip(self.refreshed.iter_mut()).enumerate().map(move |(i, (b, r))| {
             if let Some(applied) = b.apply_pending() {
                 applied_pending.push_back(applied)
             }
             KBucketRef {
                 index: BucketIndex(i),
-                bucket: b
+                bucket: b,
+                refreshed: r
             }
         })
     }
@@ -441,7 +441,8 @@ where
 /// A reference to a bucket in a `KBucketsTable`.
 pub struct KBucketRef<'a, TPeerId, TVal> {
     index: BucketIndex,
-    bucket: &'a mut KBucket<TPeerId, TVal>
+    bucket: &'a mut KBucket<TPeerId, TVal>,
+    refreshed: &'a mut Option<Instant>
 }
 
 impl<TKey, TVal> KBucketRef<'_, TKey, TVal>
@@ -449,16 +450,48 @@ where
     TKey: Clone + AsRef<KeyBytes>,
     TVal: Clone
 {
+    /// Returns the index of the bucket, i.e. the bucket contains the keys
+    /// whose distance `d` to the local key satisfies `2^index <= d < 2^(index + 1)`.
+    pub fn index(&self) -> usize {
+        self.index.get()
+    }
+
     /// Returns the number of entries in the bucket.
     pub fn num_entries(&self) -> usize {
         self.bucket.num_entries()
     }
 
+    /// Returns the number of entries in the bucket that are considered connected.
+    pub fn num_connected(&self) -> usize {
+        self.bucket.num_connected()
+    }
+
+    /// Returns an iterator over the entries in the bucket, ordered from
+    /// least-recently (dis)connected to most-recently (dis)connected.
+    pub fn iter(&self) -> impl Iterator<Item = EntryRefView<'_, TKey, TVal>> {
+        self.bucket.iter_views()
+    }
+
     /// Returns true if the bucket has a pending node.
     pub fn has_pending(&self) -> bool {
         self.bucket.pending().map_or(false, |n| !n.is_ready())
     }
 
+    /// Checks whether the bucket has not been refreshed within the given
+    /// interval, or never.
+    pub fn is_stale(&self, interval: Duration) -> bool {
+        match *self.refreshed {
+            Some(t) => t.elapsed() >= interval,
+            None => true
+        }
+    }
+
+    /// Marks the bucket as refreshed, i.e. records that a lookup for a key
+    /// falling into the bucket has just been started.
+    pub fn mark_refreshed(&mut self) {
+        *self.refreshed = Some(Instant::now())
+    }
+
     /// Tests whether the given distance falls into this bucket.
     pub fn contains(&self, d: &Distance) -> bool {
         BucketIndex::new(d).map_or(false, |i| i == self.index)
@@ -553,6 +586,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn bucket_refreshed() {
+        let local_key = Key::from(PeerId::random());
+        let mut table = KBucketsTable::<_, ()>::new(local_key, Duration::from_secs(5));
+        let interval = Duration::from_secs(60);
+        assert!(table.buckets().all(|b| b.is_stale(interval)));
+
+        table.buckets().nth(255).unwrap().mark_refreshed();
+        let stale = table.buckets().filter(|b| b.is_stale(interval)).count();
+        assert_eq!(stale, NUM_BUCKETS - 1);
+        assert!(!table.buckets().nth(255).unwrap().is_stale(interval));
+        assert!(table.buckets().nth(255).unwrap().is_stale(Duration::from_secs(0)));
+    }
+
     #[test]
     fn closest() {
         let local_key = Key::from(PeerId::random());
@@ -74,6 +74,10 @@ impl<TKey, TVal> PendingNode<TKey, TVal> {
     pub fn set_ready_at(&mut self, t: Instant) {
         self.replace = t;
     }
+
+    pub fn into_node(self) -> Node<TKey, TVal> {
+        self.node
+    }
 }
 
 /// A `Node` in a bucket, representing a peer participating
@@ -87,6 +91,29 @@ pub struct Node<TKey, TVal> {
     pub value: TVal,
 }
 
+/// A node in a bucket together with the information the bucket
+/// tracks about it.
+#[derive(Debug, Clone)]
+struct Slot<TKey, TVal> {
+    /// The node in the slot.
+    node: Node<TKey, TVal>,
+    /// The instant at which the node has last been seen connected, if ever.
+    last_seen: Option<Instant>,
+    /// Whether the node is protected from eviction.
+    protected: bool,
+}
+
+impl<TKey, TVal> Slot<TKey, TVal> {
+    /// Creates a new slot for a node newly inserted with the given status.
+    fn new(node: Node<TKey, TVal>, status: NodeStatus) -> Self {
+        let last_seen = match status {
+            NodeStatus::Connected => Some(Instant::now()),
+            NodeStatus::Disconnected => None
+        };
+        Slot { node, last_seen, protected: false }
+    }
+}
+
 /// The position of a node in a `KBucket`, i.e. a non-negative integer
 /// in the range `[0, K_VALUE)`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -97,7 +124,7 @@ pub struct Position(usize);
 #[derive(Debug, Clone)]
 pub struct KBucket<TKey, TVal> {
     /// The nodes contained in the bucket.
-    nodes: ArrayVec<[Node<TKey, TVal>; K_VALUE.get()]>,
+    nodes: ArrayVec<[Slot<TKey, TVal>; K_VALUE.get()]>,
 
     /// The position (index) in `nodes` that marks the first connected node.
     ///
@@ -114,8 +141,8 @@ pub struct KBucket<TKey, TVal> {
     first_connected_pos: Option<usize>,
 
     /// A node that is pending to be inserted into a full bucket, should the
-    /// least-recently connected (and currently disconnected) node not be
-    /// marked as connected within `unresponsive_timeout`.
+    /// least-recently connected (and currently disconnected) unprotected node
+    /// not be marked as connected within `unresponsive_timeout`.
     pending: Option<PendingNode<TKey, TVal>>,
 
     /// The timeout window before a new pending node is eligible for insertion,
@@ -190,12 +217,33 @@ where
 
     /// Returns a reference to a node in the bucket.
     pub fn get(&self, key: &TKey) -> Option<&Node<TKey, TVal>> {
-        self.position(key).map(|p| &self.nodes[p.0])
+        self.position(key).map(|p| &self.nodes[p.0].node)
     }
 
     /// Returns an iterator over the nodes in the bucket, together with their status.
     pub fn iter(&self) -> impl Iterator<Item = (&Node<TKey, TVal>, NodeStatus)> {
-        self.nodes.iter().enumerate().map(move |(p, n)| (n, self.status(Position(p))))
+        self.nodes.iter().enumerate().map(move |(p, s)| (&s.node, self.status(Position(p))))
+    }
+
+    /// Returns an iterator over by-reference views of the nodes in the bucket.
+    pub fn iter_views(&self) -> impl Iterator<Item = EntryRefView<'_, TKey, TVal>> {
+        self.nodes.iter().enumerate().map(move |(p, s)| EntryRefView {
+            node: NodeRefView {
+                key: &s.node.key,
+                value: &s.node.value
+            },
+            status: self.status(Position(p)),
+            last_seen: s.last_seen,
+            protected: s.protected
+        })
+    }
+
+    /// Returns the position of the node that is evicted if a pending node
+    /// is inserted into the full bucket, i.e. the least-recently connected
+    /// node that is currently disconnected and not protected, if any.
+    fn eviction_candidate(&self) -> Option<Position> {
+        let num_disconnected = self.first_connected_pos.unwrap_or_else(|| self.nodes.len());
+        self.nodes[.. num_disconnected].iter().position(|s| !s.protected).map(Position)
     }
 
     /// Inserts the pending node into the bucket, if its timeout has elapsed,
@@ -208,36 +256,36 @@ where
         if let Some(pending) = self.pending.take() {
             if pending.replace <= Instant::now() {
                 if self.nodes.is_full() {
-                    if self.status(Position(0)) == NodeStatus::Connected {
-                        // The bucket is full with connected nodes. Drop the pending node.
-                        return None
-                    }
+                    // If the bucket is full with connected or protected nodes,
+                    // the pending node is dropped.
+                    let evict = self.eviction_candidate()?;
                     // The pending node will be inserted.
                     let inserted = pending.node.clone();
+                    let slot = Slot::new(pending.node, pending.status);
                     // A connected pending node goes at the end of the list for
                     // the connected peers, removing the least-recently connected.
                     if pending.status == NodeStatus::Connected {
-                        let evicted = Some(self.nodes.remove(0));
+                        let evicted = Some(self.nodes.remove(evict.0).node);
                         self.first_connected_pos = self.first_connected_pos
                             .map_or_else(
                                 | | Some(self.nodes.len()),
                                 |p| p.checked_sub(1));
-                        self.nodes.push(pending.node);
+                        self.nodes.push(slot);
                         return Some(AppliedPending { inserted, evicted })
                     }
                     // A disconnected pending node goes at the end of the list
                     // for the disconnected peers.
                     else if let Some(p) = self.first_connected_pos {
                         if let Some(insert_pos) = p.checked_sub(1) {
-                            let evicted = Some(self.nodes.remove(0));
-                            self.nodes.insert(insert_pos, pending.node);
+                            let evicted = Some(self.nodes.remove(evict.0).node);
+                            self.nodes.insert(insert_pos, slot);
                             return Some(AppliedPending { inserted, evicted })
                         }
                     } else {
                         // All nodes are disconnected. Insert the new node as the most
                         // recently disconnected, removing the least-recently disconnected.
-                        let evicted = Some(self.nodes.remove(0));
-                        self.nodes.push(pending.node);
+                        let evicted = Some(self.nodes.remove(evict.0).node);
+                        self.nodes.push(slot);
                         return Some(AppliedPending { inserted, evicted })
                     }
                 } else {
@@ -273,9 +321,17 @@ where
         // nodes (i.e. most-recently disconnected or most-recently connected,
         // respectively).
         if let Some(pos) = self.position(key) {
+            // If the least-recently connected, unprotected node re-establishes
+            // its connected status, drop the pending node.
+            if self.eviction_candidate() == Some(pos) && status == NodeStatus::Connected {
+                self.pending = None
+            }
             // Remove the node from its current position.
             let old_status = self.status(pos);
-            let node = self.nodes.remove(pos.0);
+            let mut slot = self.nodes.remove(pos.0);
+            if old_status == NodeStatus::Connected || status == NodeStatus::Connected {
+                slot.last_seen = Some(Instant::now());
+            }
             // Adjust `first_connected_pos` accordingly.
             match old_status {
                 NodeStatus::Connected =>
@@ -289,13 +345,8 @@ where
                     self.first_connected_pos = self.first_connected_pos
                         .and_then(|p| p.checked_sub(1))
             }
-            // If the least-recently connected node re-establishes its
-            // connected status, drop the pending node.
-            if pos == Position(0) && status == NodeStatus::Connected {
-                self.pending = None
-            }
             // Reinsert the node with the desired status.
-            match self.insert(node, status) {
+            match self.insert_slot(slot, status) {
                 InsertResult::Inserted => {},
                 _ => unreachable!("The node is removed before being (re)inserted.")
             }
@@ -307,9 +358,10 @@ where
     /// The status of the node to insert determines the result as follows:
     ///
     ///   * `NodeStatus::Connected`: If the bucket is full and either all nodes are connected
-    ///     or there is already a pending node, insertion fails with `InsertResult::Full`.
-    ///     If the bucket is full but at least one node is disconnected and there is no pending
-    ///     node, the new node is inserted as pending, yielding `InsertResult::Pending`.
+    ///     or protected, or there is already a pending node, insertion fails with
+    ///     `InsertResult::Full`. If the bucket is full but at least one node is disconnected
+    ///     and not protected and there is no pending node, the new node is inserted as pending,
+    ///     yielding `InsertResult::Pending`.
     ///     Otherwise the bucket has free slots and the new node is added to the end of the
     ///     bucket as the most-recently connected node.
     ///
@@ -320,25 +372,32 @@ where
     ///     the new node is added as the last element of the bucket.
     ///
     pub fn insert(&mut self, node: Node<TKey, TVal>, status: NodeStatus) -> InsertResult<TKey> {
+        self.insert_slot(Slot::new(node, status), status)
+    }
+
+    /// Inserts a node into the bucket with the given status, retaining the
+    /// information tracked about the node. See [`KBucket::insert`].
+    fn insert_slot(&mut self, slot: Slot<TKey, TVal>, status: NodeStatus) -> InsertResult<TKey> {
         match status {
             NodeStatus::Connected => {
                 if self.nodes.is_full() {
-                    if self.first_connected_pos == Some(0) || self.pending.is_some() {
-                        return InsertResult::Full
-                    } else {
-                        self.pending = Some(PendingNode {
-                            node,
-                            status: NodeStatus::Connected,
-                            replace: Instant::now() + self.pending_timeout,
-                        });
-                        return InsertResult::Pending {
-                            disconnected: self.nodes[0].key.clone()
+                    match self.eviction_candidate() {
+                        Some(pos) if self.pending.is_none() => {
+                            self.pending = Some(PendingNode {
+                                node: slot.node,
+                                status: NodeStatus::Connected,
+                                replace: Instant::now() + self.pending_timeout,
+                            });
+                            return InsertResult::Pending {
+                                disconnected: self.nodes[pos.0].node.key.clone()
+                            }
                         }
+                        _ => return InsertResult::Full
                     }
                 }
                 let pos = self.nodes.len();
                 self.first_connected_pos = self.first_connected_pos.or(Some(pos));
-                self.nodes.push(node);
+                self.nodes.push(slot);
                 InsertResult::Inserted
             }
             NodeStatus::Disconnected => {
@@ -346,16 +405,58 @@ where
                     return InsertResult::Full
                 }
                 if let Some(ref mut first_connected_pos) = self.first_connected_pos {
-                    self.nodes.insert(*first_connected_pos, node);
+                    self.nodes.insert(*first_connected_pos, slot);
                     *first_connected_pos += 1;
                 } else {
-                    self.nodes.push(node);
+                    self.nodes.push(slot);
                 }
                 InsertResult::Inserted
             }
         }
     }
 
+    /// Removes the node referred to by the given key from the bucket,
+    /// returning it together with its status, if it was in the bucket.
+    pub fn remove(&mut self, key: &TKey) -> Option<(Node<TKey, TVal>, NodeStatus)> {
+        let pos = self.position(key)?;
+        let status = self.status(pos);
+        let slot = self.nodes.remove(pos.0);
+        // Adjust `first_connected_pos` accordingly.
+        match status {
+            NodeStatus::Connected =>
+                if self.first_connected_pos == Some(self.nodes.len()) {
+                    // It was the last connected node.
+                    self.first_connected_pos = None
+                }
+            NodeStatus::Disconnected =>
+                self.first_connected_pos = self.first_connected_pos
+                    .and_then(|p| p.checked_sub(1))
+        }
+        Some((slot.node, status))
+    }
+
+    /// Removes the pending node of the bucket, if there is any.
+    pub fn remove_pending(&mut self) -> Option<PendingNode<TKey, TVal>> {
+        self.pending.take()
+    }
+
+    /// Checks whether the node at the given position is protected from eviction.
+    pub fn is_protected(&self, pos: Position) -> bool {
+        self.nodes[pos.0].protected
+    }
+
+    /// Protects the node at the given position from eviction, or removes
+    /// the protection.
+    pub fn set_protected(&mut self, pos: Position, protected: bool) {
+        self.nodes[pos.0].protected = protected
+    }
+
+    /// Returns the instant at which the node at the given position has last
+    /// been seen connected, if ever.
+    pub fn last_seen(&self, pos: Position) -> Option<Instant> {
+        self.nodes[pos.0].last_seen
+    }
+
     /// Returns the status of the node at the given position.
     pub fn status(&self, pos: Position) -> NodeStatus {
         if self.first_connected_pos.map_or(false, |i| pos.0 >= i) {
@@ -387,7 +488,7 @@ where
 
     /// Gets the position of an node in the bucket.
     pub fn position(&self, key: &TKey) -> Option<Position> {
-        self.nodes.iter().position(|p| p.key.as_ref() == key.as_ref()).map(Position)
+        self.nodes.iter().position(|s| s.node.key.as_ref() == key.as_ref()).map(Position)
     }
 
     /// Gets a mutable reference to the node identified by the given key.
@@ -395,7 +496,7 @@ where
     /// Returns `None` if the given key does not refer to an node in the
     /// bucket.
     pub fn get_mut(&mut self, key: &TKey) -> Option<&mut Node<TKey, TVal>> {
-        self.nodes.iter_mut().find(move |p| p.key.as_ref() == key.as_ref())
+        self.nodes.iter_mut().map(|s| &mut s.node).find(move |n| n.key.as_ref() == key.as_ref())
     }
 }
 
@@ -605,7 +706,7 @@ mod tests {
 
             // Capture position and key of the random node to update.
             let pos = pos.0 % num_nodes;
-            let key = bucket.nodes[pos].key.clone();
+            let key = bucket.nodes[pos].node.key.clone();
 
             // Record the (ordered) list of status of all nodes in the bucket.
             let mut expected = bucket.iter().map(|(n,s)| (n.key.clone(), s)).collect::<Vec<_>>();
@@ -627,4 +728,65 @@ mod tests {
 
         quickcheck(prop as fn(_,_,_) -> _);
     }
+
+    #[test]
+    fn bucket_remove() {
+        fn prop(mut bucket: KBucket<Key<PeerId>, ()>, pos: Position) -> bool {
+            let num_nodes = bucket.num_entries();
+
+            // Capture position, key and status of the random node to remove.
+            let pos = pos.0 % num_nodes;
+            let key = bucket.nodes[pos].node.key.clone();
+            let status = bucket.status(Position(pos));
+
+            let mut expected = bucket.iter().map(|(n,s)| (n.key.clone(), s)).collect::<Vec<_>>();
+            expected.remove(pos);
+
+            // Remove the node, preserving the status and order of all other nodes.
+            let removed = bucket.remove(&key).map(|(n, s)| (n.key, s));
+            let actual = bucket.iter().map(|(n,s)| (n.key.clone(), s)).collect::<Vec<_>>();
+            removed == Some((key, status)) && expected == actual
+        }
+
+        quickcheck(prop as fn(_,_) -> _);
+    }
+
+    #[test]
+    fn full_bucket_protected() {
+        let mut bucket = KBucket::<Key<PeerId>, ()>::new(Duration::from_secs(1));
+        fill_bucket(&mut bucket, NodeStatus::Disconnected);
+        let nodes = bucket.iter().map(|(n,_)| n.clone()).collect::<Vec<_>>();
+
+        // Protect the least-recently connected node.
+        bucket.set_protected(Position(0), true);
+
+        // A connected pending node is scheduled to replace the least-recently
+        // connected node that is not protected.
+        let key = Key::new(PeerId::random());
+        let node = Node { key: key.clone(), value: () };
+        match bucket.insert(node.clone(), NodeStatus::Connected) {
+            InsertResult::Pending { disconnected } => assert_eq!(disconnected, nodes[1].key),
+            x => panic!("{:?}", x)
+        }
+        let pending = bucket.pending_mut().expect("No pending node.");
+        pending.set_ready_at(Instant::now() - Duration::from_secs(1));
+        let result = bucket.apply_pending();
+        assert_eq!(result, Some(AppliedPending {
+            inserted: node.clone(),
+            evicted: Some(nodes[1].clone())
+        }));
+        assert!(bucket.is_protected(bucket.position(&nodes[0].key).unwrap()));
+        assert!(bucket.last_seen(bucket.position(&key).unwrap()).is_some());
+        assert!(bucket.last_seen(bucket.position(&nodes[0].key).unwrap()).is_none());
+
+        // If all disconnected nodes are protected, no pending node is accepted.
+        for i in 0 .. bucket.num_disconnected() {
+            bucket.set_protected(Position(i), true);
+        }
+        let node = Node { key: Key::new(PeerId::random()), value: () };
+        match bucket.insert(node, NodeStatus::Connected) {
+            InsertResult::Full => {},
+            x => panic!("{:?}", x)
+        }
+    }
 }
@@ -25,13 +25,20 @@ pub use super::bucket::{Node, NodeStatus, InsertResult, AppliedPending, K_VALUE}
 pub use super::key::*;
 
 use super::*;
+use super::bucket::Position;
 
 /// An immutable by-reference view of a bucket entry.
 pub struct EntryRefView<'a, TPeerId, TVal> {
     /// The node represented by the entry.
     pub node: NodeRefView<'a, TPeerId, TVal>,
     /// The status of the node identified by the key.
-    pub status: NodeStatus
+    pub status: NodeStatus,
+    /// The instant at which the node has last been seen connected, if ever.
+    ///
+    /// Always `None` for a pending entry.
+    pub last_seen: Option<Instant>,
+    /// Whether the node is protected from eviction.
+    pub protected: bool
 }
 
 /// An immutable by-reference view of a `Node`.
@@ -117,19 +124,28 @@ where
     /// pending insertion into a bucket.
     pub fn view(&'a mut self) -> Option<EntryRefView<'a, TKey, TVal>> {
         match self {
-            Entry::Present(entry, status) => Some(EntryRefView {
-                node: NodeRefView {
-                    key: entry.0.key,
-                    value: entry.value()
-                },
-                status: *status
-            }),
+            Entry::Present(entry, status) => {
+                let pos = entry.position();
+                let last_seen = entry.0.bucket.last_seen(pos);
+                let protected = entry.0.bucket.is_protected(pos);
+                Some(EntryRefView {
+                    node: NodeRefView {
+                        key: entry.0.key,
+                        value: entry.value()
+                    },
+                    status: *status,
+                    last_seen,
+                    protected
+                })
+            }
             Entry::Pending(entry, status) => Some(EntryRefView {
                 node: NodeRefView {
                     key: entry.0.key,
                     value: entry.value()
                 },
-                status: *status
+                status: *status,
+                last_seen: None,
+                protected: false
             }),
             _ => None
         }
@@ -194,6 +210,35 @@ where
         self.0.bucket.update(self.0.key, status);
         Self::new(self.0.bucket, self.0.key)
     }
+
+    /// Checks whether the entry is protected from eviction.
+    pub fn is_protected(&self) -> bool {
+        self.0.bucket.is_protected(self.position())
+    }
+
+    /// Protects the entry from being evicted in favour of a pending entry,
+    /// or removes the protection.
+    ///
+    /// A protected entry stays in the bucket until it is explicitly removed.
+    pub fn set_protected(&mut self, protected: bool) {
+        let pos = self.position();
+        self.0.bucket.set_protected(pos, protected)
+    }
+
+    /// Removes the entry from the bucket.
+    pub fn remove(self) -> EntryView<TKey, TVal> {
+        let (node, status) = self.0.bucket
+            .remove(self.0.key)
+            .expect("We can only build a PresentEntry if the entry is in the bucket; QED");
+        EntryView { node, status }
+    }
+
+    /// Returns the position of the entry in the bucket.
+    fn position(&self) -> Position {
+        self.0.bucket
+            .position(self.0.key)
+            .expect("We can only build a PresentEntry if the entry is in the bucket; QED")
+    }
 }
 
 /// An entry waiting for a slot to be available in a bucket.
@@ -227,6 +272,15 @@ where
         self.0.bucket.update_pending(status);
         PendingEntry::new(self.0.bucket, self.0.key)
     }
+
+    /// Removes the pending entry from the bucket.
+    pub fn remove(self) -> EntryView<TKey, TVal> {
+        let pending = self.0.bucket
+            .remove_pending()
+            .expect("We can only build a PendingEntry if the entry is pending; QED");
+        let status = pending.status();
+        EntryView { node: pending.into_node(), status }
+    }
 }
 
 /// An entry that is not present in any bucket.
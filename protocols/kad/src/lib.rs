@@ -26,6 +26,7 @@
 
 pub mod handler;
 pub mod kbucket;
+pub mod metrics;
 pub mod protocol;
 pub mod record;
 
@@ -39,13 +40,14 @@ mod dht_proto {
 }
 
 pub use addresses::Addresses;
-pub use behaviour::{Kademlia, KademliaConfig, KademliaEvent, Quorum};
+pub use behaviour::{Kademlia, KademliaCaching, KademliaConfig, KademliaEvent, Quorum};
 pub use behaviour::{
     BootstrapResult,
     BootstrapOk,
     BootstrapError,
 
     GetRecordResult,
+    GetRecordProgress,
     GetRecordOk,
     GetRecordError,
 
@@ -65,8 +67,10 @@ pub use behaviour::{
     GetProvidersOk,
     GetProvidersError,
 };
+pub use metrics::KademliaMetrics;
 pub use protocol::KadConnectionType;
-pub use record::{store, Record, ProviderRecord};
+pub use query::{QueryId, QueryParallelism, QueryStats};
+pub use record::{store, validator::{self, RecordValidator, InvalidRecord}, Record, ProviderRecord};
 
 use std::num::NonZeroUsize;
 
@@ -0,0 +1,90 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Instrumentation of the Kademlia DHT.
+//!
+//! A [`KademliaMetrics`] implementation installed with
+//! [`Kademlia::set_metrics`] is notified about every finished query,
+//! together with its [`QueryStats`], as well as about changes to the
+//! routing table, so that the health of the DHT can be monitored, e.g.
+//! by exporting the observations to a metrics system.
+//!
+//! [`Kademlia::set_metrics`]: crate::Kademlia::set_metrics
+
+use crate::query::{QueryId, QueryStats};
+use libp2p_core::PeerId;
+
+/// Receives observations about the queries and the routing table of a
+/// [`Kademlia`](crate::Kademlia) behaviour.
+///
+/// All methods have empty default implementations, so that an
+/// implementation only needs to provide those it is interested in.
+pub trait KademliaMetrics: Send + 'static {
+    /// Called whenever a query finishes, successfully or not.
+    ///
+    /// Queries that internally consist of multiple phases, like
+    /// [`Kademlia::put_record`](crate::Kademlia::put_record), are only
+    /// reported once, with the statistics accumulated over all phases.
+    fn query_finished(&mut self, _id: QueryId, _kind: QueryKind, _outcome: QueryOutcome, _stats: &QueryStats) {}
+
+    /// Called whenever the number of entries in a bucket of the routing
+    /// table changed, with the index of the bucket and the new number of
+    /// entries.
+    ///
+    /// Buckets are indexed by the position of the most significant bit of
+    /// the distance of their keys to the local key, i.e. higher indices
+    /// are farther away.
+    fn bucket_size_changed(&mut self, _bucket: usize, _num_entries: usize) {}
+
+    /// Called whenever a peer is evicted from the routing table to make
+    /// room for a new peer.
+    fn peer_evicted(&mut self, _peer: &PeerId) {}
+}
+
+/// The kind of a finished query.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum QueryKind {
+    /// A bootstrap query, see [`Kademlia::bootstrap`](crate::Kademlia::bootstrap).
+    Bootstrap,
+    /// A lookup of the closest peers to a key.
+    GetClosestPeers,
+    /// A lookup of the providers of a key.
+    GetProviders,
+    /// The announcement of the local node as a provider of a key.
+    AddProvider,
+    /// A lookup of the records for a key.
+    GetRecord,
+    /// The storage of a record at the closest peers to its key, including
+    /// the replication, republication and caching of records.
+    PutRecord,
+}
+
+/// The outcome of a finished query.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum QueryOutcome {
+    /// The query succeeded.
+    Success,
+    /// The query finished without finding any record or provider.
+    NotFound,
+    /// The query finished without reaching the required quorum.
+    QuorumFailed,
+    /// The query timed out.
+    Timeout,
+}
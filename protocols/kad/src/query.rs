@@ -24,7 +24,7 @@ use peers::PeersIterState;
 use peers::closest::{ClosestPeersIter, ClosestPeersIterConfig};
 use peers::fixed::FixedPeersIter;
 
-use crate::K_VALUE;
+use crate::{K_VALUE, ALPHA_VALUE};
 use crate::kbucket::{Key, KeyBytes};
 use either::Either;
 use fnv::FnvHashMap;
@@ -103,22 +103,75 @@ impl<TInner> QueryPool<TInner> {
         T: Into<KeyBytes>,
         I: IntoIterator<Item = Key<PeerId>>
     {
-        let cfg = ClosestPeersIterConfig {
-            num_results: self.config.replication_factor.get(),
-            .. ClosestPeersIterConfig::default()
-        };
-        let peer_iter = QueryPeerIter::Closest(ClosestPeersIter::with_config(cfg, target, peers));
+        let peer_iter = self.closest_peers_iter(target, peers);
         self.add(peer_iter, inner)
     }
 
-    fn add(&mut self, peer_iter: QueryPeerIter, inner: TInner) -> QueryId {
+    /// Adds a query to the pool that iterates towards the closest peers to the
+    /// target, using an ID previously obtained from [`QueryPool::next_query_id`].
+    pub fn continue_iter_closest<T, I>(&mut self, id: QueryId, target: T, peers: I, inner: TInner)
+    where
+        T: Into<KeyBytes>,
+        I: IntoIterator<Item = Key<PeerId>>
+    {
+        assert!(!self.queries.contains_key(&id));
+        let peer_iter = self.closest_peers_iter(target, peers);
+        let query = Query::new(id, peer_iter, inner);
+        self.queries.insert(id, query);
+    }
+
+    /// Continues an earlier query with the given ID and statistics by adding
+    /// a query to the pool that contacts a fixed set of peers, thereby
+    /// retaining the ID and accumulating the statistics.
+    pub fn continue_fixed<I>(&mut self, id: QueryId, peers: I, inner: TInner, stats: QueryStats)
+    where
+        I: IntoIterator<Item = Key<PeerId>>
+    {
+        assert!(!self.queries.contains_key(&id));
+        let peers = peers.into_iter().map(|k| k.into_preimage()).collect::<Vec<_>>();
+        let parallelism = self.config.replication_factor.get();
+        let peer_iter = QueryPeerIter::Fixed(FixedPeersIter::new(peers, parallelism));
+        let mut query = Query::new(id, peer_iter, inner);
+        query.stats = stats;
+        query.stats.end = None;
+        self.queries.insert(id, query);
+    }
+
+    /// Allocates a new query ID without adding a query to the pool.
+    ///
+    /// This is useful for operations that complete without ever contacting
+    /// any peers, or that need to know the ID before the query is added
+    /// with [`QueryPool::continue_iter_closest`].
+    pub fn next_query_id(&mut self) -> QueryId {
         let id = QueryId(self.next_id);
         self.next_id = self.next_id.wrapping_add(1);
+        id
+    }
+
+    fn add(&mut self, peer_iter: QueryPeerIter, inner: TInner) -> QueryId {
+        let id = self.next_query_id();
         let query = Query::new(id, peer_iter, inner);
         self.queries.insert(id, query);
         id
     }
 
+    fn closest_peers_iter<T, I>(&self, target: T, peers: I) -> QueryPeerIter
+    where
+        T: Into<KeyBytes>,
+        I: IntoIterator<Item = Key<PeerId>>
+    {
+        let num_results = self.config.replication_factor.get();
+        let parallelism = self.config.parallelism;
+        let cfg = ClosestPeersIterConfig {
+            num_results,
+            parallelism: parallelism.alpha.get(),
+            stalled_parallelism: parallelism.stalled_parallelism(num_results),
+            adaptive_parallelism: parallelism.adaptive,
+            .. ClosestPeersIterConfig::default()
+        };
+        QueryPeerIter::Closest(ClosestPeersIter::with_config(cfg, target, peers))
+    }
+
     /// Returns a reference to a query with the given ID, if it is in the pool.
     pub fn get(&self, id: &QueryId) -> Option<&Query<TInner>> {
         self.queries.get(id)
@@ -137,6 +190,7 @@ impl<TInner> QueryPool<TInner> {
 
         for (&query_id, query) in self.queries.iter_mut() {
             query.started = query.started.or(Some(now));
+            query.stats.start = query.stats.start.or(Some(now));
             match query.next(now) {
                 PeersIterState::Finished => {
                     finished = Some(query_id);
@@ -159,16 +213,19 @@ impl<TInner> QueryPool<TInner> {
 
         if let Some((query_id, peer_id)) = waiting {
             let query = self.queries.get_mut(&query_id).expect("s.a.");
+            query.stats.requests += 1;
             return QueryPoolState::Waiting(Some((query, peer_id)))
         }
 
         if let Some(query_id) = finished {
-            let query = self.queries.remove(&query_id).expect("s.a.");
+            let mut query = self.queries.remove(&query_id).expect("s.a.");
+            query.stats.end = Some(now);
             return QueryPoolState::Finished(query)
         }
 
         if let Some(query_id) = timeout {
-            let query = self.queries.remove(&query_id).expect("s.a.");
+            let mut query = self.queries.remove(&query_id).expect("s.a.");
+            query.stats.end = Some(now);
             return QueryPoolState::Timeout(query)
         }
 
@@ -189,13 +246,61 @@ pub struct QueryId(usize);
 pub struct QueryConfig {
     pub timeout: Duration,
     pub replication_factor: NonZeroUsize,
+    pub parallelism: QueryParallelism,
 }
 
 impl Default for QueryConfig {
     fn default() -> Self {
         QueryConfig {
             timeout: Duration::from_secs(60),
-            replication_factor: NonZeroUsize::new(K_VALUE.get()).expect("K_VALUE > 0")
+            replication_factor: NonZeroUsize::new(K_VALUE.get()).expect("K_VALUE > 0"),
+            parallelism: QueryParallelism::default(),
+        }
+    }
+}
+
+/// The parallelism of an iterative query towards the closest peers to a key.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QueryParallelism {
+    /// The maximum number of requests in flight while the query makes
+    /// progress towards the key, i.e. the `α` parameter of the Kademlia paper.
+    ///
+    /// Defaults to [`ALPHA_VALUE`].
+    pub alpha: NonZeroUsize,
+
+    /// The maximum number of requests in flight once the query stopped
+    /// making progress towards the key, i.e. the `β` parameter.
+    ///
+    /// `None` means up to the replication factor. Defaults to `None`.
+    pub beta: Option<NonZeroUsize>,
+
+    /// Whether to widen the parallelism of the query while peers time out.
+    ///
+    /// If enabled, every request that times out permits one more request in
+    /// flight, up to `beta`, and every successful response takes one such
+    /// additional request away again, down to `alpha`. Defaults to `false`.
+    pub adaptive: bool,
+}
+
+impl QueryParallelism {
+    /// The effective `β` of a query searching for `num_results` peers.
+    ///
+    /// Never less than `alpha`.
+    fn stalled_parallelism(&self, num_results: usize) -> usize {
+        let beta = match self.beta {
+            Some(beta) => beta.get(),
+            None => num_results
+        };
+        usize::max(beta, self.alpha.get())
+    }
+}
+
+impl Default for QueryParallelism {
+    fn default() -> Self {
+        QueryParallelism {
+            alpha: ALPHA_VALUE,
+            beta: None,
+            adaptive: false,
         }
     }
 }
@@ -209,6 +314,11 @@ pub struct Query<TInner> {
     /// The instant when the query started (i.e. began waiting for the first
     /// result from a peer).
     started: Option<Instant>,
+    /// The number of hops from the local node at which the peers of the
+    /// query have been discovered. Peers not in the map are one hop away.
+    hops: FnvHashMap<PeerId, u32>,
+    /// The statistics of the query.
+    stats: QueryStats,
     /// The opaque inner query state.
     pub inner: TInner,
 }
@@ -222,7 +332,14 @@ enum QueryPeerIter {
 impl<TInner> Query<TInner> {
     /// Creates a new query without starting it.
     fn new(id: QueryId, peer_iter: QueryPeerIter, inner: TInner) -> Self {
-        Query { id, inner, peer_iter, started: None }
+        Query {
+            id,
+            inner,
+            peer_iter,
+            started: None,
+            hops: FnvHashMap::default(),
+            stats: QueryStats::default(),
+        }
     }
 
     /// Gets the unique ID of the query.
@@ -230,11 +347,30 @@ impl<TInner> Query<TInner> {
         self.id
     }
 
+    /// Gets the current statistics of the query.
+    pub fn stats(&self) -> &QueryStats {
+        &self.stats
+    }
+
+    /// Changes the parallelism of the query.
+    ///
+    /// Only affects queries iterating towards the closest peers to a key.
+    /// Requests that are already in progress are not affected.
+    pub fn set_parallelism(&mut self, parallelism: QueryParallelism) {
+        if let QueryPeerIter::Closest(iter) = &mut self.peer_iter {
+            let stalled = parallelism.beta.map(NonZeroUsize::get);
+            iter.set_parallelism(parallelism.alpha.get(), stalled, parallelism.adaptive)
+        }
+    }
+
     /// Informs the query that the attempt to contact `peer` failed.
     pub fn on_failure(&mut self, peer: &PeerId) {
-        match &mut self.peer_iter {
+        let updated = match &mut self.peer_iter {
             QueryPeerIter::Closest(iter) => iter.on_failure(peer),
             QueryPeerIter::Fixed(iter) => iter.on_failure(peer)
+        };
+        if updated {
+            self.stats.failure += 1;
         }
     }
 
@@ -245,9 +381,23 @@ impl<TInner> Query<TInner> {
     where
         I: IntoIterator<Item = PeerId>
     {
-        match &mut self.peer_iter {
-            QueryPeerIter::Closest(iter) => iter.on_success(peer, new_peers),
+        let hop = self.hops.get(peer).cloned().unwrap_or(1);
+        let updated = match &mut self.peer_iter {
+            QueryPeerIter::Closest(iter) => {
+                let new_peers = new_peers.into_iter().collect::<Vec<_>>();
+                let updated = iter.on_success(peer, new_peers.iter().cloned());
+                if updated {
+                    for p in new_peers {
+                        self.hops.entry(p).or_insert(hop + 1);
+                    }
+                }
+                updated
+            }
             QueryPeerIter::Fixed(iter) => iter.on_success(peer)
+        };
+        if updated {
+            self.stats.success += 1;
+            self.stats.hops = u32::max(self.stats.hops, hop);
         }
     }
 
@@ -284,7 +434,7 @@ impl<TInner> Query<TInner> {
             QueryPeerIter::Closest(iter) => Either::Left(iter.into_result()),
             QueryPeerIter::Fixed(iter) => Either::Right(iter.into_result())
         };
-        QueryResult { inner: self.inner, peers }
+        QueryResult { inner: self.inner, peers, stats: self.stats }
     }
 }
 
@@ -293,6 +443,66 @@ pub struct QueryResult<TInner, TPeers> {
     /// The opaque inner query state.
     pub inner: TInner,
     /// The successfully contacted peers.
-    pub peers: TPeers
+    pub peers: TPeers,
+    /// The statistics of the query.
+    pub stats: QueryStats,
+}
+
+/// Execution statistics of a query.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct QueryStats {
+    requests: u32,
+    success: u32,
+    failure: u32,
+    hops: u32,
+    start: Option<Instant>,
+    end: Option<Instant>,
+}
+
+impl QueryStats {
+    /// Gets the total number of requests initiated by the query.
+    pub fn num_requests(&self) -> u32 {
+        self.requests
+    }
+
+    /// Gets the number of successful requests.
+    pub fn num_successes(&self) -> u32 {
+        self.success
+    }
+
+    /// Gets the number of failed requests.
+    ///
+    /// Requests that time out but are not answered later count as neither
+    /// successful nor failed.
+    pub fn num_failures(&self) -> u32 {
+        self.failure
+    }
+
+    /// Gets the number of requests without a result, i.e. requests that
+    /// are still pending or that timed out.
+    pub fn num_pending(&self) -> u32 {
+        self.requests - (self.success + self.failure)
+    }
+
+    /// Gets the maximum number of hops from the local node to a peer that
+    /// successfully responded to the query.
+    ///
+    /// The peers initially known to the local node are one hop away, the
+    /// peers they return are two hops away, and so on.
+    pub fn hops(&self) -> u32 {
+        self.hops
+    }
+
+    /// Gets the duration of the query.
+    ///
+    /// If the query has not yet finished, the duration is measured from the
+    /// start of the query to the current instant. `None` if the query has
+    /// not yet started.
+    pub fn duration(&self) -> Option<Duration> {
+        self.start.map(|start| match self.end {
+            Some(end) => end - start,
+            None => Instant::now() - start,
+        })
+    }
 }
 
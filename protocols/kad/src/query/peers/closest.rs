@@ -45,6 +45,13 @@ pub struct ClosestPeersIter {
 
     /// The number of peers for which the iterator is currently waiting for results.
     num_waiting: usize,
+
+    /// The additional parallelism granted on top of `parallelism` while
+    /// iterating, if adaptive parallelism is enabled.
+    ///
+    /// Incremented whenever a peer times out and decremented whenever a
+    /// successful result is delivered, bounded by `stalled_parallelism`.
+    extra_parallelism: usize,
 }
 
 /// Configuration for a `ClosestPeersIter`.
@@ -57,6 +64,23 @@ pub struct ClosestPeersIterConfig {
     /// nodes to a target. Defaults to `ALPHA_VALUE`.
     pub parallelism: usize,
 
+    /// Allowed level of parallelism while the iterator is stalled.
+    ///
+    /// The `β` parameter, i.e. the maximum number of peers that the iterator is
+    /// allowed to wait for in parallel after it stopped making progress towards
+    /// the target. Defaults to `K_VALUE`.
+    pub stalled_parallelism: usize,
+
+    /// Whether the parallelism adapts to timeouts.
+    ///
+    /// If enabled, every peer that times out raises the parallelism used while
+    /// iterating by one, up to `stalled_parallelism`, and every successful result
+    /// lowers it again by one, down to `parallelism`. Lookups on lossy networks
+    /// thus do not have to wait for the timeouts of unresponsive peers, whereas
+    /// lookups among responsive peers use no more than `parallelism`.
+    /// Defaults to `false`.
+    pub adaptive_parallelism: bool,
+
     /// Number of results (closest peers) to search for.
     ///
     /// The number of closest peers for which the iterator must obtain successful results
@@ -76,6 +100,8 @@ impl Default for ClosestPeersIterConfig {
     fn default() -> Self {
         ClosestPeersIterConfig {
             parallelism: ALPHA_VALUE.get(),
+            stalled_parallelism: K_VALUE.get(),
+            adaptive_parallelism: false,
             num_results: K_VALUE.get(),
             peer_timeout: Duration::from_secs(10),
         }
@@ -118,7 +144,27 @@ impl ClosestPeersIter {
             target,
             state,
             closest_peers,
-            num_waiting: 0
+            num_waiting: 0,
+            extra_parallelism: 0,
+        }
+    }
+
+    /// Changes the parallelism of the iterator.
+    ///
+    /// See [`ClosestPeersIterConfig::parallelism`],
+    /// [`ClosestPeersIterConfig::stalled_parallelism`] and
+    /// [`ClosestPeersIterConfig::adaptive_parallelism`]. Requests that are already
+    /// in progress are not affected. A `stalled_parallelism` of `None` permits
+    /// up to `num_results` requests while the iterator is stalled.
+    pub fn set_parallelism(&mut self, parallelism: usize, stalled_parallelism: Option<usize>, adaptive: bool) {
+        let stalled_parallelism = stalled_parallelism.unwrap_or(self.config.num_results);
+        self.config.parallelism = parallelism;
+        self.config.stalled_parallelism = usize::max(stalled_parallelism, parallelism);
+        self.config.adaptive_parallelism = adaptive;
+        if !adaptive {
+            self.extra_parallelism = 0
+        } else {
+            self.extra_parallelism = self.extra_parallelism.min(self.max_extra_parallelism())
         }
     }
 
@@ -136,13 +182,13 @@ impl ClosestPeersIter {
     ///
     /// If the iterator is finished, it is not currently waiting for a
     /// result from `peer`, or a result for `peer` has already been reported,
-    /// calling this function has no effect.
-    pub fn on_success<I>(&mut self, peer: &PeerId, closer_peers: I)
+    /// calling this function has no effect and `false` is returned.
+    pub fn on_success<I>(&mut self, peer: &PeerId, closer_peers: I) -> bool
     where
         I: IntoIterator<Item = PeerId>
     {
         if let State::Finished = self.state {
-            return
+            return false
         }
 
         let key = Key::from(peer.clone());
@@ -150,7 +196,7 @@ impl ClosestPeersIter {
 
         // Mark the peer as succeeded.
         match self.closest_peers.entry(distance) {
-            Entry::Vacant(..) => return,
+            Entry::Vacant(..) => return false,
             Entry::Occupied(mut e) => match e.get().state {
                 PeerState::Waiting(..) => {
                     debug_assert!(self.num_waiting > 0);
@@ -162,10 +208,14 @@ impl ClosestPeersIter {
                 }
                 PeerState::NotContacted
                     | PeerState::Failed
-                    | PeerState::Succeeded => return
+                    | PeerState::Succeeded => return false
             }
         }
 
+        // Responsive peers gradually reduce the additional parallelism
+        // granted for timed out peers.
+        self.extra_parallelism = self.extra_parallelism.saturating_sub(1);
+
         let num_closest = self.closest_peers.len();
         let mut progress = false;
 
@@ -199,7 +249,9 @@ impl ClosestPeersIter {
                     State::Stalled
                 }
             State::Finished => State::Finished
-        }
+        };
+
+        true
     }
 
     /// Callback for informing the iterator about a failed request to a peer
@@ -210,27 +262,29 @@ impl ClosestPeersIter {
     ///
     /// If the iterator is finished, it is not currently waiting for a
     /// result from `peer`, or a result for `peer` has already been reported,
-    /// calling this function has no effect.
-    pub fn on_failure(&mut self, peer: &PeerId) {
+    /// calling this function has no effect and `false` is returned.
+    pub fn on_failure(&mut self, peer: &PeerId) -> bool {
         if let State::Finished = self.state {
-            return
+            return false
         }
 
         let key = Key::from(peer.clone());
         let distance = key.distance(&self.target);
 
         match self.closest_peers.entry(distance) {
-            Entry::Vacant(_) => return,
+            Entry::Vacant(_) => false,
             Entry::Occupied(mut e) => match e.get().state {
                 PeerState::Waiting(_) => {
                     debug_assert!(self.num_waiting > 0);
                     self.num_waiting -= 1;
-                    e.get_mut().state = PeerState::Failed
+                    e.get_mut().state = PeerState::Failed;
+                    true
                 }
                 PeerState::Unresponsive => {
-                    e.get_mut().state = PeerState::Failed
+                    e.get_mut().state = PeerState::Failed;
+                    true
                 }
-                _ => {}
+                _ => false
             }
         }
     }
@@ -271,6 +325,7 @@ impl ClosestPeersIter {
 
         // Check if the iterator is at capacity w.r.t. the allowed parallelism.
         let at_capacity = self.at_capacity();
+        let max_extra_parallelism = self.max_extra_parallelism();
 
         for peer in self.closest_peers.values_mut() {
             match peer.state {
@@ -281,7 +336,13 @@ impl ClosestPeersIter {
                         // their results can still be delivered to the iterator.
                         debug_assert!(self.num_waiting > 0);
                         self.num_waiting -= 1;
-                        peer.state = PeerState::Unresponsive
+                        peer.state = PeerState::Unresponsive;
+                        // With adaptive parallelism, every timeout permits an
+                        // additional request while iterating.
+                        if self.config.adaptive_parallelism {
+                            self.extra_parallelism = (self.extra_parallelism + 1)
+                                .min(max_extra_parallelism);
+                        }
                     }
                     else if at_capacity {
                         // The iterator is still waiting for a result from a peer and is
@@ -363,17 +424,23 @@ impl ClosestPeersIter {
 
     /// Checks if the iterator is at capacity w.r.t. the permitted parallelism.
     ///
-    /// While the iterator is stalled, up to `num_results` parallel requests
-    /// are allowed. This is a slightly more permissive variant of the
-    /// requirement that the initiator "resends the FIND_NODE to all of the
-    /// k closest nodes it has not already queried".
+    /// While the iterator is stalled, up to `stalled_parallelism` (by default
+    /// `num_results`) parallel requests are allowed. This is a slightly more
+    /// permissive variant of the requirement that the initiator "resends the
+    /// FIND_NODE to all of the k closest nodes it has not already queried".
     fn at_capacity(&self) -> bool {
         match self.state {
-            State::Stalled => self.num_waiting >= self.config.num_results,
-            State::Iterating { .. } => self.num_waiting >= self.config.parallelism,
+            State::Stalled => self.num_waiting >= self.config.stalled_parallelism,
+            State::Iterating { .. } =>
+                self.num_waiting >= self.config.parallelism + self.extra_parallelism,
             State::Finished => true
         }
     }
+
+    /// The upper bound of `extra_parallelism`.
+    fn max_extra_parallelism(&self) -> usize {
+        self.config.stalled_parallelism.saturating_sub(self.config.parallelism)
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -467,9 +534,12 @@ mod tests {
     fn random_iter<G: Rng>(g: &mut G) -> ClosestPeersIter {
         let known_closest_peers = random_peers(g.gen_range(1, 60)).map(Key::from);
         let target = Key::from(Into::<Multihash>::into(PeerId::random()));
+        let num_results = g.gen_range(1, 25);
         let config = ClosestPeersIterConfig {
             parallelism: g.gen_range(1, 10),
-            num_results: g.gen_range(1, 25),
+            stalled_parallelism: num_results,
+            adaptive_parallelism: false,
+            num_results,
             peer_timeout: Duration::from_secs(g.gen_range(10, 30)),
         };
         ClosestPeersIter::with_config(config, target, known_closest_peers)
@@ -633,7 +703,7 @@ mod tests {
             match iter.next(now) {
                 PeersIterState::Waiting(Some(p)) => {
                     let peer2 = p.into_owned();
-                    iter.on_success(&peer2, closer.clone())
+                    iter.on_success(&peer2, closer.clone());
                 }
                 PeersIterState::Finished => {}
                 _ => panic!("Unexpectedly iter state."),
@@ -691,4 +761,47 @@ mod tests {
 
         QuickCheck::new().tests(10).quickcheck(prop as fn(_) -> _)
     }
+
+    #[test]
+    fn adaptive_parallelism() {
+        fn num_waiting_after_timeout(adaptive: bool) -> (usize, usize) {
+            let config = ClosestPeersIterConfig {
+                parallelism: 1,
+                stalled_parallelism: 3,
+                adaptive_parallelism: adaptive,
+                .. ClosestPeersIterConfig::default()
+            };
+            let target = Key::from(Into::<Multihash>::into(PeerId::random()));
+            let peers = random_peers(10).map(Key::from);
+            let mut iter = ClosestPeersIter::with_config(config, target, peers);
+            let mut now = Instant::now();
+
+            // Wait for the first peer to time out.
+            match iter.next(now) {
+                PeersIterState::Waiting(Some(_)) => {}
+                s => panic!("Unexpected iterator state: {:?}", s)
+            }
+            assert_eq!(iter.next(now), PeersIterState::WaitingAtCapacity);
+            now += iter.config.peer_timeout;
+
+            // Contact as many peers as permitted.
+            let mut contacted = Vec::new();
+            loop {
+                match iter.next(now) {
+                    PeersIterState::Waiting(Some(p)) => contacted.push(p.into_owned()),
+                    PeersIterState::WaitingAtCapacity if contacted.is_empty() => {}
+                    PeersIterState::WaitingAtCapacity => break,
+                    s => panic!("Unexpected iterator state: {:?}", s)
+                }
+            }
+            let num_waiting = iter.num_waiting();
+
+            // A successful result reduces the parallelism again.
+            iter.on_success(&contacted[0], iter::empty());
+            (num_waiting, iter.extra_parallelism)
+        }
+
+        assert_eq!(num_waiting_after_timeout(false), (1, 0));
+        assert_eq!(num_waiting_after_timeout(true), (2, 0));
+    }
 }
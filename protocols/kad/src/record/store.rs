@@ -62,6 +62,7 @@ pub enum Error {
 pub trait RecordStore<'a> {
     type RecordsIter: Iterator<Item = Cow<'a, Record>>;
     type ProvidedIter: Iterator<Item = Cow<'a, ProviderRecord>>;
+    type ProvidersIter: Iterator<Item = Cow<'a, ProviderRecord>>;
 
     /// Gets a record from the store, given its key.
     fn get(&'a self, k: &Key) -> Option<Cow<Record>>;
@@ -89,6 +90,10 @@ pub trait RecordStore<'a> {
     /// node owning the store is itself the provider.
     fn provided(&'a self) -> Self::ProvidedIter;
 
+    /// Gets an iterator over all stored provider records, whoever the
+    /// provider is.
+    fn provider_records(&'a self) -> Self::ProvidersIter;
+
     /// Removes a provider record from the store.
     fn remove_provider(&'a mut self, k: &Key, p: &PeerId);
 }
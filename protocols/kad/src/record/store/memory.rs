@@ -25,7 +25,7 @@ use libp2p_core::PeerId;
 use smallvec::SmallVec;
 use std::borrow::Cow;
 use std::collections::{hash_map, hash_set, HashMap, HashSet};
-use std::iter;
+use std::{iter, slice};
 
 /// In-memory implementation of a `RecordStore`.
 pub struct MemoryStore {
@@ -106,6 +106,13 @@ impl<'a> RecordStore<'a> for MemoryStore {
         fn(&'a ProviderRecord) -> Cow<'a, ProviderRecord>
     >;
 
+    type ProvidersIter = iter::FlatMap<
+        hash_map::Values<'a, Key, SmallVec<[ProviderRecord; K_VALUE.get()]>>,
+        iter::Map<slice::Iter<'a, ProviderRecord>, fn(&'a ProviderRecord) -> Cow<'a, ProviderRecord>>,
+        fn(&'a SmallVec<[ProviderRecord; K_VALUE.get()]>)
+            -> iter::Map<slice::Iter<'a, ProviderRecord>, fn(&'a ProviderRecord) -> Cow<'a, ProviderRecord>>
+    >;
+
     fn get(&'a self, k: &Key) -> Option<Cow<Record>> {
         self.records.get(k).map(Cow::Borrowed)
     }
@@ -198,6 +205,10 @@ impl<'a> RecordStore<'a> for MemoryStore {
         self.provided.iter().map(Cow::Borrowed)
     }
 
+    fn provider_records(&'a self) -> Self::ProvidersIter {
+        self.providers.values().flat_map(|ps| ps.iter().map(Cow::Borrowed as fn(_) -> _))
+    }
+
     fn remove_provider(&'a mut self, key: &Key, provider: &PeerId) {
         if let hash_map::Entry::Occupied(mut e) = self.providers.entry(key.clone()) {
             let providers = e.get_mut();
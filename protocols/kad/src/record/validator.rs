@@ -0,0 +1,79 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Validation of records exchanged with other peers.
+//!
+//! By default, any record received from a remote peer is accepted. A
+//! [`RecordValidator`] installed with [`Kademlia::set_record_validator`]
+//! lets an application check the records it stores on behalf of other
+//! peers, as well as the records returned by lookups, and choose between
+//! conflicting values for the same key.
+//!
+//! [`Kademlia::set_record_validator`]: crate::Kademlia::set_record_validator
+
+use super::{Key, Record};
+use std::{error, fmt};
+
+/// Checks the correctness of records and selects the best among
+/// conflicting records for the same key.
+pub trait RecordValidator: Send + 'static {
+    /// Checks whether a record received from a remote peer is valid.
+    ///
+    /// Invalid records are neither stored in response to a `PUT_VALUE`
+    /// request nor returned as the result of [`Kademlia::get_record`].
+    ///
+    /// [`Kademlia::get_record`]: crate::Kademlia::get_record
+    fn validate(&mut self, record: &Record) -> Result<(), InvalidRecord>;
+
+    /// Selects the best among the given, valid records for the same key,
+    /// returning its index.
+    ///
+    /// `records` is never empty. The default implementation selects the
+    /// first record.
+    fn select(&mut self, _key: &Key, _records: &[Record]) -> usize {
+        0
+    }
+}
+
+/// The error returned by a [`RecordValidator`] for an invalid record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidRecord {
+    reason: String,
+}
+
+impl InvalidRecord {
+    /// Creates a new error with the given reason.
+    pub fn new(reason: impl Into<String>) -> Self {
+        InvalidRecord { reason: reason.into() }
+    }
+
+    /// Returns the reason why the record is invalid.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for InvalidRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid record: {}", self.reason)
+    }
+}
+
+impl error::Error for InvalidRecord {}
@@ -0,0 +1,143 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use criterion::{Bencher, Criterion, criterion_main, criterion_group};
+use futures::{future, prelude::*};
+use libp2p_core::{identity, upgrade::{self, Negotiated}};
+use libp2p_core::transport::{Transport, ListenerEvent, MemoryTransport, memory::Channel};
+use libp2p_noise::{Keypair, NoiseConfig, NoiseOutput, X25519};
+use std::{alloc::{GlobalAlloc, Layout, System}, pin::Pin, task::Poll};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A global allocator that keeps track of the number of allocated bytes.
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+type Session = NoiseOutput<Negotiated<Channel<Vec<u8>>>>;
+
+/// Establish a noise session over an in-memory connection.
+fn connect(release_idle_buffers: bool) -> (Session, Session) {
+    let config = |keys| NoiseConfig::xx(keys).release_idle_buffers(release_idle_buffers);
+    let server_keys = Keypair::<X25519>::new()
+        .into_authentic(&identity::Keypair::generate_ed25519())
+        .unwrap();
+    let client_keys = Keypair::<X25519>::new()
+        .into_authentic(&identity::Keypair::generate_ed25519())
+        .unwrap();
+
+    futures::executor::block_on(async move {
+        let mut listener = MemoryTransport.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let addr = listener.try_next().await.unwrap().unwrap().into_new_address().unwrap();
+        let server = async {
+            let socket = match listener.try_next().await.unwrap().unwrap() {
+                ListenerEvent::Upgrade { upgrade, .. } => upgrade.await.unwrap(),
+                _ => panic!("unexpected listener event")
+            };
+            upgrade::apply_inbound(socket, config(server_keys)).await.unwrap().1
+        };
+        let client = async {
+            let socket = MemoryTransport.dial(addr).unwrap().await.unwrap();
+            upgrade::apply_outbound(socket, config(client_keys), upgrade::Version::V1)
+                .await
+                .unwrap()
+                .1
+        };
+        future::join(server, client).await
+    })
+}
+
+/// Send the given data from one session to the other.
+fn transfer(from: &mut Session, to: &mut Session, data: &[u8]) {
+    let mut received = vec![0; data.len()];
+    futures::executor::block_on(future::join(
+        async {
+            from.write_all(data).await.unwrap();
+            from.flush().await.unwrap();
+        },
+        async { to.read_exact(&mut received).await.unwrap() }
+    ));
+    assert_eq!(received, data);
+}
+
+/// Poll the session for reading once, as a connection handler waiting
+/// for data from the remote does.
+fn poll_idle(session: &mut Session) {
+    futures::executor::block_on(future::poll_fn(|cx| {
+        let mut buf = [0; 1];
+        assert!(Pin::new(&mut *session).poll_read(cx, &mut buf).is_pending());
+        Poll::Ready(())
+    }))
+}
+
+/// Reports the number of bytes retained by idle sessions after
+/// some data has been exchanged.
+fn idle_memory(_: &mut Criterion) {
+    const CONNECTIONS: usize = 100;
+    for &release in &[false, true] {
+        let before = ALLOCATED.load(Ordering::SeqCst);
+        let sessions = (0 .. CONNECTIONS)
+            .map(|_| {
+                let (mut a, mut b) = connect(release);
+                transfer(&mut a, &mut b, b"ping");
+                transfer(&mut b, &mut a, b"pong");
+                poll_idle(&mut a);
+                poll_idle(&mut b);
+                (a, b)
+            })
+            .collect::<Vec<_>>();
+        let after = ALLOCATED.load(Ordering::SeqCst);
+        println!("idle memory per session (release idle buffers: {}): {} bytes",
+            release, after.saturating_sub(before) / (2 * CONNECTIONS));
+        drop(sessions)
+    }
+}
+
+fn bench_transfer(bench: &mut Bencher, release: bool) {
+    let (mut a, mut b) = connect(release);
+    let data = vec![0x2a; 1024 * 1024];
+    bench.iter(|| transfer(&mut a, &mut b, &data))
+}
+
+fn transfer_1mib(c: &mut Criterion) {
+    c.bench_function("transfer 1 MiB", |b| bench_transfer(b, false));
+    c.bench_function("transfer 1 MiB, releasing idle buffers", |b| bench_transfer(b, true));
+}
+
+criterion_group!(benches, idle_memory, transfer_1mib);
+criterion_main!(benches);
@@ -38,6 +38,16 @@ pub enum NoiseError {
     InvalidPayload(prost::DecodeError),
     /// A signature was required and could not be created.
     SigningError(identity::error::SigningError),
+    /// The handshake did not complete within the configured timeout.
+    HandshakeTimeout,
+    /// The identity of the remote was rejected by the configured
+    /// identity verifier.
+    IdentityRejected,
+    /// The resumption ticket presented by the remote is unknown,
+    /// expired or has already been used.
+    UnknownTicket,
+    /// Only one of the parties pads the frames of the session.
+    PaddingMismatch,
     #[doc(hidden)]
     __Nonexhaustive
 }
@@ -51,6 +61,10 @@ impl fmt::Display for NoiseError {
             NoiseError::InvalidPayload(e) => write!(f, "{}", e),
             NoiseError::AuthenticationFailed => f.write_str("Authentication failed"),
             NoiseError::SigningError(e) => write!(f, "{}", e),
+            NoiseError::HandshakeTimeout => f.write_str("handshake timed out"),
+            NoiseError::IdentityRejected => f.write_str("remote identity rejected"),
+            NoiseError::UnknownTicket => f.write_str("unknown resumption ticket"),
+            NoiseError::PaddingMismatch => f.write_str("padding mismatch"),
             NoiseError::__Nonexhaustive => f.write_str("__Nonexhaustive")
         }
     }
@@ -65,6 +79,10 @@ impl Error for NoiseError {
             NoiseError::AuthenticationFailed => None,
             NoiseError::InvalidPayload(e) => Some(e),
             NoiseError::SigningError(e) => Some(e),
+            NoiseError::HandshakeTimeout => None,
+            NoiseError::IdentityRejected => None,
+            NoiseError::UnknownTicket => None,
+            NoiseError::PaddingMismatch => None,
             NoiseError::__Nonexhaustive => None
         }
     }
@@ -0,0 +1,197 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Events of Noise handshakes and established sessions, e.g. for metrics.
+
+use crate::NoiseError;
+use libp2p_core::upgrade::SecurityStats;
+use std::{fmt, sync::Arc, time::Duration};
+
+/// An event of a Noise handshake or an established session, as passed to
+/// the callback registered with [`NoiseConfig::with_events`](crate::NoiseConfig::with_events).
+///
+/// Every event carries the name of the Noise protocol of the handshake,
+/// e.g. `Noise_XX_25519_ChaChaPoly_SHA256`, and whether the local node
+/// is the initiator of the handshake.
+#[derive(Debug)]
+pub enum NoiseEvent<'a> {
+    /// A handshake has been started.
+    HandshakeStarted {
+        protocol: &'a str,
+        initiator: bool
+    },
+    /// A handshake completed successfully.
+    HandshakeCompleted {
+        protocol: &'a str,
+        initiator: bool,
+        /// The duration of the handshake.
+        duration: Duration
+    },
+    /// A handshake failed.
+    HandshakeFailed {
+        protocol: &'a str,
+        initiator: bool,
+        /// The duration of the handshake until it failed.
+        duration: Duration,
+        /// The error the handshake failed with.
+        error: &'a NoiseError
+    },
+    /// A frame received on an established session could not be decrypted.
+    DecryptionFailed {
+        protocol: &'a str,
+        initiator: bool
+    },
+    /// An established session has been dropped.
+    SessionClosed {
+        protocol: &'a str,
+        initiator: bool,
+        /// The number of plaintext bytes encrypted on the session.
+        bytes_encrypted: u64,
+        /// The number of plaintext bytes decrypted on the session.
+        bytes_decrypted: u64
+    }
+}
+
+/// A callback receiving events.
+type Callback = dyn Fn(&NoiseEvent<'_>) + Send + Sync;
+
+/// The event callback of a session.
+#[derive(Clone)]
+pub(crate) struct Events {
+    callback: Arc<Callback>,
+    protocol: String,
+    initiator: bool
+}
+
+impl Events {
+    pub(crate) fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&NoiseEvent<'_>) + Send + Sync + 'static
+    {
+        Events { callback: Arc::new(callback), protocol: String::new(), initiator: false }
+    }
+
+    /// Create an event callback with the same callback for a new session
+    /// with the given protocol name and role.
+    pub(crate) fn session(&self, protocol: &str, initiator: bool) -> Self {
+        Events { callback: self.callback.clone(), protocol: protocol.to_string(), initiator }
+    }
+
+    pub(crate) fn handshake_started(&self) {
+        (self.callback)(&NoiseEvent::HandshakeStarted {
+            protocol: &self.protocol,
+            initiator: self.initiator
+        })
+    }
+
+    pub(crate) fn handshake_completed(&self, duration: Duration) {
+        (self.callback)(&NoiseEvent::HandshakeCompleted {
+            protocol: &self.protocol,
+            initiator: self.initiator,
+            duration
+        })
+    }
+
+    pub(crate) fn handshake_failed(&self, duration: Duration, error: &NoiseError) {
+        (self.callback)(&NoiseEvent::HandshakeFailed {
+            protocol: &self.protocol,
+            initiator: self.initiator,
+            duration,
+            error
+        })
+    }
+
+    pub(crate) fn decryption_failed(&self) {
+        (self.callback)(&NoiseEvent::DecryptionFailed {
+            protocol: &self.protocol,
+            initiator: self.initiator
+        })
+    }
+
+    pub(crate) fn session_closed(&self, bytes_encrypted: u64, bytes_decrypted: u64) {
+        (self.callback)(&NoiseEvent::SessionClosed {
+            protocol: &self.protocol,
+            initiator: self.initiator,
+            bytes_encrypted,
+            bytes_decrypted
+        })
+    }
+}
+
+impl fmt::Debug for Events {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Events")
+            .field("protocol", &self.protocol)
+            .field("initiator", &self.initiator)
+            .finish()
+    }
+}
+
+/// The statistics of an established session, reported to the event
+/// callback, if any, when the session is dropped.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    pub(crate) events: Option<Events>,
+    pub(crate) bytes_encrypted: u64,
+    pub(crate) bytes_decrypted: u64,
+    /// The number of bytes sent on the underlying stream.
+    pub(crate) bytes_sent: u64,
+    /// The number of bytes received on the underlying stream.
+    pub(crate) bytes_received: u64,
+    pub(crate) frames_sent: u64,
+    pub(crate) frames_received: u64
+}
+
+impl Stats {
+    /// Record a frame sent with the given lengths of the plaintext of the
+    /// established session and the cipher text, excluding the length prefix.
+    pub(crate) fn frame_sent(&mut self, plaintext: usize, ciphertext: usize) {
+        self.bytes_encrypted += plaintext as u64;
+        self.bytes_sent += 2 + ciphertext as u64;
+        self.frames_sent += 1
+    }
+
+    /// Record a frame received with the given length of the cipher text,
+    /// excluding the length prefix.
+    pub(crate) fn frame_received(&mut self, ciphertext: usize) {
+        self.bytes_received += 2 + ciphertext as u64;
+        self.frames_received += 1
+    }
+
+    /// The statistics in the form of [`SecurityStats`].
+    pub(crate) fn security_stats(&self) -> SecurityStats {
+        SecurityStats {
+            plaintext_sent: self.bytes_encrypted,
+            plaintext_received: self.bytes_decrypted,
+            ciphertext_sent: self.bytes_sent,
+            ciphertext_received: self.bytes_received,
+            frames_sent: self.frames_sent,
+            frames_received: self.frames_received
+        }
+    }
+}
+
+impl Drop for Stats {
+    fn drop(&mut self) {
+        if let Some(events) = &self.events {
+            events.session_closed(self.bytes_encrypted, self.bytes_decrypted)
+        }
+    }
+}
@@ -22,43 +22,278 @@
 
 pub mod handshake;
 
+use bytes::Bytes;
+use crate::event::{Events, Stats};
+use crate::protocol::CustomResolver;
+use crate::resumption::Resumption;
 use futures::ready;
 use futures::prelude::*;
+use libp2p_core::{identity, PeerId, upgrade::{SecuredStream, SecurityStats}};
 use log::{debug, trace};
+use rand::Rng;
 use snow;
-use std::{fmt, io, pin::Pin, ops::DerefMut, task::{Context, Poll}};
+use std::{fmt, io, pin::Pin, ops::DerefMut, sync::Arc, task::{Context, Poll}, time::Duration};
 
 const MAX_NOISE_PKG_LEN: usize = 65535;
 const MAX_WRITE_BUF_LEN: usize = 16384;
-const TOTAL_BUFFER_LEN: usize = 2 * MAX_NOISE_PKG_LEN + 3 * MAX_WRITE_BUF_LEN;
+/// The maximum size of the write buffer, i.e. the maximum length of the
+/// plaintext of a single noise message, leaving room for the AEAD tag.
+pub(crate) const MAX_FRAME_LEN: usize = MAX_NOISE_PKG_LEN - 16;
 
-/// A single `Buffer` contains multiple non-overlapping byte buffers.
+/// The byte buffers for reading and writing, each of which contains a
+/// buffer for the plaintext and one for the cipher text.
+///
+/// The buffers are allocated on first use and can be released while
+/// the respective direction of the session is idle.
 struct Buffer {
-    inner: Box<[u8; TOTAL_BUFFER_LEN]>
+    read: Vec<u8>,
+    write: Vec<u8>,
+    /// The buffer for the padded plaintext, if padding is enabled.
+    pad: Vec<u8>,
+    /// The length of the write buffer.
+    write_len: usize,
+    /// The padding applied to the plaintext of frames in transport mode.
+    padding: Padding
 }
 
-/// A mutable borrow of all byte buffers, backed by `Buffer`.
-struct BufferBorrow<'a> {
-    read: &'a mut [u8],
-    read_crypto: &'a mut [u8],
-    write: &'a mut [u8],
-    write_crypto: &'a mut [u8]
+impl Buffer {
+    /// Create a new buffer with a write buffer of the given length.
+    fn new(write_len: usize) -> Self {
+        Buffer {
+            read: Vec::new(),
+            write: Vec::new(),
+            pad: Vec::new(),
+            write_len,
+            padding: Padding::None
+        }
+    }
+
+    /// Borrow the read buffer and the buffer for the decrypted data,
+    /// allocating them if necessary.
+    fn read_mut(&mut self) -> (&mut [u8], &mut [u8]) {
+        if self.read.is_empty() {
+            self.read = vec![0; 2 * MAX_NOISE_PKG_LEN]
+        }
+        self.read.split_at_mut(MAX_NOISE_PKG_LEN)
+    }
+
+    /// Borrow the write buffer and the buffer for the encrypted data,
+    /// allocating them if necessary.
+    ///
+    /// The encrypted write buffer is twice the size of the write buffer,
+    /// but at least twice the default size to accommodate handshake
+    /// messages, including the KEM public key or ciphertext of a hybrid
+    /// key exchange, and at most the maximum noise message size. With padding,
+    /// the encrypted write buffer is always of the maximum noise message size.
+    fn write_mut(&mut self) -> (&mut [u8], &mut [u8]) {
+        if self.write.is_empty() {
+            let crypto_len = if self.padding == Padding::None {
+                std::cmp::min(
+                    2 * std::cmp::max(self.write_len, MAX_WRITE_BUF_LEN),
+                    MAX_NOISE_PKG_LEN)
+            } else {
+                MAX_NOISE_PKG_LEN
+            };
+            self.write = vec![0; self.write_len + crypto_len]
+        }
+        self.write.split_at_mut(self.write_len)
+    }
+
+    /// Encrypt the first `len` bytes of the write buffer into the encrypted
+    /// write buffer, padding the plaintext if the session is in transport mode.
+    fn encrypt(&mut self, session: &mut SnowState, len: usize) -> Result<usize, snow::Error> {
+        self.write_mut();
+        let (write, write_crypto) = self.write.split_at_mut(self.write_len);
+        if self.padding == Padding::None || !session.is_transport() {
+            return session.write_message(&write[.. len], write_crypto)
+        }
+        if self.pad.is_empty() {
+            self.pad = vec![0; MAX_FRAME_LEN]
+        }
+        let padded_len = self.padding.padded_len(len);
+        self.pad[.. 2].copy_from_slice(&(len as u16).to_be_bytes());
+        self.pad[2 .. 2 + len].copy_from_slice(&write[.. len]);
+        for b in &mut self.pad[2 + len .. padded_len] {
+            *b = 0
+        }
+        session.write_message(&self.pad[.. padded_len], write_crypto)
+    }
+
+    /// Set the length of the write buffer and the padding.
+    ///
+    /// The length of the write buffer is reduced as necessary for a frame
+    /// to fit into a single noise message after padding.
+    ///
+    /// Must only be called while the write buffer is not in use.
+    fn set_write_len(&mut self, len: usize, padding: Padding) {
+        let len = std::cmp::min(len, padding.max_data_len());
+        if len != self.write_len || padding != self.padding {
+            self.write = Vec::new();
+            self.pad = Vec::new();
+            self.write_len = len;
+            self.padding = padding
+        }
+    }
+
+    /// Release the read buffers. Must only be called while no data is buffered.
+    fn release_read(&mut self) {
+        self.read = Vec::new()
+    }
+
+    /// Release the write buffers. Must only be called while no data is buffered.
+    fn release_write(&mut self) {
+        self.write = Vec::new();
+        self.pad = Vec::new()
+    }
 }
 
-impl Buffer {
-    /// Create a mutable borrow by splitting the buffer slice.
-    fn borrow_mut(&mut self) -> BufferBorrow<'_> {
-        let (r, w) = self.inner.split_at_mut(2 * MAX_NOISE_PKG_LEN);
-        let (read, read_crypto) = r.split_at_mut(MAX_NOISE_PKG_LEN);
-        let (write, write_crypto) = w.split_at_mut(MAX_WRITE_BUF_LEN);
-        BufferBorrow { read, read_crypto, write, write_crypto }
+/// The padding applied to the plaintext of the frames of an established
+/// session in order to obscure the sizes of the messages exchanged.
+///
+/// With padding, the plaintext of every frame starts with the length of
+/// the data contained in the frame as a 2 byte big-endian integer, followed
+/// by the data and the padding. Both sides of a session must use padding
+/// for the session to work, though not necessarily the same kind, which
+/// is checked during the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    /// Frames are not padded.
+    None,
+    /// Every frame is padded to the given length of the plaintext,
+    /// which must be at least 3. A frame thus carries at most the
+    /// given length minus 2 bytes of data. Lengths beyond the maximum
+    /// plaintext length of a noise message are reduced to it.
+    Fixed(u16),
+    /// Every frame is padded with a random number of bytes up to the
+    /// given bound.
+    Random(u16)
+}
+
+impl Padding {
+    /// The maximum length of the data of a single frame.
+    fn max_data_len(self) -> usize {
+        match self {
+            Padding::None => MAX_FRAME_LEN,
+            Padding::Fixed(n) => std::cmp::min(usize::from(n), MAX_FRAME_LEN) - 2,
+            Padding::Random(_) => MAX_FRAME_LEN - 2
+        }
+    }
+
+    /// The length of the padded plaintext of a frame with the given
+    /// length of data, including the length prefix.
+    fn padded_len(self, len: usize) -> usize {
+        match self {
+            Padding::None => len,
+            Padding::Fixed(n) => std::cmp::min(std::cmp::max(usize::from(n), len + 2), MAX_FRAME_LEN),
+            Padding::Random(n) => {
+                let padding = rand::thread_rng().gen_range(0, usize::from(n) + 1);
+                std::cmp::min(len + 2 + padding, MAX_FRAME_LEN)
+            }
+        }
     }
 }
 
-/// A passthrough enum for the two kinds of state machines in `snow`
+/// Strips the padding from the given decrypted payload of a frame,
+/// returning the length of the data following the length prefix.
+fn unpad(payload: &[u8]) -> Option<usize> {
+    if payload.len() < 2 {
+        return None
+    }
+    let len = usize::from(u16::from_be_bytes([payload[0], payload[1]]));
+    if len + 2 > payload.len() {
+        return None
+    }
+    Some(len)
+}
+
+/// Copy the given message unaltered into the given payload buffer,
+/// returning the length of the payload.
+fn copy_message(message: &[u8], payload: &mut [u8]) -> Result<usize, snow::Error> {
+    if message.len() > payload.len() {
+        return Err(snow::Error::Input)
+    }
+    payload[.. message.len()].copy_from_slice(message);
+    Ok(message.len())
+}
+
+/// A passthrough enum for the kinds of state machines in `snow`.
 pub(crate) enum SnowState {
     Transport(snow::TransportState),
-    Handshake(snow::HandshakeState)
+    Handshake(snow::HandshakeState),
+    /// An `IK` handshake that may fall back to `XX`, see
+    /// [`rt1_fallback_initiator`](handshake::rt1_fallback_initiator).
+    Fallback(Box<Fallback>),
+    /// An established session whose frames are neither encrypted nor
+    /// authenticated, see [`NoiseConfig::dangerous_disable_encryption`](crate::NoiseConfig::dangerous_disable_encryption).
+    Plaintext(snow::TransportState)
+}
+
+/// The handshake message with which the responder of an `IK` handshake
+/// signals a fallback to `XX` to the initiator.
+///
+/// A single byte is shorter than any `IK` handshake message and can thus
+/// never be mistaken for the second message of the `IK` pattern.
+const FALLBACK_SIGNAL: [u8; 1] = [0];
+
+/// A pair of handshake states of which only one is used to complete the
+/// handshake, depending on whether the first handshake message exchanged
+/// could be processed with the `IK` handshake state.
+///
+/// If the responder cannot process the first `IK` message, it discards
+/// the message and answers with [`FALLBACK_SIGNAL`], whereupon the
+/// initiator starts over with the first message of the `XX` pattern. Both
+/// handshake states thus use independent ephemeral keys.
+pub(crate) struct Fallback {
+    /// The primary `IK` handshake state.
+    ik: snow::HandshakeState,
+    /// The `XX` handshake state that is used if the `IK` handshake fails.
+    xx: snow::HandshakeState,
+    /// Whether the handshake fell back to `XX`, once decided.
+    fell_back: Option<bool>,
+    /// Whether the responder has yet to send the [`FALLBACK_SIGNAL`].
+    signal_pending: bool
+}
+
+impl Fallback {
+    pub(crate) fn new(ik: snow::HandshakeState, xx: snow::HandshakeState) -> Self {
+        Fallback { ik, xx, fell_back: None, signal_pending: false }
+    }
+
+    fn session(&mut self) -> &mut snow::HandshakeState {
+        if self.fell_back == Some(true) { &mut self.xx } else { &mut self.ik }
+    }
+
+    fn read_message(&mut self, message: &[u8], payload: &mut [u8]) -> Result<usize, snow::Error> {
+        if self.fell_back.is_some() {
+            return self.session().read_message(message, payload)
+        }
+        if self.ik.is_initiator() && message == FALLBACK_SIGNAL {
+            debug!("IK handshake rejected by the responder, falling back to XX");
+            self.fell_back = Some(true);
+            return Ok(0)
+        }
+        match self.ik.read_message(message, payload) {
+            Ok(n) => {
+                self.fell_back = Some(false);
+                Ok(n)
+            }
+            Err(e) if !self.ik.is_initiator() => {
+                debug!("IK handshake failed ({:?}), falling back to XX", e);
+                self.fell_back = Some(true);
+                self.signal_pending = true;
+                Ok(0)
+            }
+            Err(e) => Err(e)
+        }
+    }
+
+    fn write_message(&mut self, message: &[u8], payload: &mut [u8]) -> Result<usize, snow::Error> {
+        if self.signal_pending {
+            self.signal_pending = false;
+            return copy_message(&FALLBACK_SIGNAL, payload)
+        }
+        self.session().write_message(message, payload)
+    }
 }
 
 impl SnowState {
@@ -66,6 +301,8 @@ impl SnowState {
         match self {
             SnowState::Handshake(session) => session.read_message(message, payload),
             SnowState::Transport(session) => session.read_message(message, payload),
+            SnowState::Fallback(session) => session.read_message(message, payload),
+            SnowState::Plaintext(_) => copy_message(message, payload)
         }
     }
 
@@ -73,6 +310,8 @@ impl SnowState {
         match self {
             SnowState::Handshake(session) => session.write_message(message, payload),
             SnowState::Transport(session) => session.write_message(message, payload),
+            SnowState::Fallback(session) => session.write_message(message, payload),
+            SnowState::Plaintext(_) => copy_message(message, payload)
         }
     }
 
@@ -80,13 +319,71 @@ impl SnowState {
         match self {
             SnowState::Handshake(session) => session.get_remote_static(),
             SnowState::Transport(session) => session.get_remote_static(),
+            SnowState::Fallback(session) => match session.fell_back {
+                Some(true) => session.xx.get_remote_static(),
+                _ => session.ik.get_remote_static()
+            },
+            SnowState::Plaintext(session) => session.get_remote_static()
+        }
+    }
+
+    /// The hash of the handshake, available while the session is in handshake mode.
+    pub fn get_handshake_hash(&self) -> Option<&[u8]> {
+        match self {
+            SnowState::Handshake(session) => Some(session.get_handshake_hash()),
+            SnowState::Transport(_) | SnowState::Plaintext(_) => None,
+            SnowState::Fallback(session) => match session.fell_back {
+                Some(true) => Some(session.xx.get_handshake_hash()),
+                _ => Some(session.ik.get_handshake_hash())
+            }
+        }
+    }
+
+    /// Whether the session is in transport mode, i.e. the handshake is complete.
+    pub fn is_transport(&self) -> bool {
+        matches!(self, SnowState::Transport(_) | SnowState::Plaintext(_))
+    }
+
+    /// Stop encrypting the frames of a session in transport mode.
+    pub fn into_plaintext(self) -> Self {
+        match self {
+            SnowState::Transport(session) => SnowState::Plaintext(session),
+            other => other
+        }
+    }
+
+    /// Rekey the outgoing cipher of a session in transport mode.
+    pub fn rekey_outgoing(&mut self) {
+        if let SnowState::Transport(session) = self {
+            session.rekey_outgoing()
+        }
+    }
+
+    /// Rekey the incoming cipher of a session in transport mode.
+    pub fn rekey_incoming(&mut self) {
+        if let SnowState::Transport(session) = self {
+            session.rekey_incoming()
+        }
+    }
+
+    /// Whether the session is an `IK` handshake that fell back to `XX`.
+    pub fn fell_back(&self) -> bool {
+        match self {
+            SnowState::Fallback(session) => session.fell_back == Some(true),
+            _ => false
         }
     }
 
     pub fn into_transport_mode(self) -> Result<snow::TransportState, snow::Error> {
         match self {
             SnowState::Handshake(session) => session.into_transport_mode(),
-            SnowState::Transport(_) => Err(snow::Error::State(snow::error::StateProblem::HandshakeAlreadyFinished)),
+            SnowState::Transport(_) | SnowState::Plaintext(_) =>
+                Err(snow::Error::State(snow::error::StateProblem::HandshakeAlreadyFinished)),
+            SnowState::Fallback(session) => match session.fell_back {
+                Some(true) => session.xx.into_transport_mode(),
+                Some(false) => session.ik.into_transport_mode(),
+                None => Err(snow::Error::State(snow::error::StateProblem::HandshakeNotFinished))
+            }
         }
     }
 }
@@ -99,7 +396,151 @@ pub struct NoiseOutput<T> {
     session: SnowState,
     buffer: Buffer,
     read_state: ReadState,
-    write_state: WriteState
+    write_state: WriteState,
+    remote_early_data: Option<Vec<u8>>,
+    early_muxer: Option<String>,
+    handshake_hash: Vec<u8>,
+    pub(crate) rekey: Rekey,
+    release_idle_buffers: bool,
+    stats: Stats
+}
+
+/// The configuration of a session, i.e. of the handshake and the
+/// resulting established session.
+#[derive(Debug, Clone)]
+pub(crate) struct OutputConfig {
+    /// The conditions for rekeying the sending direction.
+    pub(crate) rekey: RekeyPolicy,
+    /// The length of the write buffer, i.e. the maximum length of the
+    /// plaintext of a frame.
+    pub(crate) write_buffer_len: usize,
+    /// Whether to release the buffers of a direction of the session
+    /// while it is idle.
+    pub(crate) release_idle_buffers: bool,
+    /// The padding applied to the plaintext of frames.
+    pub(crate) padding: Padding,
+    /// The timeout of the handshake, if any.
+    pub(crate) handshake_timeout: Option<Duration>,
+    /// The verifier of the identity of the remote, if any.
+    pub(crate) verifier: Option<IdentityVerifier>,
+    /// The custom crypto resolver of the session, if any.
+    pub(crate) resolver: Option<CustomResolver>,
+    /// The event callback of the session, if any.
+    pub(crate) events: Option<Events>,
+    /// The resumption configuration of the session, if any.
+    pub(crate) resumption: Option<Resumption>,
+    /// Whether the frames of the established session are sent without
+    /// encryption and authentication.
+    pub(crate) plaintext: bool,
+    /// The key log of the session, if any.
+    #[cfg(feature = "keylog")]
+    pub(crate) keylog: Option<crate::keylog::KeyLog>
+}
+
+/// A callback deciding whether to accept the identity of a remote,
+/// given its peer ID, public identity key and static DH public key.
+type VerifyFn = dyn Fn(&PeerId, &identity::PublicKey, &[u8]) -> bool + Send + Sync;
+
+/// The verifier of the identity of a remote.
+#[derive(Clone)]
+pub(crate) struct IdentityVerifier(Arc<VerifyFn>);
+
+impl IdentityVerifier {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&PeerId, &identity::PublicKey, &[u8]) -> bool + Send + Sync + 'static
+    {
+        IdentityVerifier(Arc::new(f))
+    }
+
+    /// Whether the remote with the given public identity key and
+    /// static DH public key is accepted.
+    pub(crate) fn accepts(&self, id_pk: &identity::PublicKey, dh_pk: &[u8]) -> bool {
+        (self.0)(&id_pk.clone().into_peer_id(), id_pk, dh_pk)
+    }
+}
+
+impl fmt::Debug for IdentityVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IdentityVerifier")
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            rekey: RekeyPolicy::default(),
+            write_buffer_len: MAX_WRITE_BUF_LEN,
+            release_idle_buffers: false,
+            padding: Padding::None,
+            handshake_timeout: None,
+            verifier: None,
+            resolver: None,
+            events: None,
+            resumption: None,
+            plaintext: false,
+            #[cfg(feature = "keylog")]
+            keylog: None
+        }
+    }
+}
+
+/// The conditions for automatically rekeying the sending direction
+/// of an established session.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RekeyPolicy {
+    /// Rekey after this many frames have been sent with the current key.
+    /// Never `Some(0)`, which would demand a rekey before every frame.
+    pub(crate) max_frames: Option<u64>,
+    /// Rekey after this many plaintext bytes have been sent with the current key.
+    /// Never `Some(0)`, which would demand a rekey before every frame.
+    pub(crate) max_bytes: Option<u64>
+}
+
+/// The rekeying state of the sending direction of a session.
+///
+/// A rekey is signaled to the remote with an encrypted frame with an
+/// empty payload, after which the sender rekeys its outgoing cipher.
+/// Upon receiving such a frame, the remote rekeys its incoming cipher
+/// accordingly. A due rekey is performed before the next data frame is
+/// written, such that an idle session does not send any rekey frames.
+///
+/// Since frames with an empty payload carry no meaning in the libp2p
+/// Noise specification, rekeying is only performed if both parties
+/// advertised support for it during the handshake.
+#[derive(Debug, Default)]
+pub(crate) struct Rekey {
+    policy: RekeyPolicy,
+    /// Whether rekeying has been negotiated with the remote.
+    pub(crate) negotiated: bool,
+    /// The number of frames sent with the current key.
+    frames: u64,
+    /// The number of plaintext bytes sent with the current key.
+    bytes: u64,
+    /// Whether a rekey has been explicitly requested.
+    requested: bool
+}
+
+impl Rekey {
+    /// Whether the sending direction is due for a rekey.
+    fn is_due(&self) -> bool {
+        self.negotiated && (self.requested
+            || matches!(self.policy.max_frames, Some(n) if self.frames >= n)
+            || matches!(self.policy.max_bytes, Some(n) if self.bytes >= n))
+    }
+
+    /// Record that a frame with the given plaintext length has been sent.
+    fn sent(&mut self, len: usize) {
+        self.frames += 1;
+        self.bytes += len as u64;
+    }
+
+    /// Reset the state after a rekey.
+    fn reset(&mut self) {
+        self.frames = 0;
+        self.bytes = 0;
+        self.requested = false;
+    }
 }
 
 impl<T> fmt::Debug for NoiseOutput<T> {
@@ -116,9 +557,116 @@ impl<T> NoiseOutput<T> {
         NoiseOutput {
             io,
             session,
-            buffer: Buffer { inner: Box::new([0; TOTAL_BUFFER_LEN]) },
+            buffer: Buffer::new(MAX_WRITE_BUF_LEN),
             read_state: ReadState::Init,
-            write_state: WriteState::Init
+            write_state: WriteState::Init,
+            remote_early_data: None,
+            early_muxer: None,
+            handshake_hash: Vec::new(),
+            rekey: Rekey::default(),
+            release_idle_buffers: false,
+            stats: Stats::default()
+        }
+    }
+
+    /// Apply the configuration of the established session.
+    ///
+    /// Must be called before any data is written to the session.
+    pub(crate) fn configure(&mut self, config: OutputConfig) {
+        debug_assert!(matches!(self.write_state, WriteState::Init));
+        self.rekey.policy = config.rekey;
+        self.buffer.set_write_len(config.write_buffer_len, config.padding);
+        self.release_idle_buffers = config.release_idle_buffers;
+        if self.release_idle_buffers {
+            self.buffer.release_read();
+            self.buffer.release_write()
+        }
+        #[cfg(feature = "keylog")]
+        {
+            if let (Some(keylog), SnowState::Transport(session)) = (&config.keylog, &self.session) {
+                keylog.log(session.is_initiator(), &self.handshake_hash)
+            }
+        }
+        self.stats.events = config.events;
+    }
+
+    /// Rekey the sending direction of the session.
+    ///
+    /// The rekey takes effect with the next write, whereby the remote
+    /// is signaled to rekey its receiving direction accordingly. Rekeying
+    /// limits the amount of data encrypted with the same key and prevents
+    /// a compromised key from revealing earlier traffic.
+    ///
+    /// Returns `false` and has no effect if rekeying has not been negotiated
+    /// with the remote, see [`NoiseOutput::rekey_negotiated`].
+    pub fn rekey(&mut self) -> bool {
+        self.rekey.requested = self.rekey.negotiated;
+        self.rekey.negotiated
+    }
+
+    /// Whether rekeying has been negotiated with the remote, i.e. both
+    /// parties advertised support for it during the handshake, see
+    /// [`NoiseConfig::with_rekeying`](crate::NoiseConfig::with_rekeying).
+    pub fn rekey_negotiated(&self) -> bool {
+        self.rekey.negotiated
+    }
+
+    /// The early data sent by the remote during the handshake, if any.
+    pub fn remote_early_data(&self) -> Option<&[u8]> {
+        self.remote_early_data.as_ref().map(|d| &d[..])
+    }
+
+    /// The stream multiplexer agreed on with the remote during the handshake,
+    /// if any, see [`NoiseConfig::with_stream_muxers`](crate::NoiseConfig::with_stream_muxers).
+    pub fn early_muxer(&self) -> Option<&str> {
+        self.early_muxer.as_deref()
+    }
+
+    /// The static DH public key of the remote, if the remote sent one
+    /// during the handshake.
+    ///
+    /// Together with the [`handshake_hash`](NoiseOutput::handshake_hash),
+    /// this allows for key pinning or out-of-band verification of the remote.
+    pub fn remote_static_dh_key(&self) -> Option<&[u8]> {
+        self.session.get_remote_static()
+    }
+
+    /// The hash of the completed handshake.
+    ///
+    /// The handshake hash uniquely identifies the session and is known
+    /// only to both parties of the handshake. It can therefore be used
+    /// for channel binding, i.e. to bind authentication performed by
+    /// higher-level protocols to this particular session.
+    pub fn handshake_hash(&self) -> &[u8] {
+        &self.handshake_hash
+    }
+
+    /// Stop encrypting the frames of the established session.
+    pub(crate) fn into_plaintext(self) -> Self {
+        NoiseOutput { session: self.session.into_plaintext(), .. self }
+    }
+
+    /// Encrypt the first `len` bytes of the write buffer into a frame
+    /// and prepare for writing it out.
+    fn encrypt_frame(&mut self, len: usize) -> Result<(), io::Error> {
+        match self.buffer.encrypt(&mut self.session, len) {
+            Ok(n) => {
+                trace!("write: cipher text len = {} bytes", n);
+                self.rekey.sent(len);
+                let plaintext = if self.session.is_transport() { len } else { 0 };
+                self.stats.frame_sent(plaintext, n);
+                self.write_state = WriteState::WriteLen {
+                    len: n,
+                    buf: u16::to_be_bytes(n as u16),
+                    off: 0
+                };
+                Ok(())
+            }
+            Err(e) => {
+                debug!("encryption error: {:?}", e);
+                self.write_state = WriteState::EncErr;
+                Err(io::ErrorKind::InvalidData.into())
+            }
         }
     }
 }
@@ -158,50 +706,62 @@ enum WriteState {
     EncErr
 }
 
-impl<T: AsyncRead + Unpin> AsyncRead for NoiseOutput<T> {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &mut [u8],
-    ) -> Poll<Result<usize, std::io::Error>> {
-        let mut this = self.deref_mut();
+/// Frames that only signal a rekey count as overhead, as do the handshake
+/// messages and their payloads.
+impl<T> SecuredStream for NoiseOutput<T> {
+    fn security_stats(&self) -> SecurityStats {
+        self.stats.security_stats()
+    }
 
-        let buffer = this.buffer.borrow_mut();
+    fn early_muxer(&self) -> Option<&[u8]> {
+        self.early_muxer.as_ref().map(|m| m.as_bytes())
+    }
+}
 
+impl<T: AsyncRead + Unpin> NoiseOutput<T> {
+    /// Read and decrypt the next frame, unless the current frame has
+    /// not yet been consumed entirely.
+    ///
+    /// Upon success, the read state is either `CopyData` or `Eof(Ok(()))`.
+    fn poll_read_frame(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
         loop {
-            trace!("read state: {:?}", this.read_state);
-            match this.read_state {
+            trace!("read state: {:?}", self.read_state);
+            match self.read_state {
                 ReadState::Init => {
-                    this.read_state = ReadState::ReadLen { buf: [0, 0], off: 0 };
+                    self.read_state = ReadState::ReadLen { buf: [0, 0], off: 0 };
                 }
                 ReadState::ReadLen { mut buf, mut off } => {
-                    let n = match read_frame_len(&mut this.io, cx, &mut buf, &mut off) {
+                    let n = match read_frame_len(&mut self.io, cx, &mut buf, &mut off) {
                         Poll::Ready(Ok(Some(n))) => n,
                         Poll::Ready(Ok(None)) => {
                             trace!("read: eof");
-                            this.read_state = ReadState::Eof(Ok(()));
-                            return Poll::Ready(Ok(0))
+                            self.read_state = ReadState::Eof(Ok(()));
+                            return Poll::Ready(Ok(()))
                         }
                         Poll::Ready(Err(e)) => {
                             return Poll::Ready(Err(e))
                         }
                         Poll::Pending => {
-                            this.read_state = ReadState::ReadLen { buf, off };
-
+                            self.read_state = ReadState::ReadLen { buf, off };
+                            if off == 0 && self.release_idle_buffers {
+                                self.buffer.release_read()
+                            }
                             return Poll::Pending;
                         }
                     };
                     trace!("read: next frame len = {}", n);
                     if n == 0 {
                         trace!("read: empty frame");
-                        this.read_state = ReadState::Init;
+                        self.read_state = ReadState::Init;
                         continue
                     }
-                    this.read_state = ReadState::ReadData { len: usize::from(n), off: 0 }
+                    self.read_state = ReadState::ReadData { len: usize::from(n), off: 0 }
                 }
                 ReadState::ReadData { len, ref mut off } => {
+                    let padded = self.buffer.padding != Padding::None;
+                    let (read, read_crypto) = self.buffer.read_mut();
                     let n = match ready!(
-                        Pin::new(&mut this.io).poll_read(cx, &mut buffer.read[*off ..len])
+                        Pin::new(&mut self.io).poll_read(cx, &mut read[*off ..len])
                     ) {
                         Ok(n) => n,
                         Err(e) => return Poll::Ready(Err(e)),
@@ -210,39 +770,71 @@ impl<T: AsyncRead + Unpin> AsyncRead for NoiseOutput<T> {
                     trace!("read: read {}/{} bytes", *off + n, len);
                     if n == 0 {
                         trace!("read: eof");
-                        this.read_state = ReadState::Eof(Err(()));
+                        self.read_state = ReadState::Eof(Err(()));
                         return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()))
                     }
 
                     *off += n;
                     if len == *off {
                         trace!("read: decrypting {} bytes", len);
-                        if let Ok(n) = this.session.read_message(
-                            &buffer.read[.. len],
-                            buffer.read_crypto
+                        if let Ok(n) = self.session.read_message(
+                            &read[.. len],
+                            read_crypto
                         ){
                             trace!("read: payload len = {} bytes", n);
-                            this.read_state = ReadState::CopyData { len: n, off: 0 }
+                            self.stats.frame_received(len);
+                            // An empty payload either signals a rekey or carries
+                            // no data at all. Either way, it must not be mistaken
+                            // for the end of the stream by the reader.
+                            if n == 0 && self.session.is_transport() {
+                                if self.rekey.negotiated {
+                                    trace!("read: rekey");
+                                    self.session.rekey_incoming();
+                                } else {
+                                    trace!("read: empty frame");
+                                }
+                                self.read_state = ReadState::Init;
+                                continue
+                            }
+                            if padded && self.session.is_transport() {
+                                match unpad(&read_crypto[.. n]) {
+                                    Some(0) => {
+                                        trace!("read: empty frame");
+                                        self.read_state = ReadState::Init;
+                                        continue
+                                    }
+                                    Some(len) => {
+                                        trace!("read: data len = {} bytes", len);
+                                        self.stats.bytes_decrypted += len as u64;
+                                        self.read_state = ReadState::CopyData { len: len + 2, off: 2 };
+                                        continue
+                                    }
+                                    None => {
+                                        debug!("invalid padding");
+                                        if let Some(events) = &self.stats.events {
+                                            events.decryption_failed()
+                                        }
+                                        self.read_state = ReadState::DecErr;
+                                        return Poll::Ready(Err(io::ErrorKind::InvalidData.into()))
+                                    }
+                                }
+                            }
+                            if self.session.is_transport() {
+                                self.stats.bytes_decrypted += n as u64;
+                            }
+                            self.read_state = ReadState::CopyData { len: n, off: 0 }
                         } else {
                             debug!("decryption error");
-                            this.read_state = ReadState::DecErr;
+                            if let Some(events) = &self.stats.events {
+                                events.decryption_failed()
+                            }
+                            self.read_state = ReadState::DecErr;
                             return Poll::Ready(Err(io::ErrorKind::InvalidData.into()))
                         }
                     }
                 }
-                ReadState::CopyData { len, ref mut off } => {
-                    let n = std::cmp::min(len - *off, buf.len());
-                    buf[.. n].copy_from_slice(&buffer.read_crypto[*off .. *off + n]);
-                    trace!("read: copied {}/{} bytes", *off + n, len);
-                    *off += n;
-                    if len == *off {
-                        this.read_state = ReadState::ReadLen { buf: [0, 0], off: 0 };
-                    }
-                    return Poll::Ready(Ok(n))
-                }
-                ReadState::Eof(Ok(())) => {
-                    trace!("read: eof");
-                    return Poll::Ready(Ok(0))
+                ReadState::CopyData { .. } | ReadState::Eof(Ok(())) => {
+                    return Poll::Ready(Ok(()))
                 }
                 ReadState::Eof(Err(())) => {
                     trace!("read: eof (unexpected)");
@@ -254,6 +846,59 @@ impl<T: AsyncRead + Unpin> AsyncRead for NoiseOutput<T> {
     }
 }
 
+impl<T: AsyncRead + Unpin> AsyncRead for NoiseOutput<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        let this = self.deref_mut();
+        ready!(this.poll_read_frame(cx))?;
+        if let ReadState::CopyData { len, ref mut off } = this.read_state {
+            let (_, read_crypto) = this.buffer.read_mut();
+            let n = std::cmp::min(len - *off, buf.len());
+            buf[.. n].copy_from_slice(&read_crypto[*off .. *off + n]);
+            trace!("read: copied {}/{} bytes", *off + n, len);
+            *off += n;
+            if len == *off {
+                this.read_state = ReadState::ReadLen { buf: [0, 0], off: 0 };
+            }
+            return Poll::Ready(Ok(n))
+        }
+        trace!("read: eof");
+        Poll::Ready(Ok(0))
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncBufRead for NoiseOutput<T> {
+    fn poll_fill_buf(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>
+    ) -> Poll<Result<&[u8], std::io::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_read_frame(cx))?;
+        if let ReadState::CopyData { len, off } = this.read_state {
+            let (_, read_crypto) = this.buffer.read_mut();
+            return Poll::Ready(Ok(&read_crypto[off .. len]))
+        }
+        // Frames with an empty payload are skipped while reading, so an
+        // empty buffer is only ever returned at the end of the stream.
+        trace!("read: eof");
+        Poll::Ready(Ok(&[]))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        let this = self.deref_mut();
+        if let ReadState::CopyData { len, ref mut off } = this.read_state {
+            *off = std::cmp::min(*off + amt, len);
+            trace!("read: consumed {}/{} bytes", *off, len);
+            if len == *off {
+                this.read_state = ReadState::ReadLen { buf: [0, 0], off: 0 };
+            }
+        }
+    }
+}
+
 impl<T: AsyncWrite + Unpin> AsyncWrite for NoiseOutput<T> {
     fn poll_write(
         mut self: Pin<&mut Self>,
@@ -262,36 +907,30 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for NoiseOutput<T> {
     ) -> Poll<Result<usize, std::io::Error>>{
         let mut this = self.deref_mut();
 
-        let buffer = this.buffer.borrow_mut();
-
         loop {
             trace!("write state: {:?}", this.write_state);
             match this.write_state {
                 WriteState::Init => {
+                    if this.session.is_transport() && this.rekey.is_due() {
+                        let (_, write_crypto) = this.buffer.write_mut();
+                        this.write_state = encrypt_rekey(&mut this.session, &mut this.rekey, &mut this.stats, write_crypto);
+                        if let WriteState::EncErr = this.write_state {
+                            return Poll::Ready(Err(io::ErrorKind::InvalidData.into()))
+                        }
+                        continue
+                    }
                     this.write_state = WriteState::BufferData { off: 0 }
                 }
                 WriteState::BufferData { ref mut off } => {
-                    let n = std::cmp::min(MAX_WRITE_BUF_LEN - *off, buf.len());
-                    buffer.write[*off .. *off + n].copy_from_slice(&buf[.. n]);
+                    let (write, _) = this.buffer.write_mut();
+                    let n = std::cmp::min(write.len() - *off, buf.len());
+                    write[*off .. *off + n].copy_from_slice(&buf[.. n]);
                     trace!("write: buffered {} bytes", *off + n);
                     *off += n;
-                    if *off == MAX_WRITE_BUF_LEN {
-                        trace!("write: encrypting {} bytes", *off);
-                        match this.session.write_message(buffer.write, buffer.write_crypto) {
-                            Ok(n) => {
-                                trace!("write: cipher text len = {} bytes", n);
-                                this.write_state = WriteState::WriteLen {
-                                    len: n,
-                                    buf: u16::to_be_bytes(n as u16),
-                                    off: 0
-                                }
-                            }
-                            Err(e) => {
-                                debug!("encryption error: {:?}", e);
-                                this.write_state = WriteState::EncErr;
-                                return Poll::Ready(Err(io::ErrorKind::InvalidData.into()))
-                            }
-                        }
+                    if *off == write.len() {
+                        let len = *off;
+                        trace!("write: encrypting {} bytes", len);
+                        this.encrypt_frame(len)?
                     }
                     return Poll::Ready(Ok(n))
                 }
@@ -316,8 +955,9 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for NoiseOutput<T> {
                     this.write_state = WriteState::WriteData { len, off: 0 }
                 }
                 WriteState::WriteData { len, ref mut off } => {
+                    let (_, write_crypto) = this.buffer.write_mut();
                     let n = match ready!(
-                        Pin::new(&mut this.io).poll_write(cx, &buffer.write_crypto[*off .. len])
+                        Pin::new(&mut this.io).poll_write(cx, &write_crypto[*off .. len])
                     ) {
                         Ok(n) => n,
                         Err(e) => return Poll::Ready(Err(e)),
@@ -349,27 +989,25 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for NoiseOutput<T> {
     ) -> Poll<Result<(), std::io::Error>> {
         let mut this = self.deref_mut();
 
-        let buffer = this.buffer.borrow_mut();
-
         loop {
             match this.write_state {
-                WriteState::Init => return Pin::new(&mut this.io).poll_flush(cx),
+                WriteState::Init => {
+                    ready!(Pin::new(&mut this.io).poll_flush(cx))?;
+                    if this.release_idle_buffers {
+                        this.buffer.release_write()
+                    }
+                    return Poll::Ready(Ok(()))
+                }
                 WriteState::BufferData { off } => {
+                    // In transport mode, an empty payload may signal a rekey,
+                    // hence there is nothing to send if no data is buffered.
+                    if off == 0 && this.session.is_transport() {
+                        this.write_state = WriteState::Init;
+                        continue
+                    }
                     trace!("flush: encrypting {} bytes", off);
-                    match this.session.write_message(&buffer.write[.. off], buffer.write_crypto) {
-                        Ok(n) => {
-                            trace!("flush: cipher text len = {} bytes", n);
-                            this.write_state = WriteState::WriteLen {
-                                len: n,
-                                buf: u16::to_be_bytes(n as u16),
-                                off: 0
-                            }
-                        }
-                        Err(e) => {
-                            debug!("encryption error: {:?}", e);
-                            this.write_state = WriteState::EncErr;
-                            return Poll::Ready(Err(io::ErrorKind::InvalidData.into()))
-                        }
+                    if let Err(e) = this.encrypt_frame(off) {
+                        return Poll::Ready(Err(e))
                     }
                 }
                 WriteState::WriteLen { len, mut buf, mut off } => {
@@ -393,8 +1031,9 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for NoiseOutput<T> {
                     this.write_state = WriteState::WriteData { len, off: 0 }
                 }
                 WriteState::WriteData { len, ref mut off } => {
+                    let (_, write_crypto) = this.buffer.write_mut();
                     let n = match ready!(
-                        Pin::new(&mut this.io).poll_write(cx, &buffer.write_crypto[*off .. len])
+                        Pin::new(&mut this.io).poll_write(cx, &write_crypto[*off .. len])
                     ) {
                         Ok(n) => n,
                         Err(e) => return Poll::Ready(Err(e)),
@@ -429,6 +1068,141 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for NoiseOutput<T> {
     }
 }
 
+/// A message-oriented view of a [`NoiseOutput`], i.e. a `Stream` of the
+/// messages received and a `Sink` of the messages to send on the session,
+/// whereby every message corresponds to the payload of a single frame.
+///
+/// Message boundaries are thereby preserved end to end without any
+/// additional framing. A message must not be larger than the write
+/// buffer of the session, see
+/// [`NoiseConfig::with_write_buffer_len`](crate::NoiseConfig::with_write_buffer_len).
+/// Empty messages are not sent, since a frame with an empty payload
+/// signals a rekey if rekeying has been negotiated.
+#[derive(Debug)]
+pub struct NoiseFramed<T> {
+    io: NoiseOutput<T>
+}
+
+impl<T> NoiseFramed<T> {
+    /// Borrow the underlying session.
+    pub fn get_ref(&self) -> &NoiseOutput<T> {
+        &self.io
+    }
+
+    /// Mutably borrow the underlying session.
+    pub fn get_mut(&mut self) -> &mut NoiseOutput<T> {
+        &mut self.io
+    }
+
+    /// Unwrap the underlying session.
+    pub fn into_inner(self) -> NoiseOutput<T> {
+        self.io
+    }
+}
+
+impl<T> NoiseOutput<T> {
+    /// Turn the session into a [`NoiseFramed`] `Stream` and `Sink` of
+    /// messages.
+    ///
+    /// If a frame has been partially read via `AsyncRead` or `AsyncBufRead`,
+    /// the first message received is the remainder of that frame.
+    pub fn into_framed(self) -> NoiseFramed<T> {
+        NoiseFramed { io: self }
+    }
+}
+
+impl<T> SecuredStream for NoiseFramed<T> {
+    fn security_stats(&self) -> SecurityStats {
+        self.io.security_stats()
+    }
+}
+
+impl<T: AsyncRead + Unpin> Stream for NoiseFramed<T> {
+    type Item = Result<Bytes, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut self.get_mut().io;
+        if let Err(e) = ready!(this.poll_read_frame(cx)) {
+            return Poll::Ready(Some(Err(e)))
+        }
+        if let ReadState::CopyData { len, off } = this.read_state {
+            let (_, read_crypto) = this.buffer.read_mut();
+            let msg = Bytes::copy_from_slice(&read_crypto[off .. len]);
+            trace!("read: message of {} bytes", msg.len());
+            this.read_state = ReadState::ReadLen { buf: [0, 0], off: 0 };
+            return Poll::Ready(Some(Ok(msg)))
+        }
+        trace!("read: eof");
+        Poll::Ready(None)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Sink<Bytes> for NoiseFramed<T> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = &mut self.get_mut().io;
+        loop {
+            match this.write_state {
+                WriteState::Init if this.session.is_transport() && this.rekey.is_due() => {
+                    let (_, write_crypto) = this.buffer.write_mut();
+                    this.write_state = encrypt_rekey(&mut this.session, &mut this.rekey, &mut this.stats, write_crypto);
+                    if let WriteState::EncErr = this.write_state {
+                        return Poll::Ready(Err(io::ErrorKind::InvalidData.into()))
+                    }
+                }
+                WriteState::Init | WriteState::BufferData { off: 0 } => {
+                    return Poll::Ready(Ok(()))
+                }
+                // Write out any pending frame first.
+                _ => ready!(Pin::new(&mut *this).poll_flush(cx))?
+            }
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: Bytes) -> Result<(), Self::Error> {
+        let this = &mut self.get_mut().io;
+        if msg.is_empty() {
+            return Ok(())
+        }
+        let (write, _) = this.buffer.write_mut();
+        if msg.len() > write.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "message too large"))
+        }
+        write[.. msg.len()].copy_from_slice(&msg);
+        trace!("write: encrypting message of {} bytes", msg.len());
+        this.encrypt_frame(msg.len())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().io).poll_close(cx)
+    }
+}
+
+/// Encrypt a frame with an empty payload into the given buffer, signaling
+/// a rekey to the remote, and rekey the outgoing cipher of the session.
+///
+/// Returns the next state for writing out the encrypted frame.
+fn encrypt_rekey(session: &mut SnowState, rekey: &mut Rekey, stats: &mut Stats, buf: &mut [u8]) -> WriteState {
+    trace!("write: rekey");
+    match session.write_message(&[], buf) {
+        Ok(n) => {
+            session.rekey_outgoing();
+            rekey.reset();
+            stats.frame_sent(0, n);
+            WriteState::WriteLen { len: n, buf: u16::to_be_bytes(n as u16), off: 0 }
+        }
+        Err(e) => {
+            debug!("encryption error: {:?}", e);
+            WriteState::EncErr
+        }
+    }
+}
+
 /// Read 2 bytes as frame length from the given source into the given buffer.
 ///
 /// Panics if `off >= 2`.
@@ -494,3 +1268,119 @@ fn write_frame_len<W: AsyncWrite + Unpin>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{Keypair, NoiseConfig, X25519, XX};
+    use futures::executor::block_on;
+    use libp2p_core::transport::{ListenerEvent, MemoryTransport, Transport, memory::Channel};
+    use libp2p_core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+    use super::*;
+
+    type Session = NoiseOutput<Channel<Vec<u8>>>;
+
+    fn config() -> NoiseConfig<XX, X25519> {
+        let id = identity::Keypair::generate_ed25519();
+        NoiseConfig::xx(Keypair::<X25519>::new().into_authentic(&id).unwrap())
+    }
+
+    /// Performs a handshake between a listener and a dialer with the given
+    /// configurations, returning the listener and dialer sessions.
+    fn connect(listener: NoiseConfig<XX, X25519>, dialer: NoiseConfig<XX, X25519>) -> (Session, Session) {
+        let addr: libp2p_core::Multiaddr = format!("/memory/{}", rand::random::<u64>()).parse().unwrap();
+        let mut listener_stream = MemoryTransport.listen_on(addr.clone()).unwrap();
+        let inbound_info = listener.protocol_info().next().unwrap();
+        let outbound_info = dialer.protocol_info().next().unwrap();
+        block_on(async move {
+            let inbound = async {
+                loop {
+                    if let ListenerEvent::Upgrade { upgrade, .. } = listener_stream.next().await.unwrap().unwrap() {
+                        let socket = upgrade.await.unwrap();
+                        return listener.upgrade_inbound(socket, inbound_info).await.unwrap().1
+                    }
+                }
+            };
+            let outbound = async {
+                let socket = MemoryTransport.dial(addr).unwrap().await.unwrap();
+                dialer.upgrade_outbound(socket, outbound_info).await.unwrap().1
+            };
+            future::join(inbound, outbound).await
+        })
+    }
+
+    /// Sends a frame with an empty payload, as other implementations may do.
+    fn send_empty_frame(session: &mut Session) {
+        let mut frame = [0u8; 64];
+        let n = session.session.write_message(&[], &mut frame[2 ..]).unwrap();
+        frame[.. 2].copy_from_slice(&(n as u16).to_be_bytes());
+        block_on(async {
+            session.io.write_all(&frame[.. n + 2]).await.unwrap();
+            session.io.flush().await.unwrap();
+        })
+    }
+
+    #[test]
+    fn empty_frame_without_rekey_negotiation() {
+        // Only the listener supports rekeying, the dialer behaves like an
+        // implementation of the libp2p Noise specification.
+        let (mut listener, mut dialer) = connect(config().with_rekeying(), config());
+        assert!(!listener.rekey_negotiated());
+        assert!(!dialer.rekey_negotiated());
+        assert!(!listener.rekey());
+
+        send_empty_frame(&mut dialer);
+        block_on(async {
+            dialer.write_all(b"hello").await.unwrap();
+            dialer.flush().await.unwrap();
+            // The empty frame is skipped and does not rekey the session.
+            let mut buf = [0u8; 5];
+            listener.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        })
+    }
+
+    #[test]
+    fn empty_frame_with_rekey_negotiation() {
+        let (mut listener, mut dialer) = connect(config().with_rekeying(), config().with_rekeying());
+        assert!(listener.rekey_negotiated());
+        assert!(dialer.rekey_negotiated());
+
+        send_empty_frame(&mut dialer);
+        dialer.session.rekey_outgoing();
+        block_on(async {
+            dialer.write_all(b"hello").await.unwrap();
+            dialer.flush().await.unwrap();
+            // The empty frame rekeys the session.
+            let mut buf = [0u8; 5];
+            listener.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        })
+    }
+
+    #[test]
+    fn zero_rekey_limits_are_disabled() {
+        let (mut listener, mut dialer) = connect(config().with_rekeying(),
+            config().rekey_after_frames(0).rekey_after_bytes(0));
+        assert!(dialer.rekey_negotiated());
+        block_on(async {
+            dialer.write_all(b"hello").await.unwrap();
+            dialer.flush().await.unwrap();
+            let mut buf = [0u8; 5];
+            listener.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        })
+    }
+
+    #[test]
+    fn empty_frame_fill_buf() {
+        let (mut listener, mut dialer) = connect(config(), config());
+
+        send_empty_frame(&mut dialer);
+        block_on(async {
+            dialer.write_all(b"hello").await.unwrap();
+            dialer.flush().await.unwrap();
+            // The empty frame is skipped instead of signaling the end of the stream.
+            assert_eq!(listener.fill_buf().await.unwrap(), b"hello");
+        })
+    }
+}
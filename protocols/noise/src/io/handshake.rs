@@ -19,6 +19,36 @@
 // DEALINGS IN THE SOFTWARE.
 
 //! Noise protocol handshake I/O.
+//!
+//! Every handshake message that identifies the local node to the remote
+//! also carries the application-provided early data given to the handshake,
+//! if any. The early data received from the remote is available from the
+//! resulting [`NoiseOutput`] via [`NoiseOutput::remote_early_data`].
+//!
+//! Likewise, the handshake messages carry the names of the stream multiplexers
+//! supported by the local node, if any, as specified by the libp2p Noise
+//! specification. If both parties send their multiplexers, they agree on the
+//! first multiplexer of the initiator which the responder supports, available
+//! via [`NoiseOutput::early_muxer`].
+//!
+//! > **Note**: Whether early data is encrypted and to what extent the remote
+//! > is authenticated at the time the early data is sent depends on the chosen
+//! > handshake pattern, e.g. the first message of the `IX` pattern is sent in
+//! > plaintext. See [Payload Security Properties] for details.
+//!
+//! With the hybrid key exchange, the first two handshake messages also carry
+//! the ephemeral Kyber768 public key of the initiator and the ciphertext of
+//! the responder, respectively, adding about 1.1 KiB to each. The payload of
+//! a handshake message is limited to the default write buffer size either
+//! way, which leaves ample room for them within a single noise message.
+//!
+//! The handshake payloads are encoded as specified by the [libp2p Noise
+//! specification][libp2p-noise-spec]. Older versions of this crate prefixed
+//! the encoded payload with its length, which is still supported as the
+//! legacy payload format, see [`LegacyConfig`].
+//!
+//! [Payload Security Properties]: http://noiseprotocol.org/noise.html#payload-security-properties
+//! [libp2p-noise-spec]: https://github.com/libp2p/specs/tree/master/noise
 
 mod payload_proto {
     include!(concat!(env!("OUT_DIR"), "/payload.proto.rs"));
@@ -26,22 +56,32 @@ mod payload_proto {
 
 use crate::error::NoiseError;
 use crate::protocol::{Protocol, PublicKey, KeypairIdentity};
-use crate::io::SnowState;
+use crate::io::{SnowState, Fallback, OutputConfig, ReadState};
+use crate::resumption::TICKET_ID_LEN;
 use libp2p_core::identity;
+use log::debug;
 use futures::prelude::*;
 use futures::task;
 use futures::io::AsyncReadExt;
 use prost::Message;
-use std::{pin::Pin, task::Context};
-use super::NoiseOutput;
+use std::{io, pin::Pin, task::Context};
+use super::{NoiseOutput, MAX_WRITE_BUF_LEN};
+use wasm_timer::{Delay, Instant};
 
 /// The identity of the remote established during a handshake.
+///
+/// Every variant carries the hash of the completed handshake, which
+/// uniquely identifies the session and can be used for channel binding,
+/// see [`NoiseOutput::handshake_hash`].
 pub enum RemoteIdentity<C> {
     /// The remote provided no identifying information.
     ///
     /// The identity of the remote is unknown and must be obtained through
     /// a different, out-of-band channel.
-    Unknown,
+    Unknown {
+        /// The hash of the completed handshake.
+        handshake_hash: Vec<u8>
+    },
 
     /// The remote provided a static DH public key.
     ///
@@ -51,7 +91,12 @@ pub enum RemoteIdentity<C> {
     /// > **Note**: To rule out active attacks like a MITM, trust in the public key must
     /// > still be established, e.g. by comparing the key against an expected or
     /// > otherwise known public key.
-    StaticDhKey(PublicKey<C>),
+    StaticDhKey {
+        /// The static DH public key of the remote.
+        dh_key: PublicKey<C>,
+        /// The hash of the completed handshake.
+        handshake_hash: Vec<u8>
+    },
 
     /// The remote provided a public identity key in addition to a static DH
     /// public key and the latter is authentic w.r.t. the former.
@@ -59,7 +104,34 @@ pub enum RemoteIdentity<C> {
     /// > **Note**: To rule out active attacks like a MITM, trust in the public key must
     /// > still be established, e.g. by comparing the key against an expected or
     /// > otherwise known public key.
-    IdentityKey(identity::PublicKey)
+    IdentityKey {
+        /// The public identity key of the remote.
+        id_key: identity::PublicKey,
+        /// The static DH public key of the remote.
+        dh_key: PublicKey<C>,
+        /// The hash of the completed handshake.
+        handshake_hash: Vec<u8>
+    }
+}
+
+impl<C> RemoteIdentity<C> {
+    /// The static DH public key of the remote, if the remote sent one.
+    pub fn static_dh_key(&self) -> Option<&PublicKey<C>> {
+        match self {
+            RemoteIdentity::Unknown { .. } => None,
+            RemoteIdentity::StaticDhKey { dh_key, .. } => Some(dh_key),
+            RemoteIdentity::IdentityKey { dh_key, .. } => Some(dh_key)
+        }
+    }
+
+    /// The hash of the completed handshake.
+    pub fn handshake_hash(&self) -> &[u8] {
+        match self {
+            RemoteIdentity::Unknown { handshake_hash } => handshake_hash,
+            RemoteIdentity::StaticDhKey { handshake_hash, .. } => handshake_hash,
+            RemoteIdentity::IdentityKey { handshake_hash, .. } => handshake_hash
+        }
+    }
 }
 
 /// The options for identity exchange in an authenticated handshake.
@@ -90,6 +162,48 @@ pub enum IdentityExchange {
     None { remote: identity::PublicKey }
 }
 
+/// The content of the handshake payloads provided by the application.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Payload {
+    /// The early data to send to the remote.
+    pub early_data: Vec<u8>,
+    /// The names of the stream multiplexers supported by the local node,
+    /// in order of preference, for the early negotiation of a multiplexer.
+    pub stream_muxers: Vec<String>,
+    /// Whether to advertise support for rekeying to the remote.
+    pub rekey: bool,
+    /// Whether the frames of the session are padded, which the remote
+    /// must agree on.
+    pub padding: bool,
+}
+
+/// The compatibility configuration for the legacy handshake payload
+/// format of older versions of this crate, in which the encoded payload
+/// of a handshake message is prefixed with its length.
+///
+/// By default, the payloads sent are in the format of the libp2p Noise
+/// specification, as understood by other libp2p implementations, while
+/// received payloads in either format are accepted. Once a payload in the
+/// legacy format is received, the remaining payloads sent to the remote
+/// are in the legacy format as well.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LegacyConfig {
+    /// Whether to send payloads in the legacy format right from the start,
+    /// e.g. for remotes that are known to only understand the legacy format.
+    pub send_legacy_handshake: bool,
+    /// Whether to accept payloads in the legacy format.
+    pub recv_legacy_handshake: bool
+}
+
+impl Default for LegacyConfig {
+    fn default() -> Self {
+        LegacyConfig {
+            send_legacy_handshake: false,
+            recv_legacy_handshake: true
+        }
+    }
+}
+
 /// A future performing a Noise handshake pattern.
 pub struct Handshake<T, C>(
     Pin<Box<dyn Future<
@@ -97,6 +211,58 @@ pub struct Handshake<T, C>(
     > + Send>>
 );
 
+impl<T, C> Handshake<T, C> {
+    /// Apply the given configuration to the established session once the
+    /// handshake is complete, subject to the handshake timeout and the
+    /// identity verifier of the configuration and reporting the progress
+    /// of the handshake to the event callback of the configuration, if any.
+    pub(crate) fn configure(self, config: OutputConfig) -> Self
+    where
+        T: 'static,
+        C: AsRef<[u8]> + 'static
+    {
+        let events = config.events.clone();
+        let start = events.as_ref().map(|events| {
+            events.handshake_started();
+            Instant::now()
+        });
+        let handshake = match config.handshake_timeout {
+            None => self.0,
+            Some(timeout) => {
+                let delay = Delay::new(timeout);
+                Box::pin(future::select(self.0, delay).map(|r| match r {
+                    future::Either::Left((result, _)) => result,
+                    future::Either::Right(_) => Err(NoiseError::HandshakeTimeout)
+                }))
+            }
+        };
+        Handshake(Box::pin(handshake.map(move |result| {
+            let result = result.and_then(|(remote, io)| {
+                if let RemoteIdentity::IdentityKey { id_key, dh_key, handshake_hash } = &remote {
+                    if let Some(verifier) = &config.verifier {
+                        if !verifier.accepts(id_key, dh_key.as_ref()) {
+                            return Err(NoiseError::IdentityRejected)
+                        }
+                    }
+                    if let Some(resumption) = &config.resumption {
+                        resumption.issue(id_key, dh_key.as_ref(), handshake_hash)
+                    }
+                }
+                let mut io = if config.plaintext { io.into_plaintext() } else { io };
+                io.configure(config);
+                Ok((remote, io))
+            });
+            if let (Some(events), Some(start)) = (events, start) {
+                match &result {
+                    Ok(_) => events.handshake_completed(start.elapsed()),
+                    Err(e) => events.handshake_failed(start.elapsed(), e)
+                }
+            }
+            result
+        })))
+    }
+}
+
 impl<T, C> Future for Handshake<T, C> {
     type Output = Result<(RemoteIdentity<C>, NoiseOutput<T>), NoiseError>;
 
@@ -126,14 +292,16 @@ pub fn rt1_initiator<T, C>(
     io: T,
     session: Result<snow::HandshakeState, NoiseError>,
     identity: KeypairIdentity,
-    identity_x: IdentityExchange
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: Payload
 ) -> Handshake<T, C>
 where
     T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
     C: Protocol<C> + AsRef<[u8]>
 {
     Handshake(Box::pin(async move {
-        let mut state = State::new(io, session, identity, identity_x)?;
+        let mut state = State::new(io, session.map(SnowState::Handshake), identity, identity_x, legacy, payload)?;
         send_identity(&mut state).await?;
         recv_identity(&mut state).await?;
         state.finish()
@@ -161,13 +329,15 @@ pub fn rt1_responder<T, C>(
     session: Result<snow::HandshakeState, NoiseError>,
     identity: KeypairIdentity,
     identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: Payload
 ) -> Handshake<T, C>
 where
     T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
     C: Protocol<C> + AsRef<[u8]>
 {
     Handshake(Box::pin(async move {
-        let mut state = State::new(io, session, identity, identity_x)?;
+        let mut state = State::new(io, session.map(SnowState::Handshake), identity, identity_x, legacy, payload)?;
         recv_identity(&mut state).await?;
         send_identity(&mut state).await?;
         state.finish()
@@ -196,14 +366,16 @@ pub fn rt15_initiator<T, C>(
     io: T,
     session: Result<snow::HandshakeState, NoiseError>,
     identity: KeypairIdentity,
-    identity_x: IdentityExchange
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: Payload
 ) -> Handshake<T, C>
 where
     T: AsyncWrite + AsyncRead + Unpin + Send + 'static,
     C: Protocol<C> + AsRef<[u8]>
 {
     Handshake(Box::pin(async move {
-        let mut state = State::new(io, session, identity, identity_x)?;
+        let mut state = State::new(io, session.map(SnowState::Handshake), identity, identity_x, legacy, payload)?;
         send_empty(&mut state).await?;
         recv_identity(&mut state).await?;
         send_identity(&mut state).await?;
@@ -233,21 +405,320 @@ pub fn rt15_responder<T, C>(
     io: T,
     session: Result<snow::HandshakeState, NoiseError>,
     identity: KeypairIdentity,
-    identity_x: IdentityExchange
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: Payload
 ) -> Handshake<T, C>
 where
     T: AsyncWrite + AsyncRead + Unpin + Send + 'static,
     C: Protocol<C> + AsRef<[u8]>
 {
     Handshake(Box::pin(async move {
-        let mut state = State::new(io, session, identity, identity_x)?;
+        let mut state = State::new(io, session.map(SnowState::Handshake), identity, identity_x, legacy, payload)?;
+        recv_empty(&mut state).await?;
+        send_identity(&mut state).await?;
+        recv_identity(&mut state).await?;
+        state.finish()
+    }))
+}
+
+/// Creates an authenticated Noise handshake for the initiator of a
+/// single roundtrip (2 message) handshake pattern that falls back to
+/// a 1.5-roundtrip (3 message) handshake pattern if the responder fails
+/// to process the first message.
+///
+/// The `sessions` are expected to be the initiators of an `IK` handshake and
+/// an `XX` handshake, respectively. If the responder rejects the first
+/// message of the `IK` handshake, e.g. because the static DH public key of
+/// the responder known to the initiator is stale, it signals a fallback,
+/// whereupon the `XX` handshake is performed from the start.
+///
+/// Subject to the chosen [`IdentityExchange`], the message sequence is
+/// either that of [`rt1_initiator`], if the responder accepts the
+/// first message, or that of [`rt15_initiator`] after the fallback signal
+/// otherwise.
+///
+/// ```raw
+/// initiator -{id}-> responder
+/// initiator <-{id}- responder
+/// ```
+///
+/// or
+///
+/// ```raw
+/// initiator -{id}-> responder
+/// initiator <-{fallback}- responder
+/// initiator --{}--> responder
+/// initiator <-{id}- responder
+/// initiator -{id}-> responder
+/// ```
+pub fn rt1_fallback_initiator<T, C>(
+    io: T,
+    sessions: Result<(snow::HandshakeState, snow::HandshakeState), NoiseError>,
+    identity: KeypairIdentity,
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: Payload
+) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    Handshake(Box::pin(async move {
+        let session = sessions.map(|(ik, xx)|
+            SnowState::Fallback(Box::new(Fallback::new(ik, xx))));
+        let mut state = State::new(io, session, identity, identity_x, legacy, payload)?;
+        send_identity(&mut state).await?;
+        if recv_identity_or_fallback(&mut state).await? {
+            send_empty(&mut state).await?;
+            recv_identity(&mut state).await?;
+            send_identity(&mut state).await?;
+        }
+        state.finish()
+    }))
+}
+
+/// Creates an authenticated Noise handshake for the responder of a
+/// single roundtrip (2 message) handshake pattern that falls back to
+/// a 1.5-roundtrip (3 message) handshake pattern if the first message
+/// cannot be processed.
+///
+/// The `sessions` are expected to be the responders of an `IK` handshake and
+/// an `XX` handshake, respectively. If the first message cannot be processed
+/// by the `IK` session, it is discarded and a fallback is signaled to the
+/// initiator, followed by the `XX` handshake, whereby the local identity is
+/// always sent to the remote, regardless of the chosen [`IdentityExchange`].
+///
+/// See [`rt1_fallback_initiator`] for the message sequences.
+pub fn rt1_fallback_responder<T, C>(
+    io: T,
+    sessions: Result<(snow::HandshakeState, snow::HandshakeState), NoiseError>,
+    identity: KeypairIdentity,
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: Payload
+) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    Handshake(Box::pin(async move {
+        let session = sessions.map(|(ik, xx)|
+            SnowState::Fallback(Box::new(Fallback::new(ik, xx))));
+        let mut state = State::new(io, session, identity, identity_x, legacy, payload)?;
+        if recv_identity_or_fallback(&mut state).await? {
+            send_empty(&mut state).await?;
+            recv_empty(&mut state).await?;
+            state.send_identity = true;
+            send_identity(&mut state).await?;
+            recv_identity(&mut state).await?;
+        } else {
+            send_identity(&mut state).await?;
+        }
+        state.finish()
+    }))
+}
+
+/// Creates a Noise handshake for the initiator of a single roundtrip
+/// (2 message) handshake pattern in which only the responder identifies
+/// itself, in the second message payload (e.g. `NX`).
+///
+/// The first (unencrypted) message payload is always empty and the local
+/// node remains anonymous to the remote. Hence no early data is sent.
+///
+/// ```raw
+/// initiator --{}--> responder
+/// initiator <-{id}- responder
+/// ```
+pub fn nx_initiator<T, C>(
+    io: T,
+    session: Result<snow::HandshakeState, NoiseError>,
+    identity: KeypairIdentity,
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig
+) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    Handshake(Box::pin(async move {
+        let mut state = State::new(io, session.map(SnowState::Handshake), identity, identity_x, legacy, Payload::default())?;
+        send_empty(&mut state).await?;
+        recv_identity(&mut state).await?;
+        state.finish()
+    }))
+}
+
+/// Creates a Noise handshake for the responder of a single roundtrip
+/// (2 message) handshake pattern in which only the responder identifies
+/// itself, in the second message payload (e.g. `NX`).
+///
+/// ```raw
+/// initiator --{}--> responder
+/// initiator <-{id}- responder
+/// ```
+pub fn nx_responder<T, C>(
+    io: T,
+    session: Result<snow::HandshakeState, NoiseError>,
+    identity: KeypairIdentity,
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: Payload
+) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    Handshake(Box::pin(async move {
+        let mut state = State::new(io, session.map(SnowState::Handshake), identity, identity_x, legacy, payload)?;
         recv_empty(&mut state).await?;
         send_identity(&mut state).await?;
+        state.finish()
+    }))
+}
+
+/// Creates a Noise handshake for the initiator of a 1.5-roundtrip
+/// (3 message) handshake pattern in which only the initiator identifies
+/// itself, in the third message payload (e.g. `XN`).
+///
+/// The first (unencrypted) message payload is always empty and the
+/// second message payload only carries the early data of the remote.
+///
+/// ```raw
+/// initiator --{}--> responder
+/// initiator <-{}--- responder
+/// initiator -{id}-> responder
+/// ```
+pub fn xn_initiator<T, C>(
+    io: T,
+    session: Result<snow::HandshakeState, NoiseError>,
+    identity: KeypairIdentity,
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: Payload
+) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    Handshake(Box::pin(async move {
+        let mut state = State::new(io, session.map(SnowState::Handshake), identity, identity_x, legacy, payload)?;
+        send_empty(&mut state).await?;
+        recv_identity(&mut state).await?;
+        send_identity(&mut state).await?;
+        state.finish()
+    }))
+}
+
+/// Creates a Noise handshake for the responder of a 1.5-roundtrip
+/// (3 message) handshake pattern in which only the initiator identifies
+/// itself, in the third message payload (e.g. `XN`).
+///
+/// The local node remains anonymous to the remote, i.e. the second message
+/// payload only carries the local early data.
+///
+/// ```raw
+/// initiator --{}--> responder
+/// initiator <-{}--- responder
+/// initiator -{id}-> responder
+/// ```
+pub fn xn_responder<T, C>(
+    io: T,
+    session: Result<snow::HandshakeState, NoiseError>,
+    identity: KeypairIdentity,
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: Payload
+) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    Handshake(Box::pin(async move {
+        let mut state = State::new(io, session.map(SnowState::Handshake), identity, identity_x, legacy, payload)?;
+        recv_empty(&mut state).await?;
+        send_early_data(&mut state).await?;
+        recv_identity(&mut state).await?;
+        state.finish()
+    }))
+}
+
+/// Creates a Noise handshake for the initiator of a single roundtrip
+/// (2 message) handshake pattern resuming an earlier session, in which
+/// the static keys of both parties are known (e.g. `KK`).
+///
+/// The ID of the resumption ticket is sent in plaintext ahead of the
+/// first message, allowing the responder to look up the ticket and
+/// thereby the static DH public key and public identity key of the
+/// initiator.
+///
+/// ```raw
+/// initiator -[ticket id]{id}-> responder
+/// initiator <-{id}------------ responder
+/// ```
+pub fn resume_initiator<T, C>(
+    io: T,
+    session: Result<snow::HandshakeState, NoiseError>,
+    ticket_id: [u8; TICKET_ID_LEN],
+    identity: KeypairIdentity,
+    remote: identity::PublicKey,
+    legacy: LegacyConfig,
+    payload: Payload
+) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    Handshake(Box::pin(async move {
+        let session = session?;
+        let mut io = io;
+        // The ticket ID is flushed together with the first message.
+        io.write_all(&ticket_id).await?;
+        let mut state = State::new(io, Ok(SnowState::Handshake(session)), identity,
+            IdentityExchange::None { remote }, legacy, payload)?;
+        send_identity(&mut state).await?;
         recv_identity(&mut state).await?;
         state.finish()
     }))
 }
 
+/// Creates a Noise handshake for the responder of a single roundtrip
+/// (2 message) handshake pattern resuming an earlier session, see
+/// [`resume_initiator`].
+///
+/// The session is created by `lookup` from the received ticket ID,
+/// along with the known public identity key of the initiator.
+///
+/// ```raw
+/// initiator -[ticket id]{id}-> responder
+/// initiator <-{id}------------ responder
+/// ```
+pub fn resume_responder<T, C, F>(
+    io: T,
+    lookup: F,
+    identity: KeypairIdentity,
+    legacy: LegacyConfig,
+    payload: Payload
+) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>,
+    F: FnOnce(&[u8; TICKET_ID_LEN]) -> Result<(snow::HandshakeState, identity::PublicKey), NoiseError>
+        + Send + 'static
+{
+    Handshake(Box::pin(async move {
+        let mut io = io;
+        let mut ticket_id = [0u8; TICKET_ID_LEN];
+        io.read_exact(&mut ticket_id).await?;
+        let (session, remote) = lookup(&ticket_id)?;
+        let mut state = State::new(io, Ok(SnowState::Handshake(session)), identity,
+            IdentityExchange::None { remote }, legacy, payload)?;
+        recv_identity(&mut state).await?;
+        send_identity(&mut state).await?;
+        state.finish()
+    }))
+}
+
 //////////////////////////////////////////////////////////////////////////////
 // Internal
 
@@ -264,6 +735,20 @@ struct State<T> {
     id_remote_pubkey: Option<identity::PublicKey>,
     /// Whether to send the public identity key of the local node to the remote.
     send_identity: bool,
+    /// The payload to send to the remote with the local identity.
+    payload: Payload,
+    /// The received early data of the remote, if any.
+    remote_early_data: Option<Vec<u8>>,
+    /// The stream multiplexers supported by the remote, if received.
+    remote_stream_muxers: Vec<String>,
+    /// Whether the stream multiplexers supported by the local node have been sent.
+    sent_stream_muxers: bool,
+    /// Whether the remote advertised support for rekeying.
+    remote_rekey: bool,
+    /// Whether support for rekeying has been advertised to the remote.
+    sent_rekey: bool,
+    /// The compatibility configuration for the legacy payload format.
+    legacy: LegacyConfig,
 }
 
 impl<T> State<T> {
@@ -274,9 +759,11 @@ impl<T> State<T> {
     /// Noise handshake pattern.
     fn new(
         io: T,
-        session: Result<snow::HandshakeState, NoiseError>,
+        session: Result<SnowState, NoiseError>,
         identity: KeypairIdentity,
-        identity_x: IdentityExchange
+        identity_x: IdentityExchange,
+        legacy: LegacyConfig,
+        payload: Payload
     ) -> Result<Self, NoiseError> {
         let (id_remote_pubkey, send_identity) = match identity_x {
             IdentityExchange::Mutual => (None, true),
@@ -287,10 +774,17 @@ impl<T> State<T> {
         session.map(|s|
             State {
                 identity,
-                io: NoiseOutput::new(io, SnowState::Handshake(s)),
+                io: NoiseOutput::new(io, s),
                 dh_remote_pubkey_sig: None,
                 id_remote_pubkey,
-                send_identity
+                send_identity,
+                payload,
+                remote_early_data: None,
+                remote_stream_muxers: Vec::new(),
+                sent_stream_muxers: false,
+                remote_rekey: false,
+                sent_rekey: false,
+                legacy
             }
         )
     }
@@ -300,7 +794,7 @@ impl<T> State<T>
 {
     /// Finish a handshake, yielding the established remote identity and the
     /// [`NoiseOutput`] for communicating on the encrypted channel.
-    fn finish<C>(self) -> Result<(RemoteIdentity<C>, NoiseOutput<T>), NoiseError>
+    fn finish<C>(mut self) -> Result<(RemoteIdentity<C>, NoiseOutput<T>), NoiseError>
     where
         C: Protocol<C> + AsRef<[u8]>
     {
@@ -311,21 +805,53 @@ impl<T> State<T>
                 Ok(dh_pk) => Some(dh_pk)
             }
         };
+        // Rekeying is only enabled if both parties have received the
+        // support for rekeying of the other.
+        self.io.rekey.negotiated = self.sent_rekey && self.remote_rekey;
+        let handshake_hash = self.io.session.get_handshake_hash()
+            .map(|h| h.to_vec())
+            .unwrap_or_default();
         match self.io.session.into_transport_mode() {
             Err(e) => Err(e.into()),
             Ok(s) => {
                 let remote = match (self.id_remote_pubkey, dh_remote_pubkey) {
-                    (_, None) => RemoteIdentity::Unknown,
-                    (None, Some(dh_pk)) => RemoteIdentity::StaticDhKey(dh_pk),
+                    (_, None) => RemoteIdentity::Unknown {
+                        handshake_hash: handshake_hash.clone()
+                    },
+                    (None, Some(dh_pk)) => RemoteIdentity::StaticDhKey {
+                        dh_key: dh_pk,
+                        handshake_hash: handshake_hash.clone()
+                    },
                     (Some(id_pk), Some(dh_pk)) => {
                         if C::verify(&id_pk, &dh_pk, &self.dh_remote_pubkey_sig) {
-                            RemoteIdentity::IdentityKey(id_pk)
+                            RemoteIdentity::IdentityKey {
+                                id_key: id_pk,
+                                dh_key: dh_pk,
+                                handshake_hash: handshake_hash.clone()
+                            }
                         } else {
                             return Err(NoiseError::InvalidKey)
                         }
                     }
                 };
-                Ok((remote, NoiseOutput { session: SnowState::Transport(s), .. self.io }))
+                // Both parties agree on a multiplexer only if both have received
+                // the multiplexers of the other.
+                let early_muxer = if self.sent_stream_muxers {
+                    let local = &self.payload.stream_muxers;
+                    let remote = &self.remote_stream_muxers;
+                    let (initiator, responder) = if s.is_initiator() { (local, remote) } else { (remote, local) };
+                    initiator.iter().find(|m| responder.contains(m)).cloned()
+                } else {
+                    None
+                };
+                let io = NoiseOutput {
+                    session: SnowState::Transport(s),
+                    remote_early_data: self.remote_early_data,
+                    early_muxer,
+                    handshake_hash,
+                    .. self.io
+                };
+                Ok((remote, io))
             }
         }
     }
@@ -353,22 +879,58 @@ where
     Ok(())
 }
 
+/// A future for receiving a Noise handshake message, yielding its payload.
+async fn recv<T>(state: &mut State<T>) -> Result<Vec<u8>, NoiseError>
+where
+    T: AsyncRead + Unpin,
+{
+    let msg = future::poll_fn(|cx| {
+        Pin::new(&mut state.io).poll_fill_buf(cx).map_ok(|buf| buf.to_vec())
+    }).await?;
+    if msg.is_empty() {
+        if let ReadState::Eof(_) = state.io.read_state {
+            return Err(NoiseError::Io(io::ErrorKind::UnexpectedEof.into()))
+        }
+    }
+    Pin::new(&mut state.io).consume(msg.len());
+    Ok(msg)
+}
+
 /// A future for receiving a Noise handshake message with a payload
 /// identifying the remote.
 async fn recv_identity<T>(state: &mut State<T>) -> Result<(), NoiseError>
 where
     T: AsyncRead + Unpin,
 {
-    let mut len_buf = [0,0];
-    state.io.read_exact(&mut len_buf).await?;
-    let len = u16::from_be_bytes(len_buf) as usize;
+    let msg = recv(state).await?;
+    recv_identity_payload(state, &msg)
+}
 
-    let mut payload_buf = vec![0; len];
-    state.io.read_exact(&mut payload_buf).await?;
-    let pb = payload_proto::Identity::decode(&payload_buf[..])?;
+/// A future for receiving the first Noise handshake message of a handshake
+/// that may fall back to a different pattern, see [`rt1_fallback_initiator`]
+/// and [`rt1_fallback_responder`].
+///
+/// Returns `true` if the handshake fell back, in which case the message
+/// payload is discarded, otherwise the payload is expected to identify
+/// the remote.
+async fn recv_identity_or_fallback<T>(state: &mut State<T>) -> Result<bool, NoiseError>
+where
+    T: AsyncRead + Unpin,
+{
+    let msg = recv(state).await?;
+    if state.io.session.fell_back() {
+        return Ok(true)
+    }
+    recv_identity_payload(state, &msg)?;
+    Ok(false)
+}
 
-    if !pb.pubkey.is_empty() {
-        let pk = identity::PublicKey::from_protobuf_encoding(&pb.pubkey)
+/// Process a received Noise handshake message payload identifying the remote.
+fn recv_identity_payload<T>(state: &mut State<T>, msg: &[u8]) -> Result<(), NoiseError> {
+    let pb = decode_payload(state, msg)?;
+
+    if !pb.identity_key.is_empty() {
+        let pk = identity::PublicKey::from_protobuf_encoding(&pb.identity_key)
             .map_err(|_| NoiseError::InvalidKey)?;
         if let Some(ref k) = state.id_remote_pubkey {
             if k != &pk {
@@ -377,29 +939,103 @@ where
         }
         state.id_remote_pubkey = Some(pk);
     }
-    if !pb.signature.is_empty() {
-        state.dh_remote_pubkey_sig = Some(pb.signature);
+    if !pb.identity_sig.is_empty() {
+        state.dh_remote_pubkey_sig = Some(pb.identity_sig);
+    }
+    if !pb.data.is_empty() {
+        state.remote_early_data = Some(pb.data);
+    }
+    let mut remote_padding = false;
+    if let Some(extensions) = pb.extensions {
+        state.remote_stream_muxers = extensions.stream_muxers;
+        state.remote_rekey |= extensions.rekey;
+        remote_padding = extensions.padding;
+    }
+    // Either both parties pad the frames of the session or neither does.
+    if remote_padding != state.payload.padding {
+        return Err(NoiseError::PaddingMismatch)
     }
 
     Ok(())
 }
 
+/// Decode a received Noise handshake message payload, in the legacy
+/// format if enabled and applicable, and in the format of the
+/// specification otherwise.
+fn decode_payload<T>(state: &mut State<T>, msg: &[u8])
+    -> Result<payload_proto::NoiseHandshakePayload, NoiseError>
+{
+    if state.legacy.recv_legacy_handshake && msg.len() >= 2 {
+        // A payload in the legacy format is prefixed with its length.
+        let len = u16::from_be_bytes([msg[0], msg[1]]) as usize;
+        if len == msg.len() - 2 {
+            if let Ok(pb) = payload_proto::NoiseHandshakePayload::decode(&msg[2 ..]) {
+                debug!("Received a handshake payload in the legacy format.");
+                state.legacy.send_legacy_handshake = true;
+                return Ok(pb)
+            }
+        }
+    }
+    Ok(payload_proto::NoiseHandshakePayload::decode(msg)?)
+}
+
 /// Send a Noise handshake message with a payload identifying the local node to the remote.
 async fn send_identity<T>(state: &mut State<T>) -> Result<(), NoiseError>
 where
     T: AsyncWrite + Unpin,
 {
-    let mut pb = payload_proto::Identity::default();
+    let mut pb = payload_proto::NoiseHandshakePayload::default();
     if state.send_identity {
-        pb.pubkey = state.identity.public.clone().into_protobuf_encoding()
+        pb.identity_key = state.identity.public.clone().into_protobuf_encoding()
     }
     if let Some(ref sig) = state.identity.signature {
-        pb.signature = sig.clone()
+        pb.identity_sig = sig.clone()
+    }
+    send_payload(state, pb).await
+}
+
+/// Send a Noise handshake message with a payload that only carries the
+/// early data of the local node, without identifying the local node.
+async fn send_early_data<T>(state: &mut State<T>) -> Result<(), NoiseError>
+where
+    T: AsyncWrite + Unpin,
+{
+    send_payload(state, payload_proto::NoiseHandshakePayload::default()).await
+}
+
+/// Send a Noise handshake message with the given payload, the early data
+/// and the stream multiplexers of the local node.
+async fn send_payload<T>(state: &mut State<T>, mut pb: payload_proto::NoiseHandshakePayload)
+    -> Result<(), NoiseError>
+where
+    T: AsyncWrite + Unpin,
+{
+    pb.data = state.payload.early_data.clone();
+    if !state.payload.stream_muxers.is_empty() || state.payload.rekey || state.payload.padding {
+        pb.extensions = Some(payload_proto::NoiseExtensions {
+            stream_muxers: state.payload.stream_muxers.clone(),
+            rekey: state.payload.rekey,
+            padding: state.payload.padding
+        });
+        state.sent_stream_muxers = !state.payload.stream_muxers.is_empty();
+        state.sent_rekey = state.payload.rekey;
+    }
+    let len = pb.encoded_len();
+    let mut buf = Vec::with_capacity(len + 2);
+    if state.legacy.send_legacy_handshake {
+        buf.extend_from_slice(&(len as u16).to_be_bytes())
     }
-    let mut buf = Vec::with_capacity(pb.encoded_len());
     pb.encode(&mut buf).expect("Vec<u8> provides capacity as needed");
-    let len = (buf.len() as u16).to_be_bytes();
-    state.io.write_all(&len).await?;
+    // The payload must fit into a single handshake message, together with
+    // the DH public keys and, with the hybrid key exchange, the KEM public
+    // key or ciphertext, which the encrypted write buffer accommodates.
+    if len + 2 > MAX_WRITE_BUF_LEN {
+        return Err(NoiseError::Io(io::Error::new(io::ErrorKind::InvalidInput,
+            "handshake payload too large")))
+    }
+    if buf.is_empty() {
+        return send_empty(state).await
+    }
     state.io.write_all(&buf).await?;
     state.io.flush().await?;
     Ok(())
@@ -0,0 +1,97 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Export of session secrets for debugging, e.g. to decrypt captured
+//! traffic in Wireshark.
+//!
+//! > **Note**: Only available with the `keylog` feature, which must never
+//! > be enabled in production builds.
+
+use crate::protocol::CapturedKeys;
+use std::{fmt, sync::Arc};
+
+/// The secrets of an established session, as passed to the callback
+/// registered with [`NoiseConfig::with_key_log`](crate::NoiseConfig::with_key_log).
+#[derive(Debug)]
+pub struct SessionKeys<'a> {
+    /// Whether the local node is the initiator of the handshake.
+    pub initiator: bool,
+    /// The handshake hash of the session.
+    pub handshake_hash: &'a [u8],
+    /// The cipher key for traffic from the initiator to the responder.
+    pub initiator_key: &'a [u8],
+    /// The cipher key for traffic from the responder to the initiator.
+    pub responder_key: &'a [u8],
+}
+
+/// A callback receiving the secrets of established sessions.
+type Callback = dyn Fn(&SessionKeys<'_>) + Send + Sync;
+
+/// The key log of a session, capturing the cipher keys of the session
+/// and passing them to a callback once the session is established.
+///
+/// Clones share the captured keys. Use [`KeyLog::session`] to obtain
+/// a key log for a new session.
+#[derive(Clone)]
+pub(crate) struct KeyLog {
+    callback: Arc<Callback>,
+    keys: CapturedKeys
+}
+
+impl KeyLog {
+    pub(crate) fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&SessionKeys<'_>) + Send + Sync + 'static
+    {
+        KeyLog { callback: Arc::new(callback), keys: CapturedKeys::default() }
+    }
+
+    /// Create a key log with the same callback for a new session.
+    pub(crate) fn session(&self) -> Self {
+        KeyLog { callback: self.callback.clone(), keys: CapturedKeys::default() }
+    }
+
+    /// The keys captured for the session.
+    pub(crate) fn keys(&self) -> &CapturedKeys {
+        &self.keys
+    }
+
+    /// Pass the captured keys of the established session with the given
+    /// handshake hash to the callback.
+    pub(crate) fn log(&self, initiator: bool, handshake_hash: &[u8]) {
+        let logged = self.keys.with(|initiator_key, responder_key| {
+            (self.callback)(&SessionKeys {
+                initiator,
+                handshake_hash,
+                initiator_key,
+                responder_key
+            })
+        });
+        if logged.is_none() {
+            log::warn!("No session keys captured for the key log.")
+        }
+    }
+}
+
+impl fmt::Debug for KeyLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("KeyLog")
+    }
+}
@@ -24,8 +24,15 @@
 //! >           both on the API and the wire protocol.
 //!
 //! This crate provides `libp2p_core::InboundUpgrade` and `libp2p_core::OutboundUpgrade`
-//! implementations for various noise handshake patterns (currently `IK`, `IX`, and `XX`)
-//! over a particular choice of Diffie–Hellman key agreement (currently only X25519).
+//! implementations for various noise handshake patterns (currently `IK`, `IX`, and `XX`,
+//! as well as `IK` with a fallback to `XX`, and the resumption of earlier sessions)
+//! over a particular choice of Diffie–Hellman key agreement (currently only X25519,
+//! optionally combined with the Kyber768 KEM with the `hybrid-pq` feature).
+//!
+//! The `XX` handshake over [`X25519Spec`] keys is compliant with the
+//! [libp2p Noise specification][libp2p-noise-spec] and thus interoperates
+//! with other libp2p implementations, whereas [`X25519`] keys retain the
+//! protocol names and signatures of older versions of this crate.
 //!
 //! All upgrades produce as output a pair, consisting of the remote's static public key
 //! and a `NoiseOutput` which represents the established cryptographic session with the
@@ -50,29 +57,56 @@
 //! ```
 //!
 //! [noise]: http://noiseprotocol.org/
+//! [libp2p-noise-spec]: https://github.com/libp2p/specs/tree/master/noise
 
 mod error;
+mod event;
 mod io;
+#[cfg(feature = "keylog")]
+mod keylog;
 mod protocol;
+mod resumption;
 
 pub use error::NoiseError;
-pub use io::NoiseOutput;
+pub use event::NoiseEvent;
+pub use io::{NoiseFramed, NoiseOutput, Padding};
+use event::Events;
+use io::{IdentityVerifier, OutputConfig, MAX_FRAME_LEN};
 pub use io::handshake;
-pub use io::handshake::{Handshake, RemoteIdentity, IdentityExchange};
-pub use protocol::{Keypair, AuthenticKeypair, KeypairIdentity, PublicKey, SecretKey};
-pub use protocol::{Protocol, ProtocolParams, x25519::X25519, IX, IK, XX};
+pub use io::handshake::{Handshake, RemoteIdentity, IdentityExchange, LegacyConfig, Payload};
+pub use protocol::{Keypair, AuthenticKeypair, CryptoBackend, DhKeypair, KeypairIdentity, PublicKey, SecretKey};
+pub use libp2p_core::psk::PreSharedKey;
+#[cfg(feature = "keylog")]
+pub use keylog::SessionKeys;
+#[cfg(feature = "keylog")]
+use keylog::KeyLog;
+pub use protocol::{Protocol, ProtocolParams, Cipher, Hash, x25519::X25519, x25519_spec::X25519Spec, IX, IK, XX, IKXX, NX, XN, KK, Resume};
+use protocol::{CustomResolver, Resolver};
+pub use resumption::{ResumptionCache, Ticket};
+use resumption::Resumption;
 
 use futures::prelude::*;
 use libp2p_core::{identity, PeerId, UpgradeInfo, InboundUpgrade, OutboundUpgrade};
-use std::pin::Pin;
+use std::{pin::Pin, time::Duration};
 use zeroize::Zeroize;
 
+/// Appended to the prologue of handshakes of sessions without encryption,
+/// such that the handshake fails unless both parties disable encryption.
+const PLAINTEXT_PROLOGUE: &[u8] = b"noise-libp2p-plaintext-transport";
+
 /// The protocol upgrade configuration.
 #[derive(Clone)]
 pub struct NoiseConfig<P, C: Zeroize, R = ()> {
     dh_keys: AuthenticKeypair<C>,
     params: ProtocolParams,
     remote: R,
+    payload: Payload,
+    prologue: Vec<u8>,
+    psk: Option<PreSharedKey>,
+    output: OutputConfig,
+    inbound_timeout: Option<Duration>,
+    outbound_timeout: Option<Duration>,
+    legacy: LegacyConfig,
     _marker: std::marker::PhantomData<P>
 }
 
@@ -82,6 +116,385 @@ impl<H, C: Zeroize, R> NoiseConfig<H, C, R> {
     pub fn into_authenticated(self) -> NoiseAuthenticated<H, C, R> {
         NoiseAuthenticated { config: self }
     }
+
+    /// Set the application-provided early data to send to the remote
+    /// along with the local identity during the handshake.
+    ///
+    /// The early data of the remote is available after the handshake
+    /// via [`NoiseOutput::remote_early_data`]. The handshake fails if
+    /// the early data together with the local identity does not fit
+    /// into a single handshake message.
+    pub fn with_early_data(mut self, data: Vec<u8>) -> Self {
+        self.payload.early_data = data;
+        self
+    }
+
+    /// Set the names of the supported stream multiplexers, in order of
+    /// preference, to send to the remote during the handshake.
+    ///
+    /// If the remote sends its multiplexers as well, both parties agree on
+    /// the first multiplexer of the initiator that is supported by the
+    /// responder, available via [`NoiseOutput::early_muxer`]. A transport
+    /// upgraded with [`multiplex_early`](libp2p_core::transport::upgrade::Builder::multiplex_early)
+    /// uses the agreed multiplexer without negotiating it.
+    pub fn with_stream_muxers<I, S>(mut self, muxers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>
+    {
+        self.payload.stream_muxers = muxers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the compatibility configuration for the legacy handshake payload
+    /// format of older versions of this crate.
+    ///
+    /// See [`LegacyConfig`] for the default behaviour.
+    pub fn with_legacy_config(mut self, legacy: LegacyConfig) -> Self {
+        self.legacy = legacy;
+        self
+    }
+
+    /// Set the prologue of the handshake, i.e. data that both parties
+    /// must agree on for the handshake to succeed.
+    ///
+    /// The prologue is not sent to the remote but mixed into the
+    /// handshake hash, which allows binding the handshake to its
+    /// context, e.g. a transport that runs the handshake over an outer
+    /// secure channel can bind the certificate hashes of that channel.
+    /// The default is an empty prologue.
+    pub fn with_prologue(mut self, prologue: Vec<u8>) -> Self {
+        self.prologue = prologue;
+        if self.output.plaintext {
+            self.prologue.extend_from_slice(PLAINTEXT_PROLOGUE)
+        }
+        self
+    }
+
+    /// Advertise support for rekeying the established session to the remote.
+    ///
+    /// Rekeying is signaled with frames that have an empty payload, which is
+    /// not part of the libp2p Noise specification. It is therefore only
+    /// enabled if the remote advertises support for rekeying as well, see
+    /// [`NoiseOutput::rekey_negotiated`]. Otherwise frames with an empty
+    /// payload are skipped.
+    pub fn with_rekeying(mut self) -> Self {
+        self.payload.rekey = true;
+        self
+    }
+
+    /// Automatically rekey the sending direction of the established session
+    /// after the given number of frames have been sent with the same key.
+    /// A limit of `0` frames disables this condition.
+    ///
+    /// Implies [`NoiseConfig::with_rekeying`]. See [`NoiseOutput::rekey`].
+    pub fn rekey_after_frames(mut self, frames: u64) -> Self {
+        self.output.rekey.max_frames = Some(frames).filter(|n| *n > 0);
+        self.with_rekeying()
+    }
+
+    /// Automatically rekey the sending direction of the established session
+    /// after the given number of plaintext bytes have been sent with the same key.
+    /// A limit of `0` bytes disables this condition.
+    ///
+    /// Implies [`NoiseConfig::with_rekeying`]. See [`NoiseOutput::rekey`].
+    pub fn rekey_after_bytes(mut self, bytes: u64) -> Self {
+        self.output.rekey.max_bytes = Some(bytes).filter(|n| *n > 0);
+        self.with_rekeying()
+    }
+
+    /// Set the size of the write buffer of the established session, i.e.
+    /// the maximum length of the plaintext of a single frame.
+    ///
+    /// Larger frames reduce the framing and encryption overhead for bulk
+    /// transfers at the cost of latency and memory. The default is 16 KiB.
+    /// The length is capped at the maximum noise message length of 64 KiB
+    /// minus the authentication tag and must not be zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is zero.
+    pub fn with_write_buffer_len(mut self, len: usize) -> Self {
+        assert!(len > 0, "write buffer length must not be zero");
+        self.output.write_buffer_len = std::cmp::min(len, MAX_FRAME_LEN);
+        self
+    }
+
+    /// Release the I/O buffers of the established session while it is idle.
+    ///
+    /// The read and write buffers of a session, about 180 KiB in total with
+    /// the default write buffer size, are always allocated on first use.
+    /// If enabled, the read buffers are released whenever no data is
+    /// available to read and the write buffers are released after every
+    /// flush, trading allocations for a lower memory footprint of nodes
+    /// with many mostly idle connections. Disabled by default.
+    pub fn release_idle_buffers(mut self, release: bool) -> Self {
+        self.output.release_idle_buffers = release;
+        self
+    }
+
+    /// Pad the frames of the established session according to the given
+    /// policy, to obscure the sizes of the messages exchanged.
+    ///
+    /// The remote must be configured with padding as well, though not
+    /// necessarily of the same kind, which is negotiated during the handshake.
+    /// The handshake fails with [`NoiseError::PaddingMismatch`] otherwise.
+    /// A fixed padding also limits the size of the write buffer, see
+    /// [`Padding::Fixed`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a fixed padding of less than 3 bytes is given.
+    pub fn with_padding(mut self, padding: Padding) -> Self {
+        if let Padding::Fixed(n) = padding {
+            assert!(n >= 3, "fixed padding must be at least 3 bytes")
+        }
+        self.output.padding = padding;
+        self.payload.padding = padding != Padding::None;
+        self
+    }
+
+    /// **Disable the encryption** of the established session, using the
+    /// handshake only to authenticate the remote.
+    ///
+    /// After the handshake, frames are sent in plaintext, i.e. they are
+    /// neither encrypted nor authenticated and can be read and modified
+    /// by anyone on the path. This saves the CPU cost of encryption on
+    /// links that are already encrypted and integrity-protected below,
+    /// e.g. by a VPN between datacenters, and must not be used otherwise.
+    ///
+    /// The handshake fails unless the remote disables encryption as well.
+    pub fn dangerous_disable_encryption(mut self) -> Self {
+        if !self.output.plaintext {
+            self.output.plaintext = true;
+            self.prologue.extend_from_slice(PLAINTEXT_PROLOGUE)
+        }
+        self
+    }
+
+    /// Use the given AEAD cipher and hash function for the handshake and the
+    /// established session, instead of the default ChaChaPoly and SHA256.
+    ///
+    /// The cipher suite is part of the negotiated protocol name, e.g.
+    /// `/noise/xx/25519/aesgcm/sha256/0.1.0`, so the remote must be
+    /// configured with the same cipher suite. AES-GCM is the better choice
+    /// on hardware with AES instructions, whereas ChaChaPoly is faster on
+    /// targets without them.
+    pub fn with_cipher_suite(mut self, cipher: Cipher, hash: Hash) -> Self {
+        self.params = self.params.with_cipher_suite(cipher, hash);
+        self
+    }
+
+    /// Use the **experimental** hybrid X25519+Kyber768 key exchange, which
+    /// mixes the shared secret of the post-quantum Kyber768 KEM into the
+    /// handshake in addition to the X25519 DH key agreement.
+    ///
+    /// The established session remains secure as long as either of the two
+    /// is unbroken, at the cost of about 1.1 KiB more in each of the first
+    /// two handshake messages. The hybrid key exchange is part of the
+    /// negotiated protocol name, e.g. `/noise/xx/25519+kyber768/chachapoly/sha256/0.1.0`,
+    /// so it only takes effect if the remote is configured with it as well.
+    /// To fall back to plain X25519 with remotes that do not support it,
+    /// offer both configurations, e.g. with [`upgrade::SelectUpgrade`](libp2p_core::upgrade::SelectUpgrade).
+    #[cfg(feature = "hybrid-pq")]
+    pub fn with_hybrid_kyber768(mut self) -> Self {
+        self.params = self.params.into_hybrid();
+        self
+    }
+
+    /// Abort handshakes that do not complete within the given duration
+    /// with a [`NoiseError::HandshakeTimeout`].
+    ///
+    /// This bounds the time a remote can stall a handshake independently
+    /// of any timeout applied to the upgrade as a whole. Sets the timeout
+    /// of both inbound and outbound handshakes. By default, handshakes do
+    /// not time out.
+    pub fn with_handshake_timeout(self, timeout: Duration) -> Self {
+        self.with_inbound_handshake_timeout(timeout)
+            .with_outbound_handshake_timeout(timeout)
+    }
+
+    /// Abort inbound handshakes, i.e. handshakes in which the local node
+    /// is the responder, that do not complete within the given duration.
+    ///
+    /// See [`NoiseConfig::with_handshake_timeout`].
+    pub fn with_inbound_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.inbound_timeout = Some(timeout);
+        self
+    }
+
+    /// Abort outbound handshakes, i.e. handshakes in which the local node
+    /// is the initiator, that do not complete within the given duration.
+    ///
+    /// See [`NoiseConfig::with_handshake_timeout`].
+    pub fn with_outbound_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.outbound_timeout = Some(timeout);
+        self
+    }
+
+    /// Decide whether to accept the identity of a remote with the given
+    /// callback, e.g. to enforce an allowlist or to pin static DH keys.
+    ///
+    /// The callback is invoked with the peer ID, the public identity key
+    /// and the static DH public key of the remote once the remote has been
+    /// authenticated, before the upgrade completes. If it returns `false`,
+    /// the upgrade fails with [`NoiseError::IdentityRejected`].
+    ///
+    /// > **Note**: The callback is not invoked if the handshake does not
+    /// > yield an identity key of the remote, i.e. if the remote identity
+    /// > is [`RemoteIdentity::Unknown`] or [`RemoteIdentity::StaticDhKey`].
+    pub fn with_identity_verifier<F>(mut self, verifier: F) -> Self
+    where
+        F: Fn(&PeerId, &identity::PublicKey, &[u8]) -> bool + Send + Sync + 'static
+    {
+        self.output.verifier = Some(IdentityVerifier::new(verifier));
+        self
+    }
+
+    /// Use the given `snow` crypto resolver for the cryptographic primitives
+    /// of the handshake and the established sessions, e.g. for hardware
+    /// offload or certified implementations.
+    ///
+    /// Primitives not provided by the given resolver, i.e. for which it
+    /// resolves to `None`, fall back to the built-in implementations based
+    /// on `ring` and `x25519-dalek`. Since the resolver is part of the
+    /// configuration, different instances of the same binary can select
+    /// different backends at runtime, e.g. one of the [`CryptoBackend`]s.
+    ///
+    /// The resolver only applies to the handshake and the sessions. Static
+    /// DH keypairs generated with the same resolver are created with
+    /// [`Keypair::generate_with`].
+    pub fn with_crypto_resolver<T>(mut self, resolver: T) -> Self
+    where
+        T: snow::resolvers::CryptoResolver + Send + Sync + 'static
+    {
+        self.output.resolver = Some(CustomResolver::new(resolver));
+        self
+    }
+
+    /// Report the progress of handshakes and the usage of established
+    /// sessions to the given callback, e.g. to feed them into metrics.
+    ///
+    /// See [`NoiseEvent`] for the events reported.
+    pub fn with_events<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&NoiseEvent<'_>) + Send + Sync + 'static
+    {
+        self.output.events = Some(Events::new(callback));
+        self
+    }
+
+    /// Issue a resumption ticket into the given cache for every session
+    /// established with this configuration in which the remote identified
+    /// with a [`RemoteIdentity::IdentityKey`].
+    ///
+    /// A ticket allows a later connection to the same remote to perform
+    /// a faster handshake, see [`NoiseConfig::resume_dialer`]. Both
+    /// parties of the session must be configured with a cache.
+    pub fn with_resumption(mut self, cache: ResumptionCache) -> Self {
+        self.output.resumption = Some(Resumption::new(cache));
+        self
+    }
+
+    /// Pass the handshake hash and the cipher keys of every established
+    /// session to the given callback, e.g. to decrypt captured traffic
+    /// while debugging interoperability issues.
+    ///
+    /// > **Note**: Anyone with access to the logged keys can decrypt the
+    /// > traffic of the logged sessions. This method is therefore only
+    /// > available with the `keylog` feature, which must never be enabled
+    /// > in production builds.
+    #[cfg(feature = "keylog")]
+    pub fn with_key_log<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&SessionKeys<'_>) + Send + Sync + 'static
+    {
+        self.output.keylog = Some(KeyLog::new(callback));
+        self
+    }
+
+    /// The configuration of a new session, in which the local node is
+    /// the initiator or responder of the handshake.
+    fn output(&self, initiator: bool) -> OutputConfig {
+        let mut output = self.output.clone();
+        output.events = output.events.as_ref()
+            .map(|events| events.session(&self.params.name(), initiator));
+        output.resumption = output.resumption.as_ref().map(Resumption::session);
+        output.handshake_timeout = if initiator {
+            self.outbound_timeout
+        } else {
+            self.inbound_timeout
+        };
+        #[cfg(feature = "keylog")]
+        {
+            output.keylog = output.keylog.as_ref().map(KeyLog::session);
+        }
+        output
+    }
+
+    /// Mix the given pre-shared key into the handshake, using the `psk0`
+    /// modifier on the handshake pattern.
+    fn psk0(mut self, psk: PreSharedKey) -> Self {
+        if self.psk.is_none() {
+            self.params = self.params.into_psk0();
+        }
+        self.psk = Some(psk);
+        self
+    }
+}
+
+impl<H, C: AsRef<[u8]> + Zeroize, R> NoiseConfig<H, C, R> {
+    /// Create a session builder for the configured protocol parameters,
+    /// local static DH keypair and pre-shared key, if any, for a session
+    /// with the given configuration.
+    fn builder(&self, output: &OutputConfig) -> snow::Builder<'_> {
+        let builder = static_session_builder(self.params.clone(), &self.prologue, output, &self.dh_keys);
+        if let Some(psk) = &self.psk {
+            builder.psk(0, psk.as_ref())
+        } else {
+            builder
+        }
+    }
+}
+
+/// Turn the given protocol parameters and prologue into a session builder
+/// for a session with the given configuration.
+fn session_builder<'a>(params: ProtocolParams, prologue: &'a [u8], output: &OutputConfig)
+    -> snow::Builder<'a>
+{
+    params.into_builder(resolver(output)).prologue(prologue)
+}
+
+/// Turn the given protocol parameters and prologue into a session builder
+/// for a session with the given configuration and local static DH keypair.
+fn static_session_builder<'a, C>(params: ProtocolParams, prologue: &'a [u8], output: &OutputConfig, keys: &'a Keypair<C>)
+    -> snow::Builder<'a>
+where
+    C: AsRef<[u8]> + Zeroize
+{
+    let mut resolver = resolver(output);
+    if let Some(external) = keys.external() {
+        resolver = resolver.external_dh(external.clone())
+    }
+    params.into_builder(resolver)
+        .prologue(prologue)
+        .local_private_key(keys.secret().as_ref())
+}
+
+/// Create the resolver of the cryptographic primitives for a session
+/// with the given configuration.
+fn resolver(output: &OutputConfig) -> Resolver {
+    let mut resolver = Resolver::new(output.resolver.clone());
+    #[cfg(feature = "keylog")]
+    {
+        if let Some(keylog) = &output.keylog {
+            resolver = resolver.capture_keys(keylog.keys().clone())
+        }
+    }
+    if let Some(resumption) = &output.resumption {
+        resolver = resolver.capture_keys(resumption.keys().clone())
+    }
+    resolver
 }
 
 impl<C> NoiseConfig<IX, C>
@@ -94,9 +507,23 @@ where
             dh_keys,
             params: C::params_ix(),
             remote: (),
+            payload: Payload::default(),
+            prologue: Vec::new(),
+            psk: None,
+            output: OutputConfig::default(),
+            inbound_timeout: None,
+            outbound_timeout: None,
+            legacy: LegacyConfig::default(),
             _marker: std::marker::PhantomData
         }
     }
+
+    /// Require the remote to be in possession of the given pre-shared key.
+    ///
+    /// See `NoiseConfig::<XX, C>::with_psk`.
+    pub fn with_psk(self, psk: PreSharedKey) -> Self {
+        self.psk0(psk)
+    }
 }
 
 impl<C> NoiseConfig<XX, C>
@@ -109,9 +536,42 @@ where
             dh_keys,
             params: C::params_xx(),
             remote: (),
+            payload: Payload::default(),
+            prologue: Vec::new(),
+            psk: None,
+            output: OutputConfig::default(),
+            inbound_timeout: None,
+            outbound_timeout: None,
+            legacy: LegacyConfig::default(),
             _marker: std::marker::PhantomData
         }
     }
+
+    /// Require the remote to be in possession of the given pre-shared key.
+    ///
+    /// The pre-shared key is mixed into the handshake before the first message
+    /// is sent, so that a handshake with a remote that does not possess the same
+    /// key fails as early as possible. Since the protocol name used for protocol
+    /// negotiation is unaffected, this allows running a private network of nodes
+    /// that only accept connections from each other.
+    ///
+    /// > **Note**: A pre-shared key can be obtained from a swarm key file used for
+    /// > private networks by [parsing](std::str::FromStr) it into a [`PreSharedKey`].
+    pub fn with_psk(self, psk: PreSharedKey) -> Self {
+        self.psk0(psk)
+    }
+}
+
+impl<C, R> NoiseConfig<IK, C, R>
+where
+    C: Protocol<C> + Zeroize
+{
+    /// Require the remote to be in possession of the given pre-shared key.
+    ///
+    /// See `NoiseConfig::<XX, C>::with_psk`.
+    pub fn with_psk(self, psk: PreSharedKey) -> Self {
+        self.psk0(psk)
+    }
 }
 
 impl<C> NoiseConfig<IK, C>
@@ -127,6 +587,13 @@ where
             dh_keys,
             params: C::params_ik(),
             remote: (),
+            payload: Payload::default(),
+            prologue: Vec::new(),
+            psk: None,
+            output: OutputConfig::default(),
+            inbound_timeout: None,
+            outbound_timeout: None,
+            legacy: LegacyConfig::default(),
             _marker: std::marker::PhantomData
         }
     }
@@ -149,11 +616,231 @@ where
             dh_keys,
             params: C::params_ik(),
             remote: (remote_dh, remote_id),
+            payload: Payload::default(),
+            prologue: Vec::new(),
+            psk: None,
+            output: OutputConfig::default(),
+            inbound_timeout: None,
+            outbound_timeout: None,
+            legacy: LegacyConfig::default(),
+            _marker: std::marker::PhantomData
+        }
+    }
+}
+
+impl<C> NoiseConfig<IKXX, C>
+where
+    C: Protocol<C> + Zeroize
+{
+    /// Create a new `NoiseConfig` for the `IK` handshake pattern with a
+    /// fallback to the `XX` handshake pattern (recipient side).
+    ///
+    /// If the initiator knows the current static DH public key of the local
+    /// node, the handshake completes in a single roundtrip as with
+    /// [`NoiseConfig::ik_listener`]. Otherwise the handshake falls back to `XX`,
+    /// whereby the local node transmits its public identity to the remote.
+    pub fn ik_xx_listener(dh_keys: AuthenticKeypair<C>) -> Self {
+        NoiseConfig {
+            dh_keys,
+            params: C::params_ik(),
+            remote: (),
+            payload: Payload::default(),
+            prologue: Vec::new(),
+            psk: None,
+            output: OutputConfig::default(),
+            inbound_timeout: None,
+            outbound_timeout: None,
+            legacy: LegacyConfig::default(),
+            _marker: std::marker::PhantomData
+        }
+    }
+}
+
+impl<C> NoiseConfig<IKXX, C, (PublicKey<C>, identity::PublicKey)>
+where
+    C: Protocol<C> + Zeroize
+{
+    /// Create a new `NoiseConfig` for the `IK` handshake pattern with a
+    /// fallback to the `XX` handshake pattern (initiator side).
+    ///
+    /// In this configuration, the remote identity and (possibly stale) static
+    /// DH public key are known to the local node. If the remote rejects the
+    /// first handshake message, e.g. because it uses a different static DH
+    /// keypair by now, the handshake falls back to `XX` and the remote must
+    /// still identify with the given public identity key.
+    pub fn ik_xx_dialer(
+        dh_keys: AuthenticKeypair<C>,
+        remote_id: identity::PublicKey,
+        remote_dh: PublicKey<C>
+    ) -> Self {
+        NoiseConfig {
+            dh_keys,
+            params: C::params_ik(),
+            remote: (remote_dh, remote_id),
+            payload: Payload::default(),
+            prologue: Vec::new(),
+            psk: None,
+            output: OutputConfig::default(),
+            inbound_timeout: None,
+            outbound_timeout: None,
+            legacy: LegacyConfig::default(),
+            _marker: std::marker::PhantomData
+        }
+    }
+}
+
+impl<C> NoiseConfig<NX, C>
+where
+    C: Protocol<C> + Zeroize
+{
+    /// Create a new `NoiseConfig` for the `NX` handshake pattern.
+    ///
+    /// Only the responder transmits its static DH public key and public
+    /// identity key, i.e. the initiator learns the [`RemoteIdentity::IdentityKey`]
+    /// of the responder whereas the initiator remains anonymous, i.e. the
+    /// responder obtains [`RemoteIdentity::Unknown`]. The handshake completes
+    /// in a single roundtrip. Early data of the initiator is not sent.
+    pub fn nx(dh_keys: AuthenticKeypair<C>) -> Self {
+        NoiseConfig {
+            dh_keys,
+            params: C::params_nx(),
+            remote: (),
+            payload: Payload::default(),
+            prologue: Vec::new(),
+            psk: None,
+            output: OutputConfig::default(),
+            inbound_timeout: None,
+            outbound_timeout: None,
+            legacy: LegacyConfig::default(),
+            _marker: std::marker::PhantomData
+        }
+    }
+}
+
+impl<C> NoiseConfig<XN, C>
+where
+    C: Protocol<C> + Zeroize
+{
+    /// Create a new `NoiseConfig` for the `XN` handshake pattern.
+    ///
+    /// Only the initiator transmits its static DH public key and public
+    /// identity key, i.e. the responder learns the [`RemoteIdentity::IdentityKey`]
+    /// of the initiator whereas the responder remains anonymous, i.e. the
+    /// initiator obtains [`RemoteIdentity::Unknown`].
+    pub fn xn(dh_keys: AuthenticKeypair<C>) -> Self {
+        NoiseConfig {
+            dh_keys,
+            params: C::params_xn(),
+            remote: (),
+            payload: Payload::default(),
+            prologue: Vec::new(),
+            psk: None,
+            output: OutputConfig::default(),
+            inbound_timeout: None,
+            outbound_timeout: None,
+            legacy: LegacyConfig::default(),
+            _marker: std::marker::PhantomData
+        }
+    }
+}
+
+impl<C> NoiseConfig<KK, C, (PublicKey<C>, identity::PublicKey)>
+where
+    C: Protocol<C> + Zeroize
+{
+    /// Create a new `NoiseConfig` for the `KK` handshake pattern.
+    ///
+    /// In this configuration, the static DH public keys and public identity
+    /// keys of both nodes are known to each other in advance, so neither
+    /// transmits them. The handshake completes in a single roundtrip, yielding
+    /// the given [`RemoteIdentity::IdentityKey`] if the remote proves to
+    /// be in possession of the given static DH public key.
+    pub fn kk(
+        dh_keys: AuthenticKeypair<C>,
+        remote_id: identity::PublicKey,
+        remote_dh: PublicKey<C>
+    ) -> Self {
+        NoiseConfig {
+            dh_keys,
+            params: C::params_kk(),
+            remote: (remote_dh, remote_id),
+            payload: Payload::default(),
+            prologue: Vec::new(),
+            psk: None,
+            output: OutputConfig::default(),
+            inbound_timeout: None,
+            outbound_timeout: None,
+            legacy: LegacyConfig::default(),
             _marker: std::marker::PhantomData
         }
     }
 }
 
+impl<C> NoiseConfig<Resume, C, Ticket>
+where
+    C: Protocol<C> + Zeroize
+{
+    /// Create a new `NoiseConfig` for resuming an earlier session with the
+    /// given remote (initiator side), if the cache holds a ticket for it.
+    ///
+    /// The handshake follows the `KK` handshake pattern with the secret of
+    /// the ticket as a pre-shared key, completing in a single roundtrip
+    /// without transmitting the static DH public keys or public identity
+    /// keys. The ticket is removed from the cache and the resumed session
+    /// issues a new ticket in turn.
+    ///
+    /// > **Note**: If the remote does not accept the ticket, e.g. because it
+    /// > has been restarted since, the handshake fails and the connection
+    /// > must be secured with a full handshake instead.
+    pub fn resume_dialer(dh_keys: AuthenticKeypair<C>, cache: &ResumptionCache, remote: &PeerId)
+        -> Option<Self>
+    {
+        let ticket = cache.take(remote)?;
+        let psk = ticket.secret().clone();
+        let config = NoiseConfig {
+            dh_keys,
+            params: C::params_kk(),
+            remote: ticket,
+            payload: Payload::default(),
+            prologue: Vec::new(),
+            psk: None,
+            output: OutputConfig::default(),
+            inbound_timeout: None,
+            outbound_timeout: None,
+            legacy: LegacyConfig::default(),
+            _marker: std::marker::PhantomData
+        };
+        Some(config.psk0(psk).with_resumption(cache.clone()))
+    }
+}
+
+impl<C> NoiseConfig<Resume, C>
+where
+    C: Protocol<C> + Zeroize
+{
+    /// Create a new `NoiseConfig` for resuming earlier sessions with the
+    /// tickets of the given cache (recipient side).
+    ///
+    /// See [`NoiseConfig::resume_dialer`]. The handshake fails with
+    /// [`NoiseError::UnknownTicket`] if the ticket presented by the remote
+    /// is not in the cache.
+    pub fn resume_listener(dh_keys: AuthenticKeypair<C>, cache: ResumptionCache) -> Self {
+        NoiseConfig {
+            dh_keys,
+            params: C::params_kk().into_psk0(),
+            remote: (),
+            payload: Payload::default(),
+            prologue: Vec::new(),
+            psk: None,
+            output: OutputConfig::default(),
+            inbound_timeout: None,
+            outbound_timeout: None,
+            legacy: LegacyConfig::default(),
+            _marker: std::marker::PhantomData
+        }.with_resumption(cache)
+    }
+}
+
 // Handshake pattern IX /////////////////////////////////////////////////////
 
 impl<T, C> InboundUpgrade<T> for NoiseConfig<IX, C>
@@ -167,13 +854,16 @@ where
     type Future = Handshake<T, C>;
 
     fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
-        let session = self.params.into_builder()
-            .local_private_key(self.dh_keys.secret().as_ref())
+        let output = self.output(false);
+        let session = self.builder(&output)
             .build_responder()
             .map_err(NoiseError::from);
         handshake::rt1_responder(socket, session,
             self.dh_keys.into_identity(),
-            IdentityExchange::Mutual)
+            IdentityExchange::Mutual,
+            self.legacy,
+            self.payload)
+        .configure(output)
     }
 }
 
@@ -188,13 +878,16 @@ where
     type Future = Handshake<T, C>;
 
     fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
-        let session = self.params.into_builder()
-            .local_private_key(self.dh_keys.secret().as_ref())
+        let output = self.output(true);
+        let session = self.builder(&output)
             .build_initiator()
             .map_err(NoiseError::from);
         handshake::rt1_initiator(socket, session,
                                  self.dh_keys.into_identity(),
-                                 IdentityExchange::Mutual)
+                                 IdentityExchange::Mutual,
+                                 self.legacy,
+                                 self.payload)
+        .configure(output)
     }
 }
 
@@ -211,13 +904,16 @@ where
     type Future = Handshake<T, C>;
 
     fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
-        let session = self.params.into_builder()
-            .local_private_key(self.dh_keys.secret().as_ref())
+        let output = self.output(false);
+        let session = self.builder(&output)
             .build_responder()
             .map_err(NoiseError::from);
         handshake::rt15_responder(socket, session,
             self.dh_keys.into_identity(),
-            IdentityExchange::Mutual)
+            IdentityExchange::Mutual,
+            self.legacy,
+            self.payload)
+        .configure(output)
     }
 }
 
@@ -232,13 +928,16 @@ where
     type Future = Handshake<T, C>;
 
     fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
-        let session = self.params.into_builder()
-            .local_private_key(self.dh_keys.secret().as_ref())
+        let output = self.output(true);
+        let session = self.builder(&output)
             .build_initiator()
             .map_err(NoiseError::from);
         handshake::rt15_initiator(socket, session,
             self.dh_keys.into_identity(),
-            IdentityExchange::Mutual)
+            IdentityExchange::Mutual,
+            self.legacy,
+            self.payload)
+        .configure(output)
     }
 }
 
@@ -255,13 +954,16 @@ where
     type Future = Handshake<T, C>;
 
     fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
-        let session = self.params.into_builder()
-            .local_private_key(self.dh_keys.secret().as_ref())
+        let output = self.output(false);
+        let session = self.builder(&output)
             .build_responder()
             .map_err(NoiseError::from);
         handshake::rt1_responder(socket, session,
             self.dh_keys.into_identity(),
-            IdentityExchange::Receive)
+            IdentityExchange::Receive,
+            self.legacy,
+            self.payload)
+        .configure(output)
     }
 }
 
@@ -276,14 +978,292 @@ where
     type Future = Handshake<T, C>;
 
     fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
-        let session = self.params.into_builder()
-            .local_private_key(self.dh_keys.secret().as_ref())
+        let output = self.output(true);
+        let session = self.builder(&output)
+            .remote_public_key(self.remote.0.as_ref())
+            .build_initiator()
+            .map_err(NoiseError::from);
+        handshake::rt1_initiator(socket, session,
+            self.dh_keys.into_identity(),
+            IdentityExchange::Send { remote: self.remote.1 },
+            self.legacy,
+            self.payload)
+        .configure(output)
+    }
+}
+
+// Handshake pattern IK with XX fallback ///////////////////////////////////////
+
+impl<T, C, R> InboundUpgrade<T> for NoiseConfig<IKXX, C, R>
+where
+    NoiseConfig<IKXX, C, R>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let output = self.output(false);
+        let dh_keys = &self.dh_keys;
+        let prologue = &self.prologue;
+        let xx_params = C::params_xx().with_suite_of(&self.params);
+        let sessions = static_session_builder(self.params, prologue, &output, dh_keys)
+            .build_responder()
+            .map_err(NoiseError::from)
+            .and_then(|ik| {
+                let xx = static_session_builder(xx_params?, prologue, &output, dh_keys)
+                    .build_responder()?;
+                Ok((ik, xx))
+            });
+        handshake::rt1_fallback_responder(socket, sessions,
+            self.dh_keys.into_identity(),
+            IdentityExchange::Receive,
+            self.legacy,
+            self.payload)
+        .configure(output)
+    }
+}
+
+impl<T, C> OutboundUpgrade<T> for NoiseConfig<IKXX, C, (PublicKey<C>, identity::PublicKey)>
+where
+    NoiseConfig<IKXX, C, (PublicKey<C>, identity::PublicKey)>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let output = self.output(true);
+        let NoiseConfig { dh_keys, params, remote: (remote_dh, remote_id), payload, prologue, legacy, .. } = self;
+        let xx_params = C::params_xx().with_suite_of(&params);
+        let sessions = static_session_builder(params, &prologue, &output, &dh_keys)
+            .remote_public_key(remote_dh.as_ref())
+            .build_initiator()
+            .map_err(NoiseError::from)
+            .and_then(|ik| {
+                let xx = static_session_builder(xx_params?, &prologue, &output, &dh_keys)
+                    .build_initiator()?;
+                Ok((ik, xx))
+            });
+        handshake::rt1_fallback_initiator(socket, sessions,
+            dh_keys.into_identity(),
+            IdentityExchange::Send { remote: remote_id },
+            legacy,
+            payload)
+        .configure(output)
+    }
+}
+
+// Handshake pattern NX /////////////////////////////////////////////////////
+
+impl<T, C> InboundUpgrade<T> for NoiseConfig<NX, C>
+where
+    NoiseConfig<NX, C>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let output = self.output(false);
+        let session = self.builder(&output)
+            .build_responder()
+            .map_err(NoiseError::from);
+        handshake::nx_responder(socket, session,
+            self.dh_keys.into_identity(),
+            IdentityExchange::Mutual,
+            self.legacy,
+            self.payload)
+        .configure(output)
+    }
+}
+
+impl<T, C> OutboundUpgrade<T> for NoiseConfig<NX, C>
+where
+    NoiseConfig<NX, C>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let output = self.output(true);
+        let session = session_builder(self.params, &self.prologue, &output)
+            .build_initiator()
+            .map_err(NoiseError::from);
+        handshake::nx_initiator(socket, session,
+            self.dh_keys.into_identity(),
+            IdentityExchange::Receive,
+            self.legacy)
+        .configure(output)
+    }
+}
+
+// Handshake pattern XN /////////////////////////////////////////////////////
+
+impl<T, C> InboundUpgrade<T> for NoiseConfig<XN, C>
+where
+    NoiseConfig<XN, C>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let output = self.output(false);
+        let session = session_builder(self.params, &self.prologue, &output)
+            .build_responder()
+            .map_err(NoiseError::from);
+        handshake::xn_responder(socket, session,
+            self.dh_keys.into_identity(),
+            IdentityExchange::Receive,
+            self.legacy,
+            self.payload)
+        .configure(output)
+    }
+}
+
+impl<T, C> OutboundUpgrade<T> for NoiseConfig<XN, C>
+where
+    NoiseConfig<XN, C>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let output = self.output(true);
+        let session = self.builder(&output)
+            .build_initiator()
+            .map_err(NoiseError::from);
+        handshake::xn_initiator(socket, session,
+            self.dh_keys.into_identity(),
+            IdentityExchange::Mutual,
+            self.legacy,
+            self.payload)
+        .configure(output)
+    }
+}
+
+// Handshake pattern KK /////////////////////////////////////////////////////
+
+impl<T, C> InboundUpgrade<T> for NoiseConfig<KK, C, (PublicKey<C>, identity::PublicKey)>
+where
+    NoiseConfig<KK, C, (PublicKey<C>, identity::PublicKey)>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let output = self.output(false);
+        let session = self.builder(&output)
+            .remote_public_key(self.remote.0.as_ref())
+            .build_responder()
+            .map_err(NoiseError::from);
+        handshake::rt1_responder(socket, session,
+            self.dh_keys.into_identity(),
+            IdentityExchange::None { remote: self.remote.1 },
+            self.legacy,
+            self.payload)
+        .configure(output)
+    }
+}
+
+impl<T, C> OutboundUpgrade<T> for NoiseConfig<KK, C, (PublicKey<C>, identity::PublicKey)>
+where
+    NoiseConfig<KK, C, (PublicKey<C>, identity::PublicKey)>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let output = self.output(true);
+        let session = self.builder(&output)
             .remote_public_key(self.remote.0.as_ref())
             .build_initiator()
             .map_err(NoiseError::from);
         handshake::rt1_initiator(socket, session,
             self.dh_keys.into_identity(),
-            IdentityExchange::Send { remote: self.remote.1 })
+            IdentityExchange::None { remote: self.remote.1 },
+            self.legacy,
+            self.payload)
+        .configure(output)
+    }
+}
+
+// Resumption ///////////////////////////////////////////////////////////////
+
+impl<T, C> InboundUpgrade<T> for NoiseConfig<Resume, C>
+where
+    NoiseConfig<Resume, C>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let output = self.output(false);
+        let NoiseConfig { dh_keys, params, payload, prologue, legacy, .. } = self;
+        let (dh_keys, identity) = dh_keys.into_parts();
+        let session_output = output.clone();
+        let lookup = move |id: &_| {
+            let ticket = session_output.resumption.as_ref()
+                .and_then(|r| r.cache().take_by_id(id))
+                .ok_or(NoiseError::UnknownTicket)?;
+            let session = static_session_builder(params, &prologue, &session_output, &dh_keys)
+                .remote_public_key(ticket.remote_dh())
+                .psk(0, ticket.secret().as_ref())
+                .build_responder()?;
+            Ok((session, ticket.remote().clone()))
+        };
+        handshake::resume_responder(socket, lookup, identity, legacy, payload)
+            .configure(output)
+    }
+}
+
+impl<T, C> OutboundUpgrade<T> for NoiseConfig<Resume, C, Ticket>
+where
+    NoiseConfig<Resume, C, Ticket>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let output = self.output(true);
+        let session = self.builder(&output)
+            .remote_public_key(self.remote.remote_dh())
+            .build_initiator()
+            .map_err(NoiseError::from);
+        handshake::resume_initiator(socket, session,
+            *self.remote.id(),
+            self.dh_keys.into_identity(),
+            self.remote.remote().clone(),
+            self.legacy,
+            self.payload)
+        .configure(output)
     }
 }
 
@@ -333,7 +1313,7 @@ where
     fn upgrade_inbound(self, socket: T, info: Self::Info) -> Self::Future {
         Box::pin(self.config.upgrade_inbound(socket, info)
             .and_then(|(remote, io)| match remote {
-                RemoteIdentity::IdentityKey(pk) => future::ok((pk.into_peer_id(), io)),
+                RemoteIdentity::IdentityKey { id_key, .. } => future::ok((id_key.into_peer_id(), io)),
                 _ => future::err(NoiseError::AuthenticationFailed)
             }))
     }
@@ -356,7 +1336,7 @@ where
     fn upgrade_outbound(self, socket: T, info: Self::Info) -> Self::Future {
         Box::pin(self.config.upgrade_outbound(socket, info)
             .and_then(|(remote, io)| match remote {
-                RemoteIdentity::IdentityKey(pk) => future::ok((pk.into_peer_id(), io)),
+                RemoteIdentity::IdentityKey { id_key, .. } => future::ok((id_key.into_peer_id(), io)),
                 _ => future::err(NoiseError::AuthenticationFailed)
             }))
     }
@@ -20,11 +20,15 @@
 
 //! Components of a Noise protocol.
 
+#[cfg(feature = "hybrid-pq")]
+mod kyber;
 pub mod x25519;
+pub mod x25519_spec;
 
 use crate::NoiseError;
 use libp2p_core::identity;
 use rand::SeedableRng;
+use std::{borrow::Cow, fmt, io, sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}}};
 use zeroize::Zeroize;
 
 /// The parameters of a Noise protocol, consisting of a choice
@@ -33,9 +37,192 @@ use zeroize::Zeroize;
 pub struct ProtocolParams(snow::params::NoiseParams);
 
 impl ProtocolParams {
-    /// Turn the protocol parameters into a session builder.
-    pub(crate) fn into_builder(self) -> snow::Builder<'static> {
-        snow::Builder::with_resolver(self.0, Box::new(Resolver))
+    /// Turn the protocol parameters into a session builder using the
+    /// given resolver for the cryptographic primitives.
+    pub(crate) fn into_builder<'a>(self, resolver: Resolver) -> snow::Builder<'a> {
+        snow::Builder::with_resolver(self.0, Box::new(resolver))
+    }
+
+    /// Add the `psk0` modifier to the handshake pattern of these protocol
+    /// parameters, i.e. a pre-shared key is mixed into the handshake before
+    /// the first handshake message is sent.
+    pub(crate) fn into_psk0(self) -> Self {
+        let hybrid = self.is_hybrid();
+        self.with_modifiers(true, hybrid)
+    }
+
+    /// Add the `hfs` modifier to the handshake pattern of these protocol
+    /// parameters, i.e. a KEM is used alongside the DH key agreement.
+    ///
+    /// `snow` only knows the Kyber1024 KEM, which therefore appears in the
+    /// Noise protocol name, whereas the [`Resolver`] provides Kyber768 for it.
+    #[cfg(feature = "hybrid-pq")]
+    pub(crate) fn into_hybrid(self) -> Self {
+        let psk0 = self.has_psk0();
+        self.with_modifiers(psk0, true)
+    }
+
+    /// Replace the modifiers of the handshake pattern of these protocol
+    /// parameters with the given ones.
+    ///
+    /// The modifiers are always listed in the same order, so the resulting
+    /// protocol name does not depend on the order in which they are added.
+    fn with_modifiers(self, psk0: bool, hybrid: bool) -> Self {
+        let mut parts = self.0.name.split('_').map(String::from).collect::<Vec<_>>();
+        let mut modifiers = Vec::new();
+        if psk0 {
+            modifiers.push("psk0")
+        }
+        if hybrid {
+            modifiers.push("hfs")
+        }
+        let pattern = parts[1].chars().take_while(char::is_ascii_uppercase).collect::<String>();
+        parts[1] = pattern + &modifiers.join("+");
+        let dh = parts[2].split('+').next().unwrap_or_default().to_string();
+        parts[2] = if hybrid { dh + "+" + KEM_PLACEHOLDER } else { dh };
+        parts.join("_")
+            .parse()
+            .map(ProtocolParams)
+            .expect("Adding handshake modifiers yields a valid protocol name.")
+    }
+
+    /// Whether the handshake pattern of these protocol parameters has
+    /// the `psk0` modifier.
+    #[cfg(feature = "hybrid-pq")]
+    fn has_psk0(&self) -> bool {
+        self.0.name.split('_').nth(1).map_or(false, |p| p.contains("psk0"))
+    }
+
+    /// Whether these protocol parameters use the hybrid key exchange.
+    pub(crate) fn is_hybrid(&self) -> bool {
+        #[cfg(feature = "hybrid-pq")]
+        {
+            self.0.kem.is_some()
+        }
+        #[cfg(not(feature = "hybrid-pq"))]
+        {
+            false
+        }
+    }
+
+    /// Carry over the cipher suite and the key exchange of the given
+    /// protocol parameters to these protocol parameters.
+    pub(crate) fn with_suite_of(self, other: &ProtocolParams) -> Result<Self, NoiseError> {
+        let (cipher, hash) = other.cipher_suite()?;
+        let params = self.with_cipher_suite(cipher, hash);
+        #[cfg(feature = "hybrid-pq")]
+        {
+            if other.is_hybrid() {
+                return Ok(params.into_hybrid())
+            }
+        }
+        Ok(params)
+    }
+
+    /// The name of the key exchange in a libp2p protocol name.
+    pub(crate) fn dh_protocol_name(&self) -> &'static str {
+        if self.is_hybrid() {
+            "25519+kyber768"
+        } else {
+            "25519"
+        }
+    }
+
+    /// Replace the cipher and hash function of these protocol parameters.
+    pub(crate) fn with_cipher_suite(self, cipher: Cipher, hash: Hash) -> Self {
+        let mut parts = self.0.name.split('_').map(String::from).collect::<Vec<_>>();
+        parts[3] = cipher.noise_name().to_string();
+        parts[4] = hash.noise_name().to_string();
+        parts.join("_")
+            .parse()
+            .map(ProtocolParams)
+            .expect("Replacing the cipher suite yields a valid protocol name.")
+    }
+
+    /// The cipher and hash function of these protocol parameters.
+    ///
+    /// Fails if the hash function is not one of the supported [`Hash`]es.
+    pub(crate) fn cipher_suite(&self) -> Result<(Cipher, Hash), NoiseError> {
+        let cipher = match self.0.cipher {
+            snow::params::CipherChoice::ChaChaPoly => Cipher::ChaChaPoly,
+            snow::params::CipherChoice::AESGCM => Cipher::AesGcm
+        };
+        let hash = match self.0.hash {
+            snow::params::HashChoice::SHA256 => Hash::Sha256,
+            snow::params::HashChoice::SHA512 => Hash::Sha512,
+            _ => return Err(NoiseError::Noise(snow::Error::Init(snow::error::InitStage::GetHashImpl)))
+        };
+        Ok((cipher, hash))
+    }
+
+    /// The name of the Noise protocol of these parameters.
+    ///
+    /// The name of a hybrid key exchange names the KEM actually used
+    /// instead of the one `snow` knows, see [`ProtocolParams::into_hybrid`].
+    pub(crate) fn name(&self) -> Cow<'_, str> {
+        if self.is_hybrid() {
+            Cow::Owned(self.0.name.replace(KEM_PLACEHOLDER, "Kyber768"))
+        } else {
+            Cow::Borrowed(&self.0.name)
+        }
+    }
+}
+
+/// The name of the KEM in the Noise protocol name of a hybrid key exchange,
+/// which stands in for Kyber768, see [`ProtocolParams::into_hybrid`].
+const KEM_PLACEHOLDER: &str = "Kyber1024";
+
+/// The AEAD cipher used by a Noise protocol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Cipher {
+    /// ChaCha20-Poly1305, the default. Fast in software on any platform.
+    ChaChaPoly,
+    /// AES-256-GCM. Preferable on hardware with AES instructions.
+    AesGcm
+}
+
+impl Cipher {
+    /// The name of the cipher in a Noise protocol name.
+    fn noise_name(self) -> &'static str {
+        match self {
+            Cipher::ChaChaPoly => "ChaChaPoly",
+            Cipher::AesGcm => "AESGCM"
+        }
+    }
+
+    /// The name of the cipher in a libp2p protocol name.
+    pub(crate) fn protocol_name(self) -> &'static str {
+        match self {
+            Cipher::ChaChaPoly => "chachapoly",
+            Cipher::AesGcm => "aesgcm"
+        }
+    }
+}
+
+/// The hash function used by a Noise protocol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Hash {
+    /// SHA-256, the default.
+    Sha256,
+    /// SHA-512.
+    Sha512
+}
+
+impl Hash {
+    /// The name of the hash function in a Noise protocol name.
+    fn noise_name(self) -> &'static str {
+        match self {
+            Hash::Sha256 => "SHA256",
+            Hash::Sha512 => "SHA512"
+        }
+    }
+
+    /// The name of the hash function in a libp2p protocol name.
+    pub(crate) fn protocol_name(self) -> &'static str {
+        match self {
+            Hash::Sha256 => "sha256",
+            Hash::Sha512 => "sha512"
+        }
     }
 }
 
@@ -51,6 +238,28 @@ pub enum IX {}
 #[derive(Debug, Clone)]
 pub enum XX {}
 
+/// Type tag for the IK handshake pattern with a fallback to the XX
+/// handshake pattern.
+#[derive(Debug, Clone)]
+pub enum IKXX {}
+
+/// Type tag for the NX handshake pattern.
+#[derive(Debug, Clone)]
+pub enum NX {}
+
+/// Type tag for the XN handshake pattern.
+#[derive(Debug, Clone)]
+pub enum XN {}
+
+/// Type tag for the KK handshake pattern.
+#[derive(Debug, Clone)]
+pub enum KK {}
+
+/// Type tag for the KK handshake pattern resuming an earlier session
+/// with a resumption ticket.
+#[derive(Debug, Clone)]
+pub enum Resume {}
+
 /// A Noise protocol over DH keys of type `C`. The choice of `C` determines the
 /// protocol parameters for each handshake pattern.
 pub trait Protocol<C> {
@@ -60,6 +269,12 @@ pub trait Protocol<C> {
     fn params_ix() -> ProtocolParams;
     /// The protocol parameters for the XX handshake pattern.
     fn params_xx() -> ProtocolParams;
+    /// The protocol parameters for the NX handshake pattern.
+    fn params_nx() -> ProtocolParams;
+    /// The protocol parameters for the XN handshake pattern.
+    fn params_xn() -> ProtocolParams;
+    /// The protocol parameters for the KK handshake pattern.
+    fn params_kk() -> ProtocolParams;
 
     /// Construct a DH public key from a byte slice.
     fn public_from_bytes(s: &[u8]) -> Result<PublicKey<C>, NoiseError>;
@@ -95,6 +310,15 @@ pub trait Protocol<C> {
             ||
         sig.as_ref().map_or(false, |s| id_pk.verify(dh_pk.as_ref(), s))
     }
+
+    /// Signs the given static DH public key with the given identity keypair,
+    /// yielding the signature that is sent to the remote for [`verify`](Protocol::verify).
+    fn sign(id_keys: &identity::Keypair, dh_pk: &PublicKey<C>) -> Result<Vec<u8>, NoiseError>
+    where
+        C: AsRef<[u8]>
+    {
+        Ok(id_keys.sign(dh_pk.as_ref())?)
+    }
 }
 
 /// DH keypair.
@@ -102,6 +326,23 @@ pub trait Protocol<C> {
 pub struct Keypair<T: Zeroize> {
     secret: SecretKey<T>,
     public: PublicKey<T>,
+    /// The keypair performing the DH computations with the secret key,
+    /// if the secret key is not held in memory.
+    external: Option<Arc<dyn DhKeypair>>
+}
+
+/// A static DH keypair whose secret key is not held in memory, e.g.
+/// because it resides in an HSM, a TPM or a secure enclave.
+///
+/// Only the public key and the DH computation with the secret key are
+/// required of the keypair. See [`Keypair::from_dh`].
+pub trait DhKeypair: Send + Sync {
+    /// The public key of the keypair.
+    fn public(&self) -> &[u8];
+
+    /// Compute the DH shared secret of the secret key of the keypair
+    /// and the given public key into `out`.
+    fn dh(&self, public: &[u8], out: &mut [u8]) -> io::Result<()>;
 }
 
 /// A DH keypair that is authentic w.r.t. a [`identity::PublicKey`].
@@ -117,6 +358,12 @@ impl<T: Zeroize> AuthenticKeypair<T> {
     pub fn into_identity(self) -> KeypairIdentity {
         self.identity
     }
+
+    /// Split this `AuthenticKeypair` into the DH `Keypair` and its
+    /// public [`KeypairIdentity`].
+    pub(crate) fn into_parts(self) -> (Keypair<T>, KeypairIdentity) {
+        (self.keypair, self.identity)
+    }
 }
 
 impl<T: Zeroize> std::ops::Deref for AuthenticKeypair<T> {
@@ -143,17 +390,40 @@ impl<T: Zeroize> Keypair<T> {
     }
 
     /// The secret key of the DH keypair.
+    ///
+    /// The secret key of a keypair created with [`Keypair::from_dh`] is
+    /// all zeros, since the actual secret key is not held in memory.
     pub fn secret(&self) -> &SecretKey<T> {
         &self.secret
     }
 
+    /// Create a static DH keypair which performs the DH computations of a
+    /// handshake with the given [`DhKeypair`], e.g. one backed by an HSM.
+    ///
+    /// The resulting keypair is used like any other, e.g. it is turned into
+    /// an [`AuthenticKeypair`] with [`Keypair::into_authentic`].
+    pub fn from_dh(keypair: Arc<dyn DhKeypair>) -> Result<Keypair<T>, NoiseError>
+    where
+        T: Protocol<T>
+    {
+        let public = T::public_from_bytes(keypair.public())?;
+        let secret = SecretKey(T::public_from_bytes(&vec![0; keypair.public().len()])?.0);
+        Ok(Keypair { secret, public, external: Some(keypair) })
+    }
+
+    /// The keypair performing the DH computations with the secret key, if
+    /// the secret key is not held in memory.
+    pub(crate) fn external(&self) -> Option<&Arc<dyn DhKeypair>> {
+        self.external.as_ref()
+    }
+
     /// Turn this DH keypair into a [`AuthenticKeypair`], i.e. a DH keypair that
     /// is authentic w.r.t. the given identity keypair, by signing the DH public key.
     pub fn into_authentic(self, id_keys: &identity::Keypair) -> Result<AuthenticKeypair<T>, NoiseError>
     where
-        T: AsRef<[u8]>
+        T: Protocol<T> + AsRef<[u8]>
     {
-        let sig = id_keys.sign(self.public.as_ref())?;
+        let sig = T::sign(id_keys, &self.public)?;
 
         let identity = KeypairIdentity {
             public: id_keys.public(),
@@ -204,27 +474,299 @@ impl<T: AsRef<[u8]>> AsRef<[u8]> for PublicKey<T> {
 /// the choices, because it comes with unwanted additional dependencies,
 /// notably rust-crypto, and to avoid being affected by changes to
 /// the defaults.
-struct Resolver;
+///
+/// A custom resolver given by the user takes precedence over these
+/// choices, except for the KEM of the hybrid key exchange, which is
+/// always Kyber768, see [`ProtocolParams::into_hybrid`].
+pub(crate) struct Resolver {
+    /// The custom resolver, if any.
+    custom: Option<CustomResolver>,
+    /// The captures of the session keys, if any.
+    captures: Vec<CapturedKeys>,
+    /// The number of ciphers resolved so far.
+    resolved: AtomicUsize,
+    /// The keypair performing the DH computations with the local static
+    /// secret key, if it is not held in memory.
+    external: Option<Arc<dyn DhKeypair>>,
+    /// The number of DH functions resolved so far.
+    resolved_dh: AtomicUsize
+}
+
+impl Resolver {
+    pub(crate) fn new(custom: Option<CustomResolver>) -> Self {
+        Resolver {
+            custom,
+            captures: Vec::new(),
+            resolved: AtomicUsize::new(0),
+            external: None,
+            resolved_dh: AtomicUsize::new(0)
+        }
+    }
+
+    /// Perform the DH computations with the local static secret key
+    /// with the given keypair.
+    pub(crate) fn external_dh(mut self, keypair: Arc<dyn DhKeypair>) -> Self {
+        self.external = Some(keypair);
+        self
+    }
+
+    /// Capture the session keys into the given `CapturedKeys`.
+    pub(crate) fn capture_keys(mut self, keys: CapturedKeys) -> Self {
+        self.captures.push(keys);
+        self
+    }
+}
 
 impl snow::resolvers::CryptoResolver for Resolver {
     fn resolve_rng(&self) -> Option<Box<dyn snow::types::Random>> {
+        if let Some(rng) = self.custom.as_ref().and_then(|r| r.0.resolve_rng()) {
+            return Some(rng)
+        }
         Some(Box::new(Rng(rand::rngs::StdRng::from_entropy())))
     }
 
     fn resolve_dh(&self, choice: &snow::params::DHChoice) -> Option<Box<dyn snow::types::Dh>> {
-        if let snow::params::DHChoice::Curve25519 = choice {
-            Some(Box::new(Keypair::<x25519::X25519>::default()))
+        let dh = self.custom.as_ref()
+            .and_then(|r| r.0.resolve_dh(choice))
+            .or_else(|| if let snow::params::DHChoice::Curve25519 = choice {
+                Some(Box::new(Keypair::<x25519::X25519>::default()))
+            } else {
+                None
+            });
+        let external = match &self.external {
+            Some(external) => external,
+            None => return dh
+        };
+        // `snow` resolves the DH function of the static keypair first,
+        // followed by the one of the ephemeral keypair.
+        dh.map(|inner| match self.resolved_dh.fetch_add(1, Ordering::SeqCst) {
+            0 => Box::new(ExternalDh { inner, keypair: external.clone() }) as Box<_>,
+            _ => inner
+        })
+    }
+
+    fn resolve_hash(&self, choice: &snow::params::HashChoice) -> Option<Box<dyn snow::types::Hash>> {
+        self.custom.as_ref()
+            .and_then(|r| r.0.resolve_hash(choice))
+            .or_else(|| snow::resolvers::RingResolver.resolve_hash(choice))
+    }
+
+    #[cfg(feature = "hybrid-pq")]
+    fn resolve_kem(&self, _: &snow::params::KemChoice) -> Option<Box<dyn snow::types::Kem>> {
+        // The only KEM choice of `snow` stands in for Kyber768.
+        Some(Box::new(kyber::Kyber768::default()))
+    }
+
+    fn resolve_cipher(&self, choice: &snow::params::CipherChoice) -> Option<Box<dyn snow::types::Cipher>> {
+        let cipher = self.custom.as_ref()
+            .and_then(|r| r.0.resolve_cipher(choice))
+            .or_else(|| snow::resolvers::RingResolver.resolve_cipher(choice));
+        if self.captures.is_empty() {
+            return cipher
+        }
+        // `snow` resolves the cipher of the handshake first, followed
+        // by the ciphers of the two directions of the session.
+        cipher.map(|c| match self.resolved.fetch_add(1, Ordering::SeqCst) {
+            0 => c,
+            i => self.captures.iter().fold(c, |c, keys| keys.capture(i - 1, c))
+        })
+    }
+}
+
+/// The cipher keys of the two directions of a session, captured as the
+/// ciphers resolved for the session are set to them.
+///
+/// Clones share the captured keys.
+#[derive(Clone, Default)]
+pub(crate) struct CapturedKeys(Arc<Mutex<Keys>>);
+
+/// The captured cipher keys, the first being the key for traffic from
+/// the initiator to the responder.
+#[derive(Default)]
+struct Keys([Option<Vec<u8>>; 2]);
+
+impl Drop for Keys {
+    fn drop(&mut self) {
+        for k in self.0.iter_mut().flatten() {
+            k.zeroize()
+        }
+    }
+}
+
+impl CapturedKeys {
+    /// Wrap the given cipher such that the key it is set to is captured
+    /// as the key of the given direction, `0` being the direction from the
+    /// initiator to the responder.
+    fn capture(&self, direction: usize, cipher: Box<dyn snow::types::Cipher>)
+        -> Box<dyn snow::types::Cipher>
+    {
+        Box::new(CaptureCipher { inner: cipher, keys: self.clone(), direction })
+    }
+
+    /// Apply the given function to the captured keys of the initiator and
+    /// the responder, if both have been captured.
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&[u8], &[u8]) -> R) -> Option<R> {
+        let keys = self.0.lock().expect("Captured keys mutex is not poisoned.");
+        if let [Some(i), Some(r)] = &keys.0 {
+            Some(f(i, r))
         } else {
             None
         }
     }
+}
+
+impl fmt::Debug for CapturedKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CapturedKeys")
+    }
+}
+
+/// A `snow` cipher which captures the key it is set to.
+struct CaptureCipher {
+    inner: Box<dyn snow::types::Cipher>,
+    keys: CapturedKeys,
+    direction: usize
+}
+
+impl snow::types::Cipher for CaptureCipher {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn set(&mut self, key: &[u8]) {
+        if let Ok(mut keys) = self.keys.0.lock() {
+            if let Some(mut k) = keys.0[self.direction].replace(key.to_vec()) {
+                k.zeroize()
+            }
+        }
+        self.inner.set(key)
+    }
+
+    fn encrypt(&self, nonce: u64, authtext: &[u8], plaintext: &[u8], out: &mut [u8]) -> usize {
+        self.inner.encrypt(nonce, authtext, plaintext, out)
+    }
+
+    fn decrypt(&self, nonce: u64, authtext: &[u8], ciphertext: &[u8], out: &mut [u8]) -> Result<usize, ()> {
+        self.inner.decrypt(nonce, authtext, ciphertext, out)
+    }
+
+    fn rekey(&mut self) {
+        self.inner.rekey()
+    }
+}
+
+/// A `snow` DH function which delegates the DH computations with the
+/// local static secret key to a [`DhKeypair`].
+struct ExternalDh {
+    inner: Box<dyn snow::types::Dh>,
+    keypair: Arc<dyn DhKeypair>
+}
+
+impl snow::types::Dh for ExternalDh {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn pub_len(&self) -> usize {
+        self.inner.pub_len()
+    }
+
+    fn priv_len(&self) -> usize {
+        self.inner.priv_len()
+    }
+
+    fn set(&mut self, _: &[u8]) {
+        // The secret key is held by the external keypair.
+    }
+
+    fn generate(&mut self, _: &mut dyn snow::types::Random) {
+        // The keypair is static.
+    }
+
+    fn pubkey(&self) -> &[u8] {
+        self.keypair.public()
+    }
+
+    fn privkey(&self) -> &[u8] {
+        self.inner.privkey()
+    }
+
+    fn dh(&self, pubkey: &[u8], out: &mut [u8]) -> Result<(), ()> {
+        // `snow` passes buffers of the maximum length of any DH function.
+        let len = self.inner.pub_len();
+        self.keypair.dh(&pubkey[.. len], &mut out[.. len]).map_err(|e| {
+            log::debug!("DH with external keypair failed: {}", e)
+        })
+    }
+}
+
+/// A `snow::CryptoResolver` given by the user, e.g. for hardware offload
+/// or certified implementations of the cryptographic primitives.
+#[derive(Clone)]
+pub(crate) struct CustomResolver(Arc<dyn snow::resolvers::CryptoResolver + Send + Sync>);
+
+impl CustomResolver {
+    pub(crate) fn new<R>(resolver: R) -> Self
+    where
+        R: snow::resolvers::CryptoResolver + Send + Sync + 'static
+    {
+        CustomResolver(Arc::new(resolver))
+    }
+}
+
+impl fmt::Debug for CustomResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomResolver")
+    }
+}
+
+/// The built-in choices of implementations of the cryptographic primitives,
+/// to be selected at runtime with [`NoiseConfig::with_crypto_resolver`]
+/// and [`Keypair::generate_with`].
+///
+/// [`NoiseConfig::with_crypto_resolver`]: crate::NoiseConfig::with_crypto_resolver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptoBackend {
+    /// The implementations of `ring`. Since `ring` does not provide
+    /// X25519 as a `snow` primitive, it is provided by `x25519-dalek`.
+    Ring,
+    /// The pure-Rust implementations of the RustCrypto project and
+    /// `x25519-dalek`.
+    #[cfg(feature = "rust-crypto")]
+    RustCrypto
+}
+
+impl snow::resolvers::CryptoResolver for CryptoBackend {
+    fn resolve_rng(&self) -> Option<Box<dyn snow::types::Random>> {
+        match self {
+            CryptoBackend::Ring => snow::resolvers::RingResolver.resolve_rng(),
+            #[cfg(feature = "rust-crypto")]
+            CryptoBackend::RustCrypto => snow::resolvers::DefaultResolver.resolve_rng()
+        }
+    }
+
+    fn resolve_dh(&self, choice: &snow::params::DHChoice) -> Option<Box<dyn snow::types::Dh>> {
+        match self {
+            CryptoBackend::Ring => snow::resolvers::RingResolver.resolve_dh(choice),
+            #[cfg(feature = "rust-crypto")]
+            CryptoBackend::RustCrypto => snow::resolvers::DefaultResolver.resolve_dh(choice)
+        }
+    }
 
     fn resolve_hash(&self, choice: &snow::params::HashChoice) -> Option<Box<dyn snow::types::Hash>> {
-        snow::resolvers::RingResolver.resolve_hash(choice)
+        match self {
+            CryptoBackend::Ring => snow::resolvers::RingResolver.resolve_hash(choice),
+            #[cfg(feature = "rust-crypto")]
+            CryptoBackend::RustCrypto => snow::resolvers::DefaultResolver.resolve_hash(choice)
+        }
     }
 
     fn resolve_cipher(&self, choice: &snow::params::CipherChoice) -> Option<Box<dyn snow::types::Cipher>> {
-        snow::resolvers::RingResolver.resolve_cipher(choice)
+        match self {
+            CryptoBackend::Ring => snow::resolvers::RingResolver.resolve_cipher(choice),
+            #[cfg(feature = "rust-crypto")]
+            CryptoBackend::RustCrypto => snow::resolvers::DefaultResolver.resolve_cipher(choice)
+        }
     }
 }
 
@@ -252,3 +794,193 @@ impl rand::RngCore for Rng {
 impl rand::CryptoRng for Rng {}
 
 impl snow::types::Random for Rng {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snow::resolvers::CryptoResolver;
+
+    // `Resolver` tells the DH functions and ciphers resolved by `snow` apart
+    // by the order in which they are resolved, which `snow` does not document.
+    // These tests pin that order down for the version of `snow` we depend on.
+
+    /// The uses of resolved primitives, by the order of their resolution.
+    #[derive(Clone, Default)]
+    struct Trace(Arc<Mutex<Vec<(usize, &'static str)>>>);
+
+    impl Trace {
+        fn record(&self, index: usize, op: &'static str) {
+            self.0.lock().unwrap().push((index, op))
+        }
+
+        fn take(&self) -> Vec<(usize, &'static str)> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    /// A resolver tracing the uses of the primitives resolved by `Resolver`.
+    #[derive(Default)]
+    struct TraceResolver {
+        dh: Trace,
+        num_dh: AtomicUsize,
+        ciphers: Trace,
+        num_ciphers: AtomicUsize
+    }
+
+    impl CryptoResolver for TraceResolver {
+        fn resolve_rng(&self) -> Option<Box<dyn snow::types::Random>> {
+            Resolver::new(None).resolve_rng()
+        }
+
+        fn resolve_dh(&self, choice: &snow::params::DHChoice) -> Option<Box<dyn snow::types::Dh>> {
+            let index = self.num_dh.fetch_add(1, Ordering::SeqCst);
+            let trace = self.dh.clone();
+            Resolver::new(None).resolve_dh(choice)
+                .map(|inner| Box::new(TraceDh { inner, index, trace }) as Box<_>)
+        }
+
+        fn resolve_hash(&self, choice: &snow::params::HashChoice) -> Option<Box<dyn snow::types::Hash>> {
+            Resolver::new(None).resolve_hash(choice)
+        }
+
+        fn resolve_cipher(&self, choice: &snow::params::CipherChoice) -> Option<Box<dyn snow::types::Cipher>> {
+            let index = self.num_ciphers.fetch_add(1, Ordering::SeqCst);
+            let trace = self.ciphers.clone();
+            Resolver::new(None).resolve_cipher(choice)
+                .map(|inner| Box::new(TraceCipher { inner, index, trace }) as Box<_>)
+        }
+    }
+
+    struct TraceDh {
+        inner: Box<dyn snow::types::Dh>,
+        index: usize,
+        trace: Trace
+    }
+
+    impl snow::types::Dh for TraceDh {
+        fn name(&self) -> &'static str {
+            self.inner.name()
+        }
+
+        fn pub_len(&self) -> usize {
+            self.inner.pub_len()
+        }
+
+        fn priv_len(&self) -> usize {
+            self.inner.priv_len()
+        }
+
+        fn set(&mut self, privkey: &[u8]) {
+            self.trace.record(self.index, "set");
+            self.inner.set(privkey)
+        }
+
+        fn generate(&mut self, rng: &mut dyn snow::types::Random) {
+            self.trace.record(self.index, "generate");
+            self.inner.generate(rng)
+        }
+
+        fn pubkey(&self) -> &[u8] {
+            self.inner.pubkey()
+        }
+
+        fn privkey(&self) -> &[u8] {
+            self.inner.privkey()
+        }
+
+        fn dh(&self, pubkey: &[u8], out: &mut [u8]) -> Result<(), ()> {
+            self.inner.dh(pubkey, out)
+        }
+    }
+
+    struct TraceCipher {
+        inner: Box<dyn snow::types::Cipher>,
+        index: usize,
+        trace: Trace
+    }
+
+    impl snow::types::Cipher for TraceCipher {
+        fn name(&self) -> &'static str {
+            self.inner.name()
+        }
+
+        fn set(&mut self, key: &[u8]) {
+            self.trace.record(self.index, "set");
+            self.inner.set(key)
+        }
+
+        fn encrypt(&self, nonce: u64, authtext: &[u8], plaintext: &[u8], out: &mut [u8]) -> usize {
+            self.trace.record(self.index, "encrypt");
+            self.inner.encrypt(nonce, authtext, plaintext, out)
+        }
+
+        fn decrypt(&self, nonce: u64, authtext: &[u8], ciphertext: &[u8], out: &mut [u8]) -> Result<usize, ()> {
+            self.trace.record(self.index, "decrypt");
+            self.inner.decrypt(nonce, authtext, ciphertext, out)
+        }
+
+        fn rekey(&mut self) {
+            self.inner.rekey()
+        }
+    }
+
+    /// Performs an XX handshake whose initiator uses the given resolver,
+    /// returning the initiator and the responder in transport mode.
+    fn handshake(resolver: TraceResolver) -> (snow::TransportState, snow::TransportState) {
+        let params: snow::params::NoiseParams = "Noise_XX_25519_ChaChaPoly_SHA256".parse().unwrap();
+        let initiator_keys = Keypair::<x25519::X25519>::new();
+        let responder_keys = Keypair::<x25519::X25519>::new();
+        let mut initiator = snow::Builder::with_resolver(params.clone(), Box::new(resolver))
+            .local_private_key(initiator_keys.secret().as_ref())
+            .build_initiator()
+            .unwrap();
+        let mut responder = snow::Builder::with_resolver(params, Box::new(Resolver::new(None)))
+            .local_private_key(responder_keys.secret().as_ref())
+            .build_responder()
+            .unwrap();
+
+        let (mut msg, mut payload) = ([0u8; 1024], [0u8; 1024]);
+        let n = initiator.write_message(&[], &mut msg).unwrap();
+        responder.read_message(&msg[.. n], &mut payload).unwrap();
+        let n = responder.write_message(&[], &mut msg).unwrap();
+        initiator.read_message(&msg[.. n], &mut payload).unwrap();
+        let n = initiator.write_message(&[], &mut msg).unwrap();
+        responder.read_message(&msg[.. n], &mut payload).unwrap();
+
+        (initiator.into_transport_mode().unwrap(), responder.into_transport_mode().unwrap())
+    }
+
+    #[test]
+    fn static_dh_is_resolved_before_ephemeral_dh() {
+        let resolver = TraceResolver::default();
+        let dh = resolver.dh.clone();
+        handshake(resolver);
+
+        // The first DH function is set to the static key, the second
+        // generates the ephemeral key.
+        assert_eq!(dh.take(), vec![(0, "set"), (1, "generate")]);
+    }
+
+    #[test]
+    fn handshake_cipher_is_resolved_before_session_ciphers() {
+        let resolver = TraceResolver::default();
+        let ciphers = resolver.ciphers.clone();
+        let (mut initiator, mut responder) = handshake(resolver);
+
+        // The first cipher is the one of the handshake, the other two are
+        // only set to the session keys at the end of the handshake.
+        let trace = ciphers.take();
+        assert_eq!(trace[0].0, 0);
+        let session = trace.into_iter().filter(|(i, _)| *i != 0).collect::<Vec<_>>();
+        assert_eq!(session, vec![(1, "set"), (2, "set")]);
+
+        // The second cipher is the one for traffic from the initiator to
+        // the responder, the third the one for the opposite direction.
+        let (mut msg, mut payload) = ([0u8; 1024], [0u8; 1024]);
+        let n = initiator.write_message(b"ping", &mut msg).unwrap();
+        responder.read_message(&msg[.. n], &mut payload).unwrap();
+        let n = responder.write_message(b"pong", &mut msg).unwrap();
+        initiator.read_message(&msg[.. n], &mut payload).unwrap();
+        assert_eq!(ciphers.take(), vec![(1, "encrypt"), (2, "decrypt")]);
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The Kyber768 KEM of the hybrid key exchange, see
+//! [`NoiseConfig::with_hybrid_kyber768`](crate::NoiseConfig::with_hybrid_kyber768).
+
+use pqcrypto_kyber::kyber768;
+use pqcrypto_traits::kem::{Ciphertext, PublicKey, SharedSecret};
+
+/// A `snow` KEM for Kyber768, based on `pqcrypto-kyber`.
+#[derive(Default)]
+pub(crate) struct Kyber768 {
+    /// The ephemeral keypair, once generated.
+    keypair: Option<(kyber768::PublicKey, kyber768::SecretKey)>
+}
+
+impl snow::types::Kem for Kyber768 {
+    fn name(&self) -> &'static str {
+        "Kyber768"
+    }
+
+    fn pub_len(&self) -> usize {
+        kyber768::public_key_bytes()
+    }
+
+    fn ciphertext_len(&self) -> usize {
+        kyber768::ciphertext_bytes()
+    }
+
+    fn shared_secret_len(&self) -> usize {
+        kyber768::shared_secret_bytes()
+    }
+
+    fn generate(&mut self, _: &mut dyn snow::types::Random) {
+        // `pqcrypto` draws its randomness from the operating system.
+        self.keypair = Some(kyber768::keypair())
+    }
+
+    fn pubkey(&self) -> &[u8] {
+        self.keypair.as_ref().map_or(&[], |(pk, _)| pk.as_bytes())
+    }
+
+    fn encapsulate(&self, pubkey: &[u8], shared_secret_out: &mut [u8], ciphertext_out: &mut [u8])
+        -> Result<(usize, usize), ()>
+    {
+        let pk = kyber768::PublicKey::from_bytes(pubkey).map_err(|_| ())?;
+        let (ss, ct) = kyber768::encapsulate(&pk);
+        let (ss, ct) = (ss.as_bytes(), ct.as_bytes());
+        shared_secret_out[.. ss.len()].copy_from_slice(ss);
+        ciphertext_out[.. ct.len()].copy_from_slice(ct);
+        Ok((ss.len(), ct.len()))
+    }
+
+    fn decapsulate(&self, ciphertext: &[u8], shared_secret_out: &mut [u8]) -> Result<usize, ()> {
+        let (_, sk) = self.keypair.as_ref().ok_or(())?;
+        let ct = kyber768::Ciphertext::from_bytes(ciphertext).map_err(|_| ())?;
+        let ss = kyber768::decapsulate(&ct, sk);
+        let ss = ss.as_bytes();
+        shared_secret_out[.. ss.len()].copy_from_slice(ss);
+        Ok(ss.len())
+    }
+}
@@ -47,6 +47,33 @@ lazy_static! {
         .parse()
         .map(ProtocolParams)
         .expect("Invalid protocol name");
+
+    static ref PARAMS_NX: ProtocolParams = "Noise_NX_25519_ChaChaPoly_SHA256"
+        .parse()
+        .map(ProtocolParams)
+        .expect("Invalid protocol name");
+
+    static ref PARAMS_XN: ProtocolParams = "Noise_XN_25519_ChaChaPoly_SHA256"
+        .parse()
+        .map(ProtocolParams)
+        .expect("Invalid protocol name");
+
+    static ref PARAMS_KK: ProtocolParams = "Noise_KK_25519_ChaChaPoly_SHA256"
+        .parse()
+        .map(ProtocolParams)
+        .expect("Invalid protocol name");
+}
+
+/// The libp2p protocol name of a Noise protocol over X25519 with the given
+/// handshake pattern and the key exchange and cipher suite of the given parameters.
+///
+/// No protocol name exists for an unsupported cipher suite, in which case
+/// no protocol is offered and the negotiation fails.
+fn protocol_name(pattern: &str, params: &ProtocolParams) -> Option<Vec<u8>> {
+    let (cipher, hash) = params.cipher_suite().ok()?;
+    let name = format!("/noise/{}/{}/{}/{}/0.1.0", pattern, params.dh_protocol_name(),
+        cipher.protocol_name(), hash.protocol_name());
+    Some(name.into_bytes())
 }
 
 /// A X25519 key.
@@ -66,29 +93,74 @@ impl Zeroize for X25519 {
 }
 
 impl UpgradeInfo for NoiseConfig<IX, X25519> {
-    type Info = &'static [u8];
-    type InfoIter = std::iter::Once<Self::Info>;
+    type Info = Vec<u8>;
+    type InfoIter = std::option::IntoIter<Self::Info>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        std::iter::once(b"/noise/ix/25519/chachapoly/sha256/0.1.0")
+        protocol_name("ix", &self.params).into_iter()
     }
 }
 
 impl UpgradeInfo for NoiseConfig<XX, X25519> {
-    type Info = &'static [u8];
-    type InfoIter = std::iter::Once<Self::Info>;
+    type Info = Vec<u8>;
+    type InfoIter = std::option::IntoIter<Self::Info>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        std::iter::once(b"/noise/xx/25519/chachapoly/sha256/0.1.0")
+        protocol_name("xx", &self.params).into_iter()
     }
 }
 
 impl<R> UpgradeInfo for NoiseConfig<IK, X25519, R> {
-    type Info = &'static [u8];
-    type InfoIter = std::iter::Once<Self::Info>;
+    type Info = Vec<u8>;
+    type InfoIter = std::option::IntoIter<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        protocol_name("ik", &self.params).into_iter()
+    }
+}
+
+impl<R> UpgradeInfo for NoiseConfig<IKXX, X25519, R> {
+    type Info = Vec<u8>;
+    type InfoIter = std::option::IntoIter<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        protocol_name("ikxx", &self.params).into_iter()
+    }
+}
+
+impl UpgradeInfo for NoiseConfig<NX, X25519> {
+    type Info = Vec<u8>;
+    type InfoIter = std::option::IntoIter<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        protocol_name("nx", &self.params).into_iter()
+    }
+}
+
+impl UpgradeInfo for NoiseConfig<XN, X25519> {
+    type Info = Vec<u8>;
+    type InfoIter = std::option::IntoIter<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        protocol_name("xn", &self.params).into_iter()
+    }
+}
+
+impl<R> UpgradeInfo for NoiseConfig<KK, X25519, R> {
+    type Info = Vec<u8>;
+    type InfoIter = std::option::IntoIter<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        protocol_name("kk", &self.params).into_iter()
+    }
+}
+
+impl<R> UpgradeInfo for NoiseConfig<Resume, X25519, R> {
+    type Info = Vec<u8>;
+    type InfoIter = std::option::IntoIter<Self::Info>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        std::iter::once(b"/noise/ik/25519/chachapoly/sha256/0.1.0")
+        protocol_name("resume", &self.params).into_iter()
     }
 }
 
@@ -106,6 +178,18 @@ impl Protocol<X25519> for X25519 {
         PARAMS_XX.clone()
     }
 
+    fn params_nx() -> ProtocolParams {
+        PARAMS_NX.clone()
+    }
+
+    fn params_xn() -> ProtocolParams {
+        PARAMS_XN.clone()
+    }
+
+    fn params_kk() -> ProtocolParams {
+        PARAMS_KK.clone()
+    }
+
     fn public_from_bytes(bytes: &[u8]) -> Result<PublicKey<X25519>, NoiseError> {
         if bytes.len() != 32 {
             return Err(NoiseError::InvalidKey)
@@ -130,7 +214,8 @@ impl Keypair<X25519> {
     pub(super) fn default() -> Self {
         Keypair {
             secret: SecretKey(X25519([0u8; 32])),
-            public: PublicKey(X25519([0u8; 32]))
+            public: PublicKey(X25519([0u8; 32])),
+            external: None
         }
     }
 
@@ -143,6 +228,37 @@ impl Keypair<X25519> {
         Self::from(sk)
     }
 
+    /// Create a new X25519 keypair with the random number generator and the
+    /// DH function of the given `snow` crypto resolver, e.g. the one given to
+    /// [`NoiseConfig::with_crypto_resolver`] or a [`CryptoBackend`].
+    ///
+    /// Primitives not provided by the resolver fall back to the built-in
+    /// implementations, as they do for the handshake.
+    pub fn generate_with<R>(resolver: &R) -> Result<Keypair<X25519>, NoiseError>
+    where
+        R: snow::resolvers::CryptoResolver + ?Sized
+    {
+        use snow::{error::InitStage, params::DHChoice, resolvers::CryptoResolver};
+
+        let builtin = Resolver::new(None);
+        let mut rng = resolver.resolve_rng()
+            .or_else(|| builtin.resolve_rng())
+            .ok_or(NoiseError::Noise(InitStage::GetRngImpl.into()))?;
+        let mut dh = resolver.resolve_dh(&DHChoice::Curve25519)
+            .or_else(|| builtin.resolve_dh(&DHChoice::Curve25519))
+            .ok_or(NoiseError::Noise(InitStage::GetDhImpl.into()))?;
+        dh.generate(&mut *rng);
+        if dh.privkey().len() != 32 {
+            return Err(NoiseError::InvalidKey)
+        }
+        let public = X25519::public_from_bytes(dh.pubkey())?;
+        let mut sk_bytes = [0u8; 32];
+        sk_bytes.copy_from_slice(dh.privkey());
+        let secret = SecretKey(X25519(sk_bytes)); // Copy
+        sk_bytes.zeroize();
+        Ok(Keypair { secret, public, external: None })
+    }
+
     /// Creates an X25519 `Keypair` from an [`identity::Keypair`], if possible.
     ///
     /// The returned keypair will be [associated with](KeypairIdentity) the
@@ -180,7 +296,7 @@ impl Keypair<X25519> {
 impl From<SecretKey<X25519>> for Keypair<X25519> {
     fn from(secret: SecretKey<X25519>) -> Keypair<X25519> {
         let public = PublicKey(X25519(x25519((secret.0).0, X25519_BASEPOINT_BYTES)));
-        Keypair { secret, public }
+        Keypair { secret, public, external: None }
     }
 }
 
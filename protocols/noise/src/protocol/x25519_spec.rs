@@ -0,0 +1,150 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! [libp2p-noise-spec] compliant Noise protocols based on X25519.
+//!
+//! [libp2p-noise-spec]: https://github.com/libp2p/specs/tree/master/noise
+
+use crate::{NoiseConfig, NoiseError, Protocol, ProtocolParams};
+use libp2p_core::UpgradeInfo;
+use libp2p_core::identity;
+use rand::Rng;
+use x25519_dalek::{X25519_BASEPOINT_BYTES, x25519};
+use zeroize::Zeroize;
+
+use super::{*, x25519::X25519};
+
+/// Prefix of static key signatures for domain separation.
+const STATIC_KEY_DOMAIN: &str = "noise-libp2p-static-key:";
+
+/// A X25519 key for use with the Noise protocols of the libp2p Noise
+/// specification.
+///
+/// Contrary to [`X25519`], the static DH public key is always signed with
+/// the identity key, using the domain separation of the specification.
+#[derive(Clone)]
+pub struct X25519Spec([u8; 32]);
+
+impl AsRef<[u8]> for X25519Spec {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+impl Zeroize for X25519Spec {
+    fn zeroize(&mut self) {
+        self.0.zeroize()
+    }
+}
+
+impl Keypair<X25519Spec> {
+    /// Create a new X25519 keypair.
+    pub fn new() -> Keypair<X25519Spec> {
+        let mut sk_bytes = [0u8; 32];
+        rand::thread_rng().fill(&mut sk_bytes);
+        let sk = SecretKey(X25519Spec(sk_bytes)); // Copy
+        sk_bytes.zeroize();
+        Self::from(sk)
+    }
+}
+
+impl Default for Keypair<X25519Spec> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Promote a X25519 secret key into a keypair.
+impl From<SecretKey<X25519Spec>> for Keypair<X25519Spec> {
+    fn from(secret: SecretKey<X25519Spec>) -> Keypair<X25519Spec> {
+        let public = PublicKey(X25519Spec(x25519((secret.0).0, X25519_BASEPOINT_BYTES)));
+        Keypair { secret, public, external: None }
+    }
+}
+
+/// The specification only defines the `XX` handshake pattern with the
+/// default cipher suite, which is negotiated as `/noise`. Other cipher
+/// suites and the hybrid key exchange are negotiated with non-standard
+/// protocol names.
+impl UpgradeInfo for NoiseConfig<XX, X25519Spec> {
+    type Info = Vec<u8>;
+    type InfoIter = std::option::IntoIter<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        let suite = self.params.cipher_suite().ok();
+        let name = suite.map(|suite| match (suite, self.params.is_hybrid()) {
+            ((Cipher::ChaChaPoly, Hash::Sha256), false) => b"/noise".to_vec(),
+            ((cipher, hash), false) => format!("/noise/{}/{}", cipher.protocol_name(), hash.protocol_name())
+                .into_bytes(),
+            ((cipher, hash), true) => format!("/noise/{}/{}/{}", self.params.dh_protocol_name(),
+                cipher.protocol_name(), hash.protocol_name())
+                .into_bytes()
+        });
+        name.into_iter()
+    }
+}
+
+/// Noise protocols for X25519 with libp2p-spec compliant signatures.
+impl Protocol<X25519Spec> for X25519Spec {
+    fn params_ik() -> ProtocolParams {
+        X25519::params_ik()
+    }
+
+    fn params_ix() -> ProtocolParams {
+        X25519::params_ix()
+    }
+
+    fn params_xx() -> ProtocolParams {
+        X25519::params_xx()
+    }
+
+    fn params_nx() -> ProtocolParams {
+        X25519::params_nx()
+    }
+
+    fn params_xn() -> ProtocolParams {
+        X25519::params_xn()
+    }
+
+    fn params_kk() -> ProtocolParams {
+        X25519::params_kk()
+    }
+
+    fn public_from_bytes(bytes: &[u8]) -> Result<PublicKey<X25519Spec>, NoiseError> {
+        if bytes.len() != 32 {
+            return Err(NoiseError::InvalidKey)
+        }
+        let mut pk = [0u8; 32];
+        pk.copy_from_slice(bytes);
+        Ok(PublicKey(X25519Spec(pk)))
+    }
+
+    fn verify(id_pk: &identity::PublicKey, dh_pk: &PublicKey<X25519Spec>, sig: &Option<Vec<u8>>) -> bool {
+        if let Some(s) = sig {
+            id_pk.verify(&[STATIC_KEY_DOMAIN.as_bytes(), dh_pk.as_ref()].concat(), s)
+        } else {
+            false
+        }
+    }
+
+    fn sign(id_keys: &identity::Keypair, dh_pk: &PublicKey<X25519Spec>) -> Result<Vec<u8>, NoiseError> {
+        Ok(id_keys.sign(&[STATIC_KEY_DOMAIN.as_bytes(), dh_pk.as_ref()].concat())?)
+    }
+}
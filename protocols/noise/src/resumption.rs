@@ -0,0 +1,318 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Resumption of noise sessions.
+//!
+//! Sessions established with a [`ResumptionCache`] configured via
+//! [`NoiseConfig::with_resumption`](crate::NoiseConfig::with_resumption)
+//! issue a resumption ticket to both parties, derived from the cipher keys
+//! of the session. A later connection to the same remote can then be
+//! secured with a single roundtrip `KK` handshake that mixes in the secret
+//! of the ticket as a pre-shared key, see
+//! [`NoiseConfig::resume_dialer`](crate::NoiseConfig::resume_dialer).
+//!
+//! Tickets are single-use: a ticket is removed from the cache once used for
+//! a resumption attempt, whether the attempt succeeds or not, and every
+//! resumed session issues a fresh ticket in turn.
+
+use crate::{PreSharedKey, protocol::CapturedKeys};
+use libp2p_core::{identity, PeerId};
+use ring::digest::{SHA256, digest};
+use std::{collections::HashMap, fmt, sync::{Arc, Mutex}, time::Duration};
+use wasm_timer::Instant;
+
+/// The length of the ID of a ticket.
+pub(crate) const TICKET_ID_LEN: usize = 32;
+
+/// The label for deriving the secret of a ticket from a session.
+const SECRET_LABEL: &[u8] = b"libp2p-noise-resumption-secret";
+
+/// The label for deriving the ID of a ticket from its secret.
+const ID_LABEL: &[u8] = b"libp2p-noise-resumption-id";
+
+/// A resumption ticket for a session with a remote.
+///
+/// The secret of the ticket is only known to the two parties of the
+/// session that issued the ticket, whereas the ID of the ticket is sent
+/// in plaintext when resuming.
+#[derive(Clone)]
+pub struct Ticket {
+    id: [u8; TICKET_ID_LEN],
+    secret: PreSharedKey,
+    remote_id: identity::PublicKey,
+    remote_dh: Vec<u8>,
+    expires: Instant
+}
+
+impl Ticket {
+    /// The public identity key of the remote the ticket was issued for.
+    pub fn remote(&self) -> &identity::PublicKey {
+        &self.remote_id
+    }
+
+    /// The ID of the ticket.
+    pub(crate) fn id(&self) -> &[u8; TICKET_ID_LEN] {
+        &self.id
+    }
+
+    /// The secret of the ticket.
+    pub(crate) fn secret(&self) -> &PreSharedKey {
+        &self.secret
+    }
+
+    /// The static DH public key of the remote the ticket was issued for.
+    pub(crate) fn remote_dh(&self) -> &[u8] {
+        &self.remote_dh
+    }
+}
+
+impl fmt::Debug for Ticket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ticket")
+            .field("remote", &self.remote_id.clone().into_peer_id())
+            .finish()
+    }
+}
+
+/// A cache of resumption tickets, keyed by the peer ID of the remote.
+///
+/// Clones share the cached tickets, so the same cache can be given to
+/// the configurations of all connections of a node.
+#[derive(Clone)]
+pub struct ResumptionCache(Arc<Mutex<Tickets>>);
+
+/// The tickets of a [`ResumptionCache`].
+struct Tickets {
+    /// The maximum number of tickets.
+    capacity: usize,
+    /// The duration for which a ticket can be used after it is issued.
+    lifetime: Duration,
+    /// The tickets by peer ID of the remote.
+    by_peer: HashMap<PeerId, Ticket>,
+    /// The peer IDs of the remotes by ticket ID.
+    by_id: HashMap<[u8; TICKET_ID_LEN], PeerId>
+}
+
+impl Tickets {
+    /// Remove the ticket for the given remote, if any.
+    fn remove(&mut self, peer: &PeerId) -> Option<Ticket> {
+        let ticket = self.by_peer.remove(peer)?;
+        self.by_id.remove(&ticket.id);
+        Some(ticket)
+    }
+}
+
+impl ResumptionCache {
+    /// Create a new cache holding at most `capacity` tickets, each of which
+    /// can be used for resumption within `lifetime` after it was issued.
+    ///
+    /// Once the cache is full, the ticket closest to its expiry is evicted
+    /// to make room for a new ticket.
+    pub fn new(capacity: usize, lifetime: Duration) -> Self {
+        ResumptionCache(Arc::new(Mutex::new(Tickets {
+            capacity,
+            lifetime,
+            by_peer: HashMap::new(),
+            by_id: HashMap::new()
+        })))
+    }
+
+    /// Whether the cache holds an unexpired ticket for the given remote.
+    pub fn contains(&self, peer: &PeerId) -> bool {
+        self.lock().by_peer.get(peer).map_or(false, |t| t.expires > Instant::now())
+    }
+
+    /// Remove the ticket for the given remote, if any, e.g. because the
+    /// remote is no longer trusted.
+    pub fn remove(&self, peer: &PeerId) {
+        self.lock().remove(peer);
+    }
+
+    /// Remove and return the unexpired ticket for the given remote, if any.
+    pub(crate) fn take(&self, peer: &PeerId) -> Option<Ticket> {
+        self.lock().remove(peer).filter(|t| t.expires > Instant::now())
+    }
+
+    /// Remove and return the unexpired ticket with the given ID, if any.
+    pub(crate) fn take_by_id(&self, id: &[u8; TICKET_ID_LEN]) -> Option<Ticket> {
+        let mut tickets = self.lock();
+        let peer = tickets.by_id.get(id)?.clone();
+        tickets.remove(&peer).filter(|t| t.expires > Instant::now())
+    }
+
+    /// Insert a ticket, replacing any previous ticket for the same remote.
+    fn insert(&self, ticket: Ticket) {
+        let mut tickets = self.lock();
+        if tickets.capacity == 0 {
+            return
+        }
+        let peer = ticket.remote_id.clone().into_peer_id();
+        tickets.remove(&peer);
+        let now = Instant::now();
+        let expired = tickets.by_peer.iter()
+            .filter(|(_, t)| t.expires <= now)
+            .map(|(p, _)| p.clone())
+            .collect::<Vec<_>>();
+        for p in expired {
+            tickets.remove(&p);
+        }
+        if tickets.by_peer.len() >= tickets.capacity {
+            let oldest = tickets.by_peer.iter()
+                .min_by_key(|(_, t)| t.expires)
+                .map(|(p, _)| p.clone());
+            if let Some(p) = oldest {
+                tickets.remove(&p);
+            }
+        }
+        tickets.by_id.insert(ticket.id, peer.clone());
+        tickets.by_peer.insert(peer, ticket);
+    }
+
+    /// The lifetime of the tickets of the cache.
+    fn lifetime(&self) -> Duration {
+        self.lock().lifetime
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Tickets> {
+        self.0.lock().expect("Resumption cache mutex is not poisoned.")
+    }
+}
+
+impl fmt::Debug for ResumptionCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResumptionCache")
+            .field("tickets", &self.lock().by_peer.len())
+            .finish()
+    }
+}
+
+/// The resumption configuration of a session, capturing the cipher keys
+/// of the session in order to issue a ticket once the session is established.
+///
+/// Clones share the captured keys. Use [`Resumption::session`] to obtain
+/// a configuration for a new session.
+#[derive(Debug, Clone)]
+pub(crate) struct Resumption {
+    cache: ResumptionCache,
+    keys: CapturedKeys
+}
+
+impl Resumption {
+    pub(crate) fn new(cache: ResumptionCache) -> Self {
+        Resumption { cache, keys: CapturedKeys::default() }
+    }
+
+    /// Create a configuration with the same cache for a new session.
+    pub(crate) fn session(&self) -> Self {
+        Resumption::new(self.cache.clone())
+    }
+
+    /// The cache receiving the tickets issued for the session.
+    pub(crate) fn cache(&self) -> &ResumptionCache {
+        &self.cache
+    }
+
+    /// The keys captured for the session.
+    pub(crate) fn keys(&self) -> &CapturedKeys {
+        &self.keys
+    }
+
+    /// Issue a ticket for the established session with the remote with
+    /// the given public identity key and static DH public key.
+    pub(crate) fn issue(&self, remote_id: &identity::PublicKey, remote_dh: &[u8], handshake_hash: &[u8]) {
+        let secret = self.keys.with(|initiator_key, responder_key| {
+            let input = [SECRET_LABEL, handshake_hash, initiator_key, responder_key].concat();
+            let mut secret = [0u8; 32];
+            secret.copy_from_slice(digest(&SHA256, &input).as_ref());
+            secret
+        });
+        let secret = match secret {
+            Some(secret) => PreSharedKey::new(secret),
+            None => {
+                log::warn!("No session keys captured for issuing a resumption ticket.");
+                return
+            }
+        };
+        let mut id = [0u8; TICKET_ID_LEN];
+        id.copy_from_slice(digest(&SHA256, &[ID_LABEL, secret.as_ref()].concat()).as_ref());
+        self.cache.insert(Ticket {
+            id,
+            secret,
+            remote_id: remote_id.clone(),
+            remote_dh: remote_dh.to_vec(),
+            expires: Instant::now() + self.cache.lifetime()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticket(id: u8, lifetime: Duration) -> Ticket {
+        Ticket {
+            id: [id; TICKET_ID_LEN],
+            secret: PreSharedKey::new([id; 32]),
+            remote_id: identity::Keypair::generate_ed25519().public(),
+            remote_dh: vec![id; 32],
+            expires: Instant::now() + lifetime
+        }
+    }
+
+    #[test]
+    fn tickets_are_single_use() {
+        let cache = ResumptionCache::new(8, Duration::from_secs(60));
+        let t1 = ticket(1, Duration::from_secs(60));
+        let t2 = ticket(2, Duration::from_secs(60));
+        let p1 = t1.remote().clone().into_peer_id();
+        let p2 = t2.remote().clone().into_peer_id();
+        cache.insert(t1);
+        cache.insert(t2);
+        assert!(cache.contains(&p1));
+        assert_eq!(cache.take(&p1).map(|t| t.id), Some([1; TICKET_ID_LEN]));
+        assert!(cache.take(&p1).is_none());
+        assert_eq!(cache.take_by_id(&[2; TICKET_ID_LEN]).map(|t| t.id), Some([2; TICKET_ID_LEN]));
+        assert!(!cache.contains(&p2));
+        assert!(cache.take_by_id(&[2; TICKET_ID_LEN]).is_none());
+    }
+
+    #[test]
+    fn expired_tickets_are_unusable() {
+        let cache = ResumptionCache::new(8, Duration::from_secs(60));
+        let t = ticket(1, Duration::from_secs(0));
+        let p = t.remote().clone().into_peer_id();
+        cache.insert(t);
+        assert!(!cache.contains(&p));
+        assert!(cache.take(&p).is_none());
+    }
+
+    #[test]
+    fn capacity_is_bounded() {
+        let cache = ResumptionCache::new(2, Duration::from_secs(60));
+        let tickets = (1 ..= 3).map(|i| ticket(i, Duration::from_secs(60 + u64::from(i)))).collect::<Vec<_>>();
+        let peers = tickets.iter().map(|t| t.remote().clone().into_peer_id()).collect::<Vec<_>>();
+        for t in tickets {
+            cache.insert(t)
+        }
+        assert!(!cache.contains(&peers[0]));
+        assert!(cache.contains(&peers[1]));
+        assert!(cache.contains(&peers[2]));
+    }
+}
@@ -18,14 +18,17 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use bytes::Bytes;
 use futures::{future::{self, Either}, prelude::*};
-use libp2p_core::identity;
-use libp2p_core::upgrade::{self, Negotiated, apply_inbound, apply_outbound};
+use libp2p_core::{either::EitherError, identity, UpgradeInfo};
+use libp2p_core::upgrade::{self, Negotiated, SecuredStream, UpgradeError, apply_inbound, apply_outbound};
 use libp2p_core::transport::{Transport, ListenerEvent};
-use libp2p_noise::{Keypair, X25519, NoiseConfig, RemoteIdentity, NoiseError, NoiseOutput};
+use libp2p_noise::{Keypair, X25519, NoiseConfig, RemoteIdentity, NoiseError, NoiseOutput, Padding, PreSharedKey};
+use libp2p_noise::{Cipher, Hash, LegacyConfig, NoiseEvent, ResumptionCache, X25519Spec};
 use libp2p_tcp::{TcpConfig, TcpTransStream};
 use log::info;
 use quickcheck::QuickCheck;
+use std::time::Duration;
 
 #[allow(dead_code)]
 fn core_upgrade_compat() {
@@ -67,6 +70,1434 @@ fn xx() {
     QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<u8>) -> bool)
 }
 
+#[test]
+fn xx_spec() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_id_public = server_id.public();
+    let client_id_public = client_id.public();
+
+    let server_dh = Keypair::<X25519Spec>::new().into_authentic(&server_id).unwrap();
+    let info = NoiseConfig::xx(server_dh.clone()).protocol_info().next();
+    assert_eq!(info, Some(b"/noise".to_vec()));
+
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(server_dh), endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |output, _| expect_identity(output, &client_id_public));
+
+    let client_dh = Keypair::<X25519Spec>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(client_dh), endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |output, _| expect_identity(output, &server_id_public));
+
+    run(server_transport, client_transport, b"hello".to_vec());
+}
+
+#[test]
+fn xx_legacy() {
+    let _ = env_logger::try_init();
+    let legacy = LegacyConfig { send_legacy_handshake: true, recv_legacy_handshake: true };
+    for &(server_legacy, client_legacy) in &[(legacy, Default::default()), (Default::default(), legacy)] {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_id_public = server_id.public();
+        let client_id_public = client_id.public();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh.clone()).with_legacy_config(server_legacy);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(client_dh.clone()).with_legacy_config(client_legacy);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, b"hello".to_vec());
+    }
+}
+
+#[test]
+fn xx_early_data() {
+    let _ = env_logger::try_init();
+    fn prop(message: Vec<u8>, server_data: Vec<u8>, client_data: Vec<u8>) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_id_public = server_id.public();
+        let client_id_public = client_id.public();
+
+        let server_data2 = server_data.clone();
+        let client_data2 = client_data.clone();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh).with_early_data(server_data);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public))
+            .and_then(move |out, _| expect_early_data(out, &client_data2));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(client_dh).with_early_data(client_data);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public))
+            .and_then(move |out, _| expect_early_data(out, &server_data2));
+
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<u8>, Vec<u8>, Vec<u8>) -> bool)
+}
+
+#[test]
+fn xx_stream_muxers() {
+    let _ = env_logger::try_init();
+    // The multiplexers of the dialer, i.e. the initiator, take precedence.
+    let server_muxers = vec!["/yamux/1.0.0", "/mplex/6.7.0"];
+    let client_muxers = vec!["/mplex/6.7.0", "/yamux/1.0.0"];
+    for &(server_muxers, client_muxers, expected) in &[
+        (&server_muxers[..], &client_muxers[..], Some("/mplex/6.7.0")),
+        (&server_muxers[.. 1], &client_muxers[..], Some("/yamux/1.0.0")),
+        (&server_muxers[.. 1], &client_muxers[.. 1], None),
+        (&[][..], &client_muxers[..], None),
+    ] {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_muxers = server_muxers.to_vec();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh).with_stream_muxers(server_muxers);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_early_muxer(out, expected));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_muxers = client_muxers.to_vec();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(client_dh).with_stream_muxers(client_muxers);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_early_muxer(out, expected));
+
+        run(server_transport, client_transport, b"hello".to_vec());
+    }
+}
+
+#[test]
+fn xx_psk() {
+    let _ = env_logger::try_init();
+    fn prop(message: Vec<u8>) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_id_public = server_id.public();
+        let client_id_public = client_id.public();
+
+        let psk = PreSharedKey::new(rand::random());
+        let psk2 = psk.clone();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh).with_psk(psk);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(client_dh).with_psk(psk2);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(10).quickcheck(prop as fn(Vec<u8>) -> bool)
+}
+
+#[test]
+fn xx_psk_mismatch() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh).with_psk(PreSharedKey::new([1; 32]));
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        });
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(client_dh).with_psk(PreSharedKey::new([2; 32]));
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        });
+
+    futures::executor::block_on(async {
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let client_fut = async {
+            let result = client_transport.dial(server_address).unwrap().await;
+            assert!(result.is_err());
+        };
+
+        let server_fut = async {
+            let result = server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade")
+                .await;
+            assert!(result.is_err());
+        };
+
+        futures::future::join(server_fut, client_fut).await;
+    })
+}
+
+#[test]
+fn xx_prologue() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_id_public = server_id.public();
+    let client_id_public = client_id.public();
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh).with_prologue(b"certhash".to_vec());
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(client_dh).with_prologue(b"certhash".to_vec());
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+    run(server_transport, client_transport, b"hello".to_vec());
+}
+
+#[test]
+fn xx_prologue_mismatch() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh).with_prologue(b"server".to_vec());
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        });
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(client_dh).with_prologue(b"client".to_vec());
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        });
+
+    futures::executor::block_on(async {
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let client_fut = async {
+            let result = client_transport.dial(server_address).unwrap().await;
+            assert!(result.is_err());
+        };
+
+        let server_fut = async {
+            let result = server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade")
+                .await;
+            assert!(result.is_err());
+        };
+
+        futures::future::join(server_fut, client_fut).await;
+    })
+}
+
+#[test]
+fn xx_plaintext() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_id_public = server_id.public();
+    let client_id_public = client_id.public();
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh)
+                .dangerous_disable_encryption()
+                .with_prologue(b"certhash".to_vec());
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(client_dh)
+                .with_prologue(b"certhash".to_vec())
+                .dangerous_disable_encryption();
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+    run(server_transport, client_transport, b"hello".to_vec());
+}
+
+#[test]
+fn xx_handshake_timeout() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh)
+                .with_inbound_handshake_timeout(Duration::from_millis(100));
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        });
+
+    // The client negotiates the noise protocol but then stalls.
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let stall = upgrade::from_fn(
+                b"/noise/xx/25519/chachapoly/sha256/0.1.0",
+                |socket, _| future::ok::<_, std::io::Error>(socket));
+            upgrade::apply(output, stall, endpoint, upgrade::Version::V1)
+        });
+
+    futures::executor::block_on(async {
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let (tx, rx) = futures::channel::oneshot::channel::<()>();
+
+        let client_fut = async {
+            let _socket = client_transport.dial(server_address).unwrap().await.expect("no error");
+            let _ = rx.await;
+        };
+
+        let server_fut = async {
+            let result = server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade")
+                .await;
+            match result {
+                Err(EitherError::B(UpgradeError::Apply(NoiseError::HandshakeTimeout))) => {}
+                _ => panic!("Unexpected handshake result")
+            }
+            drop(tx)
+        };
+
+        futures::future::join(server_fut, client_fut).await;
+    })
+}
+
+#[test]
+fn xx_identity_verifier() {
+    let _ = env_logger::try_init();
+    fn prop(message: Vec<u8>) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_id_public = server_id.public();
+        let client_id_public = client_id.public();
+        let client_peer_id = client_id_public.clone().into_peer_id();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh)
+                    .with_identity_verifier(move |peer_id, _, dh_pk| {
+                        peer_id == &client_peer_id && dh_pk.len() == 32
+                    });
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                upgrade::apply(output, NoiseConfig::xx(client_dh), endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(10).quickcheck(prop as fn(Vec<u8>) -> bool)
+}
+
+#[test]
+fn xx_identity_rejected() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh).with_identity_verifier(|_, _, _| false);
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        });
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(client_dh), endpoint, upgrade::Version::V1)
+        });
+
+    futures::executor::block_on(async {
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let client_fut = async {
+            // The client completes its side of the handshake before
+            // the server rejects it.
+            let _ = client_transport.dial(server_address).unwrap().await;
+        };
+
+        let server_fut = async {
+            let result = server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade")
+                .await;
+            match result {
+                Err(EitherError::B(UpgradeError::Apply(NoiseError::IdentityRejected))) => {}
+                _ => panic!("Unexpected handshake result")
+            }
+        };
+
+        futures::future::join(server_fut, client_fut).await;
+    })
+}
+
+#[test]
+fn xx_rekey() {
+    let _ = env_logger::try_init();
+    fn prop(messages: Vec<Vec<u8>>) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh).rekey_after_bytes(64);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            });
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(client_dh).rekey_after_frames(1);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            });
+
+        let expected = messages.concat();
+
+        futures::executor::block_on(async {
+            let mut server = server_transport
+                .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .unwrap();
+
+            let server_address = server.try_next()
+                .await
+                .expect("some event")
+                .expect("no error")
+                .into_new_address()
+                .expect("listen address");
+
+            let client_fut = async {
+                let (_, mut client_session) = client_transport.dial(server_address)
+                    .unwrap()
+                    .await
+                    .expect("no error");
+                for message in &messages {
+                    client_session.write_all(message).await.expect("no error");
+                    client_session.flush().await.expect("no error");
+                }
+                // Echoed messages are received with rekeys in between.
+                let mut client_buffer = vec![0; expected.len()];
+                client_session.read_exact(&mut client_buffer).await.expect("no error");
+                assert_eq!(client_buffer, expected);
+            };
+
+            let server_fut = async {
+                let (_, mut server_session) = server.try_next()
+                    .await
+                    .expect("some event")
+                    .map(ListenerEvent::into_upgrade)
+                    .expect("no error")
+                    .map(|client| client.0)
+                    .expect("listener upgrade")
+                    .await
+                    .expect("no error");
+                let mut server_buffer = vec![0; expected.len()];
+                server_session.read_exact(&mut server_buffer).await.expect("no error");
+                assert_eq!(server_buffer, expected);
+                assert!(server_session.rekey_negotiated());
+                for message in &messages {
+                    assert!(server_session.rekey());
+                    server_session.write_all(message).await.expect("no error");
+                    server_session.flush().await.expect("no error");
+                }
+            };
+
+            futures::future::join(server_fut, client_fut).await;
+        });
+        true
+    }
+    QuickCheck::new().max_tests(10).quickcheck(prop as fn(Vec<Vec<u8>>) -> bool)
+}
+
+#[test]
+fn xx_framed() {
+    let _ = env_logger::try_init();
+    fn prop(messages: Vec<Vec<u8>>) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh).rekey_after_frames(1);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            });
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(client_dh).with_rekeying();
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            });
+
+        // Empty messages are not sent.
+        let expected = messages.iter()
+            .filter(|m| !m.is_empty())
+            .cloned()
+            .map(Bytes::from)
+            .collect::<Vec<_>>();
+
+        futures::executor::block_on(async {
+            let mut server = server_transport
+                .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .unwrap();
+
+            let server_address = server.try_next()
+                .await
+                .expect("some event")
+                .expect("no error")
+                .into_new_address()
+                .expect("listen address");
+
+            let client_fut = async {
+                let (_, client_session) = client_transport.dial(server_address)
+                    .unwrap()
+                    .await
+                    .expect("no error");
+                let mut client_framed = client_session.into_framed();
+                for message in &messages {
+                    client_framed.send(Bytes::from(message.clone())).await.expect("no error");
+                }
+                // Echoed messages preserve their boundaries.
+                let mut received = Vec::new();
+                while received.len() < expected.len() {
+                    received.push(client_framed.try_next().await.expect("no error").expect("message"));
+                }
+                assert_eq!(received, expected);
+            };
+
+            let server_fut = async {
+                let (_, server_session) = server.try_next()
+                    .await
+                    .expect("some event")
+                    .map(ListenerEvent::into_upgrade)
+                    .expect("no error")
+                    .map(|client| client.0)
+                    .expect("listener upgrade")
+                    .await
+                    .expect("no error");
+                let mut server_framed = server_session.into_framed();
+                for message in &expected {
+                    let received = server_framed.try_next().await.expect("no error").expect("message");
+                    assert_eq!(&received, message);
+                    server_framed.send(received).await.expect("no error");
+                }
+            };
+
+            futures::future::join(server_fut, client_fut).await;
+        });
+        true
+    }
+    QuickCheck::new().max_tests(10).quickcheck(prop as fn(Vec<Vec<u8>>) -> bool)
+}
+
+#[test]
+fn xx_write_buffer_len() {
+    let _ = env_logger::try_init();
+    fn prop(server_len: u16, client_len: u16) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_id_public = server_id.public();
+        let client_id_public = client_id.public();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh)
+                    .with_write_buffer_len(usize::from(server_len) + 1);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(client_dh)
+                    .with_write_buffer_len(usize::from(client_len) + 1);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        let message = (0 .. 100_000).map(|i| i as u8).collect();
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(10).quickcheck(prop as fn(u16, u16) -> bool)
+}
+
+#[test]
+fn xx_release_idle_buffers() {
+    let _ = env_logger::try_init();
+    fn prop(message: Vec<u8>) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_id_public = server_id.public();
+        let client_id_public = client_id.public();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh).release_idle_buffers(true);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(client_dh).release_idle_buffers(true);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<u8>) -> bool)
+}
+
+#[test]
+fn xx_channel_binding() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_dh_public = server_dh.public().clone();
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(server_dh), endpoint, upgrade::Version::V1)
+        });
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_dh_public = client_dh.public().clone();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(client_dh), endpoint, upgrade::Version::V1)
+        });
+
+    futures::executor::block_on(async {
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let client_fut = async {
+            client_transport.dial(server_address)
+                .unwrap()
+                .await
+                .expect("no error")
+        };
+
+        let server_fut = async {
+            server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade")
+                .await
+                .expect("no error")
+        };
+
+        let ((server_remote, server_session), (client_remote, client_session)) =
+            futures::future::join(server_fut, client_fut).await;
+        assert_eq!(server_session.remote_static_dh_key(), Some(client_dh_public.as_ref()));
+        assert_eq!(client_session.remote_static_dh_key(), Some(server_dh_public.as_ref()));
+        assert!(!server_session.handshake_hash().is_empty());
+        assert_eq!(server_session.handshake_hash(), client_session.handshake_hash());
+        // The remote identity carries the same static DH key and handshake hash.
+        assert!(server_remote.static_dh_key() == Some(&client_dh_public));
+        assert!(client_remote.static_dh_key() == Some(&server_dh_public));
+        assert_eq!(server_remote.handshake_hash(), server_session.handshake_hash());
+        assert_eq!(client_remote.handshake_hash(), client_session.handshake_hash());
+    })
+}
+
+#[test]
+fn xx_buf_read() {
+    let _ = env_logger::try_init();
+    fn prop(lines: Vec<String>) -> bool {
+        let lines = lines.into_iter()
+            .map(|l| l.replace('\n', "") + "\n")
+            .collect::<Vec<_>>();
+
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                upgrade::apply(output, NoiseConfig::xx(server_dh), endpoint, upgrade::Version::V1)
+            });
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                upgrade::apply(output, NoiseConfig::xx(client_dh), endpoint, upgrade::Version::V1)
+            });
+
+        futures::executor::block_on(async {
+            let mut server = server_transport
+                .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .unwrap();
+
+            let server_address = server.try_next()
+                .await
+                .expect("some event")
+                .expect("no error")
+                .into_new_address()
+                .expect("listen address");
+
+            let client_fut = async {
+                let (_, mut client_session) = client_transport.dial(server_address)
+                    .unwrap()
+                    .await
+                    .expect("no error");
+                client_session.write_all(lines.concat().as_bytes()).await.expect("no error");
+                client_session.flush().await.expect("no error");
+            };
+
+            let server_fut = async {
+                let (_, mut server_session) = server.try_next()
+                    .await
+                    .expect("some event")
+                    .map(ListenerEvent::into_upgrade)
+                    .expect("no error")
+                    .map(|client| client.0)
+                    .expect("listener upgrade")
+                    .await
+                    .expect("no error");
+                for line in &lines {
+                    let mut buffer = String::new();
+                    server_session.read_line(&mut buffer).await.expect("no error");
+                    assert_eq!(&buffer, line);
+                }
+                assert!(server_session.fill_buf().await.expect("no error").is_empty());
+            };
+
+            futures::future::join(server_fut, client_fut).await;
+        });
+        true
+    }
+    QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<String>) -> bool)
+}
+
+#[test]
+fn xx_security_stats() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(server_dh), endpoint, upgrade::Version::V1)
+        });
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(client_dh), endpoint, upgrade::Version::V1)
+        });
+
+    let messages: [&[u8]; 3] = [b"hello", b"secured", b"world"];
+    let payload_len = messages.iter().map(|m| m.len() as u64).sum::<u64>();
+    let (client_stats, client_stats_rx) = futures::channel::oneshot::channel();
+
+    futures::executor::block_on(async {
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let client_fut = async {
+            let (_, mut client_session) = client_transport.dial(server_address)
+                .unwrap()
+                .await
+                .expect("no error");
+            for message in &messages {
+                client_session.write_all(message).await.expect("no error");
+                client_session.flush().await.expect("no error");
+            }
+            client_stats.send(client_session.security_stats()).unwrap();
+        };
+
+        let server_fut = async {
+            let (_, mut server_session) = server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade")
+                .await
+                .expect("no error");
+            let mut buffer = vec![0; payload_len as usize];
+            server_session.read_exact(&mut buffer).await.expect("no error");
+            assert_eq!(buffer, messages.concat());
+            let client_stats = client_stats_rx.await.unwrap();
+            let server_stats = server_session.security_stats();
+            assert_eq!(client_stats.plaintext_sent, payload_len);
+            assert_eq!(server_stats.plaintext_received, payload_len);
+            // The two handshake messages of the initiator and one frame per message.
+            assert_eq!(client_stats.frames_sent, 5);
+            assert_eq!(server_stats.frames_received, 5);
+            assert_eq!(client_stats.frames_received, 1);
+            assert_eq!(client_stats.ciphertext_sent, server_stats.ciphertext_received);
+            assert_eq!(client_stats.ciphertext_received, server_stats.ciphertext_sent);
+            // Every frame carries a length prefix and an authentication tag.
+            assert!(client_stats.overhead_sent() > 3 * 18);
+        };
+
+        futures::future::join(server_fut, client_fut).await;
+    })
+}
+
+#[test]
+fn xx_padding() {
+    let _ = env_logger::try_init();
+    fn prop(message: Vec<u8>, fixed: u16, random: u16) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_id_public = server_id.public();
+        let client_id_public = client_id.public();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh).with_padding(Padding::Random(random));
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let padding = Padding::Fixed(std::cmp::max(fixed, 3));
+                let noise = NoiseConfig::xx(client_dh).with_padding(padding);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<u8>, u16, u16) -> bool)
+}
+
+#[test]
+fn xx_padding_mismatch() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh).with_padding(Padding::Random(16));
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        });
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(client_dh);
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        });
+
+    futures::executor::block_on(async {
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let client_fut = async {
+            let result = client_transport.dial(server_address).unwrap().await;
+            assert!(result.is_err());
+        };
+
+        let server_fut = async {
+            let result = server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade")
+                .await;
+            assert!(result.is_err());
+        };
+
+        futures::future::join(server_fut, client_fut).await;
+    })
+}
+
+#[test]
+fn xx_padding_beyond_max_frame_len() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_id_public = server_id.public();
+    let client_id_public = client_id.public();
+
+    // A fixed padding larger than a noise message allows is reduced to it.
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh).with_padding(Padding::Fixed(u16::max_value()));
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(client_dh).with_padding(Padding::Fixed(u16::max_value()));
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+    run(server_transport, client_transport, vec![42; 100_000]);
+}
+
+#[test]
+fn xx_cipher_suite() {
+    let _ = env_logger::try_init();
+    fn prop(message: Vec<u8>) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_id_public = server_id.public();
+        let client_id_public = client_id.public();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh).with_cipher_suite(Cipher::AesGcm, Hash::Sha512);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(client_dh).with_cipher_suite(Cipher::AesGcm, Hash::Sha512);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(10).quickcheck(prop as fn(Vec<u8>) -> bool)
+}
+
+#[cfg(feature = "hybrid-pq")]
+#[test]
+fn xx_hybrid() {
+    let _ = env_logger::try_init();
+    fn prop(message: Vec<u8>) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_id_public = server_id.public();
+        let client_id_public = client_id.public();
+
+        let psk = PreSharedKey::new(rand::random());
+
+        // The order in which the hybrid key exchange and the pre-shared
+        // key are configured must not matter.
+        let server_psk = psk.clone();
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(server_dh).with_hybrid_kyber768().with_psk(server_psk);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::xx(client_dh).with_psk(psk).with_hybrid_kyber768();
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(10).quickcheck(prop as fn(Vec<u8>) -> bool)
+}
+
+#[test]
+fn xx_events() {
+    use std::sync::{Arc, Mutex};
+
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_id_public = server_id.public();
+    let client_id_public = client_id.public();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let record = |events: Arc<Mutex<Vec<_>>>| move |event: &NoiseEvent<'_>| {
+        let e = match *event {
+            NoiseEvent::HandshakeStarted { initiator, .. } => ("started", initiator, 0, 0),
+            NoiseEvent::HandshakeCompleted { initiator, .. } => ("completed", initiator, 0, 0),
+            NoiseEvent::HandshakeFailed { initiator, .. } => ("failed", initiator, 0, 0),
+            NoiseEvent::DecryptionFailed { initiator, .. } => ("decryption failed", initiator, 0, 0),
+            NoiseEvent::SessionClosed { initiator, bytes_encrypted, bytes_decrypted, .. } =>
+                ("closed", initiator, bytes_encrypted, bytes_decrypted)
+        };
+        events.lock().unwrap().push(e)
+    };
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_events = record(events.clone());
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh).with_events(server_events);
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_events = record(events.clone());
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(client_dh).with_events(client_events);
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+    let message = b"hello noise".to_vec();
+    let len = message.len() as u64;
+    run(server_transport, client_transport, message);
+
+    let mut events = events.lock().unwrap().clone();
+    events.sort();
+    assert_eq!(events, vec![
+        ("closed", false, 0, len),
+        ("closed", true, len, 0),
+        ("completed", false, 0, 0),
+        ("completed", true, 0, 0),
+        ("started", false, 0, 0),
+        ("started", true, 0, 0),
+    ]);
+}
+
+#[cfg(feature = "keylog")]
+#[test]
+fn xx_key_log() {
+    use std::sync::{Arc, Mutex};
+
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_id_public = server_id.public();
+    let client_id_public = client_id.public();
+
+    let logged = Arc::new(Mutex::new(Vec::new()));
+    let log = |logged: Arc<Mutex<Vec<_>>>| move |keys: &libp2p_noise::SessionKeys<'_>| {
+        logged.lock().unwrap().push((
+            keys.initiator,
+            keys.handshake_hash.to_vec(),
+            keys.initiator_key.to_vec(),
+            keys.responder_key.to_vec()))
+    };
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_log = log(logged.clone());
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh).with_key_log(server_log);
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_log = log(logged.clone());
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(client_dh).with_key_log(client_log);
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+    run(server_transport, client_transport, b"hello".to_vec());
+
+    let logged = logged.lock().unwrap();
+    assert_eq!(logged.len(), 2);
+    assert_ne!(logged[0].0, logged[1].0);
+    assert_eq!(logged[0].1, logged[1].1);
+    assert_eq!(logged[0].2, logged[1].2);
+    assert_eq!(logged[0].3, logged[1].3);
+    assert_ne!(logged[0].2, logged[0].3);
+}
+
+#[cfg(feature = "keylog")]
+#[test]
+fn ikxx_fallback_key_log() {
+    use std::sync::{Arc, Mutex};
+
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_id_public = server_id.public();
+    let client_id_public = client_id.public();
+
+    let logged = Arc::new(Mutex::new(Vec::new()));
+    let log = |logged: Arc<Mutex<Vec<_>>>| move |keys: &libp2p_noise::SessionKeys<'_>| {
+        logged.lock().unwrap().push((
+            keys.handshake_hash.to_vec(),
+            keys.initiator_key.to_vec(),
+            keys.responder_key.to_vec()))
+    };
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_log = log(logged.clone());
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            if endpoint.is_listener() {
+                Either::Left(apply_inbound(output,
+                    NoiseConfig::ik_xx_listener(server_dh).with_key_log(server_log)))
+            } else {
+                Either::Right(apply_outbound(output, NoiseConfig::xx(server_dh),
+                    upgrade::Version::V1))
+            }
+        })
+        .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+    // A stale static DH public key of the server forces the fallback to XX.
+    let stale_dh_public = Keypair::<X25519>::new().public().clone();
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_log = log(logged.clone());
+    let server_id_public2 = server_id_public.clone();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            if endpoint.is_dialer() {
+                Either::Left(apply_outbound(output,
+                    NoiseConfig::ik_xx_dialer(client_dh, server_id_public, stale_dh_public)
+                        .with_key_log(client_log),
+                    upgrade::Version::V1))
+            } else {
+                Either::Right(apply_inbound(output, NoiseConfig::xx(client_dh)))
+            }
+        })
+        .and_then(move |out, _| expect_identity(out, &server_id_public2));
+
+    run(server_transport, client_transport, b"hello".to_vec());
+
+    let logged = logged.lock().unwrap();
+    assert_eq!(logged.len(), 2);
+    assert_eq!(logged[0], logged[1]);
+}
+
+#[test]
+fn xx_crypto_resolver() {
+    use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+
+    /// Resolves ciphers with `ring`, counting the resolved ciphers.
+    #[derive(Clone)]
+    struct CountingResolver(Arc<AtomicUsize>);
+
+    impl snow::resolvers::CryptoResolver for CountingResolver {
+        fn resolve_rng(&self) -> Option<Box<dyn snow::types::Random>> {
+            None
+        }
+
+        fn resolve_dh(&self, _: &snow::params::DHChoice) -> Option<Box<dyn snow::types::Dh>> {
+            None
+        }
+
+        fn resolve_hash(&self, _: &snow::params::HashChoice) -> Option<Box<dyn snow::types::Hash>> {
+            None
+        }
+
+        fn resolve_cipher(&self, choice: &snow::params::CipherChoice) -> Option<Box<dyn snow::types::Cipher>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            snow::resolvers::RingResolver.resolve_cipher(choice)
+        }
+    }
+
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_id_public = server_id.public();
+    let client_id_public = client_id.public();
+
+    let resolved = Arc::new(AtomicUsize::new(0));
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server_resolver = CountingResolver(resolved.clone());
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh).with_crypto_resolver(server_resolver.clone());
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(client_dh), endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+    run(server_transport, client_transport, b"hello".to_vec());
+
+    // The cipher of the handshake and of both directions of the session.
+    assert_eq!(resolved.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn xx_crypto_backend() {
+    use libp2p_noise::CryptoBackend;
+
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_id_public = server_id.public();
+    let client_id_public = client_id.public();
+
+    let server_dh = Keypair::<X25519>::generate_with(&CryptoBackend::Ring).unwrap()
+        .into_authentic(&server_id).unwrap();
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(server_dh).with_crypto_resolver(CryptoBackend::Ring);
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(client_dh), endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+    run(server_transport, client_transport, b"hello".to_vec());
+}
+
+#[test]
+fn xx_external_dh() {
+    use libp2p_noise::DhKeypair;
+    use std::{io, sync::{Arc, atomic::{AtomicUsize, Ordering}}};
+
+    /// Keeps the secret key away from the noise config, counting the
+    /// DH computations with it.
+    struct ExternalKeypair {
+        secret: [u8; 32],
+        public: [u8; 32],
+        computed: AtomicUsize
+    }
+
+    impl DhKeypair for ExternalKeypair {
+        fn public(&self) -> &[u8] {
+            &self.public
+        }
+
+        fn dh(&self, public: &[u8], out: &mut [u8]) -> io::Result<()> {
+            let mut pk = [0u8; 32];
+            pk.copy_from_slice(public);
+            out.copy_from_slice(&x25519_dalek::x25519(self.secret, pk));
+            self.computed.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_id_public = server_id.public();
+    let client_id_public = client_id.public();
+
+    let secret = rand::random::<[u8; 32]>();
+    let external = Arc::new(ExternalKeypair {
+        secret,
+        public: x25519_dalek::x25519(secret, x25519_dalek::X25519_BASEPOINT_BYTES),
+        computed: AtomicUsize::new(0)
+    });
+
+    let server_dh = Keypair::<X25519>::from_dh(external.clone()).unwrap()
+        .into_authentic(&server_id).unwrap();
+    assert_eq!(server_dh.public().as_ref(), &external.public);
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(server_dh), endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(client_dh), endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+    run(server_transport, client_transport, b"hello".to_vec());
+
+    // The `es` computation of the responder.
+    assert_eq!(external.computed.load(Ordering::SeqCst), 1);
+}
+
 #[test]
 fn ix() {
     let _ = env_logger::try_init();
@@ -140,15 +1571,301 @@ fn ik_xx() {
     QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<u8>) -> bool)
 }
 
-type Output = (RemoteIdentity<X25519>, NoiseOutput<Negotiated<TcpTransStream>>);
+#[test]
+fn ikxx() {
+    let _ = env_logger::try_init();
+    fn prop(message: Vec<u8>, stale: bool) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let server_id_public = server_id.public();
+
+        let client_id = identity::Keypair::generate_ed25519();
+        let client_id_public = client_id.public();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        // With a stale static DH public key of the server, the handshake
+        // must fall back to XX.
+        let server_dh_public = if stale {
+            Keypair::<X25519>::new().public().clone()
+        } else {
+            server_dh.public().clone()
+        };
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                if endpoint.is_listener() {
+                    Either::Left(apply_inbound(output, NoiseConfig::ik_xx_listener(server_dh)))
+                } else {
+                    Either::Right(apply_outbound(output, NoiseConfig::xx(server_dh),
+                        upgrade::Version::V1))
+                }
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let server_id_public2 = server_id_public.clone();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                if endpoint.is_dialer() {
+                    Either::Left(apply_outbound(output,
+                        NoiseConfig::ik_xx_dialer(client_dh, server_id_public, server_dh_public),
+                        upgrade::Version::V1))
+                } else {
+                    Either::Right(apply_inbound(output, NoiseConfig::xx(client_dh)))
+                }
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public2));
+
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<u8>, bool) -> bool)
+}
+
+type Output<C = X25519> = (RemoteIdentity<C>, NoiseOutput<Negotiated<TcpTransStream>>);
+
+#[test]
+fn nx() {
+    let _ = env_logger::try_init();
+    fn prop(message: Vec<u8>) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let server_id_public = server_id.public();
+
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                upgrade::apply(output, NoiseConfig::nx(server_dh), endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_unknown(out));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                upgrade::apply(output, NoiseConfig::nx(client_dh), endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<u8>) -> bool)
+}
+
+#[test]
+fn xn() {
+    let _ = env_logger::try_init();
+    fn prop(message: Vec<u8>) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+
+        let client_id = identity::Keypair::generate_ed25519();
+        let client_id_public = client_id.public();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                upgrade::apply(output, NoiseConfig::xn(server_dh), endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                upgrade::apply(output, NoiseConfig::xn(client_dh), endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_unknown(out));
+
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<u8>) -> bool)
+}
+
+#[test]
+fn kk() {
+    let _ = env_logger::try_init();
+    fn prop(message: Vec<u8>) -> bool {
+        let server_id = identity::Keypair::generate_ed25519();
+        let server_id_public = server_id.public();
+
+        let client_id = identity::Keypair::generate_ed25519();
+        let client_id_public = client_id.public();
+
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let server_dh_public = server_dh.public().clone();
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_dh_public = client_dh.public().clone();
+
+        let client_id_public2 = client_id_public.clone();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::kk(server_dh, client_id_public2, client_dh_public);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let server_id_public2 = server_id_public.clone();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                let noise = NoiseConfig::kk(client_dh, server_id_public2, server_dh_public);
+                upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, message);
+        true
+    }
+    QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<u8>) -> bool)
+}
+
+#[test]
+fn xx_resumption() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let server_id_public = server_id.public();
+    let client_id_public = client_id.public();
+    let server_peer = server_id_public.clone().into_peer_id();
+    let client_peer = client_id_public.clone().into_peer_id();
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+
+    let server_cache = ResumptionCache::new(16, Duration::from_secs(60));
+    let client_cache = ResumptionCache::new(16, Duration::from_secs(60));
+
+    // A full handshake issues a ticket to both parties.
+    let (dh, cache, id_public) = (server_dh.clone(), server_cache.clone(), client_id_public.clone());
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(dh).with_resumption(cache);
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &id_public));
+
+    let (dh, cache, id_public) = (client_dh.clone(), client_cache.clone(), server_id_public.clone());
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            let noise = NoiseConfig::xx(dh).with_resumption(cache);
+            upgrade::apply(output, noise, endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |out, _| expect_identity(out, &id_public));
+
+    run(server_transport, client_transport, b"full".to_vec());
+    assert!(server_cache.contains(&client_peer));
+    assert!(client_cache.contains(&server_peer));
+
+    // The resumed session consumes the tickets and issues new ones.
+    for _ in 0 .. 2 {
+        let (dh, cache, id_public) = (server_dh.clone(), server_cache.clone(), client_id_public.clone());
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, _endpoint| {
+                apply_inbound(output, NoiseConfig::resume_listener(dh, cache))
+            })
+            .and_then(move |out, _| expect_identity(out, &id_public));
+
+        let noise = NoiseConfig::resume_dialer(client_dh.clone(), &client_cache, &server_peer)
+            .expect("a resumption ticket");
+        assert!(!client_cache.contains(&server_peer));
+        let id_public = server_id_public.clone();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, _| {
+                apply_outbound(output, noise.clone(), upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &id_public));
+
+        run(server_transport, client_transport, b"resumed".to_vec());
+        assert!(server_cache.contains(&client_peer));
+        assert!(client_cache.contains(&server_peer));
+    }
+}
+
+#[test]
+fn xx_resumption_unknown_ticket() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+    let server_peer = server_id.public().into_peer_id();
+    let client_peer = client_id.public().into_peer_id();
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+
+    let server_cache = ResumptionCache::new(16, Duration::from_secs(60));
+    let client_cache = ResumptionCache::new(16, Duration::from_secs(60));
+
+    let (dh, cache) = (server_dh.clone(), server_cache.clone());
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(dh).with_resumption(cache), endpoint, upgrade::Version::V1)
+        });
+    let (dh, cache) = (client_dh.clone(), client_cache.clone());
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, NoiseConfig::xx(dh).with_resumption(cache), endpoint, upgrade::Version::V1)
+        });
+    run(server_transport, client_transport, b"full".to_vec());
+
+    // The server forgets the ticket, e.g. due to a restart.
+    server_cache.remove(&client_peer);
+
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, _| {
+            apply_inbound(output, NoiseConfig::resume_listener(server_dh, server_cache))
+        });
+    let noise = NoiseConfig::resume_dialer(client_dh, &client_cache, &server_peer)
+        .expect("a resumption ticket");
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, _| {
+            apply_outbound(output, noise.clone(), upgrade::Version::V1)
+        });
+
+    futures::executor::block_on(async {
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let client_fut = async {
+            let result = client_transport.dial(server_address).unwrap().await;
+            assert!(result.is_err());
+        };
+
+        let server_fut = async {
+            let result = server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade")
+                .await;
+            match result {
+                Err(EitherError::B(UpgradeError::Apply(NoiseError::UnknownTicket))) => {}
+                _ => panic!("Unexpected result")
+            }
+        };
+
+        futures::future::join(server_fut, client_fut).await;
+    });
+
+    assert!(!client_cache.contains(&server_peer));
+}
 
-fn run<T, U>(server_transport: T, client_transport: U, message1: Vec<u8>)
+fn run<T, U, C>(server_transport: T, client_transport: U, message1: Vec<u8>)
 where
-    T: Transport<Output = Output>,
+    T: Transport<Output = Output<C>>,
     T::Dial: Send + 'static,
     T::Listener: Send + Unpin + futures::stream::TryStream + 'static,
     T::ListenerUpgrade: Send + 'static,
-    U: Transport<Output = Output>,
+    U: Transport<Output = Output<C>>,
     U::Dial: Send + 'static,
     U::Listener: Send + 'static,
     U::ListenerUpgrade: Send + 'static,
@@ -201,11 +1918,34 @@ where
     })
 }
 
-fn expect_identity(output: Output, pk: &identity::PublicKey)
-    -> impl Future<Output = Result<Output, NoiseError>>
+fn expect_identity<C>(output: Output<C>, pk: &identity::PublicKey)
+    -> impl Future<Output = Result<Output<C>, NoiseError>>
 {
     match output.0 {
-        RemoteIdentity::IdentityKey(ref k) if k == pk => future::ok(output),
+        RemoteIdentity::IdentityKey { ref id_key, .. } if id_key == pk => future::ok(output),
+        _ => panic!("Unexpected remote identity")
+    }
+}
+
+fn expect_unknown(output: Output) -> impl Future<Output = Result<Output, NoiseError>> {
+    match output.0 {
+        RemoteIdentity::Unknown { .. } => future::ok(output),
         _ => panic!("Unexpected remote identity")
     }
 }
+
+fn expect_early_muxer(output: Output, muxer: Option<&str>)
+    -> impl Future<Output = Result<Output, NoiseError>>
+{
+    assert_eq!(output.1.early_muxer(), muxer);
+    assert_eq!(output.1.early_muxer().map(str::as_bytes), SecuredStream::early_muxer(&output.1));
+    future::ok(output)
+}
+
+fn expect_early_data(output: Output, data: &[u8])
+    -> impl Future<Output = Result<Output, NoiseError>>
+{
+    let expected = if data.is_empty() { None } else { Some(data) };
+    assert_eq!(output.1.remote_early_data(), expected);
+    future::ok(output)
+}
@@ -18,7 +18,6 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::error::PlainTextError;
 use crate::handshake::Remote;
 
 use bytes::BytesMut;
@@ -40,6 +39,8 @@ use std::{io, iter, pin::Pin, task::{Context, Poll}};
 use unsigned_varint::codec::UviBytes;
 use void::Void;
 
+pub use error::PlainTextError;
+
 mod error;
 mod handshake;
 mod structs_proto {
@@ -0,0 +1,118 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Encrypting writer buffering the ciphertext written to the underlying
+//! I/O resource.
+
+use futures::{io::AsyncWrite, ready};
+use pin_project::pin_project;
+use salsa20::{XSalsa20, cipher::SyncStreamCipher};
+use std::{fmt, io, pin::Pin, task::{Context, Poll}};
+
+/// A writer encrypting all data with a stream cipher before writing it
+/// to the underlying I/O resource.
+///
+/// Data accepted by [`AsyncWrite::poll_write`] is encrypted right away and
+/// buffered until it can be written, so the cipher keystream never gets
+/// out of sync with what has been sent.
+#[pin_project]
+pub(crate) struct CryptWriter<W> {
+    #[pin]
+    inner: W,
+    buf: Vec<u8>,
+    capacity: usize,
+    cipher: XSalsa20
+}
+
+impl<W: AsyncWrite> CryptWriter<W> {
+    /// Creates a new `CryptWriter` buffering at most `capacity` bytes.
+    pub(crate) fn with_capacity(capacity: usize, inner: W, cipher: XSalsa20) -> Self {
+        CryptWriter { inner, buf: Vec::with_capacity(capacity), capacity, cipher }
+    }
+
+    /// Gets a pinned mutable reference to the underlying I/O resource.
+    pub(crate) fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut W> {
+        self.project().inner
+    }
+}
+
+/// Writes the buffered data to `inner`, removing what has been written
+/// from `buf`.
+fn poll_flush_buf<W: AsyncWrite>(mut inner: Pin<&mut W>, buf: &mut Vec<u8>, cx: &mut Context<'_>)
+    -> Poll<io::Result<()>>
+{
+    let mut ret = Poll::Ready(Ok(()));
+    let mut written = 0;
+    while written < buf.len() {
+        match inner.as_mut().poll_write(cx, &buf[written ..]) {
+            Poll::Ready(Ok(0)) => {
+                ret = Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                break
+            }
+            Poll::Ready(Ok(n)) => written += n,
+            Poll::Ready(Err(e)) => {
+                ret = Poll::Ready(Err(e));
+                break
+            }
+            Poll::Pending => {
+                ret = Poll::Pending;
+                break
+            }
+        }
+    }
+    if written > 0 {
+        buf.drain(.. written);
+    }
+    ret
+}
+
+impl<W: AsyncWrite> AsyncWrite for CryptWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        if this.buf.len() >= *this.capacity {
+            ready!(poll_flush_buf(this.inner, this.buf, cx))?;
+        }
+        let n = std::cmp::min(*this.capacity - this.buf.len(), data.len());
+        let off = this.buf.len();
+        this.buf.extend_from_slice(&data[.. n]);
+        this.cipher.apply_keystream(&mut this.buf[off ..]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+        ready!(poll_flush_buf(this.inner.as_mut(), this.buf, cx))?;
+        this.inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+        ready!(poll_flush_buf(this.inner.as_mut(), this.buf, cx))?;
+        this.inner.poll_close(cx)
+    }
+}
+
+impl<W> fmt::Debug for CryptWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CryptWriter")
+            .field("buffered", &self.buf.len())
+            .finish()
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Private network support for libp2p, compatible with the
+//! [pnet specification][spec] implemented by go-libp2p and js-libp2p.
+//!
+//! All nodes of a private network share a secret [`PreSharedKey`],
+//! usually distributed as a `swarm.key` file. The key type is defined in
+//! `libp2p-core` and also used for the pre-shared key mode of `libp2p-noise`. Before any other upgrade
+//! of a connection, both endpoints send each other a random nonce and
+//! from then on encrypt all traffic with XSalsa20 keyed by the shared key.
+//! A node without the key is thus unable to take part in any subsequent
+//! protocol negotiation, in particular the security handshake.
+//!
+//! > **Note**: The pnet layer does not authenticate the data it carries.
+//! >           It must always be followed by a security upgrade.
+//!
+//! # Usage
+//!
+//! Example:
+//!
+//! ```
+//! use libp2p_core::{Transport, transport::MemoryTransport};
+//! use libp2p_pnet::{PnetConfig, PreSharedKey};
+//!
+//! # fn main() {
+//! let key: PreSharedKey = "/key/swarm/psk/1.0.0/\n/base16/\n\
+//!     6189c5cf0b87fb800c1a9feeda73c6ab5e998db48fb9e6a978575c770ceef683"
+//!     .parse()
+//!     .unwrap();
+//! let transport = MemoryTransport::default()
+//!     .and_then(move |socket, _| PnetConfig::new(key).handshake(socket));
+//! // let transport = transport.upgrade(...).authenticate(...).multiplex(...);
+//! # }
+//! ```
+//!
+//! [spec]: https://github.com/libp2p/specs/blob/master/pnet/Private-Networks-PSK-V1.md
+
+mod crypt_writer;
+
+use crypt_writer::CryptWriter;
+use futures::prelude::*;
+use log::trace;
+use pin_project::pin_project;
+use rand::RngCore;
+use salsa20::{
+    Salsa20,
+    XSalsa20,
+    cipher::{NewStreamCipher, SyncStreamCipher, generic_array::GenericArray}
+};
+use sha3::{digest::ExtendableOutput, Shake128};
+use std::{error, fmt, io::{self, Read, Write}, pin::Pin, task::{Context, Poll}};
+
+pub use libp2p_core::psk::{KeyParseError, PreSharedKey};
+
+/// The size of the nonce exchanged at the start of a connection in bytes.
+const NONCE_SIZE: usize = 24;
+/// The size of the buffer of encrypted data waiting to be written.
+const WRITE_BUFFER_SIZE: usize = 1024;
+/// The size of a key fingerprint in bytes.
+const FINGERPRINT_SIZE: usize = 16;
+
+/// Computes the fingerprint of a pre-shared key, which can be displayed or
+/// logged to identify the key without disclosing it.
+///
+/// The fingerprint is the same as the one computed by go-libp2p.
+pub fn fingerprint(key: &PreSharedKey) -> Fingerprint {
+    let mut enc = [0u8; 64];
+    let nonce: [u8; 8] = *b"finprint";
+    let mut cipher = Salsa20::new(GenericArray::from_slice(key.as_ref()), &nonce.into());
+    cipher.apply_keystream(&mut enc);
+    let mut hasher = Shake128::default();
+    hasher.write_all(&enc).expect("writing to a hasher does not fail");
+    let mut out = [0u8; FINGERPRINT_SIZE];
+    hasher.xof_result().read_exact(&mut out).expect("reading from a XOF does not fail");
+    Fingerprint(out)
+}
+
+/// The fingerprint of a [`PreSharedKey`].
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Fingerprint([u8; FINGERPRINT_SIZE]);
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in &self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// The configuration of the private network layer of a connection.
+#[derive(Clone)]
+pub struct PnetConfig {
+    key: PreSharedKey
+}
+
+impl fmt::Debug for PnetConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PnetConfig")
+            .field("key", &fingerprint(&self.key))
+            .finish()
+    }
+}
+
+impl PnetConfig {
+    /// Creates a new configuration using the given pre-shared key.
+    pub fn new(key: PreSharedKey) -> Self {
+        PnetConfig { key }
+    }
+
+    /// Performs the private network handshake on the given socket.
+    ///
+    /// This is meant to be applied to the raw connections of a transport,
+    /// e.g. with `Transport::and_then`,
+    /// before any other upgrade.
+    pub async fn handshake<T>(self, mut socket: T) -> Result<PnetOutput<T>, PnetError>
+    where
+        T: AsyncRead + AsyncWrite + Send + Unpin + 'static
+    {
+        trace!("exchanging nonces");
+        let mut local_nonce = [0u8; NONCE_SIZE];
+        let mut remote_nonce = [0u8; NONCE_SIZE];
+        rand::thread_rng().fill_bytes(&mut local_nonce);
+        socket.write_all(&local_nonce).await.map_err(PnetError::HandshakeError)?;
+        socket.flush().await.map_err(PnetError::HandshakeError)?;
+        socket.read_exact(&mut remote_nonce).await.map_err(PnetError::HandshakeError)?;
+        trace!("setting up ciphers");
+        let key = GenericArray::from_slice(self.key.as_ref());
+        let write_cipher = XSalsa20::new(key, &local_nonce.into());
+        let read_cipher = XSalsa20::new(key, &remote_nonce.into());
+        Ok(PnetOutput::new(socket, write_cipher, read_cipher))
+    }
+}
+
+/// A connection of a private network, encrypting all data with the
+/// pre-shared key.
+#[pin_project]
+pub struct PnetOutput<S> {
+    #[pin]
+    inner: CryptWriter<S>,
+    read_cipher: XSalsa20
+}
+
+impl<S: AsyncRead + AsyncWrite> PnetOutput<S> {
+    fn new(inner: S, write_cipher: XSalsa20, read_cipher: XSalsa20) -> Self {
+        PnetOutput {
+            inner: CryptWriter::with_capacity(WRITE_BUFFER_SIZE, inner, write_cipher),
+            read_cipher
+        }
+    }
+}
+
+impl<S> fmt::Debug for PnetOutput<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PnetOutput")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> AsyncRead for PnetOutput<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        let result = this.inner.get_pin_mut().poll_read(cx, buf);
+        if let Poll::Ready(Ok(size)) = &result {
+            trace!("read {} bytes", size);
+            this.read_cipher.apply_keystream(&mut buf[.. *size]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> AsyncWrite for PnetOutput<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Error when performing the private network handshake.
+#[derive(Debug)]
+pub enum PnetError {
+    /// The nonces could not be exchanged.
+    HandshakeError(io::Error)
+}
+
+impl fmt::Display for PnetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PnetError::HandshakeError(e) => write!(f, "pnet handshake error: {}", e)
+        }
+    }
+}
+
+impl error::Error for PnetError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PnetError::HandshakeError(e) => Some(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_SPEC: &str = "/key/swarm/psk/1.0.0/\n/base16/\n\
+        6189c5cf0b87fb800c1a9feeda73c6ab5e998db48fb9e6a978575c770ceef683";
+
+    #[test]
+    fn key_fingerprint() {
+        let key = KEY_SPEC.parse::<PreSharedKey>().unwrap();
+        assert_eq!(fingerprint(&key).to_string(), "45fc986bbc9388a11d939df26f730f0c");
+        // Only the fingerprint is shown in debug output.
+        let config = format!("{:?}", PnetConfig::new(key));
+        assert!(config.contains("45fc986bbc9388a11d939df26f730f0c"));
+        assert!(!config.contains("6189c5cf"));
+    }
+}
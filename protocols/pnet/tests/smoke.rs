@@ -0,0 +1,92 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::{future, prelude::*};
+use libp2p_core::transport::{ListenerEvent, MemoryTransport, Transport};
+use libp2p_pnet::{PnetConfig, PreSharedKey};
+
+/// Sends a message from a dialer to a listener, each with its own key,
+/// and returns the message received by the listener.
+fn transfer(listener_key: PreSharedKey, dialer_key: PreSharedKey, message: Vec<u8>) -> Vec<u8> {
+    let listener_transport = MemoryTransport::default()
+        .and_then(move |socket, _| PnetConfig::new(listener_key).handshake(socket));
+    let dialer_transport = MemoryTransport::default()
+        .and_then(move |socket, _| PnetConfig::new(dialer_key).handshake(socket));
+
+    futures::executor::block_on(async move {
+        let mut listener = listener_transport
+            .listen_on("/memory/0".parse().unwrap())
+            .unwrap();
+
+        let listen_addr = listener.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let dialer_fut = async {
+            let mut socket = dialer_transport.dial(listen_addr)
+                .unwrap()
+                .await
+                .expect("no error");
+
+            socket.write_all(&message).await.expect("no error");
+            socket.close().await.expect("no error");
+        };
+
+        let listener_fut = async {
+            let mut socket = listener.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|upgrade| upgrade.0)
+                .expect("listener upgrade")
+                .await
+                .expect("no error");
+
+            let mut buffer = vec![0; message.len()];
+            socket.read_exact(&mut buffer).await.expect("no error");
+            buffer
+        };
+
+        future::join(listener_fut, dialer_fut).await.0
+    })
+}
+
+#[test]
+fn same_key_roundtrip() {
+    let _ = env_logger::try_init();
+
+    let key = PreSharedKey::new(rand::random());
+    let message = (0 .. 10_000).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+    assert_eq!(transfer(key.clone(), key, message.clone()), message);
+}
+
+#[test]
+fn different_keys_garble_data() {
+    let _ = env_logger::try_init();
+
+    let message = b"Hello, private network!".to_vec();
+    let received = transfer(PreSharedKey::new(rand::random()), PreSharedKey::new(rand::random()), message.clone());
+    assert_eq!(received.len(), message.len());
+    assert_ne!(received, message);
+}
@@ -0,0 +1,201 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Certificates carrying the libp2p public key extension.
+//!
+//! Every endpoint uses a self-signed certificate for an ephemeral key, which
+//! carries the libp2p public key of the endpoint in a custom extension,
+//! together with a signature over the certificate public key made with the
+//! corresponding libp2p secret key.
+//!
+//! The functions of this module can also be used by other transports which
+//! authenticate with such certificates, e.g. WebRTC or QUIC.
+
+use crate::error::TlsError;
+use libp2p_core::{identity, Endpoint};
+use std::time::SystemTime;
+
+/// The OID of the libp2p public key extension.
+const P2P_EXT_OID: [u64; 9] = [1, 3, 6, 1, 4, 1, 53594, 1, 1];
+
+/// The prefix of the data signed with the libp2p secret key, preceding
+/// the DER-encoded public key of the certificate.
+const P2P_SIGNING_PREFIX: [u8; 21] = *b"libp2p-tls-handshake:";
+
+/// The signature algorithms accepted for the self-signature of a certificate.
+static SIGNATURE_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384
+];
+
+/// Generate a self-signed certificate for a new ephemeral key that carries
+/// the public key of the given identity keypair, yielding the certificate
+/// and the DER-encoded PKCS#8 ephemeral secret key.
+pub fn generate(keypair: &identity::Keypair)
+    -> Result<(rustls::Certificate, rustls::PrivateKey), TlsError>
+{
+    let cert_keypair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256)?;
+    let msg = [&P2P_SIGNING_PREFIX[..], &cert_keypair.public_key_der()].concat();
+    let signature = keypair.sign(&msg)?;
+    let extension = yasna::construct_der(|writer| {
+        writer.write_sequence(|writer| {
+            writer.next().write_bytes(&keypair.public().into_protobuf_encoding());
+            writer.next().write_bytes(&signature);
+        })
+    });
+
+    let mut params = rcgen::CertificateParams::new(Vec::new());
+    params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params.custom_extensions.push(rcgen::CustomExtension::from_oid_content(&P2P_EXT_OID, extension));
+    params.key_pair = Some(cert_keypair);
+
+    let cert = rcgen::Certificate::from_params(params)?;
+    Ok((rustls::Certificate(cert.serialize_der()?), rustls::PrivateKey(cert.serialize_private_key_der())))
+}
+
+/// Verify the certificate presented by a remote with the given role,
+/// yielding the libp2p public key of the remote.
+///
+/// The certificate must be valid, self-signed and carry a libp2p public
+/// key extension with a valid signature over the certificate public key.
+pub fn verify(der: &[u8], remote: Endpoint) -> Result<identity::PublicKey, TlsError> {
+    let cert = webpki::EndEntityCert::from(der)?;
+    let anchor = webpki::trust_anchor_util::cert_der_as_trust_anchor(der)?;
+    let now = webpki::Time::try_from(SystemTime::now())
+        .map_err(|_| TlsError::InvalidCertificate)?;
+    match remote {
+        Endpoint::Listener => cert.verify_is_valid_tls_server_cert(
+            SIGNATURE_ALGS, &webpki::TLSServerTrustAnchors(&[anchor]), &[], now)?,
+        Endpoint::Dialer => cert.verify_is_valid_tls_client_cert(
+            SIGNATURE_ALGS, &webpki::TLSClientTrustAnchors(&[anchor]), &[], now)?
+    }
+
+    public_key(der)
+}
+
+/// Extract the libp2p public key from the given DER-encoded certificate,
+/// checking the signature of the libp2p public key extension.
+///
+/// Unlike `verify`, this does not check the validity and the self-signature
+/// of the certificate itself, which is left to the caller.
+pub fn public_key(der: &[u8]) -> Result<identity::PublicKey, TlsError> {
+    let (spki, extension) = parse(der)?;
+    let (public_key, signature) = yasna::parse_der(&extension, |reader| {
+        reader.read_sequence(|reader| {
+            let public_key = reader.next().read_bytes()?;
+            let signature = reader.next().read_bytes()?;
+            Ok((public_key, signature))
+        })
+    })?;
+    let public_key = identity::PublicKey::from_protobuf_encoding(&public_key)
+        .map_err(|_| TlsError::InvalidCertificate)?;
+    if public_key.verify(&[&P2P_SIGNING_PREFIX[..], &spki].concat(), &signature) {
+        Ok(public_key)
+    } else {
+        Err(TlsError::InvalidSignature)
+    }
+}
+
+/// Parse the given DER-encoded certificate, yielding its DER-encoded
+/// public key and the content of its libp2p public key extension.
+fn parse(der: &[u8]) -> Result<(Vec<u8>, Vec<u8>), TlsError> {
+    let (spki, extension) = yasna::parse_der(der, |reader| {
+        reader.read_sequence(|reader| {
+            let tbs = reader.next().read_sequence(|reader| {
+                let _version = reader.read_optional(|reader| {
+                    reader.read_tagged(yasna::Tag::context(0), |reader| reader.read_der())
+                })?;
+                let _serial = reader.next().read_der()?;
+                let _signature = reader.next().read_der()?;
+                let _issuer = reader.next().read_der()?;
+                let _validity = reader.next().read_der()?;
+                let _subject = reader.next().read_der()?;
+                let spki = reader.next().read_der()?;
+                let mut extension = None;
+                reader.read_optional(|reader| {
+                    reader.read_tagged(yasna::Tag::context(3), |reader| {
+                        reader.read_sequence_of(|reader| {
+                            reader.read_sequence(|reader| {
+                                let oid = reader.next().read_oid()?;
+                                let _critical = reader.read_default(false, |reader| reader.read_bool())?;
+                                let value = reader.next().read_bytes()?;
+                                if oid.components().as_slice() == P2P_EXT_OID {
+                                    extension = Some(value)
+                                }
+                                Ok(())
+                            })
+                        })
+                    })
+                })?;
+                Ok((spki, extension))
+            })?;
+            let _signature_algorithm = reader.next().read_der()?;
+            let _signature = reader.next().read_der()?;
+            Ok(tbs)
+        })
+    })?;
+    Ok((spki, extension.ok_or(TlsError::InvalidCertificate)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_certificate_verifies() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let (cert, _) = generate(&keypair).unwrap();
+        assert_eq!(verify(&cert.0, Endpoint::Listener).unwrap(), keypair.public());
+        assert_eq!(verify(&cert.0, Endpoint::Dialer).unwrap(), keypair.public());
+    }
+
+    #[test]
+    fn certificate_public_key_is_extracted() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let (cert, _) = generate(&keypair).unwrap();
+        assert_eq!(public_key(&cert.0).unwrap(), keypair.public());
+    }
+
+    #[test]
+    fn certificate_of_other_key_fails() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let (cert, _) = generate(&keypair).unwrap();
+        let (other, _) = generate(&keypair).unwrap();
+        // Replace the certificate public key, invalidating both the
+        // self-signature and the signature of the extension.
+        let (spki, _) = parse(&cert.0).unwrap();
+        let (other_spki, _) = parse(&other.0).unwrap();
+        let pos = cert.0.windows(spki.len()).position(|w| w == &spki[..]).unwrap();
+        let mut forged = cert.0.clone();
+        forged[pos .. pos + spki.len()].copy_from_slice(&other_spki);
+        assert!(verify(&forged, Endpoint::Listener).is_err());
+    }
+}
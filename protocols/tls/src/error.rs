@@ -0,0 +1,101 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_core::identity;
+use std::{error::Error, fmt, io};
+
+/// libp2p_tls error type.
+#[derive(Debug)]
+pub enum TlsError {
+    /// An I/O error has been encountered, e.g. a failed TLS handshake.
+    Io(io::Error),
+    /// The local certificate could not be generated.
+    CertificateGeneration(rcgen::RcgenError),
+    /// The libp2p public key extension of the local certificate could
+    /// not be signed.
+    SigningError(identity::error::SigningError),
+    /// A certificate is not a valid self-signed certificate.
+    WebPki(webpki::Error),
+    /// A certificate is malformed or lacks the libp2p public key extension.
+    InvalidCertificate,
+    /// The signature of the libp2p public key extension of a certificate
+    /// is invalid.
+    InvalidSignature,
+    #[doc(hidden)]
+    __Nonexhaustive
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Io(e) => write!(f, "{}", e),
+            TlsError::CertificateGeneration(e) => write!(f, "{}", e),
+            TlsError::SigningError(e) => write!(f, "{}", e),
+            TlsError::WebPki(e) => write!(f, "{}", e),
+            TlsError::InvalidCertificate => f.write_str("invalid certificate"),
+            TlsError::InvalidSignature => f.write_str("invalid public key extension signature"),
+            TlsError::__Nonexhaustive => f.write_str("__Nonexhaustive")
+        }
+    }
+}
+
+impl Error for TlsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TlsError::Io(e) => Some(e),
+            TlsError::CertificateGeneration(e) => Some(e),
+            TlsError::SigningError(e) => Some(e),
+            TlsError::WebPki(e) => Some(e),
+            TlsError::InvalidCertificate => None,
+            TlsError::InvalidSignature => None,
+            TlsError::__Nonexhaustive => None
+        }
+    }
+}
+
+impl From<io::Error> for TlsError {
+    fn from(e: io::Error) -> Self {
+        TlsError::Io(e)
+    }
+}
+
+impl From<rcgen::RcgenError> for TlsError {
+    fn from(e: rcgen::RcgenError) -> Self {
+        TlsError::CertificateGeneration(e)
+    }
+}
+
+impl From<identity::error::SigningError> for TlsError {
+    fn from(e: identity::error::SigningError) -> Self {
+        TlsError::SigningError(e)
+    }
+}
+
+impl From<webpki::Error> for TlsError {
+    fn from(e: webpki::Error) -> Self {
+        TlsError::WebPki(e)
+    }
+}
+
+impl From<yasna::ASN1Error> for TlsError {
+    fn from(_: yasna::ASN1Error) -> Self {
+        TlsError::InvalidCertificate
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! TLS 1.3 security protocol for libp2p, as specified by the
+//! [libp2p TLS specification][spec].
+//!
+//! Both endpoints authenticate with a self-signed certificate for an
+//! ephemeral key, which carries the libp2p public key of the endpoint and
+//! a signature over the certificate public key made with the corresponding
+//! libp2p secret key. The upgrade yields the [`PeerId`] of the remote
+//! together with a [`TlsOutput`] for communicating over the established
+//! session.
+//!
+//! # Usage
+//!
+//! Example:
+//!
+//! ```
+//! use libp2p_core::{identity, Transport, upgrade};
+//! use libp2p_tcp::TcpConfig;
+//! use libp2p_tls::TlsConfig;
+//!
+//! # fn main() {
+//! let id_keys = identity::Keypair::generate_ed25519();
+//! let tls = TlsConfig::new(&id_keys).unwrap();
+//! let builder = TcpConfig::new().upgrade(upgrade::Version::V1).authenticate(tls);
+//! // let transport = builder.multiplex(...);
+//! # }
+//! ```
+//!
+//! > **Note**: Unlike noise, TLS sessions do not support the early
+//! > negotiation of a stream multiplexer, as `async-tls` does not expose
+//! > the negotiated ALPN protocol. A transport upgraded with
+//! > [`multiplex_early`](libp2p_core::transport::upgrade::Builder::multiplex_early)
+//! > always negotiates the multiplexer of a TLS session.
+//!
+//! [spec]: https://github.com/libp2p/specs/blob/master/tls/tls.md
+
+pub mod certificate;
+mod error;
+mod stats;
+mod verifier;
+
+pub use error::TlsError;
+pub use verifier::Verifier;
+
+use async_tls::{client, server, TlsAcceptor, TlsConnector};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{identity, InboundUpgrade, OutboundUpgrade, PeerId, UpgradeInfo};
+use libp2p_core::upgrade::{SecuredStream, SecurityStats};
+use rustls::{ClientConfig, ProtocolVersion, ServerConfig};
+use stats::{Counted, Counters};
+use std::{io, iter, pin::Pin, sync::{Arc, atomic::Ordering}, task::{Context, Poll}};
+
+/// The ALPN protocol of libp2p TLS sessions.
+pub const ALPN: &[u8] = b"libp2p";
+
+/// The server name sent by the dialer, which is ignored by the listener.
+pub const SERVER_NAME: &str = "libp2p";
+
+/// The TLS upgrade configuration.
+#[derive(Clone)]
+pub struct TlsConfig {
+    certificate: rustls::Certificate,
+    private_key: rustls::PrivateKey
+}
+
+impl TlsConfig {
+    /// Create a new configuration for the given identity keypair,
+    /// generating a certificate that carries its public key.
+    pub fn new(keypair: &identity::Keypair) -> Result<Self, TlsError> {
+        let (certificate, private_key) = certificate::generate(keypair)?;
+        Ok(TlsConfig { certificate, private_key })
+    }
+
+    /// The configuration of a dialer session with the given verifier.
+    ///
+    /// This allows transports with an integrated TLS handshake, e.g. QUIC,
+    /// to authenticate with the same certificate as the upgrade.
+    pub fn client_config(&self, verifier: Arc<Verifier>) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config.versions = vec![ProtocolVersion::TLSv1_3];
+        config.set_protocols(&[ALPN.to_vec()]);
+        config.set_single_client_cert(vec![self.certificate.clone()], self.private_key.clone());
+        config.dangerous().set_certificate_verifier(verifier);
+        config
+    }
+
+    /// The configuration of a listener session with the given verifier.
+    pub fn server_config(&self, verifier: Arc<Verifier>) -> Result<ServerConfig, TlsError> {
+        let mut config = ServerConfig::new(verifier);
+        config.versions = vec![ProtocolVersion::TLSv1_3];
+        config.set_protocols(&[ALPN.to_vec()]);
+        config.set_single_cert(vec![self.certificate.clone()], self.private_key.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(config)
+    }
+}
+
+impl UpgradeInfo for TlsConfig {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(b"/tls/1.0.0")
+    }
+}
+
+impl<C> InboundUpgrade<C> for TlsConfig
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + 'static
+{
+    type Output = (PeerId, TlsOutput<C>);
+    type Error = TlsError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: C, _: Self::Info) -> Self::Future {
+        Box::pin(async move {
+            let verifier = Arc::new(Verifier::default());
+            let config = self.server_config(verifier.clone())?;
+            let counters = Arc::new(Counters::default());
+            let socket = Counted::new(socket, counters.clone());
+            let stream = TlsAcceptor::from(Arc::new(config)).accept(socket).await?;
+            let remote = verifier.remote()?;
+            Ok((remote.into_peer_id(), TlsOutput::new(TlsStream::Server(stream), counters)))
+        })
+    }
+}
+
+impl<C> OutboundUpgrade<C> for TlsConfig
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + 'static
+{
+    type Output = (PeerId, TlsOutput<C>);
+    type Error = TlsError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: C, _: Self::Info) -> Self::Future {
+        Box::pin(async move {
+            let verifier = Arc::new(Verifier::default());
+            let config = self.client_config(verifier.clone());
+            let counters = Arc::new(Counters::default());
+            let socket = Counted::new(socket, counters.clone());
+            let stream = TlsConnector::from(Arc::new(config)).connect(SERVER_NAME, socket)?.await?;
+            let remote = verifier.remote()?;
+            Ok((remote.into_peer_id(), TlsOutput::new(TlsStream::Client(stream), counters)))
+        })
+    }
+}
+
+/// An established TLS session.
+pub struct TlsOutput<T> {
+    stream: TlsStream<Counted<T>>,
+    /// The counters of the underlying stream.
+    counters: Arc<Counters>,
+    plaintext_sent: u64,
+    plaintext_received: u64
+}
+
+/// The TLS stream of the dialer or the listener of a session.
+enum TlsStream<T> {
+    Client(client::TlsStream<T>),
+    Server(server::TlsStream<T>)
+}
+
+impl<T> TlsOutput<T> {
+    /// Create a session over the given stream once the handshake is complete.
+    fn new(stream: TlsStream<Counted<T>>, counters: Arc<Counters>) -> Self {
+        TlsOutput { stream, counters, plaintext_sent: 0, plaintext_received: 0 }
+    }
+}
+
+/// The statistics count TLS records as frames, including the records
+/// of the handshake and of post-handshake messages, e.g. session tickets.
+///
+/// A session never has an early muxer, since only the [`ALPN`] protocol
+/// is offered during the handshake.
+impl<T> SecuredStream for TlsOutput<T> {
+    fn security_stats(&self) -> SecurityStats {
+        SecurityStats {
+            plaintext_sent: self.plaintext_sent,
+            plaintext_received: self.plaintext_received,
+            ciphertext_sent: self.counters.bytes_sent.load(Ordering::Relaxed),
+            ciphertext_received: self.counters.bytes_received.load(Ordering::Relaxed),
+            frames_sent: self.counters.records_sent.load(Ordering::Relaxed),
+            frames_received: self.counters.records_received.load(Ordering::Relaxed)
+        }
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsOutput<T> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8])
+        -> Poll<Result<usize, io::Error>>
+    {
+        let n = futures::ready!(match &mut self.stream {
+            TlsStream::Client(s) => Pin::new(s).poll_read(cx, buf),
+            TlsStream::Server(s) => Pin::new(s).poll_read(cx, buf)
+        })?;
+        self.plaintext_received += n as u64;
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsOutput<T> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8])
+        -> Poll<Result<usize, io::Error>>
+    {
+        let n = futures::ready!(match &mut self.stream {
+            TlsStream::Client(s) => Pin::new(s).poll_write(cx, buf),
+            TlsStream::Server(s) => Pin::new(s).poll_write(cx, buf)
+        })?;
+        self.plaintext_sent += n as u64;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        match &mut self.stream {
+            TlsStream::Client(s) => Pin::new(s).poll_flush(cx),
+            TlsStream::Server(s) => Pin::new(s).poll_flush(cx)
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        match &mut self.stream {
+            TlsStream::Client(s) => Pin::new(s).poll_close(cx),
+            TlsStream::Server(s) => Pin::new(s).poll_close(cx)
+        }
+    }
+}
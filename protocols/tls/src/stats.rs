@@ -0,0 +1,138 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Traffic statistics of TLS sessions.
+
+use futures::prelude::*;
+use std::{io, pin::Pin, sync::{Arc, atomic::{AtomicU64, Ordering}}, task::{Context, Poll}};
+
+/// The length of the header of a TLS record.
+const RECORD_HEADER_LEN: usize = 5;
+
+/// The counters of the bytes and records on the underlying stream of
+/// a session, shared between the [`Counted`] stream and the session.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub(crate) bytes_sent: AtomicU64,
+    pub(crate) bytes_received: AtomicU64,
+    pub(crate) records_sent: AtomicU64,
+    pub(crate) records_received: AtomicU64
+}
+
+/// A stream which counts the bytes and TLS records read and written.
+pub(crate) struct Counted<T> {
+    inner: T,
+    counters: Arc<Counters>,
+    read: Records,
+    write: Records
+}
+
+impl<T> Counted<T> {
+    pub(crate) fn new(inner: T, counters: Arc<Counters>) -> Self {
+        Counted { inner, counters, read: Records::default(), write: Records::default() }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Counted<T> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8])
+        -> Poll<Result<usize, io::Error>>
+    {
+        let this = &mut *self;
+        let n = futures::ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        let records = this.read.consume(&buf[.. n]);
+        this.counters.bytes_received.fetch_add(n as u64, Ordering::Relaxed);
+        this.counters.records_received.fetch_add(records, Ordering::Relaxed);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Counted<T> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8])
+        -> Poll<Result<usize, io::Error>>
+    {
+        let this = &mut *self;
+        let n = futures::ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        let records = this.write.consume(&buf[.. n]);
+        this.counters.bytes_sent.fetch_add(n as u64, Ordering::Relaxed);
+        this.counters.records_sent.fetch_add(records, Ordering::Relaxed);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// Tracks the record boundaries in one direction of a TLS stream.
+#[derive(Debug, Default)]
+struct Records {
+    /// The header of the current record, as far as it has been seen.
+    header: [u8; RECORD_HEADER_LEN],
+    /// The number of bytes of the header seen so far.
+    header_len: usize,
+    /// The number of bytes of the body of the current record not yet seen.
+    remaining: usize
+}
+
+impl Records {
+    /// Consume the given bytes of the stream, returning the number of
+    /// records started in them.
+    fn consume(&mut self, mut bytes: &[u8]) -> u64 {
+        let mut records = 0;
+        while !bytes.is_empty() {
+            if self.remaining > 0 {
+                let n = std::cmp::min(self.remaining, bytes.len());
+                self.remaining -= n;
+                bytes = &bytes[n ..];
+                continue
+            }
+            self.header[self.header_len] = bytes[0];
+            self.header_len += 1;
+            bytes = &bytes[1 ..];
+            if self.header_len == RECORD_HEADER_LEN {
+                self.remaining = usize::from(u16::from_be_bytes([self.header[3], self.header[4]]));
+                self.header_len = 0;
+                records += 1
+            }
+        }
+        records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_split_across_reads() {
+        let mut records = Records::default();
+        // Two records with bodies of 3 and 0 bytes, and the start of a third.
+        let stream = [23, 3, 3, 0, 3, 1, 2, 3, 23, 3, 3, 0, 0, 23, 3];
+        assert_eq!(records.consume(&stream[.. 2]), 0);
+        assert_eq!(records.consume(&stream[2 .. 6]), 1);
+        assert_eq!(records.consume(&stream[6 ..]), 1);
+        assert_eq!(records.consume(&[3, 0, 1, 0]), 1);
+        assert_eq!(records.remaining, 0);
+    }
+}
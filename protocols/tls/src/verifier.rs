@@ -0,0 +1,100 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Verification of the certificates presented by remotes.
+
+use crate::{certificate, error::TlsError};
+use libp2p_core::{identity, Endpoint};
+use rustls::{
+    Certificate,
+    ClientCertVerified,
+    ClientCertVerifier,
+    DistinguishedNames,
+    RootCertStore,
+    ServerCertVerified,
+    ServerCertVerifier,
+    TLSError
+};
+use std::sync::Mutex;
+
+/// The certificate verifier of a single TLS session, which accepts exactly
+/// one certificate carrying a valid libp2p public key extension.
+///
+/// The public key of the remote is recorded for the upgrade to obtain
+/// once the TLS handshake is complete.
+#[derive(Default)]
+pub struct Verifier {
+    remote: Mutex<Option<identity::PublicKey>>
+}
+
+impl Verifier {
+    /// The public key of the remote, once its certificate has been verified.
+    pub fn remote(&self) -> Result<identity::PublicKey, TlsError> {
+        self.remote.lock()
+            .expect("Verifier mutex is not poisoned.")
+            .clone()
+            .ok_or(TlsError::InvalidCertificate)
+    }
+
+    /// Takes the public key of the last verified certificate, if any.
+    ///
+    /// A verifier shared by the sessions of a listener records the key of
+    /// whichever certificate was verified last, which is only meaningful
+    /// if the caller drives these sessions one at a time.
+    pub fn take_remote(&self) -> Option<identity::PublicKey> {
+        self.remote.lock().expect("Verifier mutex is not poisoned.").take()
+    }
+
+    fn verify(&self, presented_certs: &[Certificate], remote: Endpoint) -> Result<(), TLSError> {
+        let cert = match presented_certs {
+            [cert] => cert,
+            [] => return Err(TLSError::NoCertificatesPresented),
+            _ => return Err(TLSError::General("expected exactly one certificate".to_string()))
+        };
+        let public_key = certificate::verify(&cert.0, remote)
+            .map_err(|e| TLSError::General(e.to_string()))?;
+        *self.remote.lock().expect("Verifier mutex is not poisoned.") = Some(public_key);
+        Ok(())
+    }
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        _: &RootCertStore,
+        presented_certs: &[Certificate],
+        _: webpki::DNSNameRef<'_>,
+        _: &[u8]
+    ) -> Result<ServerCertVerified, TLSError> {
+        self.verify(presented_certs, Endpoint::Listener)?;
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+impl ClientCertVerifier for Verifier {
+    fn client_auth_root_subjects(&self) -> DistinguishedNames {
+        DistinguishedNames::new()
+    }
+
+    fn verify_client_cert(&self, presented_certs: &[Certificate]) -> Result<ClientCertVerified, TLSError> {
+        self.verify(presented_certs, Endpoint::Dialer)?;
+        Ok(ClientCertVerified::assertion())
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::{future, prelude::*};
+use libp2p_core::{identity, transport::{Transport, ListenerEvent}, upgrade::{self, SecuredStream}, PeerId};
+use libp2p_tcp::TcpConfig;
+use libp2p_tls::TlsConfig;
+
+#[test]
+fn tcp_roundtrip() {
+    let _ = env_logger::try_init();
+
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_secp256k1();
+    let server_peer = server_id.public().into_peer_id();
+    let client_peer = client_id.public().into_peer_id();
+
+    let server_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, TlsConfig::new(&server_id).unwrap(), endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |(peer, session), _| expect_peer(peer, session, &client_peer));
+
+    let client_transport = TcpConfig::new()
+        .and_then(move |output, endpoint| {
+            upgrade::apply(output, TlsConfig::new(&client_id).unwrap(), endpoint, upgrade::Version::V1)
+        })
+        .and_then(move |(peer, session), _| expect_peer(peer, session, &server_peer));
+
+    futures::executor::block_on(async move {
+        let message1 = b"Hello, TLS!".to_vec();
+        let message2 = message1.clone();
+
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let client_fut = async {
+            let mut client_session = client_transport.dial(server_address.clone())
+                .unwrap()
+                .await
+                .expect("no error");
+
+            client_session.write_all(&message2).await.expect("no error");
+            client_session.close().await.expect("no error");
+        };
+
+        let server_fut = async {
+            let mut server_session = server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade")
+                .await
+                .expect("no error");
+
+            let mut server_buffer = vec![];
+            server_session.read_to_end(&mut server_buffer).await.expect("no error");
+
+            assert_eq!(server_buffer, message1);
+
+            let stats = server_session.security_stats();
+            assert_eq!(stats.plaintext_received, message1.len() as u64);
+            assert!(stats.ciphertext_received > stats.plaintext_received);
+            assert!(stats.frames_received >= 1);
+            assert_eq!(server_session.early_muxer(), None);
+        };
+
+        future::join(server_fut, client_fut).await;
+    })
+}
+
+fn expect_peer<S>(peer: PeerId, session: S, expected: &PeerId) -> future::Ready<Result<S, libp2p_tls::TlsError>> {
+    assert_eq!(&peer, expected);
+    future::ok(session)
+}
@@ -171,6 +171,9 @@ pub use libp2p_dns as dns;
 pub use libp2p_identify as identify;
 #[doc(inline)]
 pub use libp2p_kad as kad;
+#[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+#[doc(inline)]
+pub use libp2p_keystore as keystore;
 #[doc(inline)]
 pub use libp2p_floodsub as floodsub;
 #[doc(inline)]
@@ -186,12 +189,26 @@ pub use libp2p_ping as ping;
 #[doc(inline)]
 pub use libp2p_plaintext as plaintext;
 #[doc(inline)]
+pub use libp2p_pnet as pnet;
+#[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+#[doc(inline)]
+pub use libp2p_quic as quic;
+#[doc(inline)]
 pub use libp2p_secio as secio;
+#[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+#[doc(inline)]
+pub use libp2p_socks5 as socks5;
 #[doc(inline)]
 pub use libp2p_swarm as swarm;
 #[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
 #[doc(inline)]
 pub use libp2p_tcp as tcp;
+#[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+#[doc(inline)]
+pub use libp2p_tls as tls;
+#[cfg(not(any(target_os = "emscripten", target_os = "unknown")))]
+#[doc(inline)]
+pub use libp2p_udp as udp;
 #[doc(inline)]
 pub use libp2p_uds as uds;
 #[doc(inline)]
@@ -206,10 +223,12 @@ mod transport_ext;
 
 pub mod bandwidth;
 pub mod simple;
+pub mod throttle;
 
 pub use self::core::{
     identity,
     PeerId,
+    PeerIdConfig,
     Transport,
     transport::TransportError,
     upgrade::{InboundUpgrade, InboundUpgradeExt, OutboundUpgrade, OutboundUpgradeExt}
@@ -241,8 +260,13 @@ pub fn build_development_transport(keypair: identity::Keypair)
 pub fn build_tcp_ws_secio_mplex_yamux(keypair: identity::Keypair)
     -> io::Result<impl Transport<Output = (PeerId, impl core::muxing::StreamMuxer<OutboundSubstream = impl Send, Substream = impl Send, Error = impl Into<io::Error>> + Send + Sync), Error = impl error::Error + Send, Listener = impl Send, Dial = impl Send, ListenerUpgrade = impl Send> + Clone>
 {
+    let timeouts = core::transport::upgrade::UpgradeTimeouts::new()
+        .security_negotiation(Duration::from_secs(10))
+        .security_handshake(Duration::from_secs(10))
+        .muxer_negotiation(Duration::from_secs(10));
     Ok(CommonTransport::new()?
         .upgrade(core::upgrade::Version::V1)
+        .timeouts(timeouts)
         .authenticate(secio::SecioConfig::new(keypair))
         .multiplex(core::upgrade::SelectUpgrade::new(yamux::Config::default(), mplex::MplexConfig::new()))
         .map(|(peer, muxer), _| (peer, core::muxing::StreamMuxerBox::new(muxer)))
@@ -0,0 +1,414 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Limits the bandwidth of the connections of a transport.
+//!
+//! Rates are enforced with token buckets, both for all the connections of the transport taken
+//! together and for each connection individually. Throttling applies to the bytes going through
+//! the sockets, and the `Throttled` transport should therefore wrap the raw transport, before any
+//! upgrade is applied.
+
+use crate::{Multiaddr, core::{Transport, transport::{ListenerEvent, TransportError}}};
+use futures::{prelude::*, ready};
+use parking_lot::Mutex;
+use std::{cmp, io, pin::Pin, sync::Arc, task::{Context, Poll}, time::Duration};
+use wasm_timer::{Delay, Instant};
+
+/// A rate limit in bytes per second.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RateLimit {
+    bytes_per_sec: u64,
+    burst: u64,
+}
+
+impl RateLimit {
+    /// Creates a limit of `bytes_per_sec` bytes per second, allowing bursts of one second worth
+    /// of bytes.
+    ///
+    /// A rate of zero is treated as one byte per second.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let bytes_per_sec = cmp::max(bytes_per_sec, 1);
+        RateLimit { bytes_per_sec, burst: bytes_per_sec }
+    }
+
+    /// Sets the number of bytes that can be transferred at once after a period of inactivity.
+    pub fn burst(mut self, bytes: u64) -> Self {
+        self.burst = cmp::max(bytes, 1);
+        self
+    }
+}
+
+/// Configuration of a `Throttled` transport.
+///
+/// By default, no limit is applied.
+#[derive(Debug, Clone, Default)]
+pub struct ThrottleConfig {
+    upload: Option<RateLimit>,
+    download: Option<RateLimit>,
+    conn_upload: Option<RateLimit>,
+    conn_download: Option<RateLimit>,
+}
+
+impl ThrottleConfig {
+    /// Creates a configuration without limits.
+    pub fn new() -> Self {
+        ThrottleConfig::default()
+    }
+
+    /// Limits the upload rate of all the connections taken together.
+    pub fn upload(mut self, limit: RateLimit) -> Self {
+        self.upload = Some(limit);
+        self
+    }
+
+    /// Limits the download rate of all the connections taken together.
+    pub fn download(mut self, limit: RateLimit) -> Self {
+        self.download = Some(limit);
+        self
+    }
+
+    /// Limits the upload rate of each connection.
+    pub fn connection_upload(mut self, limit: RateLimit) -> Self {
+        self.conn_upload = Some(limit);
+        self
+    }
+
+    /// Limits the download rate of each connection.
+    pub fn connection_download(mut self, limit: RateLimit) -> Self {
+        self.conn_download = Some(limit);
+        self
+    }
+}
+
+/// Wraps around a `Transport` and limits the bandwidth of all the opened connections.
+#[derive(Clone)]
+pub struct Throttled<TInner> {
+    inner: TInner,
+    shared: Arc<Shared>,
+}
+
+/// State shared by all the connections of a `Throttled` transport.
+struct Shared {
+    config: ThrottleConfig,
+    upload: Option<Arc<Mutex<TokenBucket>>>,
+    download: Option<Arc<Mutex<TokenBucket>>>,
+}
+
+impl Shared {
+    fn limiters(&self) -> (Limiter, Limiter) {
+        let upload = Limiter::new(self.upload.clone(), self.config.conn_upload);
+        let download = Limiter::new(self.download.clone(), self.config.conn_download);
+        (upload, download)
+    }
+}
+
+impl<TInner> Throttled<TInner> {
+    /// Creates a new `Throttled` around the transport.
+    pub fn new(inner: TInner, config: ThrottleConfig) -> Self {
+        let bucket = |limit: Option<RateLimit>| {
+            limit.map(|l| Arc::new(Mutex::new(TokenBucket::new(l))))
+        };
+        let shared = Shared {
+            upload: bucket(config.upload),
+            download: bucket(config.download),
+            config,
+        };
+        Throttled { inner, shared: Arc::new(shared) }
+    }
+}
+
+impl<TInner> Transport for Throttled<TInner>
+where
+    TInner: Transport,
+{
+    type Output = ThrottledConnection<TInner::Output>;
+    type Error = TInner::Error;
+    type Listener = ThrottledListener<TInner::Listener>;
+    type ListenerUpgrade = ThrottledFuture<TInner::ListenerUpgrade>;
+    type Dial = ThrottledFuture<TInner::Dial>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let shared = self.shared;
+        self.inner
+            .listen_on(addr)
+            .map(move |inner| ThrottledListener { inner, shared })
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let shared = self.shared;
+        self.inner
+            .dial(addr)
+            .map(move |inner| ThrottledFuture { inner, shared })
+    }
+}
+
+/// Wraps around a `Stream` that produces connections. Wraps each connection around a rate
+/// limiter.
+#[pin_project::pin_project]
+pub struct ThrottledListener<TInner> {
+    #[pin]
+    inner: TInner,
+    shared: Arc<Shared>,
+}
+
+impl<TInner, TConn> Stream for ThrottledListener<TInner>
+where
+    TInner: TryStream<Ok = ListenerEvent<TConn>>
+{
+    type Item = Result<ListenerEvent<ThrottledFuture<TConn>>, TInner::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let event =
+            if let Some(event) = ready!(this.inner.try_poll_next(cx)?) {
+                event
+            } else {
+                return Poll::Ready(None)
+            };
+
+        let event = event.map({
+            let shared = this.shared.clone();
+            |inner| ThrottledFuture { inner, shared }
+        });
+
+        Poll::Ready(Some(Ok(event)))
+    }
+}
+
+/// Wraps around a `Future` that produces a connection. Wraps the connection around a rate
+/// limiter.
+#[pin_project::pin_project]
+pub struct ThrottledFuture<TInner> {
+    #[pin]
+    inner: TInner,
+    shared: Arc<Shared>,
+}
+
+impl<TInner: TryFuture> Future for ThrottledFuture<TInner> {
+    type Output = Result<ThrottledConnection<TInner::Ok>, TInner::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.inner.try_poll(cx)?);
+        let (upload, download) = this.shared.limiters();
+        Poll::Ready(Ok(ThrottledConnection { inner, upload, download }))
+    }
+}
+
+/// Wraps around an `AsyncRead + AsyncWrite` and limits the bandwidth that goes through it.
+#[pin_project::pin_project]
+pub struct ThrottledConnection<TInner> {
+    #[pin]
+    inner: TInner,
+    upload: Limiter,
+    download: Limiter,
+}
+
+impl<TInner: AsyncRead> AsyncRead for ThrottledConnection<TInner> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        let allowed = ready!(this.download.poll_allowed(cx, buf.len()))?;
+        let num_bytes = ready!(this.inner.poll_read(cx, &mut buf[.. allowed]))?;
+        this.download.consume(num_bytes);
+        Poll::Ready(Ok(num_bytes))
+    }
+}
+
+impl<TInner: AsyncWrite> AsyncWrite for ThrottledConnection<TInner> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        let allowed = ready!(this.upload.poll_allowed(cx, buf.len()))?;
+        let num_bytes = ready!(this.inner.poll_write(cx, &buf[.. allowed]))?;
+        this.upload.consume(num_bytes);
+        Poll::Ready(Ok(num_bytes))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.project();
+        this.inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.project();
+        this.inner.poll_close(cx)
+    }
+}
+
+/// Limits one direction of a connection, according to the bucket shared by all connections and
+/// the bucket of the connection.
+struct Limiter {
+    global: Option<Arc<Mutex<TokenBucket>>>,
+    local: Option<TokenBucket>,
+    /// Fires when enough tokens should be available to proceed.
+    delay: Option<Delay>,
+}
+
+impl Limiter {
+    fn new(global: Option<Arc<Mutex<TokenBucket>>>, local: Option<RateLimit>) -> Self {
+        Limiter {
+            global,
+            local: local.map(TokenBucket::new),
+            delay: None,
+        }
+    }
+
+    /// Returns the number of bytes, at most `len`, that can be transferred right now, or
+    /// schedules a wake-up for when transferring becomes possible again.
+    fn poll_allowed(&mut self, cx: &mut Context, len: usize) -> Poll<io::Result<usize>> {
+        if len == 0 || (self.global.is_none() && self.local.is_none()) {
+            return Poll::Ready(Ok(len))
+        }
+
+        loop {
+            if let Some(delay) = &mut self.delay {
+                ready!(delay.poll_unpin(cx))?;
+                self.delay = None;
+            }
+
+            let now = Instant::now();
+            let mut allowed = len as u64;
+            let mut wait = Duration::from_secs(0);
+            if let Some(bucket) = &mut self.local {
+                allowed = cmp::min(allowed, bucket.available(now));
+                wait = cmp::max(wait, bucket.wait(len as u64));
+            }
+            if let Some(bucket) = &self.global {
+                let mut bucket = bucket.lock();
+                allowed = cmp::min(allowed, bucket.available(now));
+                wait = cmp::max(wait, bucket.wait(len as u64));
+            }
+
+            if allowed > 0 {
+                return Poll::Ready(Ok(allowed as usize))
+            }
+            self.delay = Some(Delay::new(wait));
+        }
+    }
+
+    /// Takes `n` transferred bytes from the buckets.
+    fn consume(&mut self, n: usize) {
+        if let Some(bucket) = &mut self.local {
+            bucket.consume(n as u64)
+        }
+        if let Some(bucket) = &self.global {
+            bucket.lock().consume(n as u64)
+        }
+    }
+}
+
+/// A token bucket with one token per byte.
+///
+/// Since connections share a global bucket, more tokens than available can be consumed, in
+/// which case the bucket goes into debt.
+#[derive(Debug)]
+struct TokenBucket {
+    /// Tokens added per second.
+    rate: f64,
+    /// Maximum number of tokens.
+    capacity: f64,
+    /// Current number of tokens, negative when in debt.
+    tokens: f64,
+    /// Last time `tokens` has been updated.
+    last_update: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        TokenBucket {
+            rate: limit.bytes_per_sec as f64,
+            capacity: limit.burst as f64,
+            tokens: limit.burst as f64,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Refills the bucket and returns the number of available tokens.
+    fn available(&mut self, now: Instant) -> u64 {
+        if now > self.last_update {
+            let elapsed = (now - self.last_update).as_secs_f64();
+            self.tokens = self.capacity.min(self.tokens + elapsed * self.rate);
+            self.last_update = now;
+        }
+        if self.tokens >= 1.0 {
+            self.tokens as u64
+        } else {
+            0
+        }
+    }
+
+    /// Returns how long to wait before a transfer of `len` bytes can proceed.
+    ///
+    /// We don't wait for the full `len` bytes, but for roughly ten milliseconds worth of tokens,
+    /// to avoid both bursts and excessive wake-ups.
+    fn wait(&self, len: u64) -> Duration {
+        let wanted = (len as f64).min(self.capacity).min((self.rate / 100.0).max(1.0));
+        let missing = wanted - self.tokens;
+        if missing <= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(missing / self.rate)
+        }
+    }
+
+    fn consume(&mut self, n: u64) {
+        self.tokens -= n as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_refills() {
+        let mut bucket = TokenBucket::new(RateLimit::new(1000).burst(500));
+        let start = bucket.last_update;
+        assert_eq!(bucket.available(start), 500);
+        bucket.consume(700);
+        assert_eq!(bucket.available(start), 0);
+        // We wait for ten milliseconds worth of tokens on top of the debt.
+        let wait = bucket.wait(100);
+        assert!(wait > Duration::from_millis(209) && wait < Duration::from_millis(211));
+        assert_eq!(bucket.available(start + Duration::from_millis(350)), 150);
+        assert_eq!(bucket.available(start + Duration::from_secs(10)), 500);
+    }
+
+    #[test]
+    fn connection_upload_is_limited() {
+        let shared = Shared {
+            config: ThrottleConfig::new().connection_upload(RateLimit::new(10_000).burst(1000)),
+            upload: None,
+            download: None,
+        };
+        let (upload, download) = shared.limiters();
+        let mut conn = ThrottledConnection {
+            inner: futures::io::Cursor::new(Vec::new()),
+            upload,
+            download,
+        };
+
+        let start = Instant::now();
+        futures::executor::block_on(conn.write_all(&[0; 3000])).unwrap();
+        // The burst is sent immediately, the remaining 2000 bytes take 200ms.
+        assert!(start.elapsed() >= Duration::from_millis(190));
+        assert_eq!(conn.inner.get_ref().len(), 3000);
+    }
+}
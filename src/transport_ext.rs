@@ -20,7 +20,7 @@
 
 //! Provides the `TransportExt` trait.
 
-use crate::{bandwidth::BandwidthLogging, bandwidth::BandwidthSinks, Transport};
+use crate::{bandwidth::BandwidthLogging, bandwidth::BandwidthSinks, throttle::{ThrottleConfig, Throttled}, Transport};
 use std::{sync::Arc, time::Duration};
 
 /// Trait automatically implemented on all objects that implement `Transport`. Provides some
@@ -38,6 +38,15 @@ pub trait TransportExt: Transport {
         BandwidthLogging::new(self, period)
     }
 
+    /// Adds a layer on the `Transport` that limits the upload and download rates of the sockets
+    /// created by it.
+    fn with_throttling(self, config: ThrottleConfig) -> Throttled<Self>
+    where
+        Self: Sized
+    {
+        Throttled::new(self, config)
+    }
+
     // TODO: add methods to easily upgrade for secio/mplex/yamux
 }
 
@@ -0,0 +1,317 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Deterministic test harness for `NetworkBehaviour`s.
+//!
+//! [`new_swarm`] builds a swarm over the in-memory transport, secured with plaintext and
+//! multiplexed with mplex, which processes its connections on the task polling it rather than on
+//! a threads pool. [`drive`] polls such swarms one after the other on the current thread, in a
+//! fixed order, and hands their events to the test until it has seen what it was waiting for.
+//! Tests therefore neither open sockets nor sleep.
+//!
+//! Rather than by a duration, the tests are bounded by the number of events they may go
+//! through: [`drive`] panics once the swarms have produced [`DEFAULT_MAX_EVENTS`] events without
+//! the test being satisfied, which makes the failures independent of the speed of the machine.
+//!
+//! > **Note**: The timers of the protocols, such as their timeouts and intervals, still follow
+//! > the system clock.
+
+use futures::{executor, future, prelude::*};
+use libp2p_core::{
+    Multiaddr,
+    Negotiated,
+    PeerId,
+    Transport,
+    either::EitherError,
+    identity,
+    muxing::StreamMuxerBox,
+    nodes::Substream,
+    transport::{boxed::Boxed, memory::{MemoryTransport, MemoryTransportError}},
+    upgrade::{self, InboundUpgrade, OutboundUpgrade, UpgradeError, UpgradeInfo},
+};
+use libp2p_mplex::MplexConfig;
+use libp2p_plaintext::{PlainText2Config, PlainTextError};
+use libp2p_swarm::{
+    IntoProtocolsHandler, NetworkBehaviour, ProtocolsHandler, Swarm, SwarmBuilder, SwarmEvent,
+    StagedError,
+};
+use std::{io, task::{Context, Poll}};
+
+/// Number of events after which [`drive`] gives up.
+pub const DEFAULT_MAX_EVENTS: usize = 1000;
+
+/// Error of the [`TestTransport`].
+pub type TestTransportError = EitherError<
+    EitherError<MemoryTransportError, UpgradeError<PlainTextError>>,
+    UpgradeError<io::Error>,
+>;
+
+/// Transport of the swarms built by [`new_swarm`].
+pub type TestTransport = Boxed<(PeerId, StreamMuxerBox), TestTransportError>;
+
+/// Swarm built by [`new_swarm`].
+pub type TestSwarm<TBehaviour> = Swarm<TestTransport, TBehaviour>;
+
+/// The protocols handler of a behaviour.
+type HandlerOf<TBehaviour> =
+    <<TBehaviour as NetworkBehaviour>::ProtocolsHandler as IntoProtocolsHandler>::Handler;
+
+/// The substreams of the connections of a [`TestSwarm`].
+pub type TestSubstream = Substream<StreamMuxerBox>;
+
+/// Builds the transport of a swarm with the given identity.
+pub fn transport(keypair: &identity::Keypair) -> TestTransport {
+    MemoryTransport::default()
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key: keypair.public() })
+        .multiplex(MplexConfig::new())
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .boxed()
+}
+
+/// Builds a swarm with a new identity and the behaviour created by `behaviour` for it.
+///
+/// The connections of the swarm are processed while polling it, and its dial failures are
+/// classified by [`StagedError::stage`].
+pub fn new_swarm<TBehaviour>(behaviour: impl FnOnce(&identity::Keypair) -> TBehaviour) -> TestSwarm<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour,
+    TBehaviour::ProtocolsHandler: Send + 'static,
+    HandlerOf<TBehaviour>: ProtocolsHandler<Substream = TestSubstream> + Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::InEvent: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::OutEvent: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::Error: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundOpenInfo: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as UpgradeInfo>::Info: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as UpgradeInfo>::InfoIter: Send + 'static,
+    <<<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as UpgradeInfo>::InfoIter as IntoIterator>::IntoIter: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as InboundUpgrade<Negotiated<TestSubstream>>>::Error: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as InboundUpgrade<Negotiated<TestSubstream>>>::Future: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as UpgradeInfo>::Info: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as UpgradeInfo>::InfoIter: Send + 'static,
+    <<<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as UpgradeInfo>::InfoIter as IntoIterator>::IntoIter: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as OutboundUpgrade<Negotiated<TestSubstream>>>::Error: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as OutboundUpgrade<Negotiated<TestSubstream>>>::Future: Send + 'static,
+{
+    new_swarm_with(behaviour, |builder| builder)
+}
+
+/// Same as [`new_swarm`], with `configure` adjusting the builder of the swarm, e.g. to set its
+/// limits or its keep-alive policy.
+pub fn new_swarm_with<TBehaviour>(
+    behaviour: impl FnOnce(&identity::Keypair) -> TBehaviour,
+    configure: impl FnOnce(SwarmBuilder<TestTransport, TBehaviour>) -> SwarmBuilder<TestTransport, TBehaviour>,
+) -> TestSwarm<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour,
+    TBehaviour::ProtocolsHandler: Send + 'static,
+    HandlerOf<TBehaviour>: ProtocolsHandler<Substream = TestSubstream> + Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::InEvent: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::OutEvent: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::Error: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundOpenInfo: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as UpgradeInfo>::Info: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as UpgradeInfo>::InfoIter: Send + 'static,
+    <<<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as UpgradeInfo>::InfoIter as IntoIterator>::IntoIter: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as InboundUpgrade<Negotiated<TestSubstream>>>::Error: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as InboundUpgrade<Negotiated<TestSubstream>>>::Future: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as UpgradeInfo>::Info: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as UpgradeInfo>::InfoIter: Send + 'static,
+    <<<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as UpgradeInfo>::InfoIter as IntoIterator>::IntoIter: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as OutboundUpgrade<Negotiated<TestSubstream>>>::Error: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as OutboundUpgrade<Negotiated<TestSubstream>>>::Future: Send + 'static,
+{
+    let keypair = identity::Keypair::generate_ed25519();
+    let peer_id = keypair.public().into_peer_id();
+    let behaviour = behaviour(&keypair);
+    let builder = SwarmBuilder::new(transport(&keypair), behaviour, peer_id)
+        .run_tasks_locally()
+        .dial_error_classifier(StagedError::stage);
+    configure(builder).build()
+}
+
+/// Operations of the harness on a [`TestSwarm`].
+pub trait SwarmExt {
+    /// The events produced by the behaviour of the swarm.
+    type BehaviourEvent;
+
+    /// Returns the identity of the swarm.
+    fn peer_id(&self) -> PeerId;
+
+    /// Polls the swarm for its next event.
+    fn poll_event(&mut self, cx: &mut Context) -> Poll<SwarmEvent<Self::BehaviourEvent>>;
+
+    /// Starts listening on a new in-memory address, and drives the swarm until the address is
+    /// reported.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the swarm fails to listen.
+    fn listen_on_memory(&mut self) -> Multiaddr;
+
+    /// Starts dialing the given address.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the dial can't be started.
+    fn dial(&mut self, address: Multiaddr);
+}
+
+impl<TBehaviour> SwarmExt for TestSwarm<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour,
+    TBehaviour::ProtocolsHandler: Send + 'static,
+    HandlerOf<TBehaviour>: ProtocolsHandler<Substream = TestSubstream> + Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::InEvent: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::OutEvent: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::Error: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundOpenInfo: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as UpgradeInfo>::Info: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as UpgradeInfo>::InfoIter: Send + 'static,
+    <<<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as UpgradeInfo>::InfoIter as IntoIterator>::IntoIter: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as InboundUpgrade<Negotiated<TestSubstream>>>::Error: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol as InboundUpgrade<Negotiated<TestSubstream>>>::Future: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as UpgradeInfo>::Info: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as UpgradeInfo>::InfoIter: Send + 'static,
+    <<<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as UpgradeInfo>::InfoIter as IntoIterator>::IntoIter: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as OutboundUpgrade<Negotiated<TestSubstream>>>::Error: Send + 'static,
+    <<HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol as OutboundUpgrade<Negotiated<TestSubstream>>>::Future: Send + 'static,
+{
+    type BehaviourEvent = TBehaviour::OutEvent;
+
+    fn peer_id(&self) -> PeerId {
+        Swarm::local_peer_id(self).clone()
+    }
+
+    fn poll_event(&mut self, cx: &mut Context) -> Poll<SwarmEvent<Self::BehaviourEvent>> {
+        Swarm::next_event(self).boxed_local().poll_unpin(cx)
+    }
+
+    fn listen_on_memory(&mut self) -> Multiaddr {
+        Swarm::listen_on(self, "/memory/0".parse().expect("The address is valid; QED"))
+            .expect("The in-memory transport listens on any port; QED");
+        executor::block_on(async {
+            loop {
+                if let SwarmEvent::NewListenAddr(address) = Swarm::next_event(self).await {
+                    return address
+                }
+            }
+        })
+    }
+
+    fn dial(&mut self, address: Multiaddr) {
+        if let Err(error) = Swarm::dial_addr(self, address.clone()) {
+            panic!("Failed to dial {}: {}", address, error)
+        }
+    }
+}
+
+/// Event produced by one of the two swarms given to [`drive`].
+#[derive(Debug)]
+pub enum DrivenEvent<TFirst, TSecond> {
+    /// Event of the first swarm.
+    First(SwarmEvent<TFirst>),
+    /// Event of the second swarm.
+    Second(SwarmEvent<TSecond>),
+}
+
+/// Polls the two swarms, the first one then the second one, and passes their events to `until`
+/// until it returns a value.
+///
+/// # Panic
+///
+/// Panics if the swarms produce [`DEFAULT_MAX_EVENTS`] events without `until` returning a value.
+pub fn drive<TFirst, TSecond, TOut>(
+    first: &mut TFirst,
+    second: &mut TSecond,
+    until: impl FnMut(DrivenEvent<TFirst::BehaviourEvent, TSecond::BehaviourEvent>) -> Option<TOut>,
+) -> TOut
+where
+    TFirst: SwarmExt,
+    TSecond: SwarmExt,
+{
+    drive_with_limit(first, second, DEFAULT_MAX_EVENTS, until)
+}
+
+/// Same as [`drive`], giving up after `max_events` events instead of [`DEFAULT_MAX_EVENTS`].
+pub fn drive_with_limit<TFirst, TSecond, TOut>(
+    first: &mut TFirst,
+    second: &mut TSecond,
+    max_events: usize,
+    mut until: impl FnMut(DrivenEvent<TFirst::BehaviourEvent, TSecond::BehaviourEvent>) -> Option<TOut>,
+) -> TOut
+where
+    TFirst: SwarmExt,
+    TSecond: SwarmExt,
+{
+    let mut events = 0;
+    executor::block_on(future::poll_fn(|cx| {
+        loop {
+            let event = if let Poll::Ready(event) = first.poll_event(cx) {
+                DrivenEvent::First(event)
+            } else if let Poll::Ready(event) = second.poll_event(cx) {
+                DrivenEvent::Second(event)
+            } else {
+                return Poll::Pending
+            };
+            if let Some(out) = until(event) {
+                return Poll::Ready(out)
+            }
+            events += 1;
+            if events >= max_events {
+                panic!("The swarms produced {} events without reaching the expected state", events)
+            }
+        }
+    }))
+}
+
+/// Connects the first swarm to the second one, by making the second one listen on a new
+/// in-memory address and the first one dial it, and drives them until both report the
+/// connection.
+pub fn connect<TFirst, TSecond>(first: &mut TFirst, second: &mut TSecond)
+where
+    TFirst: SwarmExt,
+    TSecond: SwarmExt,
+{
+    let address = second.listen_on_memory();
+    first.dial(address);
+    let (first_id, second_id) = (first.peer_id(), second.peer_id());
+    let (mut first_connected, mut second_connected) = (false, false);
+    drive(first, second, |event| {
+        match event {
+            DrivenEvent::First(SwarmEvent::Connected { peer_id, .. }) if peer_id == second_id =>
+                first_connected = true,
+            DrivenEvent::Second(SwarmEvent::Connected { peer_id, .. }) if peer_id == first_id =>
+                second_connected = true,
+            _ => {}
+        }
+        if first_connected && second_connected {
+            Some(())
+        } else {
+            None
+        }
+    })
+}
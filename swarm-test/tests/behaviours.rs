@@ -0,0 +1,97 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_ping::{Ping, PingConfig, PingEvent, PingSuccess};
+use libp2p_swarm::{DynamicBehaviour, KeepAlivePolicy, PeerKeepAlive, SwarmEvent, toggle::Conditional};
+use libp2p_swarm_test::{DrivenEvent, SwarmExt, TestSubstream, connect, drive, new_swarm, new_swarm_with};
+use std::{num::NonZeroU32, sync::{Arc, atomic::{AtomicBool, Ordering}}};
+
+/// The configuration of the pings. The first ping of the listener fails, as the dialer doesn't
+/// support any protocol yet.
+fn ping_config() -> PingConfig {
+    PingConfig::new()
+        .with_keep_alive(true)
+        .with_max_failures(NonZeroU32::new(3).unwrap())
+}
+
+#[test]
+fn dynamic_behaviour_inserted_while_connected() {
+    let mut swarm1 = new_swarm(|_| {
+        let mut behaviour = DynamicBehaviour::<TestSubstream, PingEvent>::new();
+        behaviour.insert(Ping::new(ping_config()));
+        behaviour
+    });
+    let peer1_id = swarm1.peer_id();
+
+    // Without any behaviour, the handler of the dialer doesn't keep the connection alive.
+    let keep_alive = KeepAlivePolicy::new().peer(peer1_id.clone(), PeerKeepAlive::Always);
+    let mut swarm2 = new_swarm_with(
+        |_| DynamicBehaviour::<TestSubstream, PingEvent>::new(),
+        |builder| builder.keep_alive_policy(keep_alive),
+    );
+
+    connect(&mut swarm2, &mut swarm1);
+
+    let id = swarm2.insert(Ping::new(ping_config()));
+    assert!(swarm2.contains(id));
+    let pinged = drive(&mut swarm1, &mut swarm2, |event| match event {
+        DrivenEvent::Second(SwarmEvent::Behaviour(PingEvent { peer, result: Ok(PingSuccess::Ping { .. }) })) =>
+            Some(peer),
+        _ => None,
+    });
+    assert_eq!(pinged, peer1_id);
+
+    assert!(swarm2.remove(id));
+    assert!(!swarm2.remove(id));
+    assert!(swarm2.is_empty());
+}
+
+#[test]
+fn conditional_behaviour_enabled_while_connected() {
+    let mut swarm1 = new_swarm(|_| Ping::new(ping_config()));
+    let peer1_id = swarm1.peer_id();
+
+    let enabled = Arc::new(AtomicBool::new(false));
+    let predicate = {
+        let enabled = enabled.clone();
+        move || enabled.load(Ordering::SeqCst)
+    };
+    let keep_alive = KeepAlivePolicy::new().peer(peer1_id.clone(), PeerKeepAlive::Always);
+    let mut swarm2 = new_swarm_with(
+        |_| Conditional::new(Ping::new(ping_config()), predicate),
+        |builder| builder.keep_alive_policy(keep_alive),
+    );
+    assert!(!swarm2.is_enabled());
+
+    connect(&mut swarm2, &mut swarm1);
+
+    enabled.store(true, Ordering::SeqCst);
+    let pinged = drive(&mut swarm1, &mut swarm2, |event| match event {
+        DrivenEvent::Second(SwarmEvent::Behaviour(PingEvent { peer, result: Ok(PingSuccess::Ping { .. }) })) =>
+            Some(peer),
+        _ => None,
+    });
+    assert_eq!(pinged, peer1_id);
+    assert!(swarm2.is_enabled());
+
+    enabled.store(false, Ordering::SeqCst);
+    assert!(!swarm2.update());
+    assert!(!swarm2.is_enabled());
+}
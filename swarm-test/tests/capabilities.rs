@@ -0,0 +1,49 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_identify::{Identify, IdentifyEvent};
+use libp2p_swarm::{KeepAlivePolicy, ProtocolSupport, Swarm, SwarmEvent};
+use libp2p_swarm_test::{DrivenEvent, SwarmExt, connect, drive, new_swarm};
+use std::time::Duration;
+
+#[test]
+fn identify_feeds_protocol_capabilities() {
+    let identify = |keypair: &libp2p_core::identity::Keypair| {
+        Identify::new("test/1.0.0".to_owned(), "swarm-test".to_owned(), keypair.public())
+    };
+    let mut swarm1 = new_swarm(identify);
+    let mut swarm2 = new_swarm(identify);
+    // Identify alone doesn't keep the connection alive.
+    let keep_alive = KeepAlivePolicy::new().idle_timeout(Duration::from_secs(60));
+    Swarm::set_keep_alive_policy(&mut swarm1, keep_alive.clone());
+    Swarm::set_keep_alive_policy(&mut swarm2, keep_alive);
+
+    connect(&mut swarm1, &mut swarm2);
+    let peer2_id = swarm2.peer_id();
+    assert_eq!(Swarm::protocol_capabilities(&swarm1).support(&peer2_id, b"/ipfs/id/1.0.0"), ProtocolSupport::Unknown);
+
+    drive(&mut swarm1, &mut swarm2, |event| match event {
+        DrivenEvent::First(SwarmEvent::Behaviour(IdentifyEvent::Received { .. })) => Some(()),
+        _ => None,
+    });
+    let capabilities = Swarm::protocol_capabilities(&swarm1);
+    assert_eq!(capabilities.support(&peer2_id, b"/ipfs/id/1.0.0"), ProtocolSupport::Supported);
+    assert!(capabilities.is_unsupported(&peer2_id, b"/ipfs/ping/1.0.0"));
+}
@@ -0,0 +1,64 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_core::{ConnectedPoint, PeerId};
+use libp2p_ping::{Ping, PingConfig};
+use libp2p_swarm::{ConnectionGater, ConnectionTags, Swarm, SwarmEvent};
+use libp2p_swarm_test::{DrivenEvent, SwarmExt, connect, drive, new_swarm};
+
+/// Denies all new connections while a connection is labeled `"exclusive"`.
+#[derive(Default)]
+struct ExclusiveGater {
+    tags: ConnectionTags,
+}
+
+impl ConnectionGater for ExclusiveGater {
+    fn allow_secured(&mut self, _: &PeerId, _: &ConnectedPoint) -> bool {
+        self.tags.count_label("exclusive") == 0
+    }
+
+    fn inject_connection_tags(&mut self, tags: ConnectionTags) {
+        self.tags = tags;
+    }
+}
+
+#[test]
+fn gater_consults_connection_labels() {
+    let mut swarm1 = new_swarm(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+    let mut swarm2 = new_swarm(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+    let mut swarm3 = new_swarm(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+    Swarm::set_connection_gater(&mut swarm2, ExclusiveGater::default());
+
+    connect(&mut swarm1, &mut swarm2);
+    let peer1_id = swarm1.peer_id();
+    let tags = Swarm::connection_tags(&swarm2).clone();
+    assert!(tags.add_label(&peer1_id, "exclusive"));
+    assert!(tags.insert(&peer1_id, 7u8).is_ok());
+    assert_eq!(tags.labels(&peer1_id), vec!["exclusive".to_owned()]);
+    assert_eq!(tags.get::<u8>(&peer1_id), Some(7));
+
+    let address = Swarm::listeners(&swarm2).next().unwrap().clone();
+    swarm3.dial(address);
+    let gated = drive(&mut swarm3, &mut swarm2, |event| match event {
+        DrivenEvent::Second(SwarmEvent::ConnectionGated { peer_id, .. }) => Some(peer_id),
+        _ => None,
+    });
+    assert_eq!(gated, Some(swarm3.peer_id()));
+}
@@ -0,0 +1,67 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_core::{ConnectedPoint, Multiaddr};
+use libp2p_ping::{Ping, PingConfig};
+use libp2p_swarm::{AddressFailure, DialOpts, FailureKind, PeerCondition, Swarm, SwarmEvent};
+use libp2p_swarm_test::{DrivenEvent, SwarmExt, drive, new_swarm};
+use std::num::NonZeroU8;
+
+#[test]
+fn dial_opts_addresses_conditions_and_tag() {
+    let mut swarm1 = new_swarm(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+    let mut swarm2 = new_swarm(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+    let peer1_id = swarm1.peer_id();
+    let address = swarm1.listen_on_memory();
+
+    // The behaviour doesn't know any address of the peer.
+    assert!(Swarm::dial_with_opts(&mut swarm2, peer1_id.clone(), DialOpts::new()).is_none());
+
+    let unreachable: Multiaddr = "/memory/1".parse().unwrap();
+    let opts = DialOpts::new()
+        .addresses(vec![unreachable, address.clone()])
+        .concurrency(NonZeroU8::new(2).unwrap())
+        .tag(7);
+    assert!(Swarm::dial_with_opts(&mut swarm2, peer1_id.clone(), opts).is_some());
+    let not_dialing = DialOpts::new()
+        .addresses(vec![address.clone()])
+        .condition(PeerCondition::NotDialing);
+    assert!(Swarm::dial_with_opts(&mut swarm2, peer1_id.clone(), not_dialing).is_none());
+
+    drive(&mut swarm1, &mut swarm2, |event| match event {
+        DrivenEvent::Second(SwarmEvent::Connected { peer_id, endpoint, tag }) => {
+            assert_eq!(peer_id, peer1_id);
+            match endpoint {
+                ConnectedPoint::Dialer { address: dialed } => assert_eq!(dialed, address),
+                ConnectedPoint::Listener { .. } => panic!("Unexpected endpoint"),
+            }
+            assert_eq!(tag, Some(7));
+            Some(())
+        }
+        DrivenEvent::Second(SwarmEvent::UnreachableAddr { error, tag, .. }) => {
+            assert!(matches!(error, AddressFailure::Transport(_)));
+            assert_eq!(error.kind(), FailureKind::Unreachable);
+            assert_eq!(tag, Some(7));
+            None
+        }
+        _ => None,
+    });
+    assert!(Swarm::dial_with_opts(&mut swarm2, peer1_id, DialOpts::new().addresses(vec![address])).is_none());
+}
@@ -0,0 +1,77 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
+use libp2p_ping::{Ping, PingConfig, PingEvent};
+use libp2p_swarm::{ConnectionGater, Swarm, SwarmEvent};
+use libp2p_swarm_test::{DrivenEvent, SwarmExt, drive, new_swarm};
+
+/// Gater denying all the incoming connections, or the connections with a peer.
+struct DenyGater {
+    accept: bool,
+    peer: Option<PeerId>,
+}
+
+impl ConnectionGater for DenyGater {
+    fn allow_accept(&mut self, _: &Multiaddr, _: &Multiaddr) -> bool {
+        self.accept
+    }
+
+    fn allow_secured(&mut self, peer_id: &PeerId, _: &ConnectedPoint) -> bool {
+        self.peer.as_ref() != Some(peer_id)
+    }
+}
+
+/// Makes a swarm dial a listening swarm with the given gater, and returns the first connection
+/// event of the listener.
+fn gated_connection(gater: impl FnOnce(&PeerId) -> DenyGater) -> SwarmEvent<PingEvent> {
+    let mut swarm1 = new_swarm(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+    let mut swarm2 = new_swarm(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+    Swarm::set_connection_gater(&mut swarm1, gater(&swarm2.peer_id()));
+
+    let address = swarm1.listen_on_memory();
+    swarm2.dial(address);
+    drive(&mut swarm1, &mut swarm2, |event| match event {
+        DrivenEvent::First(event @ SwarmEvent::ConnectionGated { .. })
+        | DrivenEvent::First(event @ SwarmEvent::Connected { .. }) => Some(event),
+        _ => None,
+    })
+}
+
+#[test]
+fn gater_denies_accepted_connection() {
+    match gated_connection(|_| DenyGater { accept: false, peer: None }) {
+        SwarmEvent::ConnectionGated { peer_id: None, endpoint, .. } => assert!(endpoint.is_listener()),
+        event => panic!("Unexpected event: {:?}", event),
+    }
+}
+
+#[test]
+fn gater_denies_secured_peer() {
+    let mut denied = None;
+    let event = gated_connection(|peer| {
+        denied = Some(peer.clone());
+        DenyGater { accept: true, peer: Some(peer.clone()) }
+    });
+    match event {
+        SwarmEvent::ConnectionGated { peer_id, .. } => assert_eq!(peer_id, denied),
+        event => panic!("Unexpected event: {:?}", event),
+    }
+}
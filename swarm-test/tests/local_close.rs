@@ -0,0 +1,134 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::prelude::*;
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
+use libp2p_ping::{Ping, PingConfig, PingEvent, PingResult, PingSuccess, handler::PingHandler};
+use libp2p_swarm::{
+    NetworkBehaviour, NetworkBehaviourAction, PollParameters, ProtocolSupport, ProtocolsHandler,
+    Swarm, SwarmEvent,
+};
+use libp2p_swarm_test::{DrivenEvent, SwarmExt, connect, drive, new_swarm};
+use std::task::{Context, Poll};
+
+/// Pings the connected peers, disconnects from a peer when asked to, and records the peers the
+/// swarm reported as disconnected.
+struct Disconnecting<TSubstream> {
+    ping: Ping<TSubstream>,
+    disconnect: Option<PeerId>,
+    disconnected: Vec<PeerId>,
+}
+
+impl<TSubstream> Disconnecting<TSubstream> {
+    fn new() -> Self {
+        Disconnecting {
+            ping: Ping::new(PingConfig::new().with_keep_alive(true)),
+            disconnect: None,
+            disconnected: Vec::new(),
+        }
+    }
+}
+
+impl<TSubstream> NetworkBehaviour for Disconnecting<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type ProtocolsHandler = PingHandler<TSubstream>;
+    type OutEvent = PingEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        self.ping.new_handler()
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.ping.addresses_of_peer(peer_id)
+    }
+
+    fn inject_connected(&mut self, peer_id: PeerId, endpoint: ConnectedPoint) {
+        self.ping.inject_connected(peer_id, endpoint)
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId, endpoint: ConnectedPoint) {
+        self.disconnected.push(peer_id.clone());
+        self.ping.inject_disconnected(peer_id, endpoint)
+    }
+
+    fn inject_node_event(&mut self, peer_id: PeerId, result: PingResult) {
+        self.ping.inject_node_event(peer_id, result)
+    }
+
+    fn poll(&mut self, cx: &mut Context, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<<Self::ProtocolsHandler as ProtocolsHandler>::InEvent, PingEvent>>
+    {
+        if let Some(peer_id) = self.disconnect.take() {
+            return Poll::Ready(NetworkBehaviourAction::DisconnectPeer { peer_id })
+        }
+        self.ping.poll(cx, params)
+    }
+}
+
+/// Drives the swarms until the first one has pinged the second one.
+fn ping<TFirst: SwarmExt<BehaviourEvent = PingEvent>, TSecond: SwarmExt>(first: &mut TFirst, second: &mut TSecond) {
+    drive(first, second, |event| match event {
+        DrivenEvent::First(SwarmEvent::Behaviour(PingEvent { result: Ok(PingSuccess::Ping { .. }), .. })) => Some(()),
+        _ => None,
+    })
+}
+
+#[test]
+fn disconnect_peer_forgets_the_peer() {
+    let mut swarm1 = new_swarm(|_| Disconnecting::new());
+    let mut swarm2 = new_swarm(|_| Disconnecting::new());
+    let peer2_id = swarm2.peer_id();
+
+    connect(&mut swarm1, &mut swarm2);
+    ping(&mut swarm1, &mut swarm2);
+    assert!(Swarm::connection_tags(&swarm1).add_label(&peer2_id, "pinged"));
+    assert_eq!(Swarm::protocol_capabilities(&swarm1).support(&peer2_id, b"/ipfs/ping/1.0.0"), ProtocolSupport::Supported);
+
+    swarm1.disconnect = Some(peer2_id.clone());
+    let disconnected = drive(&mut swarm1, &mut swarm2, |event| match event {
+        DrivenEvent::First(SwarmEvent::Disconnected(peer_id)) => Some(peer_id),
+        _ => None,
+    });
+    assert_eq!(disconnected, peer2_id);
+    assert_eq!(swarm1.disconnected, vec![peer2_id.clone()]);
+    assert!(!Swarm::connection_tags(&swarm1).is_connected(&peer2_id));
+    assert!(Swarm::connection_tags(&swarm1).labels(&peer2_id).is_empty());
+    assert_eq!(Swarm::protocol_capabilities(&swarm1).support(&peer2_id, b"/ipfs/ping/1.0.0"), ProtocolSupport::Unknown);
+}
+
+#[test]
+fn ban_forgets_the_peer() {
+    let mut swarm1 = new_swarm(|_| Disconnecting::new());
+    let mut swarm2 = new_swarm(|_| Disconnecting::new());
+    let peer2_id = swarm2.peer_id();
+
+    connect(&mut swarm1, &mut swarm2);
+    ping(&mut swarm1, &mut swarm2);
+    assert!(Swarm::connection_tags(&swarm1).add_label(&peer2_id, "pinged"));
+    assert_eq!(Swarm::protocol_capabilities(&swarm1).support(&peer2_id, b"/ipfs/ping/1.0.0"), ProtocolSupport::Supported);
+
+    Swarm::ban_peer_id(&mut swarm1, peer2_id.clone());
+    assert_eq!(swarm1.disconnected, vec![peer2_id.clone()]);
+    assert!(!Swarm::connection_tags(&swarm1).is_connected(&peer2_id));
+    assert!(Swarm::connection_tags(&swarm1).labels(&peer2_id).is_empty());
+    assert_eq!(Swarm::protocol_capabilities(&swarm1).support(&peer2_id, b"/ipfs/ping/1.0.0"), ProtocolSupport::Unknown);
+}
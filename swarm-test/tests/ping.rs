@@ -0,0 +1,57 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_ping::{Ping, PingConfig, PingSuccess};
+use libp2p_swarm::SwarmEvent;
+use libp2p_swarm_test::{DrivenEvent, SwarmExt, connect, drive, new_swarm};
+
+#[test]
+fn ping_between_connected_swarms() {
+    let config = PingConfig::new().with_keep_alive(true);
+    let mut swarm1 = new_swarm(|_| Ping::new(config.clone()));
+    let mut swarm2 = new_swarm(|_| Ping::new(config.clone()));
+    let (peer1_id, peer2_id) = (swarm1.peer_id(), swarm2.peer_id());
+
+    connect(&mut swarm1, &mut swarm2);
+
+    let (mut pinged, mut ponged) = (None, None);
+    drive(&mut swarm1, &mut swarm2, |event| {
+        match event {
+            DrivenEvent::First(SwarmEvent::Behaviour(ping)) => {
+                if let Ok(PingSuccess::Ping { .. }) = ping.result {
+                    pinged = Some(ping.peer)
+                }
+            }
+            DrivenEvent::Second(SwarmEvent::Behaviour(ping)) => {
+                if let Ok(PingSuccess::Pong) = ping.result {
+                    ponged = Some(ping.peer)
+                }
+            }
+            _ => {}
+        }
+        if pinged.is_some() && ponged.is_some() {
+            Some(())
+        } else {
+            None
+        }
+    });
+    assert_eq!(pinged, Some(peer2_id));
+    assert_eq!(ponged, Some(peer1_id));
+}
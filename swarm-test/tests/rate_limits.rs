@@ -0,0 +1,54 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_ping::{Ping, PingConfig, PingEvent, PingSuccess};
+use libp2p_swarm::{InboundRateLimits, RateLimit, SwarmEvent};
+use libp2p_swarm_test::{DrivenEvent, SwarmExt, connect, drive, new_swarm, new_swarm_with};
+use std::{num::NonZeroU32, time::Duration};
+
+#[test]
+fn inbound_substreams_rate_limited() {
+    let config = PingConfig::new()
+        .with_keep_alive(true)
+        .with_interval(Duration::from_millis(10))
+        .with_max_failures(NonZeroU32::new(u32::MAX).unwrap());
+    let limits = InboundRateLimits::new()
+        .protocol(&b"/ipfs/ping/1.0.0"[..], RateLimit::new(2, Duration::from_secs(3600)));
+    let mut swarm1 = new_swarm_with(|_| Ping::new(config.clone()), |builder| builder.inbound_rate_limits(limits));
+    let mut swarm2 = new_swarm(|_| Ping::new(config.clone()));
+    let peer2_id = swarm2.peer_id();
+
+    connect(&mut swarm2, &mut swarm1);
+
+    let mut pongs = 0;
+    drive(&mut swarm1, &mut swarm2, |event| match event {
+        DrivenEvent::First(SwarmEvent::Behaviour(PingEvent { result: Ok(PingSuccess::Pong), .. })) => {
+            pongs += 1;
+            None
+        }
+        DrivenEvent::First(SwarmEvent::InboundSubstreamRateLimited { peer_id, protocol }) => {
+            assert_eq!(peer_id, peer2_id);
+            assert_eq!(protocol, b"/ipfs/ping/1.0.0");
+            Some(())
+        }
+        _ => None,
+    });
+    assert!(pongs <= 2);
+}
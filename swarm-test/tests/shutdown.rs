@@ -0,0 +1,43 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::executor;
+use libp2p_ping::{Ping, PingConfig};
+use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_swarm_test::{DrivenEvent, SwarmExt, connect, drive, new_swarm};
+use std::time::Duration;
+
+#[test]
+fn shutdown_closes_connections() {
+    let mut swarm1 = new_swarm(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+    let mut swarm2 = new_swarm(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+    let peer1_id = swarm1.peer_id();
+
+    connect(&mut swarm2, &mut swarm1);
+
+    // The ping handlers keep the connection alive, so it is closed after the grace period.
+    executor::block_on(swarm1.shutdown(Duration::from_millis(100)));
+    assert_eq!(Swarm::listeners(&swarm1).count(), 0);
+    let disconnected = drive(&mut swarm1, &mut swarm2, |event| match event {
+        DrivenEvent::Second(SwarmEvent::Disconnected(peer)) => Some(peer),
+        _ => None,
+    });
+    assert_eq!(disconnected, peer1_id);
+}
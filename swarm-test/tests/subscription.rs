@@ -0,0 +1,51 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::{executor, prelude::*};
+use libp2p_core::ConnectedPoint;
+use libp2p_ping::{Ping, PingConfig};
+use libp2p_swarm::{Swarm, SwarmNotification};
+use libp2p_swarm_test::{SwarmExt, connect, new_swarm};
+
+#[test]
+fn subscription_observes_swarm_events() {
+    let mut swarm1 = new_swarm(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+    let mut swarm2 = new_swarm(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+    let mut subscription = Swarm::subscribe(&mut swarm1);
+
+    connect(&mut swarm2, &mut swarm1);
+
+    // The notifications of the events produced while connecting are buffered.
+    let address = Swarm::listeners(&swarm1).next().unwrap().clone();
+    match executor::block_on(subscription.next()) {
+        Some(SwarmNotification::NewListenAddr(a)) => assert_eq!(a, address),
+        n => panic!("Unexpected notification: {:?}", n),
+    }
+    loop {
+        match executor::block_on(subscription.next()) {
+            Some(SwarmNotification::Connected { peer_id, endpoint: ConnectedPoint::Listener { .. }, .. }) => {
+                assert_eq!(peer_id, swarm2.peer_id());
+                break
+            }
+            Some(_) => {}
+            None => panic!("Subscription ended"),
+        }
+    }
+}
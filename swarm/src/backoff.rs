@@ -0,0 +1,280 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Back-off of the dials that keep failing.
+//!
+//! After a failed dial, the address is not dialed again by the swarm until a back-off delay has
+//! elapsed. Similarly, once all the addresses of a peer have failed, the peer is not dialed
+//! again until its own back-off delay has elapsed. The delay grows exponentially with the number
+//! of consecutive failures, following the [`BackoffConfig`], and is reset by a successful
+//! connection.
+//!
+//! Addresses explicitly dialed with [`ExpandedSwarm::dial_addr`](crate::ExpandedSwarm::dial_addr)
+//! are dialed regardless of the back-off, but their failures are recorded.
+
+use libp2p_core::{Multiaddr, PeerId};
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use wasm_timer::Instant;
+
+/// Curve of the back-off delays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackoffConfig {
+    initial: Duration,
+    max: Duration,
+    multiplier: f64,
+    jitter: f64,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        BackoffConfig {
+            initial: Duration::from_secs(5),
+            max: Duration::from_secs(10 * 60),
+            multiplier: 2.0,
+            jitter: 0.1,
+        }
+    }
+}
+
+impl BackoffConfig {
+    /// Creates the default curve: 5 seconds after the first failure, doubling after each failure
+    /// up to 10 minutes, with a jitter of 10%.
+    pub fn new() -> Self {
+        BackoffConfig::default()
+    }
+
+    /// Sets the delay after the first failure.
+    pub fn initial(mut self, initial: Duration) -> Self {
+        self.initial = initial;
+        self
+    }
+
+    /// Sets the maximum delay, before jitter.
+    pub fn max(mut self, max: Duration) -> Self {
+        self.max = max;
+        self
+    }
+
+    /// Sets the factor by which the delay grows after each failure.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `multiplier` is smaller than 1.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        assert!(multiplier >= 1.0, "the multiplier must be at least 1");
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Sets the fraction of the delay by which it is randomly lengthened or shortened, so that
+    /// peers failing together are not dialed again all at once.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `jitter` is not between 0 and 1.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        assert!((0.0 ..= 1.0).contains(&jitter), "the jitter must be between 0 and 1");
+        self.jitter = jitter;
+        self
+    }
+
+    /// Returns the delay after the given number of consecutive failures, which must be at
+    /// least 1.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let secs = self.initial.as_secs_f64() * self.multiplier.powi(exponent);
+        let secs = secs.min(self.max.as_secs_f64());
+        let factor = if self.jitter > 0.0 {
+            rand::thread_rng().gen_range(1.0 - self.jitter, 1.0 + self.jitter)
+        } else {
+            1.0
+        };
+        Duration::from_secs_f64(secs * factor)
+    }
+}
+
+/// Consecutive failures of a peer or an address.
+#[derive(Debug, Clone)]
+struct Failures {
+    count: u32,
+    until: Instant,
+}
+
+/// Shared handle to the back-off state of the swarm.
+#[derive(Clone, Default)]
+pub struct DialBackoff {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    config: BackoffConfig,
+    peers: HashMap<PeerId, Failures>,
+    addresses: HashMap<Multiaddr, Failures>,
+}
+
+impl DialBackoff {
+    /// Creates the back-off state with the given curve.
+    pub fn new(config: BackoffConfig) -> Self {
+        DialBackoff {
+            inner: Arc::new(Mutex::new(Inner { config, .. Inner::default() }))
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().expect("the lock is never poisoned")
+    }
+
+    /// Returns the curve of the back-off delays.
+    pub fn config(&self) -> BackoffConfig {
+        self.lock().config.clone()
+    }
+
+    /// Replaces the curve of the back-off delays. Delays already computed are not affected.
+    pub fn set_config(&self, config: BackoffConfig) {
+        self.lock().config = config;
+    }
+
+    /// Returns the moment until which the peer is backed off, if it currently is.
+    pub fn peer_backed_off_until(&self, peer_id: &PeerId) -> Option<Instant> {
+        backed_off_until(&self.lock().peers, peer_id)
+    }
+
+    /// Returns true if the peer must not be dialed for now.
+    pub fn is_peer_backed_off(&self, peer_id: &PeerId) -> bool {
+        self.peer_backed_off_until(peer_id).is_some()
+    }
+
+    /// Returns the moment until which the address is backed off, if it currently is.
+    pub fn address_backed_off_until(&self, address: &Multiaddr) -> Option<Instant> {
+        backed_off_until(&self.lock().addresses, address)
+    }
+
+    /// Returns true if the address must not be dialed for now.
+    pub fn is_address_backed_off(&self, address: &Multiaddr) -> bool {
+        self.address_backed_off_until(address).is_some()
+    }
+
+    /// Records a failed dial of a peer, once all its addresses have failed.
+    pub fn peer_failed(&self, peer_id: &PeerId) {
+        let mut inner = self.lock();
+        let Inner { config, peers, .. } = &mut *inner;
+        record_failure(config, peers, peer_id.clone());
+    }
+
+    /// Records a failed dial of an address.
+    pub fn address_failed(&self, address: &Multiaddr) {
+        let mut inner = self.lock();
+        let Inner { config, addresses, .. } = &mut *inner;
+        record_failure(config, addresses, address.clone());
+    }
+
+    /// Forgets the failures of a peer and of the address it has been reached at, if any.
+    pub fn reset(&self, peer_id: &PeerId, address: Option<&Multiaddr>) {
+        let mut inner = self.lock();
+        inner.peers.remove(peer_id);
+        if let Some(address) = address {
+            inner.addresses.remove(address);
+        }
+    }
+}
+
+/// Records a failure of `key`.
+///
+/// The failures are kept after the end of the back-off, so that the next delay keeps growing,
+/// but are forgotten once the maximum delay has elapsed since.
+fn record_failure<K: Eq + Hash>(config: &BackoffConfig, map: &mut HashMap<K, Failures>, key: K) {
+    let now = Instant::now();
+    map.retain(|_, f| f.until + config.max > now);
+    let failures = map.entry(key).or_insert(Failures { count: 0, until: now });
+    failures.count = failures.count.saturating_add(1);
+    failures.until = now + config.delay(failures.count);
+}
+
+/// Returns the end of the back-off of `key`, if it is still ongoing.
+fn backed_off_until<K: Eq + Hash>(map: &HashMap<K, Failures>, key: &K) -> Option<Instant> {
+    let until = map.get(key)?.until;
+    if until > Instant::now() {
+        Some(until)
+    } else {
+        None
+    }
+}
+
+impl fmt::Debug for DialBackoff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.lock();
+        f.debug_struct("DialBackoff")
+            .field("config", &inner.config)
+            .field("peers", &inner.peers.len())
+            .field("addresses", &inner.addresses.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_curve() {
+        let config = BackoffConfig::new()
+            .initial(Duration::from_secs(1))
+            .max(Duration::from_secs(5))
+            .multiplier(2.0)
+            .jitter(0.0);
+        let delays = (1 ..= 5).map(|n| config.delay(n).as_secs()).collect::<Vec<_>>();
+        assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+
+        let config = config.jitter(0.5);
+        for _ in 0 .. 100 {
+            let delay = config.delay(1);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_millis(1500));
+        }
+    }
+
+    #[test]
+    fn failures_back_off_until_success() {
+        let backoff = DialBackoff::new(BackoffConfig::new().jitter(0.0));
+        let peer = PeerId::random();
+        let addr = "/memory/1".parse().unwrap();
+        assert!(!backoff.is_peer_backed_off(&peer));
+
+        backoff.address_failed(&addr);
+        assert!(backoff.is_address_backed_off(&addr));
+        assert!(!backoff.is_peer_backed_off(&peer));
+
+        backoff.peer_failed(&peer);
+        backoff.peer_failed(&peer);
+        let until = backoff.peer_backed_off_until(&peer).unwrap();
+        assert!(until > Instant::now() + Duration::from_secs(9));
+
+        backoff.reset(&peer, Some(&addr));
+        assert!(!backoff.is_peer_backed_off(&peer));
+        assert!(!backoff.is_address_backed_off(&addr));
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Bans of peers and of IP subnets, with optional expirations.
+
+use libp2p_core::{Multiaddr, PeerId, address_filter::Subnet, multiaddr::Protocol};
+use std::{collections::HashMap, net::IpAddr};
+use wasm_timer::Instant;
+
+/// What a ban applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BanTarget {
+    /// A peer, whatever its address.
+    Peer(PeerId),
+    /// The IP addresses of a subnet, e.g. `10.0.0.0/8`, or a single IP address with a full
+    /// prefix.
+    Subnet(Subnet),
+}
+
+impl From<PeerId> for BanTarget {
+    fn from(peer_id: PeerId) -> Self {
+        BanTarget::Peer(peer_id)
+    }
+}
+
+impl From<Subnet> for BanTarget {
+    fn from(subnet: Subnet) -> Self {
+        BanTarget::Subnet(subnet)
+    }
+}
+
+impl From<IpAddr> for BanTarget {
+    fn from(ip: IpAddr) -> Self {
+        let prefix = if ip.is_ipv4() { 32 } else { 128 };
+        BanTarget::Subnet(Subnet::new(ip, prefix).expect("the prefix is the length of the address; QED"))
+    }
+}
+
+/// An active ban.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ban {
+    /// What the ban applies to.
+    pub target: BanTarget,
+    /// When the ban is lifted, if ever.
+    pub expires: Option<Instant>,
+}
+
+/// The set of bans of the swarm.
+#[derive(Debug, Default)]
+pub(crate) struct Bans {
+    bans: HashMap<BanTarget, Option<Instant>>,
+}
+
+impl Bans {
+    /// Adds a ban, replacing the expiration of an existing ban of the same target.
+    pub(crate) fn insert(&mut self, target: BanTarget, expires: Option<Instant>) {
+        self.remove_expired();
+        self.bans.insert(target, expires);
+    }
+
+    /// Removes a ban. Returns `true` if it was active.
+    pub(crate) fn remove(&mut self, target: &BanTarget) -> bool {
+        self.remove_expired();
+        self.bans.remove(target).is_some()
+    }
+
+    /// Returns the active bans.
+    pub(crate) fn list(&self) -> Vec<Ban> {
+        self.bans.keys()
+            .filter(|target| self.is_active(target))
+            .map(|target| Ban { target: target.clone(), expires: self.bans[target] })
+            .collect()
+    }
+
+    /// Returns `true` if the peer is banned.
+    pub(crate) fn is_peer_banned(&self, peer_id: &PeerId) -> bool {
+        self.is_active(&BanTarget::Peer(peer_id.clone()))
+    }
+
+    /// Returns `true` if any IP address of `addr` is in a banned subnet.
+    pub(crate) fn is_addr_banned(&self, addr: &Multiaddr) -> bool {
+        let now = Instant::now();
+        addr.iter()
+            .filter_map(|p| match p {
+                Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+                Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+                _ => None,
+            })
+            .any(|ip| self.bans.iter().any(|(target, expires)| match target {
+                BanTarget::Subnet(subnet) => subnet.contains(ip) && expires.map_or(true, |e| e > now),
+                BanTarget::Peer(_) => false,
+            }))
+    }
+
+    fn is_active(&self, target: &BanTarget) -> bool {
+        match self.bans.get(target) {
+            Some(Some(expires)) => *expires > Instant::now(),
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    fn remove_expired(&mut self) {
+        let now = Instant::now();
+        self.bans.retain(|_, expires| expires.map_or(true, |e| e > now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn subnet_bans_and_expiration() {
+        let mut bans = Bans::default();
+        let peer = PeerId::random();
+        bans.insert(BanTarget::Peer(peer.clone()), None);
+        bans.insert(BanTarget::Subnet("10.0.0.0/8".parse().unwrap()), Some(Instant::now() + Duration::from_secs(60)));
+        bans.insert("::1".parse::<IpAddr>().unwrap().into(), Some(Instant::now()));
+
+        assert!(bans.is_peer_banned(&peer));
+        assert!(bans.is_addr_banned(&"/ip4/10.1.2.3/tcp/1".parse().unwrap()));
+        assert!(!bans.is_addr_banned(&"/ip4/11.1.2.3/tcp/1".parse().unwrap()));
+        // The ban of `::1` has already expired.
+        assert!(!bans.is_addr_banned(&"/ip6/::1/tcp/1".parse().unwrap()));
+        assert_eq!(bans.list().len(), 2);
+
+        assert!(bans.remove(&BanTarget::Peer(peer.clone())));
+        assert!(!bans.is_peer_banned(&peer));
+        assert!(!bans.remove(&BanTarget::Peer(peer)));
+    }
+}
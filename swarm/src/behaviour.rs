@@ -18,8 +18,9 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use crate::ProtocolCapabilities;
 use crate::protocols_handler::{IntoProtocolsHandler, ProtocolsHandler};
-use libp2p_core::{ConnectedPoint, Multiaddr, PeerId, nodes::ListenerId};
+use libp2p_core::{AddressFilter, ConnectedPoint, Multiaddr, PeerId, nodes::ListenerId};
 use std::{error, task::Context, task::Poll};
 
 /// A behaviour for the network. Allows customizing the swarm.
@@ -43,6 +44,15 @@ use std::{error, task::Context, task::Poll};
 ///
 /// `#[behaviour(ignore)]` can be added on a struct field to disable generation of delegation to
 /// the fields which do not implement `NetworkBehaviour`.
+///
+/// With `#[behaviour(event_process = false)]`, the events generated by struct members are not
+/// delegated to [`NetworkBehaviourEventProcess`] but returned by `poll`. They are converted
+/// into the `out_event` with `From`, or, if no `out_event` is given, wrapped in a generated
+/// `<Name>Event` enum with one variant per struct member. Tagging a member with
+/// `#[behaviour(event = "TheEvent")]` additionally generates a `From<TheEvent>` implementation
+/// for that enum.
+///
+/// The derive supports generic structs as well as tuple structs.
 pub trait NetworkBehaviour {
     /// Handler for all the protocols the network behaviour supports.
     type ProtocolsHandler: IntoProtocolsHandler;
@@ -137,6 +147,11 @@ pub trait NetworkBehaviour {
     fn inject_new_external_addr(&mut self, _addr: &Multiaddr) {
     }
 
+    /// Indicates to the behaviour that an external address for us has expired, because
+    /// the listen addresses it was derived from have expired.
+    fn inject_expired_external_addr(&mut self, _addr: &Multiaddr) {
+    }
+
     /// A listener experienced an error.
     fn inject_listener_error(&mut self, _id: ListenerId, _err: &(dyn std::error::Error + 'static)) {
     }
@@ -145,6 +160,21 @@ pub trait NetworkBehaviour {
     fn inject_listener_closed(&mut self, _id: ListenerId) {
     }
 
+    /// Indicates to the behaviour the filter that addresses learned from the network must pass
+    /// before being dialed, stored or passed on to other peers.
+    ///
+    /// Called when the swarm is built and whenever its filter is replaced.
+    fn inject_address_filter(&mut self, _filter: &AddressFilter) {
+    }
+
+    /// Indicates to the behaviour that the swarm is shutting down.
+    ///
+    /// The swarm no longer accepts connections nor dials, and the existing connections are
+    /// closed once their in-flight substreams have finished or the grace period of the shutdown
+    /// has elapsed. See [`ExpandedSwarm::shutdown`](crate::ExpandedSwarm::shutdown).
+    fn inject_shutdown(&mut self) {
+    }
+
     /// Polls for things that swarm should do.
     ///
     /// This API mimics the API of the `Stream` trait. The method may register the current task in
@@ -178,6 +208,12 @@ pub trait PollParameters {
 
     /// Returns the peer id of the local node.
     fn local_peer_id(&self) -> &PeerId;
+
+    /// Returns the table of the protocols supported by the connected peers.
+    ///
+    /// Behaviours can use it to avoid opening substreams for protocols a peer doesn't support,
+    /// and feed it with the protocols peers advertise.
+    fn protocol_capabilities(&self) -> &ProtocolCapabilities;
 }
 
 /// When deriving [`NetworkBehaviour`] this trait must be implemented for all the possible event types
@@ -237,8 +273,66 @@ pub enum NetworkBehaviourAction<TInEvent, TOutEvent> {
     /// It is advisable to issue `ReportObservedAddr` actions at a fixed frequency
     /// per node. This way address information will be more accurate over time
     /// and individual outliers carry less weight.
+    ///
+    /// The IP address of the observed address is combined with all listen
+    /// addresses to form external addresses. If the observer is known, its port
+    /// is also used for the listen address of the same transport once other
+    /// remotes observed the same port, unless remotes disagree on the port as
+    /// happens behind a symmetric NAT.
+    ///
+    /// The resulting addresses are only candidates, advertised as external
+    /// addresses once enough distinct observers confirmed them.
     ReportObservedAddr {
         /// The observed address of the local node.
         address: Multiaddr,
+        /// The remote that observed the address, if known.
+        observer: Option<PeerId>,
+    },
+
+    /// Instructs the `Swarm` to close the connection with the given peer.
+    ///
+    /// [`NetworkBehaviour::inject_disconnected`] is invoked once the connection is closed. If
+    /// there is no connection to the peer, the action is ignored.
+    DisconnectPeer {
+        /// The peer to disconnect from.
+        peer_id: PeerId,
     },
 }
+
+impl<TInEvent, TOutEvent> NetworkBehaviourAction<TInEvent, TOutEvent> {
+    /// Maps the handler event of a `SendEvent`.
+    pub fn map_in<TNewIn>(self, f: impl FnOnce(TInEvent) -> TNewIn) -> NetworkBehaviourAction<TNewIn, TOutEvent> {
+        match self {
+            NetworkBehaviourAction::GenerateEvent(event) =>
+                NetworkBehaviourAction::GenerateEvent(event),
+            NetworkBehaviourAction::DialAddress { address } =>
+                NetworkBehaviourAction::DialAddress { address },
+            NetworkBehaviourAction::DialPeer { peer_id } =>
+                NetworkBehaviourAction::DialPeer { peer_id },
+            NetworkBehaviourAction::SendEvent { peer_id, event } =>
+                NetworkBehaviourAction::SendEvent { peer_id, event: f(event) },
+            NetworkBehaviourAction::ReportObservedAddr { address, observer } =>
+                NetworkBehaviourAction::ReportObservedAddr { address, observer },
+            NetworkBehaviourAction::DisconnectPeer { peer_id } =>
+                NetworkBehaviourAction::DisconnectPeer { peer_id },
+        }
+    }
+
+    /// Maps the event of a `GenerateEvent`.
+    pub fn map_out<TNewOut>(self, f: impl FnOnce(TOutEvent) -> TNewOut) -> NetworkBehaviourAction<TInEvent, TNewOut> {
+        match self {
+            NetworkBehaviourAction::GenerateEvent(event) =>
+                NetworkBehaviourAction::GenerateEvent(f(event)),
+            NetworkBehaviourAction::DialAddress { address } =>
+                NetworkBehaviourAction::DialAddress { address },
+            NetworkBehaviourAction::DialPeer { peer_id } =>
+                NetworkBehaviourAction::DialPeer { peer_id },
+            NetworkBehaviourAction::SendEvent { peer_id, event } =>
+                NetworkBehaviourAction::SendEvent { peer_id, event },
+            NetworkBehaviourAction::ReportObservedAddr { address, observer } =>
+                NetworkBehaviourAction::ReportObservedAddr { address, observer },
+            NetworkBehaviourAction::DisconnectPeer { peer_id } =>
+                NetworkBehaviourAction::DisconnectPeer { peer_id },
+        }
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Tracking of the protocols supported by the connected peers.
+//!
+//! The swarm keeps a [`ProtocolCapabilities`] table, which records for each connected peer the
+//! protocols it advertises, for example through the identify protocol, and the outcome of the
+//! negotiations of the substreams opened with it. Behaviours access the table through
+//! [`PollParameters::protocol_capabilities`](crate::PollParameters::protocol_capabilities).
+//!
+//! A negotiation success shows that the peer supports the protocol, and a failure to agree on
+//! any of the proposed protocols that it supports none of them. Outbound substream requests
+//! whose protocols are all known not to be supported by the peer fail right away, without
+//! opening a substream. Everything known about a peer is forgotten when it disconnects.
+
+use libp2p_core::{PeerId, upgrade::{InboundUpgrade, OutboundUpgrade, ProtocolName, UpgradeInfo}};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// Whether a peer supports a protocol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProtocolSupport {
+    /// The peer advertised the protocol or successfully negotiated it.
+    Supported,
+    /// The peer advertised protocols not including this one, or refused to negotiate it.
+    Unsupported,
+    /// Nothing is known about the protocol.
+    Unknown,
+}
+
+/// Shared table of the protocols supported by the connected peers.
+#[derive(Clone, Default)]
+pub struct ProtocolCapabilities {
+    inner: Arc<Mutex<HashMap<PeerId, PeerProtocols>>>,
+}
+
+/// What is known about the protocols of a single peer.
+#[derive(Debug, Default)]
+struct PeerProtocols {
+    /// The protocols advertised by the peer, if it advertised any.
+    advertised: Option<HashSet<Vec<u8>>>,
+    /// The protocols successfully negotiated with the peer.
+    confirmed: HashSet<Vec<u8>>,
+    /// The protocols the peer refused to negotiate.
+    refused: HashSet<Vec<u8>>,
+}
+
+impl ProtocolCapabilities {
+    /// Replaces the protocols advertised by a peer we are connected to.
+    ///
+    /// Observed negotiations take precedence over the advertised protocols.
+    pub fn set_advertised<I>(&self, peer_id: &PeerId, protocols: I)
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let protocols = protocols.into_iter().map(|p| p.as_ref().to_vec()).collect();
+        self.update(peer_id, |state| state.advertised = Some(protocols))
+    }
+
+    /// Records that a protocol has been successfully negotiated with a peer.
+    pub fn report_supported(&self, peer_id: &PeerId, protocol: &[u8]) {
+        self.update(peer_id, |state| {
+            state.refused.remove(protocol);
+            state.confirmed.insert(protocol.to_vec());
+        })
+    }
+
+    /// Records that a peer refused to negotiate a protocol.
+    pub fn report_unsupported(&self, peer_id: &PeerId, protocol: &[u8]) {
+        self.update(peer_id, |state| {
+            state.confirmed.remove(protocol);
+            state.refused.insert(protocol.to_vec());
+        })
+    }
+
+    /// Returns whether a peer supports a protocol.
+    pub fn support(&self, peer_id: &PeerId, protocol: &[u8]) -> ProtocolSupport {
+        let inner = self.inner.lock().expect("the lock is never poisoned");
+        let state = match inner.get(peer_id) {
+            Some(state) => state,
+            None => return ProtocolSupport::Unknown,
+        };
+        if state.refused.contains(protocol) {
+            ProtocolSupport::Unsupported
+        } else if state.confirmed.contains(protocol) {
+            ProtocolSupport::Supported
+        } else {
+            match &state.advertised {
+                Some(advertised) if advertised.contains(protocol) => ProtocolSupport::Supported,
+                Some(_) => ProtocolSupport::Unsupported,
+                None => ProtocolSupport::Unknown,
+            }
+        }
+    }
+
+    /// Returns true if a peer is known not to support a protocol.
+    pub fn is_unsupported(&self, peer_id: &PeerId, protocol: &[u8]) -> bool {
+        self.support(peer_id, protocol) == ProtocolSupport::Unsupported
+    }
+
+    /// Returns the protocols a peer is known to support.
+    pub fn supported_protocols(&self, peer_id: &PeerId) -> Vec<Vec<u8>> {
+        let inner = self.inner.lock().expect("the lock is never poisoned");
+        let state = match inner.get(peer_id) {
+            Some(state) => state,
+            None => return Vec::new(),
+        };
+        state.advertised.iter().flatten()
+            .chain(state.confirmed.iter())
+            .filter(|p| !state.refused.contains(*p))
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Starts tracking the protocols of a newly-connected peer.
+    pub(crate) fn connected(&self, peer_id: &PeerId) {
+        let mut inner = self.inner.lock().expect("the lock is never poisoned");
+        inner.insert(peer_id.clone(), PeerProtocols::default());
+    }
+
+    /// Forgets about the protocols of a peer.
+    pub(crate) fn disconnected(&self, peer_id: &PeerId) {
+        let mut inner = self.inner.lock().expect("the lock is never poisoned");
+        inner.remove(peer_id);
+    }
+
+    /// Applies `f` to the protocols of a peer, if we are connected to it.
+    fn update(&self, peer_id: &PeerId, f: impl FnOnce(&mut PeerProtocols)) {
+        let mut inner = self.inner.lock().expect("the lock is never poisoned");
+        if let Some(state) = inner.get_mut(peer_id) {
+            f(state)
+        }
+    }
+}
+
+impl fmt::Debug for ProtocolCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.inner.lock().expect("the lock is never poisoned");
+        f.debug_struct("ProtocolCapabilities").field("peers", &inner.len()).finish()
+    }
+}
+
+/// Upgrade that records the protocol negotiated by the wrapped upgrade as supported by the peer.
+pub(crate) struct TrackedUpgrade<TUpgrade> {
+    inner: TUpgrade,
+    tracker: Option<(ProtocolCapabilities, PeerId)>,
+}
+
+impl<TUpgrade> TrackedUpgrade<TUpgrade> {
+    pub(crate) fn new(inner: TUpgrade, tracker: Option<(ProtocolCapabilities, PeerId)>) -> Self {
+        TrackedUpgrade { inner, tracker }
+    }
+
+    fn report(&self, protocol: &[u8]) {
+        if let Some((capabilities, peer_id)) = &self.tracker {
+            capabilities.report_supported(peer_id, protocol)
+        }
+    }
+}
+
+impl<TUpgrade> UpgradeInfo for TrackedUpgrade<TUpgrade>
+where
+    TUpgrade: UpgradeInfo,
+{
+    type Info = TUpgrade::Info;
+    type InfoIter = TUpgrade::InfoIter;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.inner.protocol_info()
+    }
+}
+
+impl<TUpgrade, C> InboundUpgrade<C> for TrackedUpgrade<TUpgrade>
+where
+    TUpgrade: InboundUpgrade<C>,
+{
+    type Output = TUpgrade::Output;
+    type Error = TUpgrade::Error;
+    type Future = TUpgrade::Future;
+
+    fn upgrade_inbound(self, socket: C, info: Self::Info) -> Self::Future {
+        self.report(info.protocol_name());
+        self.inner.upgrade_inbound(socket, info)
+    }
+}
+
+impl<TUpgrade, C> OutboundUpgrade<C> for TrackedUpgrade<TUpgrade>
+where
+    TUpgrade: OutboundUpgrade<C>,
+{
+    type Output = TUpgrade::Output;
+    type Error = TUpgrade::Error;
+    type Future = TUpgrade::Future;
+
+    fn upgrade_outbound(self, socket: C, info: Self::Info) -> Self::Future {
+        self.report(info.protocol_name());
+        self.inner.upgrade_outbound(socket, info)
+    }
+}
+
+/// Returns the names of the protocols of an upgrade.
+pub(crate) fn protocol_names(upgrade: &impl UpgradeInfo) -> Vec<Vec<u8>> {
+    upgrade.protocol_info().into_iter().map(|i| i.protocol_name().to_vec()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProtocolCapabilities, ProtocolSupport};
+    use libp2p_core::PeerId;
+
+    #[test]
+    fn observations_override_advertisements() {
+        let capabilities = ProtocolCapabilities::default();
+        let peer = PeerId::random();
+        capabilities.set_advertised(&peer, &["/a"]);
+        assert_eq!(capabilities.support(&peer, b"/a"), ProtocolSupport::Unknown);
+
+        capabilities.connected(&peer);
+        assert_eq!(capabilities.support(&peer, b"/b"), ProtocolSupport::Unknown);
+        capabilities.set_advertised(&peer, &["/a"]);
+        assert_eq!(capabilities.support(&peer, b"/a"), ProtocolSupport::Supported);
+        assert!(capabilities.is_unsupported(&peer, b"/b"));
+
+        capabilities.report_supported(&peer, b"/b");
+        capabilities.report_unsupported(&peer, b"/a");
+        assert_eq!(capabilities.support(&peer, b"/b"), ProtocolSupport::Supported);
+        assert!(capabilities.is_unsupported(&peer, b"/a"));
+        assert_eq!(capabilities.supported_protocols(&peer), vec![b"/b".to_vec()]);
+
+        capabilities.disconnected(&peer);
+        assert_eq!(capabilities.support(&peer, b"/b"), ProtocolSupport::Unknown);
+    }
+}
@@ -0,0 +1,488 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Connection manager that keeps the number of connections within bounds.
+//!
+//! The [`ConnectionManager`] is a `NetworkBehaviour` that tracks the connected peers. Once the
+//! number of connections exceeds a *high watermark*, the least valuable connections are closed
+//! until only *low watermark* connections remain.
+//!
+//! The value of a connection is determined by the tags that other protocols attach to the peer
+//! through the [`PeerTags`] handle obtained with [`ConnectionManager::tags`]. Each tag carries a
+//! weight, and the score of a peer is the sum of the weights of its tags. Peers can also be
+//! protected, in which case they are never pruned. Newly-established connections are spared
+//! during a grace period, giving protocols the time to tag them.
+//!
+//! The manager can also be given the [`ConnectionTags`] of the swarm, in which case the labels
+//! of the connections count as well: each label can be given a weight added to the score of the
+//! peer, or protect the connections carrying it.
+
+use crate::{ConnectionTags, NetworkBehaviour, NetworkBehaviourAction, PollParameters};
+use crate::protocols_handler::{DummyProtocolsHandler, ProtocolsHandler};
+use futures::prelude::*;
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use void::Void;
+use wasm_timer::{Delay, Instant};
+
+/// Default duration during which a new connection can't be pruned.
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Network behaviour that prunes the least valuable connections once there are too many.
+pub struct ConnectionManager<TSubstream> {
+    /// Number of connections to trim down to.
+    low_watermark: usize,
+    /// Number of connections above which connections are trimmed.
+    high_watermark: usize,
+    /// Duration after a connection has been established during which it can't be pruned.
+    grace_period: Duration,
+    /// Tags attached to the peers.
+    tags: PeerTags,
+    /// Labels of the connections, if the manager takes them into account.
+    connection_tags: Option<ConnectionTags>,
+    /// Weights of the labels of the connections.
+    label_weights: HashMap<String, i32>,
+    /// Labels protecting the connections carrying them from being pruned.
+    protected_labels: HashSet<String>,
+    /// Connected peers, with the moment the connection has been established.
+    connected: HashMap<PeerId, Instant>,
+    /// Peers we are about to disconnect from.
+    to_disconnect: VecDeque<PeerId>,
+    /// If true, the connections must be checked against the watermarks on the next poll.
+    check_pending: bool,
+    /// Fires when the grace period of a connection that could be pruned expires.
+    grace_timer: Option<Delay>,
+    marker: PhantomData<TSubstream>,
+}
+
+impl<TSubstream> ConnectionManager<TSubstream> {
+    /// Creates a `ConnectionManager` that closes connections once there are more than
+    /// `high_watermark` of them, until `low_watermark` connections remain.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `low_watermark` is greater than `high_watermark`.
+    pub fn new(low_watermark: usize, high_watermark: usize) -> Self {
+        assert!(low_watermark <= high_watermark, "the low watermark must not exceed the high watermark");
+        ConnectionManager {
+            low_watermark,
+            high_watermark,
+            grace_period: DEFAULT_GRACE_PERIOD,
+            tags: PeerTags::default(),
+            connection_tags: None,
+            label_weights: HashMap::new(),
+            protected_labels: HashSet::new(),
+            connected: HashMap::new(),
+            to_disconnect: VecDeque::new(),
+            check_pending: false,
+            grace_timer: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Sets the duration after a connection has been established during which it can't be
+    /// pruned. Defaults to one minute.
+    pub fn grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Takes the labels of the connections into account, as attached through the given handle,
+    /// which should be the one of the swarm.
+    pub fn connection_tags(mut self, tags: ConnectionTags) -> Self {
+        self.connection_tags = Some(tags);
+        self
+    }
+
+    /// Adds `weight` to the score of the peers whose connection carries `label`.
+    ///
+    /// Only used if the manager has been given the [`ConnectionTags`] of the swarm.
+    pub fn label_weight(mut self, label: impl Into<String>, weight: i32) -> Self {
+        self.label_weights.insert(label.into(), weight);
+        self
+    }
+
+    /// Never prunes the connections carrying `label`.
+    ///
+    /// Only used if the manager has been given the [`ConnectionTags`] of the swarm.
+    pub fn protect_label(mut self, label: impl Into<String>) -> Self {
+        self.protected_labels.insert(label.into());
+        self
+    }
+
+    /// Returns a handle to tag and protect peers. The handle can be cloned and shared with the
+    /// other protocols.
+    pub fn tags(&self) -> PeerTags {
+        self.tags.clone()
+    }
+
+    /// Returns the number of connected peers.
+    pub fn num_connected(&self) -> usize {
+        self.connected.len()
+    }
+
+    /// Chooses the connections to close if the high watermark has been exceeded.
+    fn trim(&mut self) {
+        let remaining = self.connected.len() - self.to_disconnect.len();
+        if remaining <= self.high_watermark {
+            return
+        }
+
+        let now = Instant::now();
+        let tags = self.tags.inner.lock().expect("the lock is never poisoned");
+        let mut candidates = Vec::new();
+        let mut next_expiry = None::<Instant>;
+        for (peer_id, established) in &self.connected {
+            if self.to_disconnect.contains(peer_id) {
+                continue
+            }
+            let peer_tags = tags.get(peer_id);
+            if peer_tags.map_or(false, |t| !t.protections.is_empty()) {
+                continue
+            }
+            let labels = self.connection_tags.as_ref().map_or_else(Vec::new, |t| t.labels(peer_id));
+            if labels.iter().any(|l| self.protected_labels.contains(l)) {
+                continue
+            }
+            let expiry = *established + self.grace_period;
+            if expiry > now {
+                next_expiry = Some(next_expiry.map_or(expiry, |e| e.min(expiry)));
+                continue
+            }
+            let score = labels.iter()
+                .filter_map(|l| self.label_weights.get(l))
+                .fold(peer_tags.map_or(0, PeerState::score), |acc, w| acc.saturating_add(*w));
+            candidates.push((score, *established, peer_id));
+        }
+
+        // Lowest scores first; among equal scores, the most recent connections go first.
+        candidates.sort_by_key(|&(score, established, _)| (score, Reverse(established)));
+        let excess = remaining - self.low_watermark;
+        let pruned = candidates.len().min(excess);
+        log::debug!("Connection manager: {} connections, pruning {}", remaining, pruned);
+        self.to_disconnect.extend(candidates.into_iter().take(excess).map(|(_, _, p)| p.clone()));
+
+        // Some connections couldn't be pruned yet, retry when they leave their grace period.
+        self.grace_timer = if pruned < excess {
+            next_expiry.map(Delay::new_at)
+        } else {
+            None
+        };
+    }
+}
+
+impl<TSubstream> fmt::Debug for ConnectionManager<TSubstream> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectionManager")
+            .field("low_watermark", &self.low_watermark)
+            .field("high_watermark", &self.high_watermark)
+            .field("grace_period", &self.grace_period)
+            .field("connected", &self.connected.len())
+            .finish()
+    }
+}
+
+impl<TSubstream> NetworkBehaviour for ConnectionManager<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite + Unpin,
+{
+    type ProtocolsHandler = DummyProtocolsHandler<TSubstream>;
+    type OutEvent = Void;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        DummyProtocolsHandler::default()
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, peer_id: PeerId, _: ConnectedPoint) {
+        self.connected.insert(peer_id, Instant::now());
+        self.check_pending = true;
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId, _: ConnectedPoint) {
+        self.connected.remove(peer_id);
+        self.to_disconnect.retain(|p| p != peer_id);
+        self.tags.clear_weights(peer_id);
+    }
+
+    fn inject_node_event(&mut self, _: PeerId, event: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent) {
+        void::unreachable(event)
+    }
+
+    fn poll(&mut self, cx: &mut Context, _: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<<Self::ProtocolsHandler as ProtocolsHandler>::InEvent, Self::OutEvent>>
+    {
+        if let Some(timer) = self.grace_timer.as_mut() {
+            // An error of the timer only means that we check the connections earlier.
+            if Pin::new(timer).poll(cx).is_ready() {
+                self.grace_timer = None;
+                self.check_pending = true;
+            }
+        }
+
+        if self.check_pending {
+            self.check_pending = false;
+            self.trim();
+        }
+
+        if let Some(peer_id) = self.to_disconnect.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::DisconnectPeer { peer_id })
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Shared handle to attach tags and protections to peers.
+///
+/// Weighted tags are forgotten when the peer disconnects, while protections remain until they
+/// are removed with [`PeerTags::unprotect`].
+#[derive(Clone, Default)]
+pub struct PeerTags {
+    inner: Arc<Mutex<HashMap<PeerId, PeerState>>>,
+}
+
+/// Tags and protections of a single peer.
+#[derive(Debug, Default)]
+struct PeerState {
+    weights: HashMap<String, i32>,
+    protections: HashSet<String>,
+}
+
+impl PeerState {
+    fn score(&self) -> i32 {
+        self.weights.values().fold(0, |acc, w| acc.saturating_add(*w))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.weights.is_empty() && self.protections.is_empty()
+    }
+}
+
+impl PeerTags {
+    /// Attaches a tag with the given weight to a peer, replacing the previous weight of the
+    /// same tag.
+    pub fn tag(&self, peer_id: &PeerId, tag: impl Into<String>, weight: i32) {
+        let mut inner = self.inner.lock().expect("the lock is never poisoned");
+        inner.entry(peer_id.clone()).or_default().weights.insert(tag.into(), weight);
+    }
+
+    /// Removes a tag from a peer.
+    pub fn untag(&self, peer_id: &PeerId, tag: &str) {
+        self.update(peer_id, |state| { state.weights.remove(tag); })
+    }
+
+    /// Protects a peer from being pruned. A peer stays protected as long as at least one tag
+    /// protects it.
+    pub fn protect(&self, peer_id: &PeerId, tag: impl Into<String>) {
+        let mut inner = self.inner.lock().expect("the lock is never poisoned");
+        inner.entry(peer_id.clone()).or_default().protections.insert(tag.into());
+    }
+
+    /// Removes a protection from a peer. Returns `true` if the peer is still protected by
+    /// another tag.
+    pub fn unprotect(&self, peer_id: &PeerId, tag: &str) -> bool {
+        self.update(peer_id, |state| { state.protections.remove(tag); });
+        self.is_protected(peer_id)
+    }
+
+    /// Returns true if the peer is protected from being pruned.
+    pub fn is_protected(&self, peer_id: &PeerId) -> bool {
+        let inner = self.inner.lock().expect("the lock is never poisoned");
+        inner.get(peer_id).map_or(false, |s| !s.protections.is_empty())
+    }
+
+    /// Returns the score of a peer, which is the sum of the weights of its tags.
+    pub fn score(&self, peer_id: &PeerId) -> i32 {
+        let inner = self.inner.lock().expect("the lock is never poisoned");
+        inner.get(peer_id).map_or(0, PeerState::score)
+    }
+
+    fn clear_weights(&self, peer_id: &PeerId) {
+        self.update(peer_id, |state| state.weights.clear())
+    }
+
+    /// Applies `f` to the state of a peer, and forgets about the peer if nothing remains.
+    fn update(&self, peer_id: &PeerId, f: impl FnOnce(&mut PeerState)) {
+        let mut inner = self.inner.lock().expect("the lock is never poisoned");
+        if let Some(state) = inner.get_mut(peer_id) {
+            f(state);
+            if state.is_empty() {
+                inner.remove(peer_id);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for PeerTags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.inner.lock().expect("the lock is never poisoned");
+        f.debug_struct("PeerTags").field("peers", &inner.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PollParameters, ProtocolCapabilities};
+    use libp2p_core::{PeerId, transport::dummy::DummyStream};
+    use std::{thread, vec};
+
+    struct DummyParameters(PeerId, ProtocolCapabilities);
+
+    impl PollParameters for DummyParameters {
+        type SupportedProtocolsIter = vec::IntoIter<Vec<u8>>;
+        type ListenedAddressesIter = vec::IntoIter<Multiaddr>;
+        type ExternalAddressesIter = vec::IntoIter<Multiaddr>;
+
+        fn supported_protocols(&self) -> Self::SupportedProtocolsIter {
+            Vec::new().into_iter()
+        }
+
+        fn listened_addresses(&self) -> Self::ListenedAddressesIter {
+            Vec::new().into_iter()
+        }
+
+        fn external_addresses(&self) -> Self::ExternalAddressesIter {
+            Vec::new().into_iter()
+        }
+
+        fn local_peer_id(&self) -> &PeerId {
+            &self.0
+        }
+
+        fn protocol_capabilities(&self) -> &ProtocolCapabilities {
+            &self.1
+        }
+    }
+
+    fn endpoint() -> ConnectedPoint {
+        ConnectedPoint::Dialer { address: "/memory/1".parse().unwrap() }
+    }
+
+    /// Polls the manager until it is pending, simulating the disconnections it requests.
+    fn disconnected(manager: &mut ConnectionManager<DummyStream>) -> Vec<PeerId> {
+        let mut params = DummyParameters(PeerId::random(), ProtocolCapabilities::default());
+        let mut disconnected = Vec::new();
+        futures::executor::block_on(future::poll_fn(|cx| {
+            loop {
+                match manager.poll(cx, &mut params) {
+                    Poll::Ready(NetworkBehaviourAction::DisconnectPeer { peer_id }) => {
+                        manager.inject_disconnected(&peer_id, endpoint());
+                        disconnected.push(peer_id);
+                    }
+                    Poll::Ready(_) => panic!("unexpected action"),
+                    Poll::Pending => return Poll::Ready(()),
+                }
+            }
+        }));
+        disconnected
+    }
+
+    #[test]
+    fn prunes_lowest_scores_down_to_low_watermark() {
+        let mut manager = ConnectionManager::new(2, 3).grace_period(Duration::from_secs(0));
+        let tags = manager.tags();
+        let peers = (0 .. 4).map(|_| PeerId::random()).collect::<Vec<_>>();
+        tags.tag(&peers[0], "useful", 10);
+        tags.tag(&peers[1], "useless", -5);
+        tags.protect(&peers[2], "keep");
+        tags.tag(&peers[3], "useful", 5);
+
+        for peer in &peers[.. 3] {
+            manager.inject_connected(peer.clone(), endpoint());
+        }
+        assert!(disconnected(&mut manager).is_empty());
+
+        manager.inject_connected(peers[3].clone(), endpoint());
+        assert_eq!(disconnected(&mut manager), vec![peers[1].clone(), peers[3].clone()]);
+        assert_eq!(manager.num_connected(), 2);
+        assert_eq!(tags.score(&peers[1]), 0);
+        assert!(tags.is_protected(&peers[2]));
+    }
+
+    #[test]
+    fn connection_labels_count_towards_scores() {
+        let connection_tags = ConnectionTags::default();
+        let mut manager = ConnectionManager::new(2, 2)
+            .grace_period(Duration::from_secs(0))
+            .connection_tags(connection_tags.clone())
+            .label_weight("relay", -10)
+            .label_weight("validator", 5)
+            .protect_label("bootstrap");
+        let peers = (0 .. 3).map(|_| PeerId::random()).collect::<Vec<_>>();
+        for (peer, label) in peers.iter().zip(&["bootstrap", "relay", "validator"]) {
+            connection_tags.connected(peer);
+            connection_tags.add_label(peer, *label);
+        }
+        // Without its label, the peer would have the lowest score.
+        manager.tags().tag(&peers[0], "useless", -20);
+
+        for peer in &peers {
+            manager.inject_connected(peer.clone(), endpoint());
+        }
+        assert_eq!(disconnected(&mut manager), vec![peers[1].clone()]);
+    }
+
+    #[test]
+    fn grace_period_spares_new_connections() {
+        let grace = Duration::from_millis(100);
+        let mut manager = ConnectionManager::new(0, 1).grace_period(grace);
+        let peers = (0 .. 2).map(|_| PeerId::random()).collect::<Vec<_>>();
+        for peer in &peers {
+            manager.inject_connected(peer.clone(), endpoint());
+        }
+        assert!(disconnected(&mut manager).is_empty());
+
+        thread::sleep(grace);
+        let pruned = futures::executor::block_on(future::poll_fn(|cx| {
+            let mut params = DummyParameters(PeerId::random(), ProtocolCapabilities::default());
+            match manager.poll(cx, &mut params) {
+                Poll::Ready(NetworkBehaviourAction::DisconnectPeer { peer_id }) => Poll::Ready(peer_id),
+                Poll::Ready(_) => panic!("unexpected action"),
+                Poll::Pending => Poll::Pending,
+            }
+        }));
+        assert!(peers.contains(&pruned));
+    }
+
+    #[test]
+    fn unprotect_reports_remaining_protections() {
+        let tags = PeerTags::default();
+        let peer = PeerId::random();
+        tags.protect(&peer, "a");
+        tags.protect(&peer, "b");
+        assert!(tags.unprotect(&peer, "a"));
+        assert!(!tags.unprotect(&peer, "b"));
+        assert!(tags.inner.lock().unwrap().is_empty());
+    }
+}
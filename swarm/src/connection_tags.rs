@@ -0,0 +1,250 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Labels and data attached to the established connections.
+//!
+//! The swarm keeps a [`ConnectionTags`] handle, obtained with
+//! [`ExpandedSwarm::connection_tags`](crate::ExpandedSwarm::connection_tags). Behaviours and the
+//! application can attach labels, such as `"relay"` or `"bootstrap"`, and values of any type to
+//! the connections of the peers through clones of this handle. Everything attached to a
+//! connection is forgotten once it is closed or replaced.
+//!
+//! The [`ConnectionGater`](crate::ConnectionGater) of the swarm receives the handle, and the
+//! [`ConnectionManager`](crate::connection_manager::ConnectionManager) can take labels into
+//! account when choosing the connections to prune.
+
+use libp2p_core::PeerId;
+use std::{
+    any::{Any, TypeId},
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, atomic::{AtomicU64, Ordering}},
+};
+
+/// Identifier of the next tracked connection.
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(0);
+
+/// Shared handle to the labels and data attached to the established connections.
+///
+/// Labels and data can only be attached to peers we are connected to.
+#[derive(Clone, Default)]
+pub struct ConnectionTags {
+    inner: Arc<Mutex<HashMap<PeerId, ConnectionState>>>,
+}
+
+/// Labels and data of a single connection.
+#[derive(Default)]
+struct ConnectionState {
+    /// Tells the connection apart from the other connections of the peer.
+    connection: u64,
+    labels: HashSet<String>,
+    data: HashMap<TypeId, Box<dyn Any + Send>>,
+}
+
+impl ConnectionTags {
+    /// Attaches a label to the connection of a peer. Returns `false` if we are not connected to
+    /// the peer.
+    pub fn add_label(&self, peer_id: &PeerId, label: impl Into<String>) -> bool {
+        self.update(peer_id, |state| { state.labels.insert(label.into()); }).is_some()
+    }
+
+    /// Removes a label from the connection of a peer. Returns `true` if the label was attached.
+    pub fn remove_label(&self, peer_id: &PeerId, label: &str) -> bool {
+        self.update(peer_id, |state| state.labels.remove(label)).unwrap_or(false)
+    }
+
+    /// Returns true if the label is attached to the connection of the peer.
+    pub fn has_label(&self, peer_id: &PeerId, label: &str) -> bool {
+        let inner = self.lock();
+        inner.get(peer_id).map_or(false, |s| s.labels.contains(label))
+    }
+
+    /// Returns the labels attached to the connection of a peer.
+    pub fn labels(&self, peer_id: &PeerId) -> Vec<String> {
+        let inner = self.lock();
+        inner.get(peer_id).map_or_else(Vec::new, |s| s.labels.iter().cloned().collect())
+    }
+
+    /// Returns the peers whose connection carries the label.
+    pub fn peers_with_label(&self, label: &str) -> Vec<PeerId> {
+        let inner = self.lock();
+        inner.iter()
+            .filter(|(_, s)| s.labels.contains(label))
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
+
+    /// Returns the number of connections carrying the label.
+    pub fn count_label(&self, label: &str) -> usize {
+        let inner = self.lock();
+        inner.values().filter(|s| s.labels.contains(label)).count()
+    }
+
+    /// Attaches a value to the connection of a peer, replacing the previous value of the same
+    /// type. Returns the value back if we are not connected to the peer.
+    pub fn insert<T: Any + Send>(&self, peer_id: &PeerId, value: T) -> Result<(), T> {
+        let mut inner = self.lock();
+        match inner.get_mut(peer_id) {
+            Some(state) => {
+                state.data.insert(TypeId::of::<T>(), Box::new(value));
+                Ok(())
+            },
+            None => Err(value),
+        }
+    }
+
+    /// Returns a copy of the value of type `T` attached to the connection of a peer.
+    pub fn get<T: Any + Send + Clone>(&self, peer_id: &PeerId) -> Option<T> {
+        self.with(peer_id, |value: &mut T| value.clone())
+    }
+
+    /// Applies `f` to the value of type `T` attached to the connection of a peer, if any.
+    ///
+    /// The value is detached from the connection while `f` runs, such that `f` can use the
+    /// handle, and attached again afterwards, unless the connection has been closed or replaced
+    /// or another value of type `T` has been attached in the meantime.
+    pub fn with<T: Any + Send, R>(&self, peer_id: &PeerId, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let (connection, mut value) = {
+            let mut inner = self.lock();
+            let state = inner.get_mut(peer_id)?;
+            let value = state.data.remove(&TypeId::of::<T>())?;
+            (state.connection, value)
+        };
+        let result = f(value.downcast_mut().expect("values are stored under their own type id; QED"));
+        let mut inner = self.lock();
+        if let Some(state) = inner.get_mut(peer_id).filter(|s| s.connection == connection) {
+            state.data.entry(TypeId::of::<T>()).or_insert(value);
+        }
+        Some(result)
+    }
+
+    /// Removes the value of type `T` attached to the connection of a peer.
+    pub fn remove<T: Any + Send>(&self, peer_id: &PeerId) -> Option<T> {
+        let mut inner = self.lock();
+        let value = inner.get_mut(peer_id)?.data.remove(&TypeId::of::<T>())?;
+        Some(*value.downcast().expect("values are stored under their own type id; QED"))
+    }
+
+    /// Returns true if we are connected to the peer.
+    pub fn is_connected(&self, peer_id: &PeerId) -> bool {
+        let inner = self.lock();
+        inner.contains_key(peer_id)
+    }
+
+    /// Starts tracking a new connection, forgetting what was attached to the previous
+    /// connection of the peer.
+    pub(crate) fn connected(&self, peer_id: &PeerId) {
+        let connection = NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed);
+        let mut inner = self.lock();
+        inner.insert(peer_id.clone(), ConnectionState { connection, ..ConnectionState::default() });
+    }
+
+    /// Forgets about the connection of a peer.
+    pub(crate) fn disconnected(&self, peer_id: &PeerId) {
+        let mut inner = self.lock();
+        inner.remove(peer_id);
+    }
+
+    /// Applies `f` to the state of the connection of a peer, if we are connected to it.
+    fn update<R>(&self, peer_id: &PeerId, f: impl FnOnce(&mut ConnectionState) -> R) -> Option<R> {
+        let mut inner = self.lock();
+        inner.get_mut(peer_id).map(f)
+    }
+
+    /// Locks the state of the connections.
+    ///
+    /// The state stays consistent if a thread panics while holding the lock, so a poisoned lock
+    /// is recovered from.
+    fn lock(&self) -> MutexGuard<'_, HashMap<PeerId, ConnectionState>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for ConnectionTags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.lock();
+        f.debug_struct("ConnectionTags").field("connections", &inner.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConnectionTags;
+    use libp2p_core::PeerId;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Role(&'static str);
+
+    #[test]
+    fn only_connected_peers_are_tagged() {
+        let tags = ConnectionTags::default();
+        let peer = PeerId::random();
+        assert!(!tags.add_label(&peer, "relay"));
+        assert_eq!(tags.insert(&peer, Role("validator")), Err(Role("validator")));
+
+        tags.connected(&peer);
+        assert!(tags.add_label(&peer, "relay"));
+        assert!(tags.has_label(&peer, "relay"));
+        assert_eq!(tags.insert(&peer, Role("validator")), Ok(()));
+        assert_eq!(tags.peers_with_label("relay"), vec![peer.clone()]);
+        assert_eq!(tags.count_label("bootstrap"), 0);
+
+        tags.disconnected(&peer);
+        assert!(!tags.has_label(&peer, "relay"));
+        assert_eq!(tags.get::<Role>(&peer), None);
+    }
+
+    #[test]
+    fn typed_values() {
+        let tags = ConnectionTags::default();
+        let peer = PeerId::random();
+        tags.connected(&peer);
+        tags.insert(&peer, Role("validator")).unwrap();
+        tags.insert(&peer, 5u32).unwrap();
+        assert_eq!(tags.get::<Role>(&peer), Some(Role("validator")));
+        assert_eq!(tags.with(&peer, |n: &mut u32| { *n += 1; *n }), Some(6));
+        assert_eq!(tags.remove::<u32>(&peer), Some(6));
+        assert_eq!(tags.get::<u32>(&peer), None);
+
+        // A new connection starts without labels nor values.
+        tags.add_label(&peer, "relay");
+        tags.connected(&peer);
+        assert!(tags.labels(&peer).is_empty());
+        assert_eq!(tags.get::<Role>(&peer), None);
+    }
+
+    #[test]
+    fn with_can_use_the_tags() {
+        let tags = ConnectionTags::default();
+        let peer = PeerId::random();
+        tags.connected(&peer);
+        tags.insert(&peer, 5u32).unwrap();
+        let labelled = tags.with(&peer, |n: &mut u32| {
+            *n += 1;
+            tags.add_label(&peer, "relay")
+        });
+        assert_eq!(labelled, Some(true));
+        assert_eq!(tags.get::<u32>(&peer), Some(6));
+
+        // The value is not attached to a connection that replaced the original one.
+        assert_eq!(tags.with(&peer, |_: &mut u32| tags.connected(&peer)), Some(()));
+        assert_eq!(tags.get::<u32>(&peer), None);
+    }
+}
@@ -0,0 +1,273 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Structured reasons of the failures of dials.
+//!
+//! Each failed attempt to reach an address is reported with an [`AddressFailure`], telling
+//! whether the address couldn't be reached by the transport, whether the security or muxer
+//! handshake failed, or whether we refused the connection ourselves. [`AddressFailure::kind`]
+//! sums this up as a [`FailureKind`], which distinguishes an unreachable peer from a refused
+//! connection and from a failed handshake.
+//!
+//! The swarm can't tell on its own at which stage an error of the transport happened. It asks
+//! the classifier set with
+//! [`SwarmBuilder::dial_error_classifier`](crate::SwarmBuilder::dial_error_classifier), for
+//! example [`StagedError::stage`] for the transports upgraded with the `upgrade` builder of
+//! `libp2p-core`. Without classifier, all the errors of the transport are reported as
+//! [`AddressFailure::Transport`].
+
+use libp2p_core::{
+    PeerId,
+    either::EitherError,
+    nodes::{ConnectionInfo, network::{ConnectionLimit, NetworkReachError, UnknownPeerDialErr}},
+    transport::TransportError,
+    upgrade::UpgradeError,
+};
+use std::{error, fmt, io};
+
+/// Stage of the establishment of a connection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConnectionStage {
+    /// Reaching the remote with the underlying transport.
+    Transport,
+    /// Negotiating the security protocol and performing its handshake.
+    Security,
+    /// Negotiating the stream multiplexer.
+    Muxer,
+}
+
+/// Errors of a transport which know at which stage of the establishment of the connection they
+/// happened.
+pub trait StagedError {
+    /// Returns the stage the error happened at.
+    fn stage(&self) -> ConnectionStage;
+}
+
+impl StagedError for io::Error {
+    fn stage(&self) -> ConnectionStage {
+        ConnectionStage::Transport
+    }
+}
+
+/// Errors of a transport upgraded with `authenticate` then `multiplex`.
+impl<TErr, TSecErr, TMuxErr> StagedError
+    for EitherError<EitherError<TErr, UpgradeError<TSecErr>>, UpgradeError<TMuxErr>>
+{
+    fn stage(&self) -> ConnectionStage {
+        match self {
+            EitherError::A(EitherError::A(_)) => ConnectionStage::Transport,
+            EitherError::A(EitherError::B(_)) => ConnectionStage::Security,
+            EitherError::B(_) => ConnectionStage::Muxer,
+        }
+    }
+}
+
+/// Classifies the errors of a transport by the stage they happened at.
+pub(crate) type Classifier<TErr> = fn(&TErr) -> ConnectionStage;
+
+/// Default [`Classifier`], which reports all the errors as errors of the transport.
+pub(crate) fn unclassified<TErr>(_: &TErr) -> ConnectionStage {
+    ConnectionStage::Transport
+}
+
+/// Connection refused by the swarm itself.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Refusal {
+    /// The address or the peer is banned, see [`ExpandedSwarm::ban`](crate::ExpandedSwarm::ban).
+    Banned,
+    /// The [`ConnectionGater`](crate::ConnectionGater) of the swarm denied the connection.
+    Gated,
+    /// A limit of the `ConnectionLimits` of the swarm has been reached.
+    Limit(ConnectionLimit),
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Refusal::Banned => write!(f, "Connection with a banned address or peer"),
+            Refusal::Gated => write!(f, "Connection denied by the connection gater"),
+            Refusal::Limit(limit) => write!(f, "{}", limit),
+        }
+    }
+}
+
+/// Summary of an [`AddressFailure`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The remote couldn't be reached, or didn't answer in time.
+    Unreachable,
+    /// The remote was reached, but establishing a secure and multiplexed connection with the
+    /// expected peer failed.
+    HandshakeFailed,
+    /// We refused the connection.
+    Refused,
+    /// We cancelled the dial.
+    Cancelled,
+}
+
+/// Reason why an attempt to reach an address failed.
+#[derive(Debug)]
+pub enum AddressFailure {
+    /// The transport failed to reach the address.
+    Transport(Box<dyn error::Error + Send>),
+    /// The negotiation or the handshake of the security protocol failed.
+    Security(Box<dyn error::Error + Send>),
+    /// The negotiation of the stream multiplexer failed.
+    Muxer(Box<dyn error::Error + Send>),
+    /// The remote turned out to be another peer than the one dialed.
+    PeerIdMismatch {
+        /// The peer actually reached.
+        obtained: PeerId,
+    },
+    /// The remote turned out to be the local peer.
+    LocalPeerId,
+    /// The dial did not finish within the timeout of its `DialOpts`.
+    Timeout,
+    /// We refused the connection.
+    Refused(Refusal),
+    /// The dial has been cancelled through its `DialHandle`.
+    Cancelled,
+}
+
+impl AddressFailure {
+    /// Returns the summary of the failure.
+    pub fn kind(&self) -> FailureKind {
+        match self {
+            AddressFailure::Transport(_) | AddressFailure::Timeout => FailureKind::Unreachable,
+            AddressFailure::Security(_)
+            | AddressFailure::Muxer(_)
+            | AddressFailure::PeerIdMismatch { .. }
+            | AddressFailure::LocalPeerId => FailureKind::HandshakeFailed,
+            AddressFailure::Refused(_) => FailureKind::Refused,
+            AddressFailure::Cancelled => FailureKind::Cancelled,
+        }
+    }
+
+    /// Builds the failure corresponding to an error of the transport.
+    pub(crate) fn from_transport<TErr>(error: TransportError<TErr>, classify: Classifier<TErr>) -> Self
+    where
+        TErr: error::Error + Send + 'static,
+    {
+        match error {
+            TransportError::Other(err) => match classify(&err) {
+                ConnectionStage::Transport => AddressFailure::Transport(Box::new(err)),
+                ConnectionStage::Security => AddressFailure::Security(Box::new(err)),
+                ConnectionStage::Muxer => AddressFailure::Muxer(Box::new(err)),
+            },
+            err @ TransportError::MultiaddrNotSupported(_) => AddressFailure::Transport(Box::new(err)),
+        }
+    }
+
+    /// Builds the failure corresponding to an error reaching a known peer.
+    pub(crate) fn from_reach_error<TErr, TConnInfo>(
+        error: NetworkReachError<TErr, TConnInfo>,
+        classify: Classifier<TErr>,
+    ) -> Self
+    where
+        TErr: error::Error + Send + 'static,
+        TConnInfo: ConnectionInfo<PeerId = PeerId>,
+    {
+        match error {
+            NetworkReachError::Transport(err) => AddressFailure::from_transport(err, classify),
+            NetworkReachError::PeerIdMismatch { obtained } =>
+                AddressFailure::PeerIdMismatch { obtained: obtained.peer_id().clone() },
+            NetworkReachError::Cancelled => AddressFailure::Cancelled,
+            NetworkReachError::Timeout => AddressFailure::Timeout,
+            NetworkReachError::ConnectionDenied(limit) => AddressFailure::Refused(Refusal::Limit(limit)),
+        }
+    }
+
+    /// Builds the failure corresponding to an error reaching an unknown peer.
+    pub(crate) fn from_unknown_peer_error<TErr>(error: UnknownPeerDialErr<TErr>, classify: Classifier<TErr>) -> Self
+    where
+        TErr: error::Error + Send + 'static,
+    {
+        match error {
+            UnknownPeerDialErr::Transport(err) => AddressFailure::from_transport(err, classify),
+            UnknownPeerDialErr::FoundLocalPeerId => AddressFailure::LocalPeerId,
+            UnknownPeerDialErr::Cancelled => AddressFailure::Cancelled,
+            UnknownPeerDialErr::Timeout => AddressFailure::Timeout,
+            UnknownPeerDialErr::ConnectionDenied(limit) => AddressFailure::Refused(Refusal::Limit(limit)),
+        }
+    }
+}
+
+impl fmt::Display for AddressFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressFailure::Transport(err) => write!(f, "Transport error: {}", err),
+            AddressFailure::Security(err) => write!(f, "Security handshake error: {}", err),
+            AddressFailure::Muxer(err) => write!(f, "Muxer negotiation error: {}", err),
+            AddressFailure::PeerIdMismatch { obtained } =>
+                write!(f, "Peer ID mismatch, obtained: {:?}", obtained),
+            AddressFailure::LocalPeerId => write!(f, "Reached the local peer"),
+            AddressFailure::Timeout => write!(f, "Dial timed out"),
+            AddressFailure::Refused(refusal) => write!(f, "{}", refusal),
+            AddressFailure::Cancelled => write!(f, "Dial cancelled"),
+        }
+    }
+}
+
+impl error::Error for AddressFailure {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            AddressFailure::Transport(err)
+            | AddressFailure::Security(err)
+            | AddressFailure::Muxer(err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::upgrade::UpgradeStage;
+
+    type UpgradedError = EitherError<EitherError<io::Error, UpgradeError<io::Error>>, UpgradeError<io::Error>>;
+
+    #[test]
+    fn upgraded_transport_errors_are_staged() {
+        let transport: UpgradedError = EitherError::A(EitherError::A(io::ErrorKind::ConnectionRefused.into()));
+        let security: UpgradedError = EitherError::A(EitherError::B(UpgradeError::Timeout(UpgradeStage::SecurityHandshake)));
+        let muxer: UpgradedError = EitherError::B(UpgradeError::Apply(io::ErrorKind::Other.into()));
+
+        let failure = AddressFailure::from_transport(TransportError::Other(transport), StagedError::stage);
+        assert!(matches!(failure, AddressFailure::Transport(_)));
+        assert_eq!(failure.kind(), FailureKind::Unreachable);
+        let failure = AddressFailure::from_transport(TransportError::Other(security), StagedError::stage);
+        assert!(matches!(failure, AddressFailure::Security(_)));
+        assert_eq!(failure.kind(), FailureKind::HandshakeFailed);
+        let failure = AddressFailure::from_transport(TransportError::Other(muxer), StagedError::stage);
+        assert!(matches!(failure, AddressFailure::Muxer(_)));
+        assert_eq!(failure.kind(), FailureKind::HandshakeFailed);
+    }
+
+    #[test]
+    fn refusals_and_unclassified_errors() {
+        let error: NetworkReachError<io::Error, PeerId> = NetworkReachError::ConnectionDenied(ConnectionLimit::Established(1));
+        let failure = AddressFailure::from_reach_error(error, unclassified);
+        assert_eq!(failure.kind(), FailureKind::Refused);
+
+        let error: UnknownPeerDialErr<io::Error> = UnknownPeerDialErr::Transport(TransportError::Other(io::ErrorKind::Other.into()));
+        let failure = AddressFailure::from_unknown_peer_error(error, unclassified);
+        assert_eq!(failure.kind(), FailureKind::Unreachable);
+    }
+}
@@ -0,0 +1,414 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Type-erased handlers of a [`DynamicBehaviour`](super::DynamicBehaviour).
+
+use super::{AnyBox, BehaviourId};
+use super::upgrade::{DynamicInboundUpgrade, DynamicOutboundUpgrade};
+use crate::protocols_handler::{
+    IntoProtocolsHandler,
+    KeepAlive,
+    ProtocolsHandler,
+    ProtocolsHandlerEvent,
+    ProtocolsHandlerUpgrErr,
+    SubstreamProtocol,
+};
+use futures::prelude::*;
+use libp2p_core::{
+    ConnectedPoint,
+    Negotiated,
+    PeerId,
+    upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo},
+};
+use std::{cmp, error, fmt, task::Context, task::Poll, time::Duration};
+
+/// Object-safe version of an [`IntoProtocolsHandler`].
+pub(crate) trait ErasedIntoHandler<TSubstream>: Send {
+    fn into_handler(self: Box<Self>, remote_peer_id: &PeerId, connected_point: &ConnectedPoint)
+        -> Box<dyn ErasedHandler<TSubstream>>;
+    fn inbound_protocol(&self, id: BehaviourId, upgrade: &mut DynamicInboundUpgrade<TSubstream>);
+}
+
+impl<TSubstream, TIntoHandler> ErasedIntoHandler<TSubstream> for TIntoHandler
+where
+    TIntoHandler: IntoProtocolsHandler + Send + 'static,
+    TIntoHandler::Handler: ErasedHandler<TSubstream> + ProtocolsHandler<Substream = TSubstream>,
+    <TIntoHandler::Handler as ProtocolsHandler>::InboundProtocol: Send + 'static,
+    <<TIntoHandler::Handler as ProtocolsHandler>::InboundProtocol as UpgradeInfo>::Info: Send + 'static,
+    <<TIntoHandler::Handler as ProtocolsHandler>::InboundProtocol as InboundUpgrade<Negotiated<TSubstream>>>::Output: Send + 'static,
+    <<TIntoHandler::Handler as ProtocolsHandler>::InboundProtocol as InboundUpgrade<Negotiated<TSubstream>>>::Error: Send + 'static,
+    <<TIntoHandler::Handler as ProtocolsHandler>::InboundProtocol as InboundUpgrade<Negotiated<TSubstream>>>::Future: Send + 'static,
+{
+    fn into_handler(self: Box<Self>, remote_peer_id: &PeerId, connected_point: &ConnectedPoint)
+        -> Box<dyn ErasedHandler<TSubstream>>
+    {
+        Box::new(IntoProtocolsHandler::into_handler(*self, remote_peer_id, connected_point))
+    }
+
+    fn inbound_protocol(&self, id: BehaviourId, upgrade: &mut DynamicInboundUpgrade<TSubstream>) {
+        upgrade.push(id, IntoProtocolsHandler::inbound_protocol(self))
+    }
+}
+
+/// Event produced by an [`ErasedHandler`].
+pub(crate) type ErasedHandlerEvent<TSubstream> =
+    ProtocolsHandlerEvent<DynamicOutboundUpgrade<TSubstream>, AnyBox, AnyBox, Box<dyn error::Error + Send>>;
+
+/// Object-safe version of a [`ProtocolsHandler`].
+pub(crate) trait ErasedHandler<TSubstream>: Send {
+    /// Adds the listen protocol of the handler to `upgrade` and returns its timeout.
+    fn listen_protocol(&self, id: BehaviourId, upgrade: &mut DynamicInboundUpgrade<TSubstream>) -> Duration;
+    fn inject_fully_negotiated_inbound(&mut self, protocol: AnyBox);
+    fn inject_fully_negotiated_outbound(&mut self, protocol: AnyBox, info: AnyBox);
+    fn inject_event(&mut self, event: AnyBox);
+    fn inject_dial_upgrade_error(&mut self, info: AnyBox, error: ProtocolsHandlerUpgrErr<AnyBox>);
+    fn is_outbound_cancelled(&self, info: &AnyBox) -> bool;
+    fn connection_keep_alive(&self) -> KeepAlive;
+    fn poll(&mut self, cx: &mut Context) -> Poll<ErasedHandlerEvent<TSubstream>>;
+}
+
+impl<TSubstream, THandler> ErasedHandler<TSubstream> for THandler
+where
+    THandler: ProtocolsHandler<Substream = TSubstream> + Send + 'static,
+    THandler::InEvent: 'static,
+    THandler::OutEvent: Send + 'static,
+    THandler::Error: Send + 'static,
+    THandler::OutboundOpenInfo: Send + 'static,
+    THandler::InboundProtocol: Send + 'static,
+    <THandler::InboundProtocol as UpgradeInfo>::Info: Send + 'static,
+    <THandler::InboundProtocol as InboundUpgrade<Negotiated<TSubstream>>>::Output: Send + 'static,
+    <THandler::InboundProtocol as InboundUpgrade<Negotiated<TSubstream>>>::Error: Send + 'static,
+    <THandler::InboundProtocol as InboundUpgrade<Negotiated<TSubstream>>>::Future: Send + 'static,
+    THandler::OutboundProtocol: Send + 'static,
+    <THandler::OutboundProtocol as UpgradeInfo>::Info: Send + 'static,
+    <THandler::OutboundProtocol as OutboundUpgrade<Negotiated<TSubstream>>>::Output: Send + 'static,
+    <THandler::OutboundProtocol as OutboundUpgrade<Negotiated<TSubstream>>>::Error: Send + 'static,
+    <THandler::OutboundProtocol as OutboundUpgrade<Negotiated<TSubstream>>>::Future: Send + 'static,
+{
+    fn listen_protocol(&self, id: BehaviourId, upgrade: &mut DynamicInboundUpgrade<TSubstream>) -> Duration {
+        let protocol = ProtocolsHandler::listen_protocol(self);
+        let timeout = *protocol.timeout();
+        upgrade.push(id, protocol.into_upgrade().1);
+        timeout
+    }
+
+    fn inject_fully_negotiated_inbound(&mut self, protocol: AnyBox) {
+        let protocol = protocol.downcast()
+            .expect("The output of an inbound upgrade is passed to the handler that created it; QED");
+        ProtocolsHandler::inject_fully_negotiated_inbound(self, *protocol)
+    }
+
+    fn inject_fully_negotiated_outbound(&mut self, protocol: AnyBox, info: AnyBox) {
+        let protocol = protocol.downcast()
+            .expect("The output of an outbound upgrade is passed to the handler that requested it; QED");
+        let info = info.downcast()
+            .expect("The info of an outbound upgrade is passed to the handler that requested it; QED");
+        ProtocolsHandler::inject_fully_negotiated_outbound(self, *protocol, *info)
+    }
+
+    fn inject_event(&mut self, event: AnyBox) {
+        let event = event.downcast()
+            .expect("Events are sent to the handler of the behaviour that generated them; QED");
+        ProtocolsHandler::inject_event(self, *event)
+    }
+
+    fn inject_dial_upgrade_error(&mut self, info: AnyBox, error: ProtocolsHandlerUpgrErr<AnyBox>) {
+        let info = info.downcast()
+            .expect("The info of an outbound upgrade is passed to the handler that requested it; QED");
+        let error = match error {
+            ProtocolsHandlerUpgrErr::Timeout => ProtocolsHandlerUpgrErr::Timeout,
+            ProtocolsHandlerUpgrErr::Timer => ProtocolsHandlerUpgrErr::Timer,
+            ProtocolsHandlerUpgrErr::Cancelled => ProtocolsHandlerUpgrErr::Cancelled,
+            ProtocolsHandlerUpgrErr::Upgrade(err) => ProtocolsHandlerUpgrErr::Upgrade(err.map_err(|err| {
+                *err.downcast()
+                    .expect("The error of an outbound upgrade is passed to the handler that requested it; QED")
+            })),
+        };
+        ProtocolsHandler::inject_dial_upgrade_error(self, *info, error)
+    }
+
+    fn is_outbound_cancelled(&self, info: &AnyBox) -> bool {
+        let info = info.downcast_ref()
+            .expect("The info of an outbound upgrade is passed to the handler that requested it; QED");
+        ProtocolsHandler::is_outbound_cancelled(self, info)
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        ProtocolsHandler::connection_keep_alive(self)
+    }
+
+    fn poll(&mut self, cx: &mut Context) -> Poll<ErasedHandlerEvent<TSubstream>> {
+        ProtocolsHandler::poll(self, cx).map(|event| match event {
+            ProtocolsHandlerEvent::OutboundSubstreamRequest { protocol, info } =>
+                ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                    protocol: protocol.map_upgrade(DynamicOutboundUpgrade::new),
+                    info: Box::new(info) as AnyBox,
+                },
+            ProtocolsHandlerEvent::Custom(event) => ProtocolsHandlerEvent::Custom(Box::new(event) as AnyBox),
+            ProtocolsHandlerEvent::Close(err) => ProtocolsHandlerEvent::Close(Box::new(err) as Box<_>),
+        })
+    }
+}
+
+/// Implementation of `IntoProtocolsHandler` for a [`DynamicBehaviour`](super::DynamicBehaviour).
+pub struct DynamicIntoHandler<TSubstream> {
+    handlers: Vec<(BehaviourId, Box<dyn ErasedIntoHandler<TSubstream>>)>,
+}
+
+impl<TSubstream> DynamicIntoHandler<TSubstream> {
+    pub(crate) fn new(handlers: Vec<(BehaviourId, Box<dyn ErasedIntoHandler<TSubstream>>)>) -> Self {
+        DynamicIntoHandler { handlers }
+    }
+}
+
+impl<TSubstream> IntoProtocolsHandler for DynamicIntoHandler<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Handler = DynamicHandler<TSubstream>;
+
+    fn into_handler(self, remote_peer_id: &PeerId, connected_point: &ConnectedPoint) -> Self::Handler {
+        DynamicHandler {
+            handlers: self.handlers.into_iter()
+                .map(|(id, h)| (id, h.into_handler(remote_peer_id, connected_point)))
+                .collect(),
+            reported: false,
+        }
+    }
+
+    fn inbound_protocol(&self) -> <Self::Handler as ProtocolsHandler>::InboundProtocol {
+        let mut upgrade = DynamicInboundUpgrade::new();
+        for (id, handler) in &self.handlers {
+            handler.inbound_protocol(*id, &mut upgrade);
+        }
+        upgrade
+    }
+}
+
+/// Implementation of `ProtocolsHandler` for a [`DynamicBehaviour`](super::DynamicBehaviour).
+///
+/// Contains the handlers of the behaviours of the `DynamicBehaviour`. Handlers are added and
+/// removed as behaviours are inserted into and removed from the `DynamicBehaviour`.
+pub struct DynamicHandler<TSubstream> {
+    handlers: Vec<(BehaviourId, Box<dyn ErasedHandler<TSubstream>>)>,
+    /// Whether the behaviours the handler was created with have been reported.
+    reported: bool,
+}
+
+impl<TSubstream> DynamicHandler<TSubstream> {
+    fn handler_mut(&mut self, id: BehaviourId) -> Option<&mut Box<dyn ErasedHandler<TSubstream>>> {
+        self.handlers.iter_mut().find(|(i, _)| *i == id).map(|(_, h)| h)
+    }
+}
+
+/// Event sent by a [`DynamicBehaviour`](super::DynamicBehaviour) to a [`DynamicHandler`].
+pub struct DynamicInEvent<TSubstream>(InEvent<TSubstream>);
+
+pub(crate) enum InEvent<TSubstream> {
+    /// Adds the handler of a behaviour, unless the handler already has one.
+    Add(BehaviourId, Box<dyn ErasedHandler<TSubstream>>),
+    /// Removes the handler of a behaviour.
+    Remove(BehaviourId),
+    /// An event for the handler of a behaviour.
+    Event(BehaviourId, AnyBox),
+}
+
+impl<TSubstream> From<InEvent<TSubstream>> for DynamicInEvent<TSubstream> {
+    fn from(event: InEvent<TSubstream>) -> Self {
+        DynamicInEvent(event)
+    }
+}
+
+impl<TSubstream> fmt::Debug for DynamicInEvent<TSubstream> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            InEvent::Add(id, _) => f.debug_tuple("Add").field(&id).finish(),
+            InEvent::Remove(id) => f.debug_tuple("Remove").field(&id).finish(),
+            InEvent::Event(id, _) => f.debug_tuple("Event").field(&id).finish(),
+        }
+    }
+}
+
+/// Event sent by a [`DynamicHandler`] to its [`DynamicBehaviour`](super::DynamicBehaviour).
+pub struct DynamicOutEvent(pub(crate) OutEvent);
+
+pub(crate) enum OutEvent {
+    /// The behaviours whose handlers the handler was created with.
+    Handlers(Vec<BehaviourId>),
+    /// An event generated by the handler of a behaviour.
+    Event(BehaviourId, AnyBox),
+}
+
+impl fmt::Debug for DynamicOutEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            OutEvent::Handlers(ref ids) => f.debug_tuple("Handlers").field(ids).finish(),
+            OutEvent::Event(id, _) => f.debug_tuple("Event").field(&id).finish(),
+        }
+    }
+}
+
+/// Error closing the connection of a [`DynamicHandler`].
+#[derive(Debug)]
+pub struct DynamicHandlerError {
+    /// The behaviour whose handler closed the connection.
+    behaviour: BehaviourId,
+    /// The error of the handler.
+    error: Box<dyn error::Error + Send>,
+}
+
+impl DynamicHandlerError {
+    /// Returns the behaviour whose handler closed the connection.
+    pub fn behaviour(&self) -> BehaviourId {
+        self.behaviour
+    }
+}
+
+impl fmt::Display for DynamicHandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handler of behaviour {:?} closed the connection: {}", self.behaviour, self.error)
+    }
+}
+
+impl error::Error for DynamicHandlerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+impl<TSubstream> ProtocolsHandler for DynamicHandler<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type InEvent = DynamicInEvent<TSubstream>;
+    type OutEvent = DynamicOutEvent;
+    type Error = DynamicHandlerError;
+    type Substream = TSubstream;
+    type InboundProtocol = DynamicInboundUpgrade<TSubstream>;
+    type OutboundProtocol = DynamicOutboundUpgrade<TSubstream>;
+    type OutboundOpenInfo = (BehaviourId, AnyBox);
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
+        let mut upgrade = DynamicInboundUpgrade::new();
+        let mut timeout = None;
+        for (id, handler) in &self.handlers {
+            timeout = cmp::max(timeout, Some(handler.listen_protocol(*id, &mut upgrade)));
+        }
+        let protocol = SubstreamProtocol::new(upgrade);
+        match timeout {
+            Some(timeout) => protocol.with_timeout(timeout),
+            None => protocol,
+        }
+    }
+
+    fn inject_fully_negotiated_inbound(
+        &mut self,
+        (id, protocol): <Self::InboundProtocol as InboundUpgrade<Negotiated<Self::Substream>>>::Output
+    ) {
+        // The handler may have been removed while the substream was being negotiated.
+        if let Some(handler) = self.handler_mut(id) {
+            handler.inject_fully_negotiated_inbound(protocol)
+        }
+    }
+
+    fn inject_fully_negotiated_outbound(
+        &mut self,
+        protocol: <Self::OutboundProtocol as OutboundUpgrade<Negotiated<Self::Substream>>>::Output,
+        (id, info): Self::OutboundOpenInfo
+    ) {
+        if let Some(handler) = self.handler_mut(id) {
+            handler.inject_fully_negotiated_outbound(protocol, info)
+        }
+    }
+
+    fn inject_event(&mut self, DynamicInEvent(event): Self::InEvent) {
+        match event {
+            InEvent::Add(id, handler) => {
+                if self.handler_mut(id).is_none() {
+                    self.handlers.push((id, handler));
+                }
+            }
+            InEvent::Remove(id) => self.handlers.retain(|(i, _)| *i != id),
+            InEvent::Event(id, event) => {
+                if let Some(handler) = self.handler_mut(id) {
+                    handler.inject_event(event)
+                }
+            }
+        }
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        (id, info): Self::OutboundOpenInfo,
+        error: ProtocolsHandlerUpgrErr<<Self::OutboundProtocol as OutboundUpgrade<Negotiated<Self::Substream>>>::Error>
+    ) {
+        if let Some(handler) = self.handler_mut(id) {
+            handler.inject_dial_upgrade_error(info, error)
+        }
+    }
+
+    fn is_outbound_cancelled(&self, (id, info): &Self::OutboundOpenInfo) -> bool {
+        // The requests of a removed handler are no longer needed.
+        match self.handlers.iter().find(|(i, _)| i == id) {
+            Some((_, handler)) => handler.is_outbound_cancelled(info),
+            None => true,
+        }
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.handlers.iter()
+            .map(|(_, h)| h.connection_keep_alive())
+            .max()
+            .unwrap_or(KeepAlive::No)
+    }
+
+    fn poll(&mut self, cx: &mut Context) -> Poll<
+        ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
+    > {
+        if !self.reported {
+            self.reported = true;
+            let ids = self.handlers.iter().map(|(id, _)| *id).collect();
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(DynamicOutEvent(OutEvent::Handlers(ids))));
+        }
+
+        for (id, handler) in &mut self.handlers {
+            let id = *id;
+            match handler.poll(cx) {
+                Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest { protocol, info }) => {
+                    return Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                        protocol,
+                        info: (id, info),
+                    });
+                }
+                Poll::Ready(ProtocolsHandlerEvent::Custom(event)) => {
+                    let event = DynamicOutEvent(OutEvent::Event(id, event));
+                    return Poll::Ready(ProtocolsHandlerEvent::Custom(event));
+                }
+                Poll::Ready(ProtocolsHandlerEvent::Close(error)) => {
+                    let error = DynamicHandlerError { behaviour: id, error };
+                    return Poll::Ready(ProtocolsHandlerEvent::Close(error));
+                }
+                Poll::Pending => {}
+            }
+        }
+
+        Poll::Pending
+    }
+}
@@ -0,0 +1,532 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A network behaviour whose behaviours can be added and removed at runtime.
+//!
+//! A [`DynamicBehaviour`] holds boxed [`NetworkBehaviour`]s of any type whose events can be
+//! converted into a common out event. Behaviours can be inserted into and removed from it while
+//! the swarm is running, for example to load plugins after startup:
+//!
+//! - An inserted behaviour is informed of the current listen and external addresses, the
+//!   current address filter and the connected peers, and a handler is created for each
+//!   existing connection.
+//! - A removed behaviour is informed that all peers disconnected, and its handlers are removed
+//!   from the existing connections.
+//!
+//! > **Note**: The protocols of a behaviour inserted while the swarm is running are not part of
+//! > the [`PollParameters::supported_protocols`] of the swarm, which are computed when the swarm
+//! > is built.
+
+mod handler;
+mod upgrade;
+
+pub use handler::{DynamicHandler, DynamicHandlerError, DynamicInEvent, DynamicIntoHandler, DynamicOutEvent};
+pub use upgrade::{DynamicInboundUpgrade, DynamicOutboundUpgrade, DynamicProtocolName};
+
+use crate::{NetworkBehaviour, NetworkBehaviourAction, PollParameters, ProtocolCapabilities};
+use crate::protocols_handler::{IntoProtocolsHandler, ProtocolsHandler};
+use handler::{ErasedIntoHandler, InEvent, OutEvent};
+use libp2p_core::{
+    AddressFilter,
+    ConnectedPoint,
+    Multiaddr,
+    Negotiated,
+    PeerId,
+    nodes::ListenerId,
+    upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo},
+};
+use futures::prelude::*;
+use std::{any::Any, collections::{HashMap, VecDeque}, error, fmt, task::Context, task::Poll, vec};
+
+/// Type-erased value that is sent across a [`DynamicBehaviour`] and its handlers.
+type AnyBox = Box<dyn Any + Send>;
+
+type HandlerOf<TBehaviour> =
+    <<TBehaviour as NetworkBehaviour>::ProtocolsHandler as IntoProtocolsHandler>::Handler;
+type InboundOf<TBehaviour> = <HandlerOf<TBehaviour> as ProtocolsHandler>::InboundProtocol;
+type OutboundOf<TBehaviour> = <HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundProtocol;
+
+/// Identifier of a behaviour inserted into a [`DynamicBehaviour`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BehaviourId(u64);
+
+/// Implementation of `NetworkBehaviour` whose behaviours can be inserted and removed at runtime.
+///
+/// The events generated by the behaviours are converted into `TOutEvent`.
+pub struct DynamicBehaviour<TSubstream, TOutEvent> {
+    /// The behaviours, in insertion order.
+    behaviours: Vec<(BehaviourId, Box<dyn ErasedBehaviour<TSubstream, TOutEvent>>)>,
+    /// Identifier of the next inserted behaviour.
+    next_id: u64,
+    /// The connected peers and the endpoints of the connections.
+    connected: HashMap<PeerId, ConnectedPoint>,
+    /// The addresses we are listening on.
+    listen_addrs: Vec<Multiaddr>,
+    /// The external addresses of the local node.
+    external_addrs: Vec<Multiaddr>,
+    /// The last address filter reported by the swarm.
+    address_filter: Option<AddressFilter>,
+    /// Actions to return from `poll()` before polling the behaviours.
+    events: VecDeque<NetworkBehaviourAction<DynamicInEvent<TSubstream>, TOutEvent>>,
+}
+
+impl<TSubstream, TOutEvent> DynamicBehaviour<TSubstream, TOutEvent> {
+    /// Creates a `DynamicBehaviour` without any behaviour.
+    pub fn new() -> Self {
+        DynamicBehaviour {
+            behaviours: Vec::new(),
+            next_id: 0,
+            connected: HashMap::new(),
+            listen_addrs: Vec::new(),
+            external_addrs: Vec::new(),
+            address_filter: None,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Inserts a behaviour and returns its identifier.
+    ///
+    /// The behaviour is informed of the current state of the swarm, and a handler is created for
+    /// each existing connection.
+    pub fn insert<TBehaviour>(&mut self, mut behaviour: TBehaviour) -> BehaviourId
+    where
+        TBehaviour: NetworkBehaviour + Send + 'static,
+        TBehaviour::OutEvent: Into<TOutEvent>,
+        TBehaviour::ProtocolsHandler: Send + 'static,
+        HandlerOf<TBehaviour>: ProtocolsHandler<Substream = TSubstream> + Send + 'static,
+        <HandlerOf<TBehaviour> as ProtocolsHandler>::InEvent: Send + 'static,
+        <HandlerOf<TBehaviour> as ProtocolsHandler>::OutEvent: Send + 'static,
+        <HandlerOf<TBehaviour> as ProtocolsHandler>::Error: Send + 'static,
+        <HandlerOf<TBehaviour> as ProtocolsHandler>::OutboundOpenInfo: Send + 'static,
+        InboundOf<TBehaviour>: Send + 'static,
+        <InboundOf<TBehaviour> as UpgradeInfo>::Info: Send + 'static,
+        <InboundOf<TBehaviour> as InboundUpgrade<Negotiated<TSubstream>>>::Output: Send + 'static,
+        <InboundOf<TBehaviour> as InboundUpgrade<Negotiated<TSubstream>>>::Error: Send + 'static,
+        <InboundOf<TBehaviour> as InboundUpgrade<Negotiated<TSubstream>>>::Future: Send + 'static,
+        OutboundOf<TBehaviour>: Send + 'static,
+        <OutboundOf<TBehaviour> as UpgradeInfo>::Info: Send + 'static,
+        <OutboundOf<TBehaviour> as OutboundUpgrade<Negotiated<TSubstream>>>::Output: Send + 'static,
+        <OutboundOf<TBehaviour> as OutboundUpgrade<Negotiated<TSubstream>>>::Error: Send + 'static,
+        <OutboundOf<TBehaviour> as OutboundUpgrade<Negotiated<TSubstream>>>::Future: Send + 'static,
+    {
+        let id = BehaviourId(self.next_id);
+        self.next_id += 1;
+
+        for addr in &self.listen_addrs {
+            behaviour.inject_new_listen_addr(addr);
+        }
+        for addr in &self.external_addrs {
+            behaviour.inject_new_external_addr(addr);
+        }
+        if let Some(filter) = self.address_filter.as_ref() {
+            behaviour.inject_address_filter(filter);
+        }
+        for (peer_id, endpoint) in &self.connected {
+            let handler = behaviour.new_handler().into_handler(peer_id, endpoint);
+            self.events.push_back(NetworkBehaviourAction::SendEvent {
+                peer_id: peer_id.clone(),
+                event: InEvent::Add(id, Box::new(handler)).into(),
+            });
+            behaviour.inject_connected(peer_id.clone(), endpoint.clone());
+        }
+
+        self.behaviours.push((id, Box::new(behaviour)));
+        id
+    }
+
+    /// Removes a behaviour. Returns `false` if there is no behaviour with this identifier.
+    ///
+    /// The behaviour is informed that all peers disconnected, and its handlers are removed from
+    /// the existing connections.
+    pub fn remove(&mut self, id: BehaviourId) -> bool {
+        let mut behaviour = match self.behaviours.iter().position(|(i, _)| *i == id) {
+            Some(pos) => self.behaviours.remove(pos).1,
+            None => return false,
+        };
+
+        for (peer_id, endpoint) in &self.connected {
+            behaviour.inject_disconnected(peer_id, endpoint.clone());
+            self.events.push_back(NetworkBehaviourAction::SendEvent {
+                peer_id: peer_id.clone(),
+                event: InEvent::Remove(id).into(),
+            });
+        }
+
+        true
+    }
+
+    /// Returns `true` if the behaviour with the given identifier has not been removed.
+    pub fn contains(&self, id: BehaviourId) -> bool {
+        self.behaviours.iter().any(|(i, _)| *i == id)
+    }
+
+    /// Returns the identifiers of the behaviours, in insertion order.
+    pub fn ids(&self) -> impl Iterator<Item = BehaviourId> + '_ {
+        self.behaviours.iter().map(|(id, _)| *id)
+    }
+
+    /// Returns the number of behaviours.
+    pub fn len(&self) -> usize {
+        self.behaviours.len()
+    }
+
+    /// Returns `true` if there is no behaviour.
+    pub fn is_empty(&self) -> bool {
+        self.behaviours.is_empty()
+    }
+
+    fn behaviour_mut(&mut self, id: BehaviourId) -> Option<&mut Box<dyn ErasedBehaviour<TSubstream, TOutEvent>>> {
+        self.behaviours.iter_mut().find(|(i, _)| *i == id).map(|(_, b)| b)
+    }
+}
+
+impl<TSubstream, TOutEvent> Default for DynamicBehaviour<TSubstream, TOutEvent> {
+    fn default() -> Self {
+        DynamicBehaviour::new()
+    }
+}
+
+impl<TSubstream, TOutEvent> fmt::Debug for DynamicBehaviour<TSubstream, TOutEvent> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DynamicBehaviour")
+            .field("behaviours", &self.ids().collect::<Vec<_>>())
+            .field("connected", &self.connected)
+            .finish()
+    }
+}
+
+impl<TSubstream, TOutEvent> NetworkBehaviour for DynamicBehaviour<TSubstream, TOutEvent>
+where
+    TSubstream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type ProtocolsHandler = DynamicIntoHandler<TSubstream>;
+    type OutEvent = TOutEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        DynamicIntoHandler::new(self.behaviours.iter_mut().map(|(id, b)| (*id, b.new_handler())).collect())
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        let mut addresses = Vec::new();
+        for (_, behaviour) in &mut self.behaviours {
+            addresses.extend(behaviour.addresses_of_peer(peer_id));
+        }
+        addresses
+    }
+
+    fn inject_connected(&mut self, peer_id: PeerId, endpoint: ConnectedPoint) {
+        self.connected.insert(peer_id.clone(), endpoint.clone());
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_connected(peer_id.clone(), endpoint.clone());
+        }
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId, endpoint: ConnectedPoint) {
+        self.connected.remove(peer_id);
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_disconnected(peer_id, endpoint.clone());
+        }
+    }
+
+    fn inject_replaced(&mut self, peer_id: PeerId, closed_endpoint: ConnectedPoint, new_endpoint: ConnectedPoint) {
+        self.connected.insert(peer_id.clone(), new_endpoint.clone());
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_replaced(peer_id.clone(), closed_endpoint.clone(), new_endpoint.clone());
+        }
+    }
+
+    fn inject_node_event(&mut self, peer_id: PeerId, DynamicOutEvent(event): DynamicOutEvent) {
+        match event {
+            // Brings the handlers of the connection in line with the behaviours, which may have
+            // changed between the creation of the handler and the connection being established.
+            OutEvent::Handlers(ids) => {
+                let endpoint = match self.connected.get(&peer_id) {
+                    Some(endpoint) => endpoint,
+                    None => return,
+                };
+                for (id, behaviour) in &mut self.behaviours {
+                    if !ids.contains(id) {
+                        let handler = behaviour.new_handler().into_handler(&peer_id, endpoint);
+                        self.events.push_back(NetworkBehaviourAction::SendEvent {
+                            peer_id: peer_id.clone(),
+                            event: InEvent::Add(*id, handler).into(),
+                        });
+                    }
+                }
+                for id in ids {
+                    if !self.behaviours.iter().any(|(i, _)| *i == id) {
+                        self.events.push_back(NetworkBehaviourAction::SendEvent {
+                            peer_id: peer_id.clone(),
+                            event: InEvent::Remove(id).into(),
+                        });
+                    }
+                }
+            }
+            OutEvent::Event(id, event) => {
+                // The behaviour may have been removed in the meantime.
+                if let Some(behaviour) = self.behaviour_mut(id) {
+                    behaviour.inject_node_event(peer_id, event)
+                }
+            }
+        }
+    }
+
+    fn inject_addr_reach_failure(&mut self, peer_id: Option<&PeerId>, addr: &Multiaddr, error: &dyn error::Error) {
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_addr_reach_failure(peer_id, addr, error);
+        }
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_dial_failure(peer_id);
+        }
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        self.listen_addrs.push(addr.clone());
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_new_listen_addr(addr);
+        }
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        self.listen_addrs.retain(|a| a != addr);
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_expired_listen_addr(addr);
+        }
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        if !self.external_addrs.contains(addr) {
+            self.external_addrs.push(addr.clone());
+        }
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_new_external_addr(addr);
+        }
+    }
+
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        self.external_addrs.retain(|a| a != addr);
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_expired_external_addr(addr);
+        }
+    }
+
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn error::Error + 'static)) {
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_listener_error(id, err);
+        }
+    }
+
+    fn inject_listener_closed(&mut self, id: ListenerId) {
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_listener_closed(id);
+        }
+    }
+
+    fn inject_address_filter(&mut self, filter: &AddressFilter) {
+        self.address_filter = Some(filter.clone());
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_address_filter(filter);
+        }
+    }
+
+    fn inject_shutdown(&mut self) {
+        for (_, behaviour) in &mut self.behaviours {
+            behaviour.inject_shutdown();
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<DynamicInEvent<TSubstream>, Self::OutEvent>>
+    {
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(event);
+        }
+
+        if self.behaviours.is_empty() {
+            return Poll::Pending;
+        }
+
+        let mut params = DynamicPollParameters {
+            supported_protocols: params.supported_protocols().collect(),
+            listened_addresses: params.listened_addresses().collect(),
+            external_addresses: params.external_addresses().collect(),
+            local_peer_id: params.local_peer_id().clone(),
+            protocol_capabilities: params.protocol_capabilities().clone(),
+        };
+
+        for (id, behaviour) in &mut self.behaviours {
+            let action = match behaviour.poll(cx, &mut params) {
+                Poll::Ready(action) => action,
+                Poll::Pending => continue,
+            };
+            let id = *id;
+            return Poll::Ready(action.map_in(|event| InEvent::Event(id, event).into()));
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Object-safe version of a [`NetworkBehaviour`].
+trait ErasedBehaviour<TSubstream, TOutEvent>: Send {
+    fn new_handler(&mut self) -> Box<dyn ErasedIntoHandler<TSubstream>>;
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr>;
+    fn inject_connected(&mut self, peer_id: PeerId, endpoint: ConnectedPoint);
+    fn inject_disconnected(&mut self, peer_id: &PeerId, endpoint: ConnectedPoint);
+    fn inject_replaced(&mut self, peer_id: PeerId, closed_endpoint: ConnectedPoint, new_endpoint: ConnectedPoint);
+    fn inject_node_event(&mut self, peer_id: PeerId, event: AnyBox);
+    fn inject_addr_reach_failure(&mut self, peer_id: Option<&PeerId>, addr: &Multiaddr, error: &dyn error::Error);
+    fn inject_dial_failure(&mut self, peer_id: &PeerId);
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr);
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr);
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr);
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr);
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn error::Error + 'static));
+    fn inject_listener_closed(&mut self, id: ListenerId);
+    fn inject_address_filter(&mut self, filter: &AddressFilter);
+    fn inject_shutdown(&mut self);
+    fn poll(&mut self, cx: &mut Context, params: &mut DynamicPollParameters)
+        -> Poll<NetworkBehaviourAction<AnyBox, TOutEvent>>;
+}
+
+impl<TSubstream, TOutEvent, TBehaviour> ErasedBehaviour<TSubstream, TOutEvent> for TBehaviour
+where
+    TBehaviour: NetworkBehaviour + Send + 'static,
+    TBehaviour::OutEvent: Into<TOutEvent>,
+    TBehaviour::ProtocolsHandler: ErasedIntoHandler<TSubstream>,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::InEvent: Send + 'static,
+    <HandlerOf<TBehaviour> as ProtocolsHandler>::OutEvent: 'static,
+{
+    fn new_handler(&mut self) -> Box<dyn ErasedIntoHandler<TSubstream>> {
+        Box::new(NetworkBehaviour::new_handler(self))
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        NetworkBehaviour::addresses_of_peer(self, peer_id)
+    }
+
+    fn inject_connected(&mut self, peer_id: PeerId, endpoint: ConnectedPoint) {
+        NetworkBehaviour::inject_connected(self, peer_id, endpoint)
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId, endpoint: ConnectedPoint) {
+        NetworkBehaviour::inject_disconnected(self, peer_id, endpoint)
+    }
+
+    fn inject_replaced(&mut self, peer_id: PeerId, closed_endpoint: ConnectedPoint, new_endpoint: ConnectedPoint) {
+        NetworkBehaviour::inject_replaced(self, peer_id, closed_endpoint, new_endpoint)
+    }
+
+    fn inject_node_event(&mut self, peer_id: PeerId, event: AnyBox) {
+        let event = event.downcast()
+            .expect("Handlers events are passed to the behaviour that created the handler; QED");
+        NetworkBehaviour::inject_node_event(self, peer_id, *event)
+    }
+
+    fn inject_addr_reach_failure(&mut self, peer_id: Option<&PeerId>, addr: &Multiaddr, error: &dyn error::Error) {
+        NetworkBehaviour::inject_addr_reach_failure(self, peer_id, addr, error)
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        NetworkBehaviour::inject_dial_failure(self, peer_id)
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        NetworkBehaviour::inject_new_listen_addr(self, addr)
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        NetworkBehaviour::inject_expired_listen_addr(self, addr)
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        NetworkBehaviour::inject_new_external_addr(self, addr)
+    }
+
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        NetworkBehaviour::inject_expired_external_addr(self, addr)
+    }
+
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn error::Error + 'static)) {
+        NetworkBehaviour::inject_listener_error(self, id, err)
+    }
+
+    fn inject_listener_closed(&mut self, id: ListenerId) {
+        NetworkBehaviour::inject_listener_closed(self, id)
+    }
+
+    fn inject_address_filter(&mut self, filter: &AddressFilter) {
+        NetworkBehaviour::inject_address_filter(self, filter)
+    }
+
+    fn inject_shutdown(&mut self) {
+        NetworkBehaviour::inject_shutdown(self)
+    }
+
+    fn poll(&mut self, cx: &mut Context, params: &mut DynamicPollParameters)
+        -> Poll<NetworkBehaviourAction<AnyBox, TOutEvent>>
+    {
+        NetworkBehaviour::poll(self, cx, params).map(|action| {
+            action
+                .map_in(|event| Box::new(event) as AnyBox)
+                .map_out(Into::into)
+        })
+    }
+}
+
+/// The `PollParameters` passed to the behaviours of a `DynamicBehaviour`.
+///
+/// Behaviours are polled through trait objects, which can't take an `impl PollParameters`.
+struct DynamicPollParameters {
+    supported_protocols: Vec<Vec<u8>>,
+    listened_addresses: Vec<Multiaddr>,
+    external_addresses: Vec<Multiaddr>,
+    local_peer_id: PeerId,
+    protocol_capabilities: ProtocolCapabilities,
+}
+
+impl PollParameters for DynamicPollParameters {
+    type SupportedProtocolsIter = vec::IntoIter<Vec<u8>>;
+    type ListenedAddressesIter = vec::IntoIter<Multiaddr>;
+    type ExternalAddressesIter = vec::IntoIter<Multiaddr>;
+
+    fn supported_protocols(&self) -> Self::SupportedProtocolsIter {
+        self.supported_protocols.clone().into_iter()
+    }
+
+    fn listened_addresses(&self) -> Self::ListenedAddressesIter {
+        self.listened_addresses.clone().into_iter()
+    }
+
+    fn external_addresses(&self) -> Self::ExternalAddressesIter {
+        self.external_addresses.clone().into_iter()
+    }
+
+    fn local_peer_id(&self) -> &PeerId {
+        &self.local_peer_id
+    }
+
+    fn protocol_capabilities(&self) -> &ProtocolCapabilities {
+        &self.protocol_capabilities
+    }
+}
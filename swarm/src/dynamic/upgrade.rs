@@ -0,0 +1,233 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Type-erased upgrades of the handlers of a [`DynamicBehaviour`](super::DynamicBehaviour).
+
+use super::{AnyBox, BehaviourId};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{
+    Negotiated,
+    upgrade::{InboundUpgrade, OutboundUpgrade, ProtocolName, UpgradeInfo},
+};
+use std::{fmt, vec};
+
+/// A protocol supported by a [`DynamicInboundUpgrade`] or a [`DynamicOutboundUpgrade`].
+#[derive(Debug, Clone)]
+pub struct DynamicProtocolName {
+    /// The name of the protocol.
+    name: Vec<u8>,
+    /// Index of the upgrade supporting the protocol.
+    upgrade: usize,
+    /// Index of the protocol in the `protocol_info()` of the upgrade.
+    index: usize,
+}
+
+impl ProtocolName for DynamicProtocolName {
+    fn protocol_name(&self) -> &[u8] {
+        &self.name
+    }
+}
+
+/// An upgrade whose protocols, output and error are type-erased.
+struct Erased<TUpgrade: UpgradeInfo> {
+    upgrade: TUpgrade,
+    infos: Vec<TUpgrade::Info>,
+}
+
+impl<TUpgrade: UpgradeInfo> Erased<TUpgrade> {
+    fn new(upgrade: TUpgrade) -> Self {
+        let infos = upgrade.protocol_info().into_iter().collect();
+        Erased { upgrade, infos }
+    }
+
+    fn names(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.infos.iter().map(|info| info.protocol_name().to_vec())
+    }
+}
+
+/// Object-safe version of an [`InboundUpgrade`].
+trait ErasedInbound<TSubstream>: Send {
+    fn protocol_names(&self) -> Vec<Vec<u8>>;
+    fn upgrade_inbound(self: Box<Self>, socket: Negotiated<TSubstream>, index: usize)
+        -> BoxFuture<'static, Result<AnyBox, AnyBox>>;
+}
+
+impl<TSubstream, TUpgrade> ErasedInbound<TSubstream> for Erased<TUpgrade>
+where
+    TUpgrade: InboundUpgrade<Negotiated<TSubstream>> + Send + 'static,
+    TUpgrade::Info: Send + 'static,
+    TUpgrade::Output: Send + 'static,
+    TUpgrade::Error: Send + 'static,
+    TUpgrade::Future: Send + 'static,
+{
+    fn protocol_names(&self) -> Vec<Vec<u8>> {
+        self.names().collect()
+    }
+
+    fn upgrade_inbound(self: Box<Self>, socket: Negotiated<TSubstream>, index: usize)
+        -> BoxFuture<'static, Result<AnyBox, AnyBox>>
+    {
+        let Erased { upgrade, mut infos } = *self;
+        upgrade.upgrade_inbound(socket, infos.swap_remove(index))
+            .map_ok(|out| Box::new(out) as AnyBox)
+            .map_err(|err| Box::new(err) as AnyBox)
+            .boxed()
+    }
+}
+
+/// Object-safe version of an [`OutboundUpgrade`].
+trait ErasedOutbound<TSubstream>: Send {
+    fn protocol_names(&self) -> Vec<Vec<u8>>;
+    fn upgrade_outbound(self: Box<Self>, socket: Negotiated<TSubstream>, index: usize)
+        -> BoxFuture<'static, Result<AnyBox, AnyBox>>;
+}
+
+impl<TSubstream, TUpgrade> ErasedOutbound<TSubstream> for Erased<TUpgrade>
+where
+    TUpgrade: OutboundUpgrade<Negotiated<TSubstream>> + Send + 'static,
+    TUpgrade::Info: Send + 'static,
+    TUpgrade::Output: Send + 'static,
+    TUpgrade::Error: Send + 'static,
+    TUpgrade::Future: Send + 'static,
+{
+    fn protocol_names(&self) -> Vec<Vec<u8>> {
+        self.names().collect()
+    }
+
+    fn upgrade_outbound(self: Box<Self>, socket: Negotiated<TSubstream>, index: usize)
+        -> BoxFuture<'static, Result<AnyBox, AnyBox>>
+    {
+        let Erased { upgrade, mut infos } = *self;
+        upgrade.upgrade_outbound(socket, infos.swap_remove(index))
+            .map_ok(|out| Box::new(out) as AnyBox)
+            .map_err(|err| Box::new(err) as AnyBox)
+            .boxed()
+    }
+}
+
+/// Inbound upgrade combining the listen protocols of the handlers of a `DynamicHandler`.
+///
+/// The output is tagged with the behaviour whose handler negotiated the protocol.
+pub struct DynamicInboundUpgrade<TSubstream> {
+    upgrades: Vec<(BehaviourId, Box<dyn ErasedInbound<TSubstream>>)>,
+}
+
+impl<TSubstream> DynamicInboundUpgrade<TSubstream> {
+    /// Creates an upgrade that doesn't support any protocol.
+    pub(crate) fn new() -> Self {
+        DynamicInboundUpgrade { upgrades: Vec::new() }
+    }
+
+    /// Adds the upgrade of the handler of the given behaviour.
+    pub(crate) fn push<TUpgrade>(&mut self, id: BehaviourId, upgrade: TUpgrade)
+    where
+        TUpgrade: InboundUpgrade<Negotiated<TSubstream>> + Send + 'static,
+        TUpgrade::Info: Send + 'static,
+        TUpgrade::Output: Send + 'static,
+        TUpgrade::Error: Send + 'static,
+        TUpgrade::Future: Send + 'static,
+    {
+        self.upgrades.push((id, Box::new(Erased::new(upgrade))));
+    }
+}
+
+impl<TSubstream> fmt::Debug for DynamicInboundUpgrade<TSubstream> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.upgrades.iter().map(|(id, _)| id)).finish()
+    }
+}
+
+impl<TSubstream> UpgradeInfo for DynamicInboundUpgrade<TSubstream> {
+    type Info = DynamicProtocolName;
+    type InfoIter = vec::IntoIter<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.upgrades.iter()
+            .enumerate()
+            .flat_map(|(upgrade, (_, u))| {
+                u.protocol_names().into_iter()
+                    .enumerate()
+                    .map(move |(index, name)| DynamicProtocolName { name, upgrade, index })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+impl<TSubstream> InboundUpgrade<Negotiated<TSubstream>> for DynamicInboundUpgrade<TSubstream> {
+    type Output = (BehaviourId, AnyBox);
+    type Error = AnyBox;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(mut self, socket: Negotiated<TSubstream>, info: Self::Info) -> Self::Future {
+        let (id, upgrade) = self.upgrades.swap_remove(info.upgrade);
+        upgrade.upgrade_inbound(socket, info.index)
+            .map_ok(move |out| (id, out))
+            .boxed()
+    }
+}
+
+/// Outbound upgrade requested by the handler of a behaviour of a `DynamicBehaviour`.
+pub struct DynamicOutboundUpgrade<TSubstream> {
+    upgrade: Box<dyn ErasedOutbound<TSubstream>>,
+}
+
+impl<TSubstream> DynamicOutboundUpgrade<TSubstream> {
+    /// Wraps the upgrade requested by a handler.
+    pub(crate) fn new<TUpgrade>(upgrade: TUpgrade) -> Self
+    where
+        TUpgrade: OutboundUpgrade<Negotiated<TSubstream>> + Send + 'static,
+        TUpgrade::Info: Send + 'static,
+        TUpgrade::Output: Send + 'static,
+        TUpgrade::Error: Send + 'static,
+        TUpgrade::Future: Send + 'static,
+    {
+        DynamicOutboundUpgrade { upgrade: Box::new(Erased::new(upgrade)) }
+    }
+}
+
+impl<TSubstream> fmt::Debug for DynamicOutboundUpgrade<TSubstream> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DynamicOutboundUpgrade").finish()
+    }
+}
+
+impl<TSubstream> UpgradeInfo for DynamicOutboundUpgrade<TSubstream> {
+    type Info = DynamicProtocolName;
+    type InfoIter = vec::IntoIter<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.upgrade.protocol_names().into_iter()
+            .enumerate()
+            .map(|(index, name)| DynamicProtocolName { name, upgrade: 0, index })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+impl<TSubstream> OutboundUpgrade<Negotiated<TSubstream>> for DynamicOutboundUpgrade<TSubstream> {
+    type Output = AnyBox;
+    type Error = AnyBox;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: Negotiated<TSubstream>, info: Self::Info) -> Self::Future {
+        self.upgrade.upgrade_outbound(socket, info.index)
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Candidates for the external addresses of the local node.
+//!
+//! Addresses remotes observe for us are only candidates until enough distinct remotes confirmed
+//! them, and only confirmed addresses are advertised to other peers, for example through identify
+//! or the DHT. Addresses configured explicitly or reported by a port mapping protocol of the
+//! gateway are trusted and advertised right away.
+
+use libp2p_core::{Multiaddr, PeerId};
+use smallvec::SmallVec;
+use std::collections::{HashMap, HashSet};
+
+/// Default number of distinct remotes that must confirm an observed address.
+pub const DEFAULT_MIN_CONFIRMATIONS: usize = 2;
+
+/// Maximum number of unconfirmed candidates tracked at the same time.
+const MAX_UNCONFIRMED: usize = 32;
+
+/// Where a candidate external address comes from, from the least to the most trusted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AddressSource {
+    /// Derived from the address a remote observed for the local node.
+    Observed,
+    /// Reported by a port mapping protocol of the gateway, such as UPnP or NAT-PMP.
+    PortMapping,
+    /// Configured explicitly.
+    Static,
+}
+
+/// A candidate external address and the confidence in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressCandidate {
+    /// The candidate address.
+    pub address: Multiaddr,
+    /// The most trusted source the address has been reported by.
+    pub source: AddressSource,
+    /// Number of distinct remotes that confirmed the address.
+    pub confirmations: usize,
+    /// Whether the address is advertised to other peers.
+    pub confirmed: bool,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    source: AddressSource,
+    /// The remotes that observed the address.
+    confirmed_by: HashSet<PeerId>,
+    /// The listen addresses the address has been derived from. The candidate expires along with
+    /// them, unless it is static.
+    listen_addrs: SmallVec<[Multiaddr; 4]>,
+}
+
+/// The candidate external addresses of the swarm.
+#[derive(Debug)]
+pub(crate) struct ExternalCandidates {
+    candidates: HashMap<Multiaddr, Candidate>,
+    min_confirmations: usize,
+}
+
+impl Default for ExternalCandidates {
+    fn default() -> Self {
+        ExternalCandidates::new(DEFAULT_MIN_CONFIRMATIONS)
+    }
+}
+
+impl ExternalCandidates {
+    pub(crate) fn new(min_confirmations: usize) -> Self {
+        ExternalCandidates { candidates: HashMap::new(), min_confirmations }
+    }
+
+    /// Reports a candidate, derived from `listen_addr` and confirmed by `observer` if known.
+    ///
+    /// Returns whether the candidate is confirmed.
+    pub(crate) fn report(
+        &mut self,
+        addr: Multiaddr,
+        source: AddressSource,
+        listen_addr: Option<&Multiaddr>,
+        observer: Option<&PeerId>,
+    ) -> bool {
+        if !self.candidates.contains_key(&addr) && source == AddressSource::Observed {
+            self.evict_unconfirmed();
+        }
+        let min_confirmations = self.min_confirmations;
+        let candidate = self.candidates.entry(addr).or_insert_with(|| Candidate {
+            source,
+            confirmed_by: HashSet::new(),
+            listen_addrs: SmallVec::new(),
+        });
+        candidate.source = candidate.source.max(source);
+        if let Some(observer) = observer {
+            candidate.confirmed_by.insert(observer.clone());
+        }
+        if let Some(listen_addr) = listen_addr {
+            if !candidate.listen_addrs.contains(listen_addr) {
+                candidate.listen_addrs.push(listen_addr.clone());
+            }
+        }
+        candidate.is_confirmed(min_confirmations)
+    }
+
+    /// Forgets about a candidate. Returns its source if it was known.
+    pub(crate) fn remove(&mut self, addr: &Multiaddr) -> Option<AddressSource> {
+        self.candidates.remove(addr).map(|c| c.source)
+    }
+
+    /// Returns the source of a candidate, if known.
+    pub(crate) fn source(&self, addr: &Multiaddr) -> Option<AddressSource> {
+        self.candidates.get(addr).map(|c| c.source)
+    }
+
+    /// Forgets about a listen address, and returns the candidates that were only derived from it.
+    pub(crate) fn expire_listen_addr(&mut self, listen_addr: &Multiaddr) -> Vec<Multiaddr> {
+        let mut expired = Vec::new();
+        self.candidates.retain(|addr, candidate| {
+            if candidate.source == AddressSource::Static || !candidate.listen_addrs.contains(listen_addr) {
+                return true
+            }
+            candidate.listen_addrs.retain(|a| a != listen_addr);
+            if candidate.listen_addrs.is_empty() {
+                expired.push(addr.clone());
+            }
+            !candidate.listen_addrs.is_empty()
+        });
+        expired
+    }
+
+    /// Forgets about the confirmed candidates for which `f` returns `false`.
+    pub(crate) fn retain_confirmed(&mut self, mut f: impl FnMut(&Multiaddr) -> bool) {
+        let min_confirmations = self.min_confirmations;
+        self.candidates.retain(|addr, c| !c.is_confirmed(min_confirmations) || f(addr));
+    }
+
+    /// Returns all the candidates, the most confirmed first.
+    pub(crate) fn list(&self) -> Vec<AddressCandidate> {
+        let mut list = self.candidates.iter()
+            .map(|(addr, c)| AddressCandidate {
+                address: addr.clone(),
+                source: c.source,
+                confirmations: c.confirmed_by.len(),
+                confirmed: c.is_confirmed(self.min_confirmations),
+            })
+            .collect::<Vec<_>>();
+        list.sort_by_key(|c| std::cmp::Reverse((c.confirmed, c.confirmations)));
+        list
+    }
+
+    /// Makes room for a new candidate by forgetting about the least confirmed unconfirmed one.
+    fn evict_unconfirmed(&mut self) {
+        let min_confirmations = self.min_confirmations;
+        let unconfirmed = self.candidates.iter()
+            .filter(|(_, c)| !c.is_confirmed(min_confirmations));
+        if unconfirmed.clone().count() < MAX_UNCONFIRMED {
+            return
+        }
+        let least = unconfirmed
+            .min_by_key(|(_, c)| c.confirmed_by.len())
+            .map(|(addr, _)| addr.clone());
+        if let Some(addr) = least {
+            self.candidates.remove(&addr);
+        }
+    }
+}
+
+impl Candidate {
+    fn is_confirmed(&self, min_confirmations: usize) -> bool {
+        self.source != AddressSource::Observed || self.confirmed_by.len() >= min_confirmations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn observed_addresses_need_distinct_confirmations() {
+        let listen = addr("/ip4/10.0.0.1/tcp/4001");
+        let external = addr("/ip4/1.2.3.4/tcp/4001");
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let mut candidates = ExternalCandidates::default();
+
+        assert!(!candidates.report(external.clone(), AddressSource::Observed, Some(&listen), Some(&a)));
+        assert!(!candidates.report(external.clone(), AddressSource::Observed, Some(&listen), Some(&a)));
+        assert!(!candidates.report(external.clone(), AddressSource::Observed, Some(&listen), None));
+        assert!(candidates.report(external.clone(), AddressSource::Observed, Some(&listen), Some(&b)));
+
+        let list = candidates.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].confirmations, 2);
+        assert!(list[0].confirmed);
+
+        assert_eq!(candidates.expire_listen_addr(&listen), vec![external]);
+        assert!(candidates.list().is_empty());
+    }
+
+    #[test]
+    fn trusted_sources_are_confirmed() {
+        let listen = addr("/ip4/10.0.0.1/tcp/4001");
+        let mapped = addr("/ip4/1.2.3.4/tcp/5001");
+        let configured = addr("/dns4/example.com/tcp/4001");
+        let mut candidates = ExternalCandidates::default();
+
+        assert!(candidates.report(mapped.clone(), AddressSource::PortMapping, Some(&listen), None));
+        assert!(candidates.report(configured.clone(), AddressSource::Static, None, None));
+        // An observation doesn't lower the trust in a candidate.
+        assert!(candidates.report(configured.clone(), AddressSource::Observed, Some(&listen), None));
+        assert_eq!(candidates.source(&configured), Some(AddressSource::Static));
+
+        assert_eq!(candidates.expire_listen_addr(&listen), vec![mapped]);
+        assert_eq!(candidates.source(&configured), Some(AddressSource::Static));
+    }
+
+    #[test]
+    fn unconfirmed_candidates_are_bounded() {
+        let mut candidates = ExternalCandidates::default();
+        for port in 0 .. 2 * MAX_UNCONFIRMED {
+            let addr = addr(&format!("/ip4/1.2.3.4/tcp/{}", port));
+            candidates.report(addr, AddressSource::Observed, None, Some(&PeerId::random()));
+        }
+        assert_eq!(candidates.list().len(), MAX_UNCONFIRMED);
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Single place to allow or deny connections.
+//!
+//! A [`ConnectionGater`] set on the swarm is consulted at each stage of the life of a
+//! connection: before dialing an address, when a remote connects to one of our listeners, and
+//! once the remote has been authenticated. Connections denied by the gater produce a
+//! [`SwarmEvent::ConnectionGated`](crate::SwarmEvent::ConnectionGated).
+//!
+//! The gater is given the [`ConnectionTags`] of the swarm when it is installed, which lets it
+//! base its decisions on the labels of the established connections, for example to limit the
+//! number of relayed connections.
+
+use crate::connection_tags::ConnectionTags;
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId, multiaddr::Protocol};
+use std::net::IpAddr;
+
+/// Decides which connections the swarm is allowed to open or accept.
+///
+/// All the methods allow the connection by default.
+pub trait ConnectionGater: Send + 'static {
+    /// Called before dialing `address`, with the peer we are trying to reach if known.
+    fn allow_dial(&mut self, _peer_id: Option<&PeerId>, _address: &Multiaddr) -> bool {
+        true
+    }
+
+    /// Called when a remote connects to one of our listeners, before the connection is upgraded.
+    ///
+    /// The IP address of the remote, if any, can be obtained with [`remote_ip`].
+    fn allow_accept(&mut self, _local_addr: &Multiaddr, _send_back_addr: &Multiaddr) -> bool {
+        true
+    }
+
+    /// Called once the remote of a dialed or accepted connection has been authenticated, before
+    /// the `NetworkBehaviour` is notified of the connection.
+    fn allow_secured(&mut self, _peer_id: &PeerId, _endpoint: &ConnectedPoint) -> bool {
+        true
+    }
+
+    /// Called when the gater is installed on a swarm, with the handle to the labels and data of
+    /// the connections of the swarm.
+    fn inject_connection_tags(&mut self, _tags: ConnectionTags) {}
+}
+
+/// Returns the IP address at the beginning of `addr`, if any.
+pub fn remote_ip(addr: &Multiaddr) -> Option<IpAddr> {
+    match addr.iter().next()? {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::remote_ip;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn extracts_remote_ip() {
+        let v4 = "/ip4/10.0.0.1/tcp/4001".parse().unwrap();
+        assert_eq!(remote_ip(&v4), Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+        let v6 = "/ip6/::1/udp/1/quic".parse().unwrap();
+        assert_eq!(remote_ip(&v6), Some(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+        assert_eq!(remote_ip(&"/dns4/example.com/tcp/1".parse().unwrap()), None);
+        assert_eq!(remote_ip(&"/memory/5".parse().unwrap()), None);
+    }
+}
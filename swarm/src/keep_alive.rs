@@ -0,0 +1,141 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Swarm-wide policy deciding how long idle connections are kept alive.
+//!
+//! Each `ProtocolsHandler` tells through [`ProtocolsHandler::connection_keep_alive`] whether
+//! it is interested in the connection, and the handlers combined by a `NetworkBehaviour` keep it
+//! alive while any of them is. The [`KeepAlivePolicy`] of the swarm applies on top of that: it
+//! can keep idle connections open for a while before closing them, or keep the connections with
+//! some peers open for good.
+//!
+//! [`ProtocolsHandler::connection_keep_alive`]: crate::ProtocolsHandler::connection_keep_alive
+
+use crate::protocols_handler::KeepAlive;
+use libp2p_core::PeerId;
+use std::{collections::HashMap, time::Duration};
+use wasm_timer::Instant;
+
+/// How long the connections of the swarm are kept alive once no handler is interested in them.
+///
+/// The policy is applied to the connections established after it has been set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeepAlivePolicy {
+    idle_timeout: Duration,
+    peers: HashMap<PeerId, PeerKeepAlive>,
+}
+
+/// Keep-alive of the connections with a specific peer, overriding the default of the policy.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PeerKeepAlive {
+    /// Keep the connection alive, even if no handler is interested in it.
+    Always,
+    /// Close the connection once no handler has been interested in it for the given duration.
+    IdleTimeout(Duration),
+}
+
+impl KeepAlivePolicy {
+    /// Creates a policy closing the connections as soon as no handler is interested in them.
+    pub fn new() -> Self {
+        KeepAlivePolicy::default()
+    }
+
+    /// Sets the duration during which connections no handler is interested in are kept open.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Overrides the keep-alive of the connections with the given peer.
+    pub fn peer(mut self, peer_id: PeerId, keep_alive: PeerKeepAlive) -> Self {
+        self.peers.insert(peer_id, keep_alive);
+        self
+    }
+
+    /// Removes the override of the keep-alive of the connections with the given peer.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) -> Option<PeerKeepAlive> {
+        self.peers.remove(peer_id)
+    }
+
+    /// Returns the keep-alive of the connections with the given peer.
+    pub fn for_peer(&self, peer_id: &PeerId) -> PeerKeepAlive {
+        self.peers.get(peer_id).cloned().unwrap_or(PeerKeepAlive::IdleTimeout(self.idle_timeout))
+    }
+}
+
+/// The keep-alive policy applied to a single connection.
+#[derive(Debug)]
+pub(crate) struct ConnectionKeepAlive {
+    policy: PeerKeepAlive,
+    /// Since when no handler has been interested in the connection.
+    idle_since: Option<Instant>,
+}
+
+impl ConnectionKeepAlive {
+    pub(crate) fn new(policy: PeerKeepAlive) -> Self {
+        ConnectionKeepAlive { policy, idle_since: None }
+    }
+
+    /// Combines the keep-alive of the handler with the policy.
+    pub(crate) fn apply(&mut self, handler: KeepAlive) -> KeepAlive {
+        match (self.policy, handler) {
+            (PeerKeepAlive::Always, _) => KeepAlive::Yes,
+            (PeerKeepAlive::IdleTimeout(timeout), KeepAlive::No) if timeout > Duration::from_secs(0) => {
+                let idle_since = *self.idle_since.get_or_insert_with(Instant::now);
+                KeepAlive::Until(idle_since + timeout)
+            }
+            (_, keep_alive) => {
+                if keep_alive != KeepAlive::No {
+                    self.idle_since = None;
+                }
+                keep_alive
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_applies_to_idle_connections() {
+        let always = PeerId::random();
+        let policy = KeepAlivePolicy::new()
+            .idle_timeout(Duration::from_secs(10))
+            .peer(always.clone(), PeerKeepAlive::Always);
+
+        let mut conn = ConnectionKeepAlive::new(policy.for_peer(&always));
+        assert_eq!(conn.apply(KeepAlive::No), KeepAlive::Yes);
+
+        let mut conn = ConnectionKeepAlive::new(policy.for_peer(&PeerId::random()));
+        assert_eq!(conn.apply(KeepAlive::Yes), KeepAlive::Yes);
+        let idle = match conn.apply(KeepAlive::No) {
+            KeepAlive::Until(t) => t,
+            other => panic!("unexpected keep-alive: {:?}", other),
+        };
+        // The idle period doesn't restart while the connection stays idle.
+        assert_eq!(conn.apply(KeepAlive::No), KeepAlive::Until(idle));
+        assert_eq!(conn.apply(KeepAlive::Yes), KeepAlive::Yes);
+
+        let mut conn = ConnectionKeepAlive::new(KeepAlivePolicy::new().for_peer(&always));
+        assert_eq!(conn.apply(KeepAlive::No), KeepAlive::No);
+    }
+}
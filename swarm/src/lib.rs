@@ -54,9 +54,23 @@
 //!
 
 mod behaviour;
+mod observed;
 mod registry;
 
+pub mod backoff;
+mod bans;
+pub mod capabilities;
+pub mod connection_manager;
+pub mod connection_tags;
+pub mod dial_failure;
+pub mod dynamic;
+pub mod external;
+pub mod gater;
+pub mod keep_alive;
+pub mod peer_store;
 pub mod protocols_handler;
+pub mod rate_limit;
+pub mod subscription;
 pub mod toggle;
 
 pub use behaviour::{
@@ -76,12 +90,26 @@ pub use protocols_handler::{
     OneShotHandler,
     SubstreamProtocol
 };
+pub use backoff::{BackoffConfig, DialBackoff};
+pub use bans::{Ban, BanTarget};
+pub use capabilities::{ProtocolCapabilities, ProtocolSupport};
+pub use connection_tags::ConnectionTags;
+pub use dial_failure::{AddressFailure, ConnectionStage, FailureKind, Refusal, StagedError};
+pub use dynamic::{BehaviourId, DynamicBehaviour};
+pub use external::{AddressCandidate, AddressSource};
+pub use gater::ConnectionGater;
+pub use keep_alive::{KeepAlivePolicy, PeerKeepAlive};
+pub use peer_store::PeerStore;
+pub use rate_limit::{InboundRateLimits, RateLimit};
+pub use subscription::{Subscription, SwarmNotification};
+pub use libp2p_core::nodes::network::{ConnectionLimit, ConnectionLimits, DialHandle, DialOpts, PeerCondition};
 
 use protocols_handler::{NodeHandlerWrapperBuilder, NodeHandlerWrapperError};
 use futures::prelude::*;
 use libp2p_core::{
-    Transport, Multiaddr, Negotiated, PeerId, InboundUpgrade, OutboundUpgrade, UpgradeInfo, ProtocolName,
-    muxing::StreamMuxer,
+    ConnectedPoint, Transport, Multiaddr, Negotiated, PeerId, InboundUpgrade, OutboundUpgrade, UpgradeInfo, ProtocolName,
+    AddressFilter, address_translation,
+    muxing::{MuxerStats, StreamMuxer},
     nodes::{
         ListenerId,
         collection::ConnectionInfo,
@@ -90,10 +118,16 @@ use libp2p_core::{
     },
     transport::TransportError
 };
+use bans::Bans;
+use external::ExternalCandidates;
+use observed::{ObservedAddrs, PortMapping};
+use rate_limit::InboundRateLimiter;
 use registry::{Addresses, AddressIntoIter};
+use subscription::Subscribers;
 use smallvec::SmallVec;
-use std::{error, fmt, ops::{Deref, DerefMut}, pin::Pin, task::{Context, Poll}};
-use std::collections::HashSet;
+use wasm_timer::Delay;
+use std::{error, fmt, ops::{Deref, DerefMut}, pin::Pin, sync::Arc, task::{Context, Poll}, time::Duration};
+use std::collections::HashMap;
 
 /// Contains the state of the network, plus the way it should behave.
 pub type Swarm<TTransport, TBehaviour, TConnInfo = PeerId> = ExpandedSwarm<
@@ -112,7 +146,14 @@ pub enum SwarmEvent<TBvEv> {
     /// Event generated by the `NetworkBehaviour`.
     Behaviour(TBvEv),
     /// We are now connected to the given peer.
-    Connected(PeerId),
+    Connected {
+        /// The peer we are connected to.
+        peer_id: PeerId,
+        /// The address we dialed, or the addresses of the incoming connection.
+        endpoint: ConnectedPoint,
+        /// The tag of the [`DialOpts`] of the dial that established the connection, if any.
+        tag: Option<u64>,
+    },
     /// We are now disconnected from the given peer.
     Disconnected(PeerId),
     /// One of our listeners has reported a new local listening address.
@@ -126,11 +167,61 @@ pub enum SwarmEvent<TBvEv> {
         peer_id: Option<PeerId>,
         /// Address that we failed to reach.
         address: Multiaddr,
-        /// Error that has been encountered.
-        error: Box<dyn error::Error + Send>,
+        /// Why the address couldn't be reached.
+        error: AddressFailure,
+        /// The tag of the [`DialOpts`] of the dial, if any.
+        tag: Option<u64>,
+    },
+    /// A dial has been cancelled through its `DialHandle`.
+    DialCancelled {
+        /// `PeerId` that we were trying to reach. `None` if we don't know in advance which peer
+        /// we were trying to reach.
+        peer_id: Option<PeerId>,
+        /// Address that was being dialed.
+        address: Multiaddr,
+        /// The tag of the [`DialOpts`] of the dial, if any.
+        tag: Option<u64>,
+    },
+    /// A connection has been denied by the [`ConnectionLimits`] of the swarm.
+    ConnectionDenied {
+        /// `PeerId` that we were trying to reach. `None` for incoming connections and if we
+        /// don't know in advance which peer we were trying to reach.
+        peer_id: Option<PeerId>,
+        /// The address being dialed, or the addresses of the incoming connection.
+        endpoint: ConnectedPoint,
+        /// The limit that has been reached.
+        limit: ConnectionLimit,
+        /// The tag of the [`DialOpts`] of the dial, if any.
+        tag: Option<u64>,
+    },
+    /// A connection has been denied by the [`ConnectionGater`] of the swarm.
+    ConnectionGated {
+        /// The peer of the connection. `None` if the connection was denied before the remote was
+        /// authenticated and we didn't know in advance which peer we were trying to reach.
+        peer_id: Option<PeerId>,
+        /// The address being dialed, or the addresses of the connection.
+        endpoint: ConnectedPoint,
+        /// The tag of the [`DialOpts`] of the dial, if any.
+        tag: Option<u64>,
     },
     /// Startng to try to reach the given peer.
     StartConnect(PeerId),
+    /// An inbound substream has been reset because the peer exceeded the rate limit of its
+    /// protocol, see [`SwarmBuilder::inbound_rate_limits`].
+    InboundSubstreamRateLimited {
+        /// The peer that opened the substream.
+        peer_id: PeerId,
+        /// The name of the negotiated protocol.
+        protocol: Vec<u8>,
+    },
+    /// The statistics of the muxer of a connection, as requested with
+    /// [`ExpandedSwarm::request_connection_stats`].
+    ConnectionStats {
+        /// The peer the connection is with.
+        peer_id: PeerId,
+        /// The statistics.
+        stats: MuxerStats,
+    },
 }
 
 /// Contains the state of the network, plus the way it should behave.
@@ -162,11 +253,62 @@ where
     /// similar mechanisms.
     external_addrs: Addresses,
 
-    /// List of nodes for which we deny any incoming connection.
-    banned_peers: HashSet<PeerId>,
+    /// Candidates for the external addresses, with the confidence in them. Only the confirmed
+    /// candidates are part of `external_addrs`.
+    external_candidates: ExternalCandidates,
+
+    /// The addresses connected remotes observed for us.
+    observed_addrs: ObservedAddrs,
+
+    /// Peers and subnets with which connections are denied.
+    bans: Bans,
+
+    /// Filter that addresses obtained from the behaviour must pass before being dialed or
+    /// reported as external addresses.
+    address_filter: AddressFilter,
+
+    /// Decides which connections are allowed, if any.
+    gater: Option<Box<dyn ConnectionGater>>,
+
+    /// Information known about remote peers, fed with the outcome of the dials.
+    peer_store: PeerStore,
+
+    /// Labels and data attached to the established connections.
+    connection_tags: ConnectionTags,
+
+    /// The protocols supported by the connected peers.
+    protocol_capabilities: ProtocolCapabilities,
+
+    /// Peers and addresses not to dial again for now, after their dials failed.
+    backoff: DialBackoff,
+
+    /// How long the new connections are kept alive once no handler is interested in them.
+    keep_alive: Arc<KeepAlivePolicy>,
+
+    /// Applies the rate limits of the inbound substreams, if any.
+    rate_limiter: Option<InboundRateLimiter>,
+
+    /// Tells at which stage of the establishment of a connection an error of the transport
+    /// happened.
+    dial_error_classifier: dial_failure::Classifier<TTransport::Error>,
+
+    /// True once [`ExpandedSwarm::shutdown`] has been called.
+    shutting_down: bool,
+
+    /// The tags of the ongoing dials of known peers.
+    dial_tags: HashMap<PeerId, u64>,
+
+    /// The tags of the ongoing dials of addresses, for which the peer is not known in advance.
+    addr_dial_tags: HashMap<Multiaddr, u64>,
 
     /// Pending event message to be delivered.
-    send_event_to_complete: Option<(PeerId, TInEvent)>
+    send_event_to_complete: Option<(PeerId, TInEvent)>,
+
+    /// Peers whose connection statistics have been requested but not sent to the connection yet.
+    stats_requests: SmallVec<[PeerId; 4]>,
+
+    /// The subscriptions to the events of the swarm.
+    subscribers: Subscribers,
 }
 
 impl<TTransport, TBehaviour, TInEvent, TOutEvent, THandler, THandlerErr, TConnInfo> Deref for
@@ -256,29 +398,119 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
 
     /// Tries to dial the given address.
     ///
-    /// Returns an error if the address is not supported.
-    pub fn dial_addr(me: &mut Self, addr: Multiaddr) -> Result<(), TransportError<TTransport::Error>> {
-        let handler = me.behaviour.new_handler();
-        me.network.dial(addr, handler.into_node_handler_builder())
+    /// Returns an error if the address is not supported or if the [`ConnectionGater`] denies the
+    /// dial.
+    pub fn dial_addr(me: &mut Self, addr: Multiaddr) -> Result<(), DialError<TTransport::Error>> {
+        ExpandedSwarm::dial_addr_with_opts(me, addr, DialOpts::default()).map(|_| ())
+    }
+
+    /// Same as `dial_addr`, but applies the given options to the dial and returns a handle to
+    /// cancel it.
+    ///
+    /// A cancelled dial produces a `SwarmEvent::DialCancelled`. The condition and the addresses
+    /// of the options do not apply.
+    pub fn dial_addr_with_opts(me: &mut Self, addr: Multiaddr, opts: DialOpts)
+        -> Result<DialHandle, DialError<TTransport::Error>>
+    {
+        if me.bans.is_addr_banned(&addr) {
+            return Err(DialError::Refused(Refusal::Banned))
+        }
+        if let Some(gater) = me.gater.as_mut() {
+            if !gater.allow_dial(None, &addr) {
+                return Err(DialError::Refused(Refusal::Gated))
+            }
+        }
+        let handler = me.behaviour.new_handler().into_node_handler_builder()
+            .with_keep_alive_policy(me.keep_alive.clone())
+            .with_inbound_rate_limiter(me.rate_limiter.clone())
+            .with_protocol_capabilities(me.protocol_capabilities.clone());
+        let tag = opts.get_tag();
+        let handle = me.network.dial_with_opts(addr.clone(), handler, opts)
+            .map_err(DialError::Transport)?;
+        if let Some(tag) = tag {
+            me.addr_dial_tags.insert(addr, tag);
+        }
+        Ok(handle)
     }
 
     /// Tries to reach the given peer using the elements in the topology.
     ///
-    /// Has no effect if we are already connected to that peer, or if no address is known for the
-    /// peer.
+    /// The addresses of the peer are obtained from the `NetworkBehaviour` and from the
+    /// [`PeerStore`]. Has no effect if we are already connected to that peer, or if no address is
+    /// known for the peer. Peers and addresses backed off by the [`DialBackoff`] are not dialed.
     pub fn dial(me: &mut Self, peer_id: PeerId) {
-        let addrs = me.behaviour.addresses_of_peer(&peer_id);
+        ExpandedSwarm::dial_with_opts(me, peer_id, DialOpts::default());
+    }
+
+    /// Same as `dial`, but applies the given options to the dial and returns a handle to
+    /// cancel it.
+    ///
+    /// Addresses given through [`DialOpts::addresses`] are dialed instead of the known addresses
+    /// of the peer, and are not subject to the address filter and the back-off. If we are already
+    /// trying to reach the peer, the addresses are appended to the ongoing dial, whose handle is
+    /// returned, unless the condition is [`PeerCondition::NotDialing`]; the other options are
+    /// ignored. Returns `None` if we are already connected to that peer, or if no address is known
+    /// for the peer. A cancelled dial produces a `SwarmEvent::DialCancelled`.
+    pub fn dial_with_opts(me: &mut Self, peer_id: PeerId, opts: DialOpts) -> Option<DialHandle> {
+        if me.bans.is_peer_banned(&peer_id) {
+            me.behaviour.inject_dial_failure(&peer_id);
+            return None
+        }
+        let filter = &me.address_filter;
+        let gater = &mut me.gater;
+        let backoff = &me.backoff;
+        let bans = &me.bans;
+        let addrs = match opts.get_addresses() {
+            Some(addrs) => addrs.iter()
+                .filter(|addr| !bans.is_addr_banned(addr))
+                .cloned()
+                .collect::<Vec<_>>(),
+            None => {
+                let mut addrs = me.behaviour.addresses_of_peer(&peer_id);
+                for addr in me.peer_store.addresses(&peer_id) {
+                    if !addrs.contains(&addr) {
+                        addrs.push(addr);
+                    }
+                }
+                addrs.into_iter()
+                    .filter(|addr| filter.allows(addr) && !backoff.is_address_backed_off(addr) && !bans.is_addr_banned(addr))
+                    .collect()
+            }
+        };
+        let addrs = addrs.into_iter()
+            .filter(|addr| gater.as_mut().map_or(true, |g| g.allow_dial(Some(&peer_id), addr)))
+            .collect::<Vec<_>>();
         match me.network.peer(peer_id.clone()) {
+            network::Peer::NotConnected(_) if me.backoff.is_peer_backed_off(&peer_id) => {
+                log::debug!("Not dialing backed off peer {:?}", peer_id);
+                me.behaviour.inject_dial_failure(&peer_id);
+                None
+            },
             network::Peer::NotConnected(peer) => {
-                let handler = me.behaviour.new_handler().into_node_handler_builder();
-                if peer.connect_iter(addrs, handler).is_err() {
-                    me.behaviour.inject_dial_failure(&peer_id);
+                let handler = me.behaviour.new_handler().into_node_handler_builder()
+                    .with_keep_alive_policy(me.keep_alive.clone())
+                    .with_inbound_rate_limiter(me.rate_limiter.clone())
+                    .with_protocol_capabilities(me.protocol_capabilities.clone());
+                let tag = opts.get_tag();
+                match peer.connect_iter_with_opts(addrs, handler, opts) {
+                    Ok(peer) => {
+                        if let Some(tag) = tag {
+                            me.dial_tags.insert(peer_id, tag);
+                        }
+                        Some(peer.dial_handle())
+                    },
+                    Err(_) => {
+                        me.behaviour.inject_dial_failure(&peer_id);
+                        None
+                    }
                 }
             },
+            network::Peer::PendingConnect(_) if opts.get_condition() == PeerCondition::NotDialing => None,
             network::Peer::PendingConnect(mut peer) => {
-                peer.append_multiaddr_attempts(addrs)
+                peer.append_multiaddr_attempts(addrs);
+                Some(peer.dial_handle())
             },
-            network::Peer::Connected(_) | network::Peer::LocalNode => {}
+            network::Peer::Connected(_) | network::Peer::LocalNode => None
         }
     }
 
@@ -301,11 +533,105 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
     /// Adds an external address.
     ///
     /// An external address is an address we are listening on but that accounts for things such as
-    /// NAT traversal.
+    /// NAT traversal. Explicitly added addresses are advertised right away and do not expire with
+    /// listen addresses.
     pub fn add_external_address(me: &mut Self, addr: Multiaddr) {
+        me.external_candidates.report(addr.clone(), AddressSource::Static, None, None);
+        me.external_addrs.add(addr)
+    }
+
+    /// Adds the external address a port mapping protocol of the gateway, such as UPnP or
+    /// NAT-PMP, maps to one of our listen addresses.
+    ///
+    /// The address is advertised right away and expires along with `listen_addr`, or when removed
+    /// with [`ExpandedSwarm::remove_external_address`] once the mapping expires.
+    pub fn add_mapped_address(me: &mut Self, listen_addr: &Multiaddr, addr: Multiaddr) {
+        me.external_candidates.report(addr.clone(), AddressSource::PortMapping, Some(listen_addr), None);
+        if me.external_addrs.iter().all(|a| *a != addr) {
+            me.behaviour.inject_new_external_addr(&addr);
+        }
         me.external_addrs.add(addr)
     }
 
+    /// Removes an external address, whatever its source.
+    ///
+    /// Returns `true` if the address was advertised.
+    pub fn remove_external_address(me: &mut Self, addr: &Multiaddr) -> bool {
+        me.external_candidates.remove(addr);
+        let removed = me.external_addrs.remove(addr);
+        if removed {
+            me.behaviour.inject_expired_external_addr(addr);
+        }
+        removed
+    }
+
+    /// Returns the candidates for the external addresses, the most confirmed first.
+    ///
+    /// Addresses observed by remotes are only advertised once enough distinct remotes confirmed
+    /// them. See [`SwarmBuilder::external_address_confirmations`].
+    pub fn external_address_candidates(me: &Self) -> Vec<AddressCandidate> {
+        me.external_candidates.list()
+    }
+
+    /// Returns the filter that addresses learned through the behaviour must pass.
+    pub fn address_filter(me: &Self) -> &AddressFilter {
+        &me.address_filter
+    }
+
+    /// Replaces the filter that addresses learned through the behaviour must pass.
+    ///
+    /// The filter applies to the addresses the swarm dials on behalf of the behaviour and to
+    /// observed addresses, and is passed on to the behaviour. Addresses explicitly given to
+    /// [`ExpandedSwarm::dial_addr`] or [`ExpandedSwarm::add_external_address`] are not filtered.
+    pub fn set_address_filter(me: &mut Self, filter: AddressFilter) {
+        me.behaviour.inject_address_filter(&filter);
+        me.address_filter = filter;
+    }
+
+    /// Returns the store of the information known about remote peers.
+    ///
+    /// The store can be cloned to be shared, for example with the `NetworkBehaviour`.
+    pub fn peer_store(me: &Self) -> &PeerStore {
+        &me.peer_store
+    }
+
+    /// Returns the back-off state of the dials.
+    ///
+    /// The state can be cloned to be shared, for example with the `NetworkBehaviour`.
+    pub fn dial_backoff(me: &Self) -> &DialBackoff {
+        &me.backoff
+    }
+
+    /// Returns the policy deciding how long idle connections are kept alive.
+    pub fn keep_alive_policy(me: &Self) -> &KeepAlivePolicy {
+        &me.keep_alive
+    }
+
+    /// Sets the policy deciding how long idle connections are kept alive.
+    ///
+    /// Existing connections are not affected.
+    pub fn set_keep_alive_policy(me: &mut Self, policy: KeepAlivePolicy) {
+        me.keep_alive = Arc::new(policy);
+    }
+
+    /// Sets the [`ConnectionGater`] deciding which connections are allowed.
+    ///
+    /// Existing connections are not affected.
+    pub fn set_connection_gater(me: &mut Self, mut gater: impl ConnectionGater) {
+        gater.inject_connection_tags(me.connection_tags.clone());
+        me.gater = Some(Box::new(gater));
+    }
+
+    /// Returns the handle to the labels and data attached to the established connections.
+    pub fn connection_tags(me: &Self) -> &ConnectionTags {
+        &me.connection_tags
+    }
+
+    /// Returns the table of the protocols supported by the connected peers.
+    pub fn protocol_capabilities(me: &Self) -> &ProtocolCapabilities {
+        &me.protocol_capabilities
+    }
+
     /// Returns the connection info of a node, or `None` if we're not connected to it.
     // TODO: should take &self instead of &mut self, but the API in network requires &mut
     pub fn connection_info(me: &mut Self, peer_id: &PeerId) -> Option<TConnInfo> {
@@ -316,20 +642,180 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         }
     }
 
-    /// Bans a peer by its peer ID.
+    /// Bans a peer by its peer ID, until it is unbanned.
     ///
     /// Any incoming connection and any dialing attempt will immediately be rejected.
-    /// This function has no effect is the peer is already banned.
     pub fn ban_peer_id(me: &mut Self, peer_id: PeerId) {
-        me.banned_peers.insert(peer_id.clone());
-        if let Some(c) = me.network.peer(peer_id).into_connected() {
-            c.close();
-        }
+        ExpandedSwarm::ban(me, BanTarget::Peer(peer_id), None)
     }
 
     /// Unbans a peer.
     pub fn unban_peer_id(me: &mut Self, peer_id: PeerId) {
-        me.banned_peers.remove(&peer_id);
+        ExpandedSwarm::unban(me, &BanTarget::Peer(peer_id));
+    }
+
+    /// Bans a peer or the IP addresses of a subnet, until `expires` if given.
+    ///
+    /// The existing connections that fall under the ban are closed, and any incoming connection
+    /// or dialing attempt that does will be rejected. Banning an already banned target replaces
+    /// the expiration of the ban.
+    pub fn ban(me: &mut Self, target: impl Into<BanTarget>, expires: Option<wasm_timer::Instant>) {
+        let target = target.into();
+        me.bans.insert(target.clone(), expires);
+        let peers = match target {
+            BanTarget::Peer(peer_id) => vec![peer_id],
+            BanTarget::Subnet(_) => me.network.connected_peers().cloned().collect(),
+        };
+        for peer_id in peers {
+            let banned = match me.network.peer(peer_id.clone()).into_connected() {
+                Some(c) => {
+                    let remote_addr = match c.endpoint() {
+                        ConnectedPoint::Dialer { address } => address,
+                        ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
+                    };
+                    me.bans.is_peer_banned(&peer_id) || me.bans.is_addr_banned(remote_addr)
+                },
+                None => false,
+            };
+            if banned {
+                me.close_connection(&peer_id);
+            }
+        }
+    }
+
+    /// Lifts a ban. Returns `true` if the ban was active.
+    pub fn unban(me: &mut Self, target: &BanTarget) -> bool {
+        me.bans.remove(target)
+    }
+
+    /// Returns the active bans.
+    pub fn bans(me: &Self) -> Vec<Ban> {
+        me.bans.list()
+    }
+
+    /// Requests the statistics of the muxer of the connection to a peer, such as the bytes sent
+    /// and received on each substream.
+    ///
+    /// The statistics are reported with a [`SwarmEvent::ConnectionStats`], unless the muxer of
+    /// the connection doesn't keep any. Returns `false` if we're not connected to the peer.
+    pub fn request_connection_stats(me: &mut Self, peer_id: PeerId) -> bool {
+        if me.network.peer(peer_id.clone()).into_connected().is_none() {
+            return false
+        }
+        if !me.stats_requests.contains(&peer_id) {
+            me.stats_requests.push(peer_id);
+        }
+        true
+    }
+
+    /// Gracefully shuts down the swarm.
+    ///
+    /// The listeners are closed, the `NetworkBehaviour` is notified with
+    /// [`NetworkBehaviour::inject_shutdown`], and neither incoming connections nor dials requested
+    /// by the behaviour are accepted anymore. The existing connections stay open for at most
+    /// `grace_period`, giving their in-flight substreams the time to finish, and close as soon
+    /// as their handlers no longer keep them alive. Once the grace period has elapsed, the
+    /// remaining connections are closed and the pending dials interrupted. Closing a connection
+    /// closes its muxer, which informs the remote.
+    ///
+    /// The returned future drives the swarm, discarding the events it produces, and resolves once
+    /// the connections are closed.
+    pub async fn shutdown(&mut self, grace_period: Duration) {
+        if !self.shutting_down {
+            self.shutting_down = true;
+            let listeners = self.network.listener_ids().collect::<Vec<_>>();
+            for id in listeners {
+                let _ = self.network.remove_listener(id);
+                self.behaviour.inject_listener_closed(id);
+            }
+            for addr in self.listened_addrs.clone() {
+                self.expire_listen_addr(&addr);
+            }
+            self.behaviour.inject_shutdown();
+        }
+
+        let mut deadline = Delay::new(grace_period);
+        future::poll_fn(move |cx| {
+            loop {
+                if self.is_drained() {
+                    return Poll::Ready(())
+                }
+                if Pin::new(&mut deadline).poll(cx).is_ready() {
+                    self.close_all();
+                    return Poll::Ready(())
+                }
+                if ExpandedSwarm::poll_next_event(Pin::new(&mut *self), cx).is_pending() {
+                    return Poll::Pending
+                }
+            }
+        }).await
+    }
+
+    /// Returns true if there is neither a connection nor a connection attempt in progress.
+    fn is_drained(&self) -> bool {
+        self.network.connected_peers().next().is_none()
+            && self.network.pending_connection_peers().next().is_none()
+            && self.network.unknown_dials().next().is_none()
+            && self.network.incoming_negotiated().next().is_none()
+    }
+
+    /// Closes all the connections and interrupts the dials of known peers.
+    fn close_all(&mut self) {
+        let connected = self.network.connected_peers().cloned().collect::<Vec<_>>();
+        for peer_id in connected {
+            self.close_connection(&peer_id);
+        }
+        let pending = self.network.pending_connection_peers().cloned().collect::<Vec<_>>();
+        for peer_id in pending {
+            if let Some(peer) = self.network.peer(peer_id.clone()).into_pending_connect() {
+                peer.interrupt();
+                self.behaviour.inject_dial_failure(&peer_id);
+            }
+        }
+        self.dial_tags.clear();
+        self.addr_dial_tags.clear();
+    }
+
+    /// Returns the tag of the dial of `address`, forgetting about it if the dial is over.
+    fn dial_tag(&mut self, peer_id: Option<&PeerId>, address: &Multiaddr, finished: bool) -> Option<u64> {
+        let tag = match peer_id {
+            Some(peer_id) if finished => self.dial_tags.remove(peer_id),
+            Some(peer_id) => self.dial_tags.get(peer_id).cloned(),
+            None => None,
+        };
+        // Dials of addresses consist of a single address.
+        tag.or_else(|| self.addr_dial_tags.remove(address))
+    }
+
+    /// Closes the connection to `peer_id`, if any, and forgets about it everywhere a connection
+    /// closed by the remote is forgotten, since the `Network` only reports the latter. Returns
+    /// whether we were connected to the peer.
+    fn close_connection(&mut self, peer_id: &PeerId) -> bool {
+        let peer = match self.network.peer(peer_id.clone()).into_connected() {
+            Some(peer) => peer,
+            None => return false,
+        };
+        let endpoint = peer.endpoint().clone();
+        peer.close();
+        self.observed_addrs.remove(peer_id);
+        if let Some(limiter) = self.rate_limiter.as_ref() {
+            limiter.remove_peer(peer_id);
+        }
+        self.connection_tags.disconnected(peer_id);
+        self.protocol_capabilities.disconnected(peer_id);
+        self.behaviour.inject_disconnected(peer_id, endpoint);
+        true
+    }
+
+    /// Forgets about a listen address and the external addresses derived from it.
+    fn expire_listen_addr(&mut self, listen_addr: &Multiaddr) {
+        self.listened_addrs.retain(|a| a != listen_addr);
+        self.behaviour.inject_expired_listen_addr(listen_addr);
+        for addr in self.external_candidates.expire_listen_addr(listen_addr) {
+            if self.external_addrs.remove(&addr) {
+                self.behaviour.inject_expired_external_addr(&addr);
+            }
+        }
     }
 
     /// Returns the next event that happens in the `Swarm`.
@@ -351,11 +837,34 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         }).await
     }
 
+    /// Subscribes to the connection lifecycle, listener and dial events of the `Swarm`.
+    ///
+    /// The events are delivered to the subscription while the `Swarm` is being polled. See the
+    /// [`subscription`] module.
+    pub fn subscribe(me: &mut Self) -> Subscription {
+        ExpandedSwarm::subscribe_with_buffer(me, subscription::DEFAULT_BUFFER)
+    }
+
+    /// Same as [`ExpandedSwarm::subscribe`], but the subscription buffers up to `buffer` events
+    /// instead of [`subscription::DEFAULT_BUFFER`].
+    pub fn subscribe_with_buffer(me: &mut Self, buffer: usize) -> Subscription {
+        me.subscribers.subscribe(buffer)
+    }
+
     /// Internal function used by everything event-related.
     ///
-    /// Polls the `Swarm` for the next event.
+    /// Polls the `Swarm` for the next event and broadcasts it to the subscriptions.
     fn poll_next_event(mut self: Pin<&mut Self>, cx: &mut Context)
         -> Poll<SwarmEvent<TBehaviour::OutEvent>>
+    {
+        let event = futures::ready!(ExpandedSwarm::poll_swarm_event(self.as_mut(), cx));
+        self.subscribers.notify(&event);
+        Poll::Ready(event)
+    }
+
+    /// Polls the `Swarm` for the next event.
+    fn poll_swarm_event(mut self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<SwarmEvent<TBehaviour::OutEvent>>
     {
         // We use a `this` variable because the compiler can't mutably borrow multiple times
         // across a `Deref`.
@@ -364,34 +873,135 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         loop {
             let mut network_not_ready = false;
 
+            if let Some(limiter) = this.rate_limiter.as_ref() {
+                if let Poll::Ready((peer_id, protocol)) = limiter.poll_denied(cx) {
+                    return Poll::Ready(SwarmEvent::InboundSubstreamRateLimited { peer_id, protocol });
+                }
+            }
+
             match this.network.poll(cx) {
                 Poll::Pending => network_not_ready = true,
                 Poll::Ready(NetworkEvent::NodeEvent { conn_info, event }) => {
                     this.behaviour.inject_node_event(conn_info.peer_id().clone(), event);
                 },
+                Poll::Ready(NetworkEvent::MuxerStats { conn_info, stats }) => {
+                    return Poll::Ready(SwarmEvent::ConnectionStats {
+                        peer_id: conn_info.peer_id().clone(),
+                        stats,
+                    });
+                },
                 Poll::Ready(NetworkEvent::Connected { conn_info, endpoint }) => {
-                    if this.banned_peers.contains(conn_info.peer_id()) {
+                    // The connection ends the dial of the peer, if any.
+                    let tag = match &endpoint {
+                        ConnectedPoint::Dialer { address } => this.dial_tag(Some(conn_info.peer_id()), address, true),
+                        ConnectedPoint::Listener { .. } => {
+                            this.dial_tags.remove(conn_info.peer_id());
+                            None
+                        },
+                    };
+                    let remote_addr = match &endpoint {
+                        ConnectedPoint::Dialer { address } => address,
+                        ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
+                    };
+                    if this.shutting_down
+                        || this.bans.is_peer_banned(conn_info.peer_id())
+                        || this.bans.is_addr_banned(remote_addr)
+                    {
                         this.network.peer(conn_info.peer_id().clone())
                             .into_connected()
                             .expect("the Network just notified us that we were connected; QED")
                             .close();
+                    } else if !this.gater.as_mut().map_or(true, |g| g.allow_secured(conn_info.peer_id(), &endpoint)) {
+                        this.network.peer(conn_info.peer_id().clone())
+                            .into_connected()
+                            .expect("the Network just notified us that we were connected; QED")
+                            .close();
+                        return Poll::Ready(SwarmEvent::ConnectionGated {
+                            peer_id: Some(conn_info.peer_id().clone()),
+                            endpoint,
+                            tag,
+                        });
                     } else {
-                        this.behaviour.inject_connected(conn_info.peer_id().clone(), endpoint);
-                        return Poll::Ready(SwarmEvent::Connected(conn_info.peer_id().clone()));
+                        this.backoff.reset(conn_info.peer_id(), match &endpoint {
+                            ConnectedPoint::Dialer { address } => Some(address),
+                            ConnectedPoint::Listener { .. } => None,
+                        });
+                        if let ConnectedPoint::Dialer { address } = &endpoint {
+                            this.peer_store.add_address(conn_info.peer_id(), address.clone(), peer_store::CONNECTED_TTL);
+                            this.peer_store.report_success(conn_info.peer_id(), address);
+                        }
+                        this.connection_tags.connected(conn_info.peer_id());
+                        this.protocol_capabilities.connected(conn_info.peer_id());
+                        this.behaviour.inject_connected(conn_info.peer_id().clone(), endpoint.clone());
+                        return Poll::Ready(SwarmEvent::Connected {
+                            peer_id: conn_info.peer_id().clone(),
+                            endpoint,
+                            tag,
+                        });
                     }
                 },
                 Poll::Ready(NetworkEvent::NodeClosed { conn_info, endpoint, error }) => {
                     log::trace!("Connection {:?} with endpoint {:?} closed by {:?}",
                                 conn_info, endpoint, error);
+                    this.observed_addrs.remove(conn_info.peer_id());
+                    if let Some(limiter) = this.rate_limiter.as_ref() {
+                        limiter.remove_peer(conn_info.peer_id());
+                    }
+                    this.connection_tags.disconnected(conn_info.peer_id());
+                    this.protocol_capabilities.disconnected(conn_info.peer_id());
                     this.behaviour.inject_disconnected(conn_info.peer_id(), endpoint);
                     return Poll::Ready(SwarmEvent::Disconnected(conn_info.peer_id().clone()));
                 },
                 Poll::Ready(NetworkEvent::Replaced { new_info, closed_endpoint, endpoint, .. }) => {
-                    this.behaviour.inject_replaced(new_info.peer_id().clone(), closed_endpoint, endpoint);
+                    let peer_id = new_info.peer_id().clone();
+                    let tag = match &endpoint {
+                        ConnectedPoint::Dialer { address } => this.dial_tag(Some(&peer_id), address, true),
+                        ConnectedPoint::Listener { .. } => None,
+                    };
+                    if !this.gater.as_mut().map_or(true, |g| g.allow_secured(&peer_id, &endpoint)) {
+                        // The behaviour only knows about the replaced connection.
+                        this.network.peer(peer_id.clone())
+                            .into_connected()
+                            .expect("the Network just notified us that we were connected; QED")
+                            .close();
+                        this.observed_addrs.remove(&peer_id);
+                        this.connection_tags.disconnected(&peer_id);
+                        this.protocol_capabilities.disconnected(&peer_id);
+                        this.behaviour.inject_disconnected(&peer_id, closed_endpoint);
+                        return Poll::Ready(SwarmEvent::ConnectionGated {
+                            peer_id: Some(peer_id),
+                            endpoint,
+                            tag,
+                        });
+                    }
+                    this.connection_tags.connected(&peer_id);
+                    this.protocol_capabilities.connected(&peer_id);
+                    this.behaviour.inject_replaced(peer_id, closed_endpoint, endpoint);
+                },
+                Poll::Ready(NetworkEvent::IncomingConnection(_)) if this.shutting_down => {
+                    // Dropping the incoming connection closes it.
+                },
+                Poll::Ready(NetworkEvent::IncomingConnection(incoming))
+                    if this.bans.is_addr_banned(incoming.send_back_addr()) =>
+                {
+                    log::debug!("Refusing connection from banned address {}", incoming.send_back_addr());
                 },
                 Poll::Ready(NetworkEvent::IncomingConnection(incoming)) => {
-                    let handler = this.behaviour.new_handler();
-                    incoming.accept(handler.into_node_handler_builder());
+                    let allowed = this.gater.as_mut()
+                        .map_or(true, |g| g.allow_accept(incoming.local_addr(), incoming.send_back_addr()));
+                    if !allowed {
+                        // Dropping the incoming connection closes it.
+                        let endpoint = ConnectedPoint::Listener {
+                            local_addr: incoming.local_addr().clone(),
+                            send_back_addr: incoming.send_back_addr().clone(),
+                        };
+                        return Poll::Ready(SwarmEvent::ConnectionGated { peer_id: None, endpoint, tag: None });
+                    }
+                    let handler = this.behaviour.new_handler().into_node_handler_builder()
+                        .with_keep_alive_policy(this.keep_alive.clone())
+                        .with_inbound_rate_limiter(this.rate_limiter.clone())
+                        .with_protocol_capabilities(this.protocol_capabilities.clone());
+                    incoming.accept(handler);
                 },
                 Poll::Ready(NetworkEvent::NewListenerAddress { listen_addr, .. }) => {
                     if !this.listened_addrs.contains(&listen_addr) {
@@ -401,32 +1011,93 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     return Poll::Ready(SwarmEvent::NewListenAddr(listen_addr));
                 }
                 Poll::Ready(NetworkEvent::ExpiredListenerAddress { listen_addr, .. }) => {
-                    this.listened_addrs.retain(|a| a != &listen_addr);
-                    this.behaviour.inject_expired_listen_addr(&listen_addr);
+                    this.expire_listen_addr(&listen_addr);
                     return Poll::Ready(SwarmEvent::ExpiredListenAddr(listen_addr));
                 }
                 Poll::Ready(NetworkEvent::ListenerClosed { listener_id, .. }) =>
                     this.behaviour.inject_listener_closed(listener_id),
                 Poll::Ready(NetworkEvent::ListenerError { listener_id, error }) =>
                     this.behaviour.inject_listener_error(listener_id, &error),
+                Poll::Ready(NetworkEvent::IncomingConnectionError {
+                    local_addr,
+                    send_back_addr,
+                    error: network::IncomingError::ConnectionDenied(limit)
+                }) => {
+                    return Poll::Ready(SwarmEvent::ConnectionDenied {
+                        peer_id: None,
+                        endpoint: ConnectedPoint::Listener { local_addr, send_back_addr },
+                        limit,
+                        tag: None,
+                    });
+                },
                 Poll::Ready(NetworkEvent::IncomingConnectionError { .. }) => {},
                 Poll::Ready(NetworkEvent::DialError { peer_id, multiaddr, error, new_state }) => {
+                    let finished = !matches!(new_state, network::PeerState::Dialing { .. });
+                    let tag = this.dial_tag(Some(&peer_id), &multiaddr, finished);
+                    if let network::NetworkReachError::Cancelled = error {
+                        if let network::PeerState::NotConnected = new_state {
+                            this.behaviour.inject_dial_failure(&peer_id);
+                        }
+                        return Poll::Ready(SwarmEvent::DialCancelled {
+                            peer_id: Some(peer_id),
+                            address: multiaddr,
+                            tag,
+                        });
+                    }
+                    if !matches!(error, network::NetworkReachError::ConnectionDenied(_)) {
+                        this.peer_store.report_failure(&peer_id, &multiaddr);
+                        this.backoff.address_failed(&multiaddr);
+                        if let network::PeerState::NotConnected = new_state {
+                            this.backoff.peer_failed(&peer_id);
+                        }
+                    }
                     this.behaviour.inject_addr_reach_failure(Some(&peer_id), &multiaddr, &error);
                     if let network::PeerState::NotConnected = new_state {
                         this.behaviour.inject_dial_failure(&peer_id);
                     }
+                    if let network::NetworkReachError::ConnectionDenied(limit) = error {
+                        return Poll::Ready(SwarmEvent::ConnectionDenied {
+                            peer_id: Some(peer_id),
+                            endpoint: ConnectedPoint::Dialer { address: multiaddr },
+                            limit,
+                            tag,
+                        });
+                    }
+                    let error = AddressFailure::from_reach_error(error, this.dial_error_classifier);
                     return Poll::Ready(SwarmEvent::UnreachableAddr {
                         peer_id: Some(peer_id.clone()),
                         address: multiaddr,
-                        error: Box::new(error),
+                        error,
+                        tag,
                     });
                 },
                 Poll::Ready(NetworkEvent::UnknownPeerDialError { multiaddr, error, .. }) => {
+                    let tag = this.dial_tag(None, &multiaddr, true);
+                    if let network::UnknownPeerDialErr::Cancelled = error {
+                        return Poll::Ready(SwarmEvent::DialCancelled {
+                            peer_id: None,
+                            address: multiaddr,
+                            tag,
+                        });
+                    }
+                    if !matches!(error, network::UnknownPeerDialErr::ConnectionDenied(_)) {
+                        this.backoff.address_failed(&multiaddr);
+                    }
                     this.behaviour.inject_addr_reach_failure(None, &multiaddr, &error);
+                    if let network::UnknownPeerDialErr::ConnectionDenied(limit) = error {
+                        return Poll::Ready(SwarmEvent::ConnectionDenied {
+                            peer_id: None,
+                            endpoint: ConnectedPoint::Dialer { address: multiaddr },
+                            limit,
+                            tag,
+                        });
+                    }
+                    let error = AddressFailure::from_unknown_peer_error(error, this.dial_error_classifier);
                     return Poll::Ready(SwarmEvent::UnreachableAddr {
                         peer_id: None,
                         address: multiaddr,
-                        error: Box::new(error),
+                        error,
+                        tag,
                     });
                 },
             }
@@ -444,12 +1115,24 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                 }
             }
 
+            // Send the pending requests for connection statistics.
+            while let Some(peer_id) = this.stats_requests.first().cloned() {
+                if let Some(mut peer) = this.network.peer(peer_id).into_connected() {
+                    match peer.poll_ready_event(cx) {
+                        Poll::Ready(()) => peer.start_request_muxer_stats(),
+                        Poll::Pending => break,
+                    }
+                }
+                this.stats_requests.remove(0);
+            }
+
             let behaviour_poll = {
                 let mut parameters = SwarmPollParameters {
                     local_peer_id: &mut this.network.local_peer_id(),
                     supported_protocols: &this.supported_protocols,
                     listened_addrs: &this.listened_addrs,
-                    external_addrs: &this.external_addrs
+                    external_addrs: &this.external_addrs,
+                    protocol_capabilities: &this.protocol_capabilities,
                 };
                 this.behaviour.poll(cx, &mut parameters)
             };
@@ -461,10 +1144,27 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     return Poll::Ready(SwarmEvent::Behaviour(event))
                 },
                 Poll::Ready(NetworkBehaviourAction::DialAddress { address }) => {
-                    let _ = ExpandedSwarm::dial_addr(&mut *this, address);
+                    if this.shutting_down {
+                        log::debug!("Not dialing {} while shutting down", address);
+                    } else if !this.address_filter.allows(&address) {
+                        log::debug!("Not dialing filtered address {}", address);
+                    } else if this.bans.is_addr_banned(&address) {
+                        log::debug!("Not dialing banned address {}", address);
+                    } else if this.backoff.is_address_backed_off(&address) {
+                        log::debug!("Not dialing backed off address {}", address);
+                    } else if let Err(DialError::Refused(Refusal::Gated)) = ExpandedSwarm::dial_addr(&mut *this, address.clone()) {
+                        return Poll::Ready(SwarmEvent::ConnectionGated {
+                            peer_id: None,
+                            endpoint: ConnectedPoint::Dialer { address },
+                            tag: None,
+                        });
+                    }
                 },
                 Poll::Ready(NetworkBehaviourAction::DialPeer { peer_id }) => {
-                    if this.banned_peers.contains(&peer_id) {
+                    if this.shutting_down
+                        || this.bans.is_peer_banned(&peer_id)
+                        || this.backoff.is_peer_backed_off(&peer_id)
+                    {
                         this.behaviour.inject_dial_failure(&peer_id);
                     } else {
                         ExpandedSwarm::dial(&mut *this, peer_id.clone());
@@ -482,12 +1182,59 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                         }
                     }
                 },
-                Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address }) => {
-                    for addr in this.network.address_translation(&address) {
-                        if this.external_addrs.iter().all(|a| *a != addr) {
-                            this.behaviour.inject_new_external_addr(&addr);
+                Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address, observer }) => {
+                    if let Some(observer) = &observer {
+                        this.observed_addrs.report(observer.clone(), address.clone());
+                    }
+                    let listen_addrs = this.network.listen_addrs().cloned().collect::<Vec<_>>();
+                    for listen_addr in &listen_addrs {
+                        let mut candidates = SmallVec::<[Multiaddr; 2]>::new();
+                        candidates.extend(address_translation(listen_addr, &address));
+                        match this.observed_addrs.port_mapping(listen_addr, &listen_addrs, &address) {
+                            PortMapping::Confirmed(addr) => if !candidates.contains(&addr) {
+                                candidates.push(addr)
+                            },
+                            PortMapping::Symmetric(mapped) => {
+                                // The NAT maps our listening port differently for every remote,
+                                // so the observed ports are useless to other remotes.
+                                for addr in mapped.into_iter().filter(|a| !candidates.contains(a)) {
+                                    if this.external_candidates.source(&addr) != Some(AddressSource::Observed) {
+                                        continue
+                                    }
+                                    this.external_candidates.remove(&addr);
+                                    if this.external_addrs.remove(&addr) {
+                                        this.behaviour.inject_expired_external_addr(&addr);
+                                    }
+                                }
+                            }
+                            PortMapping::NotApplicable | PortMapping::Unconfirmed => {}
                         }
-                        this.external_addrs.add(addr);
+                        for addr in candidates {
+                            if !this.address_filter.allows(&addr) {
+                                continue
+                            }
+                            let confirmed = this.external_candidates.report(
+                                addr.clone(),
+                                AddressSource::Observed,
+                                Some(listen_addr),
+                                observer.as_ref()
+                            );
+                            if !confirmed {
+                                continue
+                            }
+                            if this.external_addrs.iter().all(|a| *a != addr) {
+                                this.behaviour.inject_new_external_addr(&addr);
+                            }
+                            this.external_addrs.add(addr);
+                        }
+                    }
+                    // Forget about confirmed candidates that lost their rank.
+                    let external_addrs = &this.external_addrs;
+                    this.external_candidates.retain_confirmed(|addr| external_addrs.iter().any(|a| a == addr));
+                },
+                Poll::Ready(NetworkBehaviourAction::DisconnectPeer { peer_id }) => {
+                    if this.close_connection(&peer_id) {
+                        return Poll::Ready(SwarmEvent::Disconnected(peer_id));
                     }
                 },
             }
@@ -538,6 +1285,50 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
     }
 }
 
+/// Error returned when dialing an address.
+///
+/// The failures of the dials once started are reported with a [`SwarmEvent::UnreachableAddr`].
+#[derive(Debug)]
+pub enum DialError<TErr> {
+    /// We refused the dial, because the address is banned or the [`ConnectionGater`] of the
+    /// swarm denied it.
+    Refused(Refusal),
+    /// The transport failed to start the dial.
+    Transport(TransportError<TErr>),
+}
+
+impl<TErr> DialError<TErr> {
+    /// Returns the summary of the failure.
+    pub fn kind(&self) -> FailureKind {
+        match self {
+            DialError::Refused(_) => FailureKind::Refused,
+            DialError::Transport(_) => FailureKind::Unreachable,
+        }
+    }
+}
+
+impl<TErr> fmt::Display for DialError<TErr>
+where TErr: fmt::Display
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DialError::Refused(refusal) => write!(f, "{}", refusal),
+            DialError::Transport(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl<TErr> error::Error for DialError<TErr>
+where TErr: error::Error + 'static
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DialError::Refused(_) => None,
+            DialError::Transport(err) => Some(err),
+        }
+    }
+}
+
 /// Parameters passed to `poll()`, that the `NetworkBehaviour` has access to.
 // TODO: #[derive(Debug)]
 pub struct SwarmPollParameters<'a> {
@@ -545,6 +1336,7 @@ pub struct SwarmPollParameters<'a> {
     supported_protocols: &'a [Vec<u8>],
     listened_addrs: &'a [Multiaddr],
     external_addrs: &'a Addresses,
+    protocol_capabilities: &'a ProtocolCapabilities,
 }
 
 impl<'a> PollParameters for SwarmPollParameters<'a> {
@@ -567,10 +1359,28 @@ impl<'a> PollParameters for SwarmPollParameters<'a> {
     fn local_peer_id(&self) -> &PeerId {
         self.local_peer_id
     }
+
+    fn protocol_capabilities(&self) -> &ProtocolCapabilities {
+        self.protocol_capabilities
+    }
 }
 
-pub struct SwarmBuilder<TTransport, TBehaviour> {
+pub struct SwarmBuilder<TTransport, TBehaviour>
+where
+    TTransport: Transport,
+{
     incoming_limit: Option<u32>,
+    connection_limits: ConnectionLimits,
+    address_filter: AddressFilter,
+    gater: Option<Box<dyn ConnectionGater>>,
+    connection_tags: ConnectionTags,
+    peer_store: PeerStore,
+    backoff: BackoffConfig,
+    keep_alive: KeepAlivePolicy,
+    inbound_rate_limits: InboundRateLimits,
+    dial_error_classifier: dial_failure::Classifier<TTransport::Error>,
+    run_tasks_locally: bool,
+    external_confirmations: usize,
     local_peer_id: PeerId,
     transport: TTransport,
     behaviour: TBehaviour,
@@ -609,6 +1419,17 @@ where TBehaviour: NetworkBehaviour,
     pub fn new(transport: TTransport, behaviour: TBehaviour, local_peer_id: PeerId) -> Self {
         SwarmBuilder {
             incoming_limit: None,
+            connection_limits: ConnectionLimits::default(),
+            address_filter: AddressFilter::default(),
+            gater: None,
+            connection_tags: ConnectionTags::default(),
+            peer_store: PeerStore::new(),
+            backoff: BackoffConfig::default(),
+            keep_alive: KeepAlivePolicy::default(),
+            inbound_rate_limits: InboundRateLimits::default(),
+            dial_error_classifier: dial_failure::unclassified,
+            run_tasks_locally: false,
+            external_confirmations: external::DEFAULT_MIN_CONFIRMATIONS,
             local_peer_id,
             transport,
             behaviour,
@@ -620,6 +1441,102 @@ where TBehaviour: NetworkBehaviour,
         self
     }
 
+    /// Sets the limits on the connections of the swarm.
+    ///
+    /// Denied connections produce a [`SwarmEvent::ConnectionDenied`].
+    pub fn connection_limits(mut self, limits: ConnectionLimits) -> Self {
+        self.connection_limits = limits;
+        self
+    }
+
+    /// Sets the filter that addresses learned through the behaviour must pass.
+    ///
+    /// See [`ExpandedSwarm::set_address_filter`].
+    pub fn address_filter(mut self, filter: AddressFilter) -> Self {
+        self.address_filter = filter;
+        self
+    }
+
+    /// Sets the [`ConnectionGater`] deciding which connections are allowed.
+    ///
+    /// See [`ExpandedSwarm::set_connection_gater`].
+    pub fn connection_gater(mut self, gater: impl ConnectionGater) -> Self {
+        self.gater = Some(Box::new(gater));
+        self
+    }
+
+    /// Sets the handle to the labels and data of the connections, so that it can be shared
+    /// with the behaviour before the swarm is built.
+    ///
+    /// Defaults to a new handle, which can be obtained with [`ExpandedSwarm::connection_tags`].
+    pub fn connection_tags(mut self, tags: ConnectionTags) -> Self {
+        self.connection_tags = tags;
+        self
+    }
+
+    /// Sets the store of the information known about remote peers, for example one persisted
+    /// with a [`peer_store::PeerStoreBackend`].
+    ///
+    /// Defaults to an empty store kept in memory.
+    pub fn peer_store(mut self, store: PeerStore) -> Self {
+        self.peer_store = store;
+        self
+    }
+
+    /// Sets the curve of the back-off delays of the dials that keep failing.
+    ///
+    /// See [`ExpandedSwarm::dial_backoff`].
+    pub fn dial_backoff(mut self, config: BackoffConfig) -> Self {
+        self.backoff = config;
+        self
+    }
+
+    /// Sets the policy deciding how long idle connections are kept alive.
+    ///
+    /// Defaults to closing the connections as soon as no handler is interested in them.
+    pub fn keep_alive_policy(mut self, policy: KeepAlivePolicy) -> Self {
+        self.keep_alive = policy;
+        self
+    }
+
+    /// Sets the rate at which each peer may open inbound substreams for each protocol.
+    ///
+    /// Denied substreams are reset and produce a [`SwarmEvent::InboundSubstreamRateLimited`].
+    /// Defaults to no limit.
+    pub fn inbound_rate_limits(mut self, limits: InboundRateLimits) -> Self {
+        self.inbound_rate_limits = limits;
+        self
+    }
+
+    /// Sets the function telling at which stage of the establishment of a connection an error of
+    /// the transport happened, so that the failed dials are reported with the right
+    /// [`AddressFailure`].
+    ///
+    /// [`StagedError::stage`] suits the transports upgraded with `authenticate` then
+    /// `multiplex`. Defaults to reporting all the errors as [`AddressFailure::Transport`].
+    pub fn dial_error_classifier(mut self, classifier: fn(&TTransport::Error) -> ConnectionStage) -> Self {
+        self.dial_error_classifier = classifier;
+        self
+    }
+
+    /// Processes the connections on the task polling the swarm, instead of on a threads pool.
+    ///
+    /// This makes the swarm entirely deterministic for a given order of polling, which is mostly
+    /// useful for tests.
+    pub fn run_tasks_locally(mut self) -> Self {
+        self.run_tasks_locally = true;
+        self
+    }
+
+    /// Sets the number of distinct remotes that must observe an address before it is advertised
+    /// as an external address.
+    ///
+    /// Defaults to [`external::DEFAULT_MIN_CONFIRMATIONS`].
+    pub fn external_address_confirmations(mut self, confirmations: usize) -> Self {
+        self.external_confirmations = confirmations;
+        self
+    }
+
     pub fn build(mut self) -> Swarm<TTransport, TBehaviour, TConnInfo> {
         let supported_protocols = self.behaviour
             .new_handler()
@@ -629,7 +1546,19 @@ where TBehaviour: NetworkBehaviour,
             .map(|info| info.protocol_name().to_vec())
             .collect();
 
-        let network = Network::new_with_incoming_limit(self.transport, self.local_peer_id, self.incoming_limit);
+        self.behaviour.inject_address_filter(&self.address_filter);
+
+        let connection_tags = self.connection_tags;
+        let gater = self.gater.map(|mut gater| {
+            gater.inject_connection_tags(connection_tags.clone());
+            gater
+        });
+
+        let mut network = Network::new_with_incoming_limit(self.transport, self.local_peer_id, self.incoming_limit);
+        network.set_connection_limits(self.connection_limits);
+        if self.run_tasks_locally {
+            network.run_tasks_locally();
+        }
 
         ExpandedSwarm {
             network,
@@ -637,8 +1566,28 @@ where TBehaviour: NetworkBehaviour,
             supported_protocols,
             listened_addrs: SmallVec::new(),
             external_addrs: Addresses::default(),
-            banned_peers: HashSet::new(),
-            send_event_to_complete: None
+            external_candidates: ExternalCandidates::new(self.external_confirmations),
+            observed_addrs: ObservedAddrs::default(),
+            bans: Bans::default(),
+            address_filter: self.address_filter,
+            gater,
+            connection_tags,
+            protocol_capabilities: ProtocolCapabilities::default(),
+            peer_store: self.peer_store,
+            backoff: DialBackoff::new(self.backoff),
+            keep_alive: Arc::new(self.keep_alive),
+            rate_limiter: if self.inbound_rate_limits.is_empty() {
+                None
+            } else {
+                Some(InboundRateLimiter::new(self.inbound_rate_limits))
+            },
+            shutting_down: false,
+            dial_error_classifier: self.dial_error_classifier,
+            dial_tags: HashMap::new(),
+            addr_dial_tags: HashMap::new(),
+            send_event_to_complete: None,
+            stats_requests: SmallVec::new(),
+            subscribers: Subscribers::default(),
         }
     }
 }
@@ -646,9 +1595,22 @@ where TBehaviour: NetworkBehaviour,
 #[cfg(test)]
 mod tests {
     use crate::protocols_handler::{DummyProtocolsHandler, ProtocolsHandler};
-    use crate::{NetworkBehaviour, NetworkBehaviourAction, PollParameters, SwarmBuilder};
+    use crate::{
+        Ban,
+        BanTarget,
+        ConnectionGater,
+        ConnectionLimits,
+        DialError,
+        ExpandedSwarm,
+        NetworkBehaviour,
+        NetworkBehaviourAction,
+        PollParameters,
+        Refusal,
+        SwarmBuilder
+    };
     use libp2p_core::{
         ConnectedPoint,
+        address_filter::Subnet,
         identity,
         Multiaddr,
         PeerId,
@@ -710,6 +1672,55 @@ mod tests {
         assert_eq!(swarm.network.incoming_limit(), Some(4));
     }
 
+    #[test]
+    fn test_build_swarm_with_connection_limits() {
+        let id = get_random_id();
+        let transport = DummyTransport::<(PeerId, Multiplex<DummyStream>)>::new();
+        let behaviour = DummyBehaviour{marker: PhantomData};
+        let limits = ConnectionLimits::new().max_established(10).max_per_subnet(24, 64, 2);
+        let swarm = SwarmBuilder::new(transport, behaviour, id.into())
+            .connection_limits(limits.clone()).build();
+        assert_eq!(swarm.network.connection_limits(), &limits);
+    }
+
+    #[test]
+    fn banned_subnets_are_not_dialed() {
+        let id = get_random_id();
+        let transport = DummyTransport::<(PeerId, Multiplex<DummyStream>)>::new();
+        let behaviour = DummyBehaviour{marker: PhantomData};
+        let mut swarm = SwarmBuilder::new(transport, behaviour, id.into()).build();
+        let subnet: Subnet = "10.0.0.0/8".parse().unwrap();
+        ExpandedSwarm::ban(&mut swarm, subnet, None);
+        assert_eq!(ExpandedSwarm::bans(&swarm), vec![Ban { target: BanTarget::Subnet(subnet), expires: None }]);
+        match ExpandedSwarm::dial_addr(&mut swarm, "/ip4/10.1.2.3/tcp/1".parse().unwrap()) {
+            Err(DialError::Refused(Refusal::Banned)) => {},
+            _ => panic!("the dial should have been denied"),
+        }
+        assert!(ExpandedSwarm::unban(&mut swarm, &BanTarget::Subnet(subnet)));
+        assert!(ExpandedSwarm::bans(&swarm).is_empty());
+    }
+
+    #[test]
+    fn gater_denies_dials() {
+        struct DenyAll;
+        impl ConnectionGater for DenyAll {
+            fn allow_dial(&mut self, _: Option<&PeerId>, _: &Multiaddr) -> bool {
+                false
+            }
+        }
+
+        let id = get_random_id();
+        let transport = DummyTransport::<(PeerId, Multiplex<DummyStream>)>::new();
+        let behaviour = DummyBehaviour{marker: PhantomData};
+        let mut swarm = SwarmBuilder::new(transport, behaviour, id.into())
+            .connection_gater(DenyAll).build();
+        let addr = "/memory/1".parse().unwrap();
+        match ExpandedSwarm::dial_addr(&mut swarm, addr) {
+            Err(DialError::Refused(Refusal::Gated)) => {},
+            _ => panic!("the dial should have been denied"),
+        }
+    }
+
     #[test]
     fn test_build_swarm_with_max_listeners_none() {
         let id = get_random_id();
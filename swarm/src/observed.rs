@@ -0,0 +1,160 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Tracking of the addresses remotes observe for the local node.
+
+use libp2p_core::{Multiaddr, PeerId, port_translation};
+use smallvec::SmallVec;
+use std::collections::HashMap;
+
+/// Number of remotes that must observe the same port before it is considered to be mapped by the
+/// NAT to a listening port.
+const MIN_CONFIRMATIONS: usize = 2;
+
+/// The last address observed for the local node by each connected remote.
+#[derive(Debug, Default)]
+pub(crate) struct ObservedAddrs {
+    by_remote: HashMap<PeerId, Multiaddr>,
+}
+
+/// The outcome of [`ObservedAddrs::port_mapping`].
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum PortMapping {
+    /// The observed address can't be attributed to the listening address.
+    NotApplicable,
+    /// Not enough remotes observed the port yet.
+    Unconfirmed,
+    /// Enough remotes observed the same port, giving this external address.
+    Confirmed(Multiaddr),
+    /// Remotes observed different ports for the same IP address, as happens behind a symmetric
+    /// NAT. Contains all external addresses derived from these ports.
+    Symmetric(SmallVec<[Multiaddr; 4]>),
+}
+
+impl ObservedAddrs {
+    /// Records the address a remote observed.
+    pub(crate) fn report(&mut self, remote: PeerId, addr: Multiaddr) {
+        self.by_remote.insert(remote, addr);
+    }
+
+    /// Forgets about the observations of a remote.
+    pub(crate) fn remove(&mut self, remote: &PeerId) {
+        self.by_remote.remove(remote);
+    }
+
+    /// Determines whether the port of an `observed` address is the external port the NAT maps
+    /// `listen_addr` to, according to the observations of all remotes.
+    ///
+    /// The observed port can only be attributed to `listen_addr` if no other listening address
+    /// of `listen_addrs` is made of the same protocols.
+    pub(crate) fn port_mapping(&self, listen_addr: &Multiaddr, listen_addrs: &[Multiaddr], observed: &Multiaddr)
+        -> PortMapping
+    {
+        let candidate = match port_translation(listen_addr, observed) {
+            Some(a) => a,
+            None => return PortMapping::NotApplicable
+        };
+        if listen_addrs.iter().filter(|a| port_translation(a, observed).is_some()).count() > 1 {
+            return PortMapping::NotApplicable
+        }
+
+        let ip = candidate.iter().next();
+        let mut confirmations = 0;
+        let mut mapped = SmallVec::<[Multiaddr; 4]>::new();
+        for addr in self.by_remote.values().filter_map(|o| port_translation(listen_addr, o)) {
+            if addr.iter().next() != ip {
+                continue
+            }
+            if addr == candidate {
+                confirmations += 1
+            }
+            if !mapped.contains(&addr) {
+                mapped.push(addr)
+            }
+        }
+
+        if mapped.len() > 1 {
+            PortMapping::Symmetric(mapped)
+        } else if confirmations >= MIN_CONFIRMATIONS {
+            PortMapping::Confirmed(candidate)
+        } else {
+            PortMapping::Unconfirmed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn confirmed_by_several_remotes() {
+        let listen = vec![addr("/ip4/10.0.0.1/udp/4001/quic"), addr("/ip4/10.0.0.1/tcp/4001")];
+        let observed = addr("/ip4/1.2.3.4/udp/5001/quic");
+        let mut addrs = ObservedAddrs::default();
+
+        addrs.report(PeerId::random(), observed.clone());
+        assert_eq!(addrs.port_mapping(&listen[0], &listen, &observed), PortMapping::Unconfirmed);
+        assert_eq!(addrs.port_mapping(&listen[1], &listen, &observed), PortMapping::NotApplicable);
+
+        addrs.report(PeerId::random(), observed.clone());
+        assert_eq!(addrs.port_mapping(&listen[0], &listen, &observed), PortMapping::Confirmed(observed));
+    }
+
+    #[test]
+    fn symmetric_nat() {
+        let listen = vec![addr("/ip4/10.0.0.1/udp/4001/quic")];
+        let mut addrs = ObservedAddrs::default();
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+
+        addrs.report(a.clone(), addr("/ip4/1.2.3.4/udp/5001/quic"));
+        addrs.report(b, addr("/ip4/1.2.3.4/udp/5001/quic"));
+        addrs.report(c, addr("/ip4/1.2.3.4/udp/5002/quic"));
+        let mapping = addrs.port_mapping(&listen[0], &listen, &addr("/ip4/1.2.3.4/udp/5002/quic"));
+        match mapping {
+            PortMapping::Symmetric(mapped) => {
+                assert_eq!(mapped.len(), 2);
+                assert!(mapped.contains(&addr("/ip4/1.2.3.4/udp/5001/quic")));
+                assert!(mapped.contains(&addr("/ip4/1.2.3.4/udp/5002/quic")));
+            }
+            m => panic!("Unexpected mapping: {:?}", m)
+        }
+
+        // Observations from another IP address are unrelated.
+        addrs.remove(&a);
+        addrs.report(a, addr("/ip4/5.6.7.8/udp/6001/quic"));
+        let mapping = addrs.port_mapping(&listen[0], &listen, &addr("/ip4/5.6.7.8/udp/6001/quic"));
+        assert_eq!(mapping, PortMapping::Unconfirmed);
+    }
+
+    #[test]
+    fn ambiguous_listen_addrs() {
+        let listen = vec![addr("/ip4/10.0.0.1/udp/4001/quic"), addr("/ip4/10.0.0.1/udp/4002/quic")];
+        let observed = addr("/ip4/1.2.3.4/udp/5001/quic");
+        let mut addrs = ObservedAddrs::default();
+        addrs.report(PeerId::random(), observed.clone());
+        addrs.report(PeerId::random(), observed.clone());
+        assert_eq!(addrs.port_mapping(&listen[0], &listen, &observed), PortMapping::NotApplicable);
+    }
+}
@@ -0,0 +1,518 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Store of the information known about remote peers.
+//!
+//! The [`PeerStore`] records, for each peer, the addresses it can be reached at along with
+//! their expiration and the outcome of the dials, the protocols it supports, and arbitrary
+//! metadata. The swarm feeds it with the results of its dials and uses it to find the
+//! addresses of the peers it dials, in addition to those given by the `NetworkBehaviour`.
+//!
+//! `PeerStore` is a cheaply cloneable handle, which can be shared with the behaviours. The
+//! records are kept in memory and can be persisted through a [`PeerStoreBackend`], such as the
+//! [`FileBackend`]. Other storages, such as databases, can be plugged in by implementing the
+//! trait.
+
+use libp2p_core::{Multiaddr, PeerId};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs,
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use wasm_timer::{SystemTime, UNIX_EPOCH};
+
+/// Time during which the address of a successful dial is kept.
+pub const CONNECTED_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Everything known about a peer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerRecord {
+    /// The addresses of the peer.
+    pub addresses: Vec<AddressRecord>,
+    /// The protocols supported by the peer.
+    pub protocols: Vec<String>,
+    /// Arbitrary metadata attached to the peer.
+    pub metadata: HashMap<String, Vec<u8>>,
+}
+
+impl PeerRecord {
+    fn is_empty(&self) -> bool {
+        self.addresses.is_empty() && self.protocols.is_empty() && self.metadata.is_empty()
+    }
+}
+
+/// An address of a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressRecord {
+    /// The address.
+    pub address: Multiaddr,
+    /// Moment after which the address is forgotten.
+    pub expires: SystemTime,
+    /// Number of successful dials of the address.
+    pub successes: u32,
+    /// Number of failed dials of the address.
+    pub failures: u32,
+}
+
+impl AddressRecord {
+    /// Returns the ratio of successful dials, or `None` if the address has never been dialed.
+    pub fn success_ratio(&self) -> Option<f64> {
+        let total = u64::from(self.successes) + u64::from(self.failures);
+        if total == 0 {
+            None
+        } else {
+            Some(f64::from(self.successes) / total as f64)
+        }
+    }
+}
+
+/// Storage in which the records of a [`PeerStore`] are persisted.
+pub trait PeerStoreBackend: Send + 'static {
+    /// Loads all the records.
+    fn load(&mut self) -> io::Result<Vec<(PeerId, PeerRecord)>>;
+
+    /// Stores the record of a peer, replacing the previous one.
+    fn store(&mut self, peer_id: &PeerId, record: &PeerRecord) -> io::Result<()>;
+
+    /// Removes the record of a peer.
+    fn remove(&mut self, peer_id: &PeerId) -> io::Result<()>;
+}
+
+/// Backend that keeps the records in memory.
+///
+/// Records survive as long as the backend, which can be moved from one store to another.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    records: HashMap<PeerId, PeerRecord>,
+}
+
+impl MemoryBackend {
+    /// Creates an empty backend.
+    pub fn new() -> Self {
+        MemoryBackend::default()
+    }
+}
+
+impl PeerStoreBackend for MemoryBackend {
+    fn load(&mut self) -> io::Result<Vec<(PeerId, PeerRecord)>> {
+        Ok(self.records.iter().map(|(p, r)| (p.clone(), r.clone())).collect())
+    }
+
+    fn store(&mut self, peer_id: &PeerId, record: &PeerRecord) -> io::Result<()> {
+        self.records.insert(peer_id.clone(), record.clone());
+        Ok(())
+    }
+
+    fn remove(&mut self, peer_id: &PeerId) -> io::Result<()> {
+        self.records.remove(peer_id);
+        Ok(())
+    }
+}
+
+/// Backend that stores each record in a file of a directory, named after the peer ID.
+///
+/// Each line of a file is either `addr <address> <expiration> <successes> <failures>`, with the
+/// expiration in seconds since the UNIX epoch, `proto <protocol>`, or `meta <key> <value>`, with
+/// the key and the value hex-encoded.
+#[derive(Debug, Clone)]
+pub struct FileBackend {
+    dir: PathBuf,
+}
+
+impl FileBackend {
+    /// Creates a backend storing the records in `dir`, which is created if necessary.
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(FileBackend { dir })
+    }
+
+    fn path(&self, peer_id: &PeerId) -> PathBuf {
+        self.dir.join(peer_id.to_base58())
+    }
+}
+
+impl PeerStoreBackend for FileBackend {
+    fn load(&mut self) -> io::Result<Vec<(PeerId, PeerRecord)>> {
+        let mut records = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let peer_id = match path.file_name().and_then(|n| n.to_str()).and_then(|n| n.parse().ok()) {
+                Some(peer_id) => peer_id,
+                // Not one of our records, such as a temporary file.
+                None => continue,
+            };
+            let record = decode_record(&fs::read_to_string(&path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+            records.push((peer_id, record));
+        }
+        Ok(records)
+    }
+
+    fn store(&mut self, peer_id: &PeerId, record: &PeerRecord) -> io::Result<()> {
+        // Write to a temporary file first, so that a crash never leaves a truncated record.
+        let path = self.path(peer_id);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, encode_record(record))?;
+        fs::rename(tmp, path)
+    }
+
+    fn remove(&mut self, peer_id: &PeerId) -> io::Result<()> {
+        match fs::remove_file(self.path(peer_id)) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            other => other,
+        }
+    }
+}
+
+fn encode_record(record: &PeerRecord) -> String {
+    let mut out = String::new();
+    for a in &record.addresses {
+        let expires = a.expires.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        out.push_str(&format!("addr {} {} {} {}\n", a.address, expires, a.successes, a.failures));
+    }
+    for p in &record.protocols {
+        out.push_str(&format!("proto {}\n", p));
+    }
+    for (k, v) in &record.metadata {
+        out.push_str(&format!("meta {} {}\n", hex(k.as_bytes()), hex(v)));
+    }
+    out
+}
+
+fn decode_record(s: &str) -> Result<PeerRecord, String> {
+    let mut record = PeerRecord::default();
+    for line in s.lines().filter(|l| !l.is_empty()) {
+        let mut parts = line.splitn(2, ' ');
+        let (kind, rest) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        match kind {
+            "addr" => {
+                let fields = rest.split(' ').collect::<Vec<_>>();
+                if fields.len() != 4 {
+                    return Err(format!("invalid address line: {}", line))
+                }
+                let invalid = |_| format!("invalid address line: {}", line);
+                record.addresses.push(AddressRecord {
+                    address: fields[0].parse().map_err(|_| format!("invalid address line: {}", line))?,
+                    expires: UNIX_EPOCH + Duration::from_secs(fields[1].parse().map_err(invalid)?),
+                    successes: fields[2].parse().map_err(invalid)?,
+                    failures: fields[3].parse().map_err(invalid)?,
+                });
+            }
+            "proto" => record.protocols.push(rest.to_owned()),
+            "meta" => {
+                let mut fields = rest.split(' ');
+                let key = fields.next().and_then(unhex).and_then(|k| String::from_utf8(k).ok());
+                let value = fields.next().and_then(unhex);
+                match (key, value) {
+                    (Some(k), Some(v)) => { record.metadata.insert(k, v); }
+                    _ => return Err(format!("invalid metadata line: {}", line)),
+                }
+            }
+            _ => return Err(format!("unknown line: {}", line)),
+        }
+    }
+    Ok(record)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|c| match c {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(c).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Shared handle to the information known about remote peers.
+#[derive(Clone)]
+pub struct PeerStore {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    records: HashMap<PeerId, PeerRecord>,
+    /// Peers whose record changed since the last flush.
+    dirty: HashSet<PeerId>,
+    backend: Box<dyn PeerStoreBackend>,
+}
+
+impl Default for PeerStore {
+    fn default() -> Self {
+        PeerStore::new()
+    }
+}
+
+impl PeerStore {
+    /// Creates an empty store kept in memory.
+    pub fn new() -> Self {
+        PeerStore::from_parts(HashMap::new(), Box::new(MemoryBackend::new()))
+    }
+
+    /// Creates a store persisted in the given backend, loading the records it contains.
+    ///
+    /// Expired addresses are discarded.
+    pub fn with_backend(mut backend: impl PeerStoreBackend) -> io::Result<Self> {
+        let records = backend.load()?.into_iter().collect();
+        let store = PeerStore::from_parts(records, Box::new(backend));
+        store.lock().expire(SystemTime::now());
+        Ok(store)
+    }
+
+    fn from_parts(records: HashMap<PeerId, PeerRecord>, backend: Box<dyn PeerStoreBackend>) -> Self {
+        PeerStore {
+            inner: Arc::new(Mutex::new(Inner { records, dirty: HashSet::new(), backend }))
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().expect("the lock is never poisoned")
+    }
+
+    /// Returns the peers with a record in the store.
+    pub fn peers(&self) -> Vec<PeerId> {
+        self.lock().records.keys().cloned().collect()
+    }
+
+    /// Returns the record of a peer, with its expired addresses removed.
+    pub fn record(&self, peer_id: &PeerId) -> Option<PeerRecord> {
+        let mut inner = self.lock();
+        inner.expire(SystemTime::now());
+        inner.records.get(peer_id).cloned()
+    }
+
+    /// Adds an address to a peer, to be kept for `ttl`.
+    ///
+    /// If the address is already known, its expiration is extended if needed.
+    pub fn add_address(&self, peer_id: &PeerId, address: Multiaddr, ttl: Duration) {
+        let expires = SystemTime::now() + ttl;
+        self.lock().update(peer_id, |record| {
+            if let Some(a) = record.addresses.iter_mut().find(|a| a.address == address) {
+                a.expires = a.expires.max(expires);
+            } else {
+                record.addresses.push(AddressRecord { address, expires, successes: 0, failures: 0 });
+            }
+        })
+    }
+
+    /// Removes an address of a peer.
+    pub fn remove_address(&self, peer_id: &PeerId, address: &Multiaddr) {
+        self.lock().update(peer_id, |record| record.addresses.retain(|a| a.address != *address))
+    }
+
+    /// Returns the unexpired addresses of a peer, the most reliable ones first.
+    ///
+    /// Addresses that have never been dialed are ranked as if half of the dials succeeded.
+    pub fn addresses(&self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        let mut inner = self.lock();
+        inner.expire(SystemTime::now());
+        let mut addresses = match inner.records.get(peer_id) {
+            Some(record) => record.addresses.clone(),
+            None => return Vec::new(),
+        };
+        let rank = |a: &AddressRecord| a.success_ratio().unwrap_or(0.5);
+        addresses.sort_by(|a, b| rank(b).partial_cmp(&rank(a)).unwrap_or(std::cmp::Ordering::Equal));
+        addresses.into_iter().map(|a| a.address).collect()
+    }
+
+    /// Records a successful dial of a known address of a peer.
+    pub fn report_success(&self, peer_id: &PeerId, address: &Multiaddr) {
+        self.lock().update_address(peer_id, address, |a| a.successes = a.successes.saturating_add(1))
+    }
+
+    /// Records a failed dial of a known address of a peer.
+    pub fn report_failure(&self, peer_id: &PeerId, address: &Multiaddr) {
+        self.lock().update_address(peer_id, address, |a| a.failures = a.failures.saturating_add(1))
+    }
+
+    /// Replaces the protocols supported by a peer.
+    pub fn set_protocols(&self, peer_id: &PeerId, protocols: impl IntoIterator<Item = String>) {
+        let protocols = protocols.into_iter().collect();
+        self.lock().update(peer_id, |record| record.protocols = protocols)
+    }
+
+    /// Returns the protocols supported by a peer.
+    pub fn protocols(&self, peer_id: &PeerId) -> Vec<String> {
+        self.lock().records.get(peer_id).map(|r| r.protocols.clone()).unwrap_or_default()
+    }
+
+    /// Returns true if the peer is known to support the given protocol.
+    pub fn supports_protocol(&self, peer_id: &PeerId, protocol: &str) -> bool {
+        self.lock().records.get(peer_id).map_or(false, |r| r.protocols.iter().any(|p| p == protocol))
+    }
+
+    /// Attaches metadata to a peer, replacing the previous value of the key.
+    pub fn set_metadata(&self, peer_id: &PeerId, key: impl Into<String>, value: Vec<u8>) {
+        let key = key.into();
+        self.lock().update(peer_id, |record| { record.metadata.insert(key, value); })
+    }
+
+    /// Returns the metadata of a peer under the given key.
+    pub fn metadata(&self, peer_id: &PeerId, key: &str) -> Option<Vec<u8>> {
+        self.lock().records.get(peer_id).and_then(|r| r.metadata.get(key).cloned())
+    }
+
+    /// Removes the metadata of a peer under the given key.
+    pub fn remove_metadata(&self, peer_id: &PeerId, key: &str) {
+        self.lock().update(peer_id, |record| { record.metadata.remove(key); })
+    }
+
+    /// Forgets everything about a peer.
+    pub fn remove_peer(&self, peer_id: &PeerId) {
+        let mut inner = self.lock();
+        if inner.records.remove(peer_id).is_some() {
+            inner.dirty.insert(peer_id.clone());
+        }
+    }
+
+    /// Writes the records changed since the last flush to the backend.
+    ///
+    /// On error, the records that couldn't be written are kept to be written by the next flush.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut inner = self.lock();
+        inner.expire(SystemTime::now());
+        let Inner { records, dirty, backend } = &mut *inner;
+        let peers = dirty.iter().cloned().collect::<Vec<_>>();
+        for peer_id in peers {
+            match records.get(&peer_id) {
+                Some(record) => backend.store(&peer_id, record)?,
+                None => backend.remove(&peer_id)?,
+            }
+            dirty.remove(&peer_id);
+        }
+        Ok(())
+    }
+}
+
+impl Inner {
+    /// Applies `f` to the record of a peer, creating it if necessary, and forgets about the peer
+    /// if the record ends up empty.
+    fn update(&mut self, peer_id: &PeerId, f: impl FnOnce(&mut PeerRecord)) {
+        let record = self.records.entry(peer_id.clone()).or_default();
+        f(record);
+        if record.is_empty() {
+            self.records.remove(peer_id);
+        }
+        self.dirty.insert(peer_id.clone());
+    }
+
+    fn update_address(&mut self, peer_id: &PeerId, address: &Multiaddr, f: impl FnOnce(&mut AddressRecord)) {
+        let found = self.records.get_mut(peer_id)
+            .and_then(|r| r.addresses.iter_mut().find(|a| a.address == *address))
+            .map(f)
+            .is_some();
+        if found {
+            self.dirty.insert(peer_id.clone());
+        }
+    }
+
+    /// Removes the addresses that expired before `now`.
+    fn expire(&mut self, now: SystemTime) {
+        let Inner { records, dirty, .. } = self;
+        records.retain(|peer_id, record| {
+            let before = record.addresses.len();
+            record.addresses.retain(|a| a.expires > now);
+            if record.addresses.len() != before {
+                dirty.insert(peer_id.clone());
+            }
+            !record.is_empty()
+        });
+    }
+}
+
+impl fmt::Debug for PeerStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PeerStore").field("peers", &self.lock().records.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn addresses_ranked_and_expired() {
+        let store = PeerStore::new();
+        let peer = PeerId::random();
+        store.add_address(&peer, addr("/memory/1"), Duration::from_secs(60));
+        store.add_address(&peer, addr("/memory/2"), Duration::from_secs(60));
+        store.add_address(&peer, addr("/memory/3"), Duration::from_secs(0));
+        store.report_failure(&peer, &addr("/memory/1"));
+        store.report_success(&peer, &addr("/memory/2"));
+        assert_eq!(store.addresses(&peer), vec![addr("/memory/2"), addr("/memory/1")]);
+
+        store.remove_address(&peer, &addr("/memory/1"));
+        store.remove_address(&peer, &addr("/memory/2"));
+        assert!(store.record(&peer).is_none());
+    }
+
+    #[test]
+    fn protocols_and_metadata() {
+        let store = PeerStore::new();
+        let peer = PeerId::random();
+        store.set_protocols(&peer, vec!["/ipfs/ping/1.0.0".to_string()]);
+        store.set_metadata(&peer, "agent", b"rust-libp2p".to_vec());
+        assert!(store.supports_protocol(&peer, "/ipfs/ping/1.0.0"));
+        assert!(!store.supports_protocol(&peer, "/ipfs/id/1.0.0"));
+        assert_eq!(store.metadata(&peer, "agent"), Some(b"rust-libp2p".to_vec()));
+        store.remove_metadata(&peer, "agent");
+        assert_eq!(store.metadata(&peer, "agent"), None);
+    }
+
+    #[test]
+    fn file_backend_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("libp2p-peer-store-{}", PeerId::random().to_base58()));
+        let peer = PeerId::random();
+        let gone = PeerId::random();
+        {
+            let store = PeerStore::with_backend(FileBackend::new(&dir).unwrap()).unwrap();
+            store.add_address(&peer, addr("/ip4/1.2.3.4/tcp/1"), Duration::from_secs(60));
+            store.report_success(&peer, &addr("/ip4/1.2.3.4/tcp/1"));
+            store.set_protocols(&peer, vec!["/a proto".to_string()]);
+            store.set_metadata(&peer, "key with spaces", vec![0, 255]);
+            store.set_metadata(&gone, "k", Vec::new());
+            store.flush().unwrap();
+            store.remove_peer(&gone);
+            store.flush().unwrap();
+        }
+
+        let store = PeerStore::with_backend(FileBackend::new(&dir).unwrap()).unwrap();
+        assert_eq!(store.peers(), vec![peer.clone()]);
+        let record = store.record(&peer).unwrap();
+        assert_eq!(record.addresses.len(), 1);
+        assert_eq!(record.addresses[0].successes, 1);
+        assert_eq!(record.protocols, vec!["/a proto".to_string()]);
+        assert_eq!(record.metadata.get("key with spaces"), Some(&vec![0, 255]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
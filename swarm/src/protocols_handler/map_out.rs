@@ -90,6 +90,11 @@ where
         self.inner.inject_dial_upgrade_error(info, error)
     }
 
+    #[inline]
+    fn is_outbound_cancelled(&self, info: &Self::OutboundOpenInfo) -> bool {
+        self.inner.is_outbound_cancelled(info)
+    }
+
     #[inline]
     fn connection_keep_alive(&self) -> KeepAlive {
         self.inner.connection_keep_alive()
@@ -146,6 +146,20 @@ pub trait ProtocolsHandler {
         >
     );
 
+    /// Returns `true` if the outbound substream requested with the given information is no
+    /// longer needed.
+    ///
+    /// This method is called by the `Swarm` for every pending outbound substream request,
+    /// whether the substream is still being opened or its protocol is being negotiated.
+    /// Once it returns `true`, the substream is dropped and
+    /// [`ProtocolsHandler::inject_dial_upgrade_error`] is called with
+    /// [`ProtocolsHandlerUpgrErr::Cancelled`].
+    ///
+    /// The default implementation never cancels a request.
+    fn is_outbound_cancelled(&self, _info: &Self::OutboundOpenInfo) -> bool {
+        false
+    }
+
     /// Returns until when the connection should be kept alive.
     ///
     /// This method is called by the `Swarm` after each invocation of
@@ -415,6 +429,10 @@ pub enum ProtocolsHandlerUpgrErr<TUpgrErr> {
     Timeout,
     /// There was an error in the timer used.
     Timer,
+    /// The request was cancelled by the handler before the negotiation was fully completed.
+    ///
+    /// See [`ProtocolsHandler::is_outbound_cancelled`].
+    Cancelled,
     /// Error while upgrading the substream to the protocol we want.
     Upgrade(UpgradeError<TUpgrErr>),
 }
@@ -431,6 +449,9 @@ where
             ProtocolsHandlerUpgrErr::Timer => {
                 write!(f, "Timer error while opening a substream")
             },
+            ProtocolsHandlerUpgrErr::Cancelled => {
+                write!(f, "Substream request cancelled while opening a substream")
+            },
             ProtocolsHandlerUpgrErr::Upgrade(err) => write!(f, "{}", err),
         }
     }
@@ -444,6 +465,7 @@ where
         match self {
             ProtocolsHandlerUpgrErr::Timeout => None,
             ProtocolsHandlerUpgrErr::Timer => None,
+            ProtocolsHandlerUpgrErr::Cancelled => None,
             ProtocolsHandlerUpgrErr::Upgrade(err) => Some(err),
         }
     }
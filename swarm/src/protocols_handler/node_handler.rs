@@ -18,6 +18,9 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use crate::capabilities::{self, ProtocolCapabilities, TrackedUpgrade};
+use crate::keep_alive::{ConnectionKeepAlive, KeepAlivePolicy, PeerKeepAlive};
+use crate::rate_limit::{InboundRateLimiter, RateLimitedUpgrade};
 use crate::protocols_handler::{
     KeepAlive,
     ProtocolsHandler,
@@ -31,15 +34,21 @@ use libp2p_core::{
     PeerId,
     nodes::collection::ConnectionInfo,
     nodes::handled_node::{IntoNodeHandler, NodeHandler, NodeHandlerEndpoint, NodeHandlerEvent},
-    upgrade::{self, InboundUpgradeApply, OutboundUpgradeApply}
+    upgrade::{self, InboundUpgradeApply, NegotiationError, OutboundUpgradeApply, UpgradeError}
 };
-use std::{error, fmt, pin::Pin, task::Context, task::Poll, time::Duration};
+use std::{error, fmt, pin::Pin, sync::Arc, task::Context, task::Poll, time::Duration};
 use wasm_timer::{Delay, Instant};
 
 /// Prototype for a `NodeHandlerWrapper`.
 pub struct NodeHandlerWrapperBuilder<TIntoProtoHandler> {
     /// The underlying handler.
     handler: TIntoProtoHandler,
+    /// The keep-alive policy of the swarm, if any.
+    keep_alive: Option<Arc<KeepAlivePolicy>>,
+    /// The rate limiter of the inbound substreams of the swarm, if any.
+    rate_limiter: Option<InboundRateLimiter>,
+    /// The table of the protocols supported by the peers, if any.
+    capabilities: Option<ProtocolCapabilities>,
 }
 
 impl<TIntoProtoHandler> NodeHandlerWrapperBuilder<TIntoProtoHandler>
@@ -51,9 +60,31 @@ where
     pub(crate) fn new(handler: TIntoProtoHandler) -> Self {
         NodeHandlerWrapperBuilder {
             handler,
+            keep_alive: None,
+            rate_limiter: None,
+            capabilities: None,
         }
     }
 
+    /// Applies the given keep-alive policy to the connection.
+    pub(crate) fn with_keep_alive_policy(mut self, policy: Arc<KeepAlivePolicy>) -> Self {
+        self.keep_alive = Some(policy);
+        self
+    }
+
+    /// Applies the given rate limiter to the inbound substreams of the connection, if any.
+    pub(crate) fn with_inbound_rate_limiter(mut self, limiter: Option<InboundRateLimiter>) -> Self {
+        self.rate_limiter = limiter;
+        self
+    }
+
+    /// Records the protocols negotiated on the connection in the given table, and fails right
+    /// away the outbound substream requests whose protocols the peer is known not to support.
+    pub(crate) fn with_protocol_capabilities(mut self, capabilities: ProtocolCapabilities) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
     /// Builds the `NodeHandlerWrapper`.
     #[deprecated(note = "Pass the NodeHandlerWrapperBuilder directly")]
     #[inline]
@@ -67,6 +98,9 @@ where
             queued_dial_upgrades: Vec::new(),
             unique_dial_upgrade_id: 0,
             shutdown: Shutdown::None,
+            keep_alive: ConnectionKeepAlive::new(PeerKeepAlive::IdleTimeout(Duration::from_secs(0))),
+            rate_limiter: None,
+            capabilities: None,
         }
     }
 }
@@ -81,19 +115,26 @@ where
     type Handler = NodeHandlerWrapper<TIntoProtoHandler::Handler>;
 
     fn into_handler(self, remote_info: &(TConnInfo, ConnectedPoint)) -> Self::Handler {
+        let peer_id = remote_info.0.peer_id();
+        let keep_alive = match self.keep_alive {
+            Some(policy) => policy.for_peer(peer_id),
+            None => PeerKeepAlive::IdleTimeout(Duration::from_secs(0)),
+        };
         NodeHandlerWrapper {
-            handler: self.handler.into_handler(&remote_info.0.peer_id(), &remote_info.1),
+            handler: self.handler.into_handler(peer_id, &remote_info.1),
             negotiating_in: Vec::new(),
             negotiating_out: Vec::new(),
             queued_dial_upgrades: Vec::new(),
             unique_dial_upgrade_id: 0,
             shutdown: Shutdown::None,
+            keep_alive: ConnectionKeepAlive::new(keep_alive),
+            rate_limiter: self.rate_limiter.map(|limiter| (limiter, peer_id.clone())),
+            capabilities: self.capabilities.map(|capabilities| (capabilities, peer_id.clone())),
         }
     }
 }
 
 /// Wraps around an implementation of `ProtocolsHandler`, and implements `NodeHandler`.
-// TODO: add a caching system for protocols that are supported or not
 pub struct NodeHandlerWrapper<TProtoHandler>
 where
     TProtoHandler: ProtocolsHandler,
@@ -101,22 +142,61 @@ where
     /// The underlying handler.
     handler: TProtoHandler,
     /// Futures that upgrade incoming substreams.
-    negotiating_in:
-        Vec<(InboundUpgradeApply<TProtoHandler::Substream, TProtoHandler::InboundProtocol>, Delay)>,
+    negotiating_in: Vec<(InboundUpgradeOf<TProtoHandler>, Delay)>,
     /// Futures that upgrade outgoing substreams. The first element of the tuple is the userdata
-    /// to pass back once successfully opened.
-    negotiating_out: Vec<(
-        TProtoHandler::OutboundOpenInfo,
-        OutboundUpgradeApply<TProtoHandler::Substream, TProtoHandler::OutboundProtocol>,
-        Delay,
-    )>,
-    /// For each outbound substream request, how to upgrade it. The first element of the tuple
-    /// is the unique identifier (see `unique_dial_upgrade_id`).
-    queued_dial_upgrades: Vec<(u64, (upgrade::Version, TProtoHandler::OutboundProtocol))>,
+    /// to pass back once successfully opened, and the second one the names of the proposed
+    /// protocols if they are tracked.
+    negotiating_out: Vec<NegotiatingOutbound<TProtoHandler>>,
+    /// Outbound substream requests whose substream hasn't been opened yet.
+    queued_dial_upgrades: Vec<QueuedDialUpgrade<TProtoHandler>>,
     /// Unique identifier assigned to each queued dial upgrade.
     unique_dial_upgrade_id: u64,
     /// The currently planned connection & handler shutdown.
     shutdown: Shutdown,
+    /// The keep-alive policy of the swarm applied to this connection.
+    keep_alive: ConnectionKeepAlive,
+    /// The rate limiter of the inbound substreams of the swarm and the peer of the connection.
+    rate_limiter: Option<(InboundRateLimiter, PeerId)>,
+    /// The table of the protocols supported by the peers and the peer of the connection.
+    capabilities: Option<(ProtocolCapabilities, PeerId)>,
+}
+
+/// Future upgrading an incoming substream of a `ProtocolsHandler`.
+type InboundUpgradeOf<TProtoHandler> = InboundUpgradeApply<
+    <TProtoHandler as ProtocolsHandler>::Substream,
+    RateLimitedUpgrade<TrackedUpgrade<<TProtoHandler as ProtocolsHandler>::InboundProtocol>>,
+>;
+
+/// Future upgrading an outgoing substream of a `ProtocolsHandler`.
+type OutboundUpgradeOf<TProtoHandler> = OutboundUpgradeApply<
+    <TProtoHandler as ProtocolsHandler>::Substream,
+    TrackedUpgrade<<TProtoHandler as ProtocolsHandler>::OutboundProtocol>,
+>;
+
+/// An outgoing substream being negotiated, with its userdata, the names of the proposed
+/// protocols and its timeout.
+type NegotiatingOutbound<TProtoHandler> = (
+    <TProtoHandler as ProtocolsHandler>::OutboundOpenInfo,
+    Vec<Vec<u8>>,
+    OutboundUpgradeOf<TProtoHandler>,
+    Delay,
+);
+
+/// An outbound substream request waiting for its substream to be opened.
+struct QueuedDialUpgrade<TProtoHandler>
+where
+    TProtoHandler: ProtocolsHandler,
+{
+    /// The unique identifier of the request (see `unique_dial_upgrade_id`).
+    id: u64,
+    /// The userdata to pass back once successfully opened.
+    info: TProtoHandler::OutboundOpenInfo,
+    /// How to upgrade the substream.
+    upgrade: (upgrade::Version, TProtoHandler::OutboundProtocol),
+    /// The names of the protocols of the upgrade, if they are tracked.
+    protocols: Vec<Vec<u8>>,
+    /// The timeout of the negotiation.
+    timeout: Duration,
 }
 
 /// The options for a planned connection & handler shutdown.
@@ -185,9 +265,9 @@ where
     type OutEvent = TProtoHandler::OutEvent;
     type Error = NodeHandlerWrapperError<TProtoHandler::Error>;
     type Substream = TProtoHandler::Substream;
-    // The first element of the tuple is the unique upgrade identifier
-    // (see `unique_dial_upgrade_id`).
-    type OutboundOpenInfo = (u64, TProtoHandler::OutboundOpenInfo, Duration);
+    // The unique upgrade identifier (see `unique_dial_upgrade_id`). The userdata of the request
+    // is kept in `queued_dial_upgrades` so that it can be cancelled before the substream opens.
+    type OutboundOpenInfo = u64;
 
     fn inject_substream(
         &mut self,
@@ -198,27 +278,29 @@ where
             NodeHandlerEndpoint::Listener => {
                 let protocol = self.handler.listen_protocol();
                 let timeout = protocol.timeout().clone();
-                let upgrade = upgrade::apply_inbound(substream, protocol.into_upgrade().1);
+                let protocol = TrackedUpgrade::new(protocol.into_upgrade().1, self.capabilities.clone());
+                let protocol = RateLimitedUpgrade::new(protocol, self.rate_limiter.clone());
+                let upgrade = upgrade::apply_inbound(substream, protocol);
                 let timeout = Delay::new(timeout);
                 self.negotiating_in.push((upgrade, timeout));
             }
-            NodeHandlerEndpoint::Dialer((upgrade_id, user_data, timeout)) => {
+            NodeHandlerEndpoint::Dialer(upgrade_id) => {
                 let pos = match self
                     .queued_dial_upgrades
                     .iter()
-                    .position(|(id, _)| id == &upgrade_id)
+                    .position(|queued| queued.id == upgrade_id)
                 {
                     Some(p) => p,
-                    None => {
-                        debug_assert!(false, "Received an upgrade with an invalid upgrade ID");
-                        return;
-                    }
+                    // The request has been cancelled in the meantime; drop the substream.
+                    None => return,
                 };
 
-                let (_, (version, upgrade)) = self.queued_dial_upgrades.remove(pos);
+                let queued = self.queued_dial_upgrades.remove(pos);
+                let (version, upgrade) = queued.upgrade;
+                let upgrade = TrackedUpgrade::new(upgrade, self.capabilities.clone());
                 let upgrade = upgrade::apply_outbound(substream, upgrade, version);
-                let timeout = Delay::new(timeout);
-                self.negotiating_out.push((user_data, upgrade, timeout));
+                let timeout = Delay::new(queued.timeout);
+                self.negotiating_out.push((queued.info, queued.protocols, upgrade, timeout));
             }
         }
     }
@@ -229,6 +311,21 @@ where
     }
 
     fn poll(&mut self, cx: &mut Context) -> Poll<Result<NodeHandlerEvent<Self::OutboundOpenInfo, Self::OutEvent>, Self::Error>> {
+        // Drop the outbound substream requests cancelled by the handler, whether their substream
+        // is still being opened or already being negotiated.
+        for n in (0..self.queued_dial_upgrades.len()).rev() {
+            if self.handler.is_outbound_cancelled(&self.queued_dial_upgrades[n].info) {
+                let queued = self.queued_dial_upgrades.remove(n);
+                self.handler.inject_dial_upgrade_error(queued.info, ProtocolsHandlerUpgrErr::Cancelled);
+            }
+        }
+        for n in (0..self.negotiating_out.len()).rev() {
+            if self.handler.is_outbound_cancelled(&self.negotiating_out[n].0) {
+                let (upgr_info, _, _, _) = self.negotiating_out.swap_remove(n);
+                self.handler.inject_dial_upgrade_error(upgr_info, ProtocolsHandlerUpgrErr::Cancelled);
+            }
+        }
+
         // Continue negotiation of newly-opened substreams on the listening side.
         // We remove each element from `negotiating_in` one by one and add them back if not ready.
         for n in (0..self.negotiating_in.len()).rev() {
@@ -249,7 +346,7 @@ where
         // Continue negotiation of newly-opened substreams.
         // We remove each element from `negotiating_out` one by one and add them back if not ready.
         for n in (0..self.negotiating_out.len()).rev() {
-            let (upgr_info, mut in_progress, mut timeout) = self.negotiating_out.swap_remove(n);
+            let (upgr_info, protocols, mut in_progress, mut timeout) = self.negotiating_out.swap_remove(n);
             match Future::poll(Pin::new(&mut timeout), cx) {
                 Poll::Ready(Ok(_)) => {
                     let err = ProtocolsHandlerUpgrErr::Timeout;
@@ -268,9 +365,16 @@ where
                     self.handler.inject_fully_negotiated_outbound(upgrade, upgr_info);
                 }
                 Poll::Pending => {
-                    self.negotiating_out.push((upgr_info, in_progress, timeout));
+                    self.negotiating_out.push((upgr_info, protocols, in_progress, timeout));
                 }
                 Poll::Ready(Err(err)) => {
+                    if let (UpgradeError::Select(NegotiationError::Failed), Some((capabilities, peer_id))) =
+                        (&err, &self.capabilities)
+                    {
+                        for protocol in &protocols {
+                            capabilities.report_unsupported(peer_id, protocol);
+                        }
+                    }
                     let err = ProtocolsHandlerUpgrErr::Upgrade(err);
                     self.handler.inject_dial_upgrade_error(upgr_info, err);
                 }
@@ -282,8 +386,10 @@ where
         let poll_result = self.handler.poll(cx);
 
         // Ask the handler whether it wants the connection (and the handler itself)
-        // to be kept alive, which determines the planned shutdown, if any.
-        match (&mut self.shutdown, self.handler.connection_keep_alive()) {
+        // to be kept alive, which, together with the keep-alive policy of the swarm,
+        // determines the planned shutdown, if any.
+        let keep_alive = self.keep_alive.apply(self.handler.connection_keep_alive());
+        match (&mut self.shutdown, keep_alive) {
             (Shutdown::Later(timer, deadline), KeepAlive::Until(t)) =>
                 if *deadline != t {
                     *deadline = t;
@@ -302,13 +408,33 @@ where
                 protocol,
                 info,
             }) => {
-                let id = self.unique_dial_upgrade_id;
                 let timeout = protocol.timeout().clone();
+                let upgrade = protocol.into_upgrade();
+                let protocols = match &self.capabilities {
+                    Some((capabilities, peer_id)) => {
+                        let protocols = capabilities::protocol_names(&upgrade.1);
+                        if !protocols.is_empty() && protocols.iter().all(|p| capabilities.is_unsupported(peer_id, p)) {
+                            // Fail as if the peer had refused the protocols, and give the handler
+                            // a chance to react.
+                            let err = UpgradeError::Select(NegotiationError::Failed);
+                            self.handler.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Upgrade(err));
+                            cx.waker().wake_by_ref();
+                            return Poll::Pending
+                        }
+                        protocols
+                    },
+                    None => Vec::new(),
+                };
+                let id = self.unique_dial_upgrade_id;
                 self.unique_dial_upgrade_id += 1;
-                self.queued_dial_upgrades.push((id, protocol.into_upgrade()));
-                return Poll::Ready(Ok(
-                    NodeHandlerEvent::OutboundSubstreamRequest((id, info, timeout)),
-                ));
+                self.queued_dial_upgrades.push(QueuedDialUpgrade {
+                    id,
+                    info,
+                    upgrade,
+                    protocols,
+                    timeout,
+                });
+                return Poll::Ready(Ok(NodeHandlerEvent::OutboundSubstreamRequest(id)));
             }
             Poll::Ready(ProtocolsHandlerEvent::Close(err)) => return Poll::Ready(Err(err.into())),
             Poll::Pending => (),
@@ -330,3 +456,203 @@ where
         Poll::Pending
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols_handler::SubstreamProtocol;
+    use futures::{io::Cursor, task::noop_waker_ref};
+    use libp2p_core::{Endpoint, Negotiated, upgrade::{DeniedUpgrade, FromFnUpgrade, InboundUpgrade, OutboundUpgrade}};
+    use std::{collections::VecDeque, io};
+
+    /// Handler requesting outbound substreams and recording the cancelled ones.
+    #[derive(Default)]
+    struct CancellingHandler {
+        requests: VecDeque<u32>,
+        to_cancel: Vec<u32>,
+        cancelled: Vec<u32>,
+    }
+
+    impl ProtocolsHandler for CancellingHandler {
+        type InEvent = ();
+        type OutEvent = ();
+        type Error = io::Error;
+        type Substream = Cursor<Vec<u8>>;
+        type InboundProtocol = DeniedUpgrade;
+        type OutboundProtocol = DeniedUpgrade;
+        type OutboundOpenInfo = u32;
+
+        fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
+            SubstreamProtocol::new(DeniedUpgrade)
+        }
+
+        fn inject_fully_negotiated_inbound(
+            &mut self,
+            _: <Self::InboundProtocol as InboundUpgrade<Negotiated<Self::Substream>>>::Output
+        ) {
+        }
+
+        fn inject_fully_negotiated_outbound(
+            &mut self,
+            _: <Self::OutboundProtocol as OutboundUpgrade<Negotiated<Self::Substream>>>::Output,
+            _: Self::OutboundOpenInfo
+        ) {
+        }
+
+        fn inject_event(&mut self, _: Self::InEvent) {}
+
+        fn inject_dial_upgrade_error(&mut self, info: Self::OutboundOpenInfo, error: ProtocolsHandlerUpgrErr<<Self::OutboundProtocol as OutboundUpgrade<Negotiated<Self::Substream>>>::Error>) {
+            match error {
+                ProtocolsHandlerUpgrErr::Cancelled => self.cancelled.push(info),
+                _ => panic!("Unexpected upgrade error for request {}", info),
+            }
+        }
+
+        fn is_outbound_cancelled(&self, info: &Self::OutboundOpenInfo) -> bool {
+            self.to_cancel.contains(info)
+        }
+
+        fn connection_keep_alive(&self) -> KeepAlive {
+            KeepAlive::Yes
+        }
+
+        fn poll(&mut self, _: &mut Context) -> Poll<
+            ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
+        > {
+            match self.requests.pop_front() {
+                Some(info) => Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(DeniedUpgrade),
+                    info,
+                }),
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    #[test]
+    fn cancelled_outbound_requests() {
+        let handler = CancellingHandler {
+            requests: vec![1, 2].into(),
+            .. CancellingHandler::default()
+        };
+        let remote = (PeerId::random(), ConnectedPoint::Dialer { address: "/memory/0".parse().unwrap() });
+        let mut wrapper = NodeHandlerWrapperBuilder::new(handler).into_handler(&remote);
+        let mut cx = Context::from_waker(noop_waker_ref());
+
+        let mut ids = Vec::new();
+        while let Poll::Ready(event) = wrapper.poll(&mut cx) {
+            match event {
+                Ok(NodeHandlerEvent::OutboundSubstreamRequest(id)) => ids.push(id),
+                _ => panic!("Unexpected event"),
+            }
+        }
+        assert_eq!(ids.len(), 2);
+
+        // The substream of the second request is being negotiated, the first one isn't open yet.
+        wrapper.inject_substream(Cursor::new(Vec::new()), NodeHandlerEndpoint::Dialer(ids[1]));
+        assert_eq!(wrapper.queued_dial_upgrades.len(), 1);
+        assert_eq!(wrapper.negotiating_out.len(), 1);
+
+        wrapper.handler.to_cancel = vec![1, 2];
+        assert!(wrapper.poll(&mut cx).is_pending());
+        wrapper.handler.cancelled.sort();
+        assert_eq!(wrapper.handler.cancelled, vec![1, 2]);
+        assert!(wrapper.queued_dial_upgrades.is_empty());
+        assert!(wrapper.negotiating_out.is_empty());
+
+        // The substream opened for a cancelled request is dropped.
+        wrapper.inject_substream(Cursor::new(Vec::new()), NodeHandlerEndpoint::Dialer(ids[0]));
+        assert!(wrapper.negotiating_out.is_empty());
+        assert!(wrapper.poll(&mut cx).is_pending());
+        assert_eq!(wrapper.handler.cancelled.len(), 2);
+    }
+
+    /// Outbound upgrade of the `/probe` protocol.
+    type ProbeUpgrade = FromFnUpgrade<
+        &'static [u8],
+        fn(Negotiated<Cursor<Vec<u8>>>, Endpoint) -> future::Ready<Result<(), io::Error>>,
+    >;
+
+    /// Handler requesting `/probe` substreams and counting the refused ones.
+    #[derive(Default)]
+    struct ProbingHandler {
+        requests: u32,
+        refused: u32,
+    }
+
+    impl ProtocolsHandler for ProbingHandler {
+        type InEvent = ();
+        type OutEvent = ();
+        type Error = io::Error;
+        type Substream = Cursor<Vec<u8>>;
+        type InboundProtocol = DeniedUpgrade;
+        type OutboundProtocol = ProbeUpgrade;
+        type OutboundOpenInfo = ();
+
+        fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
+            SubstreamProtocol::new(DeniedUpgrade)
+        }
+
+        fn inject_fully_negotiated_inbound(
+            &mut self,
+            _: <Self::InboundProtocol as InboundUpgrade<Negotiated<Self::Substream>>>::Output
+        ) {
+        }
+
+        fn inject_fully_negotiated_outbound(&mut self, _: (), _: Self::OutboundOpenInfo) {}
+
+        fn inject_event(&mut self, _: Self::InEvent) {}
+
+        fn inject_dial_upgrade_error(&mut self, _: Self::OutboundOpenInfo, error: ProtocolsHandlerUpgrErr<io::Error>) {
+            match error {
+                ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::Failed)) => self.refused += 1,
+                _ => panic!("Unexpected upgrade error"),
+            }
+        }
+
+        fn connection_keep_alive(&self) -> KeepAlive {
+            KeepAlive::Yes
+        }
+
+        fn poll(&mut self, _: &mut Context) -> Poll<
+            ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
+        > {
+            if self.requests == 0 {
+                return Poll::Pending
+            }
+            self.requests -= 1;
+            let upgrade: ProbeUpgrade = upgrade::from_fn(b"/probe", |_, _| future::ready(Ok(())));
+            Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(upgrade),
+                info: (),
+            })
+        }
+    }
+
+    #[test]
+    fn unsupported_protocols_fail_without_substream() {
+        let peer_id = PeerId::random();
+        let capabilities = ProtocolCapabilities::default();
+        capabilities.connected(&peer_id);
+        capabilities.set_advertised(&peer_id, &["/other"]);
+
+        let remote = (peer_id.clone(), ConnectedPoint::Dialer { address: "/memory/0".parse().unwrap() });
+        let mut wrapper = NodeHandlerWrapperBuilder::new(ProbingHandler { requests: 1, refused: 0 })
+            .with_protocol_capabilities(capabilities.clone())
+            .into_handler(&remote);
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(wrapper.poll(&mut cx).is_pending());
+        assert_eq!(wrapper.handler.refused, 1);
+        assert!(wrapper.queued_dial_upgrades.is_empty());
+
+        // Once the protocol is known to be supported, a substream is requested.
+        capabilities.report_supported(&peer_id, b"/probe");
+        wrapper.handler.requests = 1;
+        match wrapper.poll(&mut cx) {
+            Poll::Ready(Ok(NodeHandlerEvent::OutboundSubstreamRequest(_))) => {}
+            _ => panic!("Expected an outbound substream request"),
+        }
+        assert_eq!(wrapper.handler.refused, 1);
+        assert_eq!(wrapper.queued_dial_upgrades[0].protocols, vec![b"/probe".to_vec()]);
+    }
+}
@@ -166,6 +166,9 @@ where
             (EitherOutput::First(info), ProtocolsHandlerUpgrErr::Timer) => {
                 self.proto1.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Timer)
             },
+            (EitherOutput::First(info), ProtocolsHandlerUpgrErr::Cancelled) => {
+                self.proto1.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Cancelled)
+            },
             (EitherOutput::First(info), ProtocolsHandlerUpgrErr::Timeout) => {
                 self.proto1.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Timeout)
             },
@@ -175,6 +178,9 @@ where
             (EitherOutput::First(info), ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(EitherError::A(err)))) => {
                 self.proto1.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(err)))
             },
+            (EitherOutput::First(info), ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Timeout(stage))) => {
+                self.proto1.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Timeout(stage)))
+            },
             (EitherOutput::First(_), ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(EitherError::B(_)))) => {
                 panic!("Wrong API usage; the upgrade error doesn't match the outbound open info");
             },
@@ -184,18 +190,32 @@ where
             (EitherOutput::Second(info), ProtocolsHandlerUpgrErr::Timer) => {
                 self.proto2.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Timer)
             },
+            (EitherOutput::Second(info), ProtocolsHandlerUpgrErr::Cancelled) => {
+                self.proto2.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Cancelled)
+            },
             (EitherOutput::Second(info), ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(err))) => {
                 self.proto2.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(err)))
             },
             (EitherOutput::Second(info), ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(EitherError::B(err)))) => {
                 self.proto2.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(err)))
             },
+            (EitherOutput::Second(info), ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Timeout(stage))) => {
+                self.proto2.inject_dial_upgrade_error(info, ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Timeout(stage)))
+            },
             (EitherOutput::Second(_), ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(EitherError::A(_)))) => {
                 panic!("Wrong API usage; the upgrade error doesn't match the outbound open info");
             },
         }
     }
 
+    #[inline]
+    fn is_outbound_cancelled(&self, info: &Self::OutboundOpenInfo) -> bool {
+        match info {
+            EitherOutput::First(info) => self.proto1.is_outbound_cancelled(info),
+            EitherOutput::Second(info) => self.proto2.is_outbound_cancelled(info),
+        }
+    }
+
     #[inline]
     fn connection_keep_alive(&self) -> KeepAlive {
         cmp::max(self.proto1.connection_keep_alive(), self.proto2.connection_keep_alive())
@@ -0,0 +1,321 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Rate limiting of the inbound substreams.
+//!
+//! The [`InboundRateLimits`] of the swarm give, for each protocol name, the [`RateLimit`] at
+//! which a single peer may open inbound substreams for the protocol. Each peer has a token
+//! bucket per protocol: negotiating an inbound substream takes a token, and the tokens are
+//! given back at a steady rate. A substream negotiated while the bucket is empty is reset
+//! before reaching the `ProtocolsHandler`, and the swarm produces a
+//! [`SwarmEvent::InboundSubstreamRateLimited`](crate::SwarmEvent::InboundSubstreamRateLimited).
+
+use futures::{future, prelude::*};
+use libp2p_core::{PeerId, upgrade::{InboundUpgrade, ProtocolName, UpgradeInfo}};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use wasm_timer::Instant;
+
+/// Rate at which a peer may open inbound substreams for a protocol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RateLimit {
+    burst: u32,
+    interval: Duration,
+}
+
+impl RateLimit {
+    /// Allows up to `burst` substreams at once, then one substream per `interval`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `burst` is 0.
+    pub fn new(burst: u32, interval: Duration) -> Self {
+        assert!(burst > 0, "the burst must be at least 1");
+        RateLimit { burst, interval }
+    }
+
+    /// Returns the number of substreams allowed at once.
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// Returns the interval at which a substream is allowed again.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+/// The rate limits of the inbound substreams of the swarm, for each protocol name.
+///
+/// The substreams of the protocols without a limit are not limited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InboundRateLimits {
+    default: Option<RateLimit>,
+    protocols: HashMap<Vec<u8>, RateLimit>,
+}
+
+impl InboundRateLimits {
+    /// Creates limits which don't limit any protocol.
+    pub fn new() -> Self {
+        InboundRateLimits::default()
+    }
+
+    /// Sets the limit of the protocols without a limit of their own.
+    pub fn default_limit(mut self, limit: RateLimit) -> Self {
+        self.default = Some(limit);
+        self
+    }
+
+    /// Sets the limit of the given protocol.
+    pub fn protocol(mut self, name: impl Into<Vec<u8>>, limit: RateLimit) -> Self {
+        self.protocols.insert(name.into(), limit);
+        self
+    }
+
+    /// Returns the limit of the given protocol, if any.
+    pub fn get(&self, name: &[u8]) -> Option<RateLimit> {
+        self.protocols.get(name).copied().or(self.default)
+    }
+
+    /// Returns true if no protocol is limited.
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.protocols.is_empty()
+    }
+}
+
+/// Token bucket of a peer for a protocol.
+#[derive(Debug)]
+struct Bucket {
+    tokens: u32,
+    /// When a token was last given back, or when the bucket was last full.
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn new(limit: &RateLimit, now: Instant) -> Self {
+        Bucket { tokens: limit.burst, refilled: now }
+    }
+
+    /// Takes a token, if any is left.
+    fn try_take(&mut self, limit: &RateLimit, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.refilled);
+        let refill = if limit.interval == Duration::from_secs(0) {
+            u128::from(limit.burst)
+        } else {
+            elapsed.as_nanos() / limit.interval.as_nanos()
+        };
+        if refill > 0 {
+            let missing = limit.burst.saturating_sub(self.tokens);
+            if refill >= u128::from(missing) {
+                self.tokens = limit.burst;
+                self.refilled = now;
+            } else {
+                // `refill` is smaller than `missing`, hence fits in a `u32`.
+                self.tokens += refill as u32;
+                self.refilled += limit.interval * refill as u32;
+            }
+        }
+
+        if self.tokens == 0 {
+            return false
+        }
+        self.tokens -= 1;
+        true
+    }
+}
+
+/// State shared by the swarm and the handlers of its connections.
+#[derive(Debug)]
+struct Shared {
+    limits: InboundRateLimits,
+    buckets: HashMap<(PeerId, Vec<u8>), Bucket>,
+    /// The substreams denied since the swarm was last polled.
+    denied: VecDeque<(PeerId, Vec<u8>)>,
+    /// The task of the swarm, to wake up once a substream is denied.
+    waker: Option<Waker>,
+}
+
+/// Applies the [`InboundRateLimits`] of the swarm.
+///
+/// Cheaply cloneable handle shared by the swarm and the handlers of its connections.
+#[derive(Debug, Clone)]
+pub(crate) struct InboundRateLimiter {
+    inner: Arc<Mutex<Shared>>,
+}
+
+impl InboundRateLimiter {
+    pub(crate) fn new(limits: InboundRateLimits) -> Self {
+        InboundRateLimiter {
+            inner: Arc::new(Mutex::new(Shared {
+                limits,
+                buckets: HashMap::new(),
+                denied: VecDeque::new(),
+                waker: None,
+            })),
+        }
+    }
+
+    /// Takes a token of the peer for the protocol. Returns false, and records the denial, if the
+    /// peer has exceeded the limit.
+    pub(crate) fn allow(&self, peer_id: &PeerId, protocol: &[u8]) -> bool {
+        let mut shared = self.inner.lock().expect("The lock is never poisoned; QED");
+        let limit = match shared.limits.get(protocol) {
+            Some(limit) => limit,
+            None => return true,
+        };
+        let now = Instant::now();
+        let allowed = shared.buckets
+            .entry((peer_id.clone(), protocol.to_vec()))
+            .or_insert_with(|| Bucket::new(&limit, now))
+            .try_take(&limit, now);
+        if !allowed {
+            shared.denied.push_back((peer_id.clone(), protocol.to_vec()));
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        }
+        allowed
+    }
+
+    /// Forgets about the buckets of a peer.
+    pub(crate) fn remove_peer(&self, peer_id: &PeerId) {
+        let mut shared = self.inner.lock().expect("The lock is never poisoned; QED");
+        shared.buckets.retain(|(peer, _), _| peer != peer_id);
+    }
+
+    /// Returns the next denied substream, as the peer and the protocol name.
+    pub(crate) fn poll_denied(&self, cx: &mut Context) -> Poll<(PeerId, Vec<u8>)> {
+        let mut shared = self.inner.lock().expect("The lock is never poisoned; QED");
+        match shared.denied.pop_front() {
+            Some(denied) => Poll::Ready(denied),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Inbound upgrade applying the rate limits of the swarm to the negotiated protocol before
+/// upgrading the substream.
+///
+/// The error is `None` if the substream has been denied, in which case it is dropped without
+/// being upgraded.
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimitedUpgrade<TUpgrade> {
+    inner: TUpgrade,
+    limiter: Option<(InboundRateLimiter, PeerId)>,
+}
+
+impl<TUpgrade> RateLimitedUpgrade<TUpgrade> {
+    pub(crate) fn new(inner: TUpgrade, limiter: Option<(InboundRateLimiter, PeerId)>) -> Self {
+        RateLimitedUpgrade { inner, limiter }
+    }
+}
+
+impl<TUpgrade> UpgradeInfo for RateLimitedUpgrade<TUpgrade>
+where
+    TUpgrade: UpgradeInfo,
+{
+    type Info = TUpgrade::Info;
+    type InfoIter = TUpgrade::InfoIter;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.inner.protocol_info()
+    }
+}
+
+impl<TUpgrade, C> InboundUpgrade<C> for RateLimitedUpgrade<TUpgrade>
+where
+    TUpgrade: InboundUpgrade<C>,
+{
+    type Output = TUpgrade::Output;
+    type Error = Option<TUpgrade::Error>;
+    type Future = future::Map<
+        future::OptionFuture<TUpgrade::Future>,
+        fn(Option<Result<TUpgrade::Output, TUpgrade::Error>>) -> Result<TUpgrade::Output, Option<TUpgrade::Error>>,
+    >;
+
+    fn upgrade_inbound(self, socket: C, info: Self::Info) -> Self::Future {
+        let allowed = match &self.limiter {
+            Some((limiter, peer_id)) => limiter.allow(peer_id, info.protocol_name()),
+            None => true,
+        };
+        let upgrade = if allowed {
+            Some(self.inner.upgrade_inbound(socket, info))
+        } else {
+            log::debug!("Rate limited inbound substream of {:?}", String::from_utf8_lossy(info.protocol_name()));
+            None
+        };
+        let wrap: fn(_) -> _ = |result: Option<Result<_, _>>| match result {
+            Some(result) => result.map_err(Some),
+            None => Err(None),
+        };
+        future::OptionFuture::from(upgrade).map(wrap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_refills_at_the_limit_rate() {
+        let limit = RateLimit::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        let mut bucket = Bucket::new(&limit, start);
+
+        assert!(bucket.try_take(&limit, start));
+        assert!(bucket.try_take(&limit, start));
+        assert!(!bucket.try_take(&limit, start + Duration::from_secs(9)));
+        assert!(bucket.try_take(&limit, start + Duration::from_secs(10)));
+        assert!(!bucket.try_take(&limit, start + Duration::from_secs(15)));
+        // Refilling never exceeds the burst.
+        assert!(bucket.try_take(&limit, start + Duration::from_secs(100)));
+        assert!(bucket.try_take(&limit, start + Duration::from_secs(100)));
+        assert!(!bucket.try_take(&limit, start + Duration::from_secs(100)));
+    }
+
+    #[test]
+    fn limiter_applies_per_peer_and_protocol() {
+        let limits = InboundRateLimits::new()
+            .protocol(&b"/limited"[..], RateLimit::new(1, Duration::from_secs(3600)));
+        let limiter = InboundRateLimiter::new(limits);
+        let peer1 = PeerId::random();
+        let peer2 = PeerId::random();
+
+        assert!(limiter.allow(&peer1, b"/limited"));
+        assert!(!limiter.allow(&peer1, b"/limited"));
+        assert!(limiter.allow(&peer2, b"/limited"));
+        assert!(limiter.allow(&peer1, b"/other"));
+        assert!(limiter.allow(&peer1, b"/other"));
+
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert_eq!(limiter.poll_denied(&mut cx), Poll::Ready((peer1.clone(), b"/limited".to_vec())));
+        assert!(limiter.poll_denied(&mut cx).is_pending());
+
+        limiter.remove_peer(&peer1);
+        assert!(limiter.allow(&peer1, b"/limited"));
+    }
+}
@@ -99,6 +99,16 @@ impl Addresses {
         self.registry.push(r)
     }
 
+    /// Remove a [`Multiaddr`] from the collection, along with its reports.
+    ///
+    /// Returns `true` if the address was part of the collection.
+    pub fn remove(&mut self, a: &Multiaddr) -> bool {
+        self.reports.retain(|r| r != a);
+        let len = self.registry.len();
+        self.registry.retain(|r| r.addr != *a);
+        self.registry.len() != len
+    }
+
     /// Return an iterator over all [`Multiaddr`] values.
     ///
     /// The iteration is ordered by descending score.
@@ -223,6 +233,24 @@ mod tests {
         assert!(addresses.iter().find(|a| **a == single).is_none());
     }
 
+    #[test]
+    fn removed_addresses_disappear() {
+        let mut addresses = Addresses::default();
+        let a: Multiaddr = "/tcp/2108".parse().unwrap();
+        let b: Multiaddr = "/tcp/120".parse().unwrap();
+        addresses.add(a.clone());
+        addresses.add(a.clone());
+        addresses.add(b.clone());
+
+        assert!(addresses.remove(&a));
+        assert!(!addresses.remove(&a));
+        assert_eq!(addresses.iter().collect::<Vec<_>>(), vec![&b]);
+
+        // Reports of the removed address no longer count.
+        addresses.add(a.clone());
+        assert_eq!(addresses.iter().collect::<Vec<_>>(), vec![&b, &a]);
+    }
+
     #[test]
     fn record_score_equals_last_n_reports() {
         #[derive(PartialEq, Eq, Clone, Hash, Debug)]
@@ -0,0 +1,211 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Subscriptions to the events of the swarm.
+//!
+//! Besides polling the swarm for its events, any number of observers, such as metrics collectors
+//! or user interfaces, can subscribe to the events of the swarm with
+//! [`ExpandedSwarm::subscribe`](crate::ExpandedSwarm::subscribe). Each [`Subscription`] is a
+//! `Stream` receiving a copy of the connection lifecycle, listener and dial events, as a
+//! [`SwarmNotification`], while the owner of the swarm keeps polling it.
+//!
+//! The events of the `NetworkBehaviour` and the statistics of the connections are not broadcast.
+//!
+//! A subscription buffers a bounded number of events. The events are dropped for the
+//! subscriptions whose buffer is full, so that slow subscribers never slow down the swarm.
+
+use crate::{FailureKind, SwarmEvent};
+use futures::{channel::mpsc, prelude::*};
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
+use libp2p_core::nodes::network::ConnectionLimit;
+use std::{pin::Pin, task::{Context, Poll}};
+
+/// Default number of events buffered by a [`Subscription`].
+pub const DEFAULT_BUFFER: usize = 64;
+
+/// Event of the swarm broadcast to its subscriptions.
+///
+/// Mirrors the variants of [`SwarmEvent`] which are about the connections, the listeners and the
+/// dials.
+#[derive(Debug, Clone)]
+pub enum SwarmNotification {
+    /// We are now connected to the given peer.
+    Connected {
+        /// The peer we are connected to.
+        peer_id: PeerId,
+        /// The address we dialed, or the addresses of the incoming connection.
+        endpoint: ConnectedPoint,
+        /// The tag of the dial that established the connection, if any.
+        tag: Option<u64>,
+    },
+    /// We are now disconnected from the given peer.
+    Disconnected(PeerId),
+    /// One of our listeners has reported a new local listening address.
+    NewListenAddr(Multiaddr),
+    /// One of our listeners has reported the expiration of a listening address.
+    ExpiredListenAddr(Multiaddr),
+    /// Tried to dial an address but it ended up being unreachable.
+    UnreachableAddr {
+        /// `PeerId` that we were trying to reach, if known in advance.
+        peer_id: Option<PeerId>,
+        /// Address that we failed to reach.
+        address: Multiaddr,
+        /// Summary of the failure.
+        kind: FailureKind,
+        /// Description of the failure.
+        error: String,
+        /// The tag of the dial, if any.
+        tag: Option<u64>,
+    },
+    /// A dial has been cancelled through its `DialHandle`.
+    DialCancelled {
+        /// `PeerId` that we were trying to reach, if known in advance.
+        peer_id: Option<PeerId>,
+        /// Address that was being dialed.
+        address: Multiaddr,
+        /// The tag of the dial, if any.
+        tag: Option<u64>,
+    },
+    /// A connection has been denied by the connection limits of the swarm.
+    ConnectionDenied {
+        /// `PeerId` that we were trying to reach, if known in advance.
+        peer_id: Option<PeerId>,
+        /// The address being dialed, or the addresses of the incoming connection.
+        endpoint: ConnectedPoint,
+        /// The limit that has been reached.
+        limit: ConnectionLimit,
+        /// The tag of the dial, if any.
+        tag: Option<u64>,
+    },
+    /// A connection has been denied by the connection gater of the swarm.
+    ConnectionGated {
+        /// The peer of the connection, if known.
+        peer_id: Option<PeerId>,
+        /// The address being dialed, or the addresses of the connection.
+        endpoint: ConnectedPoint,
+        /// The tag of the dial, if any.
+        tag: Option<u64>,
+    },
+    /// Starting to try to reach the given peer.
+    StartConnect(PeerId),
+    /// An inbound substream has been reset because the peer exceeded the rate limit of its
+    /// protocol.
+    InboundSubstreamRateLimited {
+        /// The peer that opened the substream.
+        peer_id: PeerId,
+        /// The name of the negotiated protocol.
+        protocol: Vec<u8>,
+    },
+}
+
+impl SwarmNotification {
+    /// Builds the notification corresponding to an event of the swarm, if it is broadcast.
+    fn from_event<TBvEv>(event: &SwarmEvent<TBvEv>) -> Option<Self> {
+        let notification = match event {
+            SwarmEvent::Behaviour(_) | SwarmEvent::ConnectionStats { .. } => return None,
+            SwarmEvent::Connected { peer_id, endpoint, tag } => SwarmNotification::Connected {
+                peer_id: peer_id.clone(),
+                endpoint: endpoint.clone(),
+                tag: *tag,
+            },
+            SwarmEvent::Disconnected(peer_id) => SwarmNotification::Disconnected(peer_id.clone()),
+            SwarmEvent::NewListenAddr(addr) => SwarmNotification::NewListenAddr(addr.clone()),
+            SwarmEvent::ExpiredListenAddr(addr) => SwarmNotification::ExpiredListenAddr(addr.clone()),
+            SwarmEvent::UnreachableAddr { peer_id, address, error, tag } => SwarmNotification::UnreachableAddr {
+                peer_id: peer_id.clone(),
+                address: address.clone(),
+                kind: error.kind(),
+                error: error.to_string(),
+                tag: *tag,
+            },
+            SwarmEvent::DialCancelled { peer_id, address, tag } => SwarmNotification::DialCancelled {
+                peer_id: peer_id.clone(),
+                address: address.clone(),
+                tag: *tag,
+            },
+            SwarmEvent::ConnectionDenied { peer_id, endpoint, limit, tag } => SwarmNotification::ConnectionDenied {
+                peer_id: peer_id.clone(),
+                endpoint: endpoint.clone(),
+                limit: *limit,
+                tag: *tag,
+            },
+            SwarmEvent::ConnectionGated { peer_id, endpoint, tag } => SwarmNotification::ConnectionGated {
+                peer_id: peer_id.clone(),
+                endpoint: endpoint.clone(),
+                tag: *tag,
+            },
+            SwarmEvent::StartConnect(peer_id) => SwarmNotification::StartConnect(peer_id.clone()),
+            SwarmEvent::InboundSubstreamRateLimited { peer_id, protocol } =>
+                SwarmNotification::InboundSubstreamRateLimited {
+                    peer_id: peer_id.clone(),
+                    protocol: protocol.clone(),
+                },
+        };
+        Some(notification)
+    }
+}
+
+/// Stream of the [`SwarmNotification`]s of a swarm.
+///
+/// The stream ends once the swarm is dropped. Dropping the subscription unsubscribes from the
+/// swarm.
+#[derive(Debug)]
+pub struct Subscription {
+    receiver: mpsc::Receiver<SwarmNotification>,
+}
+
+impl Stream for Subscription {
+    type Item = SwarmNotification;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Stream::poll_next(Pin::new(&mut self.receiver), cx)
+    }
+}
+
+/// The subscriptions to the events of a swarm.
+#[derive(Debug, Default)]
+pub(crate) struct Subscribers {
+    senders: Vec<mpsc::Sender<SwarmNotification>>,
+}
+
+impl Subscribers {
+    /// Creates a new subscription buffering up to `buffer` events.
+    pub(crate) fn subscribe(&mut self, buffer: usize) -> Subscription {
+        let (sender, receiver) = mpsc::channel(buffer);
+        self.senders.push(sender);
+        Subscription { receiver }
+    }
+
+    /// Broadcasts an event of the swarm to the subscriptions, and forgets about the dropped ones.
+    pub(crate) fn notify<TBvEv>(&mut self, event: &SwarmEvent<TBvEv>) {
+        if self.senders.is_empty() {
+            return;
+        }
+        let notification = match SwarmNotification::from_event(event) {
+            Some(notification) => notification,
+            None => return,
+        };
+        self.senders.retain(|sender| !sender.is_closed());
+        for sender in &mut self.senders {
+            // The event is dropped for the subscriptions whose buffer is full.
+            let _ = sender.try_send(notification.clone());
+        }
+    }
+}
@@ -28,32 +28,123 @@ use crate::protocols_handler::{
     IntoProtocolsHandler
 };
 use libp2p_core::{
+    AddressFilter,
     ConnectedPoint,
     PeerId,
     Multiaddr,
     Negotiated,
     either::EitherOutput,
+    nodes::ListenerId,
     upgrade::{InboundUpgrade, OutboundUpgrade, DeniedUpgrade, EitherUpgrade}
 };
-use std::{error, task::Context, task::Poll};
+use std::{collections::{HashMap, VecDeque}, error, fmt, task::Context, task::Poll};
+
+type THandler<TBehaviour> =
+    <<TBehaviour as NetworkBehaviour>::ProtocolsHandler as IntoProtocolsHandler>::Handler;
 
 /// Implementation of `NetworkBehaviour` that can be either in the disabled or enabled state.
 ///
-/// The state can only be chosen at initialization.
-pub struct Toggle<TBehaviour> {
+/// The state can be changed at any time with [`Toggle::enable`] and [`Toggle::disable`]:
+///
+/// - An enabled behaviour is informed of the current listen and external addresses, the current
+///   address filter and the connected peers, and a handler is created for each existing
+///   connection.
+/// - A disabled behaviour is informed that all peers disconnected, and its handlers are removed
+///   from the existing connections. Substreams that were being negotiated by these handlers are
+///   dropped.
+pub struct Toggle<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour,
+{
     inner: Option<TBehaviour>,
+    /// Incremented every time the behaviour is enabled or disabled, so that handlers can tell
+    /// apart the events of the current behaviour from the ones of a previous state.
+    generation: u64,
+    /// The connected peers and the endpoints of the connections.
+    connected: HashMap<PeerId, ConnectedPoint>,
+    /// The addresses we are listening on.
+    listen_addrs: Vec<Multiaddr>,
+    /// The external addresses of the local node.
+    external_addrs: Vec<Multiaddr>,
+    /// The last address filter reported by the swarm.
+    address_filter: Option<AddressFilter>,
+    /// Events to send to the handlers before polling the behaviour.
+    pending_events: VecDeque<(PeerId, ToggleInEvent<THandler<TBehaviour>>)>,
 }
 
-impl<TBehaviour> Toggle<TBehaviour> {
+impl<TBehaviour> Toggle<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour,
+{
     /// Returns `true` if `Toggle` is enabled and `false` if it's disabled.
     pub fn is_enabled(&self) -> bool {
         self.inner.is_some()
     }
+
+    /// Returns a reference to the behaviour, if enabled.
+    pub fn as_ref(&self) -> Option<&TBehaviour> {
+        self.inner.as_ref()
+    }
+
+    /// Returns a mutable reference to the behaviour, if enabled.
+    pub fn as_mut(&mut self) -> Option<&mut TBehaviour> {
+        self.inner.as_mut()
+    }
+
+    /// Enables the given behaviour, and returns the previously enabled one.
+    pub fn enable(&mut self, mut behaviour: TBehaviour) -> Option<TBehaviour> {
+        let previous = self.disable();
+        self.generation += 1;
+
+        for addr in &self.listen_addrs {
+            behaviour.inject_new_listen_addr(addr);
+        }
+        for addr in &self.external_addrs {
+            behaviour.inject_new_external_addr(addr);
+        }
+        if let Some(filter) = self.address_filter.as_ref() {
+            behaviour.inject_address_filter(filter);
+        }
+        for (peer_id, endpoint) in &self.connected {
+            let handler = behaviour.new_handler().into_handler(peer_id, endpoint);
+            let event = InEvent::Enable { generation: self.generation, handler };
+            self.pending_events.push_back((peer_id.clone(), ToggleInEvent(event)));
+            behaviour.inject_connected(peer_id.clone(), endpoint.clone());
+        }
+
+        self.inner = Some(behaviour);
+        previous
+    }
+
+    /// Disables the behaviour, and returns it if it was enabled.
+    pub fn disable(&mut self) -> Option<TBehaviour> {
+        let mut behaviour = self.inner.take()?;
+        self.generation += 1;
+
+        for (peer_id, endpoint) in &self.connected {
+            behaviour.inject_disconnected(peer_id, endpoint.clone());
+            let event = InEvent::Disable { generation: self.generation };
+            self.pending_events.push_back((peer_id.clone(), ToggleInEvent(event)));
+        }
+
+        Some(behaviour)
+    }
 }
 
-impl<TBehaviour> From<Option<TBehaviour>> for Toggle<TBehaviour> {
+impl<TBehaviour> From<Option<TBehaviour>> for Toggle<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour,
+{
     fn from(inner: Option<TBehaviour>) -> Self {
-        Toggle { inner }
+        Toggle {
+            inner,
+            generation: 0,
+            connected: HashMap::new(),
+            listen_addrs: Vec::new(),
+            external_addrs: Vec::new(),
+            address_filter: None,
+            pending_events: VecDeque::new(),
+        }
     }
 }
 
@@ -66,7 +157,8 @@ where
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
         ToggleIntoProtoHandler {
-            inner: self.inner.as_mut().map(|i| i.new_handler())
+            inner: self.inner.as_mut().map(|i| i.new_handler()),
+            generation: self.generation,
         }
     }
 
@@ -75,18 +167,21 @@ where
     }
 
     fn inject_connected(&mut self, peer_id: PeerId, endpoint: ConnectedPoint) {
+        self.connected.insert(peer_id.clone(), endpoint.clone());
         if let Some(inner) = self.inner.as_mut() {
             inner.inject_connected(peer_id, endpoint)
         }
     }
 
     fn inject_disconnected(&mut self, peer_id: &PeerId, endpoint: ConnectedPoint) {
+        self.connected.remove(peer_id);
         if let Some(inner) = self.inner.as_mut() {
             inner.inject_disconnected(peer_id, endpoint)
         }
     }
 
     fn inject_replaced(&mut self, peer_id: PeerId, closed_endpoint: ConnectedPoint, new_endpoint: ConnectedPoint) {
+        self.connected.insert(peer_id.clone(), new_endpoint.clone());
         if let Some(inner) = self.inner.as_mut() {
             inner.inject_replaced(peer_id, closed_endpoint, new_endpoint)
         }
@@ -95,10 +190,34 @@ where
     fn inject_node_event(
         &mut self,
         peer_id: PeerId,
-        event: <<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::OutEvent
+        ToggleOutEvent(event): <<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::OutEvent
     ) {
-        if let Some(inner) = self.inner.as_mut() {
-            inner.inject_node_event(peer_id, event);
+        match event {
+            // The state may have changed between the creation of the handler and the connection
+            // being established, in which case we bring the handler up to date.
+            OutEvent::Created { generation } if generation != self.generation => {
+                let endpoint = match self.connected.get(&peer_id) {
+                    Some(endpoint) => endpoint,
+                    None => return,
+                };
+                let event = match self.inner.as_mut() {
+                    Some(inner) => InEvent::Enable {
+                        generation: self.generation,
+                        handler: inner.new_handler().into_handler(&peer_id, endpoint),
+                    },
+                    None => InEvent::Disable { generation: self.generation },
+                };
+                self.pending_events.push_back((peer_id, ToggleInEvent(event)));
+            }
+            OutEvent::Created { .. } => {}
+            OutEvent::Inner { generation, event } => {
+                if generation != self.generation {
+                    return;
+                }
+                if let Some(inner) = self.inner.as_mut() {
+                    inner.inject_node_event(peer_id, event);
+                }
+            }
         }
     }
 
@@ -115,28 +234,72 @@ where
     }
 
     fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        self.listen_addrs.push(addr.clone());
         if let Some(inner) = self.inner.as_mut() {
             inner.inject_new_listen_addr(addr)
         }
     }
 
     fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        self.listen_addrs.retain(|a| a != addr);
         if let Some(inner) = self.inner.as_mut() {
             inner.inject_expired_listen_addr(addr)
         }
     }
 
     fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        if !self.external_addrs.contains(addr) {
+            self.external_addrs.push(addr.clone());
+        }
         if let Some(inner) = self.inner.as_mut() {
             inner.inject_new_external_addr(addr)
         }
     }
 
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        self.external_addrs.retain(|a| a != addr);
+        if let Some(inner) = self.inner.as_mut() {
+            inner.inject_expired_external_addr(addr)
+        }
+    }
+
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn error::Error + 'static)) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.inject_listener_error(id, err)
+        }
+    }
+
+    fn inject_listener_closed(&mut self, id: ListenerId) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.inject_listener_closed(id)
+        }
+    }
+
+    fn inject_address_filter(&mut self, filter: &AddressFilter) {
+        self.address_filter = Some(filter.clone());
+        if let Some(inner) = self.inner.as_mut() {
+            inner.inject_address_filter(filter)
+        }
+    }
+
+    fn inject_shutdown(&mut self) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.inject_shutdown()
+        }
+    }
+
     fn poll(&mut self, cx: &mut Context, params: &mut impl PollParameters)
         -> Poll<NetworkBehaviourAction<<<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::InEvent, Self::OutEvent>>
     {
+        if let Some((peer_id, event)) = self.pending_events.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::SendEvent { peer_id, event });
+        }
+
+        let generation = self.generation;
         if let Some(inner) = self.inner.as_mut() {
-            inner.poll(cx, params)
+            inner.poll(cx, params).map(|action| {
+                action.map_in(|event| ToggleInEvent(InEvent::Inner { generation, event }))
+            })
         } else {
             Poll::Pending
         }
@@ -145,7 +308,7 @@ where
 
 impl<TEvent, TBehaviour> NetworkBehaviourEventProcess<TEvent> for Toggle<TBehaviour>
 where
-    TBehaviour: NetworkBehaviourEventProcess<TEvent>
+    TBehaviour: NetworkBehaviour + NetworkBehaviourEventProcess<TEvent>
 {
     fn inject_event(&mut self, event: TEvent) {
         if let Some(inner) = self.inner.as_mut() {
@@ -154,9 +317,181 @@ where
     }
 }
 
+/// Implementation of `NetworkBehaviour` that is enabled or disabled according to a predicate.
+///
+/// The predicate is evaluated every time the behaviour is polled or a handler is created, and the
+/// behaviour is enabled or disabled as in a [`Toggle`] whenever the result changes. Unlike with a
+/// `Toggle`, the disabled behaviour is kept, to be enabled again once the predicate holds.
+pub struct Conditional<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour,
+{
+    toggle: Toggle<TBehaviour>,
+    /// The behaviour, if disabled.
+    disabled: Option<TBehaviour>,
+    /// Returns whether the behaviour should be enabled.
+    predicate: Box<dyn FnMut() -> bool + Send>,
+}
+
+impl<TBehaviour> Conditional<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour,
+{
+    /// Creates a `Conditional` enabling the behaviour whenever `predicate` returns `true`.
+    pub fn new(behaviour: TBehaviour, predicate: impl FnMut() -> bool + Send + 'static) -> Self {
+        let mut conditional = Conditional {
+            toggle: Toggle::from(None),
+            disabled: Some(behaviour),
+            predicate: Box::new(predicate),
+        };
+        conditional.update();
+        conditional
+    }
+
+    /// Returns `true` if the behaviour is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.toggle.is_enabled()
+    }
+
+    /// Returns a reference to the behaviour, whether enabled or not.
+    pub fn get_ref(&self) -> &TBehaviour {
+        self.toggle.as_ref().or(self.disabled.as_ref())
+            .expect("The behaviour is either enabled or disabled; QED")
+    }
+
+    /// Returns a mutable reference to the behaviour, whether enabled or not.
+    pub fn get_mut(&mut self) -> &mut TBehaviour {
+        match self.disabled {
+            Some(ref mut behaviour) => behaviour,
+            None => self.toggle.as_mut().expect("The behaviour is either enabled or disabled; QED"),
+        }
+    }
+
+    /// Evaluates the predicate, enabling or disabling the behaviour accordingly, and returns
+    /// whether the behaviour is enabled.
+    pub fn update(&mut self) -> bool {
+        let enable = (self.predicate)();
+        if enable {
+            if let Some(behaviour) = self.disabled.take() {
+                self.toggle.enable(behaviour);
+            }
+        } else if self.disabled.is_none() {
+            self.disabled = self.toggle.disable();
+        }
+        enable
+    }
+}
+
+impl<TBehaviour> fmt::Debug for Conditional<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Conditional")
+            .field("behaviour", self.get_ref())
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+impl<TBehaviour> NetworkBehaviour for Conditional<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour
+{
+    type ProtocolsHandler = ToggleIntoProtoHandler<TBehaviour::ProtocolsHandler>;
+    type OutEvent = TBehaviour::OutEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        self.update();
+        self.toggle.new_handler()
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.toggle.addresses_of_peer(peer_id)
+    }
+
+    fn inject_connected(&mut self, peer_id: PeerId, endpoint: ConnectedPoint) {
+        self.toggle.inject_connected(peer_id, endpoint)
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId, endpoint: ConnectedPoint) {
+        self.toggle.inject_disconnected(peer_id, endpoint)
+    }
+
+    fn inject_replaced(&mut self, peer_id: PeerId, closed_endpoint: ConnectedPoint, new_endpoint: ConnectedPoint) {
+        self.toggle.inject_replaced(peer_id, closed_endpoint, new_endpoint)
+    }
+
+    fn inject_node_event(
+        &mut self,
+        peer_id: PeerId,
+        event: <<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::OutEvent
+    ) {
+        self.toggle.inject_node_event(peer_id, event)
+    }
+
+    fn inject_addr_reach_failure(&mut self, peer_id: Option<&PeerId>, addr: &Multiaddr, error: &dyn error::Error) {
+        self.toggle.inject_addr_reach_failure(peer_id, addr, error)
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        self.toggle.inject_dial_failure(peer_id)
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        self.toggle.inject_new_listen_addr(addr)
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        self.toggle.inject_expired_listen_addr(addr)
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        self.toggle.inject_new_external_addr(addr)
+    }
+
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        self.toggle.inject_expired_external_addr(addr)
+    }
+
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn error::Error + 'static)) {
+        self.toggle.inject_listener_error(id, err)
+    }
+
+    fn inject_listener_closed(&mut self, id: ListenerId) {
+        self.toggle.inject_listener_closed(id)
+    }
+
+    fn inject_address_filter(&mut self, filter: &AddressFilter) {
+        self.toggle.inject_address_filter(filter)
+    }
+
+    fn inject_shutdown(&mut self) {
+        self.toggle.inject_shutdown()
+    }
+
+    fn poll(&mut self, cx: &mut Context, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<<<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::InEvent, Self::OutEvent>>
+    {
+        self.update();
+        self.toggle.poll(cx, params)
+    }
+}
+
+impl<TEvent, TBehaviour> NetworkBehaviourEventProcess<TEvent> for Conditional<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour + NetworkBehaviourEventProcess<TEvent>
+{
+    fn inject_event(&mut self, event: TEvent) {
+        self.toggle.inject_event(event)
+    }
+}
+
 /// Implementation of `IntoProtocolsHandler` that can be in the disabled state.
 pub struct ToggleIntoProtoHandler<TInner> {
     inner: Option<TInner>,
+    /// The generation of the `Toggle` when the handler was created.
+    generation: u64,
 }
 
 impl<TInner> IntoProtocolsHandler for ToggleIntoProtoHandler<TInner>
@@ -167,7 +502,9 @@ where
 
     fn into_handler(self, remote_peer_id: &PeerId, connected_point: &ConnectedPoint) -> Self::Handler {
         ToggleProtoHandler {
-            inner: self.inner.map(|h| h.into_handler(remote_peer_id, connected_point))
+            inner: self.inner.map(|h| h.into_handler(remote_peer_id, connected_point)),
+            generation: self.generation,
+            reported: false,
         }
     }
 
@@ -180,22 +517,67 @@ where
     }
 }
 
+/// Event sent by a [`Toggle`] to a [`ToggleProtoHandler`].
+pub struct ToggleInEvent<TInner: ProtocolsHandler>(InEvent<TInner>);
+
+enum InEvent<TInner: ProtocolsHandler> {
+    /// Enables the handler with the given inner handler.
+    Enable { generation: u64, handler: TInner },
+    /// Disables the handler.
+    Disable { generation: u64 },
+    /// An event for the inner handler.
+    Inner { generation: u64, event: TInner::InEvent },
+}
+
+impl<TInner> fmt::Debug for ToggleInEvent<TInner>
+where
+    TInner: ProtocolsHandler,
+    TInner::InEvent: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            InEvent::Enable { generation, .. } =>
+                f.debug_struct("Enable").field("generation", &generation).finish(),
+            InEvent::Disable { generation } =>
+                f.debug_struct("Disable").field("generation", &generation).finish(),
+            InEvent::Inner { generation, ref event } =>
+                f.debug_struct("Inner").field("generation", &generation).field("event", event).finish(),
+        }
+    }
+}
+
+/// Event sent by a [`ToggleProtoHandler`] to a [`Toggle`].
+#[derive(Debug)]
+pub struct ToggleOutEvent<TOutEvent>(OutEvent<TOutEvent>);
+
+#[derive(Debug)]
+enum OutEvent<TOutEvent> {
+    /// The generation of the `Toggle` when the handler was created.
+    Created { generation: u64 },
+    /// An event generated by the inner handler.
+    Inner { generation: u64, event: TOutEvent },
+}
+
 /// Implementation of `ProtocolsHandler` that can be in the disabled state.
 pub struct ToggleProtoHandler<TInner> {
     inner: Option<TInner>,
+    /// The generation of the `Toggle` the state of the handler corresponds to.
+    generation: u64,
+    /// Whether the generation the handler was created with has been reported.
+    reported: bool,
 }
 
 impl<TInner> ProtocolsHandler for ToggleProtoHandler<TInner>
 where
     TInner: ProtocolsHandler,
 {
-    type InEvent = TInner::InEvent;
-    type OutEvent = TInner::OutEvent;
+    type InEvent = ToggleInEvent<TInner>;
+    type OutEvent = ToggleOutEvent<TInner::OutEvent>;
     type Error = TInner::Error;
     type Substream = TInner::Substream;
     type InboundProtocol = EitherUpgrade<TInner::InboundProtocol, DeniedUpgrade>;
     type OutboundProtocol = TInner::OutboundProtocol;
-    type OutboundOpenInfo = TInner::OutboundOpenInfo;
+    type OutboundOpenInfo = (u64, TInner::OutboundOpenInfo);
 
     fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
         if let Some(inner) = self.inner.as_ref() {
@@ -214,27 +596,64 @@ where
             EitherOutput::Second(v) => void::unreachable(v),
         };
 
-        self.inner.as_mut().expect("Can't receive an inbound substream if disabled; QED")
-            .inject_fully_negotiated_inbound(out)
+        // The handler may have been disabled while the substream was being negotiated, in which
+        // case the substream is dropped.
+        if let Some(inner) = self.inner.as_mut() {
+            inner.inject_fully_negotiated_inbound(out)
+        }
     }
 
     fn inject_fully_negotiated_outbound(
         &mut self,
         out: <Self::OutboundProtocol as OutboundUpgrade<Negotiated<Self::Substream>>>::Output,
-        info: Self::OutboundOpenInfo
+        (generation, info): Self::OutboundOpenInfo
     ) {
-        self.inner.as_mut().expect("Can't receive an outbound substream if disabled; QED")
-            .inject_fully_negotiated_outbound(out, info)
+        // The substream was requested by the inner handler of a previous state.
+        if generation != self.generation {
+            return;
+        }
+        if let Some(inner) = self.inner.as_mut() {
+            inner.inject_fully_negotiated_outbound(out, info)
+        }
     }
 
-    fn inject_event(&mut self, event: Self::InEvent) {
-        self.inner.as_mut().expect("Can't receive events if disabled; QED")
-            .inject_event(event)
+    fn inject_event(&mut self, ToggleInEvent(event): Self::InEvent) {
+        match event {
+            InEvent::Enable { generation, handler } if generation > self.generation => {
+                self.inner = Some(handler);
+                self.generation = generation;
+            }
+            InEvent::Disable { generation } if generation > self.generation => {
+                self.inner = None;
+                self.generation = generation;
+            }
+            InEvent::Inner { generation, event } if generation == self.generation => {
+                if let Some(inner) = self.inner.as_mut() {
+                    inner.inject_event(event)
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn inject_dial_upgrade_error(&mut self, (generation, info): Self::OutboundOpenInfo, err: ProtocolsHandlerUpgrErr<<Self::OutboundProtocol as OutboundUpgrade<Negotiated<Self::Substream>>>::Error>) {
+        if generation != self.generation {
+            return;
+        }
+        if let Some(inner) = self.inner.as_mut() {
+            inner.inject_dial_upgrade_error(info, err)
+        }
     }
 
-    fn inject_dial_upgrade_error(&mut self, info: Self::OutboundOpenInfo, err: ProtocolsHandlerUpgrErr<<Self::OutboundProtocol as OutboundUpgrade<Negotiated<Self::Substream>>>::Error>) {
-        self.inner.as_mut().expect("Can't receive an outbound substream if disabled; QED")
-            .inject_dial_upgrade_error(info, err)
+    fn is_outbound_cancelled(&self, (generation, info): &Self::OutboundOpenInfo) -> bool {
+        // The requests of the inner handler of a previous state are no longer needed.
+        if *generation != self.generation {
+            return true;
+        }
+        match self.inner.as_ref() {
+            Some(inner) => inner.is_outbound_cancelled(info),
+            None => true,
+        }
     }
 
     fn connection_keep_alive(&self) -> KeepAlive {
@@ -248,8 +667,19 @@ where
     ) -> Poll<
         ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
     > {
+        if !self.reported {
+            self.reported = true;
+            let event = ToggleOutEvent(OutEvent::Created { generation: self.generation });
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(event));
+        }
+
+        let generation = self.generation;
         if let Some(inner) = self.inner.as_mut() {
-            inner.poll(cx)
+            inner.poll(cx).map(|event| {
+                event
+                    .map_outbound_open_info(|info| (generation, info))
+                    .map_custom(|event| ToggleOutEvent(OutEvent::Inner { generation, event }))
+            })
         } else {
             Poll::Pending
         }
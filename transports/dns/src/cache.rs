@@ -0,0 +1,201 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A cache of positive and negative answers in front of another resolver.
+
+use crate::resolver::{Lookup, RecordType, Resolver};
+use futures::{prelude::*, future::BoxFuture};
+use log::trace;
+use std::{cmp, collections::HashMap, io, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+/// Caches the answers of an inner resolver.
+///
+/// Answers are kept for their TTL, bounded by [`CachingResolver::max_ttl`].
+/// Answers without records are cached as well, so that repeatedly dialing a
+/// name that does not exist does not hit the network every time. Errors are
+/// never cached.
+pub struct CachingResolver<R> {
+    inner: Arc<R>,
+    entries: Arc<Mutex<HashMap<(String, RecordType), Entry>>>,
+    capacity: usize,
+    default_ttl: Duration,
+    max_ttl: Duration,
+    negative_ttl: Duration,
+}
+
+impl<R> Clone for CachingResolver<R> {
+    fn clone(&self) -> Self {
+        CachingResolver {
+            inner: self.inner.clone(),
+            entries: self.entries.clone(),
+            capacity: self.capacity,
+            default_ttl: self.default_ttl,
+            max_ttl: self.max_ttl,
+            negative_ttl: self.negative_ttl,
+        }
+    }
+}
+
+struct Entry {
+    lookup: Lookup,
+    expires: Instant,
+}
+
+impl<R> CachingResolver<R> {
+    /// Creates a cache in front of the given resolver.
+    pub fn new(inner: R) -> CachingResolver<R> {
+        CachingResolver {
+            inner: Arc::new(inner),
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            capacity: 1024,
+            default_ttl: Duration::from_secs(60),
+            max_ttl: Duration::from_secs(24 * 60 * 60),
+            negative_ttl: Duration::from_secs(30),
+        }
+    }
+
+    /// Sets the maximum number of cached answers.
+    ///
+    /// Defaults to 1024.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets how long answers are cached whose TTL is unknown, e.g. the
+    /// answers of the system resolver.
+    ///
+    /// Defaults to 60 seconds.
+    pub fn default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Sets the maximum duration for which an answer is cached.
+    ///
+    /// Defaults to one day.
+    pub fn max_ttl(mut self, ttl: Duration) -> Self {
+        self.max_ttl = ttl;
+        self
+    }
+
+    /// Sets the maximum duration for which an answer without records is
+    /// cached.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    /// Removes all cached answers.
+    pub fn clear(&self) {
+        self.entries.lock().expect("not poisoned").clear()
+    }
+}
+
+impl<R: Resolver> Resolver for CachingResolver<R> {
+    fn lookup(&self, name: &str, ty: RecordType) -> BoxFuture<'static, io::Result<Lookup>> {
+        let key = (name.trim_end_matches('.').to_ascii_lowercase(), ty);
+        let now = Instant::now();
+
+        if let Some(entry) = self.entries.lock().expect("not poisoned").get(&key) {
+            if entry.expires > now {
+                trace!("Cached answer for {} ({:?})", key.0, ty);
+                let mut lookup = entry.lookup.clone();
+                lookup.ttl = Some(entry.expires - now);
+                return future::ok(lookup).boxed()
+            }
+        }
+
+        let this = self.clone();
+        let lookup = self.inner.lookup(name, ty);
+        async move {
+            let lookup = lookup.await?;
+            let ttl = if lookup.records.is_empty() {
+                cmp::min(lookup.ttl.unwrap_or(this.negative_ttl), this.negative_ttl)
+            } else {
+                cmp::min(lookup.ttl.unwrap_or(this.default_ttl), this.max_ttl)
+            };
+            if ttl > Duration::from_secs(0) && this.capacity > 0 {
+                let now = Instant::now();
+                let mut entries = this.entries.lock().expect("not poisoned");
+                if entries.len() >= this.capacity && !entries.contains_key(&key) {
+                    entries.retain(|_, e| e.expires > now);
+                }
+                if entries.len() >= this.capacity && !entries.contains_key(&key) {
+                    let oldest = entries.iter()
+                        .min_by_key(|(_, e)| e.expires)
+                        .map(|(k, _)| k.clone());
+                    if let Some(k) = oldest {
+                        entries.remove(&k);
+                    }
+                }
+                entries.insert(key, Entry { lookup: lookup.clone(), expires: now + ttl });
+            }
+            Ok(lookup)
+        }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Record;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counting(AtomicUsize);
+
+    impl Resolver for Counting {
+        fn lookup(&self, name: &str, _: RecordType) -> BoxFuture<'static, io::Result<Lookup>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let records = if name == "exists" {
+                vec![Record::Ip([1, 2, 3, 4].into())]
+            } else {
+                Vec::new()
+            };
+            future::ok(Lookup { records, ttl: Some(Duration::from_secs(3600)) }).boxed()
+        }
+    }
+
+    #[test]
+    fn positive_and_negative_answers() {
+        let cache = CachingResolver::new(Counting(AtomicUsize::new(0)))
+            .negative_ttl(Duration::from_secs(0))
+            .capacity(1);
+        futures::executor::block_on(async {
+            let a = cache.lookup("exists", RecordType::A).await.unwrap();
+            let b = cache.lookup("EXISTS.", RecordType::A).await.unwrap();
+            assert_eq!(a.records, b.records);
+            assert!(b.ttl.unwrap() <= Duration::from_secs(3600));
+            assert_eq!(cache.inner.0.load(Ordering::SeqCst), 1);
+
+            // Negative answers are not cached with a negative TTL of zero.
+            cache.lookup("missing", RecordType::A).await.unwrap();
+            cache.lookup("missing", RecordType::A).await.unwrap();
+            assert_eq!(cache.inner.0.load(Ordering::SeqCst), 3);
+
+            // Other record types are cached separately, evicting the first entry.
+            cache.lookup("exists", RecordType::Aaaa).await.unwrap();
+            cache.lookup("exists", RecordType::A).await.unwrap();
+            assert_eq!(cache.inner.0.load(Ordering::SeqCst), 5);
+        })
+    }
+}
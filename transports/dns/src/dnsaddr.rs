@@ -0,0 +1,149 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Expansion of `/dnsaddr` components through the `TXT` records of
+//! `_dnsaddr.<name>`, as described by the [dnsaddr specification].
+//!
+//! [dnsaddr specification]: https://github.com/multiformats/multiaddr/blob/master/protocols/DNSADDR.md
+
+use crate::{resolver::{with_timeout, Record, RecordType, Resolver}, DnsErr};
+use libp2p_core::multiaddr::{Multiaddr, Protocol};
+use log::debug;
+use std::{collections::{HashSet, VecDeque}, str, sync::Arc, time::Duration};
+
+/// The maximum number of `TXT` lookups performed for a single address.
+const MAX_LOOKUPS: usize = 32;
+
+/// The prefix of the `TXT` records of a `/dnsaddr` name.
+const DNSADDR_PREFIX: &str = "dnsaddr=";
+
+/// Limits applied while expanding a `/dnsaddr` address.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Limits {
+    /// The maximum nesting of `/dnsaddr` entries.
+    pub(crate) max_depth: usize,
+    /// The maximum number of records considered per lookup.
+    pub(crate) max_records: usize,
+    /// The maximum number of addresses returned.
+    pub(crate) max_addresses: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: 8,
+            max_records: 16,
+            max_addresses: 32,
+        }
+    }
+}
+
+/// Recursively expands the `/dnsaddr` components of `addr`.
+///
+/// The returned addresses contain no `/dnsaddr` component. Entries that do
+/// not end with the components following the `/dnsaddr` component of their
+/// parent, e.g. a `/p2p/<peer-id>`, are ignored, as are entries beyond the
+/// limits and entries leading back to names already visited.
+pub(crate) async fn resolve<E>(
+    resolver: Arc<dyn Resolver>,
+    query_timeout: Option<Duration>,
+    limits: Limits,
+    addr: Multiaddr
+) -> Result<Vec<Multiaddr>, DnsErr<E>> {
+    let mut resolved = Vec::new();
+    let mut pending = VecDeque::new();
+    let mut visited = HashSet::new();
+    let mut lookups = 0;
+    let mut error = None;
+    let original = addr.to_string();
+    pending.push_back((addr, 0));
+
+    while let Some((addr, depth)) = pending.pop_front() {
+        let components = addr.iter().collect::<Vec<_>>();
+        let index = match components.iter().position(|p| matches!(p, Protocol::Dnsaddr(_))) {
+            Some(index) => index,
+            None => {
+                resolved.push(addr.clone());
+                if resolved.len() >= limits.max_addresses {
+                    debug!("Reached the limit of {} addresses", limits.max_addresses);
+                    break
+                }
+                continue
+            }
+        };
+
+        let name = match &components[index] {
+            Protocol::Dnsaddr(name) => name.to_lowercase(),
+            _ => unreachable!("Found at index; qed")
+        };
+        if depth >= limits.max_depth {
+            debug!("Not following {}: maximum depth reached", name);
+            continue
+        }
+        if !visited.insert(name.clone()) {
+            debug!("Not following {}: already visited", name);
+            continue
+        }
+        if lookups >= MAX_LOOKUPS {
+            debug!("Not following {}: maximum number of lookups reached", name);
+            continue
+        }
+        lookups += 1;
+
+        let lookup = resolver.lookup(&format!("_dnsaddr.{}", name), RecordType::Txt);
+        let lookup = match with_timeout(lookup, query_timeout).await {
+            Ok(lookup) => lookup,
+            Err(e) => {
+                debug!("Failed to look up {}: {}", name, e);
+                error = Some(DnsErr::ResolveError { domain_name: name, error: e });
+                continue
+            }
+        };
+
+        let prefix = &components[.. index];
+        let suffix = &components[index + 1 ..];
+        let entries = lookup.records.iter()
+            .filter_map(|record| match record {
+                Record::Txt(txt) => str::from_utf8(txt).ok(),
+                Record::Ip(_) => None
+            })
+            .filter_map(|txt| txt.strip_prefix(DNSADDR_PREFIX))
+            .take(limits.max_records);
+        for entry in entries {
+            let entry = match entry.parse::<Multiaddr>() {
+                Ok(entry) => entry,
+                Err(e) => {
+                    debug!("Invalid entry of {}: {:?}: {}", name, entry, e);
+                    continue
+                }
+            };
+            if !entry.iter().collect::<Vec<_>>().ends_with(suffix) {
+                continue
+            }
+            let addr = prefix.iter().cloned().chain(entry.iter()).collect();
+            pending.push_back((addr, depth + 1));
+        }
+    }
+
+    if resolved.is_empty() {
+        return Err(error.unwrap_or_else(|| DnsErr::ResolveFail(original)))
+    }
+    Ok(resolved)
+}
@@ -32,15 +32,33 @@
 //! `/dns4/` or `/dns6/` component, a DNS resolve will be performed and the component will be
 //! replaced with respectively an `/ip4/` or an `/ip6/` component.
 //!
+//! A `/dnsaddr/` component is recursively expanded through the `TXT` records of its name, and
+//! the resulting addresses are dialed in turn until one of them succeeds.
+//!
+//! By default, names are resolved with the resolver of the operating system. Other
+//! [`Resolver`]s can be used with `DnsConfig::custom`, e.g. an [`UpstreamResolver`] querying
+//! specific name servers over UDP, TCP, TLS or HTTPS, behind a [`CachingResolver`].
+//!
+
+mod cache;
+mod dnsaddr;
+mod resolver;
+mod upstream;
+
+pub use cache::CachingResolver;
+pub use resolver::{Lookup, Record, RecordType, Resolver, SystemResolver};
 
-use futures::{prelude::*, channel::oneshot, future::BoxFuture};
+use resolver::with_timeout;
+pub use upstream::{NameServer, UpstreamResolver};
+
+use futures::{prelude::*, future::BoxFuture};
 use libp2p_core::{
     Transport,
     multiaddr::{Protocol, Multiaddr},
     transport::{TransportError, ListenerEvent}
 };
-use log::{error, debug, trace};
-use std::{error, fmt, io, net::ToSocketAddrs};
+use log::{debug, trace};
+use std::{error, fmt, io, sync::Arc, time::Duration};
 
 /// Represents the configuration for a DNS transport capability of libp2p.
 ///
@@ -53,8 +71,12 @@ use std::{error, fmt, io, net::ToSocketAddrs};
 pub struct DnsConfig<T> {
     /// Underlying transport to use once the DNS addresses have been resolved.
     inner: T,
-    /// Pool of threads to use when resolving DNS addresses.
-    thread_pool: futures::executor::ThreadPool,
+    /// Resolver to use for DNS addresses.
+    resolver: Arc<dyn Resolver>,
+    /// Maximum duration of a single DNS query.
+    query_timeout: Option<Duration>,
+    /// Limits of the expansion of `/dnsaddr/` components.
+    dnsaddr_limits: dnsaddr::Limits,
 }
 
 impl<T> DnsConfig<T> {
@@ -65,17 +87,66 @@ impl<T> DnsConfig<T> {
 
     /// Same as `new`, but allows specifying a number of threads for the resolving.
     pub fn with_resolve_threads(inner: T, num_threads: usize) -> Result<DnsConfig<T>, io::Error> {
-        let thread_pool = futures::executor::ThreadPool::builder()
-            .pool_size(num_threads)
-            .name_prefix("libp2p-dns-")
-            .create()?;
-
-        trace!("Created a DNS thread pool");
+        Ok(DnsConfig::custom(inner, SystemResolver::with_threads(num_threads)?))
+    }
 
-        Ok(DnsConfig {
+    /// Creates a configuration object resolving DNS addresses with the given resolver, e.g.
+    /// an [`UpstreamResolver`] behind a [`CachingResolver`].
+    pub fn custom(inner: T, resolver: impl Resolver) -> DnsConfig<T> {
+        DnsConfig {
             inner,
-            thread_pool,
-        })
+            resolver: Arc::new(resolver),
+            query_timeout: None,
+            dnsaddr_limits: Default::default(),
+        }
+    }
+
+    /// Sets the maximum duration of a DNS query, after which dialing fails.
+    ///
+    /// By default, queries are not limited beyond the limits of the resolver.
+    pub fn query_timeout(mut self, timeout: Duration) -> Self {
+        self.query_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how deeply `/dnsaddr` entries pointing to other `/dnsaddr` names are followed.
+    ///
+    /// Defaults to 8.
+    pub fn dnsaddr_max_depth(mut self, depth: usize) -> Self {
+        self.dnsaddr_limits.max_depth = depth;
+        self
+    }
+
+    /// Sets how many `TXT` records of a single `/dnsaddr` name are considered.
+    ///
+    /// Defaults to 16.
+    pub fn dnsaddr_max_records(mut self, records: usize) -> Self {
+        self.dnsaddr_limits.max_records = records;
+        self
+    }
+
+    /// Sets how many addresses a `/dnsaddr` address expands to at most.
+    ///
+    /// Defaults to 32.
+    pub fn dnsaddr_max_addresses(mut self, addresses: usize) -> Self {
+        self.dnsaddr_limits.max_addresses = addresses;
+        self
+    }
+
+    /// Recursively expands the `/dnsaddr/` components of `addr`.
+    ///
+    /// Entries of a `/dnsaddr` name are only retained if they end with the components
+    /// following the `/dnsaddr` component, e.g. with the `/p2p/` component identifying the
+    /// expected peer. Addresses without a `/dnsaddr/` component are returned as they are.
+    /// The other DNS components of the returned addresses are not resolved.
+    pub fn resolve_dnsaddr<E>(&self, addr: Multiaddr) -> BoxFuture<'static, Result<Vec<Multiaddr>, DnsErr<E>>>
+    where
+        E: Send + 'static
+    {
+        if !addr.iter().any(|p| matches!(p, Protocol::Dnsaddr(_))) {
+            return future::ok(vec![addr]).boxed()
+        }
+        dnsaddr::resolve(self.resolver.clone(), self.query_timeout, self.dnsaddr_limits, addr).boxed()
     }
 }
 
@@ -90,7 +161,7 @@ where
 
 impl<T> Transport for DnsConfig<T>
 where
-    T: Transport + Send + 'static,
+    T: Transport + Clone + Send + 'static,
     T::Error: Send,
     T::Dial: Send
 {
@@ -120,8 +191,10 @@ where
         // As an optimization, we immediately pass through if no component of the address contain
         // a DNS protocol.
         let contains_dns = addr.iter().any(|cmp| match cmp {
+            Protocol::Dns(_) => true,
             Protocol::Dns4(_) => true,
             Protocol::Dns6(_) => true,
+            Protocol::Dnsaddr(_) => true,
             _ => false,
         });
 
@@ -133,50 +206,85 @@ where
         }
 
         trace!("Dialing address with DNS: {}", addr);
+        let future = async move {
+            let addrs = self.resolve_dnsaddr(addr).await?;
+
+            // The addresses of a `/dnsaddr` are dialed in turn until one succeeds.
+            let mut error = None;
+            for addr in addrs {
+                match self.clone().dial_resolved(addr).await {
+                    Ok(output) => return Ok(output),
+                    Err(err) => {
+                        debug!("Dialing resolved address failed: {:?}", err);
+                        error = Some(err)
+                    }
+                }
+            }
+            Err(error.expect("resolve_dnsaddr returns at least one address; qed"))
+        };
+
+        Ok(future.boxed().right_future())
+    }
+}
+
+impl<T> DnsConfig<T>
+where
+    T: Transport + Clone + Send + 'static,
+    T::Error: Send,
+    T::Dial: Send
+{
+    /// Resolves the `/dns/`, `/dns4/` and `/dns6/` components of `addr`, then dials it with the
+    /// underlying transport.
+    ///
+    /// A `/dns/` component resolves to an IPv4 address if there is one, to an IPv6 address
+    /// otherwise.
+    fn dial_resolved(self, addr: Multiaddr) -> BoxFuture<'static, Result<T::Output, DnsErr<T::Error>>> {
         let resolve_futs = addr.iter()
             .map(|cmp| match cmp {
                 Protocol::Dns4(ref name) | Protocol::Dns6(ref name) => {
                     let name = name.to_string();
-                    let to_resolve = format!("{}:0", name);
-                    let (tx, rx) = oneshot::channel();
-                    self.thread_pool.spawn_ok(async {
-                        let to_resolve = to_resolve;
-                        let _ = tx.send(match to_resolve[..].to_socket_addrs() {
-                            Ok(list) => Ok(list.map(|s| s.ip()).collect::<Vec<_>>()),
-                            Err(e) => Err(e),
-                        });
-                    });
-
                     let is_dns4 = if let Protocol::Dns4(_) = cmp { true } else { false };
+                    let ty = if is_dns4 { RecordType::A } else { RecordType::Aaaa };
+                    let lookup = with_timeout(self.resolver.lookup(&name, ty), self.query_timeout);
+
+                    async move {
+                        let lookup = lookup.await.map_err(|err| DnsErr::ResolveError {
+                            domain_name: name.clone(),
+                            error: err,
+                        })?;
+
+                        let addr = lookup.ips()
+                            .find(|addr| (is_dns4 && addr.is_ipv4()) || (!is_dns4 && addr.is_ipv6()))
+                            .map(Protocol::from);
+                        addr.ok_or_else(|| DnsErr::ResolveFail(name))
+                    }.boxed().left_future()
+                },
+                Protocol::Dns(ref name) => {
+                    let name = name.to_string();
+                    let resolver = self.resolver.clone();
+                    let query_timeout = self.query_timeout;
 
                     async move {
-                        let list = rx.await
-                            .map_err(|_| {
-                                error!("DNS resolver crashed");
-                                DnsErr::ResolveFail(name.clone())
-                            })?
-                            .map_err(|err| DnsErr::ResolveError {
-                                domain_name: name.clone(),
-                                error: err,
-                            })?;
-
-                        list.into_iter()
-                            .filter_map(|addr| {
-                                if (is_dns4 && addr.is_ipv4()) || (!is_dns4 && addr.is_ipv6()) {
-                                    Some(Protocol::from(addr))
-                                } else {
-                                    None
-                                }
-                            })
-                            .next()
-                            .ok_or_else(|| DnsErr::ResolveFail(name))
-                    }.left_future()
+                        let mut error = None;
+                        for ty in &[RecordType::A, RecordType::Aaaa] {
+                            match with_timeout(resolver.lookup(&name, *ty), query_timeout).await {
+                                Ok(lookup) => if let Some(addr) = lookup.ips().next() {
+                                    return Ok(Protocol::from(addr))
+                                },
+                                Err(err) => error = Some(err),
+                            }
+                        }
+                        Err(match error {
+                            Some(error) => DnsErr::ResolveError { domain_name: name, error },
+                            None => DnsErr::ResolveFail(name),
+                        })
+                    }.boxed().left_future()
                 },
                 cmp => future::ready(Ok(cmp.acquire())).right_future()
             })
             .collect::<stream::FuturesOrdered<_>>();
 
-        let future = resolve_futs.collect::<Vec<_>>()
+        resolve_futs.collect::<Vec<_>>()
             .then(move |outcome| async move {
                 let outcome = outcome.into_iter().collect::<Result<Vec<_>, _>>()?;
                 let outcome = outcome.into_iter().collect::<Multiaddr>();
@@ -188,9 +296,8 @@ where
                         Err(DnsErr::MultiaddrNotSupported),
                     Err(TransportError::Other(err)) => Err(DnsErr::Underlying(err))
                 }
-            });
-
-        Ok(future.boxed().right_future())
+            })
+            .boxed()
     }
 }
 
@@ -240,7 +347,7 @@ where TErr: error::Error + 'static
 
 #[cfg(test)]
 mod tests {
-    use super::DnsConfig;
+    use super::{DnsConfig, DnsErr, Lookup, Record, RecordType, Resolver};
     use futures::{future::BoxFuture, prelude::*, stream::BoxStream};
     use libp2p_core::{
         Transport,
@@ -284,25 +391,79 @@ mod tests {
         futures::executor::block_on(async move {
             let transport = DnsConfig::new(CustomTransport).unwrap();
 
-            let _ = transport
+            transport
                 .clone()
                 .dial("/dns4/example.com/tcp/20000".parse().unwrap())
                 .unwrap()
                 .await
                 .unwrap();
 
-            let _ = transport
+            transport
                 .clone()
                 .dial("/dns6/example.com/tcp/20000".parse().unwrap())
                 .unwrap()
                 .await
                 .unwrap();
 
-            let _ = transport
+            transport
+                .clone()
+                .dial("/dns/example.com/tcp/20000".parse().unwrap())
+                .unwrap()
+                .await
+                .unwrap();
+
+            transport
                 .dial("/ip4/1.2.3.4/tcp/20000".parse().unwrap())
                 .unwrap()
                 .await
                 .unwrap();
         });
     }
+
+    #[test]
+    fn dnsaddr_resolve() {
+        struct TxtResolver;
+
+        impl Resolver for TxtResolver {
+            fn lookup(&self, name: &str, ty: RecordType) -> BoxFuture<'static, std::io::Result<Lookup>> {
+                assert_eq!(ty, RecordType::Txt);
+                let entries: &[&str] = match name {
+                    "_dnsaddr.bootstrap.io" => &[
+                        "dnsaddr=/dnsaddr/sjc.bootstrap.io/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
+                        "dnsaddr=/dnsaddr/ams.bootstrap.io/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
+                        "dnsaddr=/ip4/1.2.3.4/tcp/4001/p2p/QmSoLnSGccFuZQJzRadHn95W2CrSFmZuTdDWP8HXaHca9z",
+                        "unrelated",
+                    ],
+                    "_dnsaddr.sjc.bootstrap.io" => &[
+                        "dnsaddr=/ip4/10.0.0.1/tcp/4001/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
+                        // Loops back to the parent.
+                        "dnsaddr=/dnsaddr/bootstrap.io/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
+                    ],
+                    "_dnsaddr.ams.bootstrap.io" => &[
+                        "dnsaddr=/dns4/ams.bootstrap.io/tcp/4001/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
+                    ],
+                    _ => &[]
+                };
+                let records = entries.iter().map(|e| Record::Txt(e.as_bytes().to_vec())).collect();
+                future::ok(Lookup { records, ttl: None }).boxed()
+            }
+        }
+
+        futures::executor::block_on(async move {
+            let transport = DnsConfig::custom((), TxtResolver);
+            let addr = "/dnsaddr/bootstrap.io/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC";
+            let addrs = transport.resolve_dnsaddr::<()>(addr.parse().unwrap()).await.unwrap();
+            assert_eq!(addrs, vec![
+                "/ip4/10.0.0.1/tcp/4001/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC".parse().unwrap(),
+                "/dns4/ams.bootstrap.io/tcp/4001/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC".parse().unwrap(),
+            ]);
+
+            let transport = transport.dnsaddr_max_depth(1);
+            let result = transport.resolve_dnsaddr::<()>(addr.parse().unwrap()).await;
+            assert!(matches!(result, Err(DnsErr::ResolveFail(_))));
+
+            let addrs = transport.resolve_dnsaddr::<()>("/dnsaddr/bootstrap.io".parse().unwrap()).await.unwrap();
+            assert_eq!(addrs, vec!["/ip4/1.2.3.4/tcp/4001/p2p/QmSoLnSGccFuZQJzRadHn95W2CrSFmZuTdDWP8HXaHca9z".parse().unwrap()]);
+        });
+    }
 }
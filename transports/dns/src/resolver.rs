@@ -0,0 +1,157 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The resolver abstraction used by [`DnsConfig`](crate::DnsConfig).
+
+use futures::{prelude::*, channel::oneshot, future::BoxFuture};
+use log::{error, trace};
+use std::{io, net::{IpAddr, ToSocketAddrs}, sync::Arc, time::Duration};
+
+/// The type of records to look up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    /// IPv4 addresses.
+    A,
+    /// IPv6 addresses.
+    Aaaa,
+    /// Text records.
+    Txt,
+}
+
+/// A single record of a [`Lookup`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    /// An address of an `A` or `AAAA` record.
+    Ip(IpAddr),
+    /// The content of a `TXT` record, with its character-strings concatenated.
+    Txt(Vec<u8>),
+}
+
+/// The answer to a successful query.
+///
+/// A lookup without records means that the name does not exist or has no
+/// records of the requested type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lookup {
+    /// The records of the answer.
+    pub records: Vec<Record>,
+    /// How long the answer may be cached, if known.
+    pub ttl: Option<Duration>,
+}
+
+impl Lookup {
+    /// Iterates over the IP addresses of the answer.
+    pub fn ips(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.records.iter().filter_map(|r| match r {
+            Record::Ip(ip) => Some(*ip),
+            Record::Txt(_) => None
+        })
+    }
+}
+
+/// A DNS resolver.
+///
+/// Implementations are expected to apply their own retry policies. Errors are
+/// reported for queries that could not be answered, e.g. because no server
+/// could be reached.
+pub trait Resolver: Send + Sync + 'static {
+    /// Looks up the records of type `ty` for `name`.
+    fn lookup(&self, name: &str, ty: RecordType) -> BoxFuture<'static, io::Result<Lookup>>;
+}
+
+impl<R: Resolver + ?Sized> Resolver for Arc<R> {
+    fn lookup(&self, name: &str, ty: RecordType) -> BoxFuture<'static, io::Result<Lookup>> {
+        (**self).lookup(name, ty)
+    }
+}
+
+impl<R: Resolver + ?Sized> Resolver for Box<R> {
+    fn lookup(&self, name: &str, ty: RecordType) -> BoxFuture<'static, io::Result<Lookup>> {
+        (**self).lookup(name, ty)
+    }
+}
+
+/// Awaits `lookup`, failing with an error of kind `TimedOut` after `timeout`, if any.
+pub(crate) async fn with_timeout(
+    lookup: BoxFuture<'static, io::Result<Lookup>>,
+    timeout: Option<Duration>
+) -> io::Result<Lookup> {
+    match timeout {
+        Some(t) => async_std::future::timeout(t, lookup).await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+        None => lookup.await
+    }
+}
+
+/// Resolves names with the resolver of the operating system.
+///
+/// The blocking system calls are performed on a dedicated thread pool. Only
+/// `A` and `AAAA` lookups are supported and the answers carry no TTL.
+#[derive(Clone)]
+pub struct SystemResolver {
+    thread_pool: futures::executor::ThreadPool,
+}
+
+impl SystemResolver {
+    /// Creates a system resolver using a single thread.
+    pub fn new() -> io::Result<SystemResolver> {
+        SystemResolver::with_threads(1)
+    }
+
+    /// Creates a system resolver using `num_threads` threads.
+    pub fn with_threads(num_threads: usize) -> io::Result<SystemResolver> {
+        let thread_pool = futures::executor::ThreadPool::builder()
+            .pool_size(num_threads)
+            .name_prefix("libp2p-dns-")
+            .create()?;
+
+        trace!("Created a DNS thread pool");
+
+        Ok(SystemResolver { thread_pool })
+    }
+}
+
+impl Resolver for SystemResolver {
+    fn lookup(&self, name: &str, ty: RecordType) -> BoxFuture<'static, io::Result<Lookup>> {
+        if ty == RecordType::Txt {
+            let msg = "TXT lookups are not supported by the system resolver";
+            return future::err(io::Error::new(io::ErrorKind::Other, msg)).boxed()
+        }
+
+        let to_resolve = format!("{}:0", name);
+        let (tx, rx) = oneshot::channel();
+        self.thread_pool.spawn_ok(async move {
+            let _ = tx.send(to_resolve[..].to_socket_addrs()
+                .map(|list| list.map(|s| s.ip()).collect::<Vec<_>>()));
+        });
+
+        async move {
+            let list = rx.await.map_err(|_| {
+                error!("DNS resolver crashed");
+                io::Error::new(io::ErrorKind::Other, "DNS resolver crashed")
+            })??;
+            let records = list.into_iter()
+                .filter(|ip| ip.is_ipv4() == (ty == RecordType::A))
+                .map(Record::Ip)
+                .collect();
+            Ok(Lookup { records, ttl: None })
+        }.boxed()
+    }
+}
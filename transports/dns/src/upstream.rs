@@ -0,0 +1,432 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A resolver querying configured upstream name servers.
+
+use crate::resolver::{Lookup, Record, RecordType, Resolver};
+use async_std::net::{TcpStream, UdpSocket};
+use async_tls::TlsConnector;
+use dns_parser::{Packet, RData, ResponseCode};
+use futures::{prelude::*, future::BoxFuture};
+use log::debug;
+use std::{cmp, fmt, io, net::SocketAddr, sync::Arc, time::Duration};
+
+/// The default timeout of a query to a single name server.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The UDP payload size advertised to name servers through EDNS(0).
+const UDP_PAYLOAD_SIZE: u16 = 4096;
+
+/// The maximum size of an HTTP response to a DNS-over-HTTPS query.
+const MAX_HTTP_RESPONSE: usize = 64 * 1024;
+
+/// An upstream name server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameServer {
+    /// Plain DNS over UDP. Truncated answers are retried over TCP.
+    Udp(SocketAddr),
+    /// Plain DNS over TCP.
+    Tcp(SocketAddr),
+    /// DNS over TLS (RFC 7858). The certificate of the server must be valid
+    /// for `server_name`.
+    Tls {
+        addr: SocketAddr,
+        server_name: String,
+    },
+    /// DNS over HTTPS (RFC 8484). Queries are posted to
+    /// `https://<server_name><path>`, e.g. with a path of `/dns-query`.
+    Https {
+        addr: SocketAddr,
+        server_name: String,
+        path: String,
+    },
+}
+
+/// Resolves names by querying a list of upstream name servers.
+///
+/// The servers are tried in order until one of them answers. Negative
+/// answers are final and are not retried with the next server.
+#[derive(Clone)]
+pub struct UpstreamResolver {
+    servers: Arc<Vec<NameServer>>,
+    timeout: Duration,
+    tls: TlsConnector,
+}
+
+impl UpstreamResolver {
+    /// Creates a resolver querying the given name servers.
+    ///
+    /// TLS connections authenticate the servers with the Mozilla set of root
+    /// certificates.
+    pub fn new(servers: impl IntoIterator<Item = NameServer>) -> UpstreamResolver {
+        UpstreamResolver {
+            servers: Arc::new(servers.into_iter().collect()),
+            timeout: DEFAULT_TIMEOUT,
+            tls: TlsConnector::new(),
+        }
+    }
+
+    /// Sets the timeout of a query to a single name server.
+    ///
+    /// Defaults to 5 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends a query to a single name server and returns the raw response.
+    async fn exchange(&self, server: &NameServer, query: &[u8]) -> io::Result<Vec<u8>> {
+        match server {
+            NameServer::Udp(addr) => {
+                let response = exchange_udp(*addr, query).await?;
+                if dns_parser::Header::parse(&response).map(|h| h.truncated).unwrap_or(false) {
+                    debug!("Truncated answer from {}, retrying over TCP", addr);
+                    exchange_tcp(*addr, query).await
+                } else {
+                    Ok(response)
+                }
+            }
+            NameServer::Tcp(addr) => exchange_tcp(*addr, query).await,
+            NameServer::Tls { addr, server_name } => {
+                let socket = TcpStream::connect(addr).await?;
+                let mut stream = self.tls.connect(server_name, socket)?.await?;
+                exchange_stream(&mut stream, query).await
+            }
+            NameServer::Https { addr, server_name, path } => {
+                let socket = TcpStream::connect(addr).await?;
+                let mut stream = self.tls.connect(server_name, socket)?.await?;
+                let request = format!(
+                    "POST {} HTTP/1.1\r\n\
+                     Host: {}\r\n\
+                     Accept: application/dns-message\r\n\
+                     Content-Type: application/dns-message\r\n\
+                     Content-Length: {}\r\n\
+                     Connection: close\r\n\r\n",
+                    path, server_name, query.len());
+                stream.write_all(request.as_bytes()).await?;
+                stream.write_all(query).await?;
+                stream.flush().await?;
+                read_http_response(&mut stream).await
+            }
+        }
+    }
+}
+
+impl fmt::Debug for UpstreamResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpstreamResolver")
+            .field("servers", &self.servers)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl Resolver for UpstreamResolver {
+    fn lookup(&self, name: &str, ty: RecordType) -> BoxFuture<'static, io::Result<Lookup>> {
+        let this = self.clone();
+        let name = name.to_owned();
+        async move {
+            let mut error = io::Error::new(io::ErrorKind::Other, "No name server configured");
+            for server in this.servers.iter() {
+                // DNS-over-HTTPS clients use an ID of 0 for HTTP caches.
+                let id = match server {
+                    NameServer::Https { .. } => 0,
+                    _ => rand::random()
+                };
+                let query = encode_query(id, &name, ty)?;
+                let response = async_std::future::timeout(this.timeout, this.exchange(server, &query))
+                    .await
+                    .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+                    .and_then(|response| decode_response(id, ty, &response));
+                match response {
+                    Ok(lookup) => return Ok(lookup),
+                    Err(e) => {
+                        debug!("Query for {} to {:?} failed: {}", name, server, e);
+                        error = e
+                    }
+                }
+            }
+            Err(error)
+        }.boxed()
+    }
+}
+
+async fn exchange_udp(addr: SocketAddr, query: &[u8]) -> io::Result<Vec<u8>> {
+    let local: SocketAddr = if addr.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(addr).await?;
+    socket.send(query).await?;
+    let mut buf = vec![0; usize::from(UDP_PAYLOAD_SIZE)];
+    loop {
+        let n = socket.recv(&mut buf).await?;
+        // Datagrams with another ID are stale or spoofed.
+        if n >= 2 && buf[.. 2] == query[.. 2] {
+            buf.truncate(n);
+            return Ok(buf)
+        }
+    }
+}
+
+async fn exchange_tcp(addr: SocketAddr, query: &[u8]) -> io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(addr).await?;
+    exchange_stream(&mut stream, query).await
+}
+
+/// Sends a query over a stream with the two-byte length prefix of RFC 1035.
+async fn exchange_stream<S>(stream: &mut S, query: &[u8]) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + AsyncWrite + Unpin
+{
+    let mut message = Vec::with_capacity(query.len() + 2);
+    message.extend_from_slice(&(query.len() as u16).to_be_bytes());
+    message.extend_from_slice(query);
+    stream.write_all(&message).await?;
+    stream.flush().await?;
+    let mut len = [0; 2];
+    stream.read_exact(&mut len).await?;
+    let mut response = vec![0; usize::from(u16::from_be_bytes(len))];
+    stream.read_exact(&mut response).await?;
+    Ok(response)
+}
+
+async fn read_http_response<S>(stream: &mut S) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + Unpin
+{
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        if let Some(body) = http_body(&buf)? {
+            return Ok(body)
+        }
+        if buf.len() > MAX_HTTP_RESPONSE {
+            return Err(invalid_data("HTTP response too large"))
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into())
+        }
+        buf.extend_from_slice(&chunk[.. n]);
+    }
+}
+
+/// Extracts the body of a buffered HTTP response, if it is complete.
+fn http_body(buf: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    let end = match find(buf, b"\r\n\r\n") {
+        Some(end) => end,
+        None => return Ok(None)
+    };
+    let head = std::str::from_utf8(&buf[.. end]).map_err(|_| invalid_data("Invalid HTTP header"))?;
+    let body = &buf[end + 4 ..];
+    let mut lines = head.split("\r\n");
+
+    let status = lines.next().unwrap_or_default();
+    if !status.starts_with("HTTP/1.") {
+        return Err(invalid_data("Invalid HTTP status line"))
+    }
+    if status.split(' ').nth(1) != Some("200") {
+        let msg = format!("Unexpected HTTP status: {}", status);
+        return Err(io::Error::new(io::ErrorKind::Other, msg))
+    }
+
+    let mut content_length = None;
+    let mut chunked = false;
+    for line in lines {
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or_default().trim();
+        let value = parts.next().unwrap_or_default().trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            let len = value.parse::<usize>().map_err(|_| invalid_data("Invalid Content-Length"))?;
+            content_length = Some(len)
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked")
+        }
+    }
+
+    if chunked {
+        decode_chunked(body)
+    } else if let Some(len) = content_length {
+        Ok(if body.len() >= len { Some(body[.. len].to_vec()) } else { None })
+    } else {
+        Err(invalid_data("HTTP response without length"))
+    }
+}
+
+/// Decodes a chunked HTTP body, if it is complete.
+fn decode_chunked(mut data: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = match find(data, b"\r\n") {
+            Some(end) => end,
+            None => return Ok(None)
+        };
+        let line = std::str::from_utf8(&data[.. line_end]).map_err(|_| invalid_data("Invalid chunk"))?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| invalid_data("Invalid chunk size"))?;
+        if size == 0 {
+            return Ok(Some(body))
+        }
+        let chunk = &data[line_end + 2 ..];
+        if chunk.len() < size + 2 {
+            return Ok(None)
+        }
+        body.extend_from_slice(&chunk[.. size]);
+        data = &chunk[size + 2 ..];
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Encodes a recursive query with an EDNS(0) record.
+fn encode_query(id: u16, name: &str, ty: RecordType) -> io::Result<Vec<u8>> {
+    let name = name.trim_end_matches('.');
+    if name.is_empty() || name.len() > 253 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid domain name"))
+    }
+
+    let mut query = Vec::with_capacity(name.len() + 29);
+    query.extend_from_slice(&id.to_be_bytes());
+    // Flags with recursion desired, one question and one additional record.
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 1]);
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid domain name"))
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    let qtype: u16 = match ty {
+        RecordType::A => 1,
+        RecordType::Aaaa => 28,
+        RecordType::Txt => 16,
+    };
+    query.extend_from_slice(&qtype.to_be_bytes());
+    query.extend_from_slice(&[0, 1]); // Class IN.
+    // OPT pseudo-record of the root domain advertising our UDP payload size.
+    query.extend_from_slice(&[0, 0, 41]);
+    query.extend_from_slice(&UDP_PAYLOAD_SIZE.to_be_bytes());
+    query.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    Ok(query)
+}
+
+/// Decodes the response to a query with the given ID.
+fn decode_response(id: u16, ty: RecordType, response: &[u8]) -> io::Result<Lookup> {
+    let packet = Packet::parse(response).map_err(|e| invalid_data(&e.to_string()))?;
+    if packet.header.query || packet.header.id != id {
+        return Err(invalid_data("Unexpected DNS message"))
+    }
+    match packet.header.response_code {
+        ResponseCode::NoError | ResponseCode::NameError => {}
+        code => {
+            let msg = format!("Name server error: {:?}", code);
+            return Err(io::Error::new(io::ErrorKind::Other, msg))
+        }
+    }
+
+    let mut records = Vec::new();
+    let mut ttl = None;
+    for answer in &packet.answers {
+        let record = match (&answer.data, ty) {
+            (RData::A(a), RecordType::A) => Record::Ip(a.0.into()),
+            (RData::AAAA(a), RecordType::Aaaa) => Record::Ip(a.0.into()),
+            (RData::TXT(txt), RecordType::Txt) => Record::Txt(txt.iter().flatten().cloned().collect()),
+            // E.g. the CNAME records leading to the answer.
+            _ => continue
+        };
+        records.push(record);
+        ttl = Some(cmp::min(ttl.unwrap_or(answer.ttl), answer.ttl));
+    }
+
+    if records.is_empty() {
+        // The TTL of negative answers is given by the SOA record, if any.
+        ttl = packet.nameservers.iter()
+            .filter_map(|ns| match &ns.data {
+                RData::SOA(soa) => Some(cmp::min(ns.ttl, soa.minimum_ttl)),
+                _ => None
+            })
+            .next();
+    }
+
+    Ok(Lookup { records, ttl: ttl.map(|t| Duration::from_secs(u64::from(t))) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    /// Builds a response to `query` with the given A records.
+    fn response(query: &[u8], ips: &[Ipv4Addr], ttl: u32) -> Vec<u8> {
+        let question_end = 12 + query[12 ..].iter().position(|b| *b == 0).unwrap() + 5;
+        let mut response = query[.. question_end].to_vec();
+        response[2] |= 0x80; // Response flag.
+        response[6 .. 12].copy_from_slice(&[0, ips.len() as u8, 0, 0, 0, 0]);
+        for ip in ips {
+            // Compressed pointer to the name of the question.
+            response.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1]);
+            response.extend_from_slice(&ttl.to_be_bytes());
+            response.extend_from_slice(&[0, 4]);
+            response.extend_from_slice(&ip.octets());
+        }
+        response
+    }
+
+    #[test]
+    fn udp_lookup() {
+        async_std::task::block_on(async {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let addr = server.local_addr().unwrap();
+            async_std::task::spawn(async move {
+                let mut buf = [0; 512];
+                let (n, from) = server.recv_from(&mut buf).await.unwrap();
+                let answer = response(&buf[.. n], &[Ipv4Addr::new(1, 2, 3, 4)], 300);
+                server.send_to(&answer, from).await.unwrap();
+            });
+
+            let resolver = UpstreamResolver::new(vec![
+                // An unreachable server is skipped after the timeout.
+                NameServer::Udp("127.0.0.1:9".parse().unwrap()),
+                NameServer::Udp(addr),
+            ]).timeout(Duration::from_millis(200));
+            let lookup = resolver.lookup("example.com", RecordType::A).await.unwrap();
+            assert_eq!(lookup.records, vec![Record::Ip([1, 2, 3, 4].into())]);
+            assert_eq!(lookup.ttl, Some(Duration::from_secs(300)));
+        })
+    }
+
+    #[test]
+    fn chunked_http_body() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n";
+        assert_eq!(http_body(&response[.. response.len() - 3]).unwrap(), None);
+        assert_eq!(http_body(response).unwrap(), Some(b"abcde".to_vec()));
+        assert!(http_body(b"HTTP/1.1 404 Not Found\r\n\r\n").is_err());
+    }
+}
@@ -0,0 +1,438 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! QUIC endpoints and the background task driving their connections.
+
+use crate::error::QuicError;
+use async_std::net::UdpSocket;
+use bytes::{Bytes, BytesMut};
+use futures::{channel::mpsc, prelude::*, select};
+use futures_timer::Delay;
+use libp2p_core::identity;
+use libp2p_tls::Verifier;
+use log::{debug, trace};
+use parking_lot::{Mutex, MutexGuard};
+use quinn_proto::{
+    ClientConfig,
+    ConnectionError,
+    ConnectionEvent,
+    ConnectionHandle,
+    DatagramEvent,
+    EndpointConfig,
+    Event,
+    ServerConfig,
+    StreamId,
+    TimerSetting,
+    TimerTable,
+    Transmit
+};
+use std::{collections::HashMap, io, net::SocketAddr, sync::Arc, task::Waker, time::Instant};
+
+/// The maximum payload of a UDP datagram.
+const MAX_DATAGRAM_SIZE: usize = 65_527;
+
+/// The incoming connections of a listening endpoint, together with the
+/// address of their remote.
+pub(crate) type Incoming = mpsc::UnboundedReceiver<(ConnectionHandle, SocketAddr)>;
+
+/// A handle to a QUIC endpoint bound to a UDP socket.
+///
+/// The datagrams and timers of all connections of an endpoint are handled
+/// by a background task, which ends once all handles to the endpoint are
+/// dropped and all of its connections are drained.
+#[derive(Clone)]
+pub(crate) struct Endpoint {
+    inner: Arc<Mutex<EndpointInner>>,
+    /// Wakes up the background task after a connection has been used.
+    ///
+    /// Declared after `inner`, so that dropping the last handle wakes up the
+    /// background task once `inner` is no longer referenced by the handle.
+    notify: mpsc::UnboundedSender<()>,
+    local_addr: SocketAddr
+}
+
+pub(crate) struct EndpointInner {
+    endpoint: quinn_proto::Endpoint,
+    connections: HashMap<ConnectionHandle, Connection>,
+    /// The verifier shared by the incoming connections of a listener.
+    verifier: Option<Arc<Verifier>>,
+    /// Where to send new incoming connections, while listening.
+    incoming: Option<mpsc::UnboundedSender<(ConnectionHandle, SocketAddr)>>
+}
+
+impl Endpoint {
+    /// Creates an endpoint that only initiates connections.
+    pub(crate) fn client(socket: std::net::UdpSocket) -> Result<Self, QuicError> {
+        Endpoint::new(socket, None, None, None)
+    }
+
+    /// Creates an endpoint that accepts incoming connections, whose TLS
+    /// configuration uses `verifier`, if any.
+    pub(crate) fn server(socket: std::net::UdpSocket, config: ServerConfig, verifier: Option<Arc<Verifier>>)
+        -> Result<(Self, Incoming), QuicError>
+    {
+        let (tx, rx) = mpsc::unbounded();
+        let endpoint = Endpoint::new(socket, Some(config), verifier, Some(tx))?;
+        Ok((endpoint, rx))
+    }
+
+    fn new(
+        socket: std::net::UdpSocket,
+        config: Option<ServerConfig>,
+        verifier: Option<Arc<Verifier>>,
+        incoming: Option<mpsc::UnboundedSender<(ConnectionHandle, SocketAddr)>>
+    ) -> Result<Self, QuicError> {
+        let local_addr = socket.local_addr()?;
+        let endpoint = quinn_proto::Endpoint::new(Arc::new(EndpointConfig::default()), config.map(Arc::new))?;
+        let (notify, wakeups) = mpsc::unbounded();
+        let inner = Arc::new(Mutex::new(EndpointInner {
+            endpoint,
+            connections: HashMap::new(),
+            verifier,
+            incoming
+        }));
+        let socket = UdpSocket::from(socket);
+        async_std::task::spawn(drive(inner.clone(), socket, local_addr, wakeups));
+        Ok(Endpoint { inner, notify, local_addr })
+    }
+
+    /// The address of the UDP socket of the endpoint.
+    pub(crate) fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, EndpointInner> {
+        self.inner.lock()
+    }
+
+    /// Wakes up the background task, e.g. to send the data written to a
+    /// connection.
+    pub(crate) fn wake(&self) {
+        let _ = self.notify.unbounded_send(());
+    }
+
+    /// Initiates a connection to `remote` with the given server name, whose
+    /// TLS configuration uses `verifier`, if any.
+    pub(crate) fn connect(
+        &self,
+        config: ClientConfig,
+        remote: SocketAddr,
+        server_name: &str,
+        verifier: Option<Arc<Verifier>>
+    ) -> Result<ConnectionHandle, QuicError> {
+        let mut inner = self.lock();
+        let (handle, connection) = inner.endpoint.connect(config, remote, server_name)?;
+        inner.connections.insert(handle, Connection::new(connection, verifier));
+        self.wake();
+        Ok(handle)
+    }
+
+    /// Closes a connection, unless it is closed already.
+    pub(crate) fn close(&self, handle: ConnectionHandle) {
+        if let Some(connection) = self.lock().connections.get_mut(&handle) {
+            connection.close(Instant::now())
+        }
+        self.wake()
+    }
+
+    /// Rejects new incoming connections, leaving the existing ones open.
+    pub(crate) fn stop_listening(&self) {
+        let mut inner = self.lock();
+        inner.endpoint.reject_new_connections();
+        inner.incoming = None;
+        self.wake()
+    }
+}
+
+impl EndpointInner {
+    /// Returns the connection of `handle`, or an error if it has been
+    /// closed or lost.
+    pub(crate) fn connection(&mut self, handle: ConnectionHandle) -> Result<&mut Connection, QuicError> {
+        let connection = self.connections.get_mut(&handle)
+            .ok_or(QuicError::Connection(ConnectionError::LocallyClosed))?;
+        if let Some(error) = &connection.error {
+            return Err(QuicError::Connection(error.clone()))
+        }
+        if connection.closed {
+            return Err(QuicError::Connection(ConnectionError::LocallyClosed))
+        }
+        Ok(connection)
+    }
+
+    /// Handles a datagram received from `remote`.
+    fn handle_datagram(&mut self, now: Instant, remote: SocketAddr, data: &[u8]) {
+        let (handle, event) = match self.endpoint.handle(now, remote, None, BytesMut::from(data)) {
+            Some(event) => event,
+            None => return
+        };
+        match event {
+            DatagramEvent::ConnectionEvent(event) => {
+                if let Some(connection) = self.connections.get_mut(&handle) {
+                    connection.handle_event(event)
+                }
+            }
+            DatagramEvent::NewConnection(connection) => {
+                self.endpoint.accept();
+                let mut connection = Connection::new(connection, self.verifier.clone());
+                let accepted = self.incoming.as_ref()
+                    .map_or(false, |incoming| incoming.unbounded_send((handle, remote)).is_ok());
+                if !accepted {
+                    debug!("Closing incoming connection from {}: not listening", remote);
+                    connection.close(now)
+                }
+                self.connections.insert(handle, connection);
+            }
+        }
+    }
+
+    /// Handles the expired timers of all connections.
+    fn handle_timeouts(&mut self, now: Instant) {
+        for connection in self.connections.values_mut() {
+            connection.handle_timeouts(now)
+        }
+    }
+
+    /// Processes the events of all connections, collecting the datagrams
+    /// to send and dropping the connections that are drained.
+    fn poll(&mut self, now: Instant, transmits: &mut Vec<Transmit>) {
+        for (handle, connection) in self.connections.iter_mut() {
+            connection.poll(&mut self.endpoint, *handle, now, transmits)
+        }
+        self.connections.retain(|handle, connection| {
+            if connection.connection.is_drained() {
+                trace!("Connection {:?} drained", handle);
+                connection.wake_all();
+                return false
+            }
+            true
+        });
+        while let Some(transmit) = self.endpoint.poll_transmit() {
+            transmits.push(transmit)
+        }
+    }
+
+    /// The earliest timer of all connections.
+    fn next_timeout(&self) -> Option<Instant> {
+        self.connections.values()
+            .flat_map(|connection| connection.timers.iter().filter_map(|(_, t)| *t))
+            .min()
+    }
+}
+
+/// The state of a connection of an endpoint.
+pub(crate) struct Connection {
+    pub(crate) connection: quinn_proto::Connection,
+    /// The verifier of the libp2p certificate of the remote, unless the
+    /// remote is authenticated otherwise.
+    verifier: Option<Arc<Verifier>>,
+    /// The public key of the remote, once its certificate has been verified.
+    pub(crate) remote: Option<identity::PublicKey>,
+    timers: TimerTable<Option<Instant>>,
+    /// Whether the handshake is complete.
+    pub(crate) established: bool,
+    /// Whether the connection has been closed locally.
+    closed: bool,
+    /// The reason the connection has been lost.
+    error: Option<ConnectionError>,
+    pub(crate) wakers: Wakers
+}
+
+/// The tasks waiting for the events of a connection.
+#[derive(Default)]
+pub(crate) struct Wakers {
+    pub(crate) handshake: Option<Waker>,
+    pub(crate) inbound: Option<Waker>,
+    pub(crate) outbound: Option<Waker>,
+    pub(crate) read: HashMap<StreamId, Waker>,
+    pub(crate) write: HashMap<StreamId, Waker>
+}
+
+impl Connection {
+    fn new(connection: quinn_proto::Connection, verifier: Option<Arc<Verifier>>) -> Self {
+        Connection {
+            connection,
+            verifier,
+            remote: None,
+            timers: TimerTable::new(|| None),
+            established: false,
+            closed: false,
+            error: None,
+            wakers: Wakers::default()
+        }
+    }
+
+    /// Closes the connection, unless it is closed already.
+    pub(crate) fn close(&mut self, now: Instant) {
+        if !self.closed && self.error.is_none() {
+            self.closed = true;
+            self.connection.close(now, 0u32.into(), Bytes::new());
+            self.wake_all()
+        }
+    }
+
+    fn handle_event(&mut self, event: ConnectionEvent) {
+        self.connection.handle_event(event);
+        // Certificates are verified while handling the event, hence this is
+        // the remote of this connection even if the verifier is shared.
+        if let Some(remote) = self.verifier.as_ref().and_then(|v| v.take_remote()) {
+            self.remote = Some(remote)
+        }
+    }
+
+    fn handle_timeouts(&mut self, now: Instant) {
+        for (timer, deadline) in self.timers.iter_mut() {
+            if deadline.map_or(false, |deadline| deadline <= now) {
+                *deadline = None;
+                self.connection.handle_timeout(now, timer)
+            }
+        }
+    }
+
+    fn poll(
+        &mut self,
+        endpoint: &mut quinn_proto::Endpoint,
+        handle: ConnectionHandle,
+        now: Instant,
+        transmits: &mut Vec<Transmit>
+    ) {
+        while let Some(event) = self.connection.poll_endpoint_events() {
+            if let Some(event) = endpoint.handle_event(handle, event) {
+                self.handle_event(event)
+            }
+        }
+        while let Some(event) = self.connection.poll() {
+            self.on_event(event)
+        }
+        while let Some(transmit) = self.connection.poll_transmit(now) {
+            transmits.push(transmit)
+        }
+        while let Some(update) = self.connection.poll_timers() {
+            self.timers[update.timer] = match update.update {
+                TimerSetting::Start(deadline) => Some(deadline),
+                TimerSetting::Stop => None
+            }
+        }
+    }
+
+    fn on_event(&mut self, event: Event) {
+        match event {
+            Event::Connected => {
+                self.established = true;
+                wake(&mut self.wakers.handshake)
+            }
+            Event::ConnectionLost { reason } => {
+                debug!("Connection to {} lost: {}", self.connection.remote(), reason);
+                self.error = Some(reason);
+                self.wake_all()
+            }
+            Event::StreamOpened { .. } => wake(&mut self.wakers.inbound),
+            Event::StreamAvailable { .. } => wake(&mut self.wakers.outbound),
+            Event::StreamReadable { stream } => {
+                if let Some(waker) = self.wakers.read.remove(&stream) {
+                    waker.wake()
+                }
+            }
+            Event::StreamWritable { stream } | Event::StreamFinished { stream, .. } => {
+                if let Some(waker) = self.wakers.write.remove(&stream) {
+                    waker.wake()
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn wake_all(&mut self) {
+        wake(&mut self.wakers.handshake);
+        wake(&mut self.wakers.inbound);
+        wake(&mut self.wakers.outbound);
+        for (_, waker) in self.wakers.read.drain().chain(self.wakers.write.drain()) {
+            waker.wake()
+        }
+    }
+}
+
+fn wake(waker: &mut Option<Waker>) {
+    if let Some(waker) = waker.take() {
+        waker.wake()
+    }
+}
+
+/// What woke up the background task of an endpoint.
+enum Wakeup {
+    Datagram(io::Result<(usize, SocketAddr)>),
+    Timeout,
+    Notified
+}
+
+/// Sends and receives the datagrams of the connections of `endpoint` and
+/// handles their timers.
+async fn drive(
+    endpoint: Arc<Mutex<EndpointInner>>,
+    socket: UdpSocket,
+    local_addr: SocketAddr,
+    mut wakeups: mpsc::UnboundedReceiver<()>
+) {
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+    loop {
+        let mut transmits = Vec::new();
+        let deadline = {
+            let mut inner = endpoint.lock();
+            inner.poll(Instant::now(), &mut transmits);
+            if inner.connections.is_empty() && Arc::strong_count(&endpoint) == 1 {
+                trace!("Endpoint on {} closed", local_addr);
+                return
+            }
+            inner.next_timeout()
+        };
+
+        for transmit in transmits {
+            if let Err(e) = socket.send_to(&transmit.contents, transmit.destination).await {
+                debug!("Failed to send datagram to {}: {}", transmit.destination, e)
+            }
+        }
+
+        let wakeup = {
+            let recv = socket.recv_from(&mut buf).fuse();
+            let timeout = async move {
+                match deadline {
+                    Some(deadline) => Delay::new(deadline.saturating_duration_since(Instant::now())).await,
+                    None => future::pending::<()>().await
+                }
+            }.fuse();
+            futures::pin_mut!(recv, timeout);
+            select! {
+                result = recv => Wakeup::Datagram(result),
+                () = timeout => Wakeup::Timeout,
+                _ = wakeups.next() => Wakeup::Notified
+            }
+        };
+
+        match wakeup {
+            Wakeup::Datagram(Ok((n, remote))) =>
+                endpoint.lock().handle_datagram(Instant::now(), remote, &buf[.. n]),
+            Wakeup::Datagram(Err(e)) =>
+                debug!("Failed to receive datagram on {}: {}", local_addr, e),
+            Wakeup::Timeout => endpoint.lock().handle_timeouts(Instant::now()),
+            // Once all handles are dropped, `wakeups` is terminated and no
+            // longer selected.
+            Wakeup::Notified => while let Some(Some(())) = wakeups.next().now_or_never() {}
+        }
+    }
+}
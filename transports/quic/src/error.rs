@@ -0,0 +1,169 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_noise::NoiseError;
+use libp2p_tls::TlsError;
+use quinn_proto::{ConfigError, ConnectError, ConnectionError, FinishError, ReadError, WriteError};
+use std::{error::Error, fmt, io};
+
+/// libp2p_quic error type.
+#[derive(Debug)]
+pub enum QuicError {
+    /// An I/O error on the UDP socket of an endpoint.
+    Io(io::Error),
+    /// The TLS configuration could not be created or the certificate of the
+    /// remote is invalid.
+    Tls(TlsError),
+    /// The QUIC configuration is invalid.
+    Config(ConfigError),
+    /// A connection could not be initiated.
+    Connect(ConnectError),
+    /// The connection has been closed or lost.
+    Connection(ConnectionError),
+    /// Reading from a substream failed.
+    Read(ReadError),
+    /// Writing to a substream failed.
+    Write(WriteError),
+    /// Closing a substream failed.
+    Finish(FinishError),
+    /// A WebTransport session could not be established or has been closed.
+    WebTransport(WebTransportError)
+}
+
+impl fmt::Display for QuicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuicError::Io(e) => write!(f, "{}", e),
+            QuicError::Tls(e) => write!(f, "{}", e),
+            QuicError::Config(e) => write!(f, "{}", e),
+            QuicError::Connect(e) => write!(f, "{}", e),
+            QuicError::Connection(e) => write!(f, "{}", e),
+            QuicError::Read(e) => write!(f, "{}", e),
+            QuicError::Write(e) => write!(f, "{}", e),
+            QuicError::Finish(e) => write!(f, "{}", e),
+            QuicError::WebTransport(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl Error for QuicError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            QuicError::Io(e) => Some(e),
+            QuicError::Tls(e) => Some(e),
+            QuicError::Config(e) => Some(e),
+            QuicError::Connect(e) => Some(e),
+            QuicError::Connection(e) => Some(e),
+            QuicError::Read(e) => Some(e),
+            QuicError::Write(e) => Some(e),
+            QuicError::Finish(e) => Some(e),
+            QuicError::WebTransport(e) => Some(e)
+        }
+    }
+}
+
+impl From<QuicError> for io::Error {
+    fn from(e: QuicError) -> Self {
+        match e {
+            QuicError::Io(e) => e,
+            QuicError::Connection(e) => e.into(),
+            QuicError::Read(ReadError::Reset { .. }) =>
+                io::Error::new(io::ErrorKind::ConnectionReset, e),
+            QuicError::Write(WriteError::Stopped { .. }) | QuicError::Finish(FinishError::Stopped { .. }) =>
+                io::Error::new(io::ErrorKind::BrokenPipe, e),
+            e => io::Error::new(io::ErrorKind::Other, e)
+        }
+    }
+}
+
+impl From<io::Error> for QuicError {
+    fn from(e: io::Error) -> Self {
+        QuicError::Io(e)
+    }
+}
+
+impl From<TlsError> for QuicError {
+    fn from(e: TlsError) -> Self {
+        QuicError::Tls(e)
+    }
+}
+
+impl From<ConfigError> for QuicError {
+    fn from(e: ConfigError) -> Self {
+        QuicError::Config(e)
+    }
+}
+
+impl From<ConnectError> for QuicError {
+    fn from(e: ConnectError) -> Self {
+        QuicError::Connect(e)
+    }
+}
+
+impl From<ConnectionError> for QuicError {
+    fn from(e: ConnectionError) -> Self {
+        QuicError::Connection(e)
+    }
+}
+
+impl From<WebTransportError> for QuicError {
+    fn from(e: WebTransportError) -> Self {
+        QuicError::WebTransport(e)
+    }
+}
+
+/// Errors of WebTransport sessions.
+#[derive(Debug)]
+pub enum WebTransportError {
+    /// The remote violated the HTTP/3 or WebTransport protocol.
+    Protocol(&'static str),
+    /// The listener rejected the session with the given HTTP status.
+    Rejected(u16),
+    /// The session has been closed by the remote.
+    Closed,
+    /// The Noise handshake authenticating the session failed.
+    Noise(NoiseError)
+}
+
+impl fmt::Display for WebTransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebTransportError::Protocol(e) => write!(f, "WebTransport protocol error: {}", e),
+            WebTransportError::Rejected(status) => write!(f, "WebTransport session rejected with status {}", status),
+            WebTransportError::Closed => write!(f, "WebTransport session closed"),
+            WebTransportError::Noise(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl Error for WebTransportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WebTransportError::Noise(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<NoiseError> for WebTransportError {
+    fn from(e: NoiseError) -> Self {
+        WebTransportError::Noise(e)
+    }
+}
@@ -0,0 +1,521 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The subset of HTTP/3 ([RFC 9114]) and QPACK ([RFC 9204]) needed to
+//! establish WebTransport sessions.
+//!
+//! As both endpoints announce a dynamic table capacity of zero, header
+//! blocks only refer to the static table of QPACK, and neither encoder nor
+//! decoder streams are needed.
+//!
+//! [RFC 9114]: https://www.rfc-editor.org/rfc/rfc9114.html
+//! [RFC 9204]: https://www.rfc-editor.org/rfc/rfc9204.html
+
+/// The type of the unidirectional control stream.
+pub(crate) const STREAM_CONTROL: u64 = 0x00;
+/// The type of the unidirectional QPACK encoder stream.
+pub(crate) const STREAM_QPACK_ENCODER: u64 = 0x02;
+/// The type of the unidirectional QPACK decoder stream.
+pub(crate) const STREAM_QPACK_DECODER: u64 = 0x03;
+
+/// The type of `HEADERS` frames.
+pub(crate) const FRAME_HEADERS: u64 = 0x01;
+/// The type of `SETTINGS` frames.
+pub(crate) const FRAME_SETTINGS: u64 = 0x04;
+
+/// The signal starting a bidirectional stream of a WebTransport session,
+/// followed by the session ID.
+pub(crate) const WEBTRANSPORT_STREAM: u64 = 0x41;
+
+const SETTINGS_ENABLE_CONNECT_PROTOCOL: u64 = 0x08;
+const SETTINGS_ENABLE_WEBTRANSPORT: u64 = 0x2b60_3742;
+const SETTINGS_WEBTRANSPORT_MAX_SESSIONS: u64 = 0xc671_706a;
+
+/// A stream was created with a type that is not supported.
+pub(crate) const H3_STREAM_CREATION_ERROR: u32 = 0x103;
+/// A frame was received that is not permitted in the current state.
+pub(crate) const H3_FRAME_UNEXPECTED: u32 = 0x105;
+/// A request was rejected without any processing.
+pub(crate) const H3_REQUEST_REJECTED: u32 = 0x10b;
+
+/// Appends the QUIC variable-length encoding of `value` to `buf`.
+///
+/// # Panics
+///
+/// Panics if `value` does not fit into 62 bits.
+pub(crate) fn encode_varint(value: u64, buf: &mut Vec<u8>) {
+    if value < 1 << 6 {
+        buf.push(value as u8)
+    } else if value < 1 << 14 {
+        buf.extend_from_slice(&(value as u16 | 0x4000).to_be_bytes())
+    } else if value < 1 << 30 {
+        buf.extend_from_slice(&(value as u32 | 0x8000_0000).to_be_bytes())
+    } else {
+        assert!(value < 1 << 62, "Variable-length integers have at most 62 bits");
+        buf.extend_from_slice(&(value | 0xc000_0000_0000_0000).to_be_bytes())
+    }
+}
+
+/// Decodes the variable-length integer at the start of `buf`, yielding its
+/// value and length, or the number of bytes missing.
+pub(crate) fn decode_varint(buf: &[u8]) -> Result<(u64, usize), usize> {
+    let first = *buf.first().ok_or(1usize)?;
+    let len = 1 << (first >> 6);
+    if buf.len() < len {
+        return Err(len - buf.len())
+    }
+    let value = buf[1 .. len].iter().fold(u64::from(first & 0x3f), |v, b| v << 8 | u64::from(*b));
+    Ok((value, len))
+}
+
+/// Appends a frame of the given type to `buf`.
+pub(crate) fn encode_frame(ty: u64, payload: &[u8], buf: &mut Vec<u8>) {
+    encode_varint(ty, buf);
+    encode_varint(payload.len() as u64, buf);
+    buf.extend_from_slice(payload)
+}
+
+/// Decodes the frame at the start of `buf`, yielding its type, its payload
+/// and its total length, or the number of bytes missing.
+pub(crate) fn decode_frame(buf: &[u8]) -> Result<(u64, &[u8], usize), usize> {
+    let (ty, n) = decode_varint(buf)?;
+    let (len, m) = decode_varint(&buf[n ..])?;
+    let start = n + m;
+    let end = start.saturating_add(len as usize);
+    if buf.len() < end {
+        return Err(end - buf.len())
+    }
+    Ok((ty, &buf[start .. end], end))
+}
+
+/// Whether a frame type is reserved to exercise the requirement that
+/// unknown frames are ignored.
+pub(crate) fn is_reserved_frame(ty: u64) -> bool {
+    ty >= 0x21 && (ty - 0x21).is_multiple_of(0x1f)
+}
+
+/// The start of a control stream, i.e. its type and our settings.
+pub(crate) fn control_stream() -> Vec<u8> {
+    let mut settings = Vec::new();
+    for (id, value) in &[
+        (SETTINGS_ENABLE_CONNECT_PROTOCOL, 1),
+        (SETTINGS_ENABLE_WEBTRANSPORT, 1),
+        (SETTINGS_WEBTRANSPORT_MAX_SESSIONS, 1)
+    ] {
+        encode_varint(*id, &mut settings);
+        encode_varint(*value, &mut settings)
+    }
+    let mut buf = Vec::new();
+    encode_varint(STREAM_CONTROL, &mut buf);
+    encode_frame(FRAME_SETTINGS, &settings, &mut buf);
+    buf
+}
+
+/// The start of a stream, which determines its purpose.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum StreamHeader<'a> {
+    /// The type of a unidirectional stream.
+    Uni(u64),
+    /// A bidirectional stream of the WebTransport session with the given ID.
+    WebTransport(u64),
+    /// The first frame of a bidirectional request stream.
+    Frame(u64, &'a [u8])
+}
+
+/// Decodes the header at the start of a stream, or yields the number of
+/// bytes missing.
+pub(crate) fn decode_stream_header(bidirectional: bool, buf: &[u8]) -> Result<StreamHeader<'_>, usize> {
+    let (ty, n) = decode_varint(buf)?;
+    if !bidirectional {
+        return Ok(StreamHeader::Uni(ty))
+    }
+    if ty == WEBTRANSPORT_STREAM {
+        let (session, _) = decode_varint(&buf[n ..])?;
+        return Ok(StreamHeader::WebTransport(session))
+    }
+    let (ty, payload, _) = decode_frame(buf)?;
+    Ok(StreamHeader::Frame(ty, payload))
+}
+
+/// Encodes a header block, using the static table where possible.
+pub(crate) fn encode_headers(headers: &[(&str, &str)]) -> Vec<u8> {
+    // Neither the required insert count nor the base are used.
+    let mut buf = vec![0, 0];
+    for (name, value) in headers {
+        if let Some(index) = STATIC_TABLE.iter().position(|e| e == &(*name, *value)) {
+            // Indexed field line referring to the static table.
+            encode_int(index as u64, 6, 0xc0, &mut buf)
+        } else if let Some(index) = STATIC_TABLE.iter().position(|(n, _)| n == name) {
+            // Literal field line with a name reference to the static table.
+            encode_int(index as u64, 4, 0x50, &mut buf);
+            encode_int(value.len() as u64, 7, 0, &mut buf);
+            buf.extend_from_slice(value.as_bytes())
+        } else {
+            // Literal field line with a literal name.
+            encode_int(name.len() as u64, 3, 0x20, &mut buf);
+            buf.extend_from_slice(name.as_bytes());
+            encode_int(value.len() as u64, 7, 0, &mut buf);
+            buf.extend_from_slice(value.as_bytes())
+        }
+    }
+    buf
+}
+
+/// Decodes a header block that does not refer to the dynamic table.
+pub(crate) fn decode_headers(block: &[u8]) -> Result<Vec<(String, String)>, &'static str> {
+    let mut pos = 0;
+    if decode_int(block, &mut pos, 8)? != 0 {
+        return Err("header block refers to the dynamic table")
+    }
+    decode_int(block, &mut pos, 7)?;
+
+    let mut headers = Vec::new();
+    while pos < block.len() {
+        let first = block[pos];
+        let header = if first & 0x80 != 0 {
+            if first & 0x40 == 0 {
+                return Err("header block refers to the dynamic table")
+            }
+            let (name, value) = static_entry(decode_int(block, &mut pos, 6)?)?;
+            (name.to_owned(), value.to_owned())
+        } else if first & 0x40 != 0 {
+            if first & 0x10 == 0 {
+                return Err("header block refers to the dynamic table")
+            }
+            let (name, _) = static_entry(decode_int(block, &mut pos, 4)?)?;
+            (name.to_owned(), decode_string(block, &mut pos, 7)?)
+        } else if first & 0x20 != 0 {
+            let name = decode_string(block, &mut pos, 3)?;
+            (name, decode_string(block, &mut pos, 7)?)
+        } else {
+            return Err("header block refers to the dynamic table")
+        };
+        headers.push(header)
+    }
+    Ok(headers)
+}
+
+fn static_entry(index: u64) -> Result<(&'static str, &'static str), &'static str> {
+    STATIC_TABLE.get(index as usize).cloned().ok_or("invalid static table index")
+}
+
+/// Appends a QPACK integer with a prefix of `prefix` bits to `buf`, the
+/// remaining bits of the first byte being `flags`.
+fn encode_int(value: u64, prefix: u8, flags: u8, buf: &mut Vec<u8>) {
+    let max = (1u64 << prefix) - 1;
+    if value < max {
+        buf.push(flags | value as u8);
+        return
+    }
+    buf.push(flags | max as u8);
+    let mut rest = value - max;
+    while rest >= 0x80 {
+        buf.push(rest as u8 | 0x80);
+        rest >>= 7
+    }
+    buf.push(rest as u8)
+}
+
+/// Decodes the QPACK integer with a prefix of `prefix` bits at `pos`.
+fn decode_int(buf: &[u8], pos: &mut usize, prefix: u8) -> Result<u64, &'static str> {
+    let max = (1u64 << prefix) - 1;
+    let first = *buf.get(*pos).ok_or("truncated header block")?;
+    *pos += 1;
+    let mut value = u64::from(first) & max;
+    if value < max {
+        return Ok(value)
+    }
+    for shift in (0 .. 63).step_by(7) {
+        let byte = *buf.get(*pos).ok_or("truncated header block")?;
+        *pos += 1;
+        value = value.checked_add(u64::from(byte & 0x7f) << shift).ok_or("integer overflow")?;
+        if byte & 0x80 == 0 {
+            return Ok(value)
+        }
+    }
+    Err("integer overflow")
+}
+
+/// Decodes the QPACK string literal with a length prefix of `prefix` bits
+/// at `pos`, preceded by the Huffman flag.
+fn decode_string(buf: &[u8], pos: &mut usize, prefix: u8) -> Result<String, &'static str> {
+    let huffman = buf.get(*pos).ok_or("truncated header block")? & (1 << prefix) != 0;
+    let len = decode_int(buf, pos, prefix)? as usize;
+    let bytes = buf.get(*pos .. pos.saturating_add(len)).ok_or("truncated header block")?;
+    *pos += len;
+    let bytes = if huffman { huffman_decode(bytes)? } else { bytes.to_vec() };
+    String::from_utf8(bytes).map_err(|_| "header is not valid UTF-8")
+}
+
+/// Decodes a string encoded with the Huffman code of HPACK.
+///
+/// The code is canonical, i.e. fully determined by the length of the code
+/// of every symbol.
+fn huffman_decode(input: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut symbols = (0 .. HUFFMAN_LENGTHS.len()).collect::<Vec<_>>();
+    symbols.sort_by_key(|s| HUFFMAN_LENGTHS[*s]);
+    let mut count = [0u32; 31];
+    for len in HUFFMAN_LENGTHS.iter() {
+        count[*len as usize] += 1
+    }
+    // The first code and the index in `symbols` of every code length.
+    let mut first = [0u32; 31];
+    let mut offset = [0u32; 31];
+    for len in 2 .. first.len() {
+        first[len] = (first[len - 1] + count[len - 1]) << 1;
+        offset[len] = offset[len - 1] + count[len - 1]
+    }
+
+    let mut output = Vec::with_capacity(input.len() * 8 / 5);
+    let (mut code, mut len) = (0u32, 0usize);
+    for bit in input.iter().flat_map(|b| (0 .. 8).rev().map(move |i| (b >> i) & 1)) {
+        code = code << 1 | u32::from(bit);
+        len += 1;
+        if code >= first[len] && code - first[len] < count[len] {
+            match symbols[(offset[len] + code - first[len]) as usize] {
+                256 => return Err("Huffman string contains EOS"),
+                symbol => output.push(symbol as u8)
+            }
+            code = 0;
+            len = 0
+        } else if len == 30 {
+            return Err("invalid Huffman code")
+        }
+    }
+    // The padding consists of the most significant bits of EOS, i.e. ones.
+    if len > 7 || code != (1 << len) - 1 {
+        return Err("invalid Huffman padding")
+    }
+    Ok(output)
+}
+
+/// The length of the Huffman code of every symbol, the last one being EOS.
+static HUFFMAN_LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28,
+    28, 28, 28, 28, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6,
+    5, 5, 5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10,
+    13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6,
+    15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6, 6, 5,
+    6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28,
+    20, 22, 20, 20, 22, 22, 22, 23, 22, 23, 23, 23, 23, 23, 24, 23,
+    24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24,
+    22, 21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23,
+    21, 21, 22, 21, 23, 22, 23, 23, 20, 22, 22, 22, 23, 22, 22, 23,
+    26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25,
+    19, 21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27,
+    20, 24, 20, 21, 22, 21, 21, 23, 22, 22, 25, 25, 24, 24, 26, 23,
+    26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26,
+    30,
+];
+
+/// The static table of QPACK.
+static STATIC_TABLE: [(&str, &str); 99] = [
+    (":authority", ""),
+    (":path", "/"),
+    ("age", "0"),
+    ("content-disposition", ""),
+    ("content-length", "0"),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("referer", ""),
+    ("set-cookie", ""),
+    (":method", "CONNECT"),
+    (":method", "DELETE"),
+    (":method", "GET"),
+    (":method", "HEAD"),
+    (":method", "OPTIONS"),
+    (":method", "POST"),
+    (":method", "PUT"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "103"),
+    (":status", "200"),
+    (":status", "304"),
+    (":status", "404"),
+    (":status", "503"),
+    ("accept", "*/*"),
+    ("accept", "application/dns-message"),
+    ("accept-encoding", "gzip, deflate, br"),
+    ("accept-ranges", "bytes"),
+    ("access-control-allow-headers", "cache-control"),
+    ("access-control-allow-headers", "content-type"),
+    ("access-control-allow-origin", "*"),
+    ("cache-control", "max-age=0"),
+    ("cache-control", "max-age=2592000"),
+    ("cache-control", "max-age=604800"),
+    ("cache-control", "no-cache"),
+    ("cache-control", "no-store"),
+    ("cache-control", "public, max-age=31536000"),
+    ("content-encoding", "br"),
+    ("content-encoding", "gzip"),
+    ("content-type", "application/dns-message"),
+    ("content-type", "application/javascript"),
+    ("content-type", "application/json"),
+    ("content-type", "application/x-www-form-urlencoded"),
+    ("content-type", "image/gif"),
+    ("content-type", "image/jpeg"),
+    ("content-type", "image/png"),
+    ("content-type", "text/css"),
+    ("content-type", "text/html; charset=utf-8"),
+    ("content-type", "text/plain"),
+    ("content-type", "text/plain;charset=utf-8"),
+    ("range", "bytes=0-"),
+    ("strict-transport-security", "max-age=31536000"),
+    ("strict-transport-security", "max-age=31536000; includesubdomains"),
+    ("strict-transport-security", "max-age=31536000; includesubdomains; preload"),
+    ("vary", "accept-encoding"),
+    ("vary", "origin"),
+    ("x-content-type-options", "nosniff"),
+    ("x-xss-protection", "1; mode=block"),
+    (":status", "100"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "302"),
+    (":status", "400"),
+    (":status", "403"),
+    (":status", "421"),
+    (":status", "425"),
+    (":status", "500"),
+    ("accept-language", ""),
+    ("access-control-allow-credentials", "FALSE"),
+    ("access-control-allow-credentials", "TRUE"),
+    ("access-control-allow-headers", "*"),
+    ("access-control-allow-methods", "get"),
+    ("access-control-allow-methods", "get, post, options"),
+    ("access-control-allow-methods", "options"),
+    ("access-control-expose-headers", "content-length"),
+    ("access-control-request-headers", "content-type"),
+    ("access-control-request-method", "get"),
+    ("access-control-request-method", "post"),
+    ("alt-svc", "clear"),
+    ("authorization", ""),
+    ("content-security-policy", "script-src 'none'; object-src 'none'; base-uri 'none'"),
+    ("early-data", "1"),
+    ("expect-ct", ""),
+    ("forwarded", ""),
+    ("if-range", ""),
+    ("origin", ""),
+    ("purpose", "prefetch"),
+    ("server", ""),
+    ("timing-allow-origin", "*"),
+    ("upgrade-insecure-requests", "1"),
+    ("user-agent", ""),
+    ("x-forwarded-for", ""),
+    ("x-frame-options", "deny"),
+    ("x-frame-options", "sameorigin"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varints() {
+        // The examples of RFC 9000, appendix A.1.
+        let examples: &[(&[u8], u64)] = &[
+            (&[0xc2, 0x19, 0x7c, 0x5e, 0xff, 0x14, 0xe8, 0x8c], 151_288_809_941_952_652),
+            (&[0x9d, 0x7f, 0x3e, 0x7d], 494_878_333),
+            (&[0x7b, 0xbd], 15_293),
+            (&[0x25], 37)
+        ];
+        for (bytes, value) in examples {
+            assert_eq!(decode_varint(bytes), Ok((*value, bytes.len())));
+            let mut buf = Vec::new();
+            encode_varint(*value, &mut buf);
+            assert_eq!(&buf[..], *bytes);
+            if bytes.len() > 1 {
+                assert_eq!(decode_varint(&bytes[.. 1]), Err(bytes.len() - 1));
+            }
+        }
+        assert_eq!(decode_varint(&[]), Err(1));
+    }
+
+    #[test]
+    fn stream_headers() {
+        let mut buf = Vec::new();
+        encode_varint(WEBTRANSPORT_STREAM, &mut buf);
+        encode_varint(1234, &mut buf);
+        assert_eq!(decode_stream_header(true, &buf[.. 2]), Err(1));
+        assert_eq!(decode_stream_header(true, &buf), Ok(StreamHeader::WebTransport(1234)));
+
+        let mut buf = Vec::new();
+        encode_frame(FRAME_HEADERS, b"block", &mut buf);
+        assert_eq!(decode_stream_header(true, &buf[.. 3]), Err(4));
+        assert_eq!(decode_stream_header(true, &buf), Ok(StreamHeader::Frame(FRAME_HEADERS, &b"block"[..])));
+
+        let buf = control_stream();
+        assert_eq!(decode_stream_header(false, &buf), Ok(StreamHeader::Uni(STREAM_CONTROL)));
+        assert!(matches!(decode_frame(&buf[1 ..]), Ok((FRAME_SETTINGS, _, n)) if n == buf.len() - 1));
+    }
+
+    #[test]
+    fn huffman() {
+        // The examples of RFC 7541, appendix C.4.
+        let examples: &[(&str, &str)] = &[
+            ("www.example.com", "f1e3c2e5f23a6ba0ab90f4ff"),
+            ("no-cache", "a8eb10649cbf"),
+            ("custom-key", "25a849e95ba97d7f"),
+            ("custom-value", "25a849e95bb8e8b4bf"),
+            ("Mon, 21 Oct 2013 20:13:21 GMT", "d07abe941054d444a8200595040b8166e082a62d1bff"),
+            ("https://www.example.com", "9d29ad171863c78f0b97c8e9ae82ae43d3")
+        ];
+        for (plain, encoded) in examples {
+            let encoded = (0 .. encoded.len()).step_by(2)
+                .map(|i| u8::from_str_radix(&encoded[i .. i + 2], 16).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(huffman_decode(&encoded).unwrap(), plain.as_bytes());
+        }
+        // Padding longer than 7 bits.
+        assert!(huffman_decode(&[0xf1, 0xff]).is_err());
+        // Padding with zeros.
+        assert!(huffman_decode(&[0xf1, 0xe0]).is_err());
+    }
+
+    #[test]
+    fn headers() {
+        let headers = [
+            (":method", "CONNECT"),
+            (":protocol", "webtransport"),
+            (":authority", "example.com"),
+            (":path", "/.well-known/libp2p-webtransport?type=noise"),
+            (":status", "200")
+        ];
+        let decoded = decode_headers(&encode_headers(&headers)).unwrap();
+        let decoded = decoded.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect::<Vec<_>>();
+        assert_eq!(decoded, headers);
+
+        // `:authority: www.example.com` with a Huffman-encoded value.
+        let mut block = vec![0, 0, 0x50, 0x8c];
+        block.extend_from_slice(&[0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff]);
+        assert_eq!(decode_headers(&block).unwrap(), vec![(":authority".to_owned(), "www.example.com".to_owned())]);
+
+        // A reference to the dynamic table.
+        assert!(decode_headers(&[0, 0, 0x80]).is_err());
+        assert!(decode_headers(&[1, 0]).is_err());
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Implementation of the libp2p `Transport` trait for QUIC.
+//!
+//! QUIC connections are authenticated with the certificates of the
+//! [libp2p TLS specification][spec] during the QUIC handshake, and their
+//! bidirectional streams serve as substreams. The transport thus directly
+//! yields the `PeerId` of the remote and a `StreamMuxer`, without any
+//! upgrade.
+//!
+//! Addresses have the form `/ip4/<address>/udp/<port>/quic` or
+//! `/ip6/<address>/udp/<port>/quic`.
+//!
+//! # Usage
+//!
+//! Example:
+//!
+//! ```
+//! use libp2p_core::identity;
+//! use libp2p_quic::QuicConfig;
+//!
+//! # fn main() {
+//! let id_keys = identity::Keypair::generate_ed25519();
+//! let quic = QuicConfig::new(&id_keys).unwrap();
+//! # }
+//! ```
+//!
+//! The output of the transport can be combined with the output of an
+//! upgraded transport, e.g. TCP, through `Transport::or_transport` and
+//! `EitherOutput::factor_first`.
+//!
+//! # WebTransport
+//!
+//! `WebTransportConfig` is a transport over the same QUIC stack, running
+//! libp2p connections over WebTransport sessions authenticated by Noise,
+//! on addresses of the form `/ip4/<address>/udp/<port>/quic/webtransport`.
+//! Listeners advertise the hash of their self-signed certificate through a
+//! trailing `/certhash` component, which dialers require to accept the
+//! certificate.
+//!
+//! [spec]: https://github.com/libp2p/specs/blob/master/tls/tls.md
+
+mod endpoint;
+mod error;
+mod http3;
+mod muxer;
+mod webtransport;
+
+pub use error::{QuicError, WebTransportError};
+pub use muxer::{Connecting, OutboundQuicSubstream, QuicMuxer, QuicSubstream};
+pub use webtransport::{OutboundWebTransportSubstream, WebTransportConfig, WebTransportListenStream, WebTransportMuxer};
+
+use endpoint::{Endpoint, Incoming};
+use futures::prelude::*;
+use get_if_addrs::get_if_addrs;
+use libp2p_core::{
+    identity,
+    PeerId,
+    Transport,
+    multiaddr::{Multiaddr, Protocol},
+    transport::{ListenerEvent, TransportError}
+};
+use libp2p_tls::{TlsConfig, Verifier, SERVER_NAME};
+use log::debug;
+use quinn_proto::{ClientConfig, ServerConfig, TransportConfig};
+use std::{
+    collections::VecDeque,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration
+};
+
+/// Represents the configuration for a QUIC transport capability for libp2p.
+#[derive(Clone)]
+pub struct QuicConfig {
+    /// The configuration of the TLS handshake.
+    tls: TlsConfig,
+    /// How long a connection may be idle before it is closed.
+    idle_timeout: Duration,
+    /// How often to send a packet on idle connections, or `None` to not
+    /// send any.
+    keep_alive: Option<Duration>
+}
+
+impl QuicConfig {
+    /// Creates a new configuration for the given identity keypair.
+    pub fn new(keypair: &identity::Keypair) -> Result<Self, QuicError> {
+        Ok(QuicConfig {
+            tls: TlsConfig::new(keypair)?,
+            idle_timeout: Duration::from_secs(10),
+            keep_alive: Some(Duration::from_secs(5))
+        })
+    }
+
+    /// Sets how long a connection may be idle before it is closed.
+    ///
+    /// The shorter of the timeouts of both endpoints applies.
+    pub fn idle_timeout(mut self, value: Duration) -> Self {
+        self.idle_timeout = value;
+        self
+    }
+
+    /// Sets how often to send a packet on idle connections to keep them
+    /// alive, or `None` to not send any.
+    pub fn keep_alive(mut self, value: Option<Duration>) -> Self {
+        self.keep_alive = value;
+        self
+    }
+
+    fn transport_config(&self) -> Arc<TransportConfig> {
+        transport_config(self.idle_timeout, self.keep_alive)
+    }
+}
+
+/// The QUIC configuration for the given idle timeout and keep-alive interval.
+fn transport_config(idle_timeout: Duration, keep_alive: Option<Duration>) -> Arc<TransportConfig> {
+    Arc::new(TransportConfig {
+        idle_timeout: idle_timeout.as_millis() as u64,
+        keep_alive_interval: keep_alive.map_or(0, |d| d.as_millis() as u32),
+        .. TransportConfig::default()
+    })
+}
+
+impl Transport for QuicConfig {
+    type Output = (PeerId, QuicMuxer);
+    type Error = QuicError;
+    type Listener = QuicListenStream;
+    type ListenerUpgrade = Connecting;
+    type Dial = Connecting;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let socket_addr = multiaddr_to_socketaddr(&addr)
+            .map_err(|()| TransportError::MultiaddrNotSupported(addr))?;
+        let socket = UdpSocket::bind(socket_addr).map_err(|e| TransportError::Other(e.into()))?;
+
+        let verifier = Arc::new(Verifier::default());
+        let crypto = self.tls.server_config(verifier.clone()).map_err(|e| TransportError::Other(e.into()))?;
+        let config = ServerConfig {
+            transport: self.transport_config(),
+            crypto: Arc::new(crypto),
+            .. ServerConfig::default()
+        };
+        let (endpoint, incoming) = Endpoint::server(socket, config, Some(verifier)).map_err(TransportError::Other)?;
+
+        let local_addr = endpoint.local_addr();
+        let listen_addrs = listen_addrs(local_addr).map_err(|e| TransportError::Other(e.into()))?;
+        debug!("Listening on {:?}", listen_addrs);
+
+        Ok(QuicListenStream {
+            endpoint,
+            incoming,
+            local_addr: ip_to_multiaddr(local_addr.ip(), local_addr.port()),
+            pending: listen_addrs.into_iter().collect()
+        })
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let socket_addr = match multiaddr_to_socketaddr(&addr) {
+            Ok(socket_addr) if socket_addr.port() != 0 && !socket_addr.ip().is_unspecified() => socket_addr,
+            _ => return Err(TransportError::MultiaddrNotSupported(addr))
+        };
+        debug!("Dialing {}", addr);
+
+        let endpoint = client_endpoint(socket_addr).map_err(TransportError::Other)?;
+
+        let verifier = Arc::new(Verifier::default());
+        let config = ClientConfig {
+            transport: self.transport_config(),
+            crypto: Arc::new(self.tls.client_config(verifier.clone()))
+        };
+        let handle = endpoint.connect(config, socket_addr, SERVER_NAME, Some(verifier))
+            .map_err(TransportError::Other)?;
+        Ok(Connecting::new(endpoint, handle))
+    }
+}
+
+/// Creates an endpoint on an ephemeral port to connect to `remote`.
+fn client_endpoint(remote: SocketAddr) -> Result<Endpoint, QuicError> {
+    let bind_addr = match remote {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0)
+    };
+    Endpoint::client(UdpSocket::bind(bind_addr)?)
+}
+
+/// Stream of incoming QUIC connections on a listening endpoint.
+///
+/// Dropping the stream rejects new connections, without closing the
+/// established ones.
+pub struct QuicListenStream {
+    endpoint: Endpoint,
+    incoming: Incoming,
+    /// The address of the UDP socket of the endpoint.
+    local_addr: Multiaddr,
+    /// The listen addresses not reported yet.
+    pending: VecDeque<Multiaddr>
+}
+
+impl Stream for QuicListenStream {
+    type Item = Result<ListenerEvent<Connecting>, QuicError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(addr) = self.pending.pop_front() {
+            return Poll::Ready(Some(Ok(ListenerEvent::NewAddress(addr))))
+        }
+        match self.incoming.poll_next_unpin(cx) {
+            Poll::Ready(Some((handle, remote))) => {
+                let remote_addr = ip_to_multiaddr(remote.ip(), remote.port());
+                debug!("Incoming connection from {} at {}", remote_addr, self.local_addr);
+                Poll::Ready(Some(Ok(ListenerEvent::Upgrade {
+                    upgrade: Connecting::new(self.endpoint.clone(), handle),
+                    local_addr: self.local_addr.clone(),
+                    remote_addr
+                })))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending
+        }
+    }
+}
+
+impl Drop for QuicListenStream {
+    fn drop(&mut self) {
+        self.endpoint.stop_listening()
+    }
+}
+
+/// The addresses of a socket bound to `local_addr`, i.e. the addresses of
+/// all interfaces of the same IP version if it is unspecified.
+fn listen_addrs(local_addr: SocketAddr) -> io::Result<Vec<Multiaddr>> {
+    if local_addr.ip().is_unspecified() {
+        Ok(get_if_addrs()?
+            .into_iter()
+            .map(|iface| iface.ip())
+            .filter(|ip| ip.is_ipv4() == local_addr.is_ipv4())
+            .map(|ip| ip_to_multiaddr(ip, local_addr.port()))
+            .collect())
+    } else {
+        Ok(vec![ip_to_multiaddr(local_addr.ip(), local_addr.port())])
+    }
+}
+
+// This type of logic should probably be moved into the multiaddr package
+fn multiaddr_to_socketaddr(addr: &Multiaddr) -> Result<SocketAddr, ()> {
+    let mut iter = addr.iter();
+    let proto1 = iter.next().ok_or(())?;
+    let proto2 = iter.next().ok_or(())?;
+    let proto3 = iter.next().ok_or(())?;
+
+    if iter.next().is_some() {
+        return Err(());
+    }
+
+    match (proto1, proto2, proto3) {
+        (Protocol::Ip4(ip), Protocol::Udp(port), Protocol::Quic) => Ok(SocketAddr::new(ip.into(), port)),
+        (Protocol::Ip6(ip), Protocol::Udp(port), Protocol::Quic) => Ok(SocketAddr::new(ip.into(), port)),
+        _ => Err(()),
+    }
+}
+
+// Create a [`Multiaddr`] from the given IP address and port number.
+fn ip_to_multiaddr(ip: IpAddr, port: u16) -> Multiaddr {
+    let proto = match ip {
+        IpAddr::V4(ip) => Protocol::Ip4(ip),
+        IpAddr::V6(ip) => Protocol::Ip6(ip)
+    };
+    Multiaddr::empty().with(proto).with(Protocol::Udp(port)).with(Protocol::Quic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::multiaddr_to_socketaddr;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    #[test]
+    fn multiaddr_to_udp_conversion() {
+        assert!(multiaddr_to_socketaddr(&"/ip4/127.0.0.1/udp/1234".parse().unwrap()).is_err());
+        assert!(multiaddr_to_socketaddr(&"/ip4/127.0.0.1/tcp/1234/quic".parse().unwrap()).is_err());
+        // The underlying QUIC implementation only speaks the draft versions.
+        assert!(multiaddr_to_socketaddr(&"/ip4/127.0.0.1/udp/1234/quic-v1".parse().unwrap()).is_err());
+
+        assert_eq!(
+            multiaddr_to_socketaddr(&"/ip4/127.0.0.1/udp/12345/quic".parse().unwrap()),
+            Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 12345))
+        );
+        assert_eq!(
+            multiaddr_to_socketaddr(&"/ip6/::1/udp/12345/quic".parse().unwrap()),
+            Ok(SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 12345))
+        );
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The connections of the transport, whose bidirectional QUIC streams are
+//! the substreams of the `StreamMuxer`.
+
+use crate::{endpoint::Endpoint, error::QuicError};
+use libp2p_core::{muxing::StreamMuxer, PeerId};
+use libp2p_tls::TlsError;
+use quinn_proto::{ConnectionHandle, Dir, FinishError, ReadError, StreamId, WriteError};
+use std::{future::Future, pin::Pin, task::{Context, Poll}};
+
+/// A QUIC connection whose handshake is in progress.
+///
+/// Resolves to the `PeerId` of the remote and the established connection.
+/// Dropping it aborts the handshake.
+pub struct Connecting {
+    endpoint: Option<Endpoint>,
+    handle: ConnectionHandle
+}
+
+impl Connecting {
+    pub(crate) fn new(endpoint: Endpoint, handle: ConnectionHandle) -> Self {
+        Connecting { endpoint: Some(endpoint), handle }
+    }
+}
+
+impl Future for Connecting {
+    type Output = Result<(PeerId, QuicMuxer), QuicError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let handle = self.handle;
+        let endpoint = self.endpoint.as_ref().expect("Future polled after completion");
+        futures::ready!(poll_established(endpoint, handle, cx))?;
+        let remote = endpoint.lock().connection(handle)?.remote.clone();
+        let endpoint = self.endpoint.take().expect("Future polled after completion");
+        let muxer = QuicMuxer { endpoint, handle };
+        match remote {
+            Some(remote) => Poll::Ready(Ok((remote.into_peer_id(), muxer))),
+            // Dropping the muxer closes the connection.
+            None => Poll::Ready(Err(QuicError::Tls(TlsError::InvalidCertificate)))
+        }
+    }
+}
+
+/// Polls for the completion of the handshake of a connection.
+pub(crate) fn poll_established(endpoint: &Endpoint, handle: ConnectionHandle, cx: &mut Context)
+    -> Poll<Result<(), QuicError>>
+{
+    let mut inner = endpoint.lock();
+    let connection = inner.connection(handle)?;
+    if connection.established {
+        Poll::Ready(Ok(()))
+    } else {
+        connection.wakers.handshake = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Connecting {
+    fn drop(&mut self) {
+        if let Some(endpoint) = &self.endpoint {
+            endpoint.close(self.handle)
+        }
+    }
+}
+
+/// An established QUIC connection.
+///
+/// Dropping the muxer closes the connection.
+pub struct QuicMuxer {
+    pub(crate) endpoint: Endpoint,
+    pub(crate) handle: ConnectionHandle
+}
+
+impl QuicMuxer {
+    pub(crate) fn new(endpoint: Endpoint, handle: ConnectionHandle) -> Self {
+        QuicMuxer { endpoint, handle }
+    }
+}
+
+/// A bidirectional QUIC stream.
+#[derive(Debug)]
+pub struct QuicSubstream {
+    pub(crate) id: StreamId,
+    /// Whether the sending side has been finished.
+    finished: bool,
+    /// Whether all data sent by the remote has been read.
+    eof: bool
+}
+
+impl QuicSubstream {
+    pub(crate) fn new(id: StreamId) -> Self {
+        QuicSubstream { id, finished: false, eof: false }
+    }
+}
+
+/// A substream being opened, which is pending while the remote does not
+/// allow more streams.
+#[derive(Debug)]
+pub struct OutboundQuicSubstream(());
+
+impl StreamMuxer for QuicMuxer {
+    type Substream = QuicSubstream;
+    type OutboundSubstream = OutboundQuicSubstream;
+    type Error = QuicError;
+
+    fn poll_inbound(&self, cx: &mut Context) -> Poll<Result<Self::Substream, Self::Error>> {
+        let mut inner = self.endpoint.lock();
+        let connection = inner.connection(self.handle)?;
+        match connection.connection.accept(Dir::Bi) {
+            Some(id) => {
+                self.endpoint.wake();
+                Poll::Ready(Ok(QuicSubstream::new(id)))
+            }
+            None => {
+                connection.wakers.inbound = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn open_outbound(&self) -> Self::OutboundSubstream {
+        OutboundQuicSubstream(())
+    }
+
+    fn poll_outbound(&self, cx: &mut Context, _: &mut Self::OutboundSubstream)
+        -> Poll<Result<Self::Substream, Self::Error>>
+    {
+        let mut inner = self.endpoint.lock();
+        let connection = inner.connection(self.handle)?;
+        match connection.connection.open(Dir::Bi) {
+            Some(id) => Poll::Ready(Ok(QuicSubstream::new(id))),
+            None => {
+                connection.wakers.outbound = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn destroy_outbound(&self, _: Self::OutboundSubstream) {}
+
+    fn read_substream(&self, cx: &mut Context, s: &mut Self::Substream, buf: &mut [u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        let mut inner = self.endpoint.lock();
+        let connection = inner.connection(self.handle)?;
+        match connection.connection.read(s.id, buf) {
+            Ok(Some(n)) => {
+                // Reading grants the remote more flow control credit.
+                self.endpoint.wake();
+                Poll::Ready(Ok(n))
+            }
+            // Streams are forgotten once read to the end.
+            Ok(None) | Err(ReadError::UnknownStream) => {
+                s.eof = true;
+                Poll::Ready(Ok(0))
+            }
+            Err(ReadError::Blocked) => {
+                connection.wakers.read.insert(s.id, cx.waker().clone());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(QuicError::Read(e)))
+        }
+    }
+
+    fn write_substream(&self, cx: &mut Context, s: &mut Self::Substream, buf: &[u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        let mut inner = self.endpoint.lock();
+        let connection = inner.connection(self.handle)?;
+        match connection.connection.write(s.id, buf) {
+            Ok(n) => {
+                self.endpoint.wake();
+                Poll::Ready(Ok(n))
+            }
+            Err(WriteError::Blocked) => {
+                connection.wakers.write.insert(s.id, cx.waker().clone());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(QuicError::Write(e)))
+        }
+    }
+
+    /// Written data is sent by the background task of the endpoint as soon
+    /// as congestion and flow control allow it.
+    fn flush_substream(&self, _: &mut Context, _: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        self.endpoint.lock().connection(self.handle)?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn shutdown_substream(&self, _: &mut Context, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        if s.finished {
+            return Poll::Ready(Ok(()))
+        }
+        let mut inner = self.endpoint.lock();
+        let connection = inner.connection(self.handle)?;
+        match connection.connection.finish(s.id) {
+            Ok(()) => {
+                s.finished = true;
+                self.endpoint.wake();
+                Poll::Ready(Ok(()))
+            }
+            Err(e @ FinishError::Stopped { .. }) => Poll::Ready(Err(QuicError::Finish(e))),
+            // The stream has been reset by the remote.
+            Err(FinishError::UnknownStream) => {
+                s.finished = true;
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+
+    fn destroy_substream(&self, s: Self::Substream) {
+        let mut inner = self.endpoint.lock();
+        if let Ok(connection) = inner.connection(self.handle) {
+            connection.wakers.read.remove(&s.id);
+            connection.wakers.write.remove(&s.id);
+            if !s.finished {
+                connection.connection.reset(s.id, 0u32.into())
+            }
+            if !s.eof {
+                let _ = connection.connection.stop_sending(s.id, 0u32.into());
+            }
+            self.endpoint.wake()
+        }
+    }
+
+    fn is_remote_acknowledged(&self) -> bool {
+        true
+    }
+
+    fn close(&self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.endpoint.close(self.handle);
+        Poll::Ready(Ok(()))
+    }
+
+    fn flush_all(&self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for QuicMuxer {
+    fn drop(&mut self) {
+        self.endpoint.close(self.handle)
+    }
+}
@@ -0,0 +1,919 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! WebTransport sessions over QUIC, as described by the
+//! [libp2p WebTransport specification][spec].
+//!
+//! A QUIC connection carries a single WebTransport session, established by
+//! an HTTP/3 `CONNECT` request for `/.well-known/libp2p-webtransport?type=noise`.
+//! The bidirectional streams of the session serve as substreams, the first
+//! of which, opened by the dialer, carries a Noise `XX` handshake
+//! authenticating both peers. The hash of the TLS certificate of the
+//! listener is bound to the handshake through its prologue.
+//!
+//! Listeners use a self-signed certificate valid for 12 days and advertise
+//! its hash with their addresses, which have the form
+//! `/ip4/<address>/udp/<port>/quic/webtransport/certhash/<multihash>`.
+//! Dialers only accept certificates matching one of the `/certhash`
+//! components of the dialed address.
+//!
+//! # Compatibility
+//!
+//! The underlying QUIC stack implements draft 24 of QUIC, hence browsers,
+//! which implement QUIC version 1, cannot connect yet. Neither datagrams
+//! nor unidirectional WebTransport streams are supported, and the
+//! certificate hashes are not exchanged in the Noise handshake payload.
+//!
+//! [spec]: https://github.com/libp2p/specs/blob/master/webtransport/README.md
+
+use crate::{
+    client_endpoint,
+    endpoint::{Connection, Endpoint, Incoming},
+    error::{QuicError, WebTransportError},
+    http3::{self, StreamHeader},
+    ip_to_multiaddr,
+    listen_addrs,
+    muxer::{QuicMuxer, QuicSubstream},
+    transport_config
+};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{
+    identity,
+    PeerId,
+    Transport,
+    multiaddr::{multihash::{self, Multihash}, Multiaddr, Protocol},
+    muxing::{self, StreamMuxer},
+    transport::{ListenerEvent, TransportError},
+    upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo}
+};
+use libp2p_noise::{Keypair, NoiseAuthenticated, NoiseConfig, NoiseOutput, X25519Spec, XX};
+use libp2p_tls::TlsError;
+use log::debug;
+use parking_lot::Mutex;
+use quinn_proto::{ClientConfig, ConnectionHandle, Dir, ReadError, ServerConfig, StreamId, WriteError};
+use std::{
+    collections::VecDeque,
+    io,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration
+};
+
+/// The path of the `CONNECT` request establishing a session.
+const PATH: &str = "/.well-known/libp2p-webtransport?type=noise";
+
+/// The ALPN protocols of HTTP/3, in its final and draft 24 version.
+const ALPN: &[&[u8]] = &[b"h3", b"h3-24"];
+
+/// The name of the listener in its certificate, which is not verified.
+const SERVER_NAME: &str = "localhost";
+
+/// How long the certificate of a listener is valid, staying below the 14
+/// days accepted by browsers.
+const CERTIFICATE_VALIDITY_DAYS: i64 = 12;
+
+/// The maximum size of the header of a stream, including the request and
+/// response headers of the session.
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+
+/// Represents the configuration of a WebTransport transport capability for
+/// libp2p.
+#[derive(Clone)]
+pub struct WebTransportConfig {
+    /// The configuration of the Noise handshake.
+    noise: NoiseConfig<XX, X25519Spec>,
+    /// The self-signed certificate presented when listening.
+    certificate: rustls::Certificate,
+    private_key: rustls::PrivateKey,
+    /// The hash of `certificate`.
+    certhash: Multihash,
+    /// How long a connection may be idle before it is closed.
+    idle_timeout: Duration,
+    /// How often to send a packet on idle connections, or `None` to not
+    /// send any.
+    keep_alive: Option<Duration>
+}
+
+impl WebTransportConfig {
+    /// Creates a new configuration for the given identity keypair, with a
+    /// new self-signed certificate.
+    ///
+    /// The certificate is not renewed, i.e. listeners must be restarted
+    /// with a new configuration before it expires.
+    pub fn new(keypair: &identity::Keypair) -> Result<Self, QuicError> {
+        let dh_keys = Keypair::<X25519Spec>::new().into_authentic(keypair).map_err(WebTransportError::Noise)?;
+        let (certificate, private_key) = generate_certificate()?;
+        Ok(WebTransportConfig {
+            noise: NoiseConfig::xx(dh_keys),
+            certhash: certhash(&certificate),
+            certificate,
+            private_key,
+            idle_timeout: Duration::from_secs(10),
+            keep_alive: Some(Duration::from_secs(5))
+        })
+    }
+
+    /// Sets how long a connection may be idle before it is closed.
+    ///
+    /// The shorter of the timeouts of both endpoints applies.
+    pub fn idle_timeout(mut self, value: Duration) -> Self {
+        self.idle_timeout = value;
+        self
+    }
+
+    /// Sets how often to send a packet on idle connections to keep them
+    /// alive, or `None` to not send any.
+    pub fn keep_alive(mut self, value: Option<Duration>) -> Self {
+        self.keep_alive = value;
+        self
+    }
+
+    /// The hash of the certificate, as advertised with the listen addresses.
+    pub fn certhash(&self) -> &Multihash {
+        &self.certhash
+    }
+
+    /// The Noise handshake, bound to the certificate with the given hash.
+    fn noise(self, certhash: Multihash) -> NoiseAuthenticated<XX, X25519Spec, ()> {
+        self.noise.with_prologue(certhash.into_bytes()).into_authenticated()
+    }
+}
+
+impl Transport for WebTransportConfig {
+    type Output = (PeerId, WebTransportMuxer);
+    type Error = QuicError;
+    type Listener = WebTransportListenStream;
+    type ListenerUpgrade = BoxFuture<'static, Result<Self::Output, QuicError>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, QuicError>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let socket_addr = match multiaddr_to_socketaddr(&addr) {
+            Ok((socket_addr, certhashes)) if certhashes.is_empty() => socket_addr,
+            _ => return Err(TransportError::MultiaddrNotSupported(addr))
+        };
+        let socket = UdpSocket::bind(socket_addr).map_err(|e| TransportError::Other(e.into()))?;
+
+        let mut crypto = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+        crypto.versions = vec![rustls::ProtocolVersion::TLSv1_3];
+        crypto.set_protocols(&alpn());
+        crypto.set_single_cert(vec![self.certificate.clone()], self.private_key.clone())
+            .map_err(|e| TransportError::Other(TlsError::from(io::Error::new(io::ErrorKind::InvalidInput, e)).into()))?;
+        let config = ServerConfig {
+            transport: transport_config(self.idle_timeout, self.keep_alive),
+            crypto: Arc::new(crypto),
+            .. ServerConfig::default()
+        };
+        let (endpoint, incoming) = Endpoint::server(socket, config, None).map_err(TransportError::Other)?;
+
+        let local_addr = endpoint.local_addr();
+        let certhash = Protocol::Certhash(self.certhash.clone());
+        let listen_addrs = listen_addrs(local_addr).map_err(|e| TransportError::Other(e.into()))?
+            .into_iter()
+            .map(|addr| addr.with(Protocol::WebTransport).with(certhash.clone()))
+            .collect::<VecDeque<_>>();
+        debug!("Listening on {:?}", listen_addrs);
+
+        Ok(WebTransportListenStream {
+            endpoint,
+            incoming,
+            local_addr: ip_to_multiaddr(local_addr.ip(), local_addr.port())
+                .with(Protocol::WebTransport)
+                .with(certhash),
+            pending: listen_addrs,
+            config: self
+        })
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let (socket_addr, certhashes) = match multiaddr_to_socketaddr(&addr) {
+            Ok((socket_addr, certhashes))
+                if socket_addr.port() != 0 && !socket_addr.ip().is_unspecified() && !certhashes.is_empty() =>
+                    (socket_addr, certhashes),
+            _ => return Err(TransportError::MultiaddrNotSupported(addr))
+        };
+        debug!("Dialing {}", addr);
+
+        let endpoint = client_endpoint(socket_addr).map_err(TransportError::Other)?;
+        let verifier = Arc::new(CerthashVerifier { hashes: certhashes, accepted: Mutex::new(None) });
+        let mut crypto = rustls::ClientConfig::new();
+        crypto.versions = vec![rustls::ProtocolVersion::TLSv1_3];
+        crypto.set_protocols(&alpn());
+        crypto.enable_sni = false;
+        crypto.dangerous().set_certificate_verifier(verifier.clone());
+        let config = ClientConfig {
+            transport: transport_config(self.idle_timeout, self.keep_alive),
+            crypto: Arc::new(crypto)
+        };
+        let handle = endpoint.connect(config, socket_addr, SERVER_NAME, None).map_err(TransportError::Other)?;
+        let role = Role::Dialer { authority: socket_addr.to_string() };
+        let muxer = WebTransportMuxer::new(endpoint, handle, role);
+
+        Ok(async move {
+            future::poll_fn(|cx| muxer.poll_session(cx)).await?;
+            let certhash = verifier.accepted.lock().take().ok_or(QuicError::Tls(TlsError::InvalidCertificate))?;
+            let muxer = Arc::new(muxer);
+            let substream = muxing::outbound_from_ref_and_wrap(muxer.clone()).await?;
+            let noise = self.noise(certhash);
+            let info = noise.protocol_info().next().expect("Noise has a protocol name");
+            let (peer, output) = noise.upgrade_outbound(substream, info).await.map_err(WebTransportError::Noise)?;
+            close_handshake_stream(output).await?;
+            Ok((peer, unwrap_muxer(muxer)))
+        }.boxed())
+    }
+}
+
+/// Stream of incoming WebTransport sessions on a listening endpoint.
+///
+/// Dropping the stream rejects new connections, without closing the
+/// established ones.
+pub struct WebTransportListenStream {
+    endpoint: Endpoint,
+    incoming: Incoming,
+    config: WebTransportConfig,
+    /// The address of the UDP socket of the endpoint.
+    local_addr: Multiaddr,
+    /// The listen addresses not reported yet.
+    pending: VecDeque<Multiaddr>
+}
+
+impl Stream for WebTransportListenStream {
+    type Item = Result<ListenerEvent<BoxFuture<'static, Result<(PeerId, WebTransportMuxer), QuicError>>>, QuicError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(addr) = self.pending.pop_front() {
+            return Poll::Ready(Some(Ok(ListenerEvent::NewAddress(addr))))
+        }
+        let (handle, remote) = match self.incoming.poll_next_unpin(cx) {
+            Poll::Ready(Some(incoming)) => incoming,
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending
+        };
+        let remote_addr = ip_to_multiaddr(remote.ip(), remote.port()).with(Protocol::WebTransport);
+        debug!("Incoming connection from {} at {}", remote_addr, self.local_addr);
+
+        let muxer = Arc::new(WebTransportMuxer::new(self.endpoint.clone(), handle, Role::Listener));
+        let certhash = self.config.certhash.clone();
+        let noise = self.config.clone().noise(certhash);
+        let upgrade = async move {
+            let substream = muxing::inbound_from_ref_and_wrap(muxer.clone()).await?;
+            let info = noise.protocol_info().next().expect("Noise has a protocol name");
+            let (peer, output) = noise.upgrade_inbound(substream, info).await.map_err(WebTransportError::Noise)?;
+            close_handshake_stream(output).await?;
+            Ok((peer, unwrap_muxer(muxer)))
+        };
+        Poll::Ready(Some(Ok(ListenerEvent::Upgrade {
+            upgrade: upgrade.boxed(),
+            local_addr: self.local_addr.clone(),
+            remote_addr
+        })))
+    }
+}
+
+impl Drop for WebTransportListenStream {
+    fn drop(&mut self) {
+        self.endpoint.stop_listening()
+    }
+}
+
+/// Closes the stream of the Noise handshake once both peers are done with
+/// it, such that neither discards data the other has not read yet.
+async fn close_handshake_stream<T>(mut output: NoiseOutput<T>) -> Result<(), QuicError>
+where
+    T: AsyncRead + AsyncWrite + Unpin
+{
+    output.close().await?;
+    match output.read(&mut [0]).await? {
+        0 => Ok(()),
+        _ => Err(WebTransportError::Protocol("unexpected data after the Noise handshake").into())
+    }
+}
+
+/// Takes back the muxer from the `Arc` shared with the dropped handshake
+/// stream.
+fn unwrap_muxer(muxer: Arc<WebTransportMuxer>) -> WebTransportMuxer {
+    match Arc::try_unwrap(muxer) {
+        Ok(muxer) => muxer,
+        Err(_) => unreachable!("The handshake stream has been dropped")
+    }
+}
+
+/// A QUIC connection carrying a WebTransport session, whose bidirectional
+/// streams are the substreams of the `StreamMuxer`.
+///
+/// Dropping the muxer closes the connection.
+pub struct WebTransportMuxer {
+    /// The session, always locked before the endpoint.
+    session: Mutex<Session>,
+    quic: QuicMuxer
+}
+
+impl WebTransportMuxer {
+    fn new(endpoint: Endpoint, handle: ConnectionHandle, role: Role) -> Self {
+        WebTransportMuxer {
+            session: Mutex::new(Session::new(role)),
+            quic: QuicMuxer::new(endpoint, handle)
+        }
+    }
+
+    /// Processes the HTTP/3 streams of the connection, returning the
+    /// session for further processing.
+    fn poll_streams(&self, cx: &mut Context) -> Result<parking_lot::MutexGuard<'_, Session>, QuicError> {
+        let mut session = self.session.lock();
+        let mut inner = self.quic.endpoint.lock();
+        let progress = session.poll(inner.connection(self.quic.handle)?, cx)?;
+        drop(inner);
+        if progress {
+            self.quic.endpoint.wake()
+        }
+        Ok(session)
+    }
+
+    /// Polls for the establishment of the session.
+    fn poll_session(&self, cx: &mut Context) -> Poll<Result<(), QuicError>> {
+        if self.poll_streams(cx)?.established {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// A substream being opened, which is pending while the remote does not
+/// allow more streams or the signal of its session cannot be sent.
+#[derive(Debug)]
+pub struct OutboundWebTransportSubstream {
+    /// The opened stream and the part of its header not written yet.
+    stream: Option<(StreamId, Vec<u8>)>
+}
+
+impl StreamMuxer for WebTransportMuxer {
+    type Substream = QuicSubstream;
+    type OutboundSubstream = OutboundWebTransportSubstream;
+    type Error = QuicError;
+
+    fn poll_inbound(&self, cx: &mut Context) -> Poll<Result<Self::Substream, Self::Error>> {
+        let mut session = self.session.lock();
+        if session.inbound.is_empty() {
+            drop(session);
+            session = self.poll_streams(cx)?;
+        }
+        match session.inbound.pop_front() {
+            Some(id) => Poll::Ready(Ok(QuicSubstream::new(id))),
+            None => Poll::Pending
+        }
+    }
+
+    fn open_outbound(&self) -> Self::OutboundSubstream {
+        OutboundWebTransportSubstream { stream: None }
+    }
+
+    fn poll_outbound(&self, cx: &mut Context, outbound: &mut Self::OutboundSubstream)
+        -> Poll<Result<Self::Substream, Self::Error>>
+    {
+        let session_id = self.session.lock().id()
+            .ok_or(WebTransportError::Protocol("the session is not established"))?;
+        let mut inner = self.quic.endpoint.lock();
+        let connection = inner.connection(self.quic.handle)?;
+        if outbound.stream.is_none() {
+            match connection.connection.open(Dir::Bi) {
+                Some(id) => {
+                    let mut header = Vec::new();
+                    http3::encode_varint(http3::WEBTRANSPORT_STREAM, &mut header);
+                    http3::encode_varint(session_id, &mut header);
+                    outbound.stream = Some((id, header))
+                }
+                None => {
+                    connection.wakers.outbound = Some(cx.waker().clone());
+                    return Poll::Pending
+                }
+            }
+        }
+        let (id, header) = outbound.stream.as_mut().expect("The stream has been opened above");
+        while !header.is_empty() {
+            match connection.connection.write(*id, header) {
+                Ok(n) => {
+                    header.drain(.. n);
+                }
+                Err(WriteError::Blocked) => {
+                    connection.wakers.write.insert(*id, cx.waker().clone());
+                    drop(inner);
+                    self.quic.endpoint.wake();
+                    return Poll::Pending
+                }
+                Err(e) => return Poll::Ready(Err(QuicError::Write(e)))
+            }
+        }
+        drop(inner);
+        self.quic.endpoint.wake();
+        let (id, _) = outbound.stream.take().expect("The stream has been opened above");
+        Poll::Ready(Ok(QuicSubstream::new(id)))
+    }
+
+    fn destroy_outbound(&self, outbound: Self::OutboundSubstream) {
+        if let Some((id, _)) = outbound.stream {
+            self.quic.destroy_substream(QuicSubstream::new(id))
+        }
+    }
+
+    fn read_substream(&self, cx: &mut Context, s: &mut Self::Substream, buf: &mut [u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        self.quic.read_substream(cx, s, buf)
+    }
+
+    fn write_substream(&self, cx: &mut Context, s: &mut Self::Substream, buf: &[u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        self.quic.write_substream(cx, s, buf)
+    }
+
+    fn flush_substream(&self, cx: &mut Context, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        self.quic.flush_substream(cx, s)
+    }
+
+    fn shutdown_substream(&self, cx: &mut Context, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        self.quic.shutdown_substream(cx, s)
+    }
+
+    fn destroy_substream(&self, s: Self::Substream) {
+        self.quic.destroy_substream(s)
+    }
+
+    fn is_remote_acknowledged(&self) -> bool {
+        self.quic.is_remote_acknowledged()
+    }
+
+    fn close(&self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.quic.close(cx)
+    }
+
+    fn flush_all(&self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.quic.flush_all(cx)
+    }
+}
+
+/// Which side of the session we are.
+enum Role {
+    /// Waiting for the `CONNECT` request of the dialer.
+    Listener,
+    /// Sending the `CONNECT` request to the given authority.
+    Dialer { authority: String }
+}
+
+/// The HTTP/3 state of a connection carrying a WebTransport session.
+struct Session {
+    role: Role,
+    /// Whether our control stream and request have been sent.
+    started: bool,
+    /// The streams of the remote whose header is incomplete.
+    headers: Vec<(StreamId, Vec<u8>)>,
+    /// The streams of the remote whose data is discarded.
+    ignored: Vec<StreamId>,
+    /// Data to write to our streams, and whether to finish them afterwards.
+    writes: Vec<(StreamId, Vec<u8>, bool)>,
+    /// The stream of the `CONNECT` request, whose ID is the session ID.
+    request: Option<StreamId>,
+    /// The response of the listener received so far, when dialing.
+    response: Vec<u8>,
+    /// Whether the session has been accepted.
+    established: bool,
+    /// The streams received before the session was established, together
+    /// with their session ID.
+    early: Vec<(StreamId, u64)>,
+    /// The streams of the session not yet returned by `poll_inbound`.
+    inbound: VecDeque<StreamId>
+}
+
+/// The outcome of reading the header of a stream.
+enum Read {
+    Complete,
+    Blocked,
+    Ended
+}
+
+impl Session {
+    fn new(role: Role) -> Self {
+        Session {
+            role,
+            started: false,
+            headers: Vec::new(),
+            ignored: Vec::new(),
+            writes: Vec::new(),
+            request: None,
+            response: Vec::new(),
+            established: false,
+            early: Vec::new(),
+            inbound: VecDeque::new()
+        }
+    }
+
+    /// The ID of the session, once established.
+    fn id(&self) -> Option<u64> {
+        self.request.filter(|_| self.established).map(|id| id.0)
+    }
+
+    /// Processes the streams of the connection as far as possible,
+    /// registering the current task to be woken up on further progress.
+    ///
+    /// Returns whether anything has been sent or received.
+    fn poll(&mut self, connection: &mut Connection, cx: &mut Context) -> Result<bool, QuicError> {
+        if !connection.established {
+            connection.wakers.handshake = Some(cx.waker().clone());
+            return Ok(false)
+        }
+        let Connection { connection: conn, wakers, .. } = connection;
+        let mut progress = false;
+
+        if !self.started {
+            self.start(conn)?;
+            progress = true
+        }
+
+        while let Some(id) = conn.accept(Dir::Uni).or_else(|| conn.accept(Dir::Bi)) {
+            self.headers.push((id, Vec::new()));
+            progress = true
+        }
+
+        let mut i = 0;
+        while i < self.headers.len() {
+            let id = self.headers[i].0;
+            let bidirectional = id.dir() == Dir::Bi;
+            let read = read_header(conn, id, &mut self.headers[i].1, |buf| {
+                http3::decode_stream_header(bidirectional, buf).map(drop)
+            })?;
+            match read {
+                Read::Complete => {
+                    let (id, header) = self.headers.swap_remove(i);
+                    self.on_stream(conn, id, &header)?;
+                    progress = true
+                }
+                Read::Blocked => {
+                    wakers.read.insert(id, cx.waker().clone());
+                    i += 1
+                }
+                Read::Ended => {
+                    self.headers.swap_remove(i);
+                    progress = true
+                }
+            }
+        }
+
+        if let (Role::Dialer { .. }, Some(request), false) = (&self.role, self.request, self.established) {
+            loop {
+                match read_header(conn, request, &mut self.response, |buf| http3::decode_frame(buf).map(drop))? {
+                    Read::Complete => {
+                        progress = true;
+                        let (ty, payload, _) = http3::decode_frame(&self.response).expect("The frame is complete");
+                        if http3::is_reserved_frame(ty) {
+                            self.response.clear();
+                            continue
+                        }
+                        if ty != http3::FRAME_HEADERS {
+                            return Err(WebTransportError::Protocol("unexpected frame instead of the response").into())
+                        }
+                        let headers = http3::decode_headers(payload).map_err(WebTransportError::Protocol)?;
+                        match header(&headers, ":status") {
+                            Some("200") => self.establish(conn),
+                            Some(status) => return Err(WebTransportError::Rejected(status.parse().unwrap_or(0)).into()),
+                            None => return Err(WebTransportError::Protocol("response without status").into())
+                        }
+                    }
+                    Read::Blocked => {
+                        wakers.read.insert(request, cx.waker().clone());
+                    }
+                    Read::Ended => return Err(WebTransportError::Closed.into())
+                }
+                break
+            }
+        }
+
+        // Neither settings, nor capsules on the request stream are used.
+        let mut discarded = [0; 1024];
+        let mut i = 0;
+        while i < self.ignored.len() {
+            let id = self.ignored[i];
+            match discard(conn, id, &mut discarded) {
+                Read::Blocked => {
+                    wakers.read.insert(id, cx.waker().clone());
+                    i += 1
+                }
+                _ => {
+                    self.ignored.swap_remove(i);
+                }
+            }
+        }
+        if let (Some(request), true) = (self.request, self.established) {
+            match discard(conn, request, &mut discarded) {
+                Read::Blocked => {
+                    wakers.read.insert(request, cx.waker().clone());
+                }
+                _ => return Err(WebTransportError::Closed.into())
+            }
+        }
+
+        let mut i = 0;
+        while i < self.writes.len() {
+            let (id, finish) = (self.writes[i].0, self.writes[i].2);
+            let data = &mut self.writes[i].1;
+            match conn.write(id, data) {
+                Ok(n) => {
+                    data.drain(.. n);
+                    progress = true
+                }
+                Err(WriteError::Blocked) => {}
+                Err(e) => {
+                    debug!("Failed to write to stream {}: {}", id, e);
+                    self.writes.swap_remove(i);
+                    continue
+                }
+            }
+            if self.writes[i].1.is_empty() {
+                if finish {
+                    let _ = conn.finish(id);
+                }
+                self.writes.swap_remove(i);
+                continue
+            }
+            wakers.write.insert(id, cx.waker().clone());
+            i += 1
+        }
+
+        wakers.inbound = Some(cx.waker().clone());
+        Ok(progress)
+    }
+
+    /// Opens our control stream and, when dialing, sends the request.
+    fn start(&mut self, conn: &mut quinn_proto::Connection) -> Result<(), QuicError> {
+        self.started = true;
+        let control = conn.open(Dir::Uni)
+            .ok_or(WebTransportError::Protocol("no unidirectional stream allowed"))?;
+        self.writes.push((control, http3::control_stream(), false));
+
+        if let Role::Dialer { authority } = &self.role {
+            let request = conn.open(Dir::Bi)
+                .ok_or(WebTransportError::Protocol("no bidirectional stream allowed"))?;
+            let headers = http3::encode_headers(&[
+                (":method", "CONNECT"),
+                (":scheme", "https"),
+                (":authority", authority),
+                (":path", PATH),
+                (":protocol", "webtransport")
+            ]);
+            let mut frame = Vec::new();
+            http3::encode_frame(http3::FRAME_HEADERS, &headers, &mut frame);
+            self.writes.push((request, frame, false));
+            self.request = Some(request)
+        }
+        Ok(())
+    }
+
+    /// Handles a stream of the remote whose header has been received.
+    fn on_stream(&mut self, conn: &mut quinn_proto::Connection, id: StreamId, header: &[u8])
+        -> Result<(), QuicError>
+    {
+        let header = http3::decode_stream_header(id.dir() == Dir::Bi, header)
+            .expect("The header is complete");
+        match header {
+            StreamHeader::Uni(http3::STREAM_CONTROL)
+            | StreamHeader::Uni(http3::STREAM_QPACK_ENCODER)
+            | StreamHeader::Uni(http3::STREAM_QPACK_DECODER) => self.ignored.push(id),
+            StreamHeader::Uni(ty) => {
+                debug!("Ignoring unidirectional stream {} of type {:#x}", id, ty);
+                let _ = conn.stop_sending(id, http3::H3_STREAM_CREATION_ERROR.into());
+            }
+            StreamHeader::WebTransport(session) if self.established => self.on_session_stream(conn, id, session),
+            StreamHeader::WebTransport(session) => self.early.push((id, session)),
+            StreamHeader::Frame(http3::FRAME_HEADERS, block) if matches!(self.role, Role::Listener) =>
+                self.on_request(conn, id, block)?,
+            StreamHeader::Frame(ty, _) if http3::is_reserved_frame(ty) => self.headers.push((id, Vec::new())),
+            StreamHeader::Frame(ty, _) => {
+                debug!("Unexpected frame of type {:#x} on stream {}", ty, id);
+                reject(conn, id, http3::H3_FRAME_UNEXPECTED)
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a bidirectional stream of the session with the given ID.
+    fn on_session_stream(&mut self, conn: &mut quinn_proto::Connection, id: StreamId, session: u64) {
+        if self.id() == Some(session) {
+            self.inbound.push_back(id)
+        } else {
+            debug!("Rejecting stream {} of unknown session {}", id, session);
+            reject(conn, id, http3::H3_REQUEST_REJECTED)
+        }
+    }
+
+    /// Handles a request of the dialer, accepting the first valid
+    /// `CONNECT` request as the session.
+    fn on_request(&mut self, conn: &mut quinn_proto::Connection, id: StreamId, block: &[u8])
+        -> Result<(), QuicError>
+    {
+        if self.request.is_some() {
+            debug!("Rejecting request on stream {}: session already established", id);
+            reject(conn, id, http3::H3_REQUEST_REJECTED);
+            return Ok(())
+        }
+        let headers = http3::decode_headers(block).map_err(WebTransportError::Protocol)?;
+        let valid = header(&headers, ":method") == Some("CONNECT")
+            && header(&headers, ":protocol") == Some("webtransport")
+            && header(&headers, ":path") == Some(PATH);
+        let status = if valid { "200" } else { "404" };
+        let mut response = Vec::new();
+        http3::encode_frame(http3::FRAME_HEADERS, &http3::encode_headers(&[(":status", status)]), &mut response);
+        self.writes.push((id, response, !valid));
+        if valid {
+            self.request = Some(id);
+            self.establish(conn)
+        } else {
+            debug!("Rejecting request on stream {}: {:?}", id, headers);
+            let _ = conn.stop_sending(id, http3::H3_REQUEST_REJECTED.into());
+        }
+        Ok(())
+    }
+
+    /// Marks the session as established, accepting the streams of the
+    /// session received before.
+    fn establish(&mut self, conn: &mut quinn_proto::Connection) {
+        debug!("WebTransport session {:?} established", self.request);
+        self.established = true;
+        for (id, session) in std::mem::take(&mut self.early) {
+            self.on_session_stream(conn, id, session)
+        }
+    }
+}
+
+/// Reads the header of a stream into `buf` until `missing` no longer
+/// yields the number of bytes missing, never reading beyond the header.
+fn read_header(
+    conn: &mut quinn_proto::Connection,
+    id: StreamId,
+    buf: &mut Vec<u8>,
+    missing: impl Fn(&[u8]) -> Result<(), usize>
+) -> Result<Read, QuicError> {
+    loop {
+        let n = match missing(buf) {
+            Ok(()) => return Ok(Read::Complete),
+            Err(n) => n
+        };
+        let len = buf.len();
+        if len + n > MAX_HEADER_SIZE {
+            return Err(WebTransportError::Protocol("stream header too large").into())
+        }
+        buf.resize(len + n, 0);
+        match conn.read(id, &mut buf[len ..]) {
+            Ok(Some(n)) => buf.truncate(len + n),
+            Err(ReadError::Blocked) => {
+                buf.truncate(len);
+                return Ok(Read::Blocked)
+            }
+            Ok(None) | Err(_) => return Ok(Read::Ended)
+        }
+    }
+}
+
+/// Reads and discards the available data of a stream.
+fn discard(conn: &mut quinn_proto::Connection, id: StreamId, buf: &mut [u8]) -> Read {
+    loop {
+        match conn.read(id, buf) {
+            Ok(Some(_)) => {}
+            Err(ReadError::Blocked) => return Read::Blocked,
+            Ok(None) | Err(_) => return Read::Ended
+        }
+    }
+}
+
+/// Rejects a bidirectional stream of the remote.
+fn reject(conn: &mut quinn_proto::Connection, id: StreamId, code: u32) {
+    let _ = conn.stop_sending(id, code.into());
+    conn.reset(id, code.into())
+}
+
+/// The value of the header with the given name.
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+}
+
+fn alpn() -> Vec<Vec<u8>> {
+    ALPN.iter().map(|p| p.to_vec()).collect()
+}
+
+/// Generates a self-signed certificate for `SERVER_NAME`, valid from an hour
+/// ago to tolerate clock skew.
+fn generate_certificate() -> Result<(rustls::Certificate, rustls::PrivateKey), TlsError> {
+    let now = chrono::Utc::now();
+    let mut params = rcgen::CertificateParams::new(vec![SERVER_NAME.to_owned()]);
+    params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+    params.not_before = now - chrono::Duration::hours(1);
+    params.not_after = now + chrono::Duration::days(CERTIFICATE_VALIDITY_DAYS);
+    let certificate = rcgen::Certificate::from_params(params)?;
+    Ok((
+        rustls::Certificate(certificate.serialize_der()?),
+        rustls::PrivateKey(certificate.serialize_private_key_der())
+    ))
+}
+
+/// The SHA2-256 multihash of a certificate.
+fn certhash(certificate: &rustls::Certificate) -> Multihash {
+    multihash::encode(multihash::Hash::SHA2256, &certificate.0).expect("SHA2-256 is supported")
+}
+
+/// Verifies the certificate of the listener by the `/certhash` components
+/// of the dialed address.
+struct CerthashVerifier {
+    hashes: Vec<Multihash>,
+    /// The SHA2-256 hash of the accepted certificate.
+    accepted: Mutex<Option<Multihash>>
+}
+
+impl rustls::ServerCertVerifier for CerthashVerifier {
+    fn verify_server_cert(
+        &self,
+        _: &rustls::RootCertStore,
+        presented: &[rustls::Certificate],
+        _: webpki::DNSNameRef<'_>,
+        _: &[u8]
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        let cert = presented.first().ok_or(rustls::TLSError::NoCertificatesPresented)?;
+        let matches = self.hashes.iter().any(|hash| {
+            multihash::encode(hash.algorithm(), &cert.0).map_or(false, |h| &h == hash)
+        });
+        if !matches {
+            return Err(rustls::TLSError::General("certificate does not match any /certhash".into()))
+        }
+        *self.accepted.lock() = Some(certhash(cert));
+        Ok(rustls::ServerCertVerified::assertion())
+    }
+}
+
+/// Splits a WebTransport address into the address of the UDP socket and the
+/// values of its `/certhash` components.
+fn multiaddr_to_socketaddr(addr: &Multiaddr) -> Result<(SocketAddr, Vec<Multihash>), ()> {
+    let mut iter = addr.iter();
+    let ip = match iter.next() {
+        Some(Protocol::Ip4(ip)) => IpAddr::from(ip),
+        Some(Protocol::Ip6(ip)) => IpAddr::from(ip),
+        _ => return Err(())
+    };
+    let port = match (iter.next(), iter.next(), iter.next()) {
+        (Some(Protocol::Udp(port)), Some(Protocol::Quic), Some(Protocol::WebTransport)) => port,
+        _ => return Err(())
+    };
+    let certhashes = iter
+        .map(|proto| match proto {
+            Protocol::Certhash(hash) => Ok(hash),
+            _ => Err(())
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((SocketAddr::new(ip, port), certhashes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{certhash, generate_certificate, multiaddr_to_socketaddr};
+    use libp2p_core::multiaddr::{Multiaddr, Protocol};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    #[test]
+    fn multiaddr_to_udp_conversion() {
+        let hash = certhash(&generate_certificate().unwrap().0);
+
+        assert!(multiaddr_to_socketaddr(&"/ip4/127.0.0.1/udp/1234/quic".parse().unwrap()).is_err());
+        assert!(multiaddr_to_socketaddr(&"/ip4/127.0.0.1/tcp/1234/quic/webtransport".parse().unwrap()).is_err());
+        assert!(multiaddr_to_socketaddr(&"/ip4/127.0.0.1/udp/1234/quic/webtransport/ws".parse().unwrap()).is_err());
+
+        assert_eq!(
+            multiaddr_to_socketaddr(&"/ip4/127.0.0.1/udp/12345/quic/webtransport".parse().unwrap()),
+            Ok((SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 12345), Vec::new()))
+        );
+        let addr = "/ip6/::1/udp/12345/quic/webtransport".parse::<Multiaddr>().unwrap()
+            .with(Protocol::Certhash(hash.clone()));
+        assert_eq!(
+            multiaddr_to_socketaddr(&addr),
+            Ok((SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 12345), vec![hash]))
+        );
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::{future, prelude::*};
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::{self, StreamMuxer},
+    transport::{Transport, ListenerEvent}
+};
+use libp2p_quic::{QuicConfig, WebTransportConfig};
+use std::sync::Arc;
+
+#[test]
+fn quic_roundtrip() {
+    let _ = env_logger::try_init();
+
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_secp256k1();
+    let server_peer = server_id.public().into_peer_id();
+    let client_peer = client_id.public().into_peer_id();
+
+    let server_transport = QuicConfig::new(&server_id).unwrap();
+    let client_transport = QuicConfig::new(&client_id).unwrap();
+
+    async_std::task::block_on(async move {
+        let request = b"Hello, QUIC!".to_vec();
+        let response = b"Hello, client!".to_vec();
+
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/udp/0/quic".parse().unwrap())
+            .unwrap();
+
+        let server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let client_fut = async {
+            let (peer, muxer) = client_transport.dial(server_address.clone())
+                .unwrap()
+                .await
+                .expect("no error");
+            assert_eq!(peer, server_peer);
+
+            let mut substream = muxing::outbound_from_ref_and_wrap(Arc::new(muxer))
+                .await
+                .expect("no error");
+            substream.write_all(&request).await.expect("no error");
+            substream.close().await.expect("no error");
+
+            let mut buffer = vec![];
+            substream.read_to_end(&mut buffer).await.expect("no error");
+            assert_eq!(buffer, response);
+        };
+
+        let server_fut = async {
+            let (peer, muxer) = server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade")
+                .await
+                .expect("no error");
+            assert_eq!(peer, client_peer);
+
+            let muxer = Arc::new(muxer);
+            let mut substream = muxing::inbound_from_ref_and_wrap(muxer.clone())
+                .await
+                .expect("no error");
+            let mut buffer = vec![];
+            substream.read_to_end(&mut buffer).await.expect("no error");
+            assert_eq!(buffer, request);
+
+            substream.write_all(&response).await.expect("no error");
+            substream.close().await.expect("no error");
+            // Keeps the connection open until the client has read the response
+            // and closed the connection.
+            let closed = future::poll_fn(|cx| muxer.poll_inbound(cx)).await;
+            assert!(closed.is_err());
+        };
+
+        future::join(server_fut, client_fut).await;
+    })
+}
+
+#[test]
+fn webtransport_roundtrip() {
+    let _ = env_logger::try_init();
+
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+    let server_peer = server_id.public().into_peer_id();
+    let client_peer = client_id.public().into_peer_id();
+
+    let server_transport = WebTransportConfig::new(&server_id).unwrap();
+    let client_transport = WebTransportConfig::new(&client_id).unwrap();
+    let certhash = server_transport.certhash().clone();
+
+    async_std::task::block_on(async move {
+        let request = b"Hello, WebTransport!".to_vec();
+        let response = b"Hello, client!".to_vec();
+
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/udp/0/quic/webtransport".parse().unwrap())
+            .unwrap();
+
+        let server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+        assert_eq!(server_address.iter().last(), Some(Protocol::Certhash(certhash)));
+
+        let client_fut = async {
+            let (peer, muxer) = client_transport.dial(server_address.clone())
+                .unwrap()
+                .await
+                .expect("no error");
+            assert_eq!(peer, server_peer);
+
+            let mut substream = muxing::outbound_from_ref_and_wrap(Arc::new(muxer))
+                .await
+                .expect("no error");
+            substream.write_all(&request).await.expect("no error");
+            substream.close().await.expect("no error");
+
+            let mut buffer = vec![];
+            substream.read_to_end(&mut buffer).await.expect("no error");
+            assert_eq!(buffer, response);
+        };
+
+        let server_fut = async {
+            let (peer, muxer) = server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade")
+                .await
+                .expect("no error");
+            assert_eq!(peer, client_peer);
+
+            let muxer = Arc::new(muxer);
+            let mut substream = muxing::inbound_from_ref_and_wrap(muxer.clone())
+                .await
+                .expect("no error");
+            let mut buffer = vec![];
+            substream.read_to_end(&mut buffer).await.expect("no error");
+            assert_eq!(buffer, request);
+
+            substream.write_all(&response).await.expect("no error");
+            substream.close().await.expect("no error");
+            let closed = future::poll_fn(|cx| muxer.poll_inbound(cx)).await;
+            assert!(closed.is_err());
+        };
+
+        future::join(server_fut, client_fut).await;
+    })
+}
+
+#[test]
+fn webtransport_certhash_mismatch() {
+    let _ = env_logger::try_init();
+
+    let server_transport = WebTransportConfig::new(&identity::Keypair::generate_ed25519()).unwrap();
+    let client_transport = WebTransportConfig::new(&identity::Keypair::generate_ed25519()).unwrap();
+    // The hash of another certificate.
+    let certhash = client_transport.certhash().clone();
+
+    async_std::task::block_on(async move {
+        let mut server = server_transport
+            .listen_on("/ip4/127.0.0.1/udp/0/quic/webtransport".parse().unwrap())
+            .unwrap();
+
+        let mut server_address = server.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+        server_address.pop();
+        let server_address: Multiaddr = server_address.with(Protocol::Certhash(certhash));
+
+        let server_fut = async {
+            let upgrade = server.try_next()
+                .await
+                .expect("some event")
+                .map(ListenerEvent::into_upgrade)
+                .expect("no error")
+                .map(|client| client.0)
+                .expect("listener upgrade");
+            assert!(upgrade.await.is_err());
+        };
+
+        let client_fut = async {
+            assert!(client_transport.dial(server_address).unwrap().await.is_err());
+        };
+
+        future::join(server_fut, client_fut).await;
+    })
+}
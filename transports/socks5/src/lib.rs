@@ -0,0 +1,403 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! # libp2p-socks5
+//!
+//! This crate provides the type `Socks5Config`, which dials TCP addresses through a SOCKS5
+//! proxy, e.g. the SOCKS port of a Tor client.
+//!
+//! ## Usage
+//!
+//! Create a `Socks5Config` with the address of the proxy and pass it the transport used to
+//! reach the proxy, usually a `TcpConfig`:
+//!
+//! ```
+//! use libp2p_core::Transport;
+//! use libp2p_socks5::Socks5Config;
+//! use libp2p_tcp::TcpConfig;
+//!
+//! let transport = Socks5Config::new(TcpConfig::new(), "/ip4/127.0.0.1/tcp/9050".parse().unwrap())
+//!     .credentials("isolation", "stream-1");
+//! let _dial = transport.dial("/dns/example.com/tcp/443".parse().unwrap());
+//! ```
+//!
+//! The supported addresses are `/ip4`, `/ip6`, `/dns`, `/dns4` and `/dns6` followed by `/tcp`,
+//! and `/onion3`. Names are never resolved locally: the `/dns` components and the hidden
+//! services of `/onion3` components are passed to the proxy as such. In order not to leak
+//! names through DNS queries, a `Socks5Config` must not be wrapped in a `DnsConfig`; other
+//! layers, such as websockets, can be stacked on top of it as usual.
+//!
+//! Listening is delegated to the underlying transport as is.
+
+mod protocol;
+
+pub use protocol::ReplyError;
+
+use data_encoding::BASE32;
+use futures::{prelude::*, future::BoxFuture};
+use libp2p_core::{
+    Transport,
+    multiaddr::{Protocol, Multiaddr},
+    transport::{TransportError, ListenerEvent, TransportEvent, TransportEventKind, TransportEvents}
+};
+use log::debug;
+use protocol::Target;
+use std::{error, fmt, io};
+
+/// A transport dialing TCP addresses through a SOCKS5 proxy.
+///
+/// Any call to `dial` connects to the proxy through the underlying transport and asks the
+/// proxy to connect to the address.
+#[derive(Clone)]
+pub struct Socks5Config<T> {
+    /// Underlying transport to use to reach the proxy.
+    inner: T,
+    /// Address of the proxy.
+    proxy: Multiaddr,
+    /// Credentials to authenticate with, if any.
+    credentials: Option<Credentials>,
+    /// The bus to publish proxy failures to, if any.
+    events: Option<TransportEvents>,
+}
+
+/// Username and password authentication.
+#[derive(Clone)]
+pub(crate) struct Credentials {
+    pub(crate) username: String,
+    pub(crate) password: String,
+}
+
+impl<T> Socks5Config<T> {
+    /// Creates a new configuration object for SOCKS5, dialing the proxy at `proxy` with
+    /// `inner`.
+    pub fn new(inner: T, proxy: Multiaddr) -> Self {
+        Socks5Config {
+            inner,
+            proxy,
+            credentials: None,
+            events: None,
+        }
+    }
+
+    /// Authenticates with the given username and password if the proxy requires it.
+    ///
+    /// Tor also uses the credentials to isolate streams: connections with different
+    /// credentials are not relayed over the same circuit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the username or the password is empty or longer than 255 bytes.
+    pub fn credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        let username = username.into();
+        let password = password.into();
+        assert!((1 ..= 255).contains(&username.len()), "username must be 1 to 255 bytes long");
+        assert!((1 ..= 255).contains(&password.len()), "password must be 1 to 255 bytes long");
+        self.credentials = Some(Credentials { username, password });
+        self
+    }
+
+    /// Publishes the failures of the proxy to reach it or to connect to the dialed
+    /// address to the given event bus.
+    pub fn events(mut self, events: TransportEvents) -> Self {
+        self.events = Some(events);
+        self
+    }
+}
+
+impl<T> fmt::Debug for Socks5Config<T>
+where
+    T: fmt::Debug
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Socks5Config")
+            .field("inner", &self.inner)
+            .field("proxy", &self.proxy)
+            .field("username", &self.credentials.as_ref().map(|c| &c.username))
+            .finish()
+    }
+}
+
+impl<T> Transport for Socks5Config<T>
+where
+    T: Transport + Send + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send,
+    T::Error: Send,
+    T::Dial: Send
+{
+    type Output = T::Output;
+    type Error = Socks5Error<T::Error>;
+    type Listener = stream::MapErr<
+        stream::MapOk<T::Listener,
+            fn(ListenerEvent<T::ListenerUpgrade>) -> ListenerEvent<Self::ListenerUpgrade>>,
+        fn(T::Error) -> Self::Error>;
+    type ListenerUpgrade = future::MapErr<T::ListenerUpgrade, fn(T::Error) -> Self::Error>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let listener = self.inner.listen_on(addr).map_err(|err| err.map(Socks5Error::Underlying))?;
+        let listener = listener
+            .map_ok::<_, fn(_) -> _>(|event| event.map(|upgr| {
+                upgr.map_err::<_, fn(_) -> _>(Socks5Error::Underlying)
+            }))
+            .map_err::<_, fn(_) -> _>(Socks5Error::Underlying);
+        Ok(listener)
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let target = match multiaddr_to_target(&addr) {
+            Some(target) => target,
+            None => return Err(TransportError::MultiaddrNotSupported(addr))
+        };
+
+        debug!("Dialing {} through the proxy at {}", addr, self.proxy);
+        let dial = match self.inner.dial(self.proxy.clone()) {
+            Ok(dial) => dial,
+            Err(TransportError::MultiaddrNotSupported(proxy)) =>
+                return Err(TransportError::Other(Socks5Error::ProxyNotSupported(proxy))),
+            Err(TransportError::Other(err)) =>
+                return Err(TransportError::Other(Socks5Error::Underlying(err)))
+        };
+        let credentials = self.credentials;
+        let events = self.events;
+
+        Ok(async move {
+            let result: Result<_, Socks5Error<T::Error>> = async {
+                let mut socket = dial.await.map_err(Socks5Error::Underlying)?;
+                protocol::connect(&mut socket, credentials.as_ref(), &target).await?;
+                Ok(socket)
+            }.await;
+            if let (Err(err), Some(events)) = (&result, events) {
+                events.publish(TransportEvent {
+                    transport: "socks5",
+                    address: addr,
+                    kind: TransportEventKind::ProxyFailed { error: err.to_string() }
+                })
+            }
+            result
+        }.boxed())
+    }
+}
+
+/// Returns the destination to request from the proxy for `addr`, if supported.
+fn multiaddr_to_target(addr: &Multiaddr) -> Option<Target> {
+    let mut iter = addr.iter();
+    let target = match (iter.next()?, iter.next()) {
+        (Protocol::Ip4(ip), Some(Protocol::Tcp(port))) => Target::Ip(ip.into(), port),
+        (Protocol::Ip6(ip), Some(Protocol::Tcp(port))) => Target::Ip(ip.into(), port),
+        (Protocol::Dns(name), Some(Protocol::Tcp(port)))
+        | (Protocol::Dns4(name), Some(Protocol::Tcp(port)))
+        | (Protocol::Dns6(name), Some(Protocol::Tcp(port))) => Target::Domain(name.into_owned(), port),
+        (Protocol::Onion3(key, port), None) => {
+            let name = format!("{}.onion", BASE32.encode(key.as_ref()).to_lowercase());
+            return Some(Target::Domain(name, port))
+        }
+        _ => return None
+    };
+    if iter.next().is_some() {
+        return None
+    }
+    Some(target)
+}
+
+/// Error that can be generated by the SOCKS5 layer.
+#[derive(Debug)]
+pub enum Socks5Error<TErr> {
+    /// Error in the underlying transport layer.
+    Underlying(TErr),
+    /// The underlying transport doesn't support the address of the proxy.
+    ProxyNotSupported(Multiaddr),
+    /// I/O error on the connection to the proxy.
+    Io(io::Error),
+    /// The proxy requires an authentication method we don't support or, if no credentials
+    /// are configured, an authentication.
+    NoAcceptableMethod,
+    /// The proxy rejected the credentials.
+    AuthenticationFailed,
+    /// The proxy failed to connect to the destination.
+    Reply(ReplyError),
+    /// The proxy violated the protocol.
+    Protocol(&'static str),
+}
+
+impl<TErr> From<io::Error> for Socks5Error<TErr> {
+    fn from(err: io::Error) -> Self {
+        Socks5Error::Io(err)
+    }
+}
+
+impl<TErr> fmt::Display for Socks5Error<TErr>
+where TErr: fmt::Display
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Socks5Error::Underlying(err) => write!(f, "{}", err),
+            Socks5Error::ProxyNotSupported(addr) => write!(f, "Proxy address not supported: {}", addr),
+            Socks5Error::Io(err) => write!(f, "I/O error with the proxy: {}", err),
+            Socks5Error::NoAcceptableMethod => write!(f, "No acceptable authentication method"),
+            Socks5Error::AuthenticationFailed => write!(f, "Authentication with the proxy failed"),
+            Socks5Error::Reply(err) => write!(f, "Proxy failed to connect: {:?}", err),
+            Socks5Error::Protocol(msg) => write!(f, "SOCKS5 protocol violation: {}", msg),
+        }
+    }
+}
+
+impl<TErr> error::Error for Socks5Error<TErr>
+where TErr: error::Error + 'static
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Socks5Error::Underlying(err) => Some(err),
+            Socks5Error::Io(err) => Some(err),
+            Socks5Error::ProxyNotSupported(_)
+            | Socks5Error::NoAcceptableMethod
+            | Socks5Error::AuthenticationFailed
+            | Socks5Error::Reply(_)
+            | Socks5Error::Protocol(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{multiaddr_to_target, ReplyError, Socks5Config, Socks5Error, Target};
+    use async_std::net::TcpListener;
+    use futures::prelude::*;
+    use libp2p_core::{Transport, multiaddr::Multiaddr, transport::{TransportError, TransportEvent, TransportEventKind, TransportEvents}};
+    use libp2p_tcp::TcpConfig;
+
+    const ONION3: &str = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd";
+
+    #[test]
+    fn multiaddr_to_target_conversion() {
+        let target = |s: &str| multiaddr_to_target(&s.parse::<Multiaddr>().unwrap());
+
+        assert_eq!(target("/ip4/1.2.3.4/tcp/80"), Some(Target::Ip([1, 2, 3, 4].into(), 80)));
+        assert_eq!(target("/ip6/::1/tcp/80"), Some(Target::Ip("::1".parse().unwrap(), 80)));
+        assert_eq!(target("/dns4/example.com/tcp/443"), Some(Target::Domain("example.com".into(), 443)));
+        assert_eq!(
+            target(&format!("/onion3/{}:1234", ONION3)),
+            Some(Target::Domain(format!("{}.onion", ONION3), 1234))
+        );
+        assert_eq!(target("/ip4/1.2.3.4/udp/80"), None);
+        assert_eq!(target("/ip4/1.2.3.4/tcp/80/ws"), None);
+        assert_eq!(target(&format!("/onion3/{}:1234/tcp/80", ONION3)), None);
+        assert_eq!(target("/dnsaddr/example.com"), None);
+    }
+
+    /// Runs a proxy accepting one connection and expecting a request for `domain:port`,
+    /// which it answers with `reply`. On success, sends `b"hello"` over the connection.
+    async fn proxy(listener: TcpListener, credentials: Option<(&str, &str)>, domain: String, port: u16, reply: u8) {
+        let (mut socket, _) = listener.accept().await.unwrap();
+
+        let mut header = [0; 2];
+        socket.read_exact(&mut header).await.unwrap();
+        let mut methods = vec![0; header[1] as usize];
+        socket.read_exact(&mut methods).await.unwrap();
+        match credentials {
+            Some((username, password)) => {
+                assert!(methods.contains(&2));
+                socket.write_all(&[5, 2]).await.unwrap();
+                let mut auth = vec![0; 3 + username.len() + password.len()];
+                socket.read_exact(&mut auth).await.unwrap();
+                assert_eq!(&auth[2 .. 2 + username.len()], username.as_bytes());
+                assert_eq!(&auth[3 + username.len() ..], password.as_bytes());
+                socket.write_all(&[1, 0]).await.unwrap();
+            }
+            None => socket.write_all(&[5, 0]).await.unwrap()
+        }
+
+        let mut request = [0; 5];
+        socket.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request[.. 4], &[5, 1, 0, 3]);
+        let mut name = vec![0; request[4] as usize + 2];
+        socket.read_exact(&mut name).await.unwrap();
+        assert_eq!(&name[.. name.len() - 2], domain.as_bytes());
+        assert_eq!(&name[name.len() - 2 ..], &port.to_be_bytes());
+
+        socket.write_all(&[5, reply, 0, 1, 127, 0, 0, 1, 0, 80]).await.unwrap();
+        if reply == 0 {
+            socket.write_all(b"hello").await.unwrap();
+        }
+    }
+
+    #[test]
+    fn dial_domain_through_proxy() {
+        async_std::task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let proxy_addr: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", listener.local_addr().unwrap().port())
+                .parse().unwrap();
+            let server = async_std::task::spawn(
+                proxy(listener, Some(("user", "secret")), "example.com".into(), 443, 0)
+            );
+
+            let transport = Socks5Config::new(TcpConfig::new(), proxy_addr).credentials("user", "secret");
+            let mut socket = transport.dial("/dns/example.com/tcp/443".parse().unwrap()).unwrap().await.unwrap();
+            let mut buf = [0; 5];
+            socket.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+            server.await;
+        })
+    }
+
+    #[test]
+    fn dial_onion3_failure() {
+        async_std::task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let proxy_addr: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", listener.local_addr().unwrap().port())
+                .parse().unwrap();
+            let server = async_std::task::spawn(
+                proxy(listener, None, format!("{}.onion", ONION3), 1234, 4)
+            );
+
+            let events = TransportEvents::new();
+            let mut subscriber = events.subscribe();
+            let transport = Socks5Config::new(TcpConfig::new(), proxy_addr).events(events);
+            let addr: Multiaddr = format!("/onion3/{}:1234", ONION3).parse().unwrap();
+            let result = transport.dial(addr.clone()).unwrap().await;
+            match result {
+                Err(Socks5Error::Reply(ReplyError::HostUnreachable)) => {}
+                Err(err) => panic!("Unexpected error: {:?}", err),
+                Ok(_) => panic!("Unexpected success"),
+            }
+            server.await;
+
+            match subscriber.next().await {
+                Some(TransportEvent { transport: "socks5", address, kind: TransportEventKind::ProxyFailed { .. } }) =>
+                    assert_eq!(address, addr),
+                e => panic!("Unexpected event: {:?}", e),
+            }
+        })
+    }
+
+    #[test]
+    fn unsupported_addresses() {
+        let transport = Socks5Config::new(TcpConfig::new(), "/ip4/127.0.0.1/tcp/9050".parse().unwrap());
+        match transport.clone().dial("/ip4/1.2.3.4/udp/80".parse().unwrap()) {
+            Err(TransportError::MultiaddrNotSupported(_)) => {}
+            _ => panic!("Expected MultiaddrNotSupported"),
+        }
+
+        let transport = Socks5Config::new(TcpConfig::new(), "/memory/5".parse().unwrap());
+        match transport.dial("/ip4/1.2.3.4/tcp/80".parse().unwrap()) {
+            Err(TransportError::Other(Socks5Error::ProxyNotSupported(_))) => {}
+            _ => panic!("Expected ProxyNotSupported"),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The client side of the SOCKS protocol version 5, as specified by
+//! [RFC 1928], with the username/password authentication of [RFC 1929].
+//!
+//! [RFC 1928]: https://tools.ietf.org/html/rfc1928
+//! [RFC 1929]: https://tools.ietf.org/html/rfc1929
+
+use crate::{Credentials, Socks5Error};
+use futures::prelude::*;
+use std::net::IpAddr;
+
+const VERSION: u8 = 5;
+const AUTH_VERSION: u8 = 1;
+
+const METHOD_NO_AUTH: u8 = 0;
+const METHOD_USERNAME_PASSWORD: u8 = 2;
+const METHOD_NOT_ACCEPTABLE: u8 = 0xff;
+
+const CMD_CONNECT: u8 = 1;
+
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+/// The destination of a connection through the proxy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Target {
+    Ip(IpAddr, u16),
+    /// A name resolved by the proxy.
+    Domain(String, u16),
+}
+
+/// The reply code of a failed `CONNECT` request.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReplyError {
+    /// General SOCKS server failure.
+    GeneralFailure,
+    /// The connection is not allowed by the rules of the proxy.
+    NotAllowed,
+    /// The network of the destination is unreachable.
+    NetworkUnreachable,
+    /// The destination is unreachable.
+    HostUnreachable,
+    /// The destination refused the connection.
+    ConnectionRefused,
+    /// The TTL expired, e.g. a Tor circuit could not be built in time.
+    TtlExpired,
+    /// The proxy does not support the `CONNECT` command.
+    CommandNotSupported,
+    /// The proxy does not support the type of address of the destination.
+    AddressTypeNotSupported,
+    /// A reply code not defined by RFC 1928.
+    Other(u8),
+}
+
+impl ReplyError {
+    fn from_code(code: u8) -> Self {
+        match code {
+            1 => ReplyError::GeneralFailure,
+            2 => ReplyError::NotAllowed,
+            3 => ReplyError::NetworkUnreachable,
+            4 => ReplyError::HostUnreachable,
+            5 => ReplyError::ConnectionRefused,
+            6 => ReplyError::TtlExpired,
+            7 => ReplyError::CommandNotSupported,
+            8 => ReplyError::AddressTypeNotSupported,
+            code => ReplyError::Other(code),
+        }
+    }
+}
+
+/// Performs the handshake on a connection to the proxy, asking it to connect
+/// to `target`.
+///
+/// Once the handshake succeeded, the connection is relayed to the target.
+pub(crate) async fn connect<S, E>(
+    socket: &mut S,
+    credentials: Option<&Credentials>,
+    target: &Target
+) -> Result<(), Socks5Error<E>>
+where
+    S: AsyncRead + AsyncWrite + Unpin
+{
+    // Method negotiation.
+    let methods: &[u8] = match credentials {
+        Some(_) => &[METHOD_NO_AUTH, METHOD_USERNAME_PASSWORD],
+        None => &[METHOD_NO_AUTH],
+    };
+    let mut msg = vec![VERSION, methods.len() as u8];
+    msg.extend_from_slice(methods);
+    socket.write_all(&msg).await?;
+    socket.flush().await?;
+
+    let mut reply = [0; 2];
+    socket.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(Socks5Error::Protocol("unexpected version in method selection"))
+    }
+    match (reply[1], credentials) {
+        (METHOD_NO_AUTH, _) => {}
+        (METHOD_USERNAME_PASSWORD, Some(credentials)) => authenticate(socket, credentials).await?,
+        (METHOD_NOT_ACCEPTABLE, _) => return Err(Socks5Error::NoAcceptableMethod),
+        _ => return Err(Socks5Error::Protocol("proxy selected a method that was not offered")),
+    }
+
+    // Connection request.
+    let mut msg = vec![VERSION, CMD_CONNECT, 0];
+    let port = match target {
+        Target::Ip(IpAddr::V4(ip), port) => {
+            msg.push(ATYP_IPV4);
+            msg.extend_from_slice(&ip.octets());
+            port
+        }
+        Target::Ip(IpAddr::V6(ip), port) => {
+            msg.push(ATYP_IPV6);
+            msg.extend_from_slice(&ip.octets());
+            port
+        }
+        Target::Domain(name, port) => {
+            if name.len() > 255 {
+                return Err(Socks5Error::Protocol("domain name longer than 255 bytes"))
+            }
+            msg.push(ATYP_DOMAIN);
+            msg.push(name.len() as u8);
+            msg.extend_from_slice(name.as_bytes());
+            port
+        }
+    };
+    msg.extend_from_slice(&port.to_be_bytes());
+    socket.write_all(&msg).await?;
+    socket.flush().await?;
+
+    // The reply ends with the address bound by the proxy, which we ignore.
+    let mut reply = [0; 4];
+    socket.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(Socks5Error::Protocol("unexpected version in reply"))
+    }
+    if reply[1] != 0 {
+        return Err(Socks5Error::Reply(ReplyError::from_code(reply[1])))
+    }
+    let len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0; 1];
+            socket.read_exact(&mut len).await?;
+            usize::from(len[0])
+        }
+        _ => return Err(Socks5Error::Protocol("unknown address type in reply")),
+    };
+    let mut bound = vec![0; len + 2];
+    socket.read_exact(&mut bound).await?;
+
+    Ok(())
+}
+
+/// Performs the username/password authentication.
+async fn authenticate<S, E>(socket: &mut S, credentials: &Credentials) -> Result<(), Socks5Error<E>>
+where
+    S: AsyncRead + AsyncWrite + Unpin
+{
+    let mut msg = vec![AUTH_VERSION, credentials.username.len() as u8];
+    msg.extend_from_slice(credentials.username.as_bytes());
+    msg.push(credentials.password.len() as u8);
+    msg.extend_from_slice(credentials.password.as_bytes());
+    socket.write_all(&msg).await?;
+    socket.flush().await?;
+
+    let mut reply = [0; 2];
+    socket.read_exact(&mut reply).await?;
+    if reply[0] != AUTH_VERSION {
+        return Err(Socks5Error::Protocol("unexpected version in authentication reply"))
+    }
+    if reply[1] != 0 {
+        return Err(Socks5Error::AuthenticationFailed)
+    }
+    Ok(())
+}
@@ -0,0 +1,221 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Listening on the addresses of a network interface given by name.
+
+use crate::{apply_config, bind_reuse, ip_to_multiaddr, Buffer, TcpConfig, TcpTransStream, IF_POLL_INTERVAL};
+use async_std::net::{TcpListener, TcpStream};
+use futures::{future, prelude::*, stream::{AbortHandle, BoxStream, SelectAll}};
+use futures_timer::Delay;
+use get_if_addrs::get_if_addrs;
+use libp2p_core::transport::ListenerEvent;
+use log::{debug, trace};
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll}
+};
+
+/// Stream that listens on all addresses of a network interface of one IP
+/// version, binding and closing a listener whenever an address appears or
+/// disappears.
+pub(crate) struct InterfaceListenStream {
+    /// The name of the interface.
+    interface: String,
+    /// Whether to listen on the IPv4 or the IPv6 addresses of the interface.
+    ipv4: bool,
+    /// The port of the listeners, 0 until the first listener is bound if
+    /// no port has been requested.
+    port: u16,
+    /// The listeners per IP address.
+    listeners: HashMap<IpAddr, AbortHandle>,
+    /// The incoming connections of all listeners.
+    incoming: SelectAll<BoxStream<'static, (SocketAddr, io::Result<TcpStream>)>>,
+    /// The next check for address changes.
+    poll: Delay,
+    /// Temporary buffer of listener events.
+    pending: Buffer,
+    /// Original configuration.
+    config: TcpConfig
+}
+
+impl InterfaceListenStream {
+    /// Listens on the addresses of `interface` of the same IP version as
+    /// `listen_addr`, using its port.
+    pub(crate) fn new(config: TcpConfig, interface: String, listen_addr: SocketAddr) -> io::Result<Self> {
+        let mut stream = InterfaceListenStream {
+            interface,
+            ipv4: listen_addr.is_ipv4(),
+            port: listen_addr.port(),
+            listeners: HashMap::new(),
+            incoming: SelectAll::new(),
+            poll: Delay::new(IF_POLL_INTERVAL),
+            pending: Buffer::new(),
+            config
+        };
+        stream.update()?;
+        if stream.listeners.is_empty() {
+            debug!("No address to listen on for interface {}", stream.interface)
+        }
+        Ok(stream)
+    }
+
+    /// Binds listeners to new addresses of the interface and closes the
+    /// listeners of addresses that disappeared.
+    fn update(&mut self) -> io::Result<()> {
+        let addrs = get_if_addrs()?
+            .into_iter()
+            .filter(|iface| iface.name == self.interface)
+            .map(|iface| iface.ip())
+            .filter(|ip| ip.is_ipv4() == self.ipv4)
+            // Link-local IPv6 addresses cannot be bound to without a scope ID.
+            .filter(|ip| match ip {
+                IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 != 0xfe80,
+                IpAddr::V4(_) => true
+            })
+            .collect::<Vec<_>>();
+
+        let expired = self.listeners.keys()
+            .filter(|ip| !addrs.contains(ip))
+            .cloned()
+            .collect::<Vec<_>>();
+        for ip in expired {
+            if let Some(handle) = self.listeners.remove(&ip) {
+                handle.abort()
+            }
+            let addr = SocketAddr::new(ip, self.port);
+            if let Some(port_reuse) = &self.config.port_reuse {
+                port_reuse.unregister(addr)
+            }
+            let ma = ip_to_multiaddr(ip, self.port);
+            debug!("Expired listen address: {}", ma);
+            self.pending.push_back(Ok(ListenerEvent::AddressExpired(ma)))
+        }
+
+        for ip in addrs {
+            if self.listeners.contains_key(&ip) {
+                continue
+            }
+            // Addresses may not be usable yet, e.g. during IPv6 duplicate
+            // address detection, so we retry on the next update.
+            let listener = match self.bind(SocketAddr::new(ip, self.port)) {
+                Ok(listener) => listener,
+                Err(err) => {
+                    debug!("Failed to listen on {} of interface {}: {}", ip, self.interface, err);
+                    continue
+                }
+            };
+            let local_addr = listener.local_addr()?;
+            self.port = local_addr.port();
+            if let Some(port_reuse) = &self.config.port_reuse {
+                port_reuse.register(local_addr)
+            }
+
+            let accept = stream::unfold(Arc::new(listener), move |listener| async move {
+                let result = listener.accept().await.map(|(sock, _)| sock);
+                Some(((local_addr, result), listener))
+            });
+            let (accept, handle) = stream::abortable(accept);
+            self.incoming.push(accept.boxed());
+            self.listeners.insert(ip, handle);
+
+            let ma = ip_to_multiaddr(ip, self.port);
+            debug!("New listen address: {}", ma);
+            self.pending.push_back(Ok(ListenerEvent::NewAddress(ma)))
+        }
+
+        Ok(())
+    }
+
+    fn bind(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        match &self.config.port_reuse {
+            Some(_) => bind_reuse(addr),
+            None => std::net::TcpListener::bind(addr).map(TcpListener::from)
+        }
+    }
+}
+
+impl Stream for InterfaceListenStream {
+    type Item = <Buffer as IntoIterator>::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(event) = this.pending.pop_front() {
+                return Poll::Ready(Some(event))
+            }
+
+            if this.poll.poll_unpin(cx).is_ready() {
+                this.poll = Delay::new(IF_POLL_INTERVAL);
+                if let Err(err) = this.update() {
+                    debug!("Failed to check the addresses of interface {}: {}", this.interface, err);
+                    return Poll::Ready(Some(Err(err)))
+                }
+                continue
+            }
+
+            let (local_addr, sock) = match this.incoming.poll_next_unpin(cx) {
+                Poll::Ready(Some(incoming)) => incoming,
+                // Without listeners, we wait for the next update.
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending
+            };
+            let sock = match sock {
+                Ok(sock) => sock,
+                Err(err) => {
+                    debug!("error accepting incoming connection: {}", err);
+                    return Poll::Ready(Some(Err(err)))
+                }
+            };
+            let remote_addr = match sock.peer_addr() {
+                Ok(addr) => ip_to_multiaddr(addr.ip(), addr.port()),
+                Err(err) => {
+                    debug!("Failed to get peer address: {:?}", err);
+                    continue
+                }
+            };
+            let local_addr = ip_to_multiaddr(local_addr.ip(), local_addr.port());
+
+            let upgrade = match apply_config(&this.config, &sock) {
+                Ok(()) => {
+                    trace!("Incoming connection from {} at {}", remote_addr, local_addr);
+                    future::ok(TcpTransStream { inner: sock })
+                }
+                Err(err) => {
+                    debug!("Error upgrading incoming connection from {}: {:?}", remote_addr, err);
+                    future::err(err)
+                }
+            };
+            return Poll::Ready(Some(Ok(ListenerEvent::Upgrade { upgrade, local_addr, remote_addr })))
+        }
+    }
+}
+
+impl Drop for InterfaceListenStream {
+    fn drop(&mut self) {
+        if let Some(port_reuse) = &self.config.port_reuse {
+            for ip in self.listeners.keys() {
+                port_reuse.unregister(SocketAddr::new(*ip, self.port))
+            }
+        }
+    }
+}
@@ -36,10 +36,16 @@
 //! The `TcpConfig` structs implements the `Transport` trait of the `swarm` library. See the
 //! documentation of `swarm` and of libp2p in general to learn how to use the `Transport` trait.
 
+mod interface;
+mod proxy;
+
+pub use proxy::HttpProxy;
+
 use async_std::net::TcpStream;
 use futures::{future::{self, Ready}, prelude::*};
 use futures_timer::Delay;
 use get_if_addrs::{IfAddr, get_if_addrs};
+use interface::InterfaceListenStream;
 use ipnet::{IpNet, Ipv4Net, Ipv6Net};
 use libp2p_core::{
     Transport,
@@ -48,14 +54,18 @@ use libp2p_core::{
 };
 use log::{debug, trace};
 use std::{
-    collections::VecDeque,
+    collections::{HashSet, VecDeque},
     io,
     iter::{self, FromIterator},
     net::{IpAddr, SocketAddr},
     pin::Pin,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
     time::Duration
 };
+/// How often the host addresses are checked for changes when listening on
+/// all interfaces or on a named interface.
+const IF_POLL_INTERVAL: Duration = Duration::from_secs(10);
 
 /// Represents the configuration for a TCP/IP transport capability for libp2p.
 ///
@@ -69,6 +79,53 @@ pub struct TcpConfig {
     ttl: Option<u32>,
     /// `TCP_NODELAY` to set for opened sockets, or `None` to keep default.
     nodelay: Option<bool>,
+    /// Keep-alive interval to set for opened sockets, or `None` to keep default.
+    keepalive: Option<Option<Duration>>,
+    /// `SO_SNDBUF` to set for opened sockets, or `None` to keep default.
+    send_buffer_size: Option<usize>,
+    /// `SO_RCVBUF` to set for opened sockets, or `None` to keep default.
+    recv_buffer_size: Option<usize>,
+    /// `IP_TOS` or `IPV6_TCLASS` to set for opened sockets, or `None` to keep default.
+    traffic_class: Option<u32>,
+    /// The listen addresses to bind dialed sockets to, if port reuse is enabled.
+    port_reuse: Option<PortReuse>,
+    /// The network interface whose addresses to listen on instead of a wildcard address.
+    interface: Option<String>,
+    /// The HTTP proxy to tunnel dials through, if any.
+    http_proxy: Option<HttpProxy>,
+}
+
+/// The listen addresses of the listeners of a [`TcpConfig`] and its clones
+/// with port reuse enabled, which serve as the local addresses of dials.
+#[derive(Debug, Clone, Default)]
+struct PortReuse {
+    listen_addrs: Arc<Mutex<HashSet<SocketAddr>>>
+}
+
+impl PortReuse {
+    /// Registers a listen address for use by dials.
+    fn register(&self, addr: SocketAddr) {
+        trace!("Registering for port reuse: {}", addr);
+        self.listen_addrs.lock().expect("lock is not poisoned").insert(addr);
+    }
+
+    /// Unregisters a listen address once the listener is closed.
+    fn unregister(&self, addr: SocketAddr) {
+        trace!("Unregistering for port reuse: {}", addr);
+        self.listen_addrs.lock().expect("lock is not poisoned").remove(&addr);
+    }
+
+    /// Returns a listen address of the same IP version as the given remote
+    /// IP address and whose IP address can reach it, if any.
+    fn local_dial_addr(&self, remote_ip: &IpAddr) -> Option<SocketAddr> {
+        self.listen_addrs.lock().expect("lock is not poisoned")
+            .iter()
+            .find(|addr| {
+                addr.is_ipv4() == remote_ip.is_ipv4()
+                    && (addr.ip().is_unspecified() || addr.ip().is_loopback() == remote_ip.is_loopback())
+            })
+            .cloned()
+    }
 }
 
 impl TcpConfig {
@@ -78,6 +135,13 @@ impl TcpConfig {
             sleep_on_error: Duration::from_millis(100),
             ttl: None,
             nodelay: None,
+            keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            traffic_class: None,
+            port_reuse: None,
+            interface: None,
+            http_proxy: None,
         }
     }
 
@@ -88,10 +152,78 @@ impl TcpConfig {
     }
 
     /// Sets the `TCP_NODELAY` to set for opened sockets.
+    ///
+    /// Enabling `TCP_NODELAY` disables Nagle's algorithm, which otherwise
+    /// delays the small messages of e.g. protocol negotiations.
     pub fn nodelay(mut self, value: bool) -> Self {
         self.nodelay = Some(value);
         self
     }
+
+    /// Sets the keep-alive interval to set for opened sockets, i.e. the
+    /// idle time after which keep-alive probes are sent, or `None` to
+    /// disable keep-alive.
+    pub fn keepalive(mut self, value: Option<Duration>) -> Self {
+        self.keepalive = Some(value);
+        self
+    }
+
+    /// Sets the size of the send buffer (`SO_SNDBUF`) of opened sockets.
+    pub fn send_buffer_size(mut self, value: usize) -> Self {
+        self.send_buffer_size = Some(value);
+        self
+    }
+
+    /// Sets the size of the receive buffer (`SO_RCVBUF`) of opened sockets.
+    pub fn recv_buffer_size(mut self, value: usize) -> Self {
+        self.recv_buffer_size = Some(value);
+        self
+    }
+
+    /// Sets the traffic class of opened sockets, i.e. `IP_TOS` for IPv4
+    /// and `IPV6_TCLASS` for IPv6 sockets, e.g. for DSCP marking.
+    ///
+    /// The traffic class is only set on Unix platforms.
+    pub fn traffic_class(mut self, value: u32) -> Self {
+        self.traffic_class = Some(value);
+        self
+    }
+
+    /// Enables or disables port reuse.
+    ///
+    /// With port reuse, listeners are bound with `SO_REUSEADDR` and
+    /// `SO_REUSEPORT`, and dialed sockets are bound to the local port of
+    /// a listener of this configuration or its clones, so that remotes
+    /// observe the listen address of the local node. This is a prerequisite
+    /// for TCP hole punching. Dials use an ephemeral port as usual if there
+    /// is no suitable listener or binding to the listen port fails.
+    pub fn port_reuse(mut self, port_reuse: bool) -> Self {
+        self.port_reuse = if port_reuse { Some(PortReuse::default()) } else { None };
+        self
+    }
+
+    /// Restricts listening on a wildcard address to the addresses of the given network
+    /// interface, e.g. `eth0`.
+    ///
+    /// Listening on `/ip4/0.0.0.0/tcp/<port>` or `/ip6/::/tcp/<port>` then binds one socket
+    /// to each address of the interface of the same IP version. The addresses are checked
+    /// periodically, and sockets are bound or closed as addresses appear or disappear,
+    /// which is reported with `NewAddress` and `AddressExpired` events. Listening on a
+    /// specific IP address is unaffected.
+    pub fn interface(mut self, name: impl Into<String>) -> Self {
+        self.interface = Some(name.into());
+        self
+    }
+
+    /// Tunnels dials through the given HTTP proxy with `CONNECT` requests.
+    ///
+    /// Besides IP addresses, `/dns`, `/dns4` and `/dns6` addresses can then be dialed, and are
+    /// resolved by the proxy. Port reuse does not apply to tunneled dials, and listening is
+    /// unaffected.
+    pub fn http_proxy(mut self, proxy: HttpProxy) -> Self {
+        self.http_proxy = Some(proxy);
+        self
+    }
 }
 
 impl Transport for TcpConfig {
@@ -109,10 +241,20 @@ impl Transport for TcpConfig {
                 return Err(TransportError::MultiaddrNotSupported(addr))
             };
 
+        if let (Some(interface), true) = (&self.interface, socket_addr.ip().is_unspecified()) {
+            let interface = interface.clone();
+            let listener = InterfaceListenStream::new(self, interface, socket_addr)
+                .map_err(TransportError::Other)?;
+            return Ok(Box::pin(listener))
+        }
+
         async fn do_listen(cfg: TcpConfig, socket_addr: SocketAddr)
             -> Result<impl Stream<Item = Result<ListenerEvent<Ready<Result<TcpTransStream, io::Error>>>, io::Error>>, io::Error>
         {
-            let listener = async_std::net::TcpListener::bind(&socket_addr).await?;
+            let listener = match &cfg.port_reuse {
+                Some(_) => bind_reuse(socket_addr)?,
+                None => async_std::net::TcpListener::bind(&socket_addr).await?
+            };
             let local_addr = listener.local_addr()?;
             let port = local_addr.port();
 
@@ -148,11 +290,20 @@ impl Transport for TcpConfig {
                 }
             };
 
+            if let Some(port_reuse) = &cfg.port_reuse {
+                port_reuse.register(local_addr)
+            }
+
             let listen_stream = TcpListenStream {
                 stream: listener,
+                local_addr,
                 pause: None,
                 pause_duration: cfg.sleep_on_error,
                 port,
+                if_poll: match addrs {
+                    Addresses::Many(_) => Some(Delay::new(IF_POLL_INTERVAL)),
+                    Addresses::One(_) => None
+                },
                 addrs,
                 pending,
                 config: cfg
@@ -165,6 +316,10 @@ impl Transport for TcpConfig {
     }
 
     fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        if let Some(authority) = self.http_proxy.as_ref().and_then(|p| proxy::tunneled_authority(p, &addr)) {
+            return Ok(Box::pin(proxy::dial(self, addr, authority)))
+        }
+
         let socket_addr =
             if let Ok(socket_addr) = multiaddr_to_socketaddr(&addr) {
                 if socket_addr.port() == 0 || socket_addr.ip().is_unspecified() {
@@ -179,7 +334,17 @@ impl Transport for TcpConfig {
         debug!("Dialing {}", addr);
 
         async fn do_dial(cfg: TcpConfig, socket_addr: SocketAddr) -> Result<TcpTransStream, io::Error> {
-            let stream = TcpStream::connect(&socket_addr).await?;
+            let local_addr = cfg.port_reuse.as_ref().and_then(|p| p.local_dial_addr(&socket_addr.ip()));
+            let stream = match local_addr {
+                Some(local_addr) => match connect_reuse(local_addr, socket_addr).await {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        debug!("Dialing {} from {} failed ({}), using an ephemeral port", socket_addr, local_addr, err);
+                        TcpStream::connect(&socket_addr).await?
+                    }
+                },
+                None => TcpStream::connect(&socket_addr).await?
+            };
             apply_config(&cfg, &stream)?;
             Ok(TcpTransStream { inner: stream })
         }
@@ -250,6 +415,121 @@ fn apply_config(config: &TcpConfig, socket: &TcpStream) -> Result<(), io::Error>
         socket.set_nodelay(nodelay)?;
     }
 
+    if config.keepalive.is_none()
+        && config.send_buffer_size.is_none()
+        && config.recv_buffer_size.is_none()
+        && config.traffic_class.is_none()
+    {
+        return Ok(())
+    }
+
+    with_socket(socket, |s| {
+        if let Some(keepalive) = config.keepalive {
+            s.set_keepalive(keepalive)?;
+        }
+
+        if let Some(size) = config.send_buffer_size {
+            s.set_send_buffer_size(size)?;
+        }
+
+        if let Some(size) = config.recv_buffer_size {
+            s.set_recv_buffer_size(size)?;
+        }
+
+        if let Some(class) = config.traffic_class {
+            set_traffic_class(s, class)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Creates a socket for the given address with port reuse enabled.
+fn reuse_socket(addr: &SocketAddr) -> io::Result<socket2::Socket> {
+    let domain = if addr.is_ipv4() { socket2::Domain::ipv4() } else { socket2::Domain::ipv6() };
+    let socket = socket2::Socket::new(domain, socket2::Type::stream(), Some(socket2::Protocol::tcp()))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true)?;
+    Ok(socket)
+}
+
+/// Binds a listener to the given address with port reuse enabled.
+fn bind_reuse(addr: SocketAddr) -> io::Result<async_std::net::TcpListener> {
+    let socket = reuse_socket(&addr)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(async_std::net::TcpListener::from(socket.into_tcp_listener()))
+}
+
+/// Connects to the given remote address from the given local address,
+/// with port reuse enabled.
+async fn connect_reuse(local_addr: SocketAddr, remote_addr: SocketAddr) -> io::Result<TcpStream> {
+    let socket = reuse_socket(&local_addr)?;
+    socket.bind(&local_addr.into())?;
+    socket.set_nonblocking(true)?;
+    match socket.connect(&remote_addr.into()) {
+        Ok(()) => {}
+        #[cfg(unix)]
+        Err(err) if err.raw_os_error() == Some(libc::EINPROGRESS) => {}
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+        Err(err) => return Err(err)
+    }
+    let stream = async_io::Async::new(socket.into_tcp_stream())?;
+    stream.writable().await?;
+    if let Some(err) = stream.get_ref().take_error()? {
+        return Err(err)
+    }
+    Ok(TcpStream::from(stream.into_inner()?))
+}
+
+/// Calls the given function with a `socket2::Socket` for the given stream,
+/// for setting socket options not exposed by `async_std`.
+fn with_socket<R>(stream: &TcpStream, f: impl FnOnce(&socket2::Socket) -> io::Result<R>) -> io::Result<R> {
+    #[cfg(unix)]
+    let socket = {
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+        unsafe { socket2::Socket::from_raw_fd(stream.as_raw_fd()) }
+    };
+    #[cfg(windows)]
+    let socket = {
+        use std::os::windows::io::{AsRawSocket, FromRawSocket};
+        unsafe { socket2::Socket::from_raw_socket(stream.as_raw_socket()) }
+    };
+    // The socket is owned by the stream and must not be closed here.
+    let socket = std::mem::ManuallyDrop::new(socket);
+    f(&socket)
+}
+
+/// Sets `IP_TOS` or `IPV6_TCLASS` on the given socket, depending on its address family.
+#[cfg(unix)]
+fn set_traffic_class(socket: &socket2::Socket, class: u32) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let (level, name) = match socket.local_addr()?.as_inet6() {
+        Some(_) => (libc::IPPROTO_IPV6, libc::IPV6_TCLASS),
+        None => (libc::IPPROTO_IP, libc::IP_TOS)
+    };
+    let value = class as libc::c_int;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t)
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error())
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_traffic_class(_: &socket2::Socket, _: u32) -> io::Result<()> {
+    debug!("Setting the traffic class is not supported on this platform");
     Ok(())
 }
 
@@ -268,6 +548,8 @@ type Buffer = VecDeque<Result<ListenerEvent<Ready<Result<TcpTransStream, io::Err
 pub struct TcpListenStream {
     /// The incoming connections.
     stream: async_std::net::TcpListener,
+    /// The address the listener is bound to.
+    local_addr: SocketAddr,
     /// The current pause if any.
     pause: Option<Delay>,
     /// How long to pause after an error.
@@ -276,12 +558,22 @@ pub struct TcpListenStream {
     port: u16,
     /// The set of known addresses.
     addrs: Addresses,
+    /// The next check for changes of the host addresses, if listening on all interfaces.
+    if_poll: Option<Delay>,
     /// Temporary buffer of listener events.
     pending: Buffer,
     /// Original configuration.
     config: TcpConfig
 }
 
+impl Drop for TcpListenStream {
+    fn drop(&mut self) {
+        if let Some(port_reuse) = &self.config.port_reuse {
+            port_reuse.unregister(self.local_addr)
+        }
+    }
+}
+
 // If we listen on all interfaces, find out to which interface the given
 // socket address belongs. In case we think the address is new, check
 // all host interfaces again and report new and expired listen addresses.
@@ -304,8 +596,28 @@ fn check_for_interface_changes(
     // The local IP address of this socket is new to us.
     // We check for changes in the set of host addresses and report new
     // and expired addresses.
-    //
-    // TODO: We do not detect expired addresses unless there is a new address.
+    update_listen_addrs(listen_port, listen_addrs, pending)?;
+
+    // We should now be able to find the local address, if not something
+    // is seriously wrong and we report an error.
+    if listen_addrs.iter()
+        .find(|(ip, net, _)| ip == &socket_addr.ip() || net.contains(&socket_addr.ip()))
+        .is_none()
+    {
+        let msg = format!("{} does not match any listen address", socket_addr.ip());
+        return Err(io::Error::new(io::ErrorKind::Other, msg))
+    }
+
+    Ok(())
+}
+
+// Check for changes in the set of host addresses and report new and
+// expired listen addresses.
+fn update_listen_addrs(
+    listen_port: u16,
+    listen_addrs: &mut Vec<(IpAddr, IpNet, Multiaddr)>,
+    pending: &mut Buffer
+) -> Result<(), io::Error> {
     let old_listen_addrs = std::mem::replace(listen_addrs, host_addresses(listen_port)?);
 
     // Check for addresses no longer in use.
@@ -324,16 +636,6 @@ fn check_for_interface_changes(
         }
     }
 
-    // We should now be able to find the local address, if not something
-    // is seriously wrong and we report an error.
-    if listen_addrs.iter()
-        .find(|(ip, net, _)| ip == &socket_addr.ip() || net.contains(&socket_addr.ip()))
-        .is_none()
-    {
-        let msg = format!("{} does not match any listen address", socket_addr.ip());
-        return Err(io::Error::new(io::ErrorKind::Other, msg))
-    }
-
     Ok(())
 }
 
@@ -349,10 +651,33 @@ impl TcpListenStream {
                 let _ = pause.await;
             }
 
+            // When listening on all interfaces, the host addresses are
+            // checked periodically while waiting for incoming connections.
+            let accepted = {
+                let accept = self.stream.accept();
+                futures::pin_mut!(accept);
+                match &mut self.if_poll {
+                    Some(if_poll) => match future::select(accept, if_poll).await {
+                        future::Either::Left((accepted, _)) => Some(accepted),
+                        future::Either::Right(((), _)) => None
+                    },
+                    None => Some(accept.await)
+                }
+            };
+
             // TODO: do we get the peer_addr at the same time?
-            let (sock, _) = match self.stream.accept().await {
-                Ok(s) => s,
-                Err(e) => {
+            let (sock, _) = match accepted {
+                Some(Ok(s)) => s,
+                None => {
+                    self.if_poll = Some(Delay::new(IF_POLL_INTERVAL));
+                    if let Addresses::Many(ref mut addrs) = self.addrs {
+                        if let Err(err) = update_listen_addrs(self.port, addrs, &mut self.pending) {
+                            return (Err(err), self);
+                        }
+                    }
+                    continue
+                }
+                Some(Err(e)) => {
                     debug!("error accepting incoming connection: {}", e);
                     self.pause = Some(Delay::new(self.pause_duration));
                     return (Err(e), self);
@@ -489,6 +814,90 @@ mod tests {
         async_std::task::block_on(futures::future::join(server, client)).1.unwrap();
     }
 
+    #[test]
+    fn socket_options() {
+        use std::time::Duration;
+        use super::{apply_config, with_socket};
+
+        let config = TcpConfig::new()
+            .nodelay(true)
+            .keepalive(Some(Duration::from_secs(30)))
+            .send_buffer_size(64 * 1024)
+            .recv_buffer_size(64 * 1024)
+            .traffic_class(0x10);
+
+        async_std::task::block_on(async {
+            let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let stream = async_std::net::TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+            apply_config(&config, &stream).unwrap();
+            assert!(stream.nodelay().unwrap());
+            with_socket(&stream, |s| {
+                assert_eq!(s.keepalive()?, Some(Duration::from_secs(30)));
+                assert!(s.send_buffer_size()? >= 64 * 1024);
+                assert!(s.recv_buffer_size()? >= 64 * 1024);
+                Ok(())
+            }).unwrap();
+        })
+    }
+
+    #[test]
+    fn port_reuse_dialing() {
+        let config = TcpConfig::new().port_reuse(true);
+
+        let mut listener = config.clone()
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .expect("listener");
+        let mut remote = TcpConfig::new()
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .expect("remote listener");
+
+        async_std::task::block_on(async move {
+            let listen_addr = listener.next().await
+                .expect("some event")
+                .expect("no error")
+                .into_new_address()
+                .expect("listen address");
+            let remote_addr = remote.next().await
+                .expect("some event")
+                .expect("no error")
+                .into_new_address()
+                .expect("listen address");
+
+            let _dialer = config.dial(remote_addr).expect("dialer").await.expect("connection");
+            match remote.next().await.expect("some event").expect("no error") {
+                ListenerEvent::Upgrade { remote_addr, .. } => assert_eq!(remote_addr, listen_addr),
+                other => panic!("Unexpected listener event: {:?}", other.map(|_| ()))
+            }
+        })
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn interface_listening() {
+        let mut listener = TcpConfig::new()
+            .interface("lo")
+            .listen_on("/ip4/0.0.0.0/tcp/0".parse().unwrap())
+            .expect("listener");
+
+        async_std::task::block_on(async move {
+            let addr = listener.next().await
+                .expect("some event")
+                .expect("no error")
+                .into_new_address()
+                .expect("listen address");
+            match addr.iter().next() {
+                Some(Protocol::Ip4(ip)) => assert!(ip.is_loopback()),
+                other => panic!("Unexpected protocol: {:?}", other)
+            }
+
+            let _dialer = TcpConfig::new().dial(addr.clone()).expect("dialer").await.expect("connection");
+            match listener.next().await.expect("some event").expect("no error") {
+                ListenerEvent::Upgrade { local_addr, .. } => assert_eq!(local_addr, addr),
+                other => panic!("Unexpected listener event: {:?}", other.map(|_| ()))
+            }
+        })
+    }
+
     #[test]
     fn multiaddr_to_tcp_conversion() {
         use std::net::Ipv6Addr;
@@ -622,4 +1031,108 @@ mod tests {
             .unwrap();
         assert!(tcp.listen_on(addr).is_err());
     }
+
+    /// Runs an HTTP proxy answering the first `CONNECT` request with `response`, and
+    /// returns the request.
+    async fn http_proxy(listener: async_std::net::TcpListener, response: &'static [u8]) -> String {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            socket.read_exact(&mut byte).await.unwrap();
+            request.push(byte[0]);
+        }
+        socket.write_all(response).await.unwrap();
+        String::from_utf8(request).unwrap()
+    }
+
+    #[test]
+    fn http_proxy_tunneling() {
+        use super::HttpProxy;
+
+        async_std::task::block_on(async {
+            let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://us%40er:pa%3Ass@{}", listener.local_addr().unwrap());
+            let proxy = async_std::task::spawn(
+                http_proxy(listener, b"HTTP/1.1 200 Connection established\r\n\r\nhello")
+            );
+
+            let config = TcpConfig::new().http_proxy(HttpProxy::new(&url).unwrap());
+            let mut socket = config.dial("/dns/example.com/tcp/443".parse().unwrap())
+                .expect("dialer")
+                .await
+                .expect("connection");
+            let mut buf = [0; 5];
+            socket.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+
+            let request = proxy.await;
+            assert!(request.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+            assert!(request.contains("Proxy-Authorization: Basic dXNAZXI6cGE6c3M=\r\n"));
+        })
+    }
+
+    #[test]
+    fn http_proxy_refusal() {
+        use super::HttpProxy;
+        use libp2p_core::transport::{TransportEvent, TransportEventKind, TransportEvents};
+
+        async_std::task::block_on(async {
+            let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let proxy = async_std::task::spawn(
+                http_proxy(listener, b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+            );
+
+            let events = TransportEvents::new();
+            let mut subscriber = events.subscribe();
+            let config = TcpConfig::new().http_proxy(HttpProxy::new(&url).unwrap().events(events));
+            let addr: Multiaddr = "/ip6/::1/tcp/4001".parse().unwrap();
+            let err = config.dial(addr.clone())
+                .expect("dialer")
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+
+            let request = proxy.await;
+            assert!(request.starts_with("CONNECT [::1]:4001 HTTP/1.1\r\n"));
+            assert!(!request.contains("Proxy-Authorization"));
+
+            match subscriber.next().await {
+                Some(TransportEvent { transport: "tcp", address, kind: TransportEventKind::ProxyFailed { .. } }) =>
+                    assert_eq!(address, addr),
+                e => panic!("Unexpected event: {:?}", e)
+            }
+        })
+    }
+
+    #[test]
+    fn http_proxy_allowed_ports() {
+        use super::HttpProxy;
+
+        assert!(HttpProxy::new("https://127.0.0.1:3128").is_err());
+        assert!(HttpProxy::new("127.0.0.1:3128").is_err());
+        assert!(HttpProxy::new("http://[::1]:3128").is_ok());
+
+        // Nothing listens on the proxy port, so only direct dials succeed.
+        let proxy = HttpProxy::new("http://127.0.0.1:1").unwrap().allowed_ports(vec![443]);
+        let config = TcpConfig::new().http_proxy(proxy);
+        let mut listener = TcpConfig::new()
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .expect("listener");
+
+        async_std::task::block_on(async move {
+            let addr = listener.next().await
+                .expect("some event")
+                .expect("no error")
+                .into_new_address()
+                .expect("listen address");
+            let _dialer = config.clone().dial(addr).expect("dialer").await.expect("connection");
+            assert!(config.clone().dial("/dns/example.com/tcp/80".parse().unwrap()).is_err());
+            assert!(config.dial("/ip4/127.0.0.1/tcp/443".parse().unwrap())
+                .expect("dialer")
+                .await
+                .is_err());
+        })
+    }
 }
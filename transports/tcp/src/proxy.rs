@@ -0,0 +1,205 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Tunneling of dials through an HTTP proxy with `CONNECT` requests.
+
+use crate::{apply_config, TcpConfig, TcpTransStream};
+use async_std::net::TcpStream;
+use data_encoding::BASE64;
+use futures::prelude::*;
+use libp2p_core::{
+    multiaddr::{Multiaddr, Protocol},
+    transport::{TransportEvent, TransportEventKind, TransportEvents}
+};
+use log::debug;
+use percent_encoding::percent_decode_str;
+use std::{collections::HashSet, fmt, io, str};
+use url::Url;
+
+/// The maximum size of the response to a `CONNECT` request.
+const MAX_RESPONSE_SIZE: usize = 8192;
+
+/// An HTTP proxy through which dials are tunneled.
+#[derive(Clone)]
+pub struct HttpProxy {
+    /// The host name or IP address of the proxy.
+    host: String,
+    /// The port of the proxy.
+    port: u16,
+    /// The value of the `Proxy-Authorization` header, if any.
+    authorization: Option<String>,
+    /// The target ports to tunnel, or `None` for all ports.
+    ports: Option<HashSet<u16>>,
+    /// The bus to publish failed tunnels to, if any.
+    events: Option<TransportEvents>,
+}
+
+impl HttpProxy {
+    /// Creates a proxy configuration from a URL of the form
+    /// `http://[<username>:<password>@]<host>[:<port>]`, e.g. the value of the `HTTPS_PROXY`
+    /// environment variable.
+    ///
+    /// The port defaults to 80. Percent-encoded credentials are decoded.
+    pub fn new(url: &str) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", msg, url));
+        let url = Url::parse(url).map_err(|_| invalid("invalid proxy URL"))?;
+        if url.scheme() != "http" {
+            return Err(invalid("unsupported proxy scheme"))
+        }
+        let host = match url.host() {
+            Some(url::Host::Ipv6(ip)) => ip.to_string(),
+            Some(host) => host.to_string(),
+            None => return Err(invalid("no host in proxy URL"))
+        };
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        let proxy = HttpProxy { host, port, authorization: None, ports: None, events: None };
+        if url.username().is_empty() {
+            return Ok(proxy)
+        }
+        let decode = |s| percent_decode_str(s).decode_utf8()
+            .map(|s| s.into_owned())
+            .map_err(|_| invalid("invalid credentials in proxy URL"));
+        let username = decode(url.username())?;
+        let password = decode(url.password().unwrap_or(""))?;
+        Ok(proxy.credentials(&username, &password))
+    }
+
+    /// Authenticates with the proxy using the basic authentication scheme.
+    pub fn credentials(mut self, username: &str, password: &str) -> Self {
+        let encoded = BASE64.encode(format!("{}:{}", username, password).as_bytes());
+        self.authorization = Some(format!("Basic {}", encoded));
+        self
+    }
+
+    /// Only tunnels dials to the given ports, e.g. 443 for proxies only allowing HTTPS.
+    ///
+    /// Dials to other ports are made directly. By default, all dials are tunneled.
+    pub fn allowed_ports(mut self, ports: impl IntoIterator<Item = u16>) -> Self {
+        self.ports = Some(ports.into_iter().collect());
+        self
+    }
+
+    /// Publishes the failures to establish tunnels to the given event bus.
+    pub fn events(mut self, events: TransportEvents) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Returns true if dials to `port` are tunneled.
+    fn tunnels(&self, port: u16) -> bool {
+        self.ports.as_ref().map_or(true, |ports| ports.contains(&port))
+    }
+}
+
+impl fmt::Debug for HttpProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpProxy")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("authorization", &self.authorization.as_ref().map(|_| "<hidden>"))
+            .field("ports", &self.ports)
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+/// Returns the `<host>:<port>` authority to request from `proxy` for `addr`, if `addr`
+/// is tunneled.
+///
+/// Besides IP addresses, `/dns`, `/dns4` and `/dns6` names are supported, leaving their
+/// resolution to the proxy.
+pub(crate) fn tunneled_authority(proxy: &HttpProxy, addr: &Multiaddr) -> Option<String> {
+    let mut iter = addr.iter();
+    let (authority, port) = match (iter.next()?, iter.next()?) {
+        (Protocol::Ip4(ip), Protocol::Tcp(port)) if !ip.is_unspecified() =>
+            (format!("{}:{}", ip, port), port),
+        (Protocol::Ip6(ip), Protocol::Tcp(port)) if !ip.is_unspecified() =>
+            (format!("[{}]:{}", ip, port), port),
+        (Protocol::Dns(name), Protocol::Tcp(port))
+        | (Protocol::Dns4(name), Protocol::Tcp(port))
+        | (Protocol::Dns6(name), Protocol::Tcp(port)) =>
+            (format!("{}:{}", name, port), port),
+        _ => return None
+    };
+    if iter.next().is_some() || port == 0 || !proxy.tunnels(port) {
+        return None
+    }
+    Some(authority)
+}
+
+/// Dials `addr` through the proxy of `config`, given the `authority` of `addr`.
+pub(crate) async fn dial(config: TcpConfig, addr: Multiaddr, authority: String) -> io::Result<TcpTransStream> {
+    let result = connect(&config, authority).await;
+    let events = config.http_proxy.as_ref().and_then(|proxy| proxy.events.as_ref());
+    if let (Err(err), Some(events)) = (&result, events) {
+        events.publish(TransportEvent {
+            transport: "tcp",
+            address: addr,
+            kind: TransportEventKind::ProxyFailed { error: err.to_string() }
+        })
+    }
+    result
+}
+
+/// Connects to the proxy of `config` and asks it to connect to `authority`.
+async fn connect(config: &TcpConfig, authority: String) -> io::Result<TcpTransStream> {
+    let proxy = config.http_proxy.as_ref().expect("dialing through the proxy of the config");
+    debug!("Dialing {} through the proxy at {}:{}", authority, proxy.host, proxy.port);
+    let mut stream = TcpStream::connect((proxy.host.as_str(), proxy.port)).await?;
+    apply_config(config, &stream)?;
+
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some(authorization) = &proxy.authorization {
+        request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    // The response is read byte by byte, as the data following it belongs to the tunnel.
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_RESPONSE_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "proxy response too large"))
+        }
+        let mut byte = [0];
+        stream.read_exact(&mut byte).await?;
+        response.push(byte[0]);
+    }
+
+    let status_line = str::from_utf8(&response)
+        .ok()
+        .and_then(|response| response.lines().next())
+        .unwrap_or_default();
+    let status = match status_line.split(' ').collect::<Vec<_>>().as_slice() {
+        [version, status, ..] if version.starts_with("HTTP/1.") => status.parse::<u16>().ok(),
+        _ => None
+    };
+    match status {
+        Some(200 ..= 299) => Ok(TcpTransStream { inner: stream }),
+        Some(407) => Err(io::Error::new(io::ErrorKind::PermissionDenied,
+            format!("proxy authentication required: {}", status_line))),
+        Some(_) => Err(io::Error::new(io::ErrorKind::ConnectionRefused,
+            format!("proxy refused to connect to {}: {}", authority, status_line))),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData,
+            format!("invalid proxy response: {:?}", status_line))),
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The message channel to a remote.
+
+use crate::{endpoint::Endpoint, reliability::Reliability};
+use bytes::{Buf, Bytes};
+use futures::{channel::mpsc, prelude::*, ready};
+use futures_timer::Delay;
+use std::{
+    collections::VecDeque,
+    fmt,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant
+};
+
+/// A channel of messages to and from a remote.
+///
+/// As a `Stream` and a `Sink`, the connection yields and sends messages,
+/// each of which is carried by one datagram. Whether messages are lost,
+/// duplicated or reordered depends on the `Reliability` of the
+/// connection. The stream never ends, as UDP has no notion of closing a
+/// connection.
+///
+/// As `AsyncRead` and `AsyncWrite`, the connection is a stream of bytes,
+/// every write sending a message of at most the maximum message size, such
+/// that reliable connections can be upgraded like any other connection.
+///
+/// The connection is meant to be polled by a single task. Messages are
+/// only received while the connection is polled, and at most as many
+/// messages as datagrams are queued per remote are buffered until they are
+/// read.
+pub struct UdpConnection {
+    endpoint: Endpoint,
+    remote: SocketAddr,
+    /// The datagrams of the remote.
+    datagrams: mpsc::Receiver<Bytes>,
+    reliability: Box<dyn Reliability>,
+    /// The maximum size of the messages sent.
+    max_message_size: usize,
+    /// The maximum number of messages buffered.
+    queue_size: usize,
+    /// The messages received and not read yet.
+    messages: VecDeque<Bytes>,
+    /// The unread part of the message read through `AsyncRead`.
+    read_buf: Bytes,
+    /// The timer of the reliability strategy, with its deadline.
+    timer: Option<(Instant, Delay)>,
+    /// The kind of error that failed the connection.
+    error: Option<io::ErrorKind>
+}
+
+impl UdpConnection {
+    pub(crate) fn new(
+        endpoint: Endpoint,
+        remote: SocketAddr,
+        datagrams: mpsc::Receiver<Bytes>,
+        reliability: Box<dyn Reliability>,
+        max_message_size: usize,
+        queue_size: usize
+    ) -> Self {
+        UdpConnection {
+            endpoint,
+            remote,
+            datagrams,
+            reliability,
+            max_message_size,
+            queue_size,
+            messages: VecDeque::new(),
+            read_buf: Bytes::new(),
+            timer: None,
+            error: None
+        }
+    }
+
+    /// The address of the local socket.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.endpoint.local_addr()
+    }
+
+    /// The address of the remote.
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote
+    }
+
+    /// The maximum size of the messages sent.
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size
+    }
+
+    /// Handles the datagrams received and the timer of the reliability
+    /// strategy, registering the current task for further progress.
+    fn drive(&mut self, cx: &mut Context) -> io::Result<()> {
+        if let Some(kind) = self.error {
+            return Err(kind.into())
+        }
+        let result = self.drive_inner(cx);
+        if let Err(e) = &result {
+            self.error = Some(e.kind())
+        }
+        result
+    }
+
+    fn drive_inner(&mut self, cx: &mut Context) -> io::Result<()> {
+        let now = Instant::now();
+        let mut deliver = Vec::new();
+        let mut transmit = Vec::new();
+
+        while self.messages.len() + deliver.len() < self.queue_size {
+            match self.datagrams.poll_next_unpin(cx) {
+                Poll::Ready(Some(datagram)) =>
+                    self.reliability.receive(now, datagram, &mut deliver, &mut transmit),
+                Poll::Ready(None) | Poll::Pending => break
+            }
+        }
+        self.messages.extend(deliver);
+
+        self.transmit(transmit)?;
+
+        loop {
+            let deadline = match self.reliability.timeout() {
+                Some(deadline) => deadline,
+                None => {
+                    self.timer = None;
+                    return Ok(())
+                }
+            };
+            match &mut self.timer {
+                Some((d, _)) if *d == deadline => {}
+                timer => *timer = Some((deadline, Delay::new(deadline.saturating_duration_since(now))))
+            }
+            let (_, delay) = self.timer.as_mut().expect("The timer has been set above");
+            if delay.poll_unpin(cx).is_pending() {
+                return Ok(())
+            }
+            let mut transmit = Vec::new();
+            self.reliability.on_timeout(Instant::now(), &mut transmit)?;
+            self.transmit(transmit)?;
+            self.timer = None
+        }
+    }
+
+    fn transmit(&self, datagrams: Vec<Bytes>) -> io::Result<()> {
+        for datagram in datagrams {
+            self.endpoint.send(self.remote, &datagram)?
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for UdpConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpConnection")
+            .field("local_addr", &self.endpoint.local_addr())
+            .field("remote_addr", &self.remote)
+            .finish()
+    }
+}
+
+impl Stream for UdpConnection {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(message) = self.messages.pop_front() {
+            return Poll::Ready(Some(Ok(message)))
+        }
+        if let Err(e) = self.drive(cx) {
+            return Poll::Ready(Some(Err(e)))
+        }
+        match self.messages.pop_front() {
+            Some(message) => Poll::Ready(Some(Ok(message))),
+            None => Poll::Pending
+        }
+    }
+}
+
+impl Sink<Bytes> for UdpConnection {
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.drive(cx)?;
+        if self.reliability.can_send() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Sends a message, which must not exceed the maximum message size.
+    fn start_send(mut self: Pin<&mut Self>, message: Bytes) -> io::Result<()> {
+        if message.len() > self.max_message_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "message too large"))
+        }
+        let mut transmit = Vec::new();
+        self.reliability.send(Instant::now(), message, &mut transmit);
+        self.transmit(transmit)
+    }
+
+    /// Waits for the remote to acknowledge all messages sent, if the
+    /// reliability strategy acknowledges messages.
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.drive(cx)?;
+        if self.reliability.is_flushed() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Sink::poll_flush(self, cx)
+    }
+}
+
+impl AsyncRead for UdpConnection {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        // Empty messages are skipped, as reading nothing signals the end of
+        // the stream.
+        while self.read_buf.is_empty() {
+            match ready!(self.as_mut().poll_next(cx)) {
+                Some(Ok(message)) => self.read_buf = message,
+                Some(Err(e)) => return Poll::Ready(Err(e)),
+                None => return Poll::Ready(Ok(0))
+            }
+        }
+        let n = buf.len().min(self.read_buf.len());
+        buf[.. n].copy_from_slice(&self.read_buf[.. n]);
+        self.read_buf.advance(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for UdpConnection {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0))
+        }
+        ready!(Sink::poll_ready(self.as_mut(), cx))?;
+        let n = buf.len().min(self.max_message_size);
+        self.start_send(Bytes::copy_from_slice(&buf[.. n]))?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Sink::poll_flush(self, cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Sink::poll_close(self, cx)
+    }
+}
+
+impl Drop for UdpConnection {
+    fn drop(&mut self) {
+        self.endpoint.disconnect(self.remote, &self.datagrams)
+    }
+}
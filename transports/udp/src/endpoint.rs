@@ -0,0 +1,202 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! UDP sockets shared by the connections to different remotes.
+
+use async_std::net::UdpSocket;
+use bytes::Bytes;
+use futures::{channel::mpsc, prelude::*, select};
+use log::{debug, trace};
+use std::{collections::HashMap, io, net::SocketAddr, sync::{Arc, Mutex}};
+
+/// The maximum payload of a UDP datagram.
+pub(crate) const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+/// A new remote of a listening endpoint, together with the datagrams it
+/// sends.
+pub(crate) type Incoming = mpsc::Receiver<(SocketAddr, mpsc::Receiver<Bytes>)>;
+
+/// A handle to a UDP socket, whose datagrams are dispatched to the
+/// connections by their remote address.
+///
+/// The datagrams are received by a background task, which ends once all
+/// handles to the endpoint are dropped.
+#[derive(Clone)]
+pub(crate) struct Endpoint {
+    inner: Arc<Inner>,
+    /// Terminates the background task once the last handle is dropped.
+    _alive: mpsc::UnboundedSender<()>
+}
+
+struct Inner {
+    /// The socket used for sending, sharing the underlying socket with the
+    /// background task.
+    socket: std::net::UdpSocket,
+    local_addr: SocketAddr,
+    /// The datagrams of every remote are sent to its connection.
+    connections: Mutex<HashMap<SocketAddr, mpsc::Sender<Bytes>>>,
+    /// How many datagrams of a remote are buffered until they are dropped.
+    queue_size: usize
+}
+
+impl Endpoint {
+    /// Creates an endpoint that only accepts datagrams of the remotes it
+    /// connects to.
+    pub(crate) fn client(socket: std::net::UdpSocket, queue_size: usize) -> io::Result<Self> {
+        Endpoint::new(socket, queue_size, None)
+    }
+
+    /// Creates an endpoint that also accepts datagrams of new remotes,
+    /// which are reported through the returned stream.
+    ///
+    /// At most `backlog` new remotes are buffered until they are accepted.
+    pub(crate) fn server(socket: std::net::UdpSocket, queue_size: usize, backlog: usize)
+        -> io::Result<(Self, Incoming)>
+    {
+        let (tx, rx) = mpsc::channel(backlog);
+        let endpoint = Endpoint::new(socket, queue_size, Some(tx))?;
+        Ok((endpoint, rx))
+    }
+
+    fn new(
+        socket: std::net::UdpSocket,
+        queue_size: usize,
+        incoming: Option<mpsc::Sender<(SocketAddr, mpsc::Receiver<Bytes>)>>
+    ) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        let inner = Arc::new(Inner {
+            local_addr: socket.local_addr()?,
+            socket: socket.try_clone()?,
+            connections: Mutex::new(HashMap::new()),
+            queue_size
+        });
+        let (alive, closed) = mpsc::unbounded();
+        async_std::task::spawn(receive(inner.clone(), UdpSocket::from(socket), incoming, closed));
+        Ok(Endpoint { inner, _alive: alive })
+    }
+
+    /// The address of the UDP socket of the endpoint.
+    pub(crate) fn local_addr(&self) -> SocketAddr {
+        self.inner.local_addr
+    }
+
+    /// Registers a remote, returning the stream of its datagrams.
+    pub(crate) fn connect(&self, remote: SocketAddr) -> mpsc::Receiver<Bytes> {
+        let (tx, rx) = mpsc::channel(self.inner.queue_size);
+        self.inner.connections.lock().expect("lock is not poisoned").insert(remote, tx);
+        rx
+    }
+
+    /// Unregisters the remote of a dropped connection.
+    pub(crate) fn disconnect(&self, remote: SocketAddr, datagrams: &mpsc::Receiver<Bytes>) {
+        let mut connections = self.inner.connections.lock().expect("lock is not poisoned");
+        if connections.get(&remote).map_or(false, |tx| tx.is_connected_to(datagrams)) {
+            connections.remove(&remote);
+        }
+    }
+
+    /// Sends a datagram, dropping it if the send buffer of the socket is
+    /// full.
+    pub(crate) fn send(&self, remote: SocketAddr, datagram: &[u8]) -> io::Result<()> {
+        match self.inner.socket.send_to(datagram, remote) {
+            Ok(_) => Ok(()),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                trace!("Dropping datagram to {}: send buffer full", remote);
+                Ok(())
+            }
+            Err(e) => Err(e)
+        }
+    }
+}
+
+/// Receives the datagrams of `socket` and dispatches them to the
+/// connections of `inner`, until `closed` terminates.
+async fn receive(
+    inner: Arc<Inner>,
+    socket: UdpSocket,
+    mut incoming: Option<mpsc::Sender<(SocketAddr, mpsc::Receiver<Bytes>)>>,
+    mut closed: mpsc::UnboundedReceiver<()>
+) {
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+    loop {
+        let (n, remote) = select! {
+            result = socket.recv_from(&mut buf).fuse() => match result {
+                Ok(received) => received,
+                Err(e) => {
+                    debug!("Failed to receive datagram on {}: {}", inner.local_addr, e);
+                    continue
+                }
+            },
+            _ = closed.next() => {
+                trace!("Endpoint on {} closed", inner.local_addr);
+                return
+            }
+        };
+        let datagram = Bytes::copy_from_slice(&buf[.. n]);
+
+        let mut connections = inner.connections.lock().expect("lock is not poisoned");
+        if let Some(tx) = connections.get_mut(&remote) {
+            match tx.try_send(datagram) {
+                Ok(()) => continue,
+                Err(e) if e.is_full() => {
+                    trace!("Dropping datagram from {}: queue full", remote);
+                    continue
+                }
+                // The connection has been dropped, hence the datagram is
+                // handled as the first of a new connection.
+                Err(e) => {
+                    connections.remove(&remote);
+                    accept(&inner, &mut connections, &mut incoming, remote, e.into_inner())
+                }
+            }
+        } else {
+            accept(&inner, &mut connections, &mut incoming, remote, datagram)
+        }
+    }
+}
+
+/// Reports the first datagram of a new remote to the listener, if any.
+fn accept(
+    inner: &Inner,
+    connections: &mut HashMap<SocketAddr, mpsc::Sender<Bytes>>,
+    incoming: &mut Option<mpsc::Sender<(SocketAddr, mpsc::Receiver<Bytes>)>>,
+    remote: SocketAddr,
+    datagram: Bytes
+) {
+    let listener = match incoming {
+        Some(listener) => listener,
+        None => {
+            trace!("Dropping datagram from unknown remote {}", remote);
+            return
+        }
+    };
+    let (mut tx, rx) = mpsc::channel(inner.queue_size);
+    let _ = tx.try_send(datagram);
+    match listener.try_send((remote, rx)) {
+        Ok(()) => {
+            connections.insert(remote, tx);
+        }
+        Err(e) if e.is_full() => debug!("Dropping datagram from {}: too many pending remotes", remote),
+        Err(_) => {
+            debug!("Dropping datagram from {}: not listening", remote);
+            *incoming = None
+        }
+    }
+}
@@ -0,0 +1,384 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Implementation of the libp2p `Transport` trait for UDP datagrams.
+//!
+//! A connection is a channel of messages to and from a remote, without any
+//! handshake: dialing succeeds immediately and a listener reports a new
+//! connection upon the first datagram of a remote.
+//!
+//! Which messages are delivered is determined by the `Reliability` strategy
+//! of the transport. By default, messages are sent as is and may be lost,
+//! duplicated or reordered, which suits applications preferring fresh data
+//! over retransmissions, e.g. real-time gossip or game state. The `Reliable`
+//! strategy instead retransmits lost messages and delivers them in order,
+//! such that connections can be upgraded like those of stream-oriented
+//! transports.
+//!
+//! Addresses have the form `/ip4/<address>/udp/<port>` or
+//! `/ip6/<address>/udp/<port>`.
+//!
+//! # Usage
+//!
+//! Example:
+//!
+//! ```
+//! use libp2p_udp::{Reliable, UdpConfig};
+//!
+//! # fn main() {
+//! let unreliable = UdpConfig::new();
+//! let reliable = UdpConfig::new().reliability(Reliable::new);
+//! # }
+//! ```
+
+mod connection;
+mod endpoint;
+mod reliability;
+
+pub use connection::UdpConnection;
+pub use reliability::{Reliability, Reliable, Unreliable};
+
+use endpoint::{Endpoint, Incoming, MAX_DATAGRAM_SIZE};
+use futures::{future::{self, Ready}, prelude::*};
+use get_if_addrs::get_if_addrs;
+use libp2p_core::{
+    Transport,
+    multiaddr::{Multiaddr, Protocol},
+    transport::{ListenerEvent, TransportError}
+};
+use log::debug;
+use std::{
+    collections::VecDeque,
+    fmt,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll}
+};
+
+/// Represents the configuration for a UDP transport capability for libp2p.
+#[derive(Clone)]
+pub struct UdpConfig {
+    /// Creates the reliability strategy of each connection.
+    reliability: Arc<dyn Fn() -> Box<dyn Reliability> + Send + Sync>,
+    /// The maximum size of the messages sent.
+    max_message_size: usize,
+    /// How many datagrams of a remote are buffered until they are read.
+    queue_size: usize,
+    /// How many new remotes of a listener are buffered until they are
+    /// accepted.
+    backlog: usize
+}
+
+impl UdpConfig {
+    /// Creates a new configuration object for UDP, whose messages are
+    /// `Unreliable`.
+    pub fn new() -> Self {
+        UdpConfig {
+            reliability: Arc::new(|| Box::new(Unreliable)),
+            max_message_size: 1200,
+            queue_size: 128,
+            backlog: 32
+        }
+    }
+
+    /// Sets the reliability strategy of connections, of which `factory`
+    /// creates an instance for each connection.
+    ///
+    /// Both ends of a connection must use the same strategy.
+    pub fn reliability<F, R>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> R + Send + Sync + 'static,
+        R: Reliability
+    {
+        self.reliability = Arc::new(move || Box::new(factory()));
+        self
+    }
+
+    /// Sets the maximum size of the messages sent, 1200 bytes by default,
+    /// such that datagrams are not fragmented on most paths.
+    ///
+    /// The size is capped by the maximum payload of a UDP datagram, less
+    /// the overhead of the reliability strategy.
+    pub fn max_message_size(mut self, value: usize) -> Self {
+        self.max_message_size = value;
+        self
+    }
+
+    /// Sets how many datagrams of a remote are buffered until they are
+    /// read, further datagrams being dropped.
+    pub fn queue_size(mut self, value: usize) -> Self {
+        self.queue_size = value;
+        self
+    }
+
+    /// Sets how many new remotes of a listener are buffered until they are
+    /// accepted, the datagrams of further remotes being dropped.
+    pub fn backlog(mut self, value: usize) -> Self {
+        self.backlog = value;
+        self
+    }
+
+    fn connection(&self, endpoint: Endpoint, remote: SocketAddr, datagrams: futures::channel::mpsc::Receiver<bytes::Bytes>)
+        -> UdpConnection
+    {
+        let reliability = (self.reliability)();
+        let max_message_size = self.max_message_size.min(MAX_DATAGRAM_SIZE - reliability.overhead());
+        UdpConnection::new(endpoint, remote, datagrams, reliability, max_message_size, self.queue_size)
+    }
+}
+
+impl Default for UdpConfig {
+    fn default() -> Self {
+        UdpConfig::new()
+    }
+}
+
+impl fmt::Debug for UdpConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpConfig")
+            .field("max_message_size", &self.max_message_size)
+            .field("queue_size", &self.queue_size)
+            .field("backlog", &self.backlog)
+            .finish()
+    }
+}
+
+impl Transport for UdpConfig {
+    type Output = UdpConnection;
+    type Error = io::Error;
+    type Listener = UdpListenStream;
+    type ListenerUpgrade = Ready<Result<Self::Output, Self::Error>>;
+    type Dial = Ready<Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let socket_addr = multiaddr_to_socketaddr(&addr)
+            .map_err(|()| TransportError::MultiaddrNotSupported(addr))?;
+        let socket = UdpSocket::bind(socket_addr).map_err(TransportError::Other)?;
+        let (endpoint, incoming) = Endpoint::server(socket, self.queue_size, self.backlog)
+            .map_err(TransportError::Other)?;
+
+        let local_addr = endpoint.local_addr();
+        let listen_addrs = listen_addrs(local_addr).map_err(TransportError::Other)?;
+        debug!("Listening on {:?}", listen_addrs);
+
+        Ok(UdpListenStream {
+            endpoint,
+            incoming,
+            local_addr: ip_to_multiaddr(local_addr.ip(), local_addr.port()),
+            pending: listen_addrs.into_iter().collect(),
+            config: self
+        })
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let socket_addr = match multiaddr_to_socketaddr(&addr) {
+            Ok(socket_addr) if socket_addr.port() != 0 && !socket_addr.ip().is_unspecified() => socket_addr,
+            _ => return Err(TransportError::MultiaddrNotSupported(addr))
+        };
+        debug!("Dialing {}", addr);
+
+        let bind_addr = match socket_addr {
+            SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0)
+        };
+        let socket = UdpSocket::bind(bind_addr).map_err(TransportError::Other)?;
+        let endpoint = Endpoint::client(socket, self.queue_size).map_err(TransportError::Other)?;
+        let datagrams = endpoint.connect(socket_addr);
+        Ok(future::ok(self.connection(endpoint, socket_addr, datagrams)))
+    }
+}
+
+/// Stream of new remotes sending datagrams to a listening socket.
+///
+/// Dropping the stream ignores the datagrams of new remotes, without
+/// closing the existing connections.
+pub struct UdpListenStream {
+    endpoint: Endpoint,
+    incoming: Incoming,
+    /// The address of the UDP socket.
+    local_addr: Multiaddr,
+    /// The listen addresses not reported yet.
+    pending: VecDeque<Multiaddr>,
+    config: UdpConfig
+}
+
+impl Stream for UdpListenStream {
+    type Item = Result<ListenerEvent<Ready<Result<UdpConnection, io::Error>>>, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(addr) = self.pending.pop_front() {
+            return Poll::Ready(Some(Ok(ListenerEvent::NewAddress(addr))))
+        }
+        match self.incoming.poll_next_unpin(cx) {
+            Poll::Ready(Some((remote, datagrams))) => {
+                let remote_addr = ip_to_multiaddr(remote.ip(), remote.port());
+                debug!("Incoming connection from {} at {}", remote_addr, self.local_addr);
+                let connection = self.config.connection(self.endpoint.clone(), remote, datagrams);
+                Poll::Ready(Some(Ok(ListenerEvent::Upgrade {
+                    upgrade: future::ok(connection),
+                    local_addr: self.local_addr.clone(),
+                    remote_addr
+                })))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending
+        }
+    }
+}
+
+/// The addresses of a socket bound to `local_addr`, i.e. the addresses of
+/// all interfaces of the same IP version if it is unspecified.
+fn listen_addrs(local_addr: SocketAddr) -> io::Result<Vec<Multiaddr>> {
+    if local_addr.ip().is_unspecified() {
+        Ok(get_if_addrs()?
+            .into_iter()
+            .map(|iface| iface.ip())
+            .filter(|ip| ip.is_ipv4() == local_addr.is_ipv4())
+            .map(|ip| ip_to_multiaddr(ip, local_addr.port()))
+            .collect())
+    } else {
+        Ok(vec![ip_to_multiaddr(local_addr.ip(), local_addr.port())])
+    }
+}
+
+// This type of logic should probably be moved into the multiaddr package
+fn multiaddr_to_socketaddr(addr: &Multiaddr) -> Result<SocketAddr, ()> {
+    let mut iter = addr.iter();
+    let proto1 = iter.next().ok_or(())?;
+    let proto2 = iter.next().ok_or(())?;
+
+    if iter.next().is_some() {
+        return Err(());
+    }
+
+    match (proto1, proto2) {
+        (Protocol::Ip4(ip), Protocol::Udp(port)) => Ok(SocketAddr::new(ip.into(), port)),
+        (Protocol::Ip6(ip), Protocol::Udp(port)) => Ok(SocketAddr::new(ip.into(), port)),
+        _ => Err(()),
+    }
+}
+
+// Create a [`Multiaddr`] from the given IP address and port number.
+fn ip_to_multiaddr(ip: IpAddr, port: u16) -> Multiaddr {
+    let proto = match ip {
+        IpAddr::V4(ip) => Protocol::Ip4(ip),
+        IpAddr::V6(ip) => Protocol::Ip6(ip)
+    };
+    Multiaddr::empty().with(proto).with(Protocol::Udp(port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{multiaddr_to_socketaddr, Reliable, UdpConfig};
+    use bytes::Bytes;
+    use futures::prelude::*;
+    use libp2p_core::{identity, transport::{ListenerEvent, Transport}, upgrade};
+    use libp2p_plaintext::PlainText2Config;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    #[test]
+    fn multiaddr_to_udp_conversion() {
+        assert!(multiaddr_to_socketaddr(&"/ip4/127.0.0.1/tcp/1234".parse().unwrap()).is_err());
+        assert!(multiaddr_to_socketaddr(&"/ip4/127.0.0.1/udp/1234/quic".parse().unwrap()).is_err());
+
+        assert_eq!(
+            multiaddr_to_socketaddr(&"/ip4/127.0.0.1/udp/12345".parse().unwrap()),
+            Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 12345))
+        );
+        assert_eq!(
+            multiaddr_to_socketaddr(&"/ip6/::1/udp/12345".parse().unwrap()),
+            Ok(SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 12345))
+        );
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        async_std::task::block_on(async move {
+            let mut listener = UdpConfig::new().listen_on("/ip4/127.0.0.1/udp/0".parse().unwrap()).unwrap();
+            let addr = listener.next().await.unwrap().unwrap().into_new_address().unwrap();
+
+            let mut dialer = UdpConfig::new().dial(addr).unwrap().await.unwrap();
+            dialer.send(Bytes::from_static(b"ping")).await.unwrap();
+
+            let (upgrade, _) = listener.next().await.unwrap().unwrap().into_upgrade().unwrap();
+            let mut listener_conn = upgrade.await.unwrap();
+            assert_eq!(listener_conn.remote_addr().port(), dialer.local_addr().port());
+            assert_eq!(listener_conn.next().await.unwrap().unwrap(), Bytes::from_static(b"ping"));
+
+            listener_conn.send(Bytes::from_static(b"pong")).await.unwrap();
+            assert_eq!(dialer.next().await.unwrap().unwrap(), Bytes::from_static(b"pong"));
+
+            let too_large = Bytes::from(vec![0; dialer.max_message_size() + 1]);
+            assert!(dialer.send(too_large).await.is_err());
+        })
+    }
+
+    #[test]
+    fn reliable_connections_can_be_upgraded() {
+        let transport = UdpConfig::new().reliability(Reliable::new);
+        let listener_key = identity::Keypair::generate_ed25519();
+        let dialer_key = identity::Keypair::generate_ed25519();
+        let listener_peer = listener_key.public().into_peer_id();
+        let dialer_peer = dialer_key.public().into_peer_id();
+
+        async_std::task::block_on(async move {
+            let listener_transport = transport.clone().and_then(move |conn, endpoint| {
+                let plaintext = PlainText2Config { local_public_key: listener_key.public() };
+                upgrade::apply(conn, plaintext, endpoint, upgrade::Version::V1)
+            });
+            let dialer_transport = transport.and_then(move |conn, endpoint| {
+                let plaintext = PlainText2Config { local_public_key: dialer_key.public() };
+                upgrade::apply(conn, plaintext, endpoint, upgrade::Version::V1)
+            });
+
+            let mut listener = listener_transport.listen_on("/ip4/127.0.0.1/udp/0".parse().unwrap()).unwrap();
+            let addr = listener.next().await.unwrap().unwrap().into_new_address().unwrap();
+
+            let listener_fut = async {
+                let upgrade = loop {
+                    if let ListenerEvent::Upgrade { upgrade, .. } = listener.next().await.unwrap().unwrap() {
+                        break upgrade
+                    }
+                };
+                let (peer, mut output) = upgrade.await.unwrap();
+                assert_eq!(peer, dialer_peer);
+                let mut buf = vec![0; 10_000];
+                output.read_exact(&mut buf).await.unwrap();
+                output.write_all(&buf).await.unwrap();
+                output.flush().await.unwrap();
+            };
+
+            let dialer_fut = async {
+                let (peer, mut output) = dialer_transport.dial(addr).unwrap().await.unwrap();
+                assert_eq!(peer, listener_peer);
+                let data = (0 .. 10_000).map(|i| i as u8).collect::<Vec<_>>();
+                output.write_all(&data).await.unwrap();
+                output.flush().await.unwrap();
+                let mut buf = vec![0; 10_000];
+                output.read_exact(&mut buf).await.unwrap();
+                assert_eq!(buf, data);
+            };
+
+            future::join(listener_fut, dialer_fut).await;
+        })
+    }
+}
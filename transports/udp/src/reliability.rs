@@ -0,0 +1,328 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Strategies determining which messages of a connection are delivered.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use log::trace;
+use std::{collections::BTreeMap, io, time::{Duration, Instant}};
+
+/// A strategy applied to the messages of a connection.
+///
+/// Each connection has its own instance, which turns the messages sent by
+/// the application into datagrams and the datagrams received from the
+/// remote into messages. Both ends of a connection must use the same
+/// strategy.
+pub trait Reliability: Send + 'static {
+    /// The number of bytes added to each message, which are not available
+    /// to the application.
+    fn overhead(&self) -> usize {
+        0
+    }
+
+    /// Whether another message can be sent, or the strategy waits for
+    /// the remote to acknowledge messages first.
+    fn can_send(&self) -> bool {
+        true
+    }
+
+    /// Turns an outgoing message into the datagrams appended to `transmit`.
+    fn send(&mut self, now: Instant, message: Bytes, transmit: &mut Vec<Bytes>);
+
+    /// Handles a datagram of the remote, appending the messages to deliver
+    /// to `deliver` and the datagrams to send in response to `transmit`.
+    fn receive(&mut self, now: Instant, datagram: Bytes, deliver: &mut Vec<Bytes>, transmit: &mut Vec<Bytes>);
+
+    /// When `on_timeout` is to be called next, if at all.
+    fn timeout(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Handles the expiry of the timeout, e.g. by appending datagrams to
+    /// retransmit to `transmit`.
+    ///
+    /// An error fails the connection.
+    fn on_timeout(&mut self, _now: Instant, _transmit: &mut Vec<Bytes>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether all messages sent have been acknowledged by the remote.
+    fn is_flushed(&self) -> bool {
+        true
+    }
+}
+
+/// Sends every message as a single datagram, as is, delivering the
+/// datagrams of the remote that arrive in the order they arrive.
+#[derive(Debug, Clone, Default)]
+pub struct Unreliable;
+
+impl Reliability for Unreliable {
+    fn send(&mut self, _: Instant, message: Bytes, transmit: &mut Vec<Bytes>) {
+        transmit.push(message)
+    }
+
+    fn receive(&mut self, _: Instant, datagram: Bytes, deliver: &mut Vec<Bytes>, _: &mut Vec<Bytes>) {
+        deliver.push(datagram)
+    }
+}
+
+/// The datagram type of messages.
+const DATA: u8 = 0;
+/// The datagram type of acknowledgements.
+const ACK: u8 = 1;
+
+/// Delivers every message exactly once and in order, retransmitting the
+/// messages not acknowledged by the remote in time.
+///
+/// Messages are numbered and acknowledged individually. At most `window`
+/// messages are in flight, and the remote buffers at most as many messages
+/// received ahead of a missing one. The connection fails once a message has
+/// been retransmitted `max_retransmissions` times without acknowledgement.
+#[derive(Debug, Clone)]
+pub struct Reliable {
+    window: u64,
+    retransmission_timeout: Duration,
+    max_retransmissions: u32,
+    /// The sequence number of the next message sent.
+    next_send: u64,
+    /// The messages sent and not acknowledged yet, by sequence number.
+    unacked: BTreeMap<u64, Unacked>,
+    /// The sequence number of the next message to deliver.
+    next_deliver: u64,
+    /// The messages received ahead of `next_deliver`.
+    received: BTreeMap<u64, Bytes>
+}
+
+#[derive(Debug, Clone)]
+struct Unacked {
+    datagram: Bytes,
+    /// When to retransmit the datagram.
+    deadline: Instant,
+    retransmissions: u32
+}
+
+impl Reliable {
+    /// Creates the strategy with a window of 64 messages, an initial
+    /// retransmission timeout of 200 milliseconds and at most 8
+    /// retransmissions.
+    pub fn new() -> Self {
+        Reliable {
+            window: 64,
+            retransmission_timeout: Duration::from_millis(200),
+            max_retransmissions: 8,
+            next_send: 0,
+            unacked: BTreeMap::new(),
+            next_deliver: 0,
+            received: BTreeMap::new()
+        }
+    }
+
+    /// Sets the maximum number of messages in flight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` is 0.
+    pub fn window(mut self, value: u64) -> Self {
+        assert!(value > 0, "The window must not be empty");
+        self.window = value;
+        self
+    }
+
+    /// Sets how long to wait for an acknowledgement before the first
+    /// retransmission of a message, the timeout doubling with every
+    /// retransmission.
+    pub fn retransmission_timeout(mut self, value: Duration) -> Self {
+        self.retransmission_timeout = value;
+        self
+    }
+
+    /// Sets how often a message is retransmitted before the connection
+    /// fails.
+    pub fn max_retransmissions(mut self, value: u32) -> Self {
+        self.max_retransmissions = value;
+        self
+    }
+}
+
+impl Default for Reliable {
+    fn default() -> Self {
+        Reliable::new()
+    }
+}
+
+impl Reliability for Reliable {
+    fn overhead(&self) -> usize {
+        9
+    }
+
+    fn can_send(&self) -> bool {
+        (self.unacked.len() as u64) < self.window
+    }
+
+    fn send(&mut self, now: Instant, message: Bytes, transmit: &mut Vec<Bytes>) {
+        let mut datagram = BytesMut::with_capacity(message.len() + self.overhead());
+        datagram.put_u8(DATA);
+        datagram.put_u64(self.next_send);
+        datagram.put_slice(&message);
+        let datagram = datagram.freeze();
+        transmit.push(datagram.clone());
+        self.unacked.insert(self.next_send, Unacked {
+            datagram,
+            deadline: now + self.retransmission_timeout,
+            retransmissions: 0
+        });
+        self.next_send += 1
+    }
+
+    fn receive(&mut self, _: Instant, mut datagram: Bytes, deliver: &mut Vec<Bytes>, transmit: &mut Vec<Bytes>) {
+        match (datagram.first(), datagram.len()) {
+            (Some(&DATA), len) if len >= 9 => {
+                datagram.advance(1);
+                let seq = datagram.get_u64();
+                // Messages beyond the window are dropped without acknowledgement.
+                if seq >= self.next_deliver + self.window {
+                    trace!("Dropping message {} beyond the window", seq);
+                    return
+                }
+                if seq >= self.next_deliver {
+                    self.received.entry(seq).or_insert(datagram);
+                    while let Some(message) = self.received.remove(&self.next_deliver) {
+                        deliver.push(message);
+                        self.next_deliver += 1
+                    }
+                }
+                // Duplicates are acknowledged again, as the acknowledgement
+                // may have been lost.
+                let mut ack = BytesMut::with_capacity(17);
+                ack.put_u8(ACK);
+                ack.put_u64(self.next_deliver);
+                ack.put_u64(seq);
+                transmit.push(ack.freeze())
+            }
+            (Some(&ACK), 17) => {
+                datagram.advance(1);
+                let cumulative = datagram.get_u64();
+                let seq = datagram.get_u64();
+                self.unacked = self.unacked.split_off(&cumulative);
+                self.unacked.remove(&seq);
+            }
+            _ => trace!("Dropping malformed datagram")
+        }
+    }
+
+    fn timeout(&self) -> Option<Instant> {
+        self.unacked.values().map(|unacked| unacked.deadline).min()
+    }
+
+    fn on_timeout(&mut self, now: Instant, transmit: &mut Vec<Bytes>) -> io::Result<()> {
+        for (seq, unacked) in self.unacked.iter_mut().filter(|(_, unacked)| unacked.deadline <= now) {
+            if unacked.retransmissions >= self.max_retransmissions {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "message not acknowledged"))
+            }
+            unacked.retransmissions += 1;
+            let backoff = 1 << unacked.retransmissions.min(16);
+            unacked.deadline = now + self.retransmission_timeout * backoff;
+            trace!("Retransmitting message {}", seq);
+            transmit.push(unacked.datagram.clone())
+        }
+        Ok(())
+    }
+
+    fn is_flushed(&self) -> bool {
+        self.unacked.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Transfers messages between two instances, dropping the datagrams for
+    /// which `lose` returns true.
+    fn transfer(messages: &[Bytes], mut lose: impl FnMut() -> bool) -> Vec<Bytes> {
+        let (mut sender, mut receiver) = (Reliable::new().window(4), Reliable::new().window(4));
+        let mut now = Instant::now();
+        let (mut to_receiver, mut to_sender, mut delivered) = (Vec::new(), Vec::new(), Vec::new());
+        let mut pending = messages.iter().cloned();
+        let mut next = pending.next();
+
+        while next.is_some() || !sender.is_flushed() {
+            while let Some(message) = next.take() {
+                if !sender.can_send() {
+                    next = Some(message);
+                    break
+                }
+                sender.send(now, message, &mut to_receiver);
+                next = pending.next()
+            }
+            for datagram in std::mem::take(&mut to_receiver) {
+                if !lose() {
+                    receiver.receive(now, datagram, &mut delivered, &mut to_sender)
+                }
+            }
+            for datagram in std::mem::take(&mut to_sender) {
+                if !lose() {
+                    sender.receive(now, datagram, &mut Vec::new(), &mut to_receiver)
+                }
+            }
+            if let Some(deadline) = sender.timeout() {
+                now = now.max(deadline);
+                sender.on_timeout(now, &mut to_receiver).unwrap()
+            }
+        }
+        delivered
+    }
+
+    #[test]
+    fn delivers_in_order_despite_loss() {
+        let messages = (0 .. 100u8).map(|i| Bytes::from(vec![i; i as usize])).collect::<Vec<_>>();
+        assert_eq!(transfer(&messages, || false), messages);
+
+        let mut count = 0;
+        assert_eq!(transfer(&messages, || { count += 1; count % 3 == 0 }), messages);
+    }
+
+    #[test]
+    fn fails_after_max_retransmissions() {
+        let mut sender = Reliable::new().max_retransmissions(2);
+        let mut now = Instant::now();
+        let mut transmit = Vec::new();
+        sender.send(now, Bytes::from_static(b"lost"), &mut transmit);
+        for _ in 0 .. 2 {
+            now = sender.timeout().unwrap();
+            sender.on_timeout(now, &mut transmit).unwrap();
+        }
+        assert_eq!(transmit.len(), 3);
+        now = sender.timeout().unwrap();
+        assert_eq!(sender.on_timeout(now, &mut transmit).unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn malformed_datagrams_are_ignored() {
+        let mut receiver = Reliable::new();
+        let (mut deliver, mut transmit) = (Vec::new(), Vec::new());
+        for datagram in &[&b""[..], &[DATA, 0, 0], &[ACK, 0], &[7; 20]] {
+            receiver.receive(Instant::now(), Bytes::copy_from_slice(datagram), &mut deliver, &mut transmit);
+        }
+        assert!(deliver.is_empty());
+        assert!(transmit.is_empty());
+    }
+}
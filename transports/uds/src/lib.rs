@@ -28,6 +28,9 @@
 //!
 //! The `UdsConfig` transport supports multiaddresses of the form `/unix//tmp/foo`.
 //!
+//! On Linux, paths starting with `@`, e.g. `/unix/@foo`, designate sockets in the abstract
+//! namespace. These are not backed by a file and disappear as soon as they are closed.
+//!
 //! Example:
 //!
 //! ```
@@ -35,7 +38,9 @@
 //! use libp2p_uds::UdsConfig;
 //!
 //! # fn main() {
-//! let uds = UdsConfig::new();
+//! let uds = UdsConfig::new()
+//!     .permissions(0o660)
+//!     .remove_stale(true);
 //! # }
 //! ```
 //!
@@ -53,17 +58,87 @@ use libp2p_core::{
     transport::{ListenerEvent, TransportError}
 };
 use log::debug;
-use std::{io, path::PathBuf};
+use std::{fs, io, os::unix::{fs::{FileTypeExt, PermissionsExt}, net as std_net}, path::{Path, PathBuf}};
 
 /// Represents the configuration for a Unix domain sockets transport capability for libp2p.
 #[derive(Debug, Clone)]
 pub struct UdsConfig {
+    /// Permissions to set on the socket file of listeners.
+    permissions: Option<u32>,
+    /// User ID to set as owner of the socket file of listeners.
+    uid: Option<u32>,
+    /// Group ID to set as owner of the socket file of listeners.
+    gid: Option<u32>,
+    /// Whether to remove socket files nobody listens on before binding.
+    remove_stale: bool,
 }
 
 impl UdsConfig {
     /// Creates a new configuration object for Unix domain sockets.
     pub fn new() -> UdsConfig {
-        UdsConfig {}
+        UdsConfig {
+            permissions: None,
+            uid: None,
+            gid: None,
+            remove_stale: false,
+        }
+    }
+
+    /// Sets the permissions of the socket file created when listening, e.g. `0o600` to only
+    /// allow the current user to connect.
+    ///
+    /// The permissions are applied right after binding, so that the socket is briefly
+    /// accessible with the permissions given by the umask of the process. Has no effect on
+    /// sockets in the abstract namespace.
+    pub fn permissions(mut self, mode: u32) -> Self {
+        self.permissions = Some(mode);
+        self
+    }
+
+    /// Sets the owning user and group of the socket file created when listening. `None`
+    /// leaves the respective ID unchanged.
+    ///
+    /// Changing the owner usually requires elevated privileges. Has no effect on sockets in
+    /// the abstract namespace.
+    pub fn owner(mut self, uid: Option<u32>, gid: Option<u32>) -> Self {
+        self.uid = uid;
+        self.gid = gid;
+        self
+    }
+
+    /// Whether to remove a socket file left behind by a previous listener before binding.
+    ///
+    /// A socket file is only removed if connecting to it fails with
+    /// `ConnectionRefused`, i.e. if nobody listens on it anymore. Binding to a socket that is
+    /// still in use fails with `AddrInUse` either way. Defaults to `false`.
+    pub fn remove_stale(mut self, remove: bool) -> Self {
+        self.remove_stale = remove;
+        self
+    }
+
+    /// Binds a listener to the given socket path and applies the configuration.
+    fn bind(&self, path: &SocketPath) -> io::Result<std_net::UnixListener> {
+        match path {
+            SocketPath::File(path) => {
+                if self.remove_stale {
+                    remove_stale_socket(path)?
+                }
+                let listener = std_net::UnixListener::bind(path)?;
+                if let Some(mode) = self.permissions {
+                    fs::set_permissions(path, fs::Permissions::from_mode(mode))?
+                }
+                if self.uid.is_some() || self.gid.is_some() {
+                    std::os::unix::fs::chown(path, self.uid, self.gid)?
+                }
+                Ok(listener)
+            }
+            #[cfg(target_os = "linux")]
+            SocketPath::Abstract(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std_net::SocketAddr::from_abstract_name(name)?;
+                std_net::UnixListener::bind_addr(&addr)
+            }
+        }
     }
 }
 
@@ -76,7 +151,7 @@ impl Transport for UdsConfig {
 
     fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
         if let Ok(path) = multiaddr_to_path(&addr) {
-            Ok(async move { UnixListener::bind(&path).await }
+            Ok(async_std::task::spawn_blocking(move || self.bind(&path).map(UnixListener::from))
                 .map_ok(move |listener| {
                     stream::once({
                         let addr = addr.clone();
@@ -109,21 +184,41 @@ impl Transport for UdsConfig {
     }
 
     fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
-        if let Ok(path) = multiaddr_to_path(&addr) {
-            debug!("Dialing {}", addr);
-            Ok(async move { UnixStream::connect(&path).await }.boxed())
-        } else {
-            Err(TransportError::MultiaddrNotSupported(addr))
+        match multiaddr_to_path(&addr) {
+            Ok(SocketPath::File(path)) => {
+                debug!("Dialing {}", addr);
+                Ok(async move { UnixStream::connect(&path).await }.boxed())
+            }
+            #[cfg(target_os = "linux")]
+            Ok(SocketPath::Abstract(name)) => {
+                use std::os::linux::net::SocketAddrExt;
+                debug!("Dialing {}", addr);
+                Ok(async_std::task::spawn_blocking(move || {
+                    let addr = std_net::SocketAddr::from_abstract_name(&name)?;
+                    std_net::UnixStream::connect_addr(&addr).map(UnixStream::from)
+                }).boxed())
+            }
+            Err(()) => Err(TransportError::MultiaddrNotSupported(addr))
         }
     }
 }
 
-/// Turns a `Multiaddr` containing a single `Unix` component into a path.
+/// The location of a Unix domain socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SocketPath {
+    /// A socket file at an absolute path.
+    File(PathBuf),
+    /// A name in the abstract namespace of Linux.
+    #[cfg(target_os = "linux")]
+    Abstract(Vec<u8>),
+}
+
+/// Turns a `Multiaddr` containing a single `Unix` component into a socket path.
 ///
 /// Also returns an error if the path is not absolute, as we don't want to dial/listen on relative
-/// paths.
+/// paths. On Linux, paths starting with `@` designate names in the abstract namespace.
 // This type of logic should probably be moved into the multiaddr package
-fn multiaddr_to_path(addr: &Multiaddr) -> Result<PathBuf, ()> {
+fn multiaddr_to_path(addr: &Multiaddr) -> Result<SocketPath, ()> {
     let mut iter = addr.iter();
     let path = iter.next();
 
@@ -131,23 +226,53 @@ fn multiaddr_to_path(addr: &Multiaddr) -> Result<PathBuf, ()> {
         return Err(());
     }
 
-    let out: PathBuf = match path {
-        Some(Protocol::Unix(ref path)) => path.as_ref().into(),
+    let path = match path {
+        Some(Protocol::Unix(path)) => path,
         _ => return Err(())
     };
 
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(name) = path.strip_prefix('@') {
+            if name.is_empty() {
+                return Err(());
+            }
+            return Ok(SocketPath::Abstract(name.as_bytes().to_vec()));
+        }
+    }
+
+    let out = PathBuf::from(path.as_ref());
     if !out.is_absolute() {
         return Err(());
     }
 
-    Ok(out)
+    Ok(SocketPath::File(out))
+}
+
+/// Removes the socket file at `path` if nobody listens on it anymore.
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {}
+        // Other files are left alone and binding fails.
+        Ok(_) => return Ok(()),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err)
+    }
+    match std_net::UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(io::ErrorKind::AddrInUse, "socket is in use")),
+        Err(ref err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+            debug!("Removing stale socket {}", path.display());
+            fs::remove_file(path)
+        }
+        Err(err) => Err(err)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{multiaddr_to_path, UdsConfig};
+    use super::{multiaddr_to_path, SocketPath, UdsConfig};
     use futures::{channel::oneshot, prelude::*};
-    use std::{self, borrow::Cow, path::Path};
+    use std::{self, borrow::Cow, fs, os::unix::fs::PermissionsExt, path::Path};
     use libp2p_core::{Transport, multiaddr::{Protocol, Multiaddr}};
     use tempfile;
 
@@ -160,14 +285,77 @@ mod tests {
 
         assert_eq!(
             multiaddr_to_path(&Multiaddr::from(Protocol::Unix("/tmp/foo".into()))),
-            Ok(Path::new("/tmp/foo").to_owned())
+            Ok(SocketPath::File(Path::new("/tmp/foo").to_owned()))
         );
         assert_eq!(
             multiaddr_to_path(&Multiaddr::from(Protocol::Unix("/home/bar/baz".into()))),
-            Ok(Path::new("/home/bar/baz").to_owned())
+            Ok(SocketPath::File(Path::new("/home/bar/baz").to_owned()))
+        );
+        assert!(
+            multiaddr_to_path(&Multiaddr::from(Protocol::Unix("foo".into())))
+                .is_err()
         );
     }
 
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn abstract_namespace() {
+        let name = format!("@libp2p-uds-test-{}", std::process::id());
+        let addr = Multiaddr::from(Protocol::Unix(name.clone().into()));
+        assert_eq!(
+            multiaddr_to_path(&addr),
+            Ok(SocketPath::Abstract(name.as_bytes()[1..].to_vec()))
+        );
+        assert!(multiaddr_to_path(&Multiaddr::from(Protocol::Unix("@".into()))).is_err());
+
+        async_std::task::block_on(async move {
+            let mut listener = UdsConfig::new().listen_on(addr.clone()).unwrap();
+            let listen_addr = listener.try_next().await.unwrap()
+                .expect("some event")
+                .into_new_address()
+                .expect("listen address");
+            assert_eq!(listen_addr, addr);
+
+            let (upgrade, dial) = future::join(
+                listener.try_filter_map(|e| future::ok(e.into_upgrade())).try_next(),
+                UdsConfig::new().dial(addr).unwrap()
+            ).await;
+            let mut sock = upgrade.unwrap().expect("some event").0.await.unwrap();
+            dial.unwrap().write_all(&[1, 2, 3]).await.unwrap();
+            let mut buf = [0u8; 3];
+            sock.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [1, 2, 3]);
+        });
+    }
+
+    #[test]
+    fn permissions_and_stale_sockets() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket = temp_dir.path().join("socket");
+        let addr = Multiaddr::from(Protocol::Unix(Cow::Owned(socket.to_string_lossy().into_owned())));
+        let config = UdsConfig::new().permissions(0o600).remove_stale(true);
+
+        async_std::task::block_on(async move {
+            let mut listener = config.clone().listen_on(addr.clone()).unwrap();
+            listener.try_next().await.unwrap().expect("some event");
+            let mode = fs::metadata(&socket).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+
+            // The socket is in use and must not be removed.
+            let mut second = config.clone().listen_on(addr.clone()).unwrap();
+            assert!(second.try_next().await.is_err());
+            assert!(socket.exists());
+
+            // The socket file outlives the listener and is removed when binding again.
+            drop(listener);
+            assert!(socket.exists());
+            let mut stale = UdsConfig::new().listen_on(addr.clone()).unwrap();
+            assert!(stale.try_next().await.is_err());
+            let mut third = config.listen_on(addr).unwrap();
+            assert!(third.try_next().await.unwrap().is_some());
+        });
+    }
+
     #[test]
     fn communicating_between_dialer_and_listener() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -202,7 +390,7 @@ mod tests {
             let uds = UdsConfig::new();
             let addr = rx.await.unwrap();
             let mut socket = uds.dial(addr).unwrap().await.unwrap();
-            socket.write(&[1, 2, 3]).await.unwrap();
+            socket.write_all(&[1, 2, 3]).await.unwrap();
         });
     }
 
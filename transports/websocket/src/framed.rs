@@ -26,8 +26,8 @@ use futures::{future::BoxFuture, prelude::*, ready, stream::BoxStream};
 use libp2p_core::{
     Transport,
     either::EitherOutput,
-    multiaddr::{Protocol, Multiaddr},
-    transport::{ListenerEvent, TransportError}
+    multiaddr::{Protocol, Multiaddr, multihash::Multihash},
+    transport::{ListenerEvent, TransportError, TransportEvent, TransportEventKind, TransportEvents}
 };
 use log::{debug, trace};
 use soketto::{connection, data, extension::deflate::Deflate, handshake};
@@ -37,16 +37,23 @@ use url::Url;
 /// Max. number of payload bytes of a single frame.
 const MAX_DATA_SIZE: usize = 256 * 1024 * 1024;
 
+/// DNS name used to dial IP addresses over TLS, which requires a name even
+/// though SNI is disabled and the certificate is checked against its hash.
+const CERTHASH_DNS_NAME: &str = "localhost";
+
 /// A Websocket transport whose output type is a [`Stream`] and [`Sink`] of
 /// frame payloads which does not implement [`AsyncRead`] or
 /// [`AsyncWrite`]. See [`crate::WsConfig`] if you require the latter.
 #[derive(Debug, Clone)]
 pub struct WsConfig<T> {
     transport: T,
-    max_data_size: usize,
+    max_frame_size: usize,
+    max_message_size: usize,
+    split_message_size: Option<usize>,
     tls_config: tls::Config,
     max_redirects: u8,
-    use_deflate: bool
+    use_deflate: bool,
+    events: Option<TransportEvents>
 }
 
 impl<T> WsConfig<T> {
@@ -54,10 +61,13 @@ impl<T> WsConfig<T> {
     pub fn new(transport: T) -> Self {
         WsConfig {
             transport,
-            max_data_size: MAX_DATA_SIZE,
+            max_frame_size: MAX_DATA_SIZE,
+            max_message_size: MAX_DATA_SIZE,
+            split_message_size: None,
             tls_config: tls::Config::client(),
             max_redirects: 0,
-            use_deflate: false
+            use_deflate: false,
+            events: None
         }
     }
 
@@ -74,12 +84,58 @@ impl<T> WsConfig<T> {
 
     /// Get the max. frame data size we support.
     pub fn max_data_size(&self) -> usize {
-        self.max_data_size
+        self.max_frame_size
     }
 
-    /// Set the max. frame data size we support.
+    /// Set the max. frame data size and the max. message size we support.
     pub fn set_max_data_size(&mut self, size: usize) -> &mut Self {
-        self.max_data_size = size;
+        self.max_frame_size = size;
+        self.max_message_size = size;
+        self
+    }
+
+    /// Get the max. payload size of a single frame we accept.
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Set the max. payload size of a single frame we accept.
+    pub fn set_max_frame_size(&mut self, size: usize) -> &mut Self {
+        self.max_frame_size = size;
+        self
+    }
+
+    /// Get the max. size of a complete, possibly fragmented, message we accept.
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size
+    }
+
+    /// Set the max. size of a complete, possibly fragmented, message we accept.
+    pub fn set_max_message_size(&mut self, size: usize) -> &mut Self {
+        self.max_message_size = size;
+        self
+    }
+
+    /// Get the size above which outgoing binary data is split into
+    /// separate messages, if any.
+    pub fn split_message_size(&self) -> Option<usize> {
+        self.split_message_size
+    }
+
+    /// Split outgoing binary data into separate messages of at most `size`
+    /// bytes each.
+    ///
+    /// Some browsers and reverse proxies reject large frames. Every chunk is
+    /// sent as a complete binary message of its own, i.e. no RFC 6455
+    /// continuation frames are used, so receivers of the framed transport
+    /// must not rely on message boundaries if this is set. Disabled by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is `Some(0)`.
+    pub fn set_split_message_size(&mut self, size: Option<usize>) -> &mut Self {
+        assert_ne!(size, Some(0), "split message size must be positive");
+        self.split_message_size = size;
         self
     }
 
@@ -90,10 +146,18 @@ impl<T> WsConfig<T> {
     }
 
     /// Should the deflate extension (RFC 7692) be used if supported?
+    ///
+    /// This is the only way to enable permessage-deflate compression.
     pub fn use_deflate(&mut self, flag: bool) -> &mut Self {
         self.use_deflate = flag;
         self
     }
+
+    /// Publish failed TLS handshakes of dials to the given event bus.
+    pub fn set_events(&mut self, events: TransportEvents) -> &mut Self {
+        self.events = Some(events);
+        self
+    }
 }
 
 type TlsOrPlain<T> = EitherOutput<EitherOutput<client::TlsStream<T>, server::TlsStream<T>>, T>;
@@ -114,6 +178,7 @@ where
     type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
     fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let addr = normalise_tls(addr);
         let mut inner_addr = addr.clone();
 
         let (use_tls, proto) = match inner_addr.pop() {
@@ -132,7 +197,9 @@ where
         };
 
         let tls_config = self.tls_config;
-        let max_size = self.max_data_size;
+        let max_frame_size = self.max_frame_size;
+        let max_message_size = self.max_message_size;
+        let split_message_size = self.split_message_size;
         let use_deflate = self.use_deflate;
         let transport = self.transport.listen_on(inner_addr).map_err(|e| e.map(Error::Transport))?;
         let listen = transport
@@ -210,9 +277,9 @@ where
 
                         let conn = {
                             let mut builder = server.into_builder();
-                            builder.set_max_message_size(max_size);
-                            builder.set_max_frame_size(max_size);
-                            Connection::new(builder)
+                            builder.set_max_message_size(max_message_size);
+                            builder.set_max_frame_size(max_frame_size);
+                            Connection::new(builder, split_message_size)
                         };
 
                         Ok(conn)
@@ -229,12 +296,15 @@ where
     }
 
     fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let addr = normalise_tls(addr);
         // Quick sanity check of the provided Multiaddr.
-        if let Some(Protocol::Ws(_)) | Some(Protocol::Wss(_)) = addr.iter().last() {
-            // ok
-        } else {
-            debug!("{} is not a websocket multiaddr", addr);
-            return Err(TransportError::MultiaddrNotSupported(addr))
+        let mut ws_addr = addr.clone();
+        match (split_certhashes(&mut ws_addr).is_empty(), ws_addr.iter().last()) {
+            (true, Some(Protocol::Ws(_))) | (_, Some(Protocol::Wss(_))) => {}
+            _ => {
+                debug!("{} is not a websocket multiaddr", addr);
+                return Err(TransportError::MultiaddrNotSupported(addr))
+            }
         }
 
         // We are looping here in order to follow redirects (if any):
@@ -271,17 +341,21 @@ where
     async fn dial_once(self, address: Multiaddr) -> Result<Either<String, Connection<T::Output>>, Error<T::Error>> {
         trace!("dial address: {}", address);
 
-        let (host_port, dns_name) = host_and_dnsname(&address)?;
+        let (host_port, mut dns_name) = host_and_dnsname(&address)?;
 
         let mut inner_addr = address.clone();
+        let certhashes = split_certhashes(&mut inner_addr);
 
         let (use_tls, path) =
             match inner_addr.pop() {
-                Some(Protocol::Ws(path)) => (false, path),
+                Some(Protocol::Ws(path)) if certhashes.is_empty() => (false, path),
                 Some(Protocol::Wss(path)) => {
                     if dns_name.is_none() {
-                        debug!("no DNS name in {}", address);
-                        return Err(Error::InvalidMultiaddr(address))
+                        if certhashes.is_empty() || !self.tls_config.accepts_certhash() {
+                            debug!("no DNS name in {}", address);
+                            return Err(Error::InvalidMultiaddr(address))
+                        }
+                        dns_name = Some(tls::dns_name_ref(CERTHASH_DNS_NAME)?.to_owned())
                     }
                     (true, path)
                 }
@@ -304,7 +378,8 @@ where
             if use_tls { // begin TLS session
                 let dns_name = dns_name.expect("for use_tls we have checked that dns_name is some");
                 trace!("starting TLS handshake with {}", address);
-                let stream = self.tls_config.client.connect(&dns_name, stream)
+                let events = &self.events;
+                let stream = self.tls_config.connector(&certhashes).connect(&dns_name, stream)
                     .map_err(|e| {
                         // We should never enter here as we passed a `DNSNameRef` to `connect`.
                         debug!("invalid domain name: {:?}", dns_name);
@@ -312,6 +387,13 @@ where
                     })?
                     .map_err(|e| {
                         debug!("TLS handshake with {} failed: {}", address, e);
+                        if let Some(events) = events {
+                            events.publish(TransportEvent {
+                                transport: "websocket",
+                                address: address.clone(),
+                                kind: TransportEventKind::TlsFailed { error: e.to_string() }
+                            })
+                        }
                         Error::Tls(tls::Error::from(e))
                     })
                     .await?;
@@ -341,7 +423,10 @@ where
             }
             handshake::ServerResponse::Accepted { .. } => {
                 trace!("websocket handshake with {} successful", address);
-                Ok(Either::Right(Connection::new(client.into_builder())))
+                let mut builder = client.into_builder();
+                builder.set_max_message_size(self.max_message_size);
+                builder.set_max_frame_size(self.max_frame_size);
+                Ok(Either::Right(Connection::new(builder, self.split_message_size)))
             }
         }
     }
@@ -355,6 +440,8 @@ fn host_and_dnsname<T>(addr: &Multiaddr) -> Result<(String, Option<webpki::DNSNa
             Ok((format!("{}:{}", ip, port), None)),
         (Some(Protocol::Ip6(ip)), Some(Protocol::Tcp(port))) =>
             Ok((format!("{}:{}", ip, port), None)),
+        (Some(Protocol::Dns(h)), Some(Protocol::Tcp(port))) =>
+            Ok((format!("{}:{}", &h, port), Some(tls::dns_name_ref(&h)?.to_owned()))),
         (Some(Protocol::Dns4(h)), Some(Protocol::Tcp(port))) =>
             Ok((format!("{}:{}", &h, port), Some(tls::dns_name_ref(&h)?.to_owned()))),
         (Some(Protocol::Dns6(h)), Some(Protocol::Tcp(port))) =>
@@ -366,6 +453,34 @@ fn host_and_dnsname<T>(addr: &Multiaddr) -> Result<(String, Option<webpki::DNSNa
     }
 }
 
+// Remove the trailing `/certhash` components of the given [`Multiaddr`] and return their values.
+fn split_certhashes(addr: &mut Multiaddr) -> Vec<Multihash> {
+    let mut hashes = Vec::new();
+    while let Some(Protocol::Certhash(_)) = addr.iter().last() {
+        if let Some(Protocol::Certhash(hash)) = addr.pop() {
+            hashes.push(hash)
+        }
+    }
+    hashes.reverse();
+    hashes
+}
+
+// Rewrite a trailing `/tls/ws` (possibly followed by `/certhash`es) into the
+// equivalent `/wss`, which is what the rest of this module operates on.
+fn normalise_tls(mut addr: Multiaddr) -> Multiaddr {
+    let certhashes = split_certhashes(&mut addr);
+    let mut iter = addr.iter().collect::<Vec<_>>().into_iter().rev();
+    if let (Some(Protocol::Ws(path)), Some(Protocol::Tls)) = (iter.next(), iter.next()) {
+        let mut a = iter.rev().collect::<Multiaddr>();
+        a.push(Protocol::Wss(path));
+        addr = a
+    }
+    for hash in certhashes {
+        addr.push(Protocol::Certhash(hash))
+    }
+    addr
+}
+
 // Given a location URL, build a new websocket [`Multiaddr`].
 fn location_to_multiaddr<T>(location: &str) -> Result<Multiaddr, Error<T>> {
     match Url::parse(location) {
@@ -461,12 +576,17 @@ impl<T> Connection<T>
 where
     T: AsyncRead + AsyncWrite + Send + Unpin + 'static
 {
-    fn new(builder: connection::Builder<TlsOrPlain<T>>) -> Self {
+    fn new(builder: connection::Builder<TlsOrPlain<T>>, split_message_size: Option<usize>) -> Self {
         let (sender, receiver) = builder.finish();
-        let sink = quicksink::make_sink(sender, |mut sender, action| async move {
+        let sink = quicksink::make_sink(sender, move |mut sender, action| async move {
             match action {
-                quicksink::Action::Send(OutgoingData::Binary(x)) => {
-                    sender.send_binary_mut(x).await?
+                quicksink::Action::Send(OutgoingData::Binary(mut x)) => match split_message_size {
+                    Some(n) if x.len() > n => {
+                        for chunk in x.chunks_mut(n) {
+                            sender.send_binary_mut(chunk).await?
+                        }
+                    }
+                    _ => sender.send_binary_mut(x).await?
                 }
                 quicksink::Action::Send(OutgoingData::Ping(x)) => {
                     let data = x.as_ref().try_into().map_err(|_| {
@@ -554,3 +674,22 @@ where
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::normalise_tls;
+
+    #[test]
+    fn tls_ws_is_wss() {
+        let cases = [
+            ("/dns4/example.com/tcp/443/tls/ws", "/dns4/example.com/tcp/443/wss"),
+            ("/ip4/127.0.0.1/tcp/443/tls/ws/certhash/uEiDVLruJ2FsCooSUggOmL_KDicV8n0K-7E7CDbdqaJEcCw",
+             "/ip4/127.0.0.1/tcp/443/wss/certhash/uEiDVLruJ2FsCooSUggOmL_KDicV8n0K-7E7CDbdqaJEcCw"),
+            ("/ip4/127.0.0.1/tcp/80/ws", "/ip4/127.0.0.1/tcp/80/ws"),
+            ("/ip4/127.0.0.1/tcp/443/wss", "/ip4/127.0.0.1/tcp/443/wss"),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(normalise_tls(input.parse().unwrap()), expected.parse().unwrap())
+        }
+    }
+}
@@ -19,6 +19,10 @@
 // DEALINGS IN THE SOFTWARE.
 
 //! Implementation of the libp2p `Transport` trait for Websockets.
+//!
+//! Dials are tunneled through an HTTP proxy by configuring the underlying `TcpConfig` with
+//! `TcpConfig::http_proxy`. The names of `/dns` addresses are then resolved by the proxy, and
+//! the TLS session of `/wss` addresses is established end-to-end through the tunnel.
 
 pub mod error;
 pub mod framed;
@@ -32,7 +36,7 @@ use libp2p_core::{
     ConnectedPoint,
     Transport,
     multiaddr::Multiaddr,
-    transport::{map::{MapFuture, MapStream}, ListenerEvent, TransportError}
+    transport::{map::{MapFuture, MapStream}, ListenerEvent, TransportError, TransportEvents}
 };
 use rw_stream_sink::RwStreamSink;
 use std::{io, pin::Pin, task::{Context, Poll}};
@@ -65,12 +69,50 @@ impl<T> WsConfig<T> {
         self.transport.max_data_size()
     }
 
-    /// Set the max. frame data size we support.
+    /// Set the max. frame data size and the max. message size we support.
     pub fn set_max_data_size(&mut self, size: usize) -> &mut Self {
         self.transport.set_max_data_size(size);
         self
     }
 
+    /// Get the max. payload size of a single frame we accept.
+    pub fn max_frame_size(&self) -> usize {
+        self.transport.max_frame_size()
+    }
+
+    /// Set the max. payload size of a single frame we accept.
+    pub fn set_max_frame_size(&mut self, size: usize) -> &mut Self {
+        self.transport.set_max_frame_size(size);
+        self
+    }
+
+    /// Get the max. size of a complete, possibly fragmented, message we accept.
+    pub fn max_message_size(&self) -> usize {
+        self.transport.max_message_size()
+    }
+
+    /// Set the max. size of a complete, possibly fragmented, message we accept.
+    pub fn set_max_message_size(&mut self, size: usize) -> &mut Self {
+        self.transport.set_max_message_size(size);
+        self
+    }
+
+    /// Get the size above which writes are split into separate messages, if any.
+    pub fn split_message_size(&self) -> Option<usize> {
+        self.transport.split_message_size()
+    }
+
+    /// Split writes into separate binary messages of at most `size` bytes,
+    /// for peers and proxies rejecting large frames. Disabled by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is `Some(0)`.
+    pub fn set_split_message_size(&mut self, size: Option<usize>) -> &mut Self {
+        self.transport.set_split_message_size(size);
+        self
+    }
+
     /// Set the TLS configuration if TLS support is desired.
     pub fn set_tls_config(&mut self, c: tls::Config) -> &mut Self {
         self.transport.set_tls_config(c);
@@ -78,10 +120,18 @@ impl<T> WsConfig<T> {
     }
 
     /// Should the deflate extension (RFC 7692) be used if supported?
+    ///
+    /// This is the only way to enable permessage-deflate compression.
     pub fn use_deflate(&mut self, flag: bool) -> &mut Self {
         self.transport.use_deflate(flag);
         self
     }
+
+    /// Publish failed TLS handshakes of dials to the given event bus.
+    pub fn set_events(&mut self, events: TransportEvents) -> &mut Self {
+        self.transport.set_events(events);
+        self
+    }
 }
 
 impl<T> From<framed::WsConfig<T>> for WsConfig<T> {
@@ -202,6 +252,48 @@ mod tests {
         futures::executor::block_on(connect(a))
     }
 
+    #[test]
+    fn large_writes_are_split() {
+        let mut ws_config = WsConfig::new(tcp::TcpConfig::new());
+        ws_config.set_max_frame_size(1024).set_split_message_size(Some(1024));
+
+        futures::executor::block_on(async move {
+            let mut listener = ws_config.clone()
+                .listen_on("/ip4/127.0.0.1/tcp/0/ws".parse().unwrap())
+                .expect("listener");
+
+            let addr = listener.try_next().await
+                .expect("some event")
+                .expect("no error")
+                .into_new_address()
+                .expect("listen address");
+
+            let data = (0 .. 10_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+            let expected = data.clone();
+
+            let inbound = async move {
+                let (conn, _addr) = listener.try_filter_map(|e| future::ready(Ok(e.into_upgrade())))
+                    .try_next()
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let mut conn = conn.await.unwrap();
+                let mut buf = vec![0; expected.len()];
+                conn.read_exact(&mut buf).await.unwrap();
+                assert_eq!(buf, expected);
+            };
+
+            let outbound = async move {
+                let mut conn = ws_config.dial(addr).unwrap().await.unwrap();
+                conn.write_all(&data).await.unwrap();
+                conn.flush().await.unwrap();
+                conn
+            };
+
+            let ((), _conn) = futures::join!(inbound, outbound);
+        })
+    }
+
     async fn connect(listen_addr: Multiaddr) {
         let ws_config = WsConfig::new(tcp::TcpConfig::new());
 
@@ -232,4 +324,99 @@ mod tests {
         let (a, b) = futures::join!(inbound, outbound);
         a.and(b).unwrap();
     }
+    #[test]
+    fn dialing_through_http_proxy() {
+        use async_std::net::{TcpListener, TcpStream};
+
+        async_std::task::block_on(async {
+            let mut listener = WsConfig::new(tcp::TcpConfig::new())
+                .listen_on("/ip4/127.0.0.1/tcp/0/ws".parse().unwrap())
+                .expect("listener");
+            let port = match listener.try_next().await.unwrap().unwrap().into_new_address() {
+                Some(addr) => match addr.iter().nth(1) {
+                    Some(Protocol::Tcp(port)) => port,
+                    _ => panic!("Unexpected listen address: {}", addr)
+                },
+                None => panic!("Expected a listen address")
+            };
+
+            // A proxy tunneling the first `CONNECT` request to the listener.
+            let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", proxy.local_addr().unwrap());
+            let proxy = async_std::task::spawn(async move {
+                let (mut client, _) = proxy.accept().await.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0];
+                    client.read_exact(&mut byte).await.unwrap();
+                    request.push(byte[0]);
+                }
+                let expected = format!("CONNECT localhost:{} HTTP/1.1\r\n", port);
+                assert!(request.starts_with(expected.as_bytes()));
+
+                let server = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+                client.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+                let (client_read, mut client_write) = client.split();
+                let (server_read, mut server_write) = server.split();
+                let _ = future::join(
+                    futures::io::copy(client_read, &mut server_write),
+                    futures::io::copy(server_read, &mut client_write)
+                ).await;
+            });
+
+            let inbound = async move {
+                let (conn, _addr) = listener.try_filter_map(|e| future::ready(Ok(e.into_upgrade())))
+                    .try_next()
+                    .await
+                    .unwrap()
+                    .unwrap();
+                conn.await.unwrap()
+            };
+
+            let proxied = tcp::TcpConfig::new().http_proxy(tcp::HttpProxy::new(&url).unwrap());
+            let addr = format!("/dns/localhost/tcp/{}/ws", port).parse().unwrap();
+            let outbound = WsConfig::new(proxied).dial(addr).unwrap();
+
+            let (_inbound, outbound) = futures::join!(inbound, outbound);
+            drop(outbound.unwrap());
+            drop(proxy);
+        })
+    }
+
+    #[test]
+    fn tls_failures_are_published() {
+        use async_std::net::TcpListener;
+        use libp2p_core::transport::{TransportEvent, TransportEventKind, TransportEvents};
+        use super::tls;
+
+        async_std::task::block_on(async {
+            // A server closing connections instead of completing the TLS handshake.
+            let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = server.local_addr().unwrap().port();
+            let server = async_std::task::spawn(async move {
+                let (stream, _) = server.accept().await.unwrap();
+                drop(stream)
+            });
+
+            let events = TransportEvents::new();
+            let mut subscriber = events.subscribe();
+            let mut tls_config = tls::Config::builder();
+            tls_config.accept_certhash(true);
+            let mut ws_config = WsConfig::new(tcp::TcpConfig::new());
+            ws_config.set_tls_config(tls_config.finish()).set_events(events);
+
+            let addr: Multiaddr = format!(
+                "/ip4/127.0.0.1/tcp/{}/wss/certhash/uEiDVLruJ2FsCooSUggOmL_KDicV8n0K-7E7CDbdqaJEcCw",
+                port
+            ).parse().unwrap();
+            assert!(ws_config.dial(addr.clone()).unwrap().await.is_err());
+            server.await;
+
+            match subscriber.next().await {
+                Some(TransportEvent { transport: "websocket", address, kind: TransportEventKind::TlsFailed { .. } }) =>
+                    assert_eq!(address, addr),
+                e => panic!("Unexpected event: {:?}", e)
+            }
+        })
+    }
 }
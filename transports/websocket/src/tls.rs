@@ -19,13 +19,15 @@
 // DEALINGS IN THE SOFTWARE.
 
 use async_tls::{TlsConnector, TlsAcceptor};
+use libp2p_core::multiaddr::multihash::{self, Multihash};
 use std::{fmt, io, sync::Arc};
 
 /// TLS configuration.
 #[derive(Clone)]
 pub struct Config {
-    pub(crate) client: TlsConnector,
-    pub(crate) server: Option<TlsAcceptor>
+    client: Arc<rustls::ClientConfig>,
+    pub(crate) server: Option<TlsAcceptor>,
+    accept_certhash: bool
 }
 
 impl fmt::Debug for Config {
@@ -70,14 +72,38 @@ impl Config {
     /// Create a client-only configuration.
     pub fn client() -> Self {
         Config {
-            client: Arc::new(client_config()).into(),
-            server: None
+            client: Arc::new(client_config()),
+            server: None,
+            accept_certhash: false
         }
     }
 
     /// Create a new TLS configuration builder.
     pub fn builder() -> Builder {
-        Builder { client: client_config(), server: None }
+        Builder { client: client_config(), server: None, accept_certhash: false }
+    }
+
+    /// Does this configuration authenticate servers by the `/certhash` components
+    /// of the dialed address?
+    pub fn accepts_certhash(&self) -> bool {
+        self.accept_certhash
+    }
+
+    /// Create the connector used to dial an address with the given `/certhash` values.
+    ///
+    /// Unless no hash is given or certificate hashes are not accepted, the server
+    /// certificate is checked against the hashes instead of the root store.
+    pub(crate) fn connector(&self, certhashes: &[Multihash]) -> TlsConnector {
+        if certhashes.is_empty() || !self.accept_certhash {
+            return self.client.clone().into()
+        }
+        let mut client = (*self.client).clone();
+        client.dangerous().set_certificate_verifier(Arc::new(CerthashVerifier {
+            hashes: certhashes.to_vec()
+        }));
+        // The certificate is not issued for a name, which the server may not have.
+        client.enable_sni = false;
+        Arc::new(client).into()
     }
 }
 
@@ -91,7 +117,8 @@ fn client_config() -> rustls::ClientConfig {
 /// TLS configuration builder.
 pub struct Builder {
     client: rustls::ClientConfig,
-    server: Option<rustls::ServerConfig>
+    server: Option<rustls::ServerConfig>,
+    accept_certhash: bool
 }
 
 impl Builder {
@@ -113,15 +140,63 @@ impl Builder {
         Ok(self)
     }
 
+    /// Remove all trust anchors, including the default web PKI roots.
+    ///
+    /// Used together with [`Builder::add_trust`] to only trust a custom root store.
+    pub fn clear_trust(&mut self) -> &mut Self {
+        self.client.root_store = rustls::RootCertStore::empty();
+        self
+    }
+
+    /// Accept servers whose end-entity certificate matches one of the `/certhash`
+    /// components of the dialed address, e.g. self-signed certificates.
+    ///
+    /// When dialing an address with `/certhash` components, the root store is not
+    /// consulted. Disabled by default.
+    pub fn accept_certhash(&mut self, flag: bool) -> &mut Self {
+        self.accept_certhash = flag;
+        self
+    }
+
     /// Finish configuration.
     pub fn finish(self) -> Config {
         Config {
-            client: Arc::new(self.client).into(),
-            server: self.server.map(|s| Arc::new(s).into())
+            client: Arc::new(self.client),
+            server: self.server.map(|s| Arc::new(s).into()),
+            accept_certhash: self.accept_certhash
         }
     }
 }
 
+/// Verifies server certificates by their multihash.
+struct CerthashVerifier {
+    hashes: Vec<Multihash>
+}
+
+impl rustls::ServerCertVerifier for CerthashVerifier {
+    fn verify_server_cert(
+        &self,
+        _: &rustls::RootCertStore,
+        presented: &[rustls::Certificate],
+        _: webpki::DNSNameRef<'_>,
+        _: &[u8]
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        let cert = presented.first().ok_or(rustls::TLSError::NoCertificatesPresented)?;
+        if certhash_matches(&self.hashes, cert) {
+            Ok(rustls::ServerCertVerified::assertion())
+        } else {
+            Err(rustls::TLSError::General("certificate does not match any /certhash".into()))
+        }
+    }
+}
+
+/// Is the hash of `cert` one of `hashes`?
+fn certhash_matches(hashes: &[Multihash], cert: &rustls::Certificate) -> bool {
+    hashes.iter().any(|hash| {
+        multihash::encode(hash.algorithm(), &cert.0).map_or(false, |h| &h == hash)
+    })
+}
+
 pub(crate) fn dns_name_ref(name: &str) -> Result<webpki::DNSNameRef<'_>, Error> {
     webpki::DNSNameRef::try_from_ascii_str(name).map_err(|_| Error::InvalidDnsName(name.into()))
 }
@@ -169,3 +244,18 @@ impl From<io::Error> for Error {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn certhash_matching() {
+        let cert = rustls::Certificate(b"not really a certificate".to_vec());
+        let sha256 = multihash::encode(multihash::Hash::SHA2256, &cert.0).unwrap();
+        let other = multihash::encode(multihash::Hash::SHA2256, b"another certificate").unwrap();
+        assert!(certhash_matches(&[other.clone(), sha256], &cert));
+        assert!(!certhash_matches(&[other], &cert));
+        assert!(!certhash_matches(&[], &cert));
+    }
+}